@@ -0,0 +1,160 @@
+//! Capture, conversion and encode throughput
+//!
+//! Every group runs at 720p, 1080p and 4K on the mock backend, so the
+//! numbers compare from one machine and commit to the next without a
+//! display. Name real backends to measure them too, on their own displays
+//! at whatever resolution those are:
+//!
+//! ```bash
+//! SNAP_SCALE_BENCH_BACKENDS=screenshots,wgc cargo bench --bench capture
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use screenshots::image::RgbaImage;
+use snap_scale::backend::mock::MockBackend;
+use snap_scale::backend::{by_name, CaptureBackend};
+use snap_scale::buffer::{Frame, FrameBuffer, PixelFormat};
+use snap_scale::encode::{encode_frame, encode_to_vec};
+use snap_scale::metadata::DisplayDescriptor;
+use snap_scale::resize::{Resize, Size};
+use snap_scale::{EncodeOptions, OutputFormat, Transform};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const RESOLUTIONS: [(&str, u32, u32); 3] = [
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("4k", 3840, 2160),
+];
+
+/// Environment variable listing the real backends to include, as
+/// `SNAP_SCALE_BACKEND` names them
+const BACKENDS_ENV: &str = "SNAP_SCALE_BENCH_BACKENDS";
+
+/// A name for the benchmark IDs and a display to capture
+struct Target {
+    name: String,
+    backend: Arc<dyn CaptureBackend>,
+    display: DisplayDescriptor,
+}
+
+fn targets() -> Vec<Target> {
+    let mut targets: Vec<_> = RESOLUTIONS
+        .iter()
+        .map(|&(name, width, height)| {
+            let backend = MockBackend::single(width, height);
+            let display = backend.enumerate().unwrap().remove(0);
+            Target {
+                name: format!("mock/{name}"),
+                backend: Arc::new(backend),
+                display,
+            }
+        })
+        .collect();
+    let names = std::env::var(BACKENDS_ENV).unwrap_or_default();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let backend = by_name(name).unwrap_or_else(|e| panic!("{BACKENDS_ENV}: {e}"));
+        for display in backend.enumerate().unwrap() {
+            targets.push(Target {
+                name: format!("{name}/{}", display.id),
+                backend: Arc::clone(&backend),
+                display,
+            });
+        }
+    }
+    targets
+}
+
+/// The frame every conversion and encode runs on
+fn frame(width: u32, height: u32) -> RgbaImage {
+    let backend = MockBackend::single(width, height);
+    let display = &backend.enumerate().unwrap()[0];
+    backend.capture_display(display).unwrap()
+}
+
+fn bytes(width: u32, height: u32) -> Throughput {
+    Throughput::Bytes(width as u64 * height as u64 * 4)
+}
+
+fn capture(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capture");
+    group
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(500));
+    for target in targets() {
+        let (backend, display) = (&target.backend, &target.display);
+        let sample = backend.capture_display(display).unwrap();
+        group.throughput(bytes(sample.width(), sample.height()));
+        group.bench_function(BenchmarkId::new("display", &target.name), |b| {
+            b.iter(|| backend.capture_display(display).unwrap())
+        });
+        let mut buffer = FrameBuffer::new();
+        group.bench_function(BenchmarkId::new("frame", &target.name), |b| {
+            b.iter(|| backend.capture_frame(display, &mut buffer).unwrap().width())
+        });
+        group.bench_function(BenchmarkId::new("preview", &target.name), |b| {
+            b.iter(|| backend.capture_preview(display, 0.25).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn convert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert");
+    group
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(500));
+    for (name, width, height) in RESOLUTIONS {
+        let image = frame(width, height);
+        let bgra: Vec<u8> = image
+            .pixels()
+            .flat_map(|p| [p[2], p[1], p[0], p[3]])
+            .collect();
+        let stride = width as usize * 4;
+        let now = SystemTime::now();
+        let frame = Frame::new(&bgra, width, height, stride, PixelFormat::Bgra8, now).unwrap();
+        group.throughput(bytes(width, height));
+        group.bench_function(BenchmarkId::new("bgra-to-rgba", name), |b| {
+            b.iter(|| frame.to_image())
+        });
+        group.bench_function(BenchmarkId::new("downsample", name), |b| {
+            b.iter(|| snap_scale::preview::downsample(&frame, 0.25))
+        });
+        // What `--resize 50%` runs
+        let half = Resize::new(Size::Percent(50.0));
+        group.bench_function(BenchmarkId::new("resize-half", name), |b| {
+            b.iter(|| half.apply(image.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    group
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(500));
+    for (name, width, height) in RESOLUTIONS {
+        let image = frame(width, height);
+        group.throughput(bytes(width, height));
+        for format in OutputFormat::ALL {
+            let options = EncodeOptions::new(format);
+            group.bench_function(BenchmarkId::new(format.to_string(), name), |b| {
+                b.iter(|| encode_to_vec(&image, &options).unwrap())
+            });
+        }
+        let frame = Frame::from_image(&image, SystemTime::now());
+        let png = EncodeOptions::new(OutputFormat::Png);
+        group.bench_function(BenchmarkId::new("png-stream", name), |b| {
+            b.iter(|| {
+                let mut out = Vec::new();
+                encode_frame(&frame, &png, &mut out).unwrap();
+                out
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, capture, convert, encode);
+criterion_main!(benches);
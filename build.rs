@@ -0,0 +1,15 @@
+//! Generates the gRPC stubs when the `grpc` feature is on
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        // protox compiles the contract in Rust, so no `protoc` is needed
+        println!("cargo:rerun-if-changed=proto/snap_scale.proto");
+        let descriptors = protox::compile(["snap_scale.proto"], ["proto"])
+            .expect("proto/snap_scale.proto should compile");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("gRPC stubs should generate");
+    }
+}
@@ -1,52 +1,22 @@
 use screenshots::Screen;
+use snap_scale::ScalingConfig;
 use std::error::Error;
 use std::path::Path;
 use std::time::Instant;
 
-/// Represents the scaling configuration for display-aware screen captures
-#[derive(Debug)]
-struct ScalingConfig {
-    dpi_scale: f32,
-    total_scale: f32,
+/// Determines the scaling configuration by performing a test capture
+#[cfg(not(test))]
+fn scaling_for(screen: &Screen) -> ScalingConfig {
+    ScalingConfig::detect(screen)
 }
 
-impl ScalingConfig {
-    /// Determines the actual scaling factor by performing a test capture
-    #[cfg(not(test))]
-    fn determine_actual_scaling(screen: &Screen) -> f32 {
-        let test_size = 100;
-        if let Ok(test_image) = screen.capture_area(0, 0, test_size, test_size) {
-            let actual_size = test_image.width() as f32;
-            let dpi_scaled_size = test_size as f32 * screen.display_info.scale_factor;
-            actual_size / dpi_scaled_size
-        } else {
-            1.56 // Fallback to empirically determined value if test capture fails
-        }
-    }
-
-    #[cfg(test)]
-    fn determine_actual_scaling(_screen: &Screen) -> f32 {
-        1.56 // Use consistent value for testing
-    }
-
-    /// Creates a new ScalingConfig with dynamically determined scaling
-    fn new(screen: &Screen) -> Self {
-        let dpi_scale = screen.display_info.scale_factor;
-        let extra_scale = Self::determine_actual_scaling(screen);
-
-        Self {
-            dpi_scale,
-            total_scale: dpi_scale * extra_scale,
-        }
-    }
-
-    fn scale_dimension(&self, logical_size: u32) -> u32 {
-        (logical_size as f32 * self.total_scale) as u32
-    }
-
-    fn scale_coordinate(&self, logical_coord: i32) -> i32 {
-        (logical_coord as f32 * self.total_scale) as i32
-    }
+#[cfg(test)]
+fn scaling_for(screen: &Screen) -> ScalingConfig {
+    // Use consistent value for testing
+    ScalingConfig::new(
+        screen.display_info.scale_factor,
+        ScalingConfig::FALLBACK_EXTRA_SCALE,
+    )
 }
 
 /// A display-aware screen capture utility that handles DPI scaling and rotation
@@ -60,7 +30,7 @@ impl DisplayAwareCapture {
     /// Creates a new DisplayAwareCapture instance from a Screen
     fn new(screen: Screen) -> Self {
         Self {
-            scaling: ScalingConfig::new(&screen),
+            scaling: scaling_for(&screen),
             screen,
         }
     }
@@ -105,7 +75,7 @@ impl DisplayAwareCapture {
             logical_size,
             logical_size,
             (info.scale_factor * 100.0) as u32,
-            (self.scaling.total_scale * 100.0) as u32,
+            (self.scaling.total_scale() * 100.0) as u32,
             info.rotation
         );
         image.save(&filename)?;
@@ -128,17 +98,14 @@ impl DisplayAwareCapture {
         println!("├─ 📏 Scaling");
         println!(
             "│  ├─ DPI Scale: {:.2}x ({:.0}%)",
-            self.scaling.dpi_scale,
-            self.scaling.dpi_scale * 100.0
-        );
-        println!(
-            "│  ├─ Extra Scale: {:.2}x",
-            self.scaling.total_scale / self.scaling.dpi_scale
+            self.scaling.dpi_scale(),
+            self.scaling.dpi_scale() * 100.0
         );
+        println!("│  ├─ Extra Scale: {:.2}x", self.scaling.extra_scale());
         println!(
             "│  └─ Total Scale: {:.2}x ({:.0}%)",
-            self.scaling.total_scale,
-            self.scaling.total_scale * 100.0
+            self.scaling.total_scale(),
+            self.scaling.total_scale() * 100.0
         );
         println!("├─ 🔄 Rotation: {}°", info.rotation);
         println!(
@@ -164,49 +131,6 @@ mod tests {
         screen
     }
 
-    mod scaling_config {
-        use super::*;
-
-        #[test]
-        fn test_new_scaling_config() {
-            let screen = create_mock_screen(1920, 1080, 1.25, 0.0);
-            let config = ScalingConfig::new(&screen);
-
-            assert!(
-                (config.dpi_scale - 1.25).abs() < EPSILON,
-                "DPI scale should be 1.25"
-            );
-            assert!(
-                (config.total_scale - 1.25 * 1.56).abs() < EPSILON,
-                "Total scale should be DPI scale * extra scale"
-            );
-        }
-
-        #[test]
-        fn test_scale_dimension() {
-            let screen = create_mock_screen(1920, 1080, 1.0, 0.0);
-            let config = ScalingConfig::new(&screen);
-
-            assert_eq!(
-                config.scale_dimension(100),
-                (100.0 * 1.56) as u32,
-                "Should scale dimensions correctly"
-            );
-        }
-
-        #[test]
-        fn test_scale_coordinate() {
-            let screen = create_mock_screen(1920, 1080, 2.0, 0.0);
-            let config = ScalingConfig::new(&screen);
-
-            assert_eq!(
-                config.scale_coordinate(50),
-                (50.0 * 2.0 * 1.56) as i32,
-                "Should scale coordinates correctly"
-            );
-        }
-    }
-
     mod display_aware_capture {
         use super::*;
 
@@ -216,7 +140,7 @@ mod tests {
             let capture = DisplayAwareCapture::new(screen);
 
             assert!(
-                (capture.scaling.total_scale - 1.25 * 1.56).abs() < EPSILON,
+                (capture.scaling.total_scale() - 1.25 * 1.56).abs() < EPSILON,
                 "Total scaling should be correctly calculated"
             );
         }
@@ -290,7 +214,8 @@ mod tests {
                     "Image height should be larger than logical size"
                 );
 
-                let min_expected_size = (logical_size as f32 * capture.scaling.total_scale) as u32;
+                let min_expected_size =
+                    (logical_size as f32 * capture.scaling.total_scale()) as u32;
                 assert!(
                     actual_width >= min_expected_size,
                     "Image width should be at least the minimum expected size"
@@ -305,28 +230,6 @@ mod tests {
             );
         }
     }
-
-    #[cfg(feature = "proptest")]
-    mod property_tests {
-        use super::*;
-        use proptest::prelude::*;
-
-        proptest! {
-            #[test]
-            fn test_scaling_properties(
-                logical_size in 1u32..1000,
-                scale_factor in 1.0f32..4.0
-            ) {
-                let screen = create_mock_screen(1920, 1080, scale_factor, 0.0);
-                let config = ScalingConfig::new(&screen);
-                let scaled = config.scale_dimension(logical_size);
-
-                prop_assert!(scaled > logical_size, "Scaled size should be larger than logical size");
-                prop_assert!(scaled as f32 / logical_size as f32 >= scale_factor,
-                    "Scaling should at least match the scale factor");
-            }
-        }
-    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,47 @@
+//! Mapping between logical and physical coordinates with extreme bounds,
+//! scale factors and rotations: nothing panics, and frame regions stay
+//! inside the frame
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use snap_scale::{CoordinateMapper, Region, Rotation, ScalingConfig};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    bounds: (i32, i32, u32, u32),
+    region: (i32, i32, u32, u32),
+    dpi_scale: f32,
+    extra_scale: f32,
+    degrees: f32,
+}
+
+fn region((x, y, width, height): (i32, i32, u32, u32)) -> Region {
+    Region::new(x, y, width, height)
+}
+
+fuzz_target!(|input: Input| {
+    let scaling = ScalingConfig::new(input.dpi_scale, input.extra_scale);
+    assert!(scaling.total_scale().is_finite() && scaling.total_scale() > 0.0);
+    let rotation = Rotation::from_degrees(input.degrees);
+    let mapper = CoordinateMapper::new(region(input.bounds), scaling, rotation);
+    let logical = region(input.region);
+
+    let physical = mapper.to_physical(&logical);
+    mapper.to_logical(&physical);
+    if let Some(local) = mapper.global_to_local(&logical) {
+        let bounds = mapper.bounds;
+        assert!(local.width <= bounds.width && local.height <= bounds.height);
+    }
+    if let Some(frame) = mapper.to_frame(&logical) {
+        let (width, height) = mapper.physical_size();
+        let (width, height) = rotation.rotate_size(width, height);
+        let stored = Region::new(0, 0, width, height);
+        assert!(
+            stored.contains_region(&frame),
+            "{frame} should be inside the {width}x{height} frame"
+        );
+    }
+    assert_eq!(rotation.inverse().inverse(), rotation);
+});
@@ -0,0 +1,21 @@
+//! Parsing the geometry the command line takes: any text either parses or
+//! fails cleanly
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use snap_scale::{Anchor, AspectRatio, Extent, Region};
+
+fuzz_target!(|text: &str| {
+    if let Ok(region) = text.parse::<Region>() {
+        assert_eq!(region.to_string().parse::<Region>().unwrap(), region);
+    }
+    if let Ok(ratio) = text.parse::<AspectRatio>() {
+        assert!(ratio.width > 0 && ratio.height > 0);
+        assert_eq!(ratio.to_string().parse::<AspectRatio>().unwrap(), ratio);
+    }
+    if let Ok(extent) = text.parse::<Extent>() {
+        assert_eq!(extent.to_string().parse::<Extent>().unwrap(), extent);
+    }
+    let _ = text.parse::<Anchor>();
+});
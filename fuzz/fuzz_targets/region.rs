@@ -0,0 +1,72 @@
+//! `Region` arithmetic at any coordinates: nothing panics, and results stay
+//! inside what they were clamped to
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use snap_scale::{Anchor, AspectRatio, Region};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    a: (i32, i32, u32, u32),
+    b: (i32, i32, u32, u32),
+    offset: (i32, i32),
+    ratio: (u32, u32),
+    anchor: u8,
+    expand: bool,
+}
+
+fn region((x, y, width, height): (i32, i32, u32, u32)) -> Region {
+    Region::new(x, y, width, height)
+}
+
+fuzz_target!(|input: Input| {
+    let (a, b) = (region(input.a), region(input.b));
+
+    if let Some(both) = a.intersect(&b) {
+        assert!(!both.is_empty());
+        assert!(a.contains_region(&both) && b.contains_region(&both));
+        assert_eq!(a.clamp_to(&b), Some(both));
+    }
+    let union = a.union(&b);
+    // Unions wider than u32::MAX saturate
+    if union.right() >= a.right().max(b.right()) && union.bottom() >= a.bottom().max(b.bottom()) {
+        for part in [a, b] {
+            assert!(union.contains_region(&part), "{union} should hold {part}");
+        }
+    }
+    if let Some(shifted) = a.shift_into(&b) {
+        assert!(
+            b.contains_region(&shifted),
+            "{shifted} should be inside {b}"
+        );
+    }
+
+    let (dx, dy) = input.offset;
+    let moved = a.translate(dx, dy);
+    assert_eq!((moved.width, moved.height), (a.width, a.height));
+    let around = Region::around(dx, dy, a.width, a.height);
+    assert_eq!((around.width, around.height), (a.width, a.height));
+    let spanning = Region::spanning((a.x, a.y), (dx, dy));
+    // Except from one end of i32 to the other, a side too long for a u32
+    if spanning.width < u32::MAX && spanning.height < u32::MAX {
+        assert!(spanning.contains(a.x, a.y) && spanning.contains(dx, dy));
+    }
+
+    if let Some(ratio) = AspectRatio::new(input.ratio.0, input.ratio.1) {
+        let anchor = match input.anchor % 5 {
+            0 => Anchor::TopLeft,
+            1 => Anchor::TopRight,
+            2 => Anchor::BottomLeft,
+            3 => Anchor::BottomRight,
+            _ => Anchor::Center,
+        };
+        let fitted = a.with_aspect(ratio, anchor, input.expand);
+        if !input.expand {
+            assert!(fitted.width <= a.width && fitted.height <= a.height);
+        }
+    }
+
+    assert_eq!(a.to_string().parse::<Region>().unwrap(), a);
+});
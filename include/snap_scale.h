@@ -0,0 +1,141 @@
+#ifndef SNAP_SCALE_H
+#define SNAP_SCALE_H
+
+/* Generated by cbindgen from src/capi.rs; edit that instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ The call succeeded
+ */
+#define SNAP_SCALE_OK 0
+
+/*
+ An argument was null, malformed or named no display
+ */
+#define SNAP_SCALE_ERR_INVALID -1
+
+/*
+ Listing, capturing or encoding failed
+ */
+#define SNAP_SCALE_ERR_CAPTURE -2
+
+/*
+ The library panicked; it's a bug
+ */
+#define SNAP_SCALE_ERR_PANIC -3
+
+/*
+ A display, as [`snap_scale_display_info`] fills it in
+ */
+typedef struct SnapScaleDisplay {
+  uint32_t id;
+  /*
+   Origin in desktop-global logical coordinates
+   */
+  int32_t x;
+  int32_t y;
+  /*
+   Logical size
+   */
+  uint32_t width;
+  uint32_t height;
+  float scale_factor;
+  bool is_primary;
+} SnapScaleDisplay;
+
+/*
+ A captured image: RGBA pixels, or an encoded file
+ */
+typedef struct SnapScaleImage {
+  uint8_t *data;
+  size_t len;
+  /*
+   Size in physical pixels
+   */
+  uint32_t width;
+  uint32_t height;
+} SnapScaleImage;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Number of displays, or a negative status
+ */
+int32_t snap_scale_display_count(void);
+
+/*
+ Describes display `index` into `out`
+
+ # Safety
+
+ `out` must be null or point to writable memory for a `SnapScaleDisplay`.
+ */
+int32_t snap_scale_display_info(uint32_t index, SnapScaleDisplay *out);
+
+/*
+ Captures the display with `id` as RGBA, 4 bytes a pixel, rows top first
+
+ # Safety
+
+ `out` must be null or point to writable memory for a `SnapScaleImage`.
+ */
+int32_t snap_scale_capture_display(uint32_t id, SnapScaleImage *out);
+
+/*
+ Captures a logical area of the display with `id` as RGBA
+
+ # Safety
+
+ `out` must be null or point to writable memory for a `SnapScaleImage`.
+ */
+int32_t snap_scale_capture_region(uint32_t id,
+                                  int32_t x,
+                                  int32_t y,
+                                  uint32_t width,
+                                  uint32_t height,
+                                  SnapScaleImage *out);
+
+/*
+ Captures the display with `id` encoded as `format`: `png`, `jpeg`, …
+
+ # Safety
+
+ `format` must be null or a NUL-terminated string, and `out` null or
+ writable memory for a `SnapScaleImage`.
+ */
+int32_t snap_scale_capture_display_encoded(uint32_t id, const char *format, SnapScaleImage *out);
+
+/*
+ Frees an image's buffer and empties it; null and empty images are ignored
+
+ # Safety
+
+ `image` must be null or an image filled in by this library and not yet
+ freed.
+ */
+void snap_scale_image_free(SnapScaleImage *image);
+
+/*
+ Why the calling thread's latest call failed; empty after a success
+
+ Valid until the thread's next call into the library.
+ */
+const char *snap_scale_last_error(void);
+
+/*
+ The library version, e.g. `0.1.0`
+ */
+const char *snap_scale_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SNAP_SCALE_H */
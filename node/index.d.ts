@@ -0,0 +1,44 @@
+/* tslint:disable */
+/* eslint-disable */
+
+/* auto-generated by NAPI-RS */
+
+/** A display, as `listDisplays` returns it */
+export interface Display {
+  index: number
+  id: number
+  /** Origin in desktop-global logical coordinates */
+  x: number
+  y: number
+  /** Logical size */
+  width: number
+  height: number
+  rotation: number
+  scaleFactor: number
+  frequency: number
+  isPrimary: boolean
+}
+/** A logical area of a display, relative to its origin */
+export interface Area {
+  x: number
+  y: number
+  width: number
+  height: number
+}
+export interface CaptureOptions {
+  /** `png` (default), `jpeg`, `webp`, `tiff` or `qoi` */
+  format?: string
+  /** JPEG quality from 1 to 100 */
+  quality?: number
+  /** Capture only this area */
+  area?: Area
+}
+/** The displays, in index order */
+export function listDisplays(): Array<Display>
+/**
+ * Captures display `display` (default 0) into an encoded image on the
+ * thread pool
+ */
+export function capture(display?: number | undefined | null, options?: CaptureOptions | undefined | null): Promise<Buffer>
+/** Like `capture`, blocking until the image is ready */
+export function captureSync(display?: number | undefined | null, options?: CaptureOptions | undefined | null): Buffer
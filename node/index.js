@@ -0,0 +1,2 @@
+// Loads the addon `npm run build` puts next to this file
+module.exports = require("./snap_scale.node");
@@ -0,0 +1,169 @@
+//! Node.js bindings for snap_scale
+//!
+//! A Node-API addon over the capture engine, for Electron apps and test
+//! tooling that want screenshots without spawning the CLI:
+//!
+//! ```js
+//! const snap = require("snap-scale");
+//! const png = await snap.capture(0, { area: { x: 0, y: 0, width: 800, height: 600 } });
+//! ```
+//!
+//! Captures run on the libuv thread pool, so `capture` never blocks the event
+//! loop; `captureSync` does, for scripts that don't mind.
+
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Status, Task};
+use napi_derive::napi;
+use snap_scale::encode::encode_to_vec;
+use snap_scale::backend::default_backend;
+use snap_scale::source::Source;
+use snap_scale::{EncodeOptions, Error, Region};
+
+/// A display, as `listDisplays` returns it
+#[napi(object)]
+pub struct Display {
+    pub index: u32,
+    pub id: u32,
+    /// Origin in desktop-global logical coordinates
+    pub x: i32,
+    pub y: i32,
+    /// Logical size
+    pub width: u32,
+    pub height: u32,
+    pub rotation: f64,
+    pub scale_factor: f64,
+    pub frequency: f64,
+    pub is_primary: bool,
+}
+
+/// A logical area of a display, relative to its origin
+#[napi(object)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[napi(object)]
+pub struct CaptureOptions {
+    /// `png` (default), `jpeg`, `webp`, `tiff` or `qoi`
+    pub format: Option<String>,
+    /// JPEG quality from 1 to 100
+    pub quality: Option<u32>,
+    /// Capture only this area
+    pub area: Option<Area>,
+}
+
+fn error(e: Error) -> napi::Error {
+    let status = match e {
+        Error::Invalid { .. } => Status::InvalidArg,
+        _ => Status::GenericFailure,
+    };
+    napi::Error::new(status, e.to_string())
+}
+
+/// A validated capture, ready to run off the main thread
+pub struct Capture {
+    display: usize,
+    region: Option<Region>,
+    options: EncodeOptions,
+}
+
+impl Capture {
+    fn new(display: Option<u32>, options: Option<CaptureOptions>) -> napi::Result<Self> {
+        let options = options.unwrap_or(CaptureOptions {
+            format: None,
+            quality: None,
+            area: None,
+        });
+        let format = match &options.format {
+            Some(format) => format.parse().map_err(error)?,
+            None => snap_scale::OutputFormat::Png,
+        };
+        let mut encode = EncodeOptions::new(format);
+        if let Some(quality) = options.quality {
+            if !(1..=100).contains(&quality) {
+                return Err(napi::Error::new(
+                    Status::InvalidArg,
+                    format!("JPEG quality must be 1 to 100, not {quality}"),
+                ));
+            }
+            encode = encode.with_jpeg_quality(quality as u8);
+        }
+        let region = options
+            .area
+            .map(|area| Region::new(area.x, area.y, area.width, area.height));
+        if region.is_some_and(|region| region.is_empty()) {
+            return Err(napi::Error::new(
+                Status::InvalidArg,
+                "area width and height must be positive",
+            ));
+        }
+        Ok(Self {
+            display: display.unwrap_or(0) as usize,
+            region,
+            options: encode,
+        })
+    }
+
+    fn run(&self) -> napi::Result<Vec<u8>> {
+        let frame = default_backend()
+            .and_then(|backend| backend.capture(self.display, self.region))
+            .map_err(error)?;
+        encode_to_vec(&frame, &self.options).map_err(error)
+    }
+}
+
+impl Task for Capture {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        self.run()
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(output.into())
+    }
+}
+
+/// The displays, in index order
+#[napi]
+pub fn list_displays() -> napi::Result<Vec<Display>> {
+    let displays = default_backend()
+        .and_then(|backend| backend.displays())
+        .map_err(error)?;
+    Ok(displays
+        .into_iter()
+        .enumerate()
+        .map(|(index, d)| Display {
+            index: index as u32,
+            id: d.id,
+            x: d.x,
+            y: d.y,
+            width: d.width,
+            height: d.height,
+            rotation: d.rotation.into(),
+            scale_factor: d.scale_factor.into(),
+            frequency: d.frequency.into(),
+            is_primary: d.is_primary,
+        })
+        .collect())
+}
+
+/// Captures display `display` (default 0) into an encoded image on the
+/// thread pool
+#[napi(ts_return_type = "Promise<Buffer>")]
+pub fn capture(
+    display: Option<u32>,
+    options: Option<CaptureOptions>,
+) -> napi::Result<AsyncTask<Capture>> {
+    Ok(AsyncTask::new(Capture::new(display, options)?))
+}
+
+/// Like `capture`, blocking until the image is ready
+#[napi]
+pub fn capture_sync(display: Option<u32>, options: Option<CaptureOptions>) -> napi::Result<Buffer> {
+    Ok(Capture::new(display, options)?.run()?.into())
+}
@@ -0,0 +1,35 @@
+// Runs without a display server: bad arguments fail before any capture, and
+// capture failures surface as rejected promises rather than crashes
+const test = require("node:test");
+const assert = require("node:assert");
+const snap = require("..");
+
+test("exports the capture API", () => {
+  for (const name of ["listDisplays", "capture", "captureSync"]) {
+    assert.strictEqual(typeof snap[name], "function", name);
+  }
+});
+
+test("rejects bad options up front", () => {
+  assert.throws(() => snap.captureSync(0, { format: "gif" }), /invalid output format: gif/);
+  assert.throws(() => snap.capture(0, { quality: 0 }), /JPEG quality/);
+  assert.throws(
+    () => snap.capture(0, { area: { x: 0, y: 0, width: 0, height: 10 } }),
+    /width and height must be positive/,
+  );
+});
+
+test("captures into a Buffer or rejects", async () => {
+  let displays;
+  try {
+    displays = snap.listDisplays();
+  } catch (e) {
+    // No screens here; the async path must still reject cleanly
+    await assert.rejects(snap.capture(0), Error);
+    return;
+  }
+  assert.ok(displays.length > 0);
+  const png = await snap.capture(0, { area: { x: 0, y: 0, width: 8, height: 8 } });
+  assert.ok(Buffer.isBuffer(png));
+  assert.deepStrictEqual([...png.subarray(1, 4)], [0x50, 0x4e, 0x47]);
+});
@@ -0,0 +1,432 @@
+//! Color analysis of captures
+//!
+//! [`palette`] clusters an image's colors with k-means. Pixels are first
+//! binned into a 4096-bucket histogram (4 bits per channel) and the weighted
+//! bucket means are clustered, so the cost is independent of the image size
+//! and the result is deterministic.
+//!
+//! [`Histogram`] counts each channel's values and the luma of every pixel,
+//! for checking a display's output: its [`Exposure`] has the mean luminance
+//! and how many pixels are clipped to black or white, and
+//! [`Histogram::render`] plots it.
+//!
+//! [`blank`] finds captures that are a single color throughout, what most
+//! platforms return for protected content or without permission to record
+//! the screen.
+
+use crate::color::Color;
+use screenshots::image::{Rgba, RgbaImage};
+use serde::Serialize;
+
+/// Pixels with less alpha than this are ignored
+const MIN_ALPHA: u8 = 128;
+const MAX_ITERATIONS: usize = 32;
+
+/// One palette entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Swatch {
+    pub color: Color,
+    /// Fraction of the considered pixels closest to this color, 0-1
+    pub share: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    mean: [f32; 3],
+    weight: f32,
+}
+
+/// Dominant colors of `image`, most common first
+///
+/// Returns at most `colors` swatches; fewer when the image has fewer distinct
+/// colors, and none when every pixel is (mostly) transparent.
+pub fn palette(image: &RgbaImage, colors: usize) -> Vec<Swatch> {
+    let buckets = buckets(image);
+    let total: f32 = buckets.iter().map(|b| b.weight).sum();
+    if colors == 0 || total == 0.0 {
+        return Vec::new();
+    }
+
+    let mut centers = seed(&buckets, colors);
+    let mut assignment = vec![0; buckets.len()];
+    for _ in 0..MAX_ITERATIONS {
+        for (bucket, slot) in buckets.iter().zip(&mut assignment) {
+            *slot = nearest(&centers, bucket.mean);
+        }
+
+        let mut sums = vec![Bucket::default(); centers.len()];
+        for (bucket, &cluster) in buckets.iter().zip(&assignment) {
+            let sum = &mut sums[cluster];
+            for (total, channel) in sum.mean.iter_mut().zip(bucket.mean) {
+                *total += channel * bucket.weight;
+            }
+            sum.weight += bucket.weight;
+        }
+
+        let mut moved = false;
+        for (center, sum) in centers.iter_mut().zip(&sums) {
+            if sum.weight > 0.0 {
+                let mean = sum.mean.map(|c| c / sum.weight);
+                moved |= distance(mean, *center) > 0.25;
+                *center = mean;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let mut weights = vec![0.0; centers.len()];
+    for (bucket, &cluster) in buckets.iter().zip(&assignment) {
+        weights[cluster] += bucket.weight;
+    }
+    let mut swatches: Vec<_> = centers
+        .iter()
+        .zip(weights)
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(center, weight)| {
+            let [r, g, b] = center.map(|c| c.round().clamp(0.0, 255.0) as u8);
+            Swatch {
+                color: Color::new(r, g, b, u8::MAX),
+                share: weight / total,
+            }
+        })
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    swatches
+}
+
+/// Non-empty histogram buckets with their mean color and pixel count
+fn buckets(image: &RgbaImage) -> Vec<Bucket> {
+    let mut sums = vec![([0u64; 3], 0u64); 4096];
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < MIN_ALPHA {
+            continue;
+        }
+        let index = (r as usize >> 4) << 8 | (g as usize >> 4) << 4 | b as usize >> 4;
+        let (sum, count) = &mut sums[index];
+        sum[0] += r as u64;
+        sum[1] += g as u64;
+        sum[2] += b as u64;
+        *count += 1;
+    }
+
+    sums.into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(sum, count)| Bucket {
+            mean: sum.map(|c| c as f32 / count as f32),
+            weight: count as f32,
+        })
+        .collect()
+}
+
+/// Deterministic k-means++ style seeding: start from the most common bucket,
+/// then repeatedly take the bucket with the highest weight × squared distance
+/// to its nearest center
+fn seed(buckets: &[Bucket], colors: usize) -> Vec<[f32; 3]> {
+    let first = buckets
+        .iter()
+        .max_by(|a, b| a.weight.total_cmp(&b.weight))
+        .expect("histogram is not empty");
+    let mut centers = vec![first.mean];
+
+    while centers.len() < colors {
+        let (score, next) = buckets
+            .iter()
+            .map(|bucket| {
+                let d = distance(centers[nearest(&centers, bucket.mean)], bucket.mean);
+                (bucket.weight * d, bucket.mean)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("histogram is not empty");
+        if score == 0.0 {
+            break;
+        }
+        centers.push(next);
+    }
+    centers
+}
+
+fn nearest(centers: &[[f32; 3]], color: [f32; 3]) -> usize {
+    centers
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(**a, color).total_cmp(&distance(**b, color)))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Squared euclidean distance in RGB
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// The color of every pixel of `image`, when they're all the same
+///
+/// An image with no pixels isn't blank: there's nothing to judge it by.
+pub fn blank(image: &RgbaImage) -> Option<Color> {
+    let mut pixels = image.pixels();
+    let first = *pixels.next()?;
+    pixels.all(|p| *p == first).then(|| Color::from(first))
+}
+
+/// Luma of an sRGB pixel with the Rec. 709 weights, 0-255
+fn luma([r, g, b]: [u8; 3]) -> u8 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+}
+
+/// How many pixels have each value of each channel and of luma
+///
+/// Pixels with less alpha than [`palette`] considers are left out here too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Histogram {
+    pub red: Vec<u64>,
+    pub green: Vec<u64>,
+    pub blue: Vec<u64>,
+    pub luma: Vec<u64>,
+    /// Pixels counted
+    pub pixels: u64,
+    /// Pixels with every channel at 0
+    #[serde(skip)]
+    black: u64,
+    /// Pixels with every channel at 255
+    #[serde(skip)]
+    white: u64,
+    /// Pixels with some channel at 0 or 255
+    #[serde(skip)]
+    clipped: u64,
+}
+
+/// Exposure statistics of a [`Histogram`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Exposure {
+    /// Mean luma, 0-255
+    pub mean_luminance: f64,
+    /// Luma that half the pixels are at or below
+    pub median_luminance: u8,
+    /// Mean of each channel, red, green and blue, 0-255
+    pub mean: [f64; 3],
+    /// Percentage of pixels at pure black
+    pub clipped_black: f64,
+    /// Percentage of pixels at pure white
+    pub clipped_white: f64,
+    /// Percentage of pixels with some channel at 0 or 255
+    pub clipped_channels: f64,
+}
+
+impl Histogram {
+    /// Counts the pixels of `image`
+    pub fn of(image: &RgbaImage) -> Self {
+        let mut histogram = Self {
+            red: vec![0; 256],
+            green: vec![0; 256],
+            blue: vec![0; 256],
+            luma: vec![0; 256],
+            pixels: 0,
+            black: 0,
+            white: 0,
+            clipped: 0,
+        };
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a < MIN_ALPHA {
+                continue;
+            }
+            histogram.red[r as usize] += 1;
+            histogram.green[g as usize] += 1;
+            histogram.blue[b as usize] += 1;
+            histogram.luma[luma([r, g, b]) as usize] += 1;
+            histogram.pixels += 1;
+            histogram.black += u64::from([r, g, b] == [0; 3]);
+            histogram.white += u64::from([r, g, b] == [255; 3]);
+            histogram.clipped += u64::from([r, g, b].iter().any(|&c| c == 0 || c == 255));
+        }
+        histogram
+    }
+
+    pub fn exposure(&self) -> Exposure {
+        let pixels = self.pixels.max(1) as f64;
+        let mean = |counts: &[u64]| {
+            let sum: u64 = counts.iter().enumerate().map(|(v, n)| v as u64 * n).sum();
+            sum as f64 / pixels
+        };
+        let mut seen = 0;
+        let median_luminance = self
+            .luma
+            .iter()
+            .position(|&n| {
+                seen += n;
+                seen * 2 >= self.pixels.max(1)
+            })
+            .unwrap_or(0) as u8;
+        let percent = |n: u64| n as f64 * 100.0 / pixels;
+        Exposure {
+            mean_luminance: mean(&self.luma),
+            median_luminance,
+            mean: [mean(&self.red), mean(&self.green), mean(&self.blue)],
+            clipped_black: percent(self.black),
+            clipped_white: percent(self.white),
+            clipped_channels: percent(self.clipped),
+        }
+    }
+
+    /// The histogram plotted on `width`x`height` of dark gray: the luma
+    /// filled in light gray, each channel drawn over it as a line of its
+    /// color
+    ///
+    /// Heights are the square root of the counts, the tallest reaching the
+    /// top, so a spike at one value doesn't flatten everything else.
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(width, height, Rgba([32, 32, 32, 255]));
+        if width == 0 || height == 0 {
+            return image;
+        }
+        let tallest = [&self.red, &self.green, &self.blue, &self.luma]
+            .iter()
+            .flat_map(|counts| counts.iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        // Rows from the bottom each value's count reaches in column `x`
+        let bar = |counts: &[u64], x: u32| {
+            let value = (x as u64 * 256 / width as u64) as usize;
+            ((counts[value] as f64 / tallest as f64).sqrt() * height as f64).round() as u32
+        };
+        for x in 0..width {
+            for y in height - bar(&self.luma, x).min(height)..height {
+                image.put_pixel(x, y, Rgba([150, 150, 150, 255]));
+            }
+        }
+        let channels = [
+            (&self.red, Rgba([230, 60, 60, 255])),
+            (&self.green, Rgba([60, 200, 60, 255])),
+            (&self.blue, Rgba([70, 110, 240, 255])),
+        ];
+        for (counts, color) in channels {
+            for x in 0..width {
+                let top = bar(counts, x).min(height);
+                let previous = bar(counts, x.saturating_sub(1)).min(height);
+                // Joined to the last column, so steep changes stay a line
+                for reach in top.min(previous)..=top.max(previous) {
+                    if reach > 0 {
+                        image.put_pixel(x, height - reach, color);
+                    }
+                }
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    #[test]
+    fn test_two_color_image() {
+        // Three quarters red, one quarter blue
+        let image = RgbaImage::from_fn(40, 40, |x, _| {
+            if x < 30 {
+                Rgba([250, 10, 10, 255])
+            } else {
+                Rgba([10, 10, 250, 255])
+            }
+        });
+
+        let swatches = palette(&image, 4);
+
+        assert_eq!(swatches.len(), 2, "Only two distinct colors: {swatches:?}");
+        assert_eq!(swatches[0].color.hex(), "#fa0a0a");
+        assert!((swatches[0].share - 0.75).abs() < 1e-6, "{swatches:?}");
+        assert_eq!(swatches[1].color.hex(), "#0a0afa");
+    }
+
+    #[test]
+    fn test_similar_shades_merge() {
+        let image = RgbaImage::from_fn(32, 32, |x, y| {
+            let shade = ((x + y) % 8) as u8;
+            if y < 16 {
+                Rgba([200 + shade, 40, 40, 255])
+            } else {
+                Rgba([40, 180 + shade, 40, 255])
+            }
+        });
+
+        let swatches = palette(&image, 2);
+
+        assert_eq!(swatches.len(), 2);
+        let total: f32 = swatches.iter().map(|s| s.share).sum();
+        assert!((total - 1.0).abs() < 1e-4, "Shares cover every pixel");
+        assert!(swatches.iter().any(|s| s.color.r > 190 && s.color.g < 50));
+        assert!(swatches.iter().any(|s| s.color.g > 170 && s.color.r < 50));
+    }
+
+    #[test]
+    fn test_transparent_pixels_are_ignored() {
+        let image = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 0]));
+        assert!(palette(&image, 3).is_empty());
+    }
+
+    #[test]
+    fn test_histogram_and_exposure() {
+        // A quarter black, a quarter white, half mid gray
+        let image = RgbaImage::from_fn(4, 4, |x, _| match x {
+            0 => Rgba([0, 0, 0, 255]),
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([128, 128, 128, 255]),
+        });
+        let histogram = Histogram::of(&image);
+        assert_eq!(histogram.pixels, 16);
+        assert_eq!(histogram.red[128], 8);
+        assert_eq!(histogram.luma[255], 4);
+
+        let exposure = histogram.exposure();
+        assert_eq!(exposure.mean_luminance, 127.75, "{exposure:?}");
+        assert_eq!(exposure.median_luminance, 128);
+        assert_eq!(exposure.clipped_black, 25.0);
+        assert_eq!(exposure.clipped_white, 25.0);
+        assert_eq!(exposure.clipped_channels, 50.0);
+    }
+
+    #[test]
+    fn test_clipped_channels_and_transparency() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            // Saturated red: two channels clipped, counted once
+            (0, 0) => Rgba([255, 0, 0, 255]),
+            (1, 0) => Rgba([10, 250, 30, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let exposure = Histogram::of(&image).exposure();
+        assert_eq!(exposure.clipped_black, 0.0);
+        assert_eq!(exposure.clipped_channels, 50.0);
+
+        let empty = Histogram::of(&RgbaImage::new(3, 3));
+        assert_eq!(empty.pixels, 0);
+        assert_eq!(empty.exposure().mean_luminance, 0.0);
+    }
+
+    #[test]
+    fn test_blank() {
+        let black = RgbaImage::from_pixel(16, 9, Rgba([0, 0, 0, 255]));
+        assert_eq!(blank(&black), Some(Color::BLACK));
+        let gray = RgbaImage::from_pixel(4, 4, Rgba([40, 40, 40, 255]));
+        assert_eq!(blank(&gray).map(|c| c.hex()).as_deref(), Some("#282828"));
+
+        let mut one_off = black.clone();
+        one_off.put_pixel(15, 8, Rgba([0, 0, 1, 255]));
+        assert_eq!(blank(&one_off), None);
+        assert_eq!(blank(&RgbaImage::new(0, 0)), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let image = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
+        let plot = Histogram::of(&image).render(256, 100);
+        assert_eq!(plot.dimensions(), (256, 100));
+        // Every pixel is red 255, so red peaks at the right edge
+        assert_eq!(plot.get_pixel(255, 0), &Rgba([230, 60, 60, 255]));
+        assert_eq!(plot.get_pixel(128, 0), &Rgba([32, 32, 32, 255]));
+    }
+}
@@ -0,0 +1,604 @@
+//! Burning text into captures
+//!
+//! Text is drawn with a built-in 8×8 bitmap font, scaled to the requested
+//! size, or with any TrueType/OpenType font loaded via [`Font::load`]. An
+//! outline keeps labels legible on any background. [`Caption`] wraps a label
+//! as a pipeline [`Transform`]; [`Timestamp`] stamps the capture time.
+//! [`Annotator`] draws arrows, rectangles, ellipses and highlights.
+
+use crate::color::Color;
+use crate::transform::Transform;
+use crate::{Error, Result};
+use ab_glyph::{Font as _, FontArc, PxScale, ScaleFont as _};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use screenshots::image::{Rgba, RgbaImage};
+use std::path::Path;
+use std::str::FromStr;
+
+mod shapes;
+
+pub use shapes::{Annotator, Shape, Stroke, HIGHLIGHT};
+
+/// Typeface used for text
+#[derive(Debug, Clone, Default)]
+pub enum Font {
+    /// The embedded 8×8 bitmap font, scaled by whole pixels
+    #[default]
+    Builtin,
+    TrueType(FontArc),
+}
+
+impl Font {
+    /// Loads a `.ttf` / `.otf` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        FontArc::try_from_vec(data)
+            .map(Font::TrueType)
+            .map_err(|_| Error::invalid("font file", path.display().to_string()))
+    }
+}
+
+/// How text is drawn
+#[derive(Debug, Clone)]
+pub struct TextStyle {
+    pub font: Font,
+    /// Line height in pixels
+    pub size: f32,
+    pub color: Color,
+    /// Drawn around each glyph when set
+    pub outline: Option<Color>,
+    /// Filled box behind the text when set
+    pub background: Option<Color>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font: Font::Builtin,
+            size: 16.0,
+            color: Color::WHITE,
+            outline: Some(Color::BLACK),
+            background: None,
+        }
+    }
+}
+
+impl TextStyle {
+    /// Outline thickness in pixels
+    fn outline_width(&self) -> u32 {
+        if self.outline.is_some() {
+            ((self.size / 16.0).round() as u32).max(1)
+        } else {
+            0
+        }
+    }
+}
+
+/// Where a label goes in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+    Center,
+    /// Top-left corner of the text box, in image pixels
+    At(i32, i32),
+}
+
+impl Position {
+    /// Top-left corner for a `width`×`height` box in an `image_width`×`image_height`
+    /// image, keeping `margin` pixels from the edges
+    pub fn resolve(
+        self,
+        (width, height): (u32, u32),
+        (image_width, image_height): (u32, u32),
+        margin: u32,
+    ) -> (i32, i32) {
+        let (width, height) = (width as i64, height as i64);
+        let (image_width, image_height, margin) =
+            (image_width as i64, image_height as i64, margin as i64);
+        let right = image_width - width - margin;
+        let bottom = image_height - height - margin;
+        let (x, y) = match self {
+            Self::TopLeft => (margin, margin),
+            Self::TopRight => (right, margin),
+            Self::BottomLeft => (margin, bottom),
+            Self::BottomRight => (right, bottom),
+            Self::Center => ((image_width - width) / 2, (image_height - height) / 2),
+            Self::At(x, y) => (x as i64, y as i64),
+        };
+        (
+            crate::geometry::saturate_i32(x),
+            crate::geometry::saturate_i32(y),
+        )
+    }
+}
+
+/// Parses `top-left`, `top-right`, `bottom-left`, `bottom-right`, `center`
+/// or `x,y`
+impl FromStr for Position {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "top-left" => Ok(Self::TopLeft),
+            "top-right" => Ok(Self::TopRight),
+            "bottom-left" => Ok(Self::BottomLeft),
+            "bottom-right" => Ok(Self::BottomRight),
+            "center" => Ok(Self::Center),
+            _ => s
+                .split_once(',')
+                .and_then(|(x, y)| Some(Self::At(x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                .ok_or_else(|| Error::invalid("text position", s)),
+        }
+    }
+}
+
+/// Per-pixel glyph coverage, 0-1
+struct Mask {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Mask {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    fn max(&mut self, x: i64, y: i64, coverage: f32) {
+        if x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64 {
+            let cell = &mut self.data[(y as u32 * self.width + x as u32) as usize];
+            *cell = cell.max(coverage);
+        }
+    }
+
+    /// Grows the coverage by `radius` pixels in every direction
+    fn dilate(&self, radius: u32) -> Self {
+        let mut out = Self::new(self.width, self.height);
+        let r = radius as i64;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coverage = self.get(x, y);
+                if coverage == 0.0 {
+                    continue;
+                }
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        out.max(x as i64 + dx, y as i64 + dy, coverage);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Size of the box `text` occupies, including its outline
+pub fn measure_text(text: &str, style: &TextStyle) -> (u32, u32) {
+    let (width, height) = text_extent(text, style);
+    let pad = style.outline_width() * 2;
+    (width + pad, height + pad)
+}
+
+/// Unpadded text size
+fn text_extent(text: &str, style: &TextStyle) -> (u32, u32) {
+    let lines: Vec<_> = text.lines().collect();
+    let line_count = lines.len().max(1) as u32;
+    match &style.font {
+        Font::Builtin => {
+            let scale = builtin_scale(style.size);
+            let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+            (
+                longest * 8 * scale,
+                line_count * 8 * scale + (line_count - 1) * scale,
+            )
+        }
+        Font::TrueType(font) => {
+            let scaled = font.as_scaled(PxScale::from(style.size));
+            let width = lines
+                .iter()
+                .map(|line| line_width(&scaled, line))
+                .fold(0.0f32, f32::max);
+            let line_height = scaled.height() + scaled.line_gap();
+            (
+                width.ceil() as u32,
+                (line_height * (line_count - 1) as f32 + scaled.height()).ceil() as u32,
+            )
+        }
+    }
+}
+
+fn builtin_scale(size: f32) -> u32 {
+    ((size / 8.0).round() as u32).max(1)
+}
+
+fn line_width<F: ab_glyph::Font>(font: &impl ab_glyph::ScaleFont<F>, line: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Rasterizes `text` with its top-left glyph pixel at (`pad`, `pad`)
+fn rasterize(text: &str, style: &TextStyle, pad: u32) -> Mask {
+    let (width, height) = text_extent(text, style);
+    let mut mask = Mask::new(width + 2 * pad, height + 2 * pad);
+    let pad = pad as i64;
+
+    match &style.font {
+        Font::Builtin => {
+            use font8x8::UnicodeFonts;
+
+            let scale = builtin_scale(style.size) as i64;
+            for (row, line) in text.lines().enumerate() {
+                let top = pad + row as i64 * 9 * scale;
+                for (col, c) in line.chars().enumerate() {
+                    let glyph = font8x8::BASIC_FONTS
+                        .get(c)
+                        .or_else(|| font8x8::LATIN_FONTS.get(c))
+                        .unwrap_or_else(|| font8x8::BASIC_FONTS.get('?').unwrap_or([0; 8]));
+                    let left = pad + col as i64 * 8 * scale;
+                    for (gy, bits) in glyph.iter().enumerate() {
+                        for gx in 0..8 {
+                            if bits >> gx & 1 == 0 {
+                                continue;
+                            }
+                            for sy in 0..scale {
+                                for sx in 0..scale {
+                                    mask.max(
+                                        left + gx * scale + sx,
+                                        top + gy as i64 * scale + sy,
+                                        1.0,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Font::TrueType(font) => {
+            let scaled = font.as_scaled(PxScale::from(style.size));
+            let line_height = scaled.height() + scaled.line_gap();
+            for (row, line) in text.lines().enumerate() {
+                let baseline = scaled.ascent() + row as f32 * line_height;
+                let mut caret = 0.0;
+                let mut previous = None;
+                for c in line.chars() {
+                    let id = scaled.glyph_id(c);
+                    if let Some(previous) = previous {
+                        caret += scaled.kern(previous, id);
+                    }
+                    let glyph = id
+                        .with_scale_and_position(scaled.scale(), ab_glyph::point(caret, baseline));
+                    caret += scaled.h_advance(id);
+                    previous = Some(id);
+
+                    if let Some(outlined) = font.outline_glyph(glyph) {
+                        let bounds = outlined.px_bounds();
+                        outlined.draw(|gx, gy, coverage| {
+                            mask.max(
+                                pad + bounds.min.x as i64 + gx as i64,
+                                pad + bounds.min.y as i64 + gy as i64,
+                                coverage,
+                            );
+                        });
+                    }
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// Alpha-composites `color` over `pixel` with the given coverage
+pub(crate) fn blend(pixel: &mut Rgba<u8>, color: Color, coverage: f32) {
+    let alpha = coverage.clamp(0.0, 1.0) * color.a as f32 / 255.0;
+    if alpha <= 0.0 {
+        return;
+    }
+    // Straight-alpha "over", so edges drawn onto transparency keep their color
+    let [r, g, b, a] = pixel.0;
+    let below = a as f32 / 255.0 * (1.0 - alpha);
+    let out = alpha + below;
+    let mix = |dst: u8, src: u8| ((dst as f32 * below + src as f32 * alpha) / out).round() as u8;
+    pixel.0 = [
+        mix(r, color.r),
+        mix(g, color.g),
+        mix(b, color.b),
+        (out * 255.0).round() as u8,
+    ];
+}
+
+/// Draws `text` with the top-left corner of its box (see [`measure_text`])
+/// at `x`, `y`; anything outside the image is clipped
+pub fn draw_text(image: &mut RgbaImage, text: &str, x: i32, y: i32, style: &TextStyle) {
+    let pad = style.outline_width();
+    let fill = rasterize(text, style, pad);
+    let outline = style.outline.map(|color| (color, fill.dilate(pad)));
+
+    for my in 0..fill.height {
+        for mx in 0..fill.width {
+            let (px, py) = (x as i64 + mx as i64, y as i64 + my as i64);
+            if px < 0 || py < 0 || px >= image.width() as i64 || py >= image.height() as i64 {
+                continue;
+            }
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            if let Some(background) = style.background {
+                blend(pixel, background, 1.0);
+            }
+            if let Some((color, mask)) = &outline {
+                blend(pixel, *color, mask.get(mx, my));
+            }
+            blend(pixel, style.color, fill.get(mx, my));
+        }
+    }
+}
+
+/// A text label burned into every capture
+#[derive(Debug, Clone)]
+pub struct Caption {
+    pub text: String,
+    pub style: TextStyle,
+    pub position: Position,
+}
+
+impl Caption {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            style: TextStyle::default(),
+            position: Position::default(),
+        }
+    }
+
+    pub fn with_style(mut self, style: TextStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+}
+
+impl Transform for Caption {
+    fn name(&self) -> &str {
+        "caption"
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        place_text(&mut image, &self.text, &self.style, self.position);
+        Ok(image)
+    }
+}
+
+/// Draws `text` at `position`, half a line height in from the edges
+fn place_text(image: &mut RgbaImage, text: &str, style: &TextStyle, position: Position) {
+    let size = measure_text(text, style);
+    let margin = (style.size / 2.0) as u32;
+    let (x, y) = position.resolve(size, image.dimensions(), margin);
+    draw_text(image, text, x, y, style);
+}
+
+/// `strftime` format used by [`Timestamp::default`]
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Stamps the local capture time, and optionally the host and display, into
+/// a corner of every capture
+#[derive(Debug, Clone)]
+pub struct Timestamp {
+    format: String,
+    /// Appends the machine's hostname
+    pub hostname: bool,
+    /// Appended as `display <id>` when set
+    pub display: Option<String>,
+    pub style: TextStyle,
+    pub position: Position,
+}
+
+impl Default for Timestamp {
+    fn default() -> Self {
+        Self {
+            format: DEFAULT_TIMESTAMP_FORMAT.into(),
+            hostname: false,
+            display: None,
+            style: TextStyle::default(),
+            position: Position::BottomRight,
+        }
+    }
+}
+
+impl Timestamp {
+    /// A timestamp rendered with a `strftime` format such as `%H:%M`
+    pub fn new(format: impl Into<String>) -> Result<Self> {
+        let format = format.into();
+        if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+            return Err(Error::invalid("timestamp format", format));
+        }
+        Ok(Self {
+            format,
+            ..Self::default()
+        })
+    }
+
+    pub fn with_hostname(mut self, hostname: bool) -> Self {
+        self.hostname = hostname;
+        self
+    }
+
+    pub fn with_display(mut self, display: impl Into<String>) -> Self {
+        self.display = Some(display.into());
+        self
+    }
+
+    pub fn with_style(mut self, style: TextStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// The text stamped for a capture taken at `time`
+    pub fn label(&self, time: DateTime<Local>) -> String {
+        let mut label = time.format(&self.format).to_string();
+        if self.hostname {
+            label += " | ";
+            label += &gethostname::gethostname().to_string_lossy();
+        }
+        if let Some(display) = &self.display {
+            label += " | display ";
+            label += display;
+        }
+        label
+    }
+}
+
+impl Transform for Timestamp {
+    fn name(&self) -> &str {
+        "timestamp"
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        let label = self.label(Local::now());
+        place_text(&mut image, &label, &self.style, self.position);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(size: f32) -> TextStyle {
+        TextStyle {
+            size,
+            outline: None,
+            ..TextStyle::default()
+        }
+    }
+
+    fn lit_pixels(image: &RgbaImage) -> Vec<(u32, u32)> {
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0[0] > 128)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_measurements() {
+        assert_eq!(measure_text("abc", &plain(8.0)), (24, 8));
+        assert_eq!(measure_text("ab\nc", &plain(16.0)), (32, 34));
+        assert_eq!(
+            measure_text("abc", &TextStyle::default()),
+            (50, 18),
+            "Outline adds one pixel per side"
+        );
+    }
+
+    #[test]
+    fn test_text_stays_inside_its_box() {
+        let mut image = RgbaImage::new(64, 32);
+        let style = plain(16.0);
+        draw_text(&mut image, "Hi", 5, 7, &style);
+
+        let lit = lit_pixels(&image);
+        let (width, height) = measure_text("Hi", &style);
+        assert!(!lit.is_empty(), "Something was drawn");
+        assert!(lit
+            .iter()
+            .all(|&(x, y)| (5..5 + width).contains(&x) && (7..7 + height).contains(&y)));
+    }
+
+    #[test]
+    fn test_outline_surrounds_text() {
+        let mut image = RgbaImage::from_pixel(40, 20, Rgba([128, 128, 128, 255]));
+        draw_text(&mut image, "I", 0, 0, &TextStyle::default());
+
+        let dark = image.pixels().filter(|p| p.0[0] == 0).count();
+        let light = image.pixels().filter(|p| p.0[0] == 255).count();
+        assert!(dark > 0 && light > 0, "Both fill and outline are drawn");
+    }
+
+    #[test]
+    fn test_clipped_text_does_not_panic() {
+        let mut image = RgbaImage::new(10, 10);
+        draw_text(&mut image, "clipped", -20, 5, &TextStyle::default());
+        draw_text(&mut image, "clipped", 8, -3, &TextStyle::default());
+    }
+
+    #[test]
+    fn test_positions() {
+        let size = (20, 10);
+        let image = (100, 50);
+        assert_eq!(Position::TopLeft.resolve(size, image, 4), (4, 4));
+        assert_eq!(Position::BottomRight.resolve(size, image, 4), (76, 36));
+        assert_eq!(Position::Center.resolve(size, image, 4), (40, 20));
+        assert_eq!("12,-3".parse::<Position>().unwrap(), Position::At(12, -3));
+        assert!("middle".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn test_caption_transform() {
+        let caption = Caption::new("x")
+            .at(Position::TopLeft)
+            .with_style(plain(8.0));
+        let image = caption.apply(RgbaImage::new(32, 32)).unwrap();
+        let lit = lit_pixels(&image);
+        assert!(lit
+            .iter()
+            .all(|&(x, y)| (4..12).contains(&x) && (4..12).contains(&y)));
+    }
+
+    #[test]
+    fn test_timestamp_label() {
+        use chrono::TimeZone;
+
+        let time = Local.with_ymd_and_hms(2024, 3, 9, 14, 5, 7).unwrap();
+        let stamp = Timestamp::default().with_display("1");
+        assert_eq!(stamp.label(time), "2024-03-09 14:05:07 | display 1");
+
+        let stamp = Timestamp::new("%H:%M").unwrap().with_hostname(true);
+        let label = stamp.label(time);
+        assert!(
+            label.starts_with("14:05 | ") && label.len() > "14:05 | ".len(),
+            "{label}"
+        );
+
+        assert!(Timestamp::new("%Y-%").is_err());
+    }
+
+    #[test]
+    fn test_invalid_font_file() {
+        let path = std::env::temp_dir().join(format!("snap_scale_font_{}.ttf", std::process::id()));
+        std::fs::write(&path, b"not a font").unwrap();
+        let result = Font::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Invalid { .. })));
+    }
+}
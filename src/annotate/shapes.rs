@@ -0,0 +1,414 @@
+//! Arrows, rectangles, ellipses and highlights for marking up captures
+
+use super::blend;
+use crate::color::Color;
+use crate::geometry::Region;
+use crate::transform::Transform;
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+use std::str::FromStr;
+
+/// Line color and thickness for outlined shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stroke {
+    pub color: Color,
+    /// Line width in pixels
+    pub width: u32,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            color: Color::RED,
+            width: 4,
+        }
+    }
+}
+
+/// Default fill for [`Shape::Highlight`], a translucent marker yellow
+pub const HIGHLIGHT: Color = Color::new(255, 230, 0, 96);
+
+/// Something drawn over a capture, in image pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// A line with an arrowhead at `to`
+    Arrow { from: (i32, i32), to: (i32, i32) },
+    /// An outlined rectangle
+    Rect(Region),
+    /// An outlined ellipse inscribed in the region
+    Ellipse(Region),
+    /// A translucent filled rectangle
+    Highlight(Region),
+}
+
+impl FromStr for Shape {
+    type Err = Error;
+
+    /// Parses `arrow:x1,y1,x2,y2`, or `rect:`, `ellipse:` or `highlight:`
+    /// followed by `x,y,w,h`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            Error::invalid(
+                "shape (arrow:x1,y1,x2,y2 or rect|ellipse|highlight:x,y,w,h)",
+                s,
+            )
+        };
+        let (kind, coords) = s.split_once(':').ok_or_else(invalid)?;
+        match kind {
+            "arrow" => {
+                let values = coords
+                    .split(',')
+                    .map(|v| v.trim().parse::<i32>())
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|_| invalid())?;
+                let [x1, y1, x2, y2] = values.as_slice() else {
+                    return Err(invalid());
+                };
+                Ok(Self::Arrow {
+                    from: (*x1, *y1),
+                    to: (*x2, *y2),
+                })
+            }
+            "rect" => Ok(Self::Rect(coords.parse()?)),
+            "ellipse" => Ok(Self::Ellipse(coords.parse()?)),
+            "highlight" => Ok(Self::Highlight(coords.parse()?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A list of shapes burned into captures, in the order they were added
+///
+/// Each outlined shape takes the stroke current when it was added:
+///
+/// ```
+/// use snap_scale::annotate::Annotator;
+/// use snap_scale::{Color, Region};
+///
+/// let markup = Annotator::new()
+///     .highlight(Region::new(10, 10, 200, 24))
+///     .color(Color::YELLOW)
+///     .width(6)
+///     .arrow((300, 200), (215, 22));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Annotator {
+    shapes: Vec<(Shape, Stroke)>,
+    stroke: Stroke,
+    highlight: Option<Color>,
+}
+
+impl Annotator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stroke color for the shapes added after this
+    pub fn color(mut self, color: Color) -> Self {
+        self.stroke.color = color;
+        self
+    }
+
+    /// Stroke width for the shapes added after this
+    pub fn width(mut self, width: u32) -> Self {
+        self.stroke.width = width.max(1);
+        self
+    }
+
+    /// Fill for the highlights added after this, [`HIGHLIGHT`] by default
+    pub fn highlight_color(mut self, color: Color) -> Self {
+        self.highlight = Some(color);
+        self
+    }
+
+    pub fn push(mut self, shape: Shape) -> Self {
+        let stroke = match shape {
+            Shape::Highlight(_) => Stroke {
+                color: self.highlight.unwrap_or(HIGHLIGHT),
+                ..self.stroke
+            },
+            _ => self.stroke,
+        };
+        self.shapes.push((shape, stroke));
+        self
+    }
+
+    pub fn arrow(self, from: (i32, i32), to: (i32, i32)) -> Self {
+        self.push(Shape::Arrow { from, to })
+    }
+
+    pub fn rect(self, region: Region) -> Self {
+        self.push(Shape::Rect(region))
+    }
+
+    pub fn ellipse(self, region: Region) -> Self {
+        self.push(Shape::Ellipse(region))
+    }
+
+    pub fn highlight(self, region: Region) -> Self {
+        self.push(Shape::Highlight(region))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// Draws every shape; anything outside the image is clipped
+    pub fn draw(&self, image: &mut RgbaImage) {
+        for (shape, stroke) in &self.shapes {
+            draw_shape(image, shape, stroke);
+        }
+    }
+}
+
+impl Transform for Annotator {
+    fn name(&self) -> &str {
+        "shapes"
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        self.draw(&mut image);
+        Ok(image)
+    }
+}
+
+type Point = (f32, f32);
+
+/// Distance from `p` to the segment `a`-`b`
+fn segment_distance(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0)
+    };
+    let (x, y) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()
+}
+
+/// Distance from `p` to a triangle, 0 inside it
+fn triangle_distance(p: Point, [a, b, c]: [Point; 3]) -> f32 {
+    let side = |p: Point, a: Point, b: Point| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    let (d1, d2, d3) = (side(p, a, b), side(p, b, c), side(p, c, a));
+    let inside = (d1 >= 0.0 && d2 >= 0.0 && d3 >= 0.0) || (d1 <= 0.0 && d2 <= 0.0 && d3 <= 0.0);
+    if inside {
+        0.0
+    } else {
+        segment_distance(p, a, b)
+            .min(segment_distance(p, b, c))
+            .min(segment_distance(p, c, a))
+    }
+}
+
+/// Antialiased coverage of a line `width` wide at `distance` from its centre
+fn line_coverage(distance: f32, width: f32) -> f32 {
+    (width / 2.0 + 0.5 - distance).clamp(0.0, 1.0)
+}
+
+fn draw_shape(image: &mut RgbaImage, shape: &Shape, stroke: &Stroke) {
+    let width = stroke.width as f32;
+    let pad = stroke.width as i64 + 1;
+    let region = |r: &Region| {
+        (
+            (r.x as f32, r.y as f32),
+            (r.right() as f32, r.bottom() as f32),
+        )
+    };
+
+    // Bounding box and per-pixel coverage; pixels are sampled at their centres
+    let (bounds, coverage): (_, Box<dyn Fn(Point) -> f32>) = match *shape {
+        Shape::Arrow { from, to } => {
+            let (a, b) = ((from.0 as f32, from.1 as f32), (to.0 as f32, to.1 as f32));
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let length = (dx * dx + dy * dy).sqrt().max(1.0);
+            let (ux, uy) = (dx / length, dy / length);
+            let head = (width * 4.0).max(12.0).min(length);
+            let base = (b.0 - ux * head, b.1 - uy * head);
+            let half = head * 0.5;
+            let wings = [
+                b,
+                (base.0 - uy * half, base.1 + ux * half),
+                (base.0 + uy * half, base.1 - ux * half),
+            ];
+            let bounds = (
+                (a.0.min(b.0) - head, a.1.min(b.1) - head),
+                (a.0.max(b.0) + head, a.1.max(b.1) + head),
+            );
+            (
+                bounds,
+                Box::new(move |p| {
+                    let shaft = line_coverage(segment_distance(p, a, base), width);
+                    let tip = (1.0 - triangle_distance(p, wings)).clamp(0.0, 1.0);
+                    shaft.max(tip)
+                }),
+            )
+        }
+        Shape::Rect(r) => {
+            let ((left, top), (right, bottom)) = region(&r);
+            let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+            (
+                ((left, top), (right, bottom)),
+                Box::new(move |p| {
+                    (0..4)
+                        .map(|i| segment_distance(p, corners[i], corners[(i + 1) % 4]))
+                        .map(|d| line_coverage(d, width))
+                        .fold(0.0, f32::max)
+                }),
+            )
+        }
+        Shape::Ellipse(r) => {
+            let ((left, top), (right, bottom)) = region(&r);
+            let (cx, cy) = ((left + right) / 2.0, (top + bottom) / 2.0);
+            let (rx, ry) = (
+                ((right - left) / 2.0).max(0.5),
+                ((bottom - top) / 2.0).max(0.5),
+            );
+            (
+                ((left, top), (right, bottom)),
+                Box::new(move |(x, y)| {
+                    // First-order distance to the curve: |f| / |∇f|
+                    let (nx, ny) = ((x - cx) / rx, (y - cy) / ry);
+                    let f = nx * nx + ny * ny - 1.0;
+                    let gradient = ((2.0 * nx / rx).powi(2) + (2.0 * ny / ry).powi(2)).sqrt();
+                    let distance = if gradient == 0.0 {
+                        f32::MAX
+                    } else {
+                        f.abs() / gradient
+                    };
+                    line_coverage(distance, width)
+                }),
+            )
+        }
+        Shape::Highlight(r) => {
+            let ((left, top), (right, bottom)) = region(&r);
+            (
+                ((left, top), (right, bottom)),
+                Box::new(move |(x, y)| {
+                    let inside = x >= left && x < right && y >= top && y < bottom;
+                    inside as u8 as f32
+                }),
+            )
+        }
+    };
+
+    let ((x0, y0), (x1, y1)) = bounds;
+    let clamp_x = |v: f32, pad: i64| (v as i64 + pad).clamp(0, image.width() as i64);
+    let clamp_y = |v: f32, pad: i64| (v as i64 + pad).clamp(0, image.height() as i64);
+    let (x0, x1) = (clamp_x(x0.floor(), -pad), clamp_x(x1.ceil(), pad));
+    let (y0, y1) = (clamp_y(y0.floor(), -pad), clamp_y(y1.ceil(), pad));
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let cover = coverage((x as f32 + 0.5, y as f32 + 0.5));
+            if cover > 0.0 {
+                blend(image.get_pixel_mut(x as u32, y as u32), stroke.color, cover);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn canvas() -> RgbaImage {
+        RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]))
+    }
+
+    /// Fully or almost fully covered by the default stroke
+    fn is_red(image: &RgbaImage, x: u32, y: u32) -> bool {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        let red = Color::RED;
+        r.abs_diff(red.r) < 4 && g.abs_diff(red.g) < 4 && b.abs_diff(red.b) < 4
+    }
+
+    fn is_white(image: &RgbaImage, x: u32, y: u32) -> bool {
+        image.get_pixel(x, y).0 == [255, 255, 255, 255]
+    }
+
+    #[test]
+    fn test_rect_outline() {
+        let image = Annotator::new()
+            .rect(Region::new(20, 20, 40, 30))
+            .apply(canvas())
+            .unwrap();
+
+        assert!(is_red(&image, 20, 35), "Left edge");
+        assert!(is_red(&image, 40, 50), "Bottom edge");
+        assert!(is_white(&image, 40, 35), "Inside stays clear");
+        assert!(is_white(&image, 5, 5), "Outside stays clear");
+    }
+
+    #[test]
+    fn test_ellipse_outline() {
+        let image = Annotator::new()
+            .width(2)
+            .ellipse(Region::new(10, 30, 80, 40))
+            .apply(canvas())
+            .unwrap();
+
+        assert!(is_red(&image, 10, 50), "Leftmost point");
+        assert!(is_red(&image, 49, 30), "Topmost point");
+        assert!(is_white(&image, 50, 50), "Centre");
+        assert!(
+            is_white(&image, 12, 32),
+            "Corner of the box is off the curve"
+        );
+    }
+
+    #[test]
+    fn test_arrow_has_a_head() {
+        let image = Annotator::new()
+            .width(2)
+            .arrow((10, 50), (90, 50))
+            .apply(canvas())
+            .unwrap();
+
+        assert!(is_red(&image, 30, 50), "Shaft");
+        assert!(is_white(&image, 30, 55), "Shaft is thin");
+        assert!(is_red(&image, 84, 52), "Head is wider than the shaft");
+        assert!(is_white(&image, 95, 50), "Nothing past the tip");
+    }
+
+    #[test]
+    fn test_highlight_is_translucent() {
+        let image = Annotator::new()
+            .highlight(Region::new(0, 0, 10, 10))
+            .apply(canvas())
+            .unwrap();
+
+        let [r, g, b, _] = image.get_pixel(5, 5).0;
+        assert_eq!(r, 255);
+        assert!(g > 230 && b > 100 && b < 255, "Tinted yellow: {g} {b}");
+        assert!(is_white(&image, 10, 10));
+    }
+
+    #[test]
+    fn test_offscreen_shapes_are_clipped() {
+        let image = Annotator::new()
+            .arrow((-50, -50), (500, 500))
+            .ellipse(Region::new(-100, -100, 1000, 1000))
+            .apply(RgbaImage::new(20, 20))
+            .unwrap();
+        assert_eq!(image.dimensions(), (20, 20));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "arrow:1,2,-3,4".parse::<Shape>().unwrap(),
+            Shape::Arrow {
+                from: (1, 2),
+                to: (-3, 4)
+            }
+        );
+        assert_eq!(
+            "highlight:1,2,3,4".parse::<Shape>().unwrap(),
+            Shape::Highlight(Region::new(1, 2, 3, 4))
+        );
+        assert!("circle:1,2,3,4".parse::<Shape>().is_err());
+        assert!("arrow:1,2,3".parse::<Shape>().is_err());
+        assert!("rect".parse::<Shape>().is_err());
+    }
+}
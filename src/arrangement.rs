@@ -0,0 +1,321 @@
+//! A text map of how the displays are arranged
+//!
+//! [`render`] draws every display as a box at its place in the desktop's
+//! logical coordinates, scaled down to a terminal's width, and lists each one
+//! below the map with its position, sizes and scale. It's meant for checking
+//! the coordinates `--region` and `pick` take before capturing: monitors
+//! the system thinks are stacked or dislocated show up at a glance.
+
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use std::fmt::Write;
+
+/// Map columns when nothing else is asked for
+pub const DEFAULT_WIDTH: usize = 60;
+
+/// A box needs room for its corners and a label
+const MIN_BOX: (usize, usize) = (6, 3);
+
+/// How the map is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Columns the desktop is scaled to; displays too small for a label
+    /// still get a box that can reach past them
+    pub width: usize,
+    /// Plain ASCII instead of box-drawing characters, for terminals and
+    /// fonts without them
+    pub ascii: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            ascii: false,
+        }
+    }
+}
+
+// The edges a cell of a box joins, so neighbouring boxes share borders
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+#[derive(Clone, Copy)]
+enum Cell {
+    Lines(u8),
+    Text(char),
+}
+
+impl Style {
+    fn line(&self, lines: u8) -> char {
+        let across = lines & (UP | DOWN) == 0;
+        let down = lines & (LEFT | RIGHT) == 0;
+        if self.ascii {
+            return match () {
+                _ if lines == 0 => ' ',
+                _ if across => '-',
+                _ if down => '|',
+                _ => '+',
+            };
+        }
+        match lines {
+            0 => ' ',
+            _ if across => '─',
+            _ if down => '│',
+            l if l == DOWN | RIGHT => '┌',
+            l if l == DOWN | LEFT => '┐',
+            l if l == UP | LEFT => '┘',
+            l if l == UP | RIGHT => '└',
+            l if l == UP | DOWN | RIGHT => '├',
+            l if l == UP | DOWN | LEFT => '┤',
+            l if l == LEFT | RIGHT | DOWN => '┬',
+            l if l == LEFT | RIGHT | UP => '┴',
+            _ => '┼',
+        }
+    }
+
+    fn star(&self) -> char {
+        if self.ascii {
+            '*'
+        } else {
+            '★'
+        }
+    }
+}
+
+fn bounds(display: &DisplayDescriptor) -> Region {
+    Region::new(display.x, display.y, display.width, display.height)
+}
+
+/// The map of `displays` followed by the list of them, or a note that there
+/// are none
+pub fn render(displays: &[DisplayDescriptor], style: Style) -> String {
+    let Some(desktop) = displays.iter().map(bounds).reduce(|a, b| a.union(&b)) else {
+        return "no displays\n".into();
+    };
+    let mut out = map(displays, desktop, style);
+    out.push('\n');
+    out += &list(displays, desktop, style);
+    out
+}
+
+/// The boxes alone, one line per row
+fn map(displays: &[DisplayDescriptor], desktop: Region, style: Style) -> String {
+    // Edges are cells of their own, so the last one ends on the last column
+    let columns = style.width.max(MIN_BOX.0) - 1;
+    let scale = columns as f64 / desktop.width.max(1) as f64;
+    // Terminal cells are about twice as tall as they're wide
+    let row_scale = scale / 2.0;
+    let cell = |logical: i64, scale: f64| (logical as f64 * scale).round() as usize;
+
+    let boxes: Vec<_> = displays
+        .iter()
+        .map(|d| {
+            let (dx, dy) = ((d.x - desktop.x) as i64, (d.y - desktop.y) as i64);
+            let (left, top) = (cell(dx, scale), cell(dy, row_scale));
+            let right = cell(dx + d.width as i64, scale).max(left + MIN_BOX.0 - 1);
+            let bottom = cell(dy + d.height as i64, row_scale).max(top + MIN_BOX.1 - 1);
+            (left, top, right, bottom)
+        })
+        .collect();
+    let width = boxes.iter().map(|b| b.2 + 1).max().unwrap_or(0);
+    let height = boxes.iter().map(|b| b.3 + 1).max().unwrap_or(0);
+    let mut grid = vec![vec![Cell::Lines(0); width]; height];
+    let join = |grid: &mut Vec<Vec<Cell>>, row: usize, col: usize, lines: u8| {
+        let cell: &mut Cell = &mut grid[row][col];
+        *cell = match *cell {
+            Cell::Lines(old) => Cell::Lines(old | lines),
+            Cell::Text(_) => Cell::Lines(lines),
+        };
+    };
+
+    for (index, (d, &(left, top, right, bottom))) in displays.iter().zip(&boxes).enumerate() {
+        // Later displays cover earlier ones they overlap, as mirrors do
+        for row in &mut grid[top + 1..bottom] {
+            row[left + 1..right].fill(Cell::Lines(0));
+        }
+        for col in left + 1..right {
+            join(&mut grid, top, col, LEFT | RIGHT);
+            join(&mut grid, bottom, col, LEFT | RIGHT);
+        }
+        for row in top + 1..bottom {
+            join(&mut grid, row, left, UP | DOWN);
+            join(&mut grid, row, right, UP | DOWN);
+        }
+        join(&mut grid, top, left, DOWN | RIGHT);
+        join(&mut grid, top, right, DOWN | LEFT);
+        join(&mut grid, bottom, left, UP | RIGHT);
+        join(&mut grid, bottom, right, UP | LEFT);
+
+        let mut title = format!("#{index}");
+        if d.is_primary {
+            title += &format!(" {}", style.star());
+        }
+        let labels = [
+            title,
+            format!("{}x{}", d.width, d.height),
+            format!("{},{}", d.x, d.y),
+        ];
+        let room = right - left - 1;
+        for (row, label) in (top + 1..bottom).zip(&labels) {
+            for (col, c) in (left + 1..).zip(label.chars().take(room)) {
+                grid[row][col] = Cell::Text(c);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for row in grid {
+        let line: String = row
+            .into_iter()
+            .map(|cell| match cell {
+                Cell::Lines(lines) => style.line(lines),
+                Cell::Text(c) => c,
+            })
+            .collect();
+        out += line.trim_end();
+        out.push('\n');
+    }
+    out
+}
+
+/// Every display as a small tree, after the desktop they make up
+fn list(displays: &[DisplayDescriptor], desktop: Region, style: Style) -> String {
+    let (branch, last) = if style.ascii {
+        ("|- ", "`- ")
+    } else {
+        ("├─ ", "└─ ")
+    };
+    let mut out = format!(
+        "desktop: {}x{} at {},{}\n",
+        desktop.width, desktop.height, desktop.x, desktop.y
+    );
+    for (index, d) in displays.iter().enumerate() {
+        let physical = (
+            (d.width as f32 * d.scale_factor).round(),
+            (d.height as f32 * d.scale_factor).round(),
+        );
+        let primary = if d.is_primary {
+            format!(" {} primary", style.star())
+        } else {
+            String::new()
+        };
+        let name = match d.name.as_str() {
+            "" => String::new(),
+            name => format!(" ({name})"),
+        };
+        let _ = writeln!(out, "#{index} display {}{name}{primary}", d.id);
+        let _ = writeln!(out, "{branch}position: {},{}", d.x, d.y);
+        let _ = writeln!(out, "{branch}logical: {}x{}", d.width, d.height);
+        let _ = writeln!(
+            out,
+            "{branch}physical: {}x{} at {}x",
+            physical.0, physical.1, d.scale_factor
+        );
+        let _ = writeln!(out, "{last}rotation: {}°", d.rotation);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: u32, x: i32, y: i32, width: u32, height: u32) -> DisplayDescriptor {
+        DisplayDescriptor {
+            id,
+            name: String::new(),
+            x,
+            y,
+            width,
+            height,
+            rotation: 0.0,
+            scale_factor: 1.0,
+            frequency: 60.0,
+            is_primary: id == 1,
+        }
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let mut right = display(2, 1920, 0, 960, 540);
+        right.scale_factor = 2.0;
+        let displays = [display(1, 0, 0, 1920, 1080), right];
+        let style = Style {
+            width: 30,
+            ascii: true,
+        };
+        let text = render(&displays, style);
+        let (map, list) = text.split_once("\n\n").unwrap();
+        assert_eq!(
+            map,
+            "\
++------------------+---------+
+|#0 *              |#1       |
+|1920x1080         |960x540  |
+|0,0               +---------+
+|                  |
++------------------+"
+        );
+        assert_eq!(
+            list,
+            "\
+desktop: 2880x1080 at 0,0
+#0 display 1 * primary
+|- position: 0,0
+|- logical: 1920x1080
+|- physical: 1920x1080 at 1x
+`- rotation: 0°
+#1 display 2
+|- position: 1920,0
+|- logical: 960x540
+|- physical: 1920x1080 at 2x
+`- rotation: 0°
+"
+        );
+    }
+
+    #[test]
+    fn test_negative_origins_and_small_displays() {
+        // A portrait display left of the primary, and one too small to scale
+        let displays = [
+            display(1, 0, 0, 1920, 1080),
+            display(2, -1080, -420, 1080, 1920),
+            display(3, 1920, 0, 10, 10),
+        ];
+        let text = render(&displays, Style::default());
+        let (map, list) = text.split_once("\n\n").unwrap();
+        assert_eq!(
+            map,
+            "\
+┌────────────────────┐
+│#1                  │
+│1080x1920           │
+│-1080,-420          │
+│                    ├─────────────────────────────────────┬────┐
+│                    │#0 ★                                 │#2  │
+│                    │1920x1080                            ├────┘
+│                    │0,0                                  │
+│                    │                                     │
+│                    │                                     │
+│                    │                                     │
+│                    │                                     │
+│                    │                                     │
+│                    │                                     │
+│                    │                                     │
+│                    ├─────────────────────────────────────┘
+│                    │
+│                    │
+│                    │
+└────────────────────┘"
+        );
+        assert!(list.starts_with("desktop: 3010x1920 at -1080,-420\n#0 display 1 ★ primary\n"));
+    }
+
+    #[test]
+    fn test_no_displays() {
+        assert_eq!(render(&[], Style::default()), "no displays\n");
+    }
+}
@@ -0,0 +1,276 @@
+//! Crash-safe file writes
+//!
+//! [`write`] puts the bytes in a temporary file next to the destination,
+//! flushes it to disk and renames it into place. Readers — a watch-mode
+//! consumer, a file watcher, anything after a crash — see either the old file
+//! or the complete new one, never a truncated image. The temporary file lives
+//! in the destination's directory so the rename stays on one filesystem,
+//! where it is atomic. [`write_new`] does the same but never replaces an
+//! existing file, and [`numbered`] finds a free name for one.
+
+use crate::{Error, Result};
+use screenshots::image::{ImageFormat, RgbaImage};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tells apart temporary files of concurrent writes from one process
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Replaces `path` with `contents` atomically
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    write_with(path, |file| Ok(file.write_all(contents.as_ref())?))
+}
+
+/// Like [`write`], but `contents` writes into the file as it goes, so a
+/// streaming encoder never holds the whole file in memory
+pub fn write_with(
+    path: impl AsRef<Path>,
+    contents: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let temp = temp_path(path)?;
+    let result =
+        write_temp(&temp, contents).and_then(|()| fs::rename(&temp, path).map_err(Into::into));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(path);
+    Ok(())
+}
+
+/// Like [`write`], but fails with [`std::io::ErrorKind::AlreadyExists`]
+/// instead of replacing an existing file, even one created concurrently
+pub fn write_new(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    write_new_with(path, |file| Ok(file.write_all(contents.as_ref())?))
+}
+
+/// [`write_new`] for contents written as they're produced, like
+/// [`write_with`]
+pub fn write_new_with(
+    path: impl AsRef<Path>,
+    contents: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let temp = temp_path(path)?;
+    let result = write_temp(&temp, contents).and_then(|()| publish_new(&temp, path));
+    let _ = fs::remove_file(&temp);
+    result?;
+    sync_dir(path);
+    Ok(())
+}
+
+/// Links `temp` to `path`, which unlike a rename never replaces the
+/// destination. FAT, exFAT and some network mounts have no hard links
+/// (Linux's vfat refuses them as a permission error), so there the file is
+/// copied into a newly created `path` instead; a reader could then see it
+/// half written, but an existing file is still never replaced.
+fn publish_new(temp: &Path, path: &Path) -> Result<()> {
+    match fs::hard_link(temp, path) {
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::Unsupported | ErrorKind::PermissionDenied
+            ) =>
+        {
+            copy_new(temp, path)
+        }
+        result => Ok(result?),
+    }
+}
+
+fn copy_new(temp: &Path, path: &Path) -> Result<()> {
+    let mut file = File::options().write(true).create_new(true).open(path)?;
+    let copied = io::copy(&mut File::open(temp)?, &mut file).and_then(|_| file.sync_all());
+    if let Err(e) = copied {
+        let _ = fs::remove_file(path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// The first of `shot.png`, `shot-1.png`, `shot-2.png`, … for which `taken`
+/// is false
+pub fn numbered(path: impl AsRef<Path>, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let path = path.as_ref();
+    if !taken(path) {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1u64..)
+        .map(|n| path.with_file_name(format!("{stem}-{n}{extension}")))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_default()
+}
+
+/// Encodes `image` in the format its extension names and writes it
+/// atomically
+pub fn save(image: &RgbaImage, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path)?;
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, format)?;
+    write(path, bytes.into_inner())
+}
+
+/// `.<name>.<pid>.<n>.tmp` beside `path`; hidden, and unique per write
+fn temp_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::invalid("file path", path.display().to_string()))?;
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp = format!(".{}.{}.{n}.tmp", name.to_string_lossy(), std::process::id());
+    Ok(path.with_file_name(temp))
+}
+
+fn write_temp(temp: &Path, contents: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let file = File::options().write(true).create_new(true).open(temp)?;
+    let mut file = BufWriter::new(file);
+    contents(&mut file)?;
+    let file = file.into_inner().map_err(|e| e.into_error())?;
+    Ok(file.sync_all()?)
+}
+
+/// Makes the rename itself durable; best effort
+#[cfg(unix)]
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("snap_scale_atomic_{test}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_replaces_without_leftovers() {
+        let dir = scratch("replace");
+        let path = dir.join("shot.png");
+        write(&path, b"old").unwrap();
+        write(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1, "No temporary files left behind");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_keeps_the_old_file() {
+        let dir = scratch("fail");
+        let path = dir.join("shot.png");
+        write(&path, b"old").unwrap();
+
+        // A directory can't be renamed over with a file
+        let blocked = dir.join("blocked");
+        fs::create_dir(&blocked).unwrap();
+        assert!(write(&blocked, b"new").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "Temp file removed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_streamed_writes() {
+        let dir = scratch("stream");
+        let path = dir.join("shot.png");
+        write_with(&path, |file| {
+            for chunk in [&b"one "[..], b"two"] {
+                file.write_all(chunk)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"one two");
+
+        // An encoder failing halfway leaves the old file whole
+        let failed = write_with(&path, |file| {
+            file.write_all(b"half")?;
+            Err(Error::invalid("frame", "gone"))
+        });
+        assert!(failed.is_err());
+        assert!(write_new_with(&path, |_| Ok(())).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"one two");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "Temp files removed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_new_never_replaces() {
+        let dir = scratch("new");
+        let path = dir.join("shot.png");
+        write_new(&path, b"first").unwrap();
+
+        let Err(Error::Io(e)) = write_new(&path, b"second") else {
+            panic!("Second write should fail");
+        };
+        assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "Temp file removed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_new_never_replaces() {
+        let dir = scratch("copy");
+        let (temp, path) = (dir.join("temp"), dir.join("shot.png"));
+        fs::write(&temp, b"first").unwrap();
+        copy_new(&temp, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        fs::write(&temp, b"second").unwrap();
+        let Err(Error::Io(e)) = copy_new(&temp, &path) else {
+            panic!("Second copy should fail");
+        };
+        assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_numbered_skips_taken_names() {
+        let taken = ["shot.png", "shot-1.png"].map(PathBuf::from);
+        let free = |path: &str| numbered(path, |p| taken.iter().any(|t| t == p));
+        assert_eq!(free("shot.png"), PathBuf::from("shot-2.png"));
+        assert_eq!(free("other.png"), PathBuf::from("other.png"));
+        assert_eq!(
+            numbered("dir/shot", |p| p == Path::new("dir/shot")),
+            PathBuf::from("dir/shot-1"),
+            "Works without an extension"
+        );
+    }
+
+    #[test]
+    fn test_save_encodes_by_extension() {
+        let dir = scratch("save");
+        let image = RgbaImage::from_pixel(3, 2, screenshots::image::Rgba([1, 2, 3, 255]));
+        save(&image, dir.join("shot.png")).unwrap();
+
+        let decoded = screenshots::image::open(dir.join("shot.png")).unwrap();
+        assert_eq!(decoded.into_rgba8(), image);
+        assert!(save(&image, dir.join("shot.unknown")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,695 @@
+//! Where pixels come from
+//!
+//! Capturing goes through a [`CaptureBackend`], so a platform-native, mock or
+//! remote source of frames can stand in for the `screenshots` crate without
+//! the capture modes noticing. [`Screens`] is the `screenshots` one, and every
+//! backend is also a [`Source`](crate::source::Source) for the servers.
+//!
+//! `SNAP_SCALE_BACKEND` picks the backend [`default_backend`] returns; set it
+//! to `mock`, or `mock:<displays>` for a [`mock::MockBackend`] topology, to
+//! run without a display. Left unset, Wayland sessions get the desktop portal
+//! (with the `wayland` feature), Windows 10 and later gets
+//! Windows.Graphics.Capture, macOS 12.3 and later gets ScreenCaptureKit (with
+//! the `sckit` feature) and everything else gets [`Screens`].
+//!
+//! Whichever it is comes [`Guarded`], its captures retried and timed out as
+//! [`configure`] says, so the command line, the servers and the bindings all
+//! get the same.
+
+pub mod mock;
+#[cfg(all(feature = "wayland", target_os = "linux"))]
+pub mod portal;
+#[cfg(all(feature = "sckit", target_os = "macos"))]
+pub mod sckit;
+#[cfg(target_os = "windows")]
+pub mod wgc;
+
+use crate::buffer::{Frame, FrameBuffer};
+use crate::config::CaptureConfig;
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::preview;
+use crate::retry::RetryPolicy;
+use crate::scaling::ScalingConfig;
+use crate::source::Source;
+use crate::timeout;
+use crate::{Error, Result};
+use mock::MockBackend;
+use screenshots::display_info::DisplayInfo;
+use screenshots::image::RgbaImage;
+use screenshots::Screen;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// A way of listing and capturing displays
+pub trait CaptureBackend: Send + Sync {
+    /// Short name for logs, e.g. `screenshots`
+    fn name(&self) -> &'static str;
+
+    /// Displays in index order
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>>;
+
+    /// Captures all of `display`
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage>;
+
+    /// Captures `area` of `display`, clamped to it
+    ///
+    /// `area` is relative to the display's origin, in the units `screenshots`
+    /// takes: see [`ScalingConfig`] for how logical areas map onto them.
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage>;
+
+    /// Captures all of `display` as a [`Frame`] borrowing `buffer` or the
+    /// backend's own memory
+    ///
+    /// Capturing one frame after another into the same `buffer` saves the
+    /// allocation and copy [`capture_display`](CaptureBackend::capture_display)
+    /// costs each time, on backends that can write into it. The rest capture
+    /// an image and hand its memory over to `buffer`.
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        let timestamp = SystemTime::now();
+        let image = self.capture_display(display)?;
+        Ok(buffer.hold(image, timestamp))
+    }
+
+    /// A small copy of all of `display`, `scale` of its physical size each
+    /// way, `scale` being more than 0 and at most 1
+    ///
+    /// Faster than capturing and resizing: boxes of pixels are averaged
+    /// straight out of [`capture_frame`](CaptureBackend::capture_frame), and
+    /// backends that can capture at a lower resolution do that instead.
+    fn capture_preview(&self, display: &DisplayDescriptor, scale: f32) -> Result<RgbaImage> {
+        let scale = preview::check_scale(scale)?;
+        let mut buffer = FrameBuffer::new();
+        let frame = self.capture_frame(display, &mut buffer)?;
+        Ok(preview::downsample(&frame, scale))
+    }
+
+    /// Forgets anything cached about the displays, so the next
+    /// [`enumerate`](CaptureBackend::enumerate) lists them afresh
+    fn refresh(&self) {}
+
+    /// The display containing the desktop-global logical point `x`,`y`
+    fn display_at(&self, x: i32, y: i32) -> Result<DisplayDescriptor> {
+        self.enumerate()?
+            .into_iter()
+            .find(|d| Region::new(d.x, d.y, d.width, d.height).contains(x, y))
+            .ok_or_else(|| Error::invalid("point", format!("{x},{y} is on no display")))
+    }
+}
+
+/// Environment variable naming the backend, see [`by_name`]
+pub const BACKEND_ENV: &str = "SNAP_SCALE_BACKEND";
+
+/// How [`default_backend`]'s captures are retried and timed out, once
+/// [`configure`]d
+static CAPTURE: Mutex<Option<CaptureConfig>> = Mutex::new(None);
+
+/// Sets how the backends [`default_backend`] returns from now on retry and
+/// time out captures; the `[capture]` defaults apply until then
+pub fn configure(config: &CaptureConfig) {
+    *CAPTURE.lock().unwrap_or_else(PoisonError::into_inner) = Some(config.clone());
+}
+
+/// What [`configure`] set last, the defaults before that
+pub fn capture_config() -> CaptureConfig {
+    CAPTURE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_default()
+}
+
+/// The backend `$SNAP_SCALE_BACKEND` names, [`auto`] when it's unset,
+/// [`Guarded`] as [`configure`] says
+pub fn default_backend() -> Result<Arc<dyn CaptureBackend>> {
+    let backend = match std::env::var(BACKEND_ENV) {
+        Ok(name) if !name.is_empty() => by_name(&name)?,
+        _ => auto(),
+    };
+    let config = capture_config();
+    Ok(Arc::new(Guarded::new(
+        backend,
+        config.retry(),
+        config.timeout(),
+    )))
+}
+
+/// `auto`, `screenshots`, `portal`, `wgc`, `sckit`, `mock` for one 1920x1080
+/// display, or `mock:<displays>` as [`MockBackend`] parses them
+pub fn by_name(name: &str) -> Result<Arc<dyn CaptureBackend>> {
+    match name.split_once(':') {
+        None if name == "auto" => Ok(auto()),
+        None if name == "screenshots" => Ok(Arc::new(Screens)),
+        None if name == "portal" => portal(),
+        None if name == "wgc" => wgc(),
+        None if name == "sckit" => sckit(),
+        None if name == "mock" => Ok(Arc::new(MockBackend::default())),
+        Some(("mock", displays)) => Ok(Arc::new(displays.parse::<MockBackend>()?)),
+        _ => Err(Error::invalid(
+            "backend (expected auto, screenshots, portal, wgc, sckit, mock or mock:<displays>)",
+            name,
+        )),
+    }
+}
+
+/// The desktop portal on Wayland sessions where it's reachable,
+/// Windows.Graphics.Capture or ScreenCaptureKit where the system has them,
+/// [`Screens`] otherwise
+pub fn auto() -> Arc<dyn CaptureBackend> {
+    #[cfg(all(feature = "sckit", target_os = "macos"))]
+    if sckit::ScreenCaptureKit::is_supported() {
+        return Arc::new(sckit::ScreenCaptureKit);
+    }
+    #[cfg(target_os = "windows")]
+    if wgc::WgcBackend::is_supported() {
+        match wgc() {
+            Ok(wgc) => return wgc,
+            Err(e) => tracing::warn!("{e}; capturing through GDI"),
+        }
+    }
+    let session = std::env::var("XDG_SESSION_TYPE").ok();
+    let wayland = std::env::var("WAYLAND_DISPLAY").ok();
+    if is_wayland(session.as_deref(), wayland.as_deref()) {
+        match portal() {
+            Ok(portal) => return portal,
+            Err(e) => tracing::warn!("Wayland session, but {e}; X11 capture may be black"),
+        }
+    }
+    Arc::new(Screens)
+}
+
+/// Whether `$XDG_SESSION_TYPE` and `$WAYLAND_DISPLAY` say the session is
+/// Wayland
+pub fn is_wayland(session_type: Option<&str>, wayland_display: Option<&str>) -> bool {
+    match session_type {
+        Some("wayland") => true,
+        Some("x11") => false,
+        _ => wayland_display.is_some_and(|display| !display.is_empty()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn wgc() -> Result<Arc<dyn CaptureBackend>> {
+    Ok(Arc::new(wgc::WgcBackend::new()?))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn wgc() -> Result<Arc<dyn CaptureBackend>> {
+    Err(Error::Unsupported(
+        "Windows.Graphics.Capture is only available on Windows".into(),
+    ))
+}
+
+#[cfg(all(feature = "sckit", target_os = "macos"))]
+fn sckit() -> Result<Arc<dyn CaptureBackend>> {
+    if !sckit::ScreenCaptureKit::is_supported() {
+        return Err(Error::Unsupported(
+            "ScreenCaptureKit needs macOS 12.3 or later".into(),
+        ));
+    }
+    Ok(Arc::new(sckit::ScreenCaptureKit))
+}
+
+#[cfg(not(all(feature = "sckit", target_os = "macos")))]
+fn sckit() -> Result<Arc<dyn CaptureBackend>> {
+    Err(Error::Unsupported(
+        "ScreenCaptureKit capture requires the `sckit` feature on macOS".into(),
+    ))
+}
+
+#[cfg(all(feature = "wayland", target_os = "linux"))]
+fn portal() -> Result<Arc<dyn CaptureBackend>> {
+    Ok(Arc::new(portal::PortalBackend::new()?))
+}
+
+#[cfg(not(all(feature = "wayland", target_os = "linux")))]
+fn portal() -> Result<Arc<dyn CaptureBackend>> {
+    Err(Error::Unsupported(
+        "portal capture requires the `wayland` feature on Linux".into(),
+    ))
+}
+
+/// The machine's screens, through the `screenshots` crate
+///
+/// Listing screens costs a round of platform queries, so the list is kept
+/// process-wide until [`CaptureBackend::refresh`] or until a capture asks for
+/// a display it doesn't know; a [`DisplayWatcher`](crate::hotplug::DisplayWatcher)
+/// refreshes it as it polls.
+pub struct Screens;
+
+/// What [`Screens`] listed last, with the monitor names
+static SCREENS: Mutex<Option<Vec<(Screen, DisplayDescriptor)>>> = Mutex::new(None);
+
+impl Screens {
+    fn all() -> Result<Vec<(Screen, DisplayDescriptor)>> {
+        let mut cache = SCREENS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(screens) = &*cache {
+            return Ok(screens.clone());
+        }
+        let screens: Vec<_> = Screen::all()
+            .map_err(|e| Error::Unsupported(format!("listing displays: {e}")))?
+            .into_iter()
+            .map(|screen| {
+                let display = DisplayDescriptor {
+                    name: monitor_name(&screen.display_info),
+                    ..(&screen.display_info).into()
+                };
+                (screen, display)
+            })
+            .collect();
+        *cache = Some(screens.clone());
+        Ok(screens)
+    }
+
+    fn screen(display: &DisplayDescriptor) -> Result<Screen> {
+        let find = || {
+            Self::all().map(|screens| {
+                screens
+                    .into_iter()
+                    .map(|(screen, _)| screen)
+                    .find(|screen| screen.display_info.id == display.id)
+            })
+        };
+        let screen = match find()? {
+            Some(screen) => screen,
+            // Plugged in since the list was made, maybe
+            None => {
+                Screens.refresh();
+                find()?.ok_or_else(|| {
+                    Error::invalid("display", format!("display {} is gone", display.id))
+                })?
+            }
+        };
+        Ok(screen)
+    }
+}
+
+/// The RandR output's name, e.g. `HDMI-1`
+#[cfg(target_os = "linux")]
+fn monitor_name(info: &DisplayInfo) -> String {
+    use xcb::randr::GetOutputInfo;
+    let name = || -> Option<String> {
+        let (connection, _) = xcb::Connection::connect(None).ok()?;
+        let cookie = connection.send_request(&GetOutputInfo {
+            output: info.raw_handle,
+            config_timestamp: xcb::x::CURRENT_TIME,
+        });
+        let reply = connection.wait_for_reply(cookie).ok()?;
+        Some(String::from_utf8_lossy(reply.name()).into_owned())
+    };
+    name().unwrap_or_default()
+}
+
+/// The GDI device name, e.g. `\\.\DISPLAY1`
+#[cfg(target_os = "windows")]
+fn monitor_name(info: &DisplayInfo) -> String {
+    use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, HMONITOR, MONITORINFO, MONITORINFOEXW};
+    let mut monitor = MONITORINFOEXW::default();
+    monitor.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    let found = unsafe {
+        GetMonitorInfoW(
+            HMONITOR(info.raw_handle.0),
+            &mut monitor as *mut MONITORINFOEXW as *mut MONITORINFO,
+        )
+    };
+    if !found.as_bool() {
+        return String::new();
+    }
+    let len = monitor.szDevice.iter().position(|&c| c == 0);
+    String::from_utf16_lossy(&monitor.szDevice[..len.unwrap_or(monitor.szDevice.len())])
+}
+
+/// Core Graphics has no names for displays
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn monitor_name(_info: &DisplayInfo) -> String {
+    String::new()
+}
+
+impl CaptureBackend for Screens {
+    fn name(&self) -> &'static str {
+        "screenshots"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        let screens = Self::all()?;
+        Ok(screens.iter().map(|(_, display)| display.clone()).collect())
+    }
+
+    fn refresh(&self) {
+        *SCREENS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        Self::screen(display)?
+            .capture()
+            .map_err(|e| Error::Unsupported(format!("capturing display {}: {e}", display.id)))
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        Self::screen(display)?
+            .capture_area(area.x, area.y, area.width, area.height)
+            .map_err(|e| Error::Unsupported(format!("capturing display {}: {e}", display.id)))
+    }
+}
+
+/// A backend whose captures are tried again when they fail, as a
+/// [`RetryPolicy`] says, each attempt given up on after `timeout`
+///
+/// Attempts run on a thread of their own, see [`timeout::within`]. Frames
+/// captured into a caller's buffer can't, and pass straight through: the
+/// streaming modes that use them capture again at the next frame anyway.
+pub struct Guarded {
+    backend: Arc<dyn CaptureBackend>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+impl Guarded {
+    pub fn new(
+        backend: Arc<dyn CaptureBackend>,
+        retry: RetryPolicy,
+        timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            backend,
+            retry,
+            timeout,
+        }
+    }
+
+    fn attempt<T: Send + 'static>(
+        &self,
+        call: impl Fn(&dyn CaptureBackend) -> Result<T> + Clone + Send + 'static,
+    ) -> Result<T> {
+        self.retry.run(|| {
+            let backend = Arc::clone(&self.backend);
+            let call = call.clone();
+            timeout::within(self.timeout, "capture", move || call(&*backend))
+        })
+    }
+}
+
+impl CaptureBackend for Guarded {
+    fn name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        self.backend.enumerate()
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        let display = display.clone();
+        self.attempt(move |backend| backend.capture_display(&display))
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        let display = display.clone();
+        self.attempt(move |backend| backend.capture_area(&display, area))
+    }
+
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        self.backend.capture_frame(display, buffer)
+    }
+
+    fn capture_preview(&self, display: &DisplayDescriptor, scale: f32) -> Result<RgbaImage> {
+        let display = display.clone();
+        self.attempt(move |backend| backend.capture_preview(&display, scale))
+    }
+
+    fn refresh(&self) {
+        self.backend.refresh()
+    }
+
+    fn display_at(&self, x: i32, y: i32) -> Result<DisplayDescriptor> {
+        self.backend.display_at(x, y)
+    }
+}
+
+/// A shared backend, as [`default_backend`] hands them out
+impl<B: CaptureBackend + ?Sized> CaptureBackend for Arc<B> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        (**self).enumerate()
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        (**self).capture_display(display)
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        (**self).capture_area(display, area)
+    }
+
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        (**self).capture_frame(display, buffer)
+    }
+
+    fn capture_preview(&self, display: &DisplayDescriptor, scale: f32) -> Result<RgbaImage> {
+        (**self).capture_preview(display, scale)
+    }
+
+    fn refresh(&self) {
+        (**self).refresh()
+    }
+
+    fn display_at(&self, x: i32, y: i32) -> Result<DisplayDescriptor> {
+        (**self).display_at(x, y)
+    }
+}
+
+impl<B: CaptureBackend + ?Sized> Source for B {
+    fn displays(&self) -> Result<Vec<DisplayDescriptor>> {
+        self.enumerate()
+    }
+
+    fn capture(&self, index: usize, region: Option<Region>) -> Result<RgbaImage> {
+        let display = self
+            .enumerate()?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| Error::invalid("display", format!("no display #{index}")))?;
+        match region {
+            Some(region) => {
+                let scaling = ScalingConfig::probe(self, &display);
+                let area = Region::new(
+                    scaling.scale_coordinate(region.x),
+                    scaling.scale_coordinate(region.y),
+                    scaling.scale_dimension(region.width),
+                    scaling.scale_dimension(region.height),
+                );
+                self.capture_area(&display, area)
+            }
+            None => self.capture_display(&display),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    /// Two side-by-side 40x30 displays captured at twice their size, each
+    /// filled with its id
+    struct Doubled;
+
+    fn display(id: u32, x: i32) -> DisplayDescriptor {
+        DisplayDescriptor {
+            id,
+            name: String::new(),
+            x,
+            y: 0,
+            width: 40,
+            height: 30,
+            rotation: 0.0,
+            scale_factor: 1.0,
+            frequency: 60.0,
+            is_primary: x == 0,
+        }
+    }
+
+    impl CaptureBackend for Doubled {
+        fn name(&self) -> &'static str {
+            "doubled"
+        }
+
+        fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+            Ok(vec![display(1, 0), display(2, 40)])
+        }
+
+        fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+            self.capture_area(display, Region::new(0, 0, display.width, display.height))
+        }
+
+        fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+            let id = display.id as u8;
+            Ok(RgbaImage::from_pixel(
+                area.width * 2,
+                area.height * 2,
+                Rgba([id, id, id, 255]),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_display_at_finds_the_containing_display() {
+        assert_eq!(Doubled.display_at(45, 10).unwrap().id, 2);
+        assert_eq!(Doubled.display_at(0, 0).unwrap().id, 1);
+        assert!(matches!(
+            Doubled.display_at(80, 0),
+            Err(Error::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_by_name() {
+        assert_eq!(by_name("screenshots").unwrap().name(), "screenshots");
+        let mock = by_name("mock:640x480,800x600").unwrap();
+        assert_eq!((mock.name(), mock.enumerate().unwrap().len()), ("mock", 2));
+        assert_eq!(by_name("mock").unwrap().enumerate().unwrap()[0].width, 1920);
+        for bad in ["", "x11", "mock:", "screenshots:1"] {
+            assert!(by_name(bad).is_err(), "{bad:?}");
+        }
+        #[cfg(not(all(feature = "wayland", target_os = "linux")))]
+        assert!(matches!(by_name("portal"), Err(Error::Unsupported(_))));
+        #[cfg(not(target_os = "windows"))]
+        assert!(matches!(by_name("wgc"), Err(Error::Unsupported(_))));
+        #[cfg(not(all(feature = "sckit", target_os = "macos")))]
+        assert!(matches!(by_name("sckit"), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_is_wayland() {
+        assert!(is_wayland(Some("wayland"), None));
+        assert!(is_wayland(None, Some("wayland-0")));
+        assert!(is_wayland(Some("tty"), Some("wayland-0")));
+        // A stray WAYLAND_DISPLAY doesn't make an X11 session Wayland
+        assert!(!is_wayland(Some("x11"), Some("wayland-0")));
+        assert!(!is_wayland(None, Some("")));
+        assert!(!is_wayland(None, None));
+    }
+
+    #[test]
+    fn test_backends_are_sources() {
+        let source: &dyn Source = &Doubled;
+        assert_eq!(source.displays().unwrap().len(), 2);
+        let whole = source.capture(1, None).unwrap();
+        assert_eq!(
+            (whole.dimensions(), whole.get_pixel(0, 0)[0]),
+            ((80, 60), 2)
+        );
+
+        // The probe sees frames twice the size asked for, so a logical area
+        // asks for twice its size and comes back four times it
+        let area = source.capture(0, Some(Region::new(0, 0, 10, 5))).unwrap();
+        assert_eq!(area.dimensions(), (40, 20));
+        assert!(matches!(
+            source.capture(2, None),
+            Err(Error::Invalid { .. })
+        ));
+    }
+
+    /// Fails captures until the `failures`th has failed
+    struct Flaky {
+        failures: u32,
+        attempts: std::sync::atomic::AtomicU32,
+    }
+
+    impl CaptureBackend for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+            Doubled.enumerate()
+        }
+
+        fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+            self.capture_area(display, Region::new(0, 0, 1, 1))
+        }
+
+        fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+            use std::sync::atomic::Ordering;
+            let attempt = self.attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            if attempt <= self.failures {
+                return Err(Error::Unsupported(format!("display asleep ({attempt})")));
+            }
+            Doubled.capture_area(display, area)
+        }
+    }
+
+    #[test]
+    fn test_guarded_captures_are_retried() {
+        let flaky = |failures| Flaky {
+            failures,
+            attempts: Default::default(),
+        };
+        let retry = RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+            backoff: 1.0,
+        };
+        let display = display(1, 0);
+        let guarded = Guarded::new(Arc::new(flaky(2)), retry, None);
+        assert!(guarded.capture_display(&display).is_ok());
+        let source: &dyn Source = &guarded;
+        assert!(source.capture(0, Some(Region::new(0, 0, 4, 4))).is_ok());
+
+        let guarded = Guarded::new(Arc::new(flaky(3)), retry, Some(Duration::from_secs(5)));
+        let err = guarded.capture_display(&display).unwrap_err();
+        assert!(
+            matches!(err, Error::Retries { ref failures } if failures.len() == 3),
+            "{err}"
+        );
+    }
+
+    /// Never returns from a capture, as an unanswered portal dialog
+    struct Hung;
+
+    impl CaptureBackend for Hung {
+        fn name(&self) -> &'static str {
+            "hung"
+        }
+
+        fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+            Doubled.enumerate()
+        }
+
+        fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+            self.capture_area(display, Region::new(0, 0, 1, 1))
+        }
+
+        fn capture_area(&self, _: &DisplayDescriptor, _: Region) -> Result<RgbaImage> {
+            std::thread::sleep(Duration::from_secs(5));
+            Err(Error::Unsupported("too late".into()))
+        }
+    }
+
+    #[test]
+    fn test_guarded_captures_time_out() {
+        let guarded = Guarded::new(
+            Arc::new(Hung),
+            RetryPolicy::default(),
+            Some(Duration::from_millis(20)),
+        );
+        let source: &dyn Source = &guarded;
+        let err = source.capture(0, None).unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{err}");
+    }
+}
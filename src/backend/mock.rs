@@ -0,0 +1,331 @@
+//! Synthetic displays for machines without any
+//!
+//! [`MockBackend`] answers like a real desktop of the given topology, painting
+//! every frame from a [`Pattern`] over desktop-global physical coordinates,
+//! so the same pixel reads the same whether it came from a whole-display
+//! capture or an area, and a run gives identical bytes on every machine.
+//! Like the `screenshots` crate, areas are taken in logical units and frames
+//! come back in physical pixels.
+
+use crate::backend::CaptureBackend;
+use crate::buffer::{Frame, FrameBuffer, PixelFormat, BYTES_PER_PIXEL};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::preview;
+use crate::{Error, Result};
+use screenshots::image::{Rgba, RgbaImage};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What the synthetic frames show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Red rising left to right and green top to bottom across each display,
+    /// with blue telling displays apart
+    Gradient,
+    /// Black and white squares of this many physical pixels
+    Checkerboard(u32),
+    /// One color everywhere
+    Solid([u8; 4]),
+}
+
+/// A desktop of synthetic displays
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockBackend {
+    displays: Vec<DisplayDescriptor>,
+    pattern: Pattern,
+}
+
+impl MockBackend {
+    /// A desktop of `displays`, painted with [`Pattern::Gradient`]
+    pub fn new(displays: Vec<DisplayDescriptor>) -> Self {
+        Self {
+            displays,
+            pattern: Pattern::Gradient,
+        }
+    }
+
+    /// One primary display of `width`x`height` at scale 1
+    pub fn single(width: u32, height: u32) -> Self {
+        Self::new(vec![display(1, Region::new(0, 0, width, height), 1.0)])
+    }
+
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    fn pixel(&self, index: usize, display: &DisplayDescriptor, x: u32, y: u32) -> Rgba<u8> {
+        let (width, height) = physical_size(display);
+        match self.pattern {
+            Pattern::Gradient => Rgba([
+                (x * 255 / width.saturating_sub(1).max(1)) as u8,
+                (y * 255 / height.saturating_sub(1).max(1)) as u8,
+                (index as u32 * 64 % 256) as u8,
+                255,
+            ]),
+            Pattern::Checkerboard(size) => {
+                let size = size.max(1);
+                let global_x = (display.x as f32 * display.scale_factor) as i64 + x as i64;
+                let global_y = (display.y as f32 * display.scale_factor) as i64 + y as i64;
+                if (global_x.div_euclid(size as i64) + global_y.div_euclid(size as i64)) % 2 == 0 {
+                    Rgba([255, 255, 255, 255])
+                } else {
+                    Rgba([0, 0, 0, 255])
+                }
+            }
+            Pattern::Solid(color) => Rgba(color),
+        }
+    }
+
+    fn index(&self, display: &DisplayDescriptor) -> Result<usize> {
+        self.displays
+            .iter()
+            .position(|d| d.id == display.id)
+            .ok_or_else(|| Error::invalid("display", format!("display {} is gone", display.id)))
+    }
+}
+
+impl Default for MockBackend {
+    /// One 1920x1080 display
+    fn default() -> Self {
+        Self::single(1920, 1080)
+    }
+}
+
+impl FromStr for MockBackend {
+    type Err = Error;
+
+    /// Parses comma-separated displays, `WxH[@SCALE][+X+Y]` each
+    ///
+    /// Displays without a position sit to the right of the previous one; the
+    /// first is primary, ids count from 1 and display 1 is named `MOCK-1`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut displays = Vec::new();
+        let mut next_x = 0;
+        for part in s.split(',').map(str::trim) {
+            let invalid = || Error::invalid("mock display (expected e.g. 1920x1080@2+0+0)", part);
+            let (size, position) = match part.split_once('+') {
+                Some((size, position)) => (size, Some(position)),
+                None => (part, None),
+            };
+            let (size, scale) = match size.split_once('@') {
+                Some((size, scale)) => (size, scale.parse().map_err(|_| invalid())?),
+                None => (size, 1.0),
+            };
+            let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+            let (width, height): (u32, u32) = (
+                width.parse().map_err(|_| invalid())?,
+                height.parse().map_err(|_| invalid())?,
+            );
+            let (x, y) = match position {
+                Some(position) => {
+                    let (x, y) = position.split_once('+').ok_or_else(invalid)?;
+                    (
+                        x.parse().map_err(|_| invalid())?,
+                        y.parse().map_err(|_| invalid())?,
+                    )
+                }
+                None => (next_x, 0),
+            };
+            let bounds = Region::new(x, y, width, height);
+            if bounds.is_empty() || !(scale > 0.0 && scale <= 8.0) {
+                return Err(invalid());
+            }
+            next_x = bounds.right() as i32;
+            displays.push(display(displays.len() as u32 + 1, bounds, scale));
+        }
+        Ok(Self::new(displays))
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        Ok(self.displays.clone())
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        self.capture_area(display, Region::new(0, 0, display.width, display.height))
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        let index = self.index(display)?;
+        let display = &self.displays[index];
+        let area = area
+            .clamp_to(&Region::new(0, 0, display.width, display.height))
+            .ok_or_else(|| Error::invalid("area", "outside the display"))?;
+        let scale = display.scale_factor;
+        let (left, top) = (
+            (area.x as f32 * scale) as u32,
+            (area.y as f32 * scale) as u32,
+        );
+        let width = (area.width as f32 * scale) as u32;
+        let height = (area.height as f32 * scale) as u32;
+        Ok(RgbaImage::from_fn(width.max(1), height.max(1), |x, y| {
+            self.pixel(index, display, left + x, top + y)
+        }))
+    }
+
+    /// Paints straight into `buffer`
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        let timestamp = SystemTime::now();
+        let index = self.index(display)?;
+        let display = &self.displays[index];
+        let (width, height) = physical_size(display);
+        let (width, height) = (width.max(1), height.max(1));
+        let data = buffer.resize(width as usize * height as usize * BYTES_PER_PIXEL);
+        for (i, pixel) in data.chunks_exact_mut(BYTES_PER_PIXEL).enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            pixel.copy_from_slice(&self.pixel(index, display, x, y).0);
+        }
+        buffer.frame(
+            width,
+            height,
+            width as usize * BYTES_PER_PIXEL,
+            PixelFormat::Rgba8,
+            timestamp,
+        )
+    }
+
+    /// Paints only the pixels at the middle of each box
+    fn capture_preview(&self, display: &DisplayDescriptor, scale: f32) -> Result<RgbaImage> {
+        let scale = preview::check_scale(scale)?;
+        let index = self.index(display)?;
+        let display = &self.displays[index];
+        let (width, height) = physical_size(display);
+        let (out_width, out_height) = preview::preview_size(width, height, scale);
+        let middle = |i: u32, source: u32, out: u32| {
+            ((2 * i as u64 + 1) * source as u64 / (2 * out as u64)) as u32
+        };
+        Ok(RgbaImage::from_fn(out_width, out_height, |x, y| {
+            let x = middle(x, width, out_width);
+            let y = middle(y, height, out_height);
+            self.pixel(index, display, x, y)
+        }))
+    }
+}
+
+fn display(id: u32, bounds: Region, scale_factor: f32) -> DisplayDescriptor {
+    DisplayDescriptor {
+        id,
+        name: format!("MOCK-{id}"),
+        x: bounds.x,
+        y: bounds.y,
+        width: bounds.width,
+        height: bounds.height,
+        rotation: 0.0,
+        scale_factor,
+        frequency: 60.0,
+        is_primary: id == 1,
+    }
+}
+
+fn physical_size(display: &DisplayDescriptor) -> (u32, u32) {
+    (
+        (display.width as f32 * display.scale_factor) as u32,
+        (display.height as f32 * display.scale_factor) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Source;
+
+    #[test]
+    fn test_parse_topologies() {
+        let backend: MockBackend = "1920x1080, 1280x720@2".parse().unwrap();
+        let displays = backend.enumerate().unwrap();
+        assert_eq!(displays.len(), 2);
+        assert_eq!(
+            (displays[1].id, displays[1].x, displays[1].scale_factor),
+            (2, 1920, 2.0),
+            "Laid out left to right"
+        );
+        assert!(displays[0].is_primary && !displays[1].is_primary);
+
+        let stacked: MockBackend = "800x600+0+0,800x600+0+600".parse().unwrap();
+        let below = &stacked.enumerate().unwrap()[1];
+        assert_eq!((below.x, below.y), (0, 600));
+        for bad in ["", "1920", "0x1080", "1920x1080@0", "1920x1080+5", "axb"] {
+            assert!(bad.parse::<MockBackend>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_areas_match_whole_frames() {
+        let backend: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let displays = backend.enumerate().unwrap();
+        let whole = backend.capture_display(&displays[1]).unwrap();
+        assert_eq!(whole.dimensions(), (64, 48), "Physical pixels");
+        let area = backend
+            .capture_area(&displays[1], Region::new(4, 2, 8, 8))
+            .unwrap();
+        assert_eq!(area.dimensions(), (16, 16));
+        assert_eq!(area.get_pixel(0, 0), whole.get_pixel(8, 4));
+        assert_eq!(whole.get_pixel(0, 0).0, [0, 0, 64, 255]);
+        assert_eq!(whole.get_pixel(63, 47).0, [255, 255, 64, 255]);
+
+        let clamped = backend
+            .capture_area(&displays[0], Region::new(60, 40, 100, 100))
+            .unwrap();
+        assert_eq!(clamped.dimensions(), (4, 8));
+        assert!(backend
+            .capture_area(&displays[0], Region::new(70, 0, 5, 5))
+            .is_err());
+    }
+
+    #[test]
+    fn test_frames_match_captures() {
+        let backend: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let mut buffer = FrameBuffer::new();
+        for display in backend.enumerate().unwrap() {
+            let frame = backend.capture_frame(&display, &mut buffer).unwrap();
+            assert_eq!(frame.format(), PixelFormat::Rgba8);
+            assert_eq!(frame.stride(), 64 * 4);
+            assert_eq!(frame.to_image(), backend.capture_display(&display).unwrap());
+        }
+        let capacity = buffer.capacity();
+        let display = &backend.enumerate().unwrap()[0];
+        backend.capture_frame(display, &mut buffer).unwrap();
+        assert_eq!(buffer.capacity(), capacity, "The buffer is reused");
+    }
+
+    #[test]
+    fn test_previews() {
+        let backend: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let display = &backend.enumerate().unwrap()[1];
+        let preview = backend.capture_preview(display, 0.25).unwrap();
+        assert_eq!(preview.dimensions(), (16, 12), "A quarter of 64x48");
+        let whole = backend.capture_display(display).unwrap();
+        assert_eq!(preview.get_pixel(0, 0), whole.get_pixel(2, 2));
+        assert_eq!(preview.get_pixel(15, 11), whole.get_pixel(62, 46));
+        assert!(backend.capture_preview(display, 2.0).is_err());
+    }
+
+    #[test]
+    fn test_patterns_are_deterministic() {
+        let checkers = MockBackend::single(16, 16).with_pattern(Pattern::Checkerboard(4));
+        let display = &checkers.enumerate().unwrap()[0];
+        let frame = checkers.capture_display(display).unwrap();
+        assert_eq!(frame.get_pixel(0, 0).0, [255; 4]);
+        assert_eq!(frame.get_pixel(4, 0).0, [0, 0, 0, 255]);
+        assert_eq!(frame, checkers.capture_display(display).unwrap());
+
+        let solid = MockBackend::default().with_pattern(Pattern::Solid([1, 2, 3, 4]));
+        let frame = Source::capture(&solid, 0, Some(Region::new(0, 0, 2, 2))).unwrap();
+        assert!(frame.pixels().all(|p| p.0 == [1, 2, 3, 4]));
+        assert!(matches!(
+            solid.display_at(1920, 0),
+            Err(Error::Invalid { .. })
+        ));
+    }
+}
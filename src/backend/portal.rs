@@ -0,0 +1,265 @@
+//! Capture through xdg-desktop-portal on Wayland
+//!
+//! Wayland compositors don't let clients read the screen, so on a Wayland
+//! session X11-based capture only sees XWayland windows and returns black
+//! frames elsewhere. [`PortalBackend`] asks the desktop portal's
+//! `org.freedesktop.portal.Screenshot` for a non-interactive screenshot of the
+//! whole desktop, which GNOME, KDE and the wlroots portals (through
+//! wlr-screencopy) all answer, and crops each display out of it.
+//!
+//! Displays are still listed through [`Screens`], as the portal has no way to
+//! enumerate them. Every capture is a full-desktop screenshot, so capturing
+//! costs about the same whatever the area.
+
+use super::{CaptureBackend, Screens};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use screenshots::image::{self, RgbaImage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
+/// Screenshots through the desktop portal, cropped to a display
+pub struct PortalBackend {
+    connection: Connection,
+}
+
+impl PortalBackend {
+    /// Connects to the session bus
+    pub fn new() -> Result<Self> {
+        let connection = Connection::session()
+            .map_err(|e| Error::Unsupported(format!("D-Bus session bus: {e}")))?;
+        Ok(Self { connection })
+    }
+
+    /// The whole desktop, as the portal hands it over
+    fn screenshot(&self) -> Result<RgbaImage> {
+        let failed = |e: zbus::Error| Error::Unsupported(format!("desktop portal: {e}"));
+
+        // Subscribe to the request's response before asking, or a fast portal
+        // answers before anyone listens
+        let token = format!("snap_scale_{}", std::process::id());
+        let sender = self
+            .connection
+            .unique_name()
+            .ok_or_else(|| Error::Unsupported("desktop portal: no bus name".into()))?;
+        let request = request_path(sender.as_str(), &token);
+        let request = Proxy::new(
+            &self.connection,
+            DESTINATION,
+            request.as_str(),
+            "org.freedesktop.portal.Request",
+        )
+        .map_err(failed)?;
+        let mut responses = request.receive_signal("Response").map_err(failed)?;
+
+        let portal = Proxy::new(
+            &self.connection,
+            DESTINATION,
+            PATH,
+            "org.freedesktop.portal.Screenshot",
+        )
+        .map_err(failed)?;
+        let options = HashMap::from([
+            ("handle_token", Value::from(token.as_str())),
+            ("interactive", Value::from(false)),
+        ]);
+        let _: OwnedObjectPath = portal.call("Screenshot", &("", options)).map_err(failed)?;
+
+        let response = responses
+            .next()
+            .ok_or_else(|| Error::Unsupported("desktop portal: no response".into()))?;
+        let (status, results): (u32, HashMap<String, OwnedValue>) =
+            response.body().deserialize().map_err(failed)?;
+        if status != 0 {
+            return Err(Error::Unsupported(format!(
+                "desktop portal refused the screenshot (response {status})"
+            )));
+        }
+        let uri = results
+            .get("uri")
+            .and_then(|uri| String::try_from(uri.clone()).ok())
+            .ok_or_else(|| Error::Unsupported("desktop portal: no screenshot uri".into()))?;
+
+        let path = file_path(&uri)?;
+        let screenshot = image::open(&path);
+        // The portal leaves it in ~/Pictures, where nobody asked for it
+        let _ = std::fs::remove_file(&path);
+        Ok(screenshot?.to_rgba8())
+    }
+
+    fn crop(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        let displays = self.enumerate()?;
+        let desktop = displays
+            .iter()
+            .map(|d| Region::new(d.x, d.y, d.width, d.height))
+            .reduce(|a, b| a.union(&b))
+            .ok_or_else(|| Error::Unsupported("no displays".into()))?;
+        let screenshot = self.screenshot()?;
+        let bounds = crop_box(desktop, screenshot.dimensions(), display, area);
+        Ok(image::imageops::crop_imm(
+            &screenshot,
+            bounds.x as u32,
+            bounds.y as u32,
+            bounds.width,
+            bounds.height,
+        )
+        .to_image())
+    }
+}
+
+impl CaptureBackend for PortalBackend {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        Screens.enumerate()
+    }
+
+    fn refresh(&self) {
+        Screens.refresh()
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        self.crop(display, Region::new(0, 0, display.width, display.height))
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        self.crop(display, area)
+    }
+}
+
+/// Where the portal announces the response to the request `token` of the
+/// connection named `sender`
+fn request_path(sender: &str, token: &str) -> String {
+    let sender = sender.trim_start_matches(':').replace('.', "_");
+    format!("{PATH}/request/{sender}/{token}")
+}
+
+/// The local path of a `file://` uri, percent-decoded
+fn file_path(uri: &str) -> Result<PathBuf> {
+    let invalid = || Error::invalid("screenshot uri", uri);
+    let encoded = uri.strip_prefix("file://").ok_or_else(invalid)?.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [
+                *bytes.next().ok_or_else(invalid)?,
+                *bytes.next().ok_or_else(invalid)?,
+            ];
+            let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    let path = String::from_utf8(decoded).map_err(|_| invalid())?;
+    Ok(PathBuf::from(path))
+}
+
+/// The pixels of `area` of `display` in a screenshot of `desktop` that came
+/// back `size` pixels large, clamped to the screenshot
+///
+/// Portals return the desktop at its physical size, so one ratio maps the
+/// logical layout onto the screenshot.
+fn crop_box(
+    desktop: Region,
+    size: (u32, u32),
+    display: &DisplayDescriptor,
+    area: Region,
+) -> Region {
+    let ratio = size.0 as f32 / desktop.width.max(1) as f32;
+    let scale = |logical: i32| (logical as f32 * ratio).round() as i64;
+    let clamp = |pixel: i64, max: u32| pixel.clamp(0, max as i64) as u32;
+
+    // Clamped to the display first, so areas don't spill onto its neighbours
+    let local = |start: i32, length: u32, max: u32| {
+        let start = start.clamp(0, max as i32);
+        (start, (start as i64 + length as i64).min(max as i64) as i32)
+    };
+    let (left, right) = local(area.x, area.width, display.width);
+    let (top, bottom) = local(area.y, area.height, display.height);
+    let (dx, dy) = (display.x - desktop.x, display.y - desktop.y);
+    let (left, right, top, bottom) = (left + dx, right + dx, top + dy, bottom + dy);
+
+    let (x0, y0) = (clamp(scale(left), size.0), clamp(scale(top), size.1));
+    let (x1, y1) = (clamp(scale(right), size.0), clamp(scale(bottom), size.1));
+    Region::new(x0 as i32, y0 as i32, x1 - x0, y1 - y0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(x: i32, width: u32, height: u32) -> DisplayDescriptor {
+        DisplayDescriptor {
+            id: 1,
+            name: String::new(),
+            x,
+            y: 0,
+            width,
+            height,
+            rotation: 0.0,
+            scale_factor: 2.0,
+            frequency: 60.0,
+            is_primary: x == 0,
+        }
+    }
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(
+            request_path(":1.42", "snap_scale_7"),
+            "/org/freedesktop/portal/desktop/request/1_42/snap_scale_7"
+        );
+    }
+
+    #[test]
+    fn test_file_path() {
+        assert_eq!(
+            file_path("file:///home/me/Pictures/Screenshot%20from%20today.png").unwrap(),
+            PathBuf::from("/home/me/Pictures/Screenshot from today.png")
+        );
+        for bad in [
+            "/tmp/a.png",
+            "https://x/a.png",
+            "file:///a%2",
+            "file:///a%zz",
+        ] {
+            assert!(file_path(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_crop_box() {
+        // Two 100x50 logical displays side by side, captured at twice the size
+        let desktop = Region::new(0, 0, 200, 50);
+        let size = (400, 100);
+        let right = display(100, 100, 50);
+        assert_eq!(
+            crop_box(desktop, size, &right, Region::new(0, 0, 100, 50)),
+            Region::new(200, 0, 200, 100)
+        );
+        assert_eq!(
+            crop_box(desktop, size, &right, Region::new(10, 5, 20, 10)),
+            Region::new(220, 10, 40, 20)
+        );
+        // Areas running off a display stop at its edge
+        assert_eq!(
+            crop_box(desktop, size, &right, Region::new(90, 40, 100, 100)),
+            Region::new(380, 80, 20, 20)
+        );
+        let left = display(0, 100, 50);
+        assert_eq!(
+            crop_box(desktop, size, &left, Region::new(-10, 0, 200, 50)),
+            Region::new(0, 0, 200, 100)
+        );
+    }
+}
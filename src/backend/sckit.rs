@@ -0,0 +1,290 @@
+//! Capture through ScreenCaptureKit on macOS 12.3 and later
+//!
+//! [`ScreenCaptureKit`] starts a stream for each capture, takes its first
+//! frame and stops it again. Areas are captured natively through the
+//! stream's source rect rather than cropped out of the whole display, and
+//! windows can be captured on their own, covered parts included.
+//!
+//! Capturing needs the Screen Recording permission. Without it the first
+//! capture shows the system prompt and fails with the setting to change;
+//! macOS only applies a new grant once the process restarts.
+//!
+//! Built with the `sckit` feature, as linking ScreenCaptureKit keeps the
+//! binary from starting on older macOS. Displays are listed through
+//! [`Screens`].
+
+use super::{CaptureBackend, Screens};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::preview;
+use crate::{Error, Result};
+use block2::RcBlock;
+use dispatch2::DispatchQueue;
+use objc2::rc::Retained;
+use objc2::runtime::{AnyClass, ProtocolObject};
+use objc2::{define_class, msg_send, AllocAnyThread, DefinedClass};
+use objc2_core_foundation::{CGPoint, CGRect, CGSize};
+use objc2_core_graphics::{CGPreflightScreenCaptureAccess, CGRequestScreenCaptureAccess};
+use objc2_core_media::CMSampleBuffer;
+use objc2_core_video::{
+    kCVPixelFormatType_32BGRA, CVPixelBuffer, CVPixelBufferGetBaseAddress,
+    CVPixelBufferGetBytesPerRow, CVPixelBufferGetHeight, CVPixelBufferGetWidth,
+    CVPixelBufferLockBaseAddress, CVPixelBufferLockFlags, CVPixelBufferUnlockBaseAddress,
+};
+use objc2_foundation::{NSArray, NSError, NSObject, NSObjectProtocol};
+use objc2_screen_capture_kit::{
+    SCContentFilter, SCShareableContent, SCStream, SCStreamConfiguration, SCStreamOutput,
+    SCStreamOutputType,
+};
+use screenshots::image::RgbaImage;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long to wait for ScreenCaptureKit's callbacks
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Displays and windows captured through ScreenCaptureKit
+pub struct ScreenCaptureKit;
+
+impl ScreenCaptureKit {
+    /// Whether this macOS has ScreenCaptureKit
+    pub fn is_supported() -> bool {
+        AnyClass::get(c"SCStream").is_some()
+    }
+
+    /// Captures a window by its window number, even where others cover it
+    pub fn capture_window(&self, id: u64) -> Result<RgbaImage> {
+        authorize()?;
+        let content = shareable_content()?;
+        let window = unsafe { content.windows() }
+            .iter()
+            .find(|window| unsafe { window.windowID() } as u64 == id)
+            .ok_or_else(|| Error::invalid("window", format!("no window {id}")))?;
+        let frame = unsafe { window.frame() };
+        let x = frame.origin.x + frame.size.width / 2.0;
+        let y = frame.origin.y + frame.size.height / 2.0;
+        let scale = Screens
+            .display_at(x as i32, y as i32)
+            .map_or(1.0, |display| display.scale_factor as f64);
+        let filter = unsafe {
+            SCContentFilter::initWithDesktopIndependentWindow(SCContentFilter::alloc(), &window)
+        };
+        let config = unsafe { SCStreamConfiguration::new() };
+        unsafe {
+            config.setWidth((frame.size.width * scale).round() as usize);
+            config.setHeight((frame.size.height * scale).round() as usize);
+        }
+        capture(&filter, &config)
+    }
+}
+
+impl CaptureBackend for ScreenCaptureKit {
+    fn name(&self) -> &'static str {
+        "sckit"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        Screens.enumerate()
+    }
+
+    fn refresh(&self) {
+        Screens.refresh()
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        self.capture_area(display, Region::new(0, 0, display.width, display.height))
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        self.capture_scaled(display, area, display.scale_factor as f64)
+    }
+
+    /// Has ScreenCaptureKit scale the frame down before handing it over
+    fn capture_preview(&self, display: &DisplayDescriptor, scale: f32) -> Result<RgbaImage> {
+        let scale = preview::check_scale(scale)?;
+        let area = Region::new(0, 0, display.width, display.height);
+        self.capture_scaled(display, area, display.scale_factor as f64 * scale as f64)
+    }
+}
+
+impl ScreenCaptureKit {
+    /// Captures `area` of `display` at `scale` pixels per point
+    fn capture_scaled(
+        &self,
+        display: &DisplayDescriptor,
+        area: Region,
+        scale: f64,
+    ) -> Result<RgbaImage> {
+        authorize()?;
+        let bounds = Region::new(0, 0, display.width, display.height);
+        let area = area
+            .clamp_to(&bounds)
+            .ok_or_else(|| Error::invalid("area", format!("{area:?} is off the display")))?;
+
+        let content = shareable_content()?;
+        let target = unsafe { content.displays() }
+            .iter()
+            .find(|d| unsafe { d.displayID() } == display.id)
+            .ok_or_else(|| Error::invalid("display", format!("display {} is gone", display.id)))?;
+        let filter = unsafe {
+            SCContentFilter::initWithDisplay_excludingWindows(
+                SCContentFilter::alloc(),
+                &target,
+                &NSArray::new(),
+            )
+        };
+        // The source rect is in points, the output size in pixels
+        let config = unsafe { SCStreamConfiguration::new() };
+        unsafe {
+            config.setSourceRect(CGRect::new(
+                CGPoint::new(area.x as f64, area.y as f64),
+                CGSize::new(area.width as f64, area.height as f64),
+            ));
+            config.setWidth(((area.width as f64 * scale).round() as usize).max(1));
+            config.setHeight(((area.height as f64 * scale).round() as usize).max(1));
+        }
+        capture(&filter, &config)
+    }
+}
+
+/// Fails with what to do when Screen Recording isn't allowed, asking for it
+/// the first time
+fn authorize() -> Result<()> {
+    if CGPreflightScreenCaptureAccess() {
+        return Ok(());
+    }
+    // Shows the prompt once; later calls return straight away
+    CGRequestScreenCaptureAccess();
+    Err(Error::Unsupported(
+        "screen capture needs the Screen Recording permission: allow this app (or your \
+         terminal) in System Settings > Privacy & Security > Screen Recording, then start it again"
+            .into(),
+    ))
+}
+
+fn failed(error: *mut NSError) -> Error {
+    let reason = unsafe { error.as_ref() }.map_or_else(
+        || "unknown error".into(),
+        |e| e.localizedDescription().to_string(),
+    );
+    Error::Unsupported(format!("ScreenCaptureKit: {reason}"))
+}
+
+fn wait<T>(receiver: &Receiver<T>) -> Result<T> {
+    receiver
+        .recv_timeout(TIMEOUT)
+        .map_err(|_| Error::Unsupported("ScreenCaptureKit didn't answer".into()))
+}
+
+/// The displays and windows this process may capture
+fn shareable_content() -> Result<Retained<SCShareableContent>> {
+    let (sender, receiver) = mpsc::channel();
+    let handler = RcBlock::new(
+        move |content: *mut SCShareableContent, error: *mut NSError| {
+            let content = unsafe { Retained::retain(content) }.ok_or_else(|| failed(error));
+            let _ = sender.send(content);
+        },
+    );
+    unsafe { SCShareableContent::getShareableContentWithCompletionHandler(&handler) };
+    wait(&receiver)?
+}
+
+/// The first frame a stream of `filter` delivers
+fn capture(filter: &SCContentFilter, config: &SCStreamConfiguration) -> Result<RgbaImage> {
+    unsafe {
+        config.setPixelFormat(kCVPixelFormatType_32BGRA);
+        config.setShowsCursor(false);
+    }
+    let (frames, frame) = mpsc::sync_channel(1);
+    let output = FrameOutput::new(frames);
+    let stream = unsafe {
+        SCStream::initWithFilter_configuration_delegate(SCStream::alloc(), filter, config, None)
+    };
+    let queue = DispatchQueue::new("org.snapscale.frames", None);
+    unsafe {
+        stream.addStreamOutput_type_sampleHandlerQueue_error(
+            ProtocolObject::from_ref(&*output),
+            SCStreamOutputType::Screen,
+            Some(&queue),
+        )
+    }
+    .map_err(|e| failed(Retained::as_ptr(&e) as *mut NSError))?;
+
+    let (sender, started) = mpsc::channel();
+    let handler = RcBlock::new(move |error: *mut NSError| {
+        let _ = sender.send(if error.is_null() {
+            Ok(())
+        } else {
+            Err(failed(error))
+        });
+    });
+    unsafe { stream.startCaptureWithCompletionHandler(Some(&handler)) };
+    let image = wait(&started)?.and_then(|()| wait(&frame)?);
+    unsafe { stream.stopCaptureWithCompletionHandler(None) };
+    image
+}
+
+define_class!(
+    // SAFETY: NSObject has no subclassing requirements and FrameOutput
+    // doesn't implement Drop
+    #[unsafe(super = NSObject)]
+    #[name = "SnapScaleFrameOutput"]
+    #[ivars = Mutex<Option<SyncSender<Result<RgbaImage>>>>]
+    struct FrameOutput;
+
+    unsafe impl NSObjectProtocol for FrameOutput {}
+
+    unsafe impl SCStreamOutput for FrameOutput {
+        #[unsafe(method(stream:didOutputSampleBuffer:ofType:))]
+        fn stream_did_output(
+            &self,
+            _stream: &SCStream,
+            sample: &CMSampleBuffer,
+            kind: SCStreamOutputType,
+        ) {
+            if kind != SCStreamOutputType::Screen {
+                return;
+            }
+            // Frames without changes carry no image
+            let Some(pixels) = (unsafe { sample.image_buffer() }) else {
+                return;
+            };
+            if let Some(sender) = self.ivars().lock().ok().and_then(|mut s| s.take()) {
+                let _ = sender.send(read(&pixels));
+            }
+        }
+    }
+);
+
+impl FrameOutput {
+    /// An output handing its first frame to `sender`
+    fn new(sender: SyncSender<Result<RgbaImage>>) -> Retained<Self> {
+        let this = Self::alloc().set_ivars(Mutex::new(Some(sender)));
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+/// Copies a BGRA pixel buffer
+fn read(pixels: &CVPixelBuffer) -> Result<RgbaImage> {
+    let flags = CVPixelBufferLockFlags::ReadOnly;
+    if unsafe { CVPixelBufferLockBaseAddress(pixels, flags) } != 0 {
+        return Err(Error::Unsupported(
+            "ScreenCaptureKit: can't read the frame".into(),
+        ));
+    }
+    let (width, height) = (
+        CVPixelBufferGetWidth(pixels),
+        CVPixelBufferGetHeight(pixels),
+    );
+    let pitch = CVPixelBufferGetBytesPerRow(pixels);
+    let base = CVPixelBufferGetBaseAddress(pixels) as *const u8;
+    let mut data = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        data.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(base.add(row * pitch), width * 4)
+        });
+    }
+    unsafe { CVPixelBufferUnlockBaseAddress(pixels, flags) };
+    crate::window::from_bgrx(&data, width as u32, height as u32, false)
+}
@@ -0,0 +1,283 @@
+//! Capture through Windows.Graphics.Capture on Windows 10 1903 and later
+//!
+//! [`WgcBackend`] takes frames from the compositor on the GPU, so windows
+//! drawn by hardware-accelerated apps, video and games come out as shown
+//! instead of black, and HDR displays are tone-mapped to SDR by the system
+//! rather than washed out. Each capture starts a session, waits for its
+//! first frame and copies it back from the GPU.
+//!
+//! [`backend::auto`](super::auto) picks it when
+//! [`WgcBackend::is_supported`]; older systems keep the GDI path of
+//! [`Screens`], which also lists displays here.
+
+use super::{CaptureBackend, Screens};
+use crate::buffer::{Frame, FrameBuffer, PixelFormat};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use screenshots::display_info::DisplayInfo;
+use screenshots::image::{imageops, RgbaImage};
+use std::time::{Duration, Instant, SystemTime};
+use windows::core::{ComInterface, IInspectable};
+use windows::Graphics::Capture::{
+    Direct3D11CaptureFramePool, GraphicsCaptureItem, GraphicsCaptureSession,
+};
+use windows::Graphics::DirectX::Direct3D11::IDirect3DDevice;
+use windows::Graphics::DirectX::DirectXPixelFormat;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_HARDWARE;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION,
+    D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use windows::Win32::Graphics::Dxgi::IDXGIDevice;
+use windows::Win32::Graphics::Gdi::HMONITOR;
+use windows::Win32::System::WinRT::Direct3D11::{
+    CreateDirect3D11DeviceFromDXGIDevice, IDirect3DDxgiInterfaceAccess,
+};
+use windows::Win32::System::WinRT::Graphics::Capture::IGraphicsCaptureItemInterop;
+
+/// How long to wait for the compositor's first frame
+const FIRST_FRAME: Duration = Duration::from_secs(2);
+
+fn os(e: windows::core::Error) -> Error {
+    Error::Unsupported(format!("Windows.Graphics.Capture: {e}"))
+}
+
+/// The capture item for all of `display`
+fn monitor_item(display: &DisplayDescriptor) -> Result<GraphicsCaptureItem> {
+    let info = DisplayInfo::all()
+        .map_err(|e| Error::Unsupported(format!("listing displays: {e}")))?
+        .into_iter()
+        .find(|info| info.id == display.id)
+        .ok_or_else(|| Error::invalid("display", format!("display {} is gone", display.id)))?;
+    unsafe {
+        windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()
+            .and_then(|interop| interop.CreateForMonitor(HMONITOR(info.raw_handle.0)))
+    }
+    .map_err(os)
+}
+
+/// Displays captured through the compositor
+pub struct WgcBackend {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    winrt_device: IDirect3DDevice,
+}
+
+// Direct3D 11 devices are free-threaded, and the context is only used while
+// `&self` captures one frame at a time
+unsafe impl Send for WgcBackend {}
+unsafe impl Sync for WgcBackend {}
+
+impl WgcBackend {
+    /// Whether this Windows has Windows.Graphics.Capture
+    pub fn is_supported() -> bool {
+        GraphicsCaptureSession::IsSupported().unwrap_or(false)
+    }
+
+    /// Creates the Direct3D device frames are copied through
+    pub fn new() -> Result<Self> {
+        if !Self::is_supported() {
+            return Err(Error::Unsupported(
+                "Windows.Graphics.Capture needs Windows 10 1903 or later".into(),
+            ));
+        }
+        unsafe {
+            let mut device = None;
+            let mut context = None;
+            D3D11CreateDevice(
+                None,
+                D3D_DRIVER_TYPE_HARDWARE,
+                None,
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                None,
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                None,
+                Some(&mut context),
+            )
+            .map_err(os)?;
+            let missing = || Error::Unsupported("no Direct3D device".into());
+            let device = device.ok_or_else(missing)?;
+            let context = context.ok_or_else(missing)?;
+            let dxgi: IDXGIDevice = device.cast().map_err(os)?;
+            let inspectable: IInspectable =
+                CreateDirect3D11DeviceFromDXGIDevice(&dxgi).map_err(os)?;
+            Ok(Self {
+                device,
+                context,
+                winrt_device: inspectable.cast().map_err(os)?,
+            })
+        }
+    }
+
+    /// The first frame of `item`, at its physical size
+    fn capture_item(&self, item: &GraphicsCaptureItem) -> Result<RgbaImage> {
+        let mut buffer = FrameBuffer::new();
+        let (width, height) = self.capture_into(item, &mut buffer)?;
+        // Desktop and window frames are opaque
+        let frame = buffer.frame(
+            width,
+            height,
+            width as usize * 4,
+            PixelFormat::Bgra8,
+            SystemTime::now(),
+        )?;
+        crate::window::from_bgrx(frame.data(), width, height, false)
+    }
+
+    /// Copies the first frame of `item` into `buffer` as unpadded BGRA rows,
+    /// returning its size
+    fn capture_into(
+        &self,
+        item: &GraphicsCaptureItem,
+        buffer: &mut FrameBuffer,
+    ) -> Result<(u32, u32)> {
+        let pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
+            &self.winrt_device,
+            DirectXPixelFormat::B8G8R8A8UIntNormalized,
+            1,
+            item.Size().map_err(os)?,
+        )
+        .map_err(os)?;
+        let session = pool.CreateCaptureSession(item).map_err(os)?;
+        // Both need Windows 10 2004 or 11; older systems draw the cursor and a
+        // yellow border
+        let _ = session.SetIsCursorCaptureEnabled(false);
+        let _ = session.SetIsBorderRequired(false);
+        session.StartCapture().map_err(os)?;
+
+        let started = Instant::now();
+        let frame = loop {
+            match pool.TryGetNextFrame() {
+                Ok(frame) => break Ok(frame),
+                Err(_) if started.elapsed() < FIRST_FRAME => {
+                    std::thread::sleep(Duration::from_millis(5))
+                }
+                Err(_) => {
+                    break Err(Error::Unsupported(
+                        "Windows.Graphics.Capture: no frame arrived".into(),
+                    ))
+                }
+            }
+        };
+        let size = frame.and_then(|frame| {
+            let size = self.read(&frame.Surface().map_err(os)?, buffer);
+            let _ = frame.Close();
+            size
+        });
+        let _ = session.Close();
+        let _ = pool.Close();
+        size
+    }
+
+    /// Copies a frame back from the GPU into `buffer`
+    fn read(
+        &self,
+        surface: &windows::Graphics::DirectX::Direct3D11::IDirect3DSurface,
+        buffer: &mut FrameBuffer,
+    ) -> Result<(u32, u32)> {
+        unsafe {
+            let access: IDirect3DDxgiInterfaceAccess = surface.cast().map_err(os)?;
+            let texture: ID3D11Texture2D = access.GetInterface().map_err(os)?;
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            texture.GetDesc(&mut desc);
+            desc.Usage = D3D11_USAGE_STAGING;
+            desc.BindFlags = 0;
+            desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+            desc.MiscFlags = 0;
+            let mut staging = None;
+            self.device
+                .CreateTexture2D(&desc, None, Some(&mut staging))
+                .map_err(os)?;
+            let staging = staging.ok_or_else(|| Error::Unsupported("no staging texture".into()))?;
+            self.context.CopyResource(&staging, &texture);
+
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            self.context
+                .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+                .map_err(os)?;
+            let (width, height) = (desc.Width, desc.Height);
+            let row_bytes = width as usize * 4;
+            let data = buffer.resize(row_bytes * height as usize);
+            for (row, out) in data.chunks_exact_mut(row_bytes).enumerate() {
+                let start = (mapped.pData as *const u8).add(row * mapped.RowPitch as usize);
+                out.copy_from_slice(std::slice::from_raw_parts(start, row_bytes));
+            }
+            self.context.Unmap(&staging, 0);
+            Ok((width, height))
+        }
+    }
+
+    /// Captures a top-level window by handle, even where others cover it
+    pub fn capture_window(&self, hwnd: u64) -> Result<RgbaImage> {
+        let item = unsafe {
+            windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()
+                .and_then(|interop| interop.CreateForWindow(HWND(hwnd as isize)))
+        }
+        .map_err(|e| Error::invalid("window", format!("window {hwnd}: {e}")))?;
+        self.capture_item(&item)
+    }
+}
+
+impl CaptureBackend for WgcBackend {
+    fn name(&self) -> &'static str {
+        "wgc"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        Screens.enumerate()
+    }
+
+    fn refresh(&self) {
+        Screens.refresh()
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        self.capture_item(&monitor_item(display)?)
+    }
+
+    /// Copies the compositor's BGRA frame into `buffer` as it is
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        let timestamp = SystemTime::now();
+        let item = monitor_item(display)?;
+        let (width, height) = self.capture_into(&item, buffer)?;
+        buffer.frame(
+            width,
+            height,
+            width as usize * 4,
+            PixelFormat::Bgra8,
+            timestamp,
+        )
+    }
+
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        let frame = self.capture_display(display)?;
+        // Areas are logical, like the `screenshots` crate takes them
+        let scale = display.scale_factor;
+        let bounds = Region::new(0, 0, frame.width(), frame.height());
+        let physical = Region::new(
+            (area.x as f32 * scale) as i32,
+            (area.y as f32 * scale) as i32,
+            (area.width as f32 * scale) as u32,
+            (area.height as f32 * scale) as u32,
+        );
+        let crop = physical
+            .clamp_to(&bounds)
+            .ok_or_else(|| Error::invalid("area", format!("{area:?} is off the display")))?;
+        Ok(imageops::crop_imm(
+            &frame,
+            crop.x as u32,
+            crop.y as u32,
+            crop.width,
+            crop.height,
+        )
+        .to_image())
+    }
+}
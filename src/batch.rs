@@ -0,0 +1,144 @@
+//! Captures made together, reported together
+//!
+//! Capturing several displays or areas in one run shouldn't stop at the
+//! first one that fails, nor let a failure go unnoticed. A [`BatchResult`]
+//! records what became of every target, and its
+//! [`exit_code`](BatchResult::exit_code) tells all of them succeeding apart
+//! from some or all of them failing.
+
+use std::fmt;
+
+/// What became of one target: its value, or why it failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome<T> {
+    /// What was captured, e.g. `display 1` or `display 1 area 300,300,300,300`
+    pub target: String,
+    pub result: Result<T, String>,
+}
+
+/// How a batch went as a whole
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchStatus {
+    /// Every target succeeded, or there were none
+    Complete,
+    /// Some targets failed and some succeeded
+    Partial,
+    /// Every target failed
+    Failed,
+}
+
+/// Every target of a batch and its outcome, in the order they ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult<T> {
+    outcomes: Vec<Outcome<T>>,
+}
+
+impl<T> Default for BatchResult<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> BatchResult<T> {
+    pub fn new() -> Self {
+        Self {
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// Records `result` for `target`, keeping a failure's reason with its
+    /// causes
+    pub fn record<E: fmt::Display>(&mut self, target: impl Into<String>, result: Result<T, E>) {
+        self.outcomes.push(Outcome {
+            target: target.into(),
+            result: result.map_err(|e| format!("{e:#}")),
+        });
+    }
+
+    pub fn outcomes(&self) -> &[Outcome<T>] {
+        &self.outcomes
+    }
+
+    /// The targets that succeeded, with their values
+    pub fn successes(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.outcomes
+            .iter()
+            .filter_map(|o| Some((o.target.as_str(), o.result.as_ref().ok()?)))
+    }
+
+    /// The targets that failed, with why
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.outcomes
+            .iter()
+            .filter_map(|o| Some((o.target.as_str(), o.result.as_ref().err()?.as_str())))
+    }
+
+    pub fn status(&self) -> BatchStatus {
+        let failed = self.failures().count();
+        match failed {
+            0 => BatchStatus::Complete,
+            _ if failed == self.outcomes.len() => BatchStatus::Failed,
+            _ => BatchStatus::Partial,
+        }
+    }
+
+    /// 0 when complete, 1 when every target failed and 2 when only some did
+    pub fn exit_code(&self) -> u8 {
+        match self.status() {
+            BatchStatus::Complete => 0,
+            BatchStatus::Failed => 1,
+            BatchStatus::Partial => 2,
+        }
+    }
+}
+
+/// How many targets failed, then each of them and why
+impl<T> fmt::Display for BatchResult<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.failures().count();
+        write!(f, "{failed} of {} captures failed", self.outcomes.len())?;
+        for (target, reason) in self.failures() {
+            write!(f, "\n  {target}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for BatchResult<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_partial_failures_are_reported() {
+        let mut batch = BatchResult::new();
+        assert_eq!(batch.status(), BatchStatus::Complete);
+        batch.record("display 1", Ok::<_, Error>("target/1.png"));
+        batch.record(
+            "display 1 area",
+            Err(Error::invalid("area", "outside the display")),
+        );
+        assert_eq!(batch.status(), BatchStatus::Partial);
+        assert_eq!(batch.exit_code(), 2);
+        assert_eq!(
+            batch.successes().collect::<Vec<_>>(),
+            [("display 1", &"target/1.png")]
+        );
+        assert_eq!(
+            batch.to_string(),
+            "1 of 2 captures failed\n  display 1 area: invalid area: outside the display"
+        );
+    }
+
+    #[test]
+    fn test_total_failure() {
+        let mut batch = BatchResult::<()>::new();
+        batch.record("display 1", Err("asleep"));
+        batch.record("display 2", Err("asleep"));
+        assert_eq!(batch.status(), BatchStatus::Failed);
+        assert_eq!(batch.exit_code(), 1);
+        assert_eq!(batch.failures().count(), 2);
+    }
+}
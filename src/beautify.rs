@@ -0,0 +1,258 @@
+//! Presentation framing for captures
+//!
+//! [`Beautifier`] pads a capture, casts a soft drop shadow and places it on a
+//! solid or gradient background: the usual look for screenshots in docs and
+//! social posts. The shadow follows the capture's alpha, so rounded or shaped
+//! captures get a matching shadow.
+
+use crate::annotate::blend;
+use crate::color::Color;
+use crate::config::BeautifyConfig;
+use crate::transform::Transform;
+use crate::Result;
+use screenshots::image::{imageops, GrayImage, Luma, RgbaImage};
+
+/// What fills the area around the capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Solid(Color),
+    /// Linear gradient; `angle` is in degrees clockwise, 0 running left to
+    /// right and 90 top to bottom
+    Gradient {
+        from: Color,
+        to: Color,
+        angle: f32,
+    },
+}
+
+impl Background {
+    fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> Color {
+        match *self {
+            Self::Solid(color) => color,
+            Self::Gradient { from, to, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                // Project onto the gradient direction, normalised over the
+                // extent of the image along it
+                let extent = width as f32 * cos.abs() + height as f32 * sin.abs();
+                let (dx, dy) = (
+                    x as f32 + 0.5 - width as f32 / 2.0,
+                    y as f32 + 0.5 - height as f32 / 2.0,
+                );
+                let t = ((dx * cos + dy * sin) / extent.max(1.0) + 0.5).clamp(0.0, 1.0);
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                Color::new(
+                    mix(from.r, to.r),
+                    mix(from.g, to.g),
+                    mix(from.b, to.b),
+                    mix(from.a, to.a),
+                )
+            }
+        }
+    }
+}
+
+/// A drop shadow under the capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    /// Blur radius in pixels
+    pub blur: f32,
+    /// Shift right and down, in pixels
+    pub offset: (i32, i32),
+    pub color: Color,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self {
+            blur: 24.0,
+            offset: (0, 12),
+            color: Color::new(0, 0, 0, 128),
+        }
+    }
+}
+
+/// Pads a capture onto a background with a drop shadow
+#[derive(Debug, Clone, PartialEq)]
+pub struct Beautifier {
+    /// Space around the capture on every side
+    pub padding: u32,
+    pub background: Background,
+    pub shadow: Option<Shadow>,
+}
+
+impl Default for Beautifier {
+    fn default() -> Self {
+        Self::from_config(&BeautifyConfig::default())
+    }
+}
+
+impl Beautifier {
+    /// Builds the preset described by a `[beautify.<profile>]` section
+    pub fn from_config(config: &BeautifyConfig) -> Self {
+        let background = match config.background_to {
+            Some(to) => Background::Gradient {
+                from: config.background,
+                to,
+                angle: config.angle,
+            },
+            None => Background::Solid(config.background),
+        };
+        Self {
+            padding: config.padding,
+            background,
+            shadow: config.shadow.then_some(Shadow {
+                blur: config.shadow_blur,
+                offset: (config.shadow_offset[0], config.shadow_offset[1]),
+                color: config.shadow_color,
+            }),
+        }
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow: Option<Shadow>) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn render(&self, image: &RgbaImage) -> RgbaImage {
+        let (width, height) = image.dimensions();
+        let pad = self.padding;
+        let (canvas_width, canvas_height) = (width + 2 * pad, height + 2 * pad);
+        let mut canvas = RgbaImage::from_fn(canvas_width, canvas_height, |x, y| {
+            self.background
+                .color_at(x, y, canvas_width, canvas_height)
+                .into()
+        });
+
+        if let Some(shadow) = &self.shadow {
+            let mut mask = GrayImage::new(canvas_width, canvas_height);
+            let (left, top) = (
+                pad as i64 + shadow.offset.0 as i64,
+                pad as i64 + shadow.offset.1 as i64,
+            );
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let (mx, my) = (left + x as i64, top + y as i64);
+                if mx >= 0 && my >= 0 && mx < canvas_width as i64 && my < canvas_height as i64 {
+                    mask.put_pixel(mx as u32, my as u32, Luma([pixel.0[3]]));
+                }
+            }
+            // Gaussian sigma is about a third of the visible radius
+            let mask = if shadow.blur > 0.0 {
+                imageops::blur(&mask, shadow.blur / 3.0)
+            } else {
+                mask
+            };
+            for (pixel, coverage) in canvas.pixels_mut().zip(mask.pixels()) {
+                blend(pixel, shadow.color, coverage.0[0] as f32 / 255.0);
+            }
+        }
+
+        imageops::overlay(&mut canvas, image, pad as i64, pad as i64);
+        canvas
+    }
+}
+
+impl Transform for Beautifier {
+    fn name(&self) -> &str {
+        "beautify"
+    }
+
+    fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+        Ok(self.render(&image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn capture() -> RgbaImage {
+        RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]))
+    }
+
+    fn plain(padding: u32) -> Beautifier {
+        Beautifier::default()
+            .with_padding(padding)
+            .with_background(Background::Solid(Color::new(0, 0, 255, 255)))
+            .with_shadow(None)
+    }
+
+    #[test]
+    fn test_padding_and_background() {
+        let out = plain(10).render(&capture());
+
+        assert_eq!(out.dimensions(), (60, 40));
+        assert_eq!(out.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(
+            out.get_pixel(10, 10).0,
+            [255, 255, 255, 255],
+            "Capture at the padding offset"
+        );
+        assert_eq!(out.get_pixel(49, 29).0, [255, 255, 255, 255]);
+        assert_eq!(out.get_pixel(50, 30).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_shadow_darkens_below_the_capture() {
+        let shadow = Shadow {
+            blur: 6.0,
+            offset: (0, 6),
+            color: Color::BLACK,
+        };
+        let out = plain(16).with_shadow(Some(shadow)).render(&capture());
+
+        let below = out.get_pixel(36, 16 + 20 + 2).0[2];
+        let above = out.get_pixel(36, 4).0[2];
+        assert!(below < 200, "Shadow under the capture: {below}");
+        assert!(
+            below < above,
+            "Shadow is offset downwards: {below} vs {above}"
+        );
+    }
+
+    #[test]
+    fn test_gradient_runs_between_colors() {
+        let gradient = Background::Gradient {
+            from: Color::BLACK,
+            to: Color::WHITE,
+            angle: 0.0,
+        };
+        let out = plain(50)
+            .with_background(gradient)
+            .render(&RgbaImage::new(0, 0));
+
+        let left = out.get_pixel(0, 50).0[0];
+        let middle = out.get_pixel(50, 50).0[0];
+        let right = out.get_pixel(99, 50).0[0];
+        assert!(left < 5 && right > 250, "{left} {right}");
+        assert!((120..136).contains(&middle), "{middle}");
+        assert_eq!(
+            out.get_pixel(50, 0),
+            out.get_pixel(50, 99),
+            "Horizontal only"
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = BeautifyConfig {
+            background_to: Some(Color::WHITE),
+            shadow: false,
+            ..BeautifyConfig::default()
+        };
+        let beautifier = Beautifier::from_config(&config);
+
+        assert!(matches!(beautifier.background, Background::Gradient { .. }));
+        assert_eq!(beautifier.shadow, None);
+    }
+}
@@ -0,0 +1,302 @@
+//! Frames without the copy
+//!
+//! [`CaptureBackend::capture_display`](crate::backend::CaptureBackend::capture_display)
+//! hands back a new image every time, which a recording loop pays for with an
+//! allocation and a copy per frame. [`capture_frame`](crate::backend::CaptureBackend::capture_frame)
+//! instead lends out a [`Frame`]: the pixels where the backend left them,
+//! with the row stride, pixel order and capture time to read them by.
+//! Backends write into a [`FrameBuffer`] the caller keeps between frames, or
+//! lend memory of their own, as a [`FrozenDesktop`](crate::freeze::FrozenDesktop)
+//! does with its snapshot.
+
+use crate::{Error, Result};
+use screenshots::image::{Rgba, RgbaImage};
+use std::fmt;
+use std::time::SystemTime;
+
+/// Bytes per pixel of every format
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// The order of a pixel's four bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// Red, green, blue, alpha, as images are
+    #[default]
+    Rgba8,
+    /// Blue, green, red, alpha, as Windows and macOS capture
+    Bgra8,
+}
+
+impl PixelFormat {
+    /// The `[r, g, b, a]` of a pixel stored in this order
+    pub fn to_rgba(self, pixel: [u8; 4]) -> [u8; 4] {
+        match self {
+            Self::Rgba8 => pixel,
+            Self::Bgra8 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+        }
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rgba8 => "rgba8",
+            Self::Bgra8 => "bgra8",
+        })
+    }
+}
+
+/// Pixels borrowed from a backend or a [`FrameBuffer`]
+///
+/// Rows are `stride` bytes apart, which is at least `width` pixels and can
+/// be more where the backend pads them.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelFormat,
+    timestamp: SystemTime,
+}
+
+impl<'a> Frame<'a> {
+    /// A frame over `data`, which must hold `height` rows `stride` bytes
+    /// apart; the last one needn't be padded
+    pub fn new(
+        data: &'a [u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+        timestamp: SystemTime,
+    ) -> Result<Self> {
+        let row = width as usize * BYTES_PER_PIXEL;
+        if stride < row {
+            return Err(Error::invalid(
+                "stride",
+                format!("{stride} bytes is less than a {width} pixel row"),
+            ));
+        }
+        let needed = match height as usize {
+            0 => 0,
+            rows => stride * (rows - 1) + row,
+        };
+        if data.len() < needed {
+            return Err(Error::invalid(
+                "frame",
+                format!(
+                    "{} bytes is too few for {width}x{height} with a {stride} byte stride",
+                    data.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            stride,
+            format,
+            timestamp,
+        })
+    }
+
+    /// A frame borrowing `image`
+    pub fn from_image(image: &'a RgbaImage, timestamp: SystemTime) -> Self {
+        Self {
+            data: image.as_raw(),
+            width: image.width(),
+            height: image.height(),
+            stride: image.width() as usize * BYTES_PER_PIXEL,
+            format: PixelFormat::Rgba8,
+            timestamp,
+        }
+    }
+
+    /// The bytes as the backend laid them out, padding included
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Bytes from the start of one row to the start of the next
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// When the frame was captured
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The pixels of row `y`, without padding, in the frame's own order
+    ///
+    /// # Panics
+    ///
+    /// When `y` is past the last row.
+    pub fn row(&self, y: u32) -> &'a [u8] {
+        assert!(y < self.height, "row {y} of a {} row frame", self.height);
+        let start = y as usize * self.stride;
+        &self.data[start..start + self.width as usize * BYTES_PER_PIXEL]
+    }
+
+    /// Every row, top to bottom
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &'a [u8]> + '_ {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    /// The pixel at `x`,`y` as RGBA
+    ///
+    /// # Panics
+    ///
+    /// When `x`,`y` is outside the frame.
+    pub fn pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+        assert!(x < self.width, "column {x} of a {} wide frame", self.width);
+        let at = x as usize * BYTES_PER_PIXEL;
+        let bytes = &self.row(y)[at..at + BYTES_PER_PIXEL];
+        Rgba(
+            self.format
+                .to_rgba([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        )
+    }
+
+    /// An owned RGBA copy, for when the pixels have to outlive the frame
+    pub fn to_image(&self) -> RgbaImage {
+        let mut data = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for row in self.rows() {
+            match self.format {
+                PixelFormat::Rgba8 => data.extend_from_slice(row),
+                PixelFormat::Bgra8 => data.extend(
+                    row.chunks_exact(BYTES_PER_PIXEL)
+                        .flat_map(|p| self.format.to_rgba([p[0], p[1], p[2], p[3]])),
+                ),
+            }
+        }
+        RgbaImage::from_raw(self.width, self.height, data).expect("one pixel per byte quad")
+    }
+}
+
+/// Memory frames are captured into, kept from one frame to the next
+///
+/// Capturing the same display into the same buffer allocates once, on the
+/// first frame, and again only when a frame needs more room.
+#[derive(Debug, Default)]
+pub struct FrameBuffer {
+    data: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes allocated, whatever the last frame used of them
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// `len` bytes for a backend to write a frame into, keeping the
+    /// allocation when it's large enough
+    ///
+    /// Their contents are whatever was there before.
+    pub fn resize(&mut self, len: usize) -> &mut [u8] {
+        self.data.resize(len, 0);
+        &mut self.data[..len]
+    }
+
+    /// The frame a backend wrote with [`resize`](Self::resize)
+    pub fn frame(
+        &self,
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+        timestamp: SystemTime,
+    ) -> Result<Frame<'_>> {
+        Frame::new(&self.data, width, height, stride, format, timestamp)
+    }
+
+    /// Takes over an image a backend captured, without copying it
+    pub fn hold(&mut self, image: RgbaImage, timestamp: SystemTime) -> Frame<'_> {
+        let (width, height) = image.dimensions();
+        self.data = image.into_raw();
+        Frame {
+            data: &self.data,
+            width,
+            height,
+            stride: width as usize * BYTES_PER_PIXEL,
+            format: PixelFormat::Rgba8,
+            timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padded_bgra_rows() {
+        // Two 2-pixel rows padded to 12 bytes, the last one unpadded
+        let data = [
+            1, 2, 3, 255, 4, 5, 6, 255, 0, 0, 0, 0, //
+            7, 8, 9, 255, 10, 11, 12, 128,
+        ];
+        let now = SystemTime::now();
+        let frame = Frame::new(&data, 2, 2, 12, PixelFormat::Bgra8, now).unwrap();
+        assert_eq!(frame.dimensions(), (2, 2));
+        assert_eq!(frame.timestamp(), now);
+        assert_eq!(frame.row(1), &data[12..]);
+        assert_eq!(frame.rows().len(), 2);
+        assert_eq!(frame.pixel(1, 1), Rgba([12, 11, 10, 128]));
+
+        let image = frame.to_image();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(*image.get_pixel(0, 0), Rgba([3, 2, 1, 255]));
+        assert_eq!(*image.get_pixel(1, 1), Rgba([12, 11, 10, 128]));
+        let roundtrip = Frame::from_image(&image, now);
+        assert_eq!(roundtrip.stride(), 8);
+        assert_eq!(roundtrip.to_image(), image);
+
+        assert!(Frame::new(&data, 2, 2, 7, PixelFormat::Bgra8, now).is_err());
+        assert!(Frame::new(&data[..19], 2, 2, 12, PixelFormat::Bgra8, now).is_err());
+        assert!(Frame::new(&[], 0, 0, 0, PixelFormat::Rgba8, now).is_ok());
+    }
+
+    #[test]
+    fn test_buffer_keeps_its_allocation() {
+        let mut buffer = FrameBuffer::new();
+        buffer.resize(64 * 48 * 4).fill(7);
+        let capacity = buffer.capacity();
+        let now = SystemTime::now();
+        let frame = buffer
+            .frame(64, 48, 64 * 4, PixelFormat::Rgba8, now)
+            .unwrap();
+        assert_eq!(frame.pixel(63, 47), Rgba([7, 7, 7, 7]));
+
+        buffer.resize(32 * 24 * 4);
+        assert_eq!(buffer.capacity(), capacity, "Smaller frames fit");
+        assert!(buffer.frame(64, 48, 256, PixelFormat::Rgba8, now).is_err());
+
+        let image = RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 4]));
+        let raw = image.as_raw().as_ptr();
+        let held = buffer.hold(image, now);
+        assert_eq!(held.data().as_ptr(), raw, "Taken over, not copied");
+        assert_eq!(held.pixel(2, 1), Rgba([1, 2, 3, 4]));
+    }
+}
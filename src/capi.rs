@@ -0,0 +1,386 @@
+//! C interface to the capture engine
+//!
+//! With the `capi` feature the library exports the functions declared in
+//! `include/snap_scale.h`, so C, C++ and anything with a C FFI can list
+//! displays and capture them. Cargo builds the shared and static libraries
+//! on request, so
+//!
+//! ```bash
+//! cargo rustc --lib --release --features capi --crate-type cdylib,staticlib
+//! ```
+//!
+//! leaves `libsnap_scale.so` and `libsnap_scale.a` (or their platform's
+//! equivalents) in `target/release`.
+//!
+//! Every function returns [`SNAP_SCALE_OK`] or a negative status, with the
+//! reason in [`snap_scale_last_error`]. Images are heap buffers owned by the
+//! caller until handed back to [`snap_scale_image_free`]. Panics never cross
+//! into C; they surface as [`SNAP_SCALE_ERR_PANIC`].
+
+use crate::backend::default_backend;
+use crate::encode::{encode_to_vec, EncodeOptions, OutputFormat};
+use crate::geometry::Region;
+use crate::source::Source;
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// The call succeeded
+pub const SNAP_SCALE_OK: i32 = 0;
+/// An argument was null, malformed or named no display
+pub const SNAP_SCALE_ERR_INVALID: i32 = -1;
+/// Listing, capturing or encoding failed
+pub const SNAP_SCALE_ERR_CAPTURE: i32 = -2;
+/// The library panicked; it's a bug
+pub const SNAP_SCALE_ERR_PANIC: i32 = -3;
+
+/// A display, as [`snap_scale_display_info`] fills it in
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SnapScaleDisplay {
+    pub id: u32,
+    /// Origin in desktop-global logical coordinates
+    pub x: i32,
+    pub y: i32,
+    /// Logical size
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+/// A captured image: RGBA pixels, or an encoded file
+#[repr(C)]
+#[derive(Debug)]
+pub struct SnapScaleImage {
+    pub data: *mut u8,
+    pub len: usize,
+    /// Size in physical pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Runs `f`, turning its error or panic into a status and the last error
+fn status(f: impl FnOnce() -> Result<()>) -> i32 {
+    let (code, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (SNAP_SCALE_OK, String::new()),
+        Ok(Err(e @ Error::Invalid { .. })) => (SNAP_SCALE_ERR_INVALID, e.to_string()),
+        Ok(Err(e)) => (SNAP_SCALE_ERR_CAPTURE, e.to_string()),
+        Err(_) => (SNAP_SCALE_ERR_PANIC, "snap_scale panicked".to_owned()),
+    };
+    // A message can't hold NULs for C; the rest of it still helps
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+/// Index of the display with `id`
+fn index(source: &dyn Source, id: u32) -> Result<usize> {
+    source
+        .displays()?
+        .iter()
+        .position(|display| display.id == id)
+        .ok_or_else(|| Error::invalid("display", format!("no display with id {id}")))
+}
+
+/// Captures display `id`, or a logical `region` of it, as RGBA or `format`
+fn capture(
+    source: &dyn Source,
+    id: u32,
+    region: Option<Region>,
+    format: Option<OutputFormat>,
+) -> Result<(Vec<u8>, u32, u32)> {
+    let frame = source.capture(index(source, id)?, region)?;
+    let (width, height) = frame.dimensions();
+    let data = match format {
+        Some(format) => encode_to_vec(&frame, &EncodeOptions::new(format))?,
+        None => frame.into_raw(),
+    };
+    Ok((data, width, height))
+}
+
+/// Hands `data` over to the caller through `out`
+fn image(out: *mut SnapScaleImage, (data, width, height): (Vec<u8>, u32, u32)) {
+    let data = Box::into_raw(data.into_boxed_slice());
+    // SAFETY: callers checked `out` isn't null; C promised it's writable
+    unsafe {
+        out.write(SnapScaleImage {
+            data: data as *mut u8,
+            len: data.len(),
+            width,
+            height,
+        })
+    };
+}
+
+fn needs(pointer: *const impl Sized) -> Result<()> {
+    if pointer.is_null() {
+        return Err(Error::invalid("argument", "null pointer"));
+    }
+    Ok(())
+}
+
+/// Number of displays, or a negative status
+#[no_mangle]
+pub extern "C" fn snap_scale_display_count() -> i32 {
+    let mut count = 0;
+    let code = status(|| {
+        count = default_backend()?.displays()?.len() as i32;
+        Ok(())
+    });
+    if code == SNAP_SCALE_OK {
+        count
+    } else {
+        code
+    }
+}
+
+/// Describes display `index` into `out`
+///
+/// # Safety
+///
+/// `out` must be null or point to writable memory for a `SnapScaleDisplay`.
+#[no_mangle]
+pub unsafe extern "C" fn snap_scale_display_info(index: u32, out: *mut SnapScaleDisplay) -> i32 {
+    status(|| {
+        needs(out)?;
+        let displays = default_backend()?.displays()?;
+        let display = displays
+            .get(index as usize)
+            .ok_or_else(|| Error::invalid("display", format!("no display #{index}")))?;
+        // SAFETY: not null, and writable per the contract above
+        unsafe {
+            out.write(SnapScaleDisplay {
+                id: display.id,
+                x: display.x,
+                y: display.y,
+                width: display.width,
+                height: display.height,
+                scale_factor: display.scale_factor,
+                is_primary: display.is_primary,
+            })
+        };
+        Ok(())
+    })
+}
+
+/// Captures the display with `id` as RGBA, 4 bytes a pixel, rows top first
+///
+/// # Safety
+///
+/// `out` must be null or point to writable memory for a `SnapScaleImage`.
+#[no_mangle]
+pub unsafe extern "C" fn snap_scale_capture_display(id: u32, out: *mut SnapScaleImage) -> i32 {
+    status(|| {
+        needs(out)?;
+        image(out, capture(&default_backend()?, id, None, None)?);
+        Ok(())
+    })
+}
+
+/// Captures a logical area of the display with `id` as RGBA
+///
+/// # Safety
+///
+/// `out` must be null or point to writable memory for a `SnapScaleImage`.
+#[no_mangle]
+pub unsafe extern "C" fn snap_scale_capture_region(
+    id: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    out: *mut SnapScaleImage,
+) -> i32 {
+    status(|| {
+        needs(out)?;
+        let region = Region::new(x, y, width, height);
+        if region.is_empty() {
+            return Err(Error::invalid("area", "width and height must be positive"));
+        }
+        image(out, capture(&default_backend()?, id, Some(region), None)?);
+        Ok(())
+    })
+}
+
+/// Captures the display with `id` encoded as `format`: `png`, `jpeg`, …
+///
+/// # Safety
+///
+/// `format` must be null or a NUL-terminated string, and `out` null or
+/// writable memory for a `SnapScaleImage`.
+#[no_mangle]
+pub unsafe extern "C" fn snap_scale_capture_display_encoded(
+    id: u32,
+    format: *const c_char,
+    out: *mut SnapScaleImage,
+) -> i32 {
+    status(|| {
+        needs(out)?;
+        needs(format)?;
+        // SAFETY: not null, and NUL-terminated per the contract above
+        let format = unsafe { CStr::from_ptr(format) };
+        let format = format
+            .to_str()
+            .map_err(|_| Error::invalid("format", format.to_string_lossy()))?
+            .parse()?;
+        image(out, capture(&default_backend()?, id, None, Some(format))?);
+        Ok(())
+    })
+}
+
+/// Frees an image's buffer and empties it; null and empty images are ignored
+///
+/// # Safety
+///
+/// `image` must be null or an image filled in by this library and not yet
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn snap_scale_image_free(image: *mut SnapScaleImage) {
+    // SAFETY: per the contract above
+    let Some(image) = (unsafe { image.as_mut() }) else {
+        return;
+    };
+    if !image.data.is_null() {
+        // SAFETY: `data` and `len` came from a boxed slice in `image()`
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(image.data, image.len)) });
+    }
+    image.data = ptr::null_mut();
+    image.len = 0;
+}
+
+/// Why the calling thread's latest call failed; empty after a success
+///
+/// Valid until the thread's next call into the library.
+#[no_mangle]
+pub extern "C" fn snap_scale_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// The library version, e.g. `0.1.0`
+#[no_mangle]
+pub extern "C" fn snap_scale_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DisplayDescriptor;
+    use screenshots::image::{Rgba, RgbaImage};
+
+    /// Display 7, 16x9 in solid blue
+    struct Fake;
+
+    impl Source for Fake {
+        fn displays(&self) -> Result<Vec<DisplayDescriptor>> {
+            Ok(vec![DisplayDescriptor {
+                id: 7,
+                name: String::new(),
+                x: 0,
+                y: 0,
+                width: 16,
+                height: 9,
+                rotation: 0.0,
+                scale_factor: 1.0,
+                frequency: 60.0,
+                is_primary: true,
+            }])
+        }
+
+        fn capture(&self, _index: usize, region: Option<Region>) -> Result<RgbaImage> {
+            let region = region.unwrap_or(Region::new(0, 0, 16, 9));
+            Ok(RgbaImage::from_pixel(
+                region.width,
+                region.height,
+                Rgba([0, 0, 255, 255]),
+            ))
+        }
+    }
+
+    fn last_error() -> String {
+        // SAFETY: the library's own NUL-terminated string
+        unsafe { CStr::from_ptr(snap_scale_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_images_round_trip_through_c() {
+        let mut out = SnapScaleImage {
+            data: ptr::null_mut(),
+            len: 0,
+            width: 0,
+            height: 0,
+        };
+        let code = status(|| {
+            image(
+                &mut out,
+                capture(&Fake, 7, Some(Region::new(0, 0, 4, 2)), None)?,
+            );
+            Ok(())
+        });
+        assert_eq!((code, last_error()), (SNAP_SCALE_OK, String::new()));
+        assert_eq!((out.len, out.width, out.height), (4 * 2 * 4, 4, 2));
+        // SAFETY: filled in just above
+        let pixels = unsafe { std::slice::from_raw_parts(out.data, out.len) };
+        assert_eq!(&pixels[..4], [0, 0, 255, 255]);
+
+        unsafe { snap_scale_image_free(&mut out) };
+        assert!(out.data.is_null() && out.len == 0);
+        unsafe { snap_scale_image_free(&mut out) };
+        unsafe { snap_scale_image_free(ptr::null_mut()) };
+
+        let (png, ..) = capture(&Fake, 7, None, Some(OutputFormat::Png)).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_errors_become_statuses() {
+        let code = status(|| capture(&Fake, 8, None, None).map(drop));
+        assert_eq!(code, SNAP_SCALE_ERR_INVALID);
+        assert_eq!(last_error(), "invalid display: no display with id 8");
+
+        let code = unsafe { snap_scale_capture_display(7, ptr::null_mut()) };
+        assert_eq!(code, SNAP_SCALE_ERR_INVALID);
+
+        let code = status(|| Err(Error::Unsupported("no screens".into())));
+        assert_eq!(code, SNAP_SCALE_ERR_CAPTURE);
+        let code = status(|| panic!("bug"));
+        assert_eq!(
+            (code, last_error().as_str()),
+            (SNAP_SCALE_ERR_PANIC, "snap_scale panicked")
+        );
+        assert!(status(|| Ok(())) == SNAP_SCALE_OK && last_error().is_empty());
+    }
+
+    #[test]
+    fn test_header_declares_every_function() {
+        let header = include_str!("../include/snap_scale.h");
+        for declaration in [
+            "#define SNAP_SCALE_OK 0",
+            "#define SNAP_SCALE_ERR_INVALID -1",
+            "#define SNAP_SCALE_ERR_CAPTURE -2",
+            "#define SNAP_SCALE_ERR_PANIC -3",
+            "int32_t snap_scale_display_count(void);",
+            "int32_t snap_scale_display_info(uint32_t index, SnapScaleDisplay *out);",
+            "int32_t snap_scale_capture_display(uint32_t id, SnapScaleImage *out);",
+            "int32_t snap_scale_capture_display_encoded(uint32_t id, const char *format, SnapScaleImage *out);",
+            "void snap_scale_image_free(SnapScaleImage *image);",
+            "const char *snap_scale_last_error(void);",
+            "const char *snap_scale_version(void);",
+        ] {
+            assert!(header.contains(declaration), "{declaration}");
+        }
+        assert!(header.contains("int32_t snap_scale_capture_region(uint32_t id,"));
+        // SAFETY: a static NUL-terminated string
+        let version = unsafe { CStr::from_ptr(snap_scale_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+}
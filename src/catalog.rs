@@ -0,0 +1,393 @@
+//! SQLite index of saved captures
+//!
+//! With `[catalog] enabled = true` every saved capture is recorded in a
+//! [`Catalog`]: its path, capture time, display, captured region, difference
+//! hash and `--tag`s. `snap_scale history` lists recent captures and
+//! `snap_scale search` filters them by path, tag, display, time or visual
+//! similarity, so captures stay findable long after watch mode produced
+//! thousands of them.
+
+use crate::geometry::Region;
+use crate::hash::{difference_hash, ImageHash};
+use crate::{Error, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row};
+use screenshots::image::RgbaImage;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Catalog(e.to_string())
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS captures (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        captured_at TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        display TEXT NOT NULL,
+        x INTEGER,
+        y INTEGER,
+        width INTEGER,
+        height INTEGER,
+        hash TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS captures_timestamp ON captures (timestamp);
+    CREATE TABLE IF NOT EXISTS tags (
+        capture_id INTEGER NOT NULL REFERENCES captures (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (capture_id, tag)
+    );
+";
+
+/// One saved capture
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// Assigned by [`Catalog::add`]
+    pub id: i64,
+    pub path: PathBuf,
+    /// RFC 3339 local time
+    pub captured_at: String,
+    pub display: String,
+    /// Logical area captured on the display; `None` for the whole display
+    pub region: Option<Region>,
+    /// [`difference_hash`] as 16 hex digits
+    pub hash: String,
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    /// An entry for `image`, saved to `path` from `display` at `time`
+    pub fn new(image: &RgbaImage, path: &Path, display: &str, time: DateTime<Local>) -> Self {
+        Self {
+            id: 0,
+            path: std::path::absolute(path).unwrap_or_else(|_| path.to_owned()),
+            captured_at: time.to_rfc3339(),
+            display: display.to_owned(),
+            region: None,
+            hash: format!("{:016x}", difference_hash(image).0),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_region(mut self, region: Option<Region>) -> Self {
+        self.region = region;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn image_hash(&self) -> Option<ImageHash> {
+        u64::from_str_radix(&self.hash, 16).ok().map(ImageHash)
+    }
+}
+
+/// Filters for [`Catalog::search`]; every one set must match
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Substring of the path
+    pub text: Option<String>,
+    pub tags: Vec<String>,
+    pub display: Option<String>,
+    pub since: Option<DateTime<Local>>,
+    /// Exclusive
+    pub until: Option<DateTime<Local>>,
+    /// Captures whose hash is within this many bits of the given one
+    pub similar_to: Option<(ImageHash, u32)>,
+    /// Newest entries kept; all when `None`
+    pub limit: Option<usize>,
+}
+
+/// The catalog database
+pub struct Catalog {
+    connection: Connection,
+}
+
+impl Catalog {
+    /// Opens the catalog at `path`, creating the file and its tables if
+    /// needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A catalog that only lives as long as the value, for tests
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        // Watch mode and one-off captures may record at the same time
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// `<data dir>/snap_scale/catalog.sqlite`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("snap_scale").join("catalog.sqlite"))
+    }
+
+    /// Records `entry`, returning its id
+    pub fn add(&self, entry: &Entry) -> Result<i64> {
+        let timestamp = DateTime::parse_from_rfc3339(&entry.captured_at)
+            .map_err(|_| Error::invalid("capture time", entry.captured_at.clone()))?
+            .timestamp();
+        let region = entry.region.map(|r| (r.x, r.y, r.width, r.height));
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT INTO captures
+                (path, captured_at, timestamp, display, x, y, width, height, hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                entry.path.to_string_lossy(),
+                entry.captured_at,
+                timestamp,
+                entry.display,
+                region.map(|r| r.0),
+                region.map(|r| r.1),
+                region.map(|r| r.2),
+                region.map(|r| r.3),
+                entry.hash,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        for tag in &entry.tags {
+            transaction.execute(
+                "INSERT OR IGNORE INTO tags (capture_id, tag) VALUES (?1, ?2)",
+                params![id, tag],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    /// The `limit` most recent captures, newest first
+    pub fn history(&self, limit: usize) -> Result<Vec<Entry>> {
+        self.search(&Query {
+            limit: Some(limit),
+            ..Query::default()
+        })
+    }
+
+    /// Captures matching `query`, newest first
+    pub fn search(&self, query: &Query) -> Result<Vec<Entry>> {
+        let mut sql = "SELECT id, path, captured_at, display, x, y, width, height, hash
+                       FROM captures WHERE 1 = 1"
+            .to_owned();
+        let mut values = Vec::new();
+        if let Some(text) = &query.text {
+            sql += " AND instr(path, ?) > 0";
+            values.push(Value::Text(text.clone()));
+        }
+        for tag in &query.tags {
+            sql += " AND id IN (SELECT capture_id FROM tags WHERE tag = ?)";
+            values.push(Value::Text(tag.clone()));
+        }
+        if let Some(display) = &query.display {
+            sql += " AND display = ?";
+            values.push(Value::Text(display.clone()));
+        }
+        if let Some(since) = query.since {
+            sql += " AND timestamp >= ?";
+            values.push(Value::Integer(since.timestamp()));
+        }
+        if let Some(until) = query.until {
+            sql += " AND timestamp < ?";
+            values.push(Value::Integer(until.timestamp()));
+        }
+        sql += " ORDER BY timestamp DESC, id DESC";
+        // Similarity is filtered here, so the limit has to wait for it
+        if let (Some(limit), None) = (query.limit, query.similar_to) {
+            sql += " LIMIT ?";
+            values.push(Value::Integer(limit as i64));
+        }
+
+        let mut statement = self.connection.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(values), entry)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let mut entry = row?;
+            if let Some((hash, distance)) = query.similar_to {
+                if entry
+                    .image_hash()
+                    .is_none_or(|h| h.distance(&hash) > distance)
+                {
+                    continue;
+                }
+            }
+            entry.tags = self.tags(entry.id)?;
+            entries.push(entry);
+            if query.limit.is_some_and(|limit| entries.len() >= limit) {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn tags(&self, id: i64) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare_cached("SELECT tag FROM tags WHERE capture_id = ? ORDER BY tag")?;
+        let tags = statement.query_map([id], |row| row.get(0))?;
+        Ok(tags.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn entry(row: &Row) -> rusqlite::Result<Entry> {
+    let region = match (row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?) {
+        (Some(x), Some(y), Some(width), Some(height)) => Some(Region::new(x, y, width, height)),
+        _ => None,
+    };
+    Ok(Entry {
+        id: row.get(0)?,
+        path: PathBuf::from(row.get::<_, String>(1)?),
+        captured_at: row.get(2)?,
+        display: row.get(3)?,
+        region,
+        hash: row.get(8)?,
+        tags: Vec::new(),
+    })
+}
+
+/// Parses `--since`/`--until`: an RFC 3339 time, or a `YYYY-MM-DD` date
+/// meaning local midnight
+pub fn parse_time(s: &str) -> Result<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Local));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .ok_or_else(|| Error::invalid("time (expected YYYY-MM-DD or RFC 3339)", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn time(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    fn gradient(flip: bool) -> RgbaImage {
+        RgbaImage::from_fn(32, 32, |x, _| {
+            let v = (x * 8) as u8;
+            Rgba(if flip { [255 - v; 4] } else { [v; 4] })
+        })
+    }
+
+    fn catalog() -> Catalog {
+        let catalog = Catalog::in_memory().unwrap();
+        let entries = [
+            Entry::new(&gradient(false), Path::new("/shots/1.png"), "1", time(1, 9))
+                .with_tags(["login"]),
+            Entry::new(
+                &gradient(true),
+                Path::new("/shots/1-2.png"),
+                "1",
+                time(1, 10),
+            )
+            .with_region(Some(Region::new(10, 20, 300, 200))),
+            Entry::new(&gradient(false), Path::new("/other/2.png"), "2", time(2, 9))
+                .with_tags(["login", "release"]),
+        ];
+        for entry in &entries {
+            catalog.add(entry).unwrap();
+        }
+        catalog
+    }
+
+    fn paths(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|e| e.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_history_is_newest_first() {
+        let history = catalog().history(2).unwrap();
+        assert_eq!(paths(&history), ["/other/2.png", "/shots/1-2.png"]);
+        assert_eq!(history[0].tags, ["login", "release"]);
+        assert_eq!(history[1].region, Some(Region::new(10, 20, 300, 200)));
+        assert_eq!(history[0].region, None, "Whole display");
+    }
+
+    #[test]
+    fn test_search_filters_combine() {
+        let catalog = catalog();
+        let search = |query: Query| paths(&catalog.search(&query).unwrap()).join(" ");
+
+        let tagged = Query {
+            tags: vec!["login".into()],
+            ..Query::default()
+        };
+        assert_eq!(search(tagged.clone()), "/other/2.png /shots/1.png");
+        let query = Query {
+            display: Some("1".into()),
+            ..tagged
+        };
+        assert_eq!(search(query), "/shots/1.png");
+        let query = Query {
+            text: Some("shots".into()),
+            since: Some(time(1, 10)),
+            ..Query::default()
+        };
+        assert_eq!(search(query), "/shots/1-2.png");
+        let query = Query {
+            until: Some(time(1, 10)),
+            ..Query::default()
+        };
+        assert_eq!(search(query), "/shots/1.png", "until is exclusive");
+    }
+
+    #[test]
+    fn test_search_by_similarity() {
+        let hash = difference_hash(&gradient(false));
+        let query = Query {
+            similar_to: Some((hash, 4)),
+            limit: Some(1),
+            ..Query::default()
+        };
+        let found = catalog().search(&query).unwrap();
+        assert_eq!(
+            paths(&found),
+            ["/other/2.png"],
+            "Limit applies after the filter"
+        );
+    }
+
+    #[test]
+    fn test_catalog_persists() {
+        let dir =
+            std::env::temp_dir().join(format!("snap_scale_catalog_persist_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("nested").join("catalog.sqlite");
+        let entry = Entry::new(&gradient(false), Path::new("/shots/1.png"), "1", time(1, 9));
+        let id = Catalog::open(&path).unwrap().add(&entry).unwrap();
+
+        let history = Catalog::open(&path).unwrap().history(10).unwrap();
+        assert_eq!(history, [Entry { id, ..entry }]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("2024-05-01").unwrap(), time(1, 0));
+        let time = parse_time("2024-05-01T12:00:00Z").unwrap();
+        assert_eq!(time.timestamp(), 1714564800);
+        assert!(parse_time("yesterday").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Pixel colors and their common notations
+
+use crate::{Error, Result};
+use screenshots::image::Rgba;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// An 8-bit RGBA color
+///
+/// Deserializes from any notation [`FromStr`] accepts, e.g. `"#1e1e2e"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0, 255);
+    pub const WHITE: Color = Color::new(255, 255, 255, 255);
+    pub const RED: Color = Color::new(230, 40, 40, 255);
+    pub const YELLOW: Color = Color::new(255, 220, 0, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` when not fully opaque
+    pub fn hex(&self) -> String {
+        if self.a == u8::MAX {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// Hue in degrees (0-360), saturation and lightness as fractions (0-1)
+    pub fn hsl(&self) -> (f32, f32, f32) {
+        let [r, g, b] = [self.r, self.g, self.b].map(|c| c as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+
+        let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (hue, saturation, lightness)
+    }
+
+    /// CSS-style `hsl(h, s%, l%)`
+    pub fn hsl_string(&self) -> String {
+        let (h, s, l) = self.hsl();
+        format!("hsl({:.0}, {:.0}%, {:.0}%)", h, s * 100.0, l * 100.0)
+    }
+
+    /// The same color with alpha replaced
+    pub fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// CSS-style `rgba(r, g, b, a)` with alpha as a fraction
+    pub fn rgba_string(&self) -> String {
+        format!(
+            "rgba({}, {}, {}, {:.2})",
+            self.r,
+            self.g,
+            self.b,
+            self.a as f32 / 255.0
+        )
+    }
+}
+
+impl From<Rgba<u8>> for Color {
+    fn from(Rgba([r, g, b, a]): Rgba<u8>) -> Self {
+        Self::new(r, g, b, a)
+    }
+}
+
+impl From<Color> for Rgba<u8> {
+    fn from(color: Color) -> Self {
+        Rgba([color.r, color.g, color.b, color.a])
+    }
+}
+
+/// Parses `#rgb`, `#rrggbb` or `#rrggbbaa` (the `#` is optional) and a few
+/// names: black, white, red, yellow
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::invalid("color", s);
+        match s.to_ascii_lowercase().as_str() {
+            "black" => return Ok(Self::BLACK),
+            "white" => return Ok(Self::WHITE),
+            "red" => return Ok(Self::RED),
+            "yellow" => return Ok(Self::YELLOW),
+            _ => {}
+        }
+
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize, width: usize| {
+            let value = u8::from_str_radix(&hex[i * width..(i + 1) * width], 16).unwrap_or(0);
+            if width == 1 {
+                value * 17
+            } else {
+                value
+            }
+        };
+        match hex.len() {
+            3 => Ok(Self::new(channel(0, 1), channel(1, 1), channel(2, 1), 255)),
+            6 => Ok(Self::new(channel(0, 2), channel(1, 2), channel(2, 2), 255)),
+            8 => Ok(Self::new(
+                channel(0, 2),
+                channel(1, 2),
+                channel(2, 2),
+                channel(3, 2),
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(Color::new(12, 34, 255, 255).hex(), "#0c22ff");
+        assert_eq!(
+            Color::new(12, 34, 255, 128).hex(),
+            "#0c22ff80",
+            "Translucent colors keep their alpha"
+        );
+    }
+
+    #[test]
+    fn test_hsl() {
+        assert_eq!(Color::new(255, 0, 0, 255).hsl_string(), "hsl(0, 100%, 50%)");
+        assert_eq!(
+            Color::new(0, 128, 0, 255).hsl_string(),
+            "hsl(120, 100%, 25%)"
+        );
+        assert_eq!(
+            Color::new(128, 128, 128, 255).hsl_string(),
+            "hsl(0, 0%, 50%)"
+        );
+        assert_eq!(
+            Color::new(255, 0, 255, 255).hsl_string(),
+            "hsl(300, 100%, 50%)"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "#0c22ff".parse::<Color>().unwrap(),
+            Color::new(12, 34, 255, 255)
+        );
+        assert_eq!(
+            "f0a".parse::<Color>().unwrap(),
+            Color::new(255, 0, 170, 255)
+        );
+        assert_eq!(
+            "#00000080".parse::<Color>().unwrap(),
+            Color::new(0, 0, 0, 128)
+        );
+        assert_eq!("White".parse::<Color>().unwrap(), Color::WHITE);
+        for bad in ["", "#12", "#ggg", "#1234567"] {
+            assert!(bad.parse::<Color>().is_err(), "{bad:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_rgba_conversion() {
+        let pixel = Rgba([1, 2, 3, 4]);
+        assert_eq!(Rgba::from(Color::from(pixel)), pixel);
+        assert_eq!(Color::from(pixel).rgba_string(), "rgba(1, 2, 3, 0.02)");
+    }
+}
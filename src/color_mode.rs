@@ -0,0 +1,182 @@
+//! Grayscale and monochrome output
+//!
+//! [`ColorMode`] drops color from a capture, which keeps text-heavy captures
+//! small and gives OCR and printers clean input. Luma uses the Rec. 709
+//! weights. [`reduce`] then stores gray images with one channel instead of
+//! four so the savings reach the file.
+
+use crate::transform::{Rgba16Image, Transform};
+use crate::{Error, Result};
+use screenshots::image::{DynamicImage, GrayAlphaImage, GrayImage, LumaA, RgbaImage};
+use std::str::FromStr;
+
+/// Default `mono` cut-off between black and white
+pub const DEFAULT_THRESHOLD: u8 = 128;
+
+/// How many colors a capture keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Unchanged
+    #[default]
+    Color,
+    Grayscale,
+    /// Black and white: luma at or above the threshold becomes white
+    Mono(u8),
+}
+
+impl ColorMode {
+    pub fn is_color(self) -> bool {
+        self == Self::Color
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = Error;
+
+    /// Parses `color`, `grayscale`, `mono` or `mono:THRESHOLD`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::invalid("color mode (color, grayscale, mono[:threshold])", s);
+        let (mode, threshold) = match s.split_once(':') {
+            Some((mode, threshold)) => (mode, Some(threshold)),
+            None => (s, None),
+        };
+        match (mode.to_ascii_lowercase().as_str(), threshold) {
+            ("color", None) => Ok(Self::Color),
+            ("grayscale" | "gray", None) => Ok(Self::Grayscale),
+            ("mono", None) => Ok(Self::Mono(DEFAULT_THRESHOLD)),
+            ("mono", Some(threshold)) => threshold
+                .trim()
+                .parse()
+                .map(Self::Mono)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+}
+
+impl Transform for ColorMode {
+    fn name(&self) -> &str {
+        match self {
+            Self::Color => "color",
+            Self::Grayscale => "grayscale",
+            Self::Mono(_) => "mono",
+        }
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        for pixel in image.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let level = match *self {
+                Self::Color => continue,
+                Self::Grayscale => luma(r, g, b),
+                Self::Mono(threshold) if luma(r, g, b) >= threshold => 255,
+                Self::Mono(_) => 0,
+            };
+            pixel.0 = [level, level, level, a];
+        }
+        Ok(image)
+    }
+
+    fn apply16(&self, mut image: Rgba16Image) -> Result<Rgba16Image> {
+        for pixel in image.pixels_mut() {
+            let [r, g, b, a] = pixel.0.map(f32::from);
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            let level = match *self {
+                Self::Color => continue,
+                Self::Grayscale => luma.round() as u16,
+                // The threshold stays on the 8-bit scale
+                Self::Mono(threshold) if luma / 257.0 >= threshold as f32 => u16::MAX,
+                Self::Mono(_) => 0,
+            };
+            pixel.0 = [level, level, level, a as u16];
+        }
+        Ok(image)
+    }
+}
+
+/// Narrows an image to gray or gray+alpha storage when no pixel has color,
+/// and drops alpha when every pixel is opaque
+pub fn reduce(image: &RgbaImage) -> DynamicImage {
+    let gray = image.pixels().all(|p| p.0[0] == p.0[1] && p.0[1] == p.0[2]);
+    if !gray {
+        return DynamicImage::ImageRgba8(image.clone());
+    }
+    let (width, height) = image.dimensions();
+    if image.pixels().all(|p| p.0[3] == 255) {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            [image.get_pixel(x, y).0[0]].into()
+        }))
+    } else {
+        DynamicImage::ImageLumaA8(GrayAlphaImage::from_fn(width, height, |x, y| {
+            let [level, _, _, alpha] = image.get_pixel(x, y).0;
+            LumaA([level, alpha])
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn swatches() -> RgbaImage {
+        let mut image = RgbaImage::new(3, 1);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 255, 0, 255]));
+        image.put_pixel(2, 0, Rgba([200, 200, 200, 128]));
+        image
+    }
+
+    #[test]
+    fn test_grayscale_uses_luma() {
+        let out = ColorMode::Grayscale.apply(swatches()).unwrap();
+
+        assert_eq!(out.get_pixel(0, 0).0, [54, 54, 54, 255], "Red is dark");
+        assert_eq!(out.get_pixel(1, 0).0, [182, 182, 182, 255]);
+        assert_eq!(out.get_pixel(2, 0).0, [200, 200, 200, 128], "Keeps alpha");
+    }
+
+    #[test]
+    fn test_mono_thresholds() {
+        let out = ColorMode::Mono(128).apply(swatches()).unwrap();
+        let levels: Vec<u8> = out.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(levels, [0, 255, 255]);
+
+        let out = ColorMode::Mono(220).apply(swatches()).unwrap();
+        assert!(out.pixels().all(|p| p.0[0] == 0), "Higher cut-off");
+    }
+
+    #[test]
+    fn test_16_bit_grayscale() {
+        let image = Rgba16Image::from_pixel(1, 1, Rgba([1000, 1000, 1001, 300]));
+        let out = ColorMode::Grayscale.apply16(image.clone()).unwrap();
+        assert_eq!(out.get_pixel(0, 0).0, [1000, 1000, 1000, 300]);
+
+        let out = ColorMode::Mono(3).apply16(image).unwrap();
+        assert_eq!(out.get_pixel(0, 0).0[0], u16::MAX, "1000/257 is above 3");
+    }
+
+    #[test]
+    fn test_reduce_narrows_storage() {
+        let gray = ColorMode::Grayscale.apply(swatches()).unwrap();
+        assert!(matches!(reduce(&gray), DynamicImage::ImageLumaA8(_)));
+
+        let opaque = RgbaImage::from_pixel(2, 2, Rgba([7, 7, 7, 255]));
+        assert!(matches!(reduce(&opaque), DynamicImage::ImageLuma8(_)));
+        assert!(matches!(reduce(&swatches()), DynamicImage::ImageRgba8(_)));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("gray".parse::<ColorMode>().unwrap(), ColorMode::Grayscale);
+        assert_eq!("mono".parse::<ColorMode>().unwrap(), ColorMode::Mono(128));
+        assert_eq!("Mono:90".parse::<ColorMode>().unwrap(), ColorMode::Mono(90));
+        for bad in ["sepia", "mono:300", "color:1"] {
+            assert!(bad.parse::<ColorMode>().is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use crate::color::Color;
+use crate::mask::{MaskStyle, DEFAULT_STRENGTH};
+use crate::retry::RetryPolicy;
+use crate::{Error, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variable that overrides the default config file location
+pub const CONFIG_ENV: &str = "SNAP_SCALE_CONFIG";
+
+/// User configuration, loaded from `config.toml`
+///
+/// Every section is optional; a missing file behaves like an empty one.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub capture: CaptureConfig,
+    pub notifications: NotificationConfig,
+    pub hooks: HooksConfig,
+    pub upload: UploadConfig,
+    pub redact: RedactConfig,
+    pub catalog: CatalogConfig,
+    pub mqtt: MqttConfig,
+    /// Named `--beautify` presets; `default` is used when no name is given
+    pub beautify: HashMap<String, BeautifyConfig>,
+    /// Windows `snap_scale daemon` captures as they appear
+    pub rules: Vec<RuleConfig>,
+    /// Captures `snap_scale daemon` takes on a calendar
+    pub schedules: Vec<ScheduleConfig>,
+}
+
+/// A `[beautify.<profile>]` preset, see [`crate::beautify`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BeautifyConfig {
+    /// Space around the capture on every side, in pixels
+    pub padding: u32,
+    pub background: Color,
+    /// Makes the background a gradient from `background` to this color
+    pub background_to: Option<Color>,
+    /// Gradient direction in degrees clockwise; 0 runs left to right
+    pub angle: f32,
+    pub shadow: bool,
+    pub shadow_blur: f32,
+    /// Shadow shift right and down, in pixels
+    pub shadow_offset: [i32; 2],
+    pub shadow_color: Color,
+}
+
+impl Default for BeautifyConfig {
+    fn default() -> Self {
+        Self {
+            padding: 64,
+            background: Color::new(0x4f, 0x46, 0xe5, 255),
+            background_to: Some(Color::new(0xdb, 0x27, 0x77, 255)),
+            angle: 45.0,
+            shadow: true,
+            shadow_blur: 24.0,
+            shadow_offset: [0, 12],
+            shadow_color: Color::new(0, 0, 0, 128),
+        }
+    }
+}
+
+/// Windows obscured in every capture, see [`crate::redact`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Application names, matched case-insensitively: the WM_CLASS class on
+    /// X11, the executable name without `.exe` on Windows, the app name on macOS
+    pub apps: Vec<String>,
+    /// Window title substrings, matched case-insensitively
+    pub titles: Vec<String>,
+    pub style: MaskStyle,
+    /// Blur sigma or pixel block size
+    pub strength: u32,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            apps: Vec::new(),
+            titles: Vec::new(),
+            style: MaskStyle::Blur,
+            strength: DEFAULT_STRENGTH,
+        }
+    }
+}
+
+/// Credentials and defaults for [`crate::upload`]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    /// Imgur API client id; `$IMGUR_CLIENT_ID` is used when unset
+    pub imgur_client_id: Option<String>,
+    /// Settings for `--upload http(s)://...` webhooks
+    pub http: HttpUploadConfig,
+    /// Settings for `--upload sftp://...`
+    pub sftp: SftpUploadConfig,
+    /// Settings for `--upload discord`
+    pub discord: DiscordConfig,
+    /// Settings for `--upload slack`
+    pub slack: SlackConfig,
+}
+
+/// Discord channel webhook that captures are posted to
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Webhook URL from the channel's Integrations settings
+    pub webhook_url: Option<String>,
+    /// Text posted above the image; `{file}` expands to the file name
+    pub message: Option<String>,
+    /// Overrides the webhook's display name
+    pub username: Option<String>,
+}
+
+/// Slack channel that captures are shared to
+///
+/// Slack's incoming webhooks can't carry files, so this uses a bot token with
+/// the `files:write` scope instead.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlackConfig {
+    /// Bot token (`xoxb-...`); `$SLACK_TOKEN` is used when unset
+    pub token: Option<String>,
+    /// Channel id such as `C0123456789`
+    pub channel: Option<String>,
+    /// Comment posted with the image; `{file}` expands to the file name
+    pub message: Option<String>,
+}
+
+/// Retrying captures that fail, see [`crate::retry`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Capture attempts before giving up; 1 never retries
+    pub attempts: u32,
+    /// Delay before the first retry
+    pub retry_delay_ms: u64,
+    /// What the delay is multiplied by after each retry
+    pub backoff: f64,
+    /// Seconds an attempt may take before it fails; 0 waits forever
+    pub timeout_secs: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        let retry = RetryPolicy::default();
+        Self {
+            attempts: retry.attempts,
+            retry_delay_ms: retry.delay.as_millis() as u64,
+            backoff: retry.backoff,
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl CaptureConfig {
+    pub fn retry(&self) -> RetryPolicy {
+        RetryPolicy {
+            attempts: self.attempts,
+            delay: Duration::from_millis(self.retry_delay_ms),
+            backoff: self.backoff,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+}
+
+/// Authentication and retry policy for SFTP uploads
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SftpUploadConfig {
+    /// Private key passed as `sftp -i`; ssh defaults and agents apply when unset
+    pub identity_file: Option<PathBuf>,
+    /// Connection attempts before giving up
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after each failure
+    pub retry_delay_ms: u64,
+    pub connect_timeout_secs: u32,
+}
+
+impl Default for SftpUploadConfig {
+    fn default() -> Self {
+        Self {
+            identity_file: None,
+            attempts: 3,
+            retry_delay_ms: 1000,
+            connect_timeout_secs: 10,
+        }
+    }
+}
+
+/// How captures are POSTed to webhook URLs
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpUploadConfig {
+    pub mode: HttpBodyMode,
+    /// Form field holding the file in multipart mode
+    pub field: String,
+    /// Extra request headers, e.g. `Authorization`
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for HttpUploadConfig {
+    fn default() -> Self {
+        Self {
+            mode: HttpBodyMode::Multipart,
+            field: "file".into(),
+            headers: HashMap::new(),
+        }
+    }
+}
+
+/// Request body layout for webhook uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpBodyMode {
+    /// `multipart/form-data` with the image as a file field
+    #[default]
+    Multipart,
+    /// The encoded image as the whole request body
+    Raw,
+}
+
+/// Shell commands run around each capture, see [`crate::hooks`]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Runs before each capture; a failure aborts the capture
+    pub pre_capture: Option<String>,
+    /// Runs after each saved capture with `$SNAP_FILE` set
+    pub post_capture: Option<String>,
+}
+
+/// Recording of saved captures in the catalog, see `snap_scale history`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CatalogConfig {
+    /// Record every saved capture; needs the `catalog` feature
+    pub enabled: bool,
+    /// Database file; `<data dir>/snap_scale/catalog.sqlite` when unset
+    pub path: Option<PathBuf>,
+}
+
+/// A `[[rules]]` entry, see [`crate::rules`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// Names the rule in logs and metadata; the title pattern when unset
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Regular expression matching somewhere in the window title, e.g.
+    /// `(?i)error|failed`
+    pub title: String,
+    /// Only windows of this application, matched case-insensitively like
+    /// `[redact] apps`
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Directory the captures go to
+    #[serde(default = "default_rule_dir")]
+    pub dir: PathBuf,
+}
+
+fn default_rule_dir() -> PathBuf {
+    PathBuf::from("target/rules")
+}
+
+/// A `[[schedules]]` entry, see [`crate::schedule`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Names the schedule in logs; the expression when unset
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Crontab-style expression of when to capture, e.g. `0 */1 * * *`
+    pub schedule: String,
+    /// Display to capture, as for `--display`
+    #[serde(default = "default_schedule_display")]
+    pub display: String,
+    /// Directory the captures go to
+    #[serde(default = "default_schedule_dir")]
+    pub dir: PathBuf,
+}
+
+fn default_schedule_display() -> String {
+    "primary".into()
+}
+
+fn default_schedule_dir() -> PathBuf {
+    PathBuf::from("target/schedule")
+}
+
+/// Publishing of capture events to an MQTT broker, see [`crate::mqtt`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Publish an event for every saved capture; needs the `mqtt` feature
+    pub enabled: bool,
+    /// `host`, `host:port` or `mqtt://host:port`; the port defaults to 1883
+    pub broker: String,
+    /// Topic of the JSON events; images go to `<topic>/image`
+    pub topic: String,
+    /// Also publish the encoded image of each capture
+    pub image: bool,
+    /// Delivery guarantee: 0 at most once, 1 at least once, 2 exactly once
+    pub qos: u8,
+    /// Ask the broker to keep the latest message for new subscribers
+    pub retain: bool,
+    /// `snap_scale-<hostname>` when unset
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker: "localhost".into(),
+            topic: "snap_scale/captures".into(),
+            image: false,
+            qos: 1,
+            retain: false,
+            client_id: None,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// Desktop notification settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Fire a notification after every saved capture
+    pub enabled: bool,
+    /// Show the saved image as the notification's thumbnail
+    pub thumbnail: bool,
+    /// Open the file when the notification is clicked; this keeps the
+    /// process alive until the notification is dismissed or times out
+    pub open_on_click: bool,
+    /// How long the notification stays visible, in milliseconds
+    pub timeout_ms: u32,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            thumbnail: true,
+            open_on_click: false,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// Loads the config from an explicit path, which must exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("cannot read {}: {e}", path.display())))?;
+        Self::from_toml(&text)
+    }
+
+    /// Loads the config from `$SNAP_SCALE_CONFIG` or the default location,
+    /// falling back to the defaults when no file exists there
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// `$SNAP_SCALE_CONFIG`, or `<config dir>/snap_scale/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os(CONFIG_ENV)
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("snap_scale").join("config.toml")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_notification_section() {
+        let config = Config::from_toml(
+            r#"
+            [notifications]
+            enabled = true
+            open_on_click = true
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.notifications.enabled);
+        assert!(config.notifications.open_on_click);
+        assert!(
+            config.notifications.thumbnail,
+            "Unset keys should keep their defaults"
+        );
+    }
+
+    #[test]
+    fn test_hooks_section() {
+        let config = Config::from_toml(
+            r#"
+            [hooks]
+            post_capture = "cp \"$SNAP_FILE\" /archive/"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.hooks.pre_capture, None);
+        assert_eq!(
+            config.hooks.post_capture.as_deref(),
+            Some(r#"cp "$SNAP_FILE" /archive/"#)
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let config = Config::from_toml(
+            r#"
+            [[rules]]
+            name = "errors"
+            title = "(?i)error|failed"
+            dir = "captures/errors"
+
+            [[rules]]
+            title = "Save As"
+            app = "gimp"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].name.as_deref(), Some("errors"));
+        assert_eq!(config.rules[0].dir, PathBuf::from("captures/errors"));
+        assert_eq!(config.rules[1].app.as_deref(), Some("gimp"));
+        assert_eq!(config.rules[1].dir, PathBuf::from("target/rules"));
+        assert!(Config::from_toml("[[rules]]\nname = \"no title\"").is_err());
+    }
+
+    #[test]
+    fn test_schedules() {
+        let config = Config::from_toml(
+            r#"
+            [[schedules]]
+            name = "hourly"
+            schedule = "0 */1 * * *"
+            display = "1"
+            dir = "captures/hourly"
+
+            [[schedules]]
+            schedule = "@daily"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.schedules.len(), 2);
+        assert_eq!(config.schedules[0].name.as_deref(), Some("hourly"));
+        assert_eq!(config.schedules[0].display, "1");
+        assert_eq!(config.schedules[1].display, "primary");
+        assert_eq!(config.schedules[1].dir, PathBuf::from("target/schedule"));
+        assert!(Config::from_toml("[[schedules]]\nname = \"when\"").is_err());
+    }
+
+    #[test]
+    fn test_http_upload_section() {
+        let config = Config::from_toml(
+            r#"
+            [upload.http]
+            mode = "raw"
+            headers = { Authorization = "Bearer token" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.upload.http.mode, HttpBodyMode::Raw);
+        assert_eq!(config.upload.http.field, "file");
+        assert_eq!(config.upload.http.headers["Authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn test_capture_section() {
+        let config = Config::from_toml(
+            r#"
+            [capture]
+            attempts = 5
+            backoff = 1.5
+            timeout_secs = 0
+            "#,
+        )
+        .unwrap();
+
+        let retry = config.capture.retry();
+        assert_eq!(retry.attempts, 5);
+        assert_eq!(retry.backoff, 1.5);
+        assert_eq!(retry.delay, RetryPolicy::default().delay);
+        assert_eq!(config.capture.timeout(), None, "0 never times out");
+        assert_eq!(
+            CaptureConfig::default().timeout(),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_sftp_upload_section() {
+        let config = Config::from_toml(
+            r#"
+            [upload.sftp]
+            identity_file = "~/.ssh/snap_scale"
+            attempts = 5
+            "#,
+        )
+        .unwrap();
+
+        let sftp = &config.upload.sftp;
+        assert_eq!(
+            sftp.identity_file.as_deref(),
+            Some(Path::new("~/.ssh/snap_scale"))
+        );
+        assert_eq!(sftp.attempts, 5);
+        assert_eq!(sftp.retry_delay_ms, 1000, "Unset keys keep their defaults");
+    }
+
+    #[test]
+    fn test_chat_sections() {
+        let config = Config::from_toml(
+            r#"
+            [upload.discord]
+            webhook_url = "https://discord.com/api/webhooks/1/abc"
+            message = "Bug report: {file}"
+
+            [upload.slack]
+            channel = "C0123456789"
+            "#,
+        )
+        .unwrap();
+
+        let upload = &config.upload;
+        assert_eq!(
+            upload.discord.webhook_url.as_deref(),
+            Some("https://discord.com/api/webhooks/1/abc")
+        );
+        assert_eq!(
+            upload.discord.message.as_deref(),
+            Some("Bug report: {file}")
+        );
+        assert_eq!(upload.slack.channel.as_deref(), Some("C0123456789"));
+        assert_eq!(upload.slack.token, None);
+    }
+
+    #[test]
+    fn test_redact_section() {
+        let config = Config::from_toml(
+            r#"
+            [redact]
+            apps = ["KeePassXC"]
+            titles = ["- Signal"]
+            style = "pixelate"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.redact.apps, ["KeePassXC"]);
+        assert_eq!(config.redact.titles, ["- Signal"]);
+        assert_eq!(config.redact.style, MaskStyle::Pixelate);
+        assert_eq!(config.redact.strength, DEFAULT_STRENGTH);
+    }
+
+    #[test]
+    fn test_catalog_section() {
+        let config =
+            Config::from_toml("[catalog]\nenabled = true\npath = \"shots.sqlite\"").unwrap();
+        assert!(config.catalog.enabled);
+        assert_eq!(config.catalog.path, Some(PathBuf::from("shots.sqlite")));
+        assert_eq!(Config::default().catalog, CatalogConfig::default());
+    }
+
+    #[test]
+    fn test_mqtt_section() {
+        let config = Config::from_toml(
+            r#"
+            [mqtt]
+            enabled = true
+            broker = "mqtt://broker.lan:1884"
+            image = true
+            qos = 0
+            "#,
+        )
+        .unwrap();
+        assert!(config.mqtt.enabled && config.mqtt.image);
+        assert_eq!(config.mqtt.broker, "mqtt://broker.lan:1884");
+        assert_eq!(config.mqtt.qos, 0);
+        assert_eq!(config.mqtt.topic, "snap_scale/captures", "Default topic");
+    }
+
+    #[test]
+    fn test_beautify_profiles() {
+        let config = Config::from_toml(
+            r##"
+            [beautify.docs]
+            padding = 32
+            background = "#ffffff"
+            shadow_offset = [4, 8]
+
+            [beautify.social]
+            background_to = "#000"
+            "##,
+        )
+        .unwrap();
+
+        let docs = &config.beautify["docs"];
+        assert_eq!(docs.padding, 32);
+        assert_eq!(docs.background, Color::WHITE);
+        assert_eq!(docs.shadow_offset, [4, 8]);
+        assert_eq!(config.beautify["social"].background_to, Some(Color::BLACK));
+        assert!(Config::from_toml("[beautify.bad]\nbackground = \"teal-ish\"").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let err = Config::from_toml("[notifications]\nenabeld = true").unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "Typos should be reported");
+    }
+}
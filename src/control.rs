@@ -0,0 +1,445 @@
+//! Controlling the daemon through a local socket
+//!
+//! `snap_scale daemon --socket PATH` also listens on a Unix socket, or on
+//! Windows a named pipe such as `\\.\pipe\snap_scale`, for commands of a
+//! line each, so editors and scripts can trigger captures without spawning
+//! a process each time:
+//!
+//! ```text
+//! capture display=1 region=10,10,400,300 path=/tmp/area.png
+//! ok /tmp/area.png
+//! capture window=0x3c00007
+//! ok target/1-20261014-104211-123.png
+//! ```
+//!
+//! `capture` takes a whole display by index, 0 unless `display=` says,
+//! a logical `region=x,y,w,h` of it, or a window by id, decimal or `0x` hex.
+//! `path=` picks where to save, in double quotes when it has spaces. Every
+//! command is answered with a line of `ok` and the saved path, or of `error`
+//! and why; `ping` answers `ok`. Captures become [`Job`]s for the daemon's own
+//! loop, as D-Bus calls do, so they run one at a time through the same
+//! pipeline, hooks and catalog as the command line.
+
+use crate::geometry::Region;
+use crate::{Error, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// What a command asked to capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// A whole display, by index
+    Display(usize),
+    /// A logical area of a display
+    Region { display: usize, region: Region },
+    /// A top-level window, by id
+    Window(u64),
+}
+
+/// One line sent to the socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Ping,
+    Capture {
+        target: Target,
+        /// Where to save; the daemon's choice when `None`
+        path: Option<PathBuf>,
+    },
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let words = words(s)?;
+        let Some((command, args)) = words.split_first() else {
+            return Err(Error::invalid("command", "an empty line"));
+        };
+        match command.as_str() {
+            "ping" if args.is_empty() => Ok(Self::Ping),
+            "capture" => capture(args),
+            _ => Err(Error::invalid("command (capture or ping)", s)),
+        }
+    }
+}
+
+/// The arguments of `capture`, each `key=value`
+fn capture(args: &[String]) -> Result<Command> {
+    let (mut display, mut region, mut window, mut path) = (None, None, None, None);
+    for arg in args {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| Error::invalid("capture argument (expected key=value)", arg))?;
+        match key {
+            "display" => {
+                display = Some(
+                    value
+                        .parse()
+                        .map_err(|_| Error::invalid("display index", value))?,
+                )
+            }
+            "region" => region = Some(value.parse::<Region>()?),
+            "window" => {
+                let id = match value.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => value.parse(),
+                };
+                window = Some(id.map_err(|_| Error::invalid("window id", value))?)
+            }
+            "path" => path = Some(PathBuf::from(value)),
+            _ => return Err(Error::invalid("capture argument", key)),
+        }
+    }
+    if region.is_some_and(|region: Region| region.is_empty()) {
+        return Err(Error::invalid(
+            "region",
+            "width and height must be positive",
+        ));
+    }
+    let target = match (window, display, region) {
+        (Some(id), None, None) => Target::Window(id),
+        (Some(_), _, _) => {
+            return Err(Error::invalid(
+                "capture",
+                "window= goes without display= and region=",
+            ))
+        }
+        (None, display, None) => Target::Display(display.unwrap_or(0)),
+        (None, display, Some(region)) => Target::Region {
+            display: display.unwrap_or(0),
+            region,
+        },
+    };
+    Ok(Command::Capture { target, path })
+}
+
+/// Splits a line at whitespace outside double quotes, dropping the quotes
+fn words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::invalid(
+            "command",
+            format!("unclosed quote in {line}"),
+        ));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// A capture waiting for the daemon
+#[derive(Debug)]
+pub struct Job {
+    pub target: Target,
+    /// Where to save; the daemon's choice when `None`
+    pub path: Option<PathBuf>,
+    reply: Sender<std::result::Result<PathBuf, String>>,
+}
+
+impl Job {
+    /// A job for `target`, and where its answer arrives
+    pub fn new(
+        target: Target,
+        path: Option<PathBuf>,
+    ) -> (Self, Receiver<std::result::Result<PathBuf, String>>) {
+        let (reply, result) = mpsc::channel();
+        let job = Self {
+            target,
+            path,
+            reply,
+        };
+        (job, result)
+    }
+
+    /// Answers the command with the path saved to, or why nothing was
+    pub fn finish(self, result: std::result::Result<PathBuf, String>) {
+        // The client may have hung up
+        let _ = self.reply.send(result);
+    }
+}
+
+/// The answer to one line
+fn answer(line: &str, jobs: &Sender<Job>) -> String {
+    let (target, path) = match line.parse() {
+        Ok(Command::Ping) => return "ok".into(),
+        Ok(Command::Capture { target, path }) => (target, path),
+        Err(e) => return format!("error {e}"),
+    };
+    let (job, result) = Job::new(target, path);
+    if jobs.send(job).is_err() {
+        return "error the capture loop has stopped".into();
+    }
+    match result.recv() {
+        Ok(Ok(path)) => format!("ok {}", path.display()),
+        // Answers are a line each
+        Ok(Err(e)) => format!("error {}", e.replace('\n', " ")),
+        Err(_) => "error the capture was dropped".into(),
+    }
+}
+
+/// Answers the lines of one client until it hangs up
+fn converse(reader: impl Read, mut writer: impl Write, jobs: &Sender<Job>) -> std::io::Result<()> {
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", answer(&line, jobs))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Listens on the Unix socket at `path`, which only the user can connect
+/// to; captures arrive on the receiver
+///
+/// A socket file left by a daemon that didn't exit cleanly is replaced,
+/// one a running daemon listens on is not.
+#[cfg(unix)]
+pub fn listen(path: &Path) -> Result<Receiver<Job>> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let stale = std::fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_type().is_socket())
+        && UnixStream::connect(path).is_err();
+    if stale {
+        std::fs::remove_file(path)?;
+    }
+    let listener = bind_private(path)?;
+    let (jobs, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let jobs = jobs.clone();
+            std::thread::spawn(move || {
+                if let Ok(reader) = stream.try_clone() {
+                    let _ = converse(reader, stream, &jobs);
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+/// Binds a socket at `path` that nobody else can connect to, even for a
+/// moment
+///
+/// `bind` creates the socket with the umask's permissions, so it's bound in
+/// a directory only the user can enter, made owner-only there and then
+/// linked into place, which fails rather than replace a file at `path`.
+#[cfg(unix)]
+fn bind_private(path: &Path) -> Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::invalid("socket path", path.display().to_string()))?;
+    let dir = path.with_file_name(format!(
+        ".{}.{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let private = dir.join("socket");
+    let bound = UnixListener::bind(&private).and_then(|listener| {
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::hard_link(&private, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&private);
+    let _ = std::fs::remove_dir(&dir);
+    Ok(bound?)
+}
+
+/// Listens on the named pipe `path`, e.g. `\\.\pipe\snap_scale`, which
+/// remote clients can't connect to; captures arrive on the receiver
+#[cfg(windows)]
+pub fn listen(path: &Path) -> Result<Receiver<Job>> {
+    use std::fs::File;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE};
+    use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // A pipe takes one client, so every client gets a new instance
+    let instance = move || -> Result<File> {
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(name.as_ptr()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                None,
+            )
+        };
+        if pipe.is_invalid() {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(unsafe { File::from_raw_handle(pipe.0 as _) })
+    };
+    // The first instance is made here, so that a bad name fails now
+    let mut pipe = instance()?;
+    let (jobs, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        let handle = HANDLE(pipe.as_raw_handle() as isize);
+        let connected = match unsafe { ConnectNamedPipe(handle, None) } {
+            Ok(()) => true,
+            // The client connected before the wait began
+            Err(e) => e.code() == ERROR_PIPE_CONNECTED.to_hresult(),
+        };
+        if connected {
+            let jobs = jobs.clone();
+            std::thread::spawn(move || {
+                if let Ok(reader) = pipe.try_clone() {
+                    let _ = converse(reader, pipe, &jobs);
+                }
+            });
+        }
+        pipe = match instance() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                tracing::warn!("no longer listening for commands: {e}");
+                return;
+            }
+        };
+    });
+    Ok(receiver)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn listen(path: &Path) -> Result<Receiver<Job>> {
+    Err(Error::Unsupported(format!(
+        "control socket {}: local sockets are not available on this platform",
+        path.display()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(line: &str) -> (Target, Option<PathBuf>) {
+        match line.parse().unwrap() {
+            Command::Capture { target, path } => (target, path),
+            command => panic!("{line}: {command:?}"),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!("ping".parse::<Command>().unwrap(), Command::Ping);
+        assert_eq!(capture("capture"), (Target::Display(0), None));
+        assert_eq!(
+            capture("capture display=1 region=10,10,400,300 path=/tmp/area.png"),
+            (
+                Target::Region {
+                    display: 1,
+                    region: Region::new(10, 10, 400, 300)
+                },
+                Some("/tmp/area.png".into())
+            )
+        );
+        assert_eq!(
+            capture(r#"  capture window=0x3c00007 path="my shots/a.png""#),
+            (Target::Window(0x3c00007), Some("my shots/a.png".into()))
+        );
+        assert_eq!(capture("capture window=42").0, Target::Window(42));
+
+        for bad in [
+            "",
+            "snap",
+            "ping now",
+            "capture display",
+            "capture display=first",
+            "capture region=1,2,3",
+            "capture region=0,0,0,10",
+            "capture window=1 display=0",
+            "capture size=10",
+            "capture path=\"unclosed",
+        ] {
+            let err = bad.parse::<Command>().unwrap_err();
+            assert!(matches!(err, Error::Invalid { .. }), "{bad:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_lines_wait_for_their_job() {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let daemon = std::thread::spawn(move || {
+            for job in receiver {
+                let result = match job.target {
+                    Target::Window(_) => Err("no such\nwindow".to_owned()),
+                    _ => Ok(job.path.clone().unwrap_or("target/picked.png".into())),
+                };
+                job.finish(result);
+            }
+        });
+        let input = "ping\n\ncapture display=1\ncapture window=7\ncapture nothing\n";
+        let mut output = Vec::new();
+        converse(input.as_bytes(), &mut output, &jobs).unwrap();
+        drop(jobs);
+        daemon.join().unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines[..3],
+            ["ok", "ok target/picked.png", "error no such window"]
+        );
+        assert!(
+            lines[3].starts_with("error invalid capture argument"),
+            "{output}"
+        );
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("snap_scale-{}.sock", std::process::id()));
+        let receiver = listen(&path).unwrap();
+        std::thread::spawn(move || {
+            for job in receiver {
+                job.finish(Ok("target/shot.png".into()));
+            }
+        });
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"capture\n").unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ok target/shot.png\n");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Listening on it again fails while it's in use
+        assert!(listen(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Rounded corners for window captures
+//!
+//! [`RoundedCorners`] cuts the corners of a capture away with an antialiased
+//! alpha mask, matching how most window managers draw windows, and can trace
+//! the new outline with a thin border. Formats without alpha flatten the cut
+//! corners, so save as PNG.
+
+use crate::annotate::blend;
+use crate::color::Color;
+use crate::transform::Transform;
+use crate::Result;
+use screenshots::image::RgbaImage;
+
+/// Default corner radius in pixels
+pub const DEFAULT_RADIUS: u32 = 12;
+
+/// Rounds the corners of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundedCorners {
+    /// Corner radius in pixels; clamped to half the shorter side
+    pub radius: u32,
+    /// Traces the outline 1px wide when set
+    pub border: Option<Color>,
+}
+
+impl Default for RoundedCorners {
+    fn default() -> Self {
+        Self::new(DEFAULT_RADIUS)
+    }
+}
+
+impl RoundedCorners {
+    pub fn new(radius: u32) -> Self {
+        Self {
+            radius,
+            border: None,
+        }
+    }
+
+    pub fn with_border(mut self, color: Color) -> Self {
+        self.border = Some(color);
+        self
+    }
+
+    /// Rounds the corners in place
+    pub fn apply_to(&self, image: &mut RgbaImage) {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let radius = (self.radius as f32).min(width.min(height) / 2.0);
+
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let distance =
+                rounded_rect_distance(x as f32 + 0.5, y as f32 + 0.5, width, height, radius);
+            let outer = (0.5 - distance).clamp(0.0, 1.0);
+            if outer <= 0.0 {
+                *pixel = [0, 0, 0, 0].into();
+                continue;
+            }
+            if let Some(border) = self.border {
+                // The outline is the shape minus the shape inset by 1px
+                let inner = (-0.5 - distance).clamp(0.0, 1.0);
+                blend(pixel, border, (outer - inner) / outer);
+            }
+            pixel.0[3] = (pixel.0[3] as f32 * outer).round() as u8;
+        }
+    }
+}
+
+/// Signed distance from `(x, y)` to a `width`×`height` rectangle with
+/// corners of radius `r`, negative inside
+fn rounded_rect_distance(x: f32, y: f32, width: f32, height: f32, r: f32) -> f32 {
+    let qx = (x - width / 2.0).abs() - (width / 2.0 - r);
+    let qy = (y - height / 2.0).abs() - (height / 2.0 - r);
+    let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+    outside + qx.max(qy).min(0.0) - r
+}
+
+/// Fills a rounded rectangle with antialiased edges; `(x, y)` is its
+/// top-left corner
+#[cfg(feature = "frames")]
+pub(crate) fn fill_rounded_rect(
+    image: &mut RgbaImage,
+    (x, y): (f32, f32),
+    (width, height): (f32, f32),
+    radius: f32,
+    color: Color,
+) {
+    let radius = radius.min(width.min(height) / 2.0);
+    let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max);
+    let (x0, y0) = (
+        clamp(x.floor(), image.width()),
+        clamp(y.floor(), image.height()),
+    );
+    let (x1, y1) = (
+        clamp((x + width).ceil(), image.width()),
+        clamp((y + height).ceil(), image.height()),
+    );
+    for py in y0..y1 {
+        for px in x0..x1 {
+            let distance = rounded_rect_distance(
+                px as f32 + 0.5 - x,
+                py as f32 + 0.5 - y,
+                width,
+                height,
+                radius,
+            );
+            blend(image.get_pixel_mut(px, py), color, 0.5 - distance);
+        }
+    }
+}
+
+impl Transform for RoundedCorners {
+    fn name(&self) -> &str {
+        "rounded-corners"
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        self.apply_to(&mut image);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn window() -> RgbaImage {
+        RgbaImage::from_pixel(40, 30, Rgba([255, 255, 255, 255]))
+    }
+
+    #[test]
+    fn test_corners_become_transparent() {
+        let image = RoundedCorners::new(10).apply(window()).unwrap();
+
+        for (x, y) in [(0, 0), (39, 0), (0, 29), (39, 29)] {
+            assert_eq!(image.get_pixel(x, y).0[3], 0, "Corner ({x}, {y})");
+        }
+        assert_eq!(image.get_pixel(20, 0).0[3], 255, "Edges stay opaque");
+        assert_eq!(image.get_pixel(0, 15).0[3], 255);
+        assert_eq!(image.get_pixel(20, 15).0, [255, 255, 255, 255]);
+
+        let edge = image.get_pixel(0, 6).0[3];
+        assert!(edge > 0 && edge < 255, "Antialiased edge: {edge}");
+    }
+
+    #[test]
+    fn test_border_traces_the_outline() {
+        let image = RoundedCorners::new(8)
+            .with_border(Color::BLACK)
+            .apply(window())
+            .unwrap();
+
+        assert_eq!(image.get_pixel(20, 0).0, [0, 0, 0, 255], "Top edge");
+        assert_eq!(image.get_pixel(39, 15).0, [0, 0, 0, 255], "Right edge");
+        assert_eq!(image.get_pixel(20, 1).0, [255, 255, 255, 255], "1px wide");
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+    }
+
+    #[test]
+    fn test_radius_is_clamped() {
+        let image = RoundedCorners::new(1000)
+            .apply(RgbaImage::from_pixel(10, 10, Rgba([255; 4])))
+            .unwrap();
+
+        assert_eq!(image.get_pixel(5, 5).0[3], 255, "A circle remains");
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+    }
+}
@@ -0,0 +1,80 @@
+//! Mouse cursor position
+//!
+//! Positions are desktop-global logical coordinates, the same space as
+//! [`DisplayInfo`](screenshots::display_info::DisplayInfo) bounds, so they can
+//! be passed straight to `Screen::from_point`.
+
+use crate::{Error, Result};
+
+/// Current cursor position
+#[cfg(target_os = "linux")]
+pub fn position() -> Result<(i32, i32)> {
+    use xcb::x;
+
+    let unavailable = |e: &dyn std::fmt::Display| Error::Unsupported(format!("cursor query: {e}"));
+    let (conn, screen_num) = xcb::Connection::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("cursor query: no X screen".into()))?
+        .root();
+    let reply = conn
+        .wait_for_reply(conn.send_request(&x::QueryPointer { window: root }))
+        .map_err(|e| unavailable(&e))?;
+
+    let scale = x11_scale();
+    Ok((
+        (reply.root_x() as f32 / scale) as i32,
+        (reply.root_y() as f32 / scale) as i32,
+    ))
+}
+
+/// Divisor from X11 physical pixels to logical coordinates
+///
+/// Display bounds are divided by the global Xft.dpi scale, so anything read
+/// straight from the X server has to be too.
+#[cfg(target_os = "linux")]
+pub(crate) fn x11_scale() -> f32 {
+    use crate::backend::{CaptureBackend, Screens};
+
+    Screens
+        .enumerate()
+        .ok()
+        .and_then(|displays| displays.first().map(|d| d.scale_factor))
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(1.0)
+}
+
+/// Current cursor position
+#[cfg(target_os = "windows")]
+pub fn position() -> Result<(i32, i32)> {
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+    let mut point = POINT::default();
+    unsafe { GetCursorPos(&mut point) }
+        .map_err(|e| Error::Unsupported(format!("cursor query: {e}")))?;
+    Ok((point.x, point.y))
+}
+
+/// Current cursor position
+#[cfg(target_os = "macos")]
+pub fn position() -> Result<(i32, i32)> {
+    use core_graphics::event::CGEvent;
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let unavailable = || Error::Unsupported("cursor query: no event source".into());
+    let source =
+        CGEventSource::new(CGEventSourceStateID::HIDSystemState).map_err(|_| unavailable())?;
+    let location = CGEvent::new(source).map_err(|_| unavailable())?.location();
+    Ok((location.x as i32, location.y as i32))
+}
+
+/// Current cursor position
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn position() -> Result<(i32, i32)> {
+    Err(Error::Unsupported(
+        "cursor position is not available on this platform".into(),
+    ))
+}
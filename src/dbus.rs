@@ -0,0 +1,185 @@
+//! D-Bus capture service on Linux
+//!
+//! `snap_scale dbus` owns [`NAME`] on the session bus and exports the
+//! `org.snapscale.Capture` interface at [`PATH`], so desktop environments,
+//! hotkey daemons and other apps can trigger captures without spawning a
+//! process:
+//!
+//! | Method | Arguments | Returns |
+//! |---|---|---|
+//! | `Capture` | display index, path | the saved path |
+//! | `CaptureRegion` | display index, x, y, width, height, path | the saved path |
+//! | `CaptureWindow` | window id, path | the saved path |
+//!
+//! An empty path lets the daemon pick one. Regions are logical and local to
+//! the display; window ids are the X11 ids `xdotool` and `xprop` show, and
+//! windows are captured whole through [`window::capture`](crate::window::capture).
+//! Calls become [`Job`]s for the daemon's own capture loop, so they run one at
+//! a time through the same pipeline, hooks and catalog as the command line.
+
+use crate::geometry::Region;
+use crate::{Error, Result};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use zbus::fdo;
+
+/// Well-known bus name of the service
+pub const NAME: &str = "org.snapscale.Capture";
+
+/// Object path the interface is exported at
+pub const PATH: &str = "/org/snapscale/Capture";
+
+/// What a call asked to capture
+pub use crate::control::Target;
+
+/// A capture waiting for the daemon
+#[derive(Debug)]
+pub struct Job {
+    pub target: Target,
+    /// Where to save; the daemon's choice when `None`
+    pub path: Option<PathBuf>,
+    reply: Sender<std::result::Result<PathBuf, String>>,
+}
+
+impl Job {
+    /// Answers the call with the path saved to, or why nothing was
+    pub fn finish(self, result: std::result::Result<PathBuf, String>) {
+        // The caller may have timed out and gone
+        let _ = self.reply.send(result);
+    }
+}
+
+/// The exported object; turns calls into [`Job`]s
+pub struct Service {
+    jobs: Sender<Job>,
+}
+
+impl Service {
+    /// A service and the jobs its calls produce
+    pub fn new() -> (Self, Receiver<Job>) {
+        let (jobs, receiver) = mpsc::channel();
+        (Self { jobs }, receiver)
+    }
+
+    fn run(&self, target: Target, path: &str) -> fdo::Result<String> {
+        let (reply, result) = mpsc::channel();
+        let job = Job {
+            target,
+            path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            reply,
+        };
+        self.jobs
+            .send(job)
+            .map_err(|_| fdo::Error::Failed("the capture loop has stopped".into()))?;
+        let saved = result
+            .recv()
+            .map_err(|_| fdo::Error::Failed("the capture was dropped".into()))?;
+        saved
+            .map(|path| path.display().to_string())
+            .map_err(fdo::Error::Failed)
+    }
+}
+
+#[zbus::interface(name = "org.snapscale.Capture")]
+impl Service {
+    /// Captures a whole display
+    fn capture(&self, display: u32, path: &str) -> fdo::Result<String> {
+        self.run(Target::Display(display as usize), path)
+    }
+
+    /// Captures a logical area of a display
+    fn capture_region(
+        &self,
+        display: u32,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        path: &str,
+    ) -> fdo::Result<String> {
+        let region = Region::new(x, y, width, height);
+        if region.is_empty() {
+            return Err(fdo::Error::InvalidArgs(
+                "width and height must be positive".into(),
+            ));
+        }
+        let display = display as usize;
+        self.run(Target::Region { display, region }, path)
+    }
+
+    /// Captures a window's contents, or the part of it on the display under
+    /// its center where they can't be read
+    fn capture_window(&self, window: u64, path: &str) -> fdo::Result<String> {
+        self.run(Target::Window(window), path)
+    }
+}
+
+/// Claims [`NAME`] on the session bus and exports the service; calls arrive
+/// on the receiver for as long as the connection is kept
+pub fn serve() -> Result<(zbus::blocking::Connection, Receiver<Job>)> {
+    let (service, jobs) = Service::new();
+    let connection = zbus::blocking::connection::Builder::session()
+        .and_then(|builder| builder.name(NAME))
+        .and_then(|builder| builder.serve_at(PATH, service))
+        .and_then(|builder| builder.build())
+        .map_err(|e| Error::Unsupported(format!("D-Bus session bus: {e}")))?;
+    Ok((connection, jobs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_wait_for_their_job() {
+        let (service, jobs) = Service::new();
+        let daemon = std::thread::spawn(move || {
+            let mut targets = Vec::new();
+            for job in jobs {
+                targets.push(job.target);
+                let result = match job.target {
+                    Target::Window(_) => Err("no such window".to_owned()),
+                    _ => Ok(job.path.clone().unwrap_or("target/picked.png".into())),
+                };
+                job.finish(result);
+            }
+            targets
+        });
+
+        assert_eq!(service.capture(1, "").unwrap(), "target/picked.png");
+        assert_eq!(
+            service
+                .capture_region(0, 5, 5, 20, 10, "/tmp/area.png")
+                .unwrap(),
+            "/tmp/area.png"
+        );
+        let Err(fdo::Error::Failed(message)) = service.capture_window(42, "") else {
+            panic!("Window capture should fail");
+        };
+        assert_eq!(message, "no such window");
+        assert!(matches!(
+            service.capture_region(0, 0, 0, 0, 10, ""),
+            Err(fdo::Error::InvalidArgs(_))
+        ));
+        drop(service);
+
+        assert_eq!(
+            daemon.join().unwrap(),
+            [
+                Target::Display(1),
+                Target::Region {
+                    display: 0,
+                    region: Region::new(5, 5, 20, 10)
+                },
+                Target::Window(42),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_fails_once_the_loop_stops() {
+        let (service, jobs) = Service::new();
+        drop(jobs);
+        assert!(matches!(service.capture(0, ""), Err(fdo::Error::Failed(_))));
+    }
+}
@@ -0,0 +1,263 @@
+//! Comparing two images
+//!
+//! [`compare`] reports how many pixels changed, the PSNR over RGB, and a mean
+//! SSIM over 8×8 luma windows, plus a heatmap highlighting changed pixels.
+
+use crate::geometry::Region;
+use crate::{Error, Result};
+use screenshots::image::{Rgba, RgbaImage};
+use std::borrow::Cow;
+
+/// SSIM window edge in pixels
+const WINDOW: u32 = 8;
+
+/// How images are compared
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Largest per-channel difference still counted as unchanged
+    pub tolerance: u8,
+    /// Areas whose content is ignored, e.g. clocks or cursors
+    pub ignore: Vec<Region>,
+}
+
+impl DiffOptions {
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Adds an area to ignore
+    pub fn ignore(mut self, region: Region) -> Self {
+        self.ignore.push(region);
+        self
+    }
+}
+
+/// Result of [`compare`]
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    /// Pixels with a channel differing by more than the tolerance
+    pub changed_pixels: u64,
+    pub total_pixels: u64,
+    /// Mean structural similarity, 1.0 for identical images
+    pub ssim: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images
+    pub psnr: f64,
+    /// Dimmed copy of the first image with changes drawn from yellow (small)
+    /// to red (large)
+    pub heatmap: RgbaImage,
+}
+
+impl DiffReport {
+    /// Share of changed pixels, 0-100
+    pub fn changed_percent(&self) -> f64 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.changed_pixels as f64 * 100.0 / self.total_pixels as f64
+        }
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.changed_pixels == 0
+    }
+}
+
+/// Compares two images of the same size
+pub fn compare(a: &RgbaImage, b: &RgbaImage, options: &DiffOptions) -> Result<DiffReport> {
+    if a.dimensions() != b.dimensions() {
+        return Err(Error::invalid(
+            "image pair (sizes differ)",
+            format!(
+                "{}x{} vs {}x{}",
+                a.width(),
+                a.height(),
+                b.width(),
+                b.height()
+            ),
+        ));
+    }
+
+    // Masked areas take the first image's pixels, so they match everywhere
+    let b = if options.ignore.is_empty() {
+        Cow::Borrowed(b)
+    } else {
+        let mut masked = b.clone();
+        let frame = Region::new(0, 0, a.width(), a.height());
+        for region in options.ignore.iter().filter_map(|r| r.clamp_to(&frame)) {
+            for y in region.y..region.bottom() as i32 {
+                for x in region.x..region.right() as i32 {
+                    masked.put_pixel(x as u32, y as u32, *a.get_pixel(x as u32, y as u32));
+                }
+            }
+        }
+        Cow::Owned(masked)
+    };
+    let b = b.as_ref();
+
+    let mut heatmap = RgbaImage::new(a.width(), a.height());
+    let mut changed_pixels = 0;
+    let mut squared_error = 0u64;
+    for ((pa, pb), out) in a.pixels().zip(b.pixels()).zip(heatmap.pixels_mut()) {
+        let delta =
+            pa.0.iter()
+                .zip(pb.0)
+                .map(|(x, y)| x.abs_diff(y))
+                .max()
+                .unwrap_or(0);
+        squared_error += pa.0[..3]
+            .iter()
+            .zip(&pb.0[..3])
+            .map(|(&x, &y)| (x.abs_diff(y) as u64).pow(2))
+            .sum::<u64>();
+
+        *out = if delta > options.tolerance {
+            changed_pixels += 1;
+            Rgba([255, 255 - delta, 0, 255])
+        } else {
+            let dim = (luma(pa) / 3.0) as u8;
+            Rgba([dim, dim, dim, 255])
+        };
+    }
+
+    let total_pixels = a.width() as u64 * a.height() as u64;
+    let psnr = if squared_error == 0 {
+        f64::INFINITY
+    } else {
+        let mse = squared_error as f64 / (total_pixels * 3) as f64;
+        10.0 * (255.0f64.powi(2) / mse).log10()
+    };
+
+    Ok(DiffReport {
+        changed_pixels,
+        total_pixels,
+        ssim: ssim(a, b),
+        psnr,
+        heatmap,
+    })
+}
+
+fn luma(pixel: &Rgba<u8>) -> f64 {
+    let [r, g, b, _] = pixel.0;
+    0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+}
+
+/// Mean SSIM over non-overlapping windows; edge windows are clipped
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..height).step_by(WINDOW as usize) {
+        for wx in (0..width).step_by(WINDOW as usize) {
+            let mut values = Vec::with_capacity((WINDOW * WINDOW) as usize);
+            for y in wy..(wy + WINDOW).min(height) {
+                for x in wx..(wx + WINDOW).min(width) {
+                    values.push((luma(a.get_pixel(x, y)), luma(b.get_pixel(x, y))));
+                }
+            }
+
+            let n = values.len() as f64;
+            let mean_a = values.iter().map(|v| v.0).sum::<f64>() / n;
+            let mean_b = values.iter().map(|v| v.1).sum::<f64>() / n;
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (x, y) in &values {
+                var_a += (x - mean_a).powi(2);
+                var_b += (y - mean_b).powi(2);
+                covariance += (x - mean_a) * (y - mean_b);
+            }
+            let (var_a, var_b, covariance) = (var_a / n, var_b / n, covariance / n);
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a.powi(2) + mean_b.powi(2) + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        })
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let image = gradient(40, 30);
+        let report = compare(&image, &image, &DiffOptions::default()).unwrap();
+
+        assert!(report.is_identical());
+        assert_eq!(report.psnr, f64::INFINITY);
+        assert!((report.ssim - 1.0).abs() < 1e-9, "ssim {}", report.ssim);
+    }
+
+    #[test]
+    fn test_changed_block() {
+        let a = gradient(40, 30);
+        let mut b = a.clone();
+        for y in 0..10 {
+            for x in 0..12 {
+                b.put_pixel(x, y, Rgba([255, 0, 255, 255]));
+            }
+        }
+
+        let report = compare(&a, &b, &DiffOptions::default()).unwrap();
+
+        assert_eq!(report.changed_pixels, 120);
+        assert!((report.changed_percent() - 10.0).abs() < 1e-9);
+        assert!(report.ssim < 0.95, "ssim {}", report.ssim);
+        assert!(report.psnr.is_finite() && report.psnr > 0.0);
+        assert_eq!(report.heatmap.get_pixel(0, 0).0[0], 255, "Changes are red");
+        assert!(
+            report.heatmap.get_pixel(30, 20).0[0] < 128,
+            "Unchanged is dimmed"
+        );
+    }
+
+    #[test]
+    fn test_tolerance_ignores_small_noise() {
+        let a = gradient(16, 16);
+        let b = RgbaImage::from_fn(16, 16, |x, y| {
+            let mut p = *a.get_pixel(x, y);
+            p.0[2] += 2;
+            p
+        });
+
+        let strict = compare(&a, &b, &DiffOptions::default()).unwrap();
+        let lenient = compare(&a, &b, &DiffOptions::default().with_tolerance(2)).unwrap();
+
+        assert_eq!(strict.changed_pixels, 256);
+        assert!(lenient.is_identical());
+    }
+
+    #[test]
+    fn test_ignored_regions_match() {
+        let a = gradient(20, 20);
+        let mut b = a.clone();
+        b.put_pixel(5, 5, Rgba([0, 0, 0, 0]));
+        b.put_pixel(15, 15, Rgba([0, 0, 0, 0]));
+
+        let options = DiffOptions::default().ignore(Region::new(0, 0, 10, 10));
+        let report = compare(&a, &b, &options).unwrap();
+
+        assert_eq!(report.changed_pixels, 1, "Only the unmasked change counts");
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let err = compare(&gradient(4, 4), &gradient(4, 5), &DiffOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::Invalid { .. }), "{err}");
+    }
+}
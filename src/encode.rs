@@ -0,0 +1,462 @@
+use crate::buffer::{Frame, PixelFormat};
+use crate::quantize::{quantize, write_png, Palette};
+use crate::transform::Rgba16Image;
+use crate::{Error, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use screenshots::image::codecs::jpeg::JpegEncoder;
+use screenshots::image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use screenshots::image::codecs::qoi::QoiEncoder;
+use screenshots::image::codecs::tiff::TiffEncoder;
+use screenshots::image::codecs::webp::WebPEncoder;
+use screenshots::image::{ColorType, GenericImageView, ImageEncoder, Rgb, RgbaImage};
+use std::fmt;
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Image formats a capture can be encoded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Qoi,
+    Tiff,
+}
+
+impl OutputFormat {
+    /// Every supported format, in a stable order
+    pub const ALL: [OutputFormat; 5] = [Self::Png, Self::Jpeg, Self::WebP, Self::Qoi, Self::Tiff];
+
+    /// The canonical file extension, without the leading dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Qoi => "qoi",
+            Self::Tiff => "tiff",
+        }
+    }
+
+    /// The MIME type used when the encoded bytes leave the process
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Qoi => "image/qoi",
+            Self::Tiff => "image/tiff",
+        }
+    }
+
+    /// Guesses the format from a file extension (case-insensitive)
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "webp" => Some(Self::WebP),
+            "qoi" => Some(Self::Qoi),
+            "tif" | "tiff" => Some(Self::Tiff),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_extension(s).ok_or_else(|| Error::invalid("output format", s))
+    }
+}
+
+/// Encoder settings
+///
+/// Every knob has a fixed default so that encoding the same frame twice, on any
+/// platform, yields identical bytes. None of the encoders write timestamps or
+/// other environment-dependent chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub format: OutputFormat,
+    /// JPEG quality, 1-100 (ignored by the lossless formats)
+    pub jpeg_quality: u8,
+    /// Write PNGs palette-indexed (ignored by the other formats)
+    pub palette: Option<Palette>,
+}
+
+impl EncodeOptions {
+    /// Default settings for the given format
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            jpeg_quality: 90,
+            palette: None,
+        }
+    }
+
+    /// Overrides the JPEG quality
+    pub fn with_jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Writes PNGs with at most `palette.colors` colors
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self::new(OutputFormat::Png)
+    }
+}
+
+/// Encodes an RGBA frame into `writer`
+#[tracing::instrument(level = "debug", skip_all, fields(format = %options.format))]
+pub fn encode(image: &RgbaImage, options: &EncodeOptions, mut writer: impl Write) -> Result<()> {
+    let (width, height) = image.dimensions();
+    match options.format {
+        OutputFormat::Png if options.palette.is_some() => {
+            let palette = options.palette.unwrap_or_default();
+            write_png(&quantize(image, &palette), &mut writer)?;
+        }
+        OutputFormat::Png => {
+            PngEncoder::new_with_quality(
+                &mut writer,
+                CompressionType::Default,
+                FilterType::Adaptive,
+            )
+            .write_image(image.as_raw(), width, height, ColorType::Rgba8)?;
+        }
+        OutputFormat::Jpeg => jpeg(
+            &Frame::from_image(image, SystemTime::UNIX_EPOCH),
+            options,
+            writer,
+        )?,
+        OutputFormat::WebP => {
+            WebPEncoder::new_lossless(&mut writer).write_image(
+                image.as_raw(),
+                width,
+                height,
+                ColorType::Rgba8,
+            )?;
+        }
+        OutputFormat::Qoi => {
+            QoiEncoder::new(&mut writer).write_image(
+                image.as_raw(),
+                width,
+                height,
+                ColorType::Rgba8,
+            )?;
+        }
+        OutputFormat::Tiff => {
+            writer.write_all(&tiff(image.as_raw(), width, height, ColorType::Rgba8)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rows the PNG stream compresses before writing an `IDAT` chunk, at most
+const STREAM_CHUNK: usize = 1 << 20;
+
+/// Encodes `frame` straight into `writer`, a row at a time
+///
+/// Where [`encode`] holds the whole compressed PNG before writing it, and
+/// the whole frame again as RGB for JPEG, this filters and compresses PNG
+/// rows as it reads them and converts JPEG pixels as the encoder asks for
+/// them, so a stitched capture several 4K displays wide needs no second
+/// buffer its size. The PNGs decode to the same pixels as [`encode`]'s but
+/// split their data into smaller chunks. JPEGs are baseline: the encoder
+/// doesn't write progressive ones. Palette PNGs, WebP, QOI and TIFF need
+/// the whole image at once, so those frames are copied and handed to
+/// [`encode`].
+#[tracing::instrument(level = "debug", skip_all, fields(format = %options.format))]
+pub fn encode_frame(frame: &Frame, options: &EncodeOptions, writer: impl Write) -> Result<()> {
+    match options.format {
+        OutputFormat::Png if options.palette.is_none() => png_stream(frame, writer),
+        OutputFormat::Jpeg => jpeg(frame, options, writer),
+        _ => encode(&frame.to_image(), options, writer),
+    }
+}
+
+/// An RGBA PNG, compressed as the rows come in
+fn png_stream(frame: &Frame, writer: impl Write) -> Result<()> {
+    let io = |e: png::EncodingError| Error::Io(std::io::Error::other(e));
+    let (width, height) = frame.dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // The settings `encode` gives the `image` PNG encoder
+    encoder.set_compression(png::Compression::Default);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    let mut png = encoder.write_header().map_err(io)?;
+    let mut stream = png.stream_writer_with_size(STREAM_CHUNK).map_err(io)?;
+    let mut converted = Vec::new();
+    for row in frame.rows() {
+        let row = match frame.format() {
+            PixelFormat::Rgba8 => row,
+            format => {
+                converted.clear();
+                converted.extend(
+                    row.chunks_exact(4)
+                        .flat_map(|p| format.to_rgba([p[0], p[1], p[2], p[3]])),
+                );
+                &converted
+            }
+        };
+        stream.write_all(row)?;
+    }
+    stream.finish().map_err(io)?;
+    png.finish().map_err(io)
+}
+
+/// A frame without its alpha channel, as the JPEG encoder reads it
+struct RgbView<'a, 'b>(&'b Frame<'a>);
+
+impl GenericImageView for RgbView<'_, '_> {
+    type Pixel = Rgb<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, self.0.width(), self.0.height())
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Rgb<u8> {
+        let [r, g, b, _] = self.0.pixel(x, y).0;
+        Rgb([r, g, b])
+    }
+}
+
+fn jpeg(frame: &Frame, options: &EncodeOptions, mut writer: impl Write) -> Result<()> {
+    // JPEG has no alpha channel; drop it explicitly rather than relying
+    // on encoder-specific behaviour.
+    JpegEncoder::new_with_quality(&mut writer, options.jpeg_quality)
+        .encode_image(&RgbView(frame))?;
+    Ok(())
+}
+
+/// TIFF needs a seekable writer, so it is encoded in memory
+fn tiff(data: &[u8], width: u32, height: u32, color: ColorType) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    TiffEncoder::new(&mut buffer).write_image(data, width, height, color)?;
+    Ok(buffer.into_inner())
+}
+
+/// Encodes a 16-bit RGBA frame as PNG or TIFF, the formats that keep the
+/// extra precision
+pub fn encode16_to_vec(image: &Rgba16Image, format: OutputFormat) -> Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    // The encoders take native-endian samples
+    let data: Vec<u8> = image
+        .as_raw()
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect();
+    match format {
+        OutputFormat::Png => {
+            let mut buffer = Vec::new();
+            PngEncoder::new_with_quality(
+                &mut buffer,
+                CompressionType::Default,
+                FilterType::Adaptive,
+            )
+            .write_image(&data, width, height, ColorType::Rgba16)?;
+            Ok(buffer)
+        }
+        OutputFormat::Tiff => tiff(&data, width, height, ColorType::Rgba16),
+        _ => Err(Error::Unsupported(format!("16-bit {format} output"))),
+    }
+}
+
+/// Encodes an RGBA frame into a freshly allocated buffer
+pub fn encode_to_vec(image: &RgbaImage, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    encode(image, options, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Encoded image bytes as a `data:` URI, for embedding in HTML or JSON
+pub fn data_uri(bytes: &[u8], format: OutputFormat) -> String {
+    format!(
+        "data:{};base64,{}",
+        format.mime_type(),
+        BASE64_STANDARD.encode(bytes)
+    )
+}
+
+/// The eight bytes every PNG starts with
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// One chunk of an encoded PNG
+pub(crate) struct PngChunk<'a> {
+    /// The whole chunk: length, type, data and CRC
+    pub bytes: &'a [u8],
+}
+
+impl PngChunk<'_> {
+    pub fn kind(&self) -> &[u8] {
+        &self.bytes[4..8]
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[8..self.bytes.len() - 4]
+    }
+}
+
+/// Splits an encoded PNG into its chunks, for adding metadata after encoding
+pub(crate) fn png_chunks(png: &[u8]) -> Result<Vec<PngChunk<'_>>> {
+    let malformed = || Error::invalid("PNG", "malformed chunk layout");
+    let mut rest = png.strip_prefix(PNG_SIGNATURE).ok_or_else(malformed)?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let length = rest
+            .get(..4)
+            .map(|l| u32::from_be_bytes([l[0], l[1], l[2], l[3]]) as usize)
+            .ok_or_else(malformed)?;
+        let (bytes, tail) = rest.split_at_checked(length + 12).ok_or_else(malformed)?;
+        chunks.push(PngChunk { bytes });
+        rest = tail;
+    }
+    Ok(chunks)
+}
+
+/// Appends a chunk with its length and CRC
+pub(crate) fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend(crc.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn solid_frame(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([10, 20, 30, 255]))
+    }
+
+    #[test]
+    fn test_format_extension_round_trip() {
+        for format in OutputFormat::ALL {
+            assert_eq!(
+                OutputFormat::from_extension(format.extension()),
+                Some(format),
+                "Extension should map back to {format:?}"
+            );
+        }
+        assert_eq!("JPEG".parse::<OutputFormat>().unwrap(), OutputFormat::Jpeg);
+        assert!("bmp".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_encoding_is_deterministic() {
+        let frame = solid_frame(16, 16);
+        for format in OutputFormat::ALL {
+            let options = EncodeOptions::new(format);
+            let first = encode_to_vec(&frame, &options).unwrap();
+            let second = encode_to_vec(&frame, &options).unwrap();
+            assert_eq!(first, second, "{format} output should be byte-stable");
+        }
+    }
+
+    #[test]
+    fn test_palette_png_is_deterministic() {
+        let frame = solid_frame(16, 16);
+        let options = EncodeOptions::default().with_palette(Palette::new(8));
+        let first = encode_to_vec(&frame, &options).unwrap();
+
+        assert_eq!(first, encode_to_vec(&frame, &options).unwrap());
+        assert_ne!(
+            first,
+            encode_to_vec(&frame, &EncodeOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_16_bit_round_trips() {
+        let frame = Rgba16Image::from_fn(8, 4, |x, y| Rgba([x as u16 * 4097, y as u16, 1, 65535]));
+        for format in [OutputFormat::Png, OutputFormat::Tiff] {
+            let bytes = encode16_to_vec(&frame, format).unwrap();
+            let decoded = screenshots::image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.into_rgba16(), frame, "{format} keeps 16 bits");
+        }
+        assert!(encode16_to_vec(&frame, OutputFormat::Jpeg).is_err());
+    }
+
+    #[test]
+    fn test_streamed_frames_decode_the_same() {
+        let image = RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8 * 6, y as u8 * 8, 90, 200]));
+        let frame = Frame::from_image(&image, SystemTime::now());
+        let png = EncodeOptions::new(OutputFormat::Png);
+        let mut streamed = Vec::new();
+        encode_frame(&frame, &png, &mut streamed).unwrap();
+        let decoded = screenshots::image::load_from_memory(&streamed).unwrap();
+        assert_eq!(decoded.into_rgba8(), image);
+        let kinds: Vec<_> = png_chunks(&streamed)
+            .unwrap()
+            .iter()
+            .map(|c| c.kind().to_vec())
+            .collect();
+        assert_eq!(kinds.first().unwrap(), b"IHDR");
+        assert_eq!(kinds.iter().filter(|k| *k == b"IEND").count(), 1);
+        assert_eq!(kinds.last().unwrap(), b"IEND");
+
+        // Padded BGRA rows, as Windows captures them
+        let mut bgra = Vec::new();
+        for row in image.rows() {
+            bgra.extend(row.flat_map(|p| [p[2], p[1], p[0], p[3]]));
+            bgra.extend([0; 8]);
+        }
+        let padded = Frame::new(&bgra, 40, 30, 168, PixelFormat::Bgra8, frame.timestamp()).unwrap();
+        let mut from_bgra = Vec::new();
+        encode_frame(&padded, &png, &mut from_bgra).unwrap();
+        assert_eq!(from_bgra, streamed);
+
+        for format in [OutputFormat::Jpeg, OutputFormat::Qoi] {
+            let options = EncodeOptions::new(format);
+            let mut bytes = Vec::new();
+            encode_frame(&padded, &options, &mut bytes).unwrap();
+            assert_eq!(bytes, encode_to_vec(&image, &options).unwrap(), "{format}");
+        }
+    }
+
+    #[test]
+    fn test_data_uri() {
+        assert_eq!(
+            data_uri(b"\x89PNG", OutputFormat::Png),
+            "data:image/png;base64,iVBORw=="
+        );
+        let uri = data_uri(&[0xff, 0xd8], OutputFormat::Jpeg);
+        assert!(uri.starts_with("data:image/jpeg;base64,"), "{uri}");
+    }
+
+    #[test]
+    fn test_jpeg_quality_is_clamped() {
+        assert_eq!(
+            EncodeOptions::default().with_jpeg_quality(0).jpeg_quality,
+            1
+        );
+        assert_eq!(
+            EncodeOptions::default().with_jpeg_quality(250).jpeg_quality,
+            100
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::color::Color;
+use screenshots::image::ImageError;
+use std::time::Duration;
+
+/// Errors produced by the snap_scale library
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Encoding or decoding an image failed
+    #[error("image error: {0}")]
+    Image(#[from] ImageError),
+
+    /// An I/O operation failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A user-supplied value could not be parsed or is out of range
+    #[error("invalid {what}: {value}")]
+    Invalid { what: &'static str, value: String },
+
+    /// The config file could not be read or parsed
+    #[error("config error: {0}")]
+    Config(String),
+
+    /// A user hook command exited unsuccessfully
+    #[error("hook `{command}` failed: {status}")]
+    Hook { command: String, status: String },
+
+    /// A user script failed to compile or run
+    #[error("script error: {0}")]
+    Script(String),
+
+    /// Sending a capture to a remote destination failed
+    #[error("upload failed: {0}")]
+    Upload(String),
+
+    /// Text recognition failed
+    #[error("OCR failed: {0}")]
+    Ocr(String),
+
+    /// Reading or writing the capture catalog failed
+    #[error("catalog error: {0}")]
+    Catalog(String),
+
+    /// A capture differs from its stored baseline
+    #[error("`{name}` does not match its baseline: {detail}")]
+    Mismatch { name: String, detail: String },
+
+    /// A capture came back a single color throughout, e.g. all black
+    #[error(
+        "the capture of display {display} is blank, every pixel {color}: recording the screen \
+         may not be permitted, or the content is protected"
+    )]
+    Blank { display: String, color: Color },
+
+    /// Every attempt at a capture failed, each for the reason listed
+    #[error("gave up after {} attempts: {}", .failures.len(), list(.failures))]
+    Retries { failures: Vec<Error> },
+
+    /// A platform call didn't return in time
+    #[error("{what} timed out after {}s", .after.as_secs_f64())]
+    Timeout { what: String, after: Duration },
+
+    /// A platform call panicked on the thread it was run on; it's a bug
+    #[error("{what} panicked: {message}")]
+    Panicked { what: String, message: String },
+
+    /// The feature is not available on this platform or in this build
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+}
+
+impl Error {
+    /// Shorthand for building an [`Error::Invalid`]
+    pub(crate) fn invalid(what: &'static str, value: impl Into<String>) -> Self {
+        Self::Invalid {
+            what,
+            value: value.into(),
+        }
+    }
+}
+
+/// `failures` numbered and on one line
+fn list(failures: &[Error]) -> String {
+    failures
+        .iter()
+        .enumerate()
+        .map(|(i, e)| format!("{}. {e}", i + 1))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Library-wide result alias
+pub type Result<T> = std::result::Result<T, Error>;
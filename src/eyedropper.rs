@@ -0,0 +1,107 @@
+//! Reading the pixel under the cursor, over and over
+//!
+//! `snap_scale pick --live` follows the cursor and prints each new color.
+//! Finding the display under a point enumerates the displays, and mapping
+//! the point to physical pixels probes the display's scaling with a capture
+//! of its own, so an [`Eyedropper`] does both once and keeps them, with the
+//! backend, for every read after: a read is then a single 1×1 capture.
+
+use crate::backend::CaptureBackend;
+use crate::color::Color;
+use crate::geometry::{CoordinateMapper, Region};
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use std::sync::Arc;
+
+/// The color at a point of the desktop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Desktop-global logical position
+    pub x: i32,
+    pub y: i32,
+    /// Id of the display it's on
+    pub display: u32,
+    /// Where it is in that display's physical pixels
+    pub physical: (i32, i32),
+    pub color: Color,
+}
+
+/// Reads pixels from one backend, remembering its displays and their
+/// scaling between reads
+pub struct Eyedropper {
+    backend: Arc<dyn CaptureBackend>,
+    /// Each display with its mapper, probed the first time it's read from
+    displays: Vec<(DisplayDescriptor, Option<CoordinateMapper>)>,
+}
+
+impl Eyedropper {
+    /// Enumerates `backend`'s displays
+    pub fn new(backend: Arc<dyn CaptureBackend>) -> Result<Self> {
+        let displays = backend.enumerate()?;
+        Ok(Self {
+            backend,
+            displays: displays.into_iter().map(|d| (d, None)).collect(),
+        })
+    }
+
+    /// Forgets the displays and their scaling and enumerates them again,
+    /// e.g. after one was plugged in
+    pub fn refresh(&mut self) -> Result<()> {
+        self.backend.refresh();
+        *self = Self::new(Arc::clone(&self.backend))?;
+        Ok(())
+    }
+
+    /// The color at the desktop-global logical position `x`,`y`
+    pub fn sample(&mut self, x: i32, y: i32) -> Result<Sample> {
+        let backend = &*self.backend;
+        let (display, mapper) = self
+            .displays
+            .iter_mut()
+            .find(|(d, _)| Region::new(d.x, d.y, d.width, d.height).contains(x, y))
+            .ok_or_else(|| Error::invalid("point", format!("{x},{y} is on no display")))?;
+        let mapper = mapper.get_or_insert_with(|| CoordinateMapper::probe(backend, display));
+        let local = mapper
+            .global_to_local(&Region::new(x, y, 1, 1))
+            .ok_or_else(|| Error::invalid("point", format!("{x},{y} is on no display")))?;
+        let physical = mapper.to_physical(&local);
+        let image = backend.capture_area(display, Region::new(physical.x, physical.y, 1, 1))?;
+        let pixel = image
+            .get_pixel_checked(0, 0)
+            .ok_or_else(|| Error::Unsupported(format!("{x},{y} captured no pixels")))?;
+        Ok(Sample {
+            x,
+            y,
+            display: display.id,
+            physical: (physical.x, physical.y),
+            color: Color::from(*pixel),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+
+    #[test]
+    fn test_samples_follow_the_displays() {
+        let backend: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let mut dropper = Eyedropper::new(Arc::new(backend)).unwrap();
+        let sample = dropper.sample(32, 47).unwrap();
+        assert_eq!(sample.display, 1);
+        assert_eq!(sample.physical, (32, 47));
+        assert_eq!(sample.color.hex(), "#81ff00");
+        assert_eq!(dropper.sample(32, 47).unwrap(), sample, "reads repeat");
+
+        // The second display sits to the right of the first, at scale 2
+        let scaled = dropper.sample(64 + 10, 5).unwrap();
+        assert_eq!(scaled.display, 2);
+        assert_eq!(scaled.physical, (20, 10));
+
+        let err = dropper.sample(-1, -1).unwrap_err();
+        assert!(matches!(err, Error::Invalid { .. }), "{err}");
+        dropper.refresh().unwrap();
+        assert_eq!(dropper.sample(32, 47).unwrap(), sample);
+    }
+}
@@ -0,0 +1,122 @@
+//! Capturing windows as they get the focus
+//!
+//! `snap_scale focus` keeps a journal of what was worked on: it looks at the
+//! focused window every so often and captures it each time the focus moves.
+//! A [`FocusTracker`] decides when a look makes a capture. Switching through
+//! several windows in quick succession captures only the one the focus
+//! settles on, at most once per throttle interval, and returning to a window
+//! that hasn't changed since its last capture can skip it.
+
+use crate::hash::Deduplicator;
+use screenshots::image::RgbaImage;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Which focus changes to capture, throttled and deduplicated
+#[derive(Debug, Clone)]
+pub struct FocusTracker {
+    /// Least time between two captures
+    throttle: Duration,
+    /// Cloned for each window, when captures of one are deduplicated
+    dedupe: Option<Deduplicator>,
+    /// Window focused at the last look
+    focused: Option<u64>,
+    /// Whether the focus moved to `focused` and it wasn't captured yet
+    pending: bool,
+    last_capture: Option<Instant>,
+    seen: HashMap<u64, Deduplicator>,
+}
+
+impl FocusTracker {
+    pub fn new(throttle: Duration, dedupe: Option<Deduplicator>) -> Self {
+        Self {
+            throttle,
+            dedupe,
+            focused: None,
+            pending: false,
+            last_capture: None,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// The window to capture after a look at `now` found `focused` had the
+    /// focus: the focused one when the focus moved since the last capture
+    /// and the throttle has passed
+    ///
+    /// The window focused at the first look counts as just focused.
+    pub fn observe(&mut self, focused: Option<u64>, now: Instant) -> Option<u64> {
+        if focused != self.focused {
+            self.focused = focused;
+            self.pending = focused.is_some();
+        }
+        let throttled = self
+            .last_capture
+            .is_some_and(|last| now.duration_since(last) < self.throttle);
+        if !self.pending || throttled {
+            return None;
+        }
+        self.pending = false;
+        self.last_capture = Some(now);
+        self.focused
+    }
+
+    /// Whether `image` of `window` duplicates the window's last kept
+    /// capture; always false without deduplication
+    pub fn is_duplicate(&mut self, window: u64, image: &RgbaImage) -> bool {
+        let Some(dedupe) = &self.dedupe else {
+            return false;
+        };
+        self.seen
+            .entry(window)
+            .or_insert_with(|| dedupe.clone())
+            .is_duplicate(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::HashAlgorithm;
+    use screenshots::image::Rgba;
+
+    #[test]
+    fn test_captures_where_the_focus_settles() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut tracker = FocusTracker::new(Duration::from_millis(1000), None);
+        assert_eq!(tracker.observe(Some(1), at(0)), Some(1), "first look");
+        assert_eq!(tracker.observe(Some(1), at(100)), None, "unchanged");
+
+        // Switching through 2 and 3 within the throttle captures only 3
+        assert_eq!(tracker.observe(Some(2), at(200)), None);
+        assert_eq!(tracker.observe(Some(3), at(400)), None);
+        assert_eq!(tracker.observe(Some(3), at(1000)), Some(3));
+
+        // Focus away and back counts as a change, nothing focused doesn't
+        assert_eq!(tracker.observe(None, at(2500)), None);
+        assert_eq!(tracker.observe(Some(3), at(2600)), Some(3));
+        assert_eq!(tracker.observe(Some(1), at(2700)), None, "throttled");
+        assert_eq!(tracker.observe(None, at(3000)), None);
+        assert_eq!(tracker.observe(None, at(4000)), None, "unfocused");
+    }
+
+    #[test]
+    fn test_duplicates_are_per_window() {
+        let black = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 255]));
+        let gradient = RgbaImage::from_fn(16, 16, |x, _| {
+            let v = 255 - (x * 16) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let dedupe = Deduplicator::new(HashAlgorithm::Difference, 0);
+        let mut tracker = FocusTracker::new(Duration::ZERO, Some(dedupe));
+        assert!(!tracker.is_duplicate(1, &black));
+        assert!(!tracker.is_duplicate(2, &gradient));
+        assert!(tracker.is_duplicate(1, &black), "unchanged since");
+        assert!(tracker.is_duplicate(2, &gradient));
+        assert!(!tracker.is_duplicate(1, &gradient));
+
+        let mut tracker = FocusTracker::new(Duration::ZERO, None);
+        assert!(!tracker.is_duplicate(1, &black));
+        assert!(!tracker.is_duplicate(1, &black));
+    }
+}
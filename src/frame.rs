@@ -0,0 +1,296 @@
+//! Device-frame mockups
+//!
+//! Composites a capture into a browser window, laptop or phone drawn from
+//! built-in templates, for marketing and docs imagery. Frame details scale
+//! with the capture, so HiDPI captures get proportionally thicker bezels.
+//! Everything around the device is transparent; combine with
+//! [`crate::beautify`] for a background.
+
+use crate::annotate::{draw_text, measure_text, Font, TextStyle};
+use crate::color::Color;
+use crate::corners::{fill_rounded_rect, RoundedCorners};
+use crate::transform::Transform;
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use std::str::FromStr;
+
+/// A built-in frame template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    /// Window chrome with traffic lights and an address bar
+    Browser,
+    /// Screen bezel on a laptop base
+    Laptop,
+    /// Phone bezel with rounded screen corners and a notch
+    Phone,
+}
+
+impl FromStr for Device {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "browser" => Ok(Self::Browser),
+            "laptop" => Ok(Self::Laptop),
+            "phone" => Ok(Self::Phone),
+            _ => Err(Error::invalid("device frame (browser, laptop, phone)", s)),
+        }
+    }
+}
+
+/// Places captures into a device frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceFrame {
+    pub device: Device,
+    /// Text shown in the browser address bar
+    pub url: Option<String>,
+}
+
+impl DeviceFrame {
+    pub fn new(device: Device) -> Self {
+        Self { device, url: None }
+    }
+
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn render(&self, image: &RgbaImage) -> RgbaImage {
+        match self.device {
+            Device::Browser => self.browser(image),
+            Device::Laptop => laptop(image),
+            Device::Phone => phone(image),
+        }
+    }
+
+    fn browser(&self, image: &RgbaImage) -> RgbaImage {
+        let (width, height) = image.dimensions();
+        let s = unit(width, 1280);
+        let bar = (40.0 * s).round() as u32;
+        let chrome = Color::new(0xe5, 0xe7, 0xeb, 255);
+        let mut canvas = RgbaImage::from_pixel(width, height + bar, chrome.into());
+
+        let lights = [
+            Color::new(0xff, 0x5f, 0x57, 255),
+            Color::new(0xfe, 0xbc, 0x2e, 255),
+            Color::new(0x28, 0xc8, 0x40, 255),
+        ];
+        let (radius, middle) = (6.0 * s, bar as f32 / 2.0);
+        for (i, color) in lights.into_iter().enumerate() {
+            let cx = (20.0 + 20.0 * i as f32) * s;
+            fill_circle(&mut canvas, (cx, middle), radius, color);
+        }
+
+        let (left, right) = (90.0 * s, width as f32 - 90.0 * s);
+        if right > left {
+            let field_height = 24.0 * s;
+            let top = middle - field_height / 2.0;
+            fill_rounded_rect(
+                &mut canvas,
+                (left, top),
+                (right - left, field_height),
+                field_height / 2.0,
+                Color::WHITE,
+            );
+            if let Some(url) = &self.url {
+                let style = TextStyle {
+                    font: Font::Builtin,
+                    size: 8.0 * (s * 1.5).round().max(1.0),
+                    color: Color::new(0x4b, 0x55, 0x63, 255),
+                    outline: None,
+                    background: None,
+                };
+                let (_, text_height) = measure_text(url, &style);
+                let y = (middle - text_height as f32 / 2.0).round() as i32;
+                draw_text(&mut canvas, url, (left + 12.0 * s) as i32, y, &style);
+            }
+        }
+
+        imageops::overlay(&mut canvas, image, 0, bar as i64);
+        RoundedCorners::new((10.0 * s) as u32).apply_to(&mut canvas);
+        canvas
+    }
+}
+
+impl Transform for DeviceFrame {
+    fn name(&self) -> &str {
+        "device-frame"
+    }
+
+    fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+        Ok(self.render(&image))
+    }
+}
+
+/// Size multiplier for frame details, 1.0 for a `reference`-wide capture
+fn unit(width: u32, reference: u32) -> f32 {
+    (width as f32 / reference as f32).max(0.5)
+}
+
+fn fill_circle(image: &mut RgbaImage, (cx, cy): (f32, f32), radius: f32, color: Color) {
+    fill_rounded_rect(
+        image,
+        (cx - radius, cy - radius),
+        (radius * 2.0, radius * 2.0),
+        radius,
+        color,
+    );
+}
+
+fn laptop(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let s = unit(width, 1440);
+    let (side, top, bottom) = (
+        (16.0 * s).round() as u32,
+        (24.0 * s).round() as u32,
+        (24.0 * s).round() as u32,
+    );
+    let overhang = (60.0 * s).round() as u32;
+    let base = (20.0 * s).round() as u32;
+    let (lid_width, lid_height) = (width + 2 * side, height + top + bottom);
+    let mut canvas = RgbaImage::new(lid_width + 2 * overhang, lid_height + base);
+
+    let lid_x = overhang as f32;
+    fill_rounded_rect(
+        &mut canvas,
+        (lid_x, 0.0),
+        (lid_width as f32, lid_height as f32 + 8.0 * s),
+        16.0 * s,
+        Color::new(0x1f, 0x29, 0x37, 255),
+    );
+    fill_circle(
+        &mut canvas,
+        (lid_x + lid_width as f32 / 2.0, top as f32 / 2.0),
+        3.0 * s,
+        Color::new(0x37, 0x41, 0x51, 255),
+    );
+    imageops::overlay(&mut canvas, image, (overhang + side) as i64, top as i64);
+
+    let canvas_width = canvas.width() as f32;
+    fill_rounded_rect(
+        &mut canvas,
+        (0.0, lid_height as f32),
+        (canvas_width, base as f32),
+        base as f32 / 2.0,
+        Color::new(0xd1, 0xd5, 0xdb, 255),
+    );
+    let notch = 120.0 * s;
+    fill_rounded_rect(
+        &mut canvas,
+        ((canvas_width - notch) / 2.0, lid_height as f32),
+        (notch, 6.0 * s),
+        3.0 * s,
+        Color::new(0x9c, 0xa3, 0xaf, 255),
+    );
+    canvas
+}
+
+fn phone(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let s = unit(width, 390);
+    let bezel = (14.0 * s).round() as u32;
+    let screen_radius = 40.0 * s;
+    let mut canvas = RgbaImage::new(width + 2 * bezel, height + 2 * bezel);
+
+    let (canvas_width, canvas_height) = (canvas.width() as f32, canvas.height() as f32);
+    fill_rounded_rect(
+        &mut canvas,
+        (0.0, 0.0),
+        (canvas_width, canvas_height),
+        screen_radius + bezel as f32,
+        Color::new(0x11, 0x18, 0x27, 255),
+    );
+    let mut screen = image.clone();
+    RoundedCorners::new(screen_radius as u32).apply_to(&mut screen);
+    imageops::overlay(&mut canvas, &screen, bezel as i64, bezel as i64);
+
+    let (notch_width, notch_height) = (width as f32 * 0.3, 28.0 * s);
+    fill_rounded_rect(
+        &mut canvas,
+        ((canvas_width - notch_width) / 2.0, bezel as f32 + 8.0 * s),
+        (notch_width, notch_height),
+        notch_height / 2.0,
+        Color::BLACK,
+    );
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    const CAPTURE: Rgba<u8> = Rgba([10, 200, 30, 255]);
+
+    fn capture(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, CAPTURE)
+    }
+
+    #[test]
+    fn test_browser_adds_a_title_bar() {
+        let out = DeviceFrame::new(Device::Browser)
+            .with_url("example.com")
+            .render(&capture(1280, 720));
+
+        assert_eq!(out.dimensions(), (1280, 760));
+        assert_eq!(*out.get_pixel(640, 400), CAPTURE);
+        assert_eq!(out.get_pixel(0, 0).0[3], 0, "Rounded window corner");
+        assert_eq!(
+            out.get_pixel(20, 20).0,
+            [0xff, 0x5f, 0x57, 255],
+            "Close button"
+        );
+        assert_eq!(
+            out.get_pixel(1000, 20).0,
+            [255, 255, 255, 255],
+            "Address bar"
+        );
+    }
+
+    #[test]
+    fn test_laptop_surrounds_the_screen() {
+        let out = DeviceFrame::new(Device::Laptop).render(&capture(1440, 900));
+
+        assert_eq!(out.dimensions(), (1440 + 32 + 120, 900 + 48 + 20));
+        assert_eq!(*out.get_pixel(60 + 16, 24), CAPTURE, "Screen origin");
+        assert_eq!(out.get_pixel(0, 0).0[3], 0, "Transparent around the lid");
+        assert_eq!(out.get_pixel(70, 500).0, [0x1f, 0x29, 0x37, 255], "Bezel");
+        assert_eq!(
+            out.get_pixel(5, 958).0[3],
+            255,
+            "Base is wider than the lid"
+        );
+    }
+
+    #[test]
+    fn test_phone_rounds_the_screen() {
+        let out = DeviceFrame::new(Device::Phone).render(&capture(390, 844));
+
+        assert_eq!(out.dimensions(), (418, 872));
+        assert_eq!(*out.get_pixel(209, 436), CAPTURE);
+        assert_eq!(out.get_pixel(0, 0).0[3], 0);
+        assert_eq!(
+            out.get_pixel(16, 16).0,
+            [0x11, 0x18, 0x27, 255],
+            "Screen corner shows the bezel"
+        );
+        assert_eq!(out.get_pixel(209, 30).0, [0, 0, 0, 255], "Notch");
+    }
+
+    #[test]
+    fn test_small_captures_do_not_panic() {
+        for device in [Device::Browser, Device::Laptop, Device::Phone] {
+            let out = DeviceFrame::new(device)
+                .with_url("x")
+                .render(&capture(3, 2));
+            assert!(out.width() >= 3 && out.height() >= 2, "{device:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("Phone".parse::<Device>().unwrap(), Device::Phone);
+        assert!("watch".parse::<Device>().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Selecting on a frozen desktop
+//!
+//! Menus, tooltips and hover states go away as soon as focus moves to make a
+//! selection. [`FrozenDesktop`] captures every display once up front and then
+//! stands in for the backend it captured, so whatever is selected afterwards
+//! is cut from that snapshot instead of from a screen that has moved on.
+
+use crate::backend::CaptureBackend;
+use crate::buffer::{Frame, FrameBuffer};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use std::time::SystemTime;
+
+/// Every display as it was at one moment
+pub struct FrozenDesktop {
+    frames: Vec<(DisplayDescriptor, RgbaImage)>,
+    taken: SystemTime,
+}
+
+impl FrozenDesktop {
+    /// Captures every display of `backend`
+    pub fn capture(backend: &(impl CaptureBackend + ?Sized)) -> Result<Self> {
+        let taken = SystemTime::now();
+        let frames = backend
+            .enumerate()?
+            .into_iter()
+            .map(|display| {
+                let frame = backend.capture_display(&display)?;
+                Ok((display, frame))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { frames, taken })
+    }
+
+    fn frame(&self, display: &DisplayDescriptor) -> Result<&(DisplayDescriptor, RgbaImage)> {
+        self.frames
+            .iter()
+            .find(|(frozen, _)| frozen.id == display.id)
+            .ok_or_else(|| {
+                Error::invalid("display", format!("display {} wasn't frozen", display.id))
+            })
+    }
+}
+
+impl CaptureBackend for FrozenDesktop {
+    fn name(&self) -> &'static str {
+        "frozen"
+    }
+
+    fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+        Ok(self
+            .frames
+            .iter()
+            .map(|(display, _)| display.clone())
+            .collect())
+    }
+
+    fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+        Ok(self.frame(display)?.1.clone())
+    }
+
+    /// Lends out the snapshot itself, leaving `buffer` alone
+    fn capture_frame<'a>(
+        &'a self,
+        display: &DisplayDescriptor,
+        _buffer: &'a mut FrameBuffer,
+    ) -> Result<Frame<'a>> {
+        Ok(Frame::from_image(&self.frame(display)?.1, self.taken))
+    }
+
+    /// Crops the snapshot, taking areas the way the frozen backend did:
+    /// logical, scaled by how much larger its frames came back
+    fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+        let (display, frame) = self.frame(display)?;
+        let area = area
+            .clamp_to(&Region::new(0, 0, display.width, display.height))
+            .ok_or_else(|| Error::invalid("area", format!("{area:?} is off the display")))?;
+        let ratio = (
+            frame.width() as f64 / display.width.max(1) as f64,
+            frame.height() as f64 / display.height.max(1) as f64,
+        );
+        let scale = |logical: i64, ratio: f64| (logical as f64 * ratio).round() as u32;
+        let (left, top) = (scale(area.x as i64, ratio.0), scale(area.y as i64, ratio.1));
+        let right = scale(area.right(), ratio.0).min(frame.width());
+        let bottom = scale(area.bottom(), ratio.1).min(frame.height());
+        Ok(imageops::crop_imm(
+            frame,
+            left,
+            top,
+            right.saturating_sub(left).max(1),
+            bottom.saturating_sub(top).max(1),
+        )
+        .to_image())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::{MockBackend, Pattern};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A mock whose screen changes once frozen
+    struct Changing {
+        mock: MockBackend,
+        changed: AtomicBool,
+    }
+
+    impl CaptureBackend for Changing {
+        fn name(&self) -> &'static str {
+            "changing"
+        }
+
+        fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+            self.mock.enumerate()
+        }
+
+        fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+            let mut frame = self.mock.capture_display(display)?;
+            if self.changed.load(Ordering::Relaxed) {
+                frame.pixels_mut().for_each(|p| p.0 = [9, 9, 9, 255]);
+            }
+            Ok(frame)
+        }
+
+        fn capture_area(&self, display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+            self.mock.capture_area(display, area)
+        }
+    }
+
+    #[test]
+    fn test_frozen_frames_stay_put() {
+        let live = Changing {
+            mock: "64x48,32x24@2".parse().unwrap(),
+            changed: AtomicBool::new(false),
+        };
+        let frozen = FrozenDesktop::capture(&live).unwrap();
+        live.changed.store(true, Ordering::Relaxed);
+
+        let displays = frozen.enumerate().unwrap();
+        assert_eq!(displays, live.enumerate().unwrap());
+        for display in &displays {
+            let frame = frozen.capture_display(display).unwrap();
+            assert_eq!(frame, live.mock.capture_display(display).unwrap());
+        }
+
+        let mut buffer = FrameBuffer::new();
+        let frame = frozen.capture_frame(&displays[1], &mut buffer).unwrap();
+        assert_eq!(frame.timestamp(), frozen.taken);
+        assert_eq!(frame.to_image(), frozen.frames[1].1);
+        assert_eq!(buffer.capacity(), 0, "Borrowed, not copied");
+    }
+
+    #[test]
+    fn test_areas_match_the_live_backend() {
+        let live: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let live = live.with_pattern(Pattern::Checkerboard(3));
+        let frozen = FrozenDesktop::capture(&live).unwrap();
+        for display in live.enumerate().unwrap() {
+            for area in [Region::new(3, 2, 10, 7), Region::new(20, 15, 100, 100)] {
+                assert_eq!(
+                    frozen.capture_area(&display, area).unwrap(),
+                    live.capture_area(&display, area).unwrap(),
+                    "{area:?} of display {}",
+                    display.id
+                );
+            }
+        }
+        let gone = DisplayDescriptor {
+            id: 7,
+            ..live.enumerate().unwrap()[0].clone()
+        };
+        assert!(frozen.capture_display(&gone).is_err());
+    }
+}
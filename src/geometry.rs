@@ -0,0 +1,765 @@
+use crate::backend::CaptureBackend;
+use crate::metadata::DisplayDescriptor;
+use crate::scaling::ScalingConfig;
+use crate::{Error, Result};
+use screenshots::Screen;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// An axis-aligned rectangle in either logical or physical coordinates
+///
+/// Edges are computed in `i64` so regions near the `i32` limits never overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Exclusive right edge
+    pub fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    /// Exclusive bottom edge
+    pub fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Whether the point lies inside the region
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x as i64, y as i64);
+        x >= self.x as i64 && x < self.right() && y >= self.y as i64 && y < self.bottom()
+    }
+
+    /// Whether `other` lies entirely inside this region
+    pub fn contains_region(&self, other: &Region) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// The overlapping part of two regions, if any
+    pub fn intersect(&self, other: &Region) -> Option<Region> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        (right > left as i64 && bottom > top as i64).then(|| {
+            Region::new(
+                left,
+                top,
+                (right - left as i64) as u32,
+                (bottom - top as i64) as u32,
+            )
+        })
+    }
+
+    /// The smallest region containing both regions
+    pub fn union(&self, other: &Region) -> Region {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+
+        Region::new(
+            left,
+            top,
+            saturate_u32(right - left as i64),
+            saturate_u32(bottom - top as i64),
+        )
+    }
+
+    /// Clamps the region to `bounds`, returning `None` when nothing is left
+    pub fn clamp_to(&self, bounds: &Region) -> Option<Region> {
+        self.intersect(bounds)
+    }
+
+    /// A `width`x`height` region centered on `x`,`y`
+    pub fn around(x: i32, y: i32, width: u32, height: u32) -> Region {
+        let origin = |center: i32, side: u32| saturate_i32(center as i64 - side as i64 / 2);
+        Region::new(origin(x, width), origin(y, height), width, height)
+    }
+
+    /// The region with opposite corners at the two points, both inside it
+    ///
+    /// Points at `i32::MIN` and `i32::MAX` are one pixel further apart than
+    /// a side can be, so the far one is left just outside.
+    pub fn spanning(a: (i32, i32), b: (i32, i32)) -> Region {
+        let side = |p: i32, q: i32| saturate_u32((p as i64 - q as i64).abs() + 1);
+        Region::new(a.0.min(b.0), a.1.min(b.1), side(a.0, b.0), side(a.1, b.1))
+    }
+
+    /// Moves the region inside `bounds` where it fits, keeping its size, and
+    /// clamps it to them where it doesn't
+    pub fn shift_into(&self, bounds: &Region) -> Option<Region> {
+        let shift = |start: i32, side: u32, min: i32, max: i64| {
+            let end = (start as i64 + side as i64).min(max);
+            (end - side as i64).max(min as i64) as i32
+        };
+        let x = shift(self.x, self.width, bounds.x, bounds.right());
+        let y = shift(self.y, self.height, bounds.y, bounds.bottom());
+        Region::new(x, y, self.width, self.height).clamp_to(bounds)
+    }
+
+    /// Moves the region by the given offset, saturating at the `i32` limits
+    pub fn translate(&self, dx: i32, dy: i32) -> Region {
+        Region::new(
+            self.x.saturating_add(dx),
+            self.y.saturating_add(dy),
+            self.width,
+            self.height,
+        )
+    }
+
+    /// Resizes one side so the region has the given aspect ratio, keeping
+    /// `anchor` in place
+    ///
+    /// The region shrinks to fit inside itself, or with `expand` grows to
+    /// cover itself.
+    pub fn with_aspect(&self, ratio: AspectRatio, anchor: Anchor, expand: bool) -> Region {
+        let (width, height) = (self.width as u64, self.height as u64);
+        let (rw, rh) = (ratio.width as u64, ratio.height as u64);
+        let scaled =
+            |side: u64, num: u64, den: u64| saturate_u32(((side * num + den / 2) / den) as i64);
+        // Too wide when width / height > rw / rh
+        let too_wide = width * rh > height * rw;
+        let (new_width, new_height) = match (too_wide, expand) {
+            (true, false) => (scaled(height, rw, rh), self.height),
+            (true, true) => (self.width, scaled(width, rh, rw)),
+            (false, false) => (self.width, scaled(width, rh, rw)),
+            (false, true) => (scaled(height, rw, rh), self.height),
+        };
+
+        let slack = |old: u32, new: u32| old as i64 - new as i64;
+        let (dx, dy) = (slack(self.width, new_width), slack(self.height, new_height));
+        let (dx, dy) = match anchor {
+            Anchor::TopLeft => (0, 0),
+            Anchor::TopRight => (dx, 0),
+            Anchor::BottomLeft => (0, dy),
+            Anchor::BottomRight => (dx, dy),
+            Anchor::Center => (dx / 2, dy / 2),
+        };
+        let shift = |origin: i32, by: i64| {
+            (origin as i64 + by).clamp(i32::MIN as i64, i32::MAX as i64) as i32
+        };
+        Region::new(shift(self.x, dx), shift(self.y, dy), new_width, new_height)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    /// Parses `x,y,w,h`
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let invalid = || Error::invalid("region (expected x,y,w,h)", s);
+        let [x, y, w, h] = parts.as_slice() else {
+            return Err(invalid());
+        };
+
+        Ok(Region::new(
+            x.parse().map_err(|_| invalid())?,
+            y.parse().map_err(|_| invalid())?,
+            w.parse().map_err(|_| invalid())?,
+            h.parse().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+/// A width-to-height ratio such as 16:9
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// Returns `None` when either side is zero
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        (width > 0 && height > 0).then_some(Self { width, height })
+    }
+}
+
+impl fmt::Display for AspectRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = Error;
+
+    /// Parses `w:h`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::invalid("aspect ratio (expected w:h)", s);
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let side = |v: &str| v.trim().parse::<u32>().map_err(|_| invalid());
+        Self::new(side(width)?, side(height)?).ok_or_else(invalid)
+    }
+}
+
+/// A width and height such as 800x600
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extent {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for Extent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for Extent {
+    type Err = Error;
+
+    /// Parses `wxh`, both sides above zero
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::invalid("size (expected wxh)", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let side = |v: &str| match v.trim().parse::<u32>() {
+            Ok(0) | Err(_) => Err(invalid()),
+            Ok(side) => Ok(side),
+        };
+        Ok(Self {
+            width: side(width)?,
+            height: side(height)?,
+        })
+    }
+}
+
+/// The point of a region that stays fixed when it is resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    #[default]
+    Center,
+}
+
+impl FromStr for Anchor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "top-left" => Ok(Self::TopLeft),
+            "top-right" => Ok(Self::TopRight),
+            "bottom-left" => Ok(Self::BottomLeft),
+            "bottom-right" => Ok(Self::BottomRight),
+            "center" => Ok(Self::Center),
+            _ => Err(Error::invalid(
+                "anchor (top-left, top-right, bottom-left, bottom-right, center)",
+                s,
+            )),
+        }
+    }
+}
+
+/// Display rotation in clockwise quarter turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// Snaps an arbitrary angle in degrees to the nearest quarter turn
+    pub fn from_degrees(degrees: f32) -> Self {
+        if !degrees.is_finite() {
+            return Self::Deg0;
+        }
+        match ((degrees / 90.0).round() as i64).rem_euclid(4) {
+            1 => Self::Deg90,
+            2 => Self::Deg180,
+            3 => Self::Deg270,
+            _ => Self::Deg0,
+        }
+    }
+
+    pub fn degrees(self) -> u32 {
+        match self {
+            Self::Deg0 => 0,
+            Self::Deg90 => 90,
+            Self::Deg180 => 180,
+            Self::Deg270 => 270,
+        }
+    }
+
+    /// The rotation that undoes this one
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Deg90 => Self::Deg270,
+            Self::Deg270 => Self::Deg90,
+            other => other,
+        }
+    }
+
+    /// Whether the rotation swaps width and height
+    pub fn is_transposed(self) -> bool {
+        matches!(self, Self::Deg90 | Self::Deg270)
+    }
+
+    /// Size of a `width`x`height` frame after rotation
+    pub fn rotate_size(self, width: u32, height: u32) -> (u32, u32) {
+        if self.is_transposed() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Maps a region inside an unrotated `width`x`height` frame (origin at 0,0)
+    /// to the same pixels in the rotated frame
+    pub fn rotate_region(self, region: &Region, width: u32, height: u32) -> Region {
+        let (w, h) = (width as i64, height as i64);
+        let (x, y) = (region.x as i64, region.y as i64);
+        let (rw, rh) = (region.width as i64, region.height as i64);
+
+        let (nx, ny, nw, nh) = match self {
+            Self::Deg0 => (x, y, rw, rh),
+            Self::Deg90 => (h - y - rh, x, rh, rw),
+            Self::Deg180 => (w - x - rw, h - y - rh, rw, rh),
+            Self::Deg270 => (y, w - x - rw, rh, rw),
+        };
+        Region::new(
+            saturate_i32(nx),
+            saturate_i32(ny),
+            saturate_u32(nw),
+            saturate_u32(nh),
+        )
+    }
+}
+
+/// Maps regions between a display's logical coordinate space and the physical
+/// pixels of its captured frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateMapper {
+    /// Display bounds in logical, desktop-global coordinates
+    pub bounds: Region,
+    pub scaling: ScalingConfig,
+    pub rotation: Rotation,
+}
+
+impl CoordinateMapper {
+    pub fn new(bounds: Region, scaling: ScalingConfig, rotation: Rotation) -> Self {
+        Self {
+            bounds,
+            scaling,
+            rotation,
+        }
+    }
+
+    /// Mapper for a live display, probing its scaling with
+    /// [`ScalingConfig::detect`]
+    pub fn detect(screen: &Screen) -> Self {
+        let info = &screen.display_info;
+        Self::new(
+            Region::new(info.x, info.y, info.width, info.height),
+            ScalingConfig::detect(screen),
+            Rotation::from_degrees(info.rotation),
+        )
+    }
+
+    /// Mapper for a display of `backend`, probing its scaling with
+    /// [`ScalingConfig::probe`]
+    pub fn probe(backend: &(impl CaptureBackend + ?Sized), display: &DisplayDescriptor) -> Self {
+        Self::new(
+            Region::new(display.x, display.y, display.width, display.height),
+            ScalingConfig::probe(backend, display),
+            Rotation::from_degrees(display.rotation),
+        )
+    }
+
+    /// Size of the captured frame in physical pixels, before rotation
+    pub fn physical_size(&self) -> (u32, u32) {
+        (
+            self.scaling.scale_dimension(self.bounds.width),
+            self.scaling.scale_dimension(self.bounds.height),
+        )
+    }
+
+    /// Converts a display-local logical region into a physical region of the
+    /// unrotated frame
+    pub fn to_physical(&self, logical: &Region) -> Region {
+        Region::new(
+            self.scaling.scale_coordinate(logical.x),
+            self.scaling.scale_coordinate(logical.y),
+            self.scaling.scale_dimension(logical.width),
+            self.scaling.scale_dimension(logical.height),
+        )
+    }
+
+    /// Converts a physical region of the unrotated frame back to logical units
+    pub fn to_logical(&self, physical: &Region) -> Region {
+        Region::new(
+            self.scaling.unscale_coordinate(physical.x),
+            self.scaling.unscale_coordinate(physical.y),
+            self.scaling.unscale_dimension(physical.width),
+            self.scaling.unscale_dimension(physical.height),
+        )
+    }
+
+    /// Converts a desktop-global logical region into display-local logical
+    /// coordinates, clamped to the display
+    pub fn global_to_local(&self, global: &Region) -> Option<Region> {
+        // Wider than `translate` takes: an origin of `i32::MIN` has no negation
+        let local = |global: i32, origin: i32| saturate_i32(global as i64 - origin as i64);
+        global.clamp_to(&self.bounds).map(|r| {
+            Region::new(
+                local(r.x, self.bounds.x),
+                local(r.y, self.bounds.y),
+                r.width,
+                r.height,
+            )
+        })
+    }
+
+    /// Converts a display-local logical region into the physical region of
+    /// the frame as stored after rotation, clamped to the frame
+    pub fn to_frame(&self, logical: &Region) -> Option<Region> {
+        let (width, height) = self.physical_size();
+        let frame = Region::new(0, 0, width, height);
+        let physical = self.to_physical(logical).clamp_to(&frame)?;
+        Some(self.rotation.rotate_region(&physical, width, height))
+    }
+}
+
+pub(crate) fn saturate_i32(value: i64) -> i32 {
+    value.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+pub(crate) fn saturate_u32(value: i64) -> u32 {
+    value.clamp(0, u32::MAX as i64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_parse_and_display() {
+        let region: Region = "10, 20,300,400".parse().unwrap();
+        assert_eq!(region, Region::new(10, 20, 300, 400));
+        assert_eq!(region.to_string(), "10,20,300,400");
+        assert!("1,2,3".parse::<Region>().is_err(), "Needs four parts");
+        assert!("1,2,-3,4".parse::<Region>().is_err(), "Width is unsigned");
+    }
+
+    #[test]
+    fn test_region_intersect_and_union() {
+        let a = Region::new(0, 0, 100, 100);
+        let b = Region::new(50, 50, 100, 100);
+
+        assert_eq!(a.intersect(&b), Some(Region::new(50, 50, 50, 50)));
+        assert_eq!(a.union(&b), Region::new(0, 0, 150, 150));
+        assert_eq!(a.intersect(&Region::new(100, 0, 10, 10)), None);
+    }
+
+    #[test]
+    fn test_region_with_aspect() {
+        let region = Region::new(100, 100, 400, 400);
+        let wide = "16:9".parse::<AspectRatio>().unwrap();
+
+        assert_eq!(
+            region.with_aspect(wide, Anchor::Center, false),
+            Region::new(100, 187, 400, 225),
+            "Shrinks the height, centered"
+        );
+        assert_eq!(
+            region.with_aspect(wide, Anchor::BottomRight, false),
+            Region::new(100, 275, 400, 225)
+        );
+        assert_eq!(
+            region.with_aspect(wide, Anchor::TopLeft, true),
+            Region::new(100, 100, 711, 400),
+            "Expands the width"
+        );
+        assert_eq!(
+            Region::new(0, 0, 1600, 900).with_aspect(wide, Anchor::TopRight, false),
+            Region::new(0, 0, 1600, 900),
+            "Already 16:9"
+        );
+        assert_eq!(
+            Region::new(0, 0, 300, 100).with_aspect(
+                AspectRatio::new(1, 1).unwrap(),
+                Anchor::TopRight,
+                false
+            ),
+            Region::new(200, 0, 100, 100)
+        );
+    }
+
+    #[test]
+    fn test_region_around_and_shift_into() {
+        let display = Region::new(0, 0, 1920, 1080);
+        let centered = Region::around(960, 540, 400, 300);
+        assert_eq!(centered, Region::new(760, 390, 400, 300));
+        assert_eq!(centered.shift_into(&display), Some(centered));
+        assert_eq!(
+            Region::around(10, 1075, 400, 300).shift_into(&display),
+            Some(Region::new(0, 780, 400, 300)),
+            "Moved in from the corner, same size"
+        );
+        assert_eq!(
+            Region::around(960, 540, 4000, 300).shift_into(&display),
+            Some(Region::new(0, 390, 1920, 300)),
+            "Wider than the display"
+        );
+        let offset = Region::new(-1280, 200, 1280, 1024);
+        assert_eq!(
+            Region::around(-1275, 210, 100, 100).shift_into(&offset),
+            Some(Region::new(-1280, 200, 100, 100))
+        );
+    }
+
+    #[test]
+    fn test_region_spanning() {
+        assert_eq!(
+            Region::spanning((10, 20), (109, 69)),
+            Region::new(10, 20, 100, 50)
+        );
+        assert_eq!(
+            Region::spanning((109, 20), (10, 69)),
+            Region::spanning((10, 69), (109, 20))
+        );
+        assert_eq!(
+            Region::spanning((-5, -5), (-5, -5)),
+            Region::new(-5, -5, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_extent_parse() {
+        let extent: Extent = "800x600".parse().unwrap();
+        assert_eq!((extent.width, extent.height), (800, 600));
+        assert_eq!(extent.to_string(), "800x600");
+        for bad in ["800", "0x600", "800x", "x600", "-1x2"] {
+            assert!(bad.parse::<Extent>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_aspect_ratio_parse() {
+        assert_eq!(
+            "4:3".parse::<AspectRatio>().unwrap(),
+            AspectRatio::new(4, 3).unwrap()
+        );
+        for bad in ["16x9", "0:1", "1:", "a:b"] {
+            assert!(bad.parse::<AspectRatio>().is_err(), "{bad}");
+        }
+        assert_eq!("Center".parse::<Anchor>().unwrap(), Anchor::Center);
+        assert!("middle".parse::<Anchor>().is_err());
+    }
+
+    #[test]
+    fn test_rotation_from_degrees() {
+        assert_eq!(Rotation::from_degrees(0.0), Rotation::Deg0);
+        assert_eq!(Rotation::from_degrees(90.0), Rotation::Deg90);
+        assert_eq!(Rotation::from_degrees(-90.0), Rotation::Deg270);
+        assert_eq!(Rotation::from_degrees(540.0), Rotation::Deg180);
+        assert_eq!(Rotation::from_degrees(f32::NAN), Rotation::Deg0);
+    }
+
+    #[test]
+    fn test_rotate_region_quarter_turn() {
+        // Top-left 10x20 block of a 100x50 frame ends up top-right after 90°
+        let rotated = Rotation::Deg90.rotate_region(&Region::new(0, 0, 10, 20), 100, 50);
+        assert_eq!(rotated, Region::new(30, 0, 20, 10));
+    }
+
+    #[test]
+    fn test_mapper_to_frame_clamps() {
+        let mapper = CoordinateMapper::new(
+            Region::new(0, 0, 100, 100),
+            ScalingConfig::new(2.0, 1.0),
+            Rotation::Deg0,
+        );
+
+        assert_eq!(
+            mapper.to_frame(&Region::new(90, 90, 50, 50)),
+            Some(Region::new(180, 180, 20, 20)),
+            "Regions hanging off the display should be clamped"
+        );
+        assert_eq!(mapper.to_frame(&Region::new(200, 200, 5, 5)), None);
+    }
+
+    #[test]
+    fn test_global_to_local_at_the_limits() {
+        let mapper = CoordinateMapper::new(
+            Region::new(i32::MIN, 0, u32::MAX, 10),
+            ScalingConfig::identity(),
+            Rotation::Deg0,
+        );
+        assert_eq!(
+            mapper.global_to_local(&Region::new(i32::MIN + 5, 2, 10, 3)),
+            Some(Region::new(5, 2, 10, 3))
+        );
+        assert_eq!(
+            mapper.global_to_local(&Region::new(i32::MAX - 1, 0, 1, 1)),
+            Some(Region::new(i32::MAX, 0, 1, 1)),
+            "Saturates where the offset is past i32"
+        );
+    }
+
+    #[cfg(feature = "proptest")]
+    mod property_tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn rotation() -> impl Strategy<Value = Rotation> {
+            prop_oneof![
+                Just(Rotation::Deg0),
+                Just(Rotation::Deg90),
+                Just(Rotation::Deg180),
+                Just(Rotation::Deg270),
+            ]
+        }
+
+        /// A frame size together with a region that fits inside it
+        fn frame_and_region() -> impl Strategy<Value = (u32, u32, Region)> {
+            (1u32..4000, 1u32..4000).prop_flat_map(|(w, h)| {
+                (0..w, 0..h).prop_flat_map(move |(x, y)| {
+                    (1..=w - x, 1..=h - y)
+                        .prop_map(move |(rw, rh)| (w, h, Region::new(x as i32, y as i32, rw, rh)))
+                })
+            })
+        }
+
+        fn any_region() -> impl Strategy<Value = Region> {
+            (any::<i32>(), any::<i32>(), any::<u32>(), any::<u32>())
+                .prop_map(|(x, y, w, h)| Region::new(x, y, w, h))
+        }
+
+        proptest! {
+            #[test]
+            fn test_rotation_round_trip(
+                (w, h, region) in frame_and_region(),
+                rotation in rotation()
+            ) {
+                let rotated = rotation.rotate_region(&region, w, h);
+                let (rw, rh) = rotation.rotate_size(w, h);
+                let back = rotation.inverse().rotate_region(&rotated, rw, rh);
+
+                prop_assert_eq!(back, region);
+            }
+
+            #[test]
+            fn test_rotation_stays_in_frame(
+                (w, h, region) in frame_and_region(),
+                rotation in rotation()
+            ) {
+                let rotated = rotation.rotate_region(&region, w, h);
+                let (rw, rh) = rotation.rotate_size(w, h);
+
+                prop_assert!(Region::new(0, 0, rw, rh).contains_region(&rotated));
+                prop_assert_eq!(rotated.area(), region.area());
+            }
+
+            #[test]
+            fn test_four_quarter_turns_are_identity((w, h, region) in frame_and_region()) {
+                let mut current = region;
+                let (mut cw, mut ch) = (w, h);
+                for _ in 0..4 {
+                    current = Rotation::Deg90.rotate_region(&current, cw, ch);
+                    (cw, ch) = Rotation::Deg90.rotate_size(cw, ch);
+                }
+                prop_assert_eq!(current, region);
+            }
+
+            #[test]
+            fn test_clamp_stays_within_bounds(region in any_region(), bounds in any_region()) {
+                if let Some(clamped) = region.clamp_to(&bounds) {
+                    prop_assert!(bounds.contains_region(&clamped));
+                    prop_assert!(region.contains_region(&clamped));
+                    prop_assert!(!clamped.is_empty());
+                }
+            }
+
+            #[test]
+            fn test_union_contains_both(a in any_region(), b in any_region()) {
+                let union = a.union(&b);
+                // The union saturates at u32::MAX, so only check when it fits
+                if union.right() >= a.right().max(b.right())
+                    && union.bottom() >= a.bottom().max(b.bottom())
+                {
+                    prop_assert!(union.contains_region(&a));
+                    prop_assert!(union.contains_region(&b));
+                }
+            }
+
+            #[test]
+            fn test_mapper_frame_region_stays_in_frame(
+                (w, h, local) in frame_and_region(),
+                dpi_scale in 0.5f32..4.0,
+                extra_scale in 0.5f32..2.0,
+                rotation in rotation()
+            ) {
+                let mapper = CoordinateMapper::new(
+                    Region::new(0, 0, w, h),
+                    ScalingConfig::new(dpi_scale, extra_scale),
+                    rotation,
+                );
+                let (pw, ph) = mapper.physical_size();
+                let (fw, fh) = rotation.rotate_size(pw, ph);
+
+                if let Some(frame_region) = mapper.to_frame(&local) {
+                    prop_assert!(Region::new(0, 0, fw, fh).contains_region(&frame_region));
+                }
+            }
+
+            #[test]
+            fn test_mapper_round_trip(
+                (w, h, local) in frame_and_region(),
+                dpi_scale in 1.0f32..4.0,
+                extra_scale in 1.0f32..2.0
+            ) {
+                let mapper = CoordinateMapper::new(
+                    Region::new(0, 0, w, h),
+                    ScalingConfig::new(dpi_scale, extra_scale),
+                    Rotation::Deg0,
+                );
+                let back = mapper.to_logical(&mapper.to_physical(&local));
+
+                prop_assert!(back.x.abs_diff(local.x) <= 1);
+                prop_assert!(back.y.abs_diff(local.y) <= 1);
+                prop_assert!(back.width.abs_diff(local.width) <= 1);
+                prop_assert!(back.height.abs_diff(local.height) <= 1);
+            }
+        }
+    }
+}
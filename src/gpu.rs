@@ -0,0 +1,210 @@
+//! Captures as wgpu textures
+//!
+//! Real-time viewers and ML pipelines working on the GPU want frames as
+//! textures rather than images in memory. [`FrameTexture`] uploads captures
+//! into a `wgpu::Texture` it keeps and reuses while the frame size stays the
+//! same, so a capture loop costs one queue write a frame instead of a new
+//! allocation; [`capture_texture`] captures a display straight into a new
+//! one. Bring your own `Device` and `Queue`.
+//!
+//! Every backend captures into memory, so frames still go through the CPU
+//! on their way to the GPU. Handing over a backend's own GPU surfaces, such
+//! as the Direct3D 11 textures of Windows.Graphics.Capture, isn't supported.
+
+use crate::backend::CaptureBackend;
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+
+/// Format of the textures: captures are 8-bit sRGB
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// What the textures can be used for unless asked otherwise: sampling in
+/// shaders and copying to and from
+pub const USAGE: wgpu::TextureUsages = wgpu::TextureUsages::TEXTURE_BINDING
+    .union(wgpu::TextureUsages::COPY_DST)
+    .union(wgpu::TextureUsages::COPY_SRC);
+
+/// A texture frames are written into, replaced only when their size changes
+#[derive(Debug)]
+pub struct FrameTexture {
+    texture: Option<wgpu::Texture>,
+    usage: wgpu::TextureUsages,
+}
+
+impl Default for FrameTexture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameTexture {
+    pub fn new() -> Self {
+        Self {
+            texture: None,
+            usage: USAGE,
+        }
+    }
+
+    /// Creates textures with `usage` too, e.g. `RENDER_ATTACHMENT` or
+    /// `STORAGE_BINDING`
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = USAGE | usage;
+        self
+    }
+
+    /// Copies `image` into the texture, creating it on first use and again
+    /// whenever the size changes
+    ///
+    /// Fails for images larger than the device allows a texture to be.
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &RgbaImage,
+    ) -> Result<&wgpu::Texture> {
+        let (width, height) = image.dimensions();
+        let max = device.limits().max_texture_dimension_2d;
+        if width.max(height) > max {
+            return Err(Error::Unsupported(format!(
+                "a {width}x{height} frame is larger than this GPU's {max}x{max} textures"
+            )));
+        }
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = match self.texture.take() {
+            Some(texture) if texture.size() == size => texture,
+            _ => device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("snap_scale frame"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: self.usage,
+                view_formats: &[],
+            }),
+        };
+        queue.write_texture(
+            texture.as_image_copy(),
+            image.as_raw(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        Ok(self.texture.insert(texture))
+    }
+
+    /// The last frame written, if any
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        self.texture.as_ref()
+    }
+
+    pub fn into_texture(self) -> Option<wgpu::Texture> {
+        self.texture
+    }
+}
+
+/// `image` in a new texture
+pub fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+) -> Result<wgpu::Texture> {
+    let mut frame = FrameTexture::new();
+    frame.write(device, queue, image)?;
+    Ok(frame.into_texture().expect("just written"))
+}
+
+/// Captures all of `display` into a new texture
+pub fn capture_texture(
+    backend: &(impl CaptureBackend + ?Sized),
+    display: &DisplayDescriptor,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture> {
+    upload(device, queue, &backend.capture_display(display)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// The no-op backend answers at once, so there's nothing to wait for
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("the no-op backend should be ready"),
+        }
+    }
+
+    fn device() -> (wgpu::Device, wgpu::Queue) {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::NOOP,
+            backend_options: wgpu::BackendOptions {
+                noop: wgpu::NoopBackendOptions { enable: true },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let adapter = ready(instance.request_adapter(&Default::default())).unwrap();
+        ready(adapter.request_device(&Default::default())).unwrap()
+    }
+
+    #[test]
+    fn test_frames_reuse_the_texture() {
+        let (device, queue) = device();
+        let mut frame = FrameTexture::new();
+        assert!(frame.texture().is_none());
+
+        let first = frame
+            .write(&device, &queue, &RgbaImage::new(64, 48))
+            .unwrap()
+            .clone();
+        assert_eq!((first.width(), first.height()), (64, 48));
+        assert_eq!(first.format(), FORMAT);
+        assert_eq!(first.usage(), USAGE);
+
+        let again = frame
+            .write(&device, &queue, &RgbaImage::new(64, 48))
+            .unwrap();
+        assert_eq!(again, &first, "Same size, same texture");
+        let resized = frame
+            .write(&device, &queue, &RgbaImage::new(32, 24))
+            .unwrap();
+        assert_ne!(resized, &first);
+        assert_eq!((resized.width(), resized.height()), (32, 24));
+    }
+
+    #[test]
+    fn test_capture_texture() {
+        let (device, queue) = device();
+        let backend: MockBackend = "64x48,32x24@2".parse().unwrap();
+        let displays = backend.enumerate().unwrap();
+        let texture = capture_texture(&backend, &displays[1], &device, &queue).unwrap();
+        assert_eq!(
+            (texture.width(), texture.height()),
+            (64, 48),
+            "Physical size"
+        );
+
+        let max = device.limits().max_texture_dimension_2d;
+        let huge = RgbaImage::new(max + 1, 1);
+        assert!(upload(&device, &queue, &huge).is_err());
+
+        let storage = FrameTexture::new().with_usage(wgpu::TextureUsages::STORAGE_BINDING);
+        assert!(storage
+            .usage
+            .contains(USAGE | wgpu::TextureUsages::STORAGE_BINDING));
+    }
+}
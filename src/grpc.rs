@@ -0,0 +1,381 @@
+//! gRPC capture service
+//!
+//! `snap_scale grpc` serves the contract in `proto/snap_scale.proto`, so
+//! services in any language can generate a typed client instead of shelling
+//! out and parsing output:
+//!
+//! - `ListDisplays` returns the displays in index order
+//! - `Capture` encodes one display, or a logical area of it
+//! - `Record` streams frames at `fps` until `frames` are sent or the call is
+//!   cancelled
+//!
+//! Like the HTTP server's streams, `Record` never queues frames for a slow
+//! client: the next frame is captured once the last one is taken. With a
+//! token set, every call needs `authorization: Bearer <token>` metadata.
+
+use crate::encode::{encode_to_vec, EncodeOptions, OutputFormat};
+use crate::geometry::Region;
+use crate::source::Source;
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+/// Messages and stubs generated from `proto/snap_scale.proto`
+pub mod proto {
+    tonic::include_proto!("snap_scale.v1");
+}
+
+use proto::capture_service_server::{CaptureService, CaptureServiceServer};
+
+type Reply<T> = std::result::Result<Response<T>, Status>;
+
+/// Implements `CaptureService` on top of a [`Source`]
+pub struct Service<S> {
+    source: Arc<S>,
+}
+
+impl<S: Source + 'static> Service<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source: Arc::new(source),
+        }
+    }
+}
+
+/// Serves on `listener` until the process ends, requiring `token` if set
+pub async fn serve<S: Source + 'static>(
+    listener: TcpListener,
+    source: S,
+    token: Option<String>,
+) -> Result<()> {
+    let expected = token.map(|token| format!("Bearer {token}"));
+    // tonic's interceptor signature, so the error can't be boxed
+    #[allow(clippy::result_large_err)]
+    let check = move |request: Request<()>| {
+        let Some(expected) = &expected else {
+            return Ok(request);
+        };
+        let given = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if given == Some(expected.as_str()) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("missing or wrong bearer token"))
+        }
+    };
+    tonic::transport::Server::builder()
+        .add_service(CaptureServiceServer::with_interceptor(
+            Service::new(source),
+            check,
+        ))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .map_err(|e| std::io::Error::other(e).into())
+}
+
+/// Invalid arguments for bad parameters, internal errors for the rest
+fn status(e: Error) -> Status {
+    match e {
+        Error::Invalid { .. } => Status::invalid_argument(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+/// Runs a capture off the async threads
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> std::result::Result<T, Status> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(status)
+}
+
+fn region(region: Option<proto::Region>) -> Result<Option<Region>> {
+    let Some(proto::Region {
+        x,
+        y,
+        width,
+        height,
+    }) = region
+    else {
+        return Ok(None);
+    };
+    let region = Region::new(x, y, width, height);
+    if region.is_empty() {
+        return Err(Error::invalid("area", "width and height must be positive"));
+    }
+    Ok(Some(region))
+}
+
+/// `format`, or `default` when it's empty
+fn format(format: &str, default: OutputFormat) -> Result<OutputFormat> {
+    if format.is_empty() {
+        Ok(default)
+    } else {
+        format.parse()
+    }
+}
+
+fn image(frame: &RgbaImage, options: &EncodeOptions, sequence: u64) -> Result<proto::Image> {
+    let captured_at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    Ok(proto::Image {
+        data: encode_to_vec(frame, options)?,
+        mime_type: options.format.mime_type().into(),
+        width: frame.width(),
+        height: frame.height(),
+        sequence,
+        captured_at_ms,
+    })
+}
+
+#[tonic::async_trait]
+impl<S: Source + 'static> CaptureService for Service<S> {
+    async fn list_displays(
+        &self,
+        _request: Request<proto::ListDisplaysRequest>,
+    ) -> Reply<proto::ListDisplaysResponse> {
+        let source = Arc::clone(&self.source);
+        let displays = blocking(move || source.displays()).await?;
+        let displays = displays
+            .into_iter()
+            .enumerate()
+            .map(|(index, d)| proto::Display {
+                index: index as u32,
+                id: d.id,
+                x: d.x,
+                y: d.y,
+                width: d.width,
+                height: d.height,
+                rotation: d.rotation,
+                scale_factor: d.scale_factor,
+                frequency: d.frequency,
+                is_primary: d.is_primary,
+                name: d.name,
+            })
+            .collect();
+        Ok(Response::new(proto::ListDisplaysResponse { displays }))
+    }
+
+    async fn capture(&self, request: Request<proto::CaptureRequest>) -> Reply<proto::Image> {
+        let request = request.into_inner();
+        let source = Arc::clone(&self.source);
+        let image = blocking(move || {
+            let options = EncodeOptions::new(format(&request.format, OutputFormat::Png)?);
+            let frame = source.capture(request.display as usize, region(request.region)?)?;
+            image(&frame, &options, 0)
+        })
+        .await?;
+        Ok(Response::new(image))
+    }
+
+    type RecordStream = ReceiverStream<std::result::Result<proto::Image, Status>>;
+
+    async fn record(&self, request: Request<proto::RecordRequest>) -> Reply<Self::RecordStream> {
+        let request = request.into_inner();
+        let fps = match request.fps {
+            0 => 5,
+            fps @ 1..=60 => fps,
+            fps => return Err(status(Error::invalid("fps (1 to 60)", fps.to_string()))),
+        };
+        let quality = match request.quality {
+            0 => 80,
+            quality @ 1..=100 => quality as u8,
+            quality => {
+                return Err(status(Error::invalid(
+                    "JPEG quality (1 to 100)",
+                    quality.to_string(),
+                )))
+            }
+        };
+        let options =
+            EncodeOptions::new(format(&request.format, OutputFormat::Jpeg).map_err(status)?)
+                .with_jpeg_quality(quality);
+        let display = request.display as usize;
+        let region = region(request.region).map_err(status)?;
+        let interval = Duration::from_secs(1) / fps;
+
+        // Captured up front so bad parameters fail the call itself
+        let source = Arc::clone(&self.source);
+        let first = blocking(move || source.capture(display, region)).await?;
+        let source = Arc::clone(&self.source);
+        // Room for one frame: capture waits for the client to take it
+        let (frames, receiver) = mpsc::channel(1);
+        tokio::task::spawn_blocking(move || {
+            let mut frame = first;
+            for sequence in 0.. {
+                let started = Instant::now();
+                let sent = image(&frame, &options, sequence).map_err(status);
+                let failed = sent.is_err();
+                // Closed once the client goes away
+                if frames.blocking_send(sent).is_err() || failed {
+                    return;
+                }
+                if request.frames != 0 && sequence + 1 >= request.frames {
+                    return;
+                }
+                std::thread::sleep(interval.saturating_sub(started.elapsed()));
+                frame = match source.capture(display, region) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        let _ = frames.blocking_send(Err(status(e)));
+                        return;
+                    }
+                };
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::capture_service_client::CaptureServiceClient;
+    use super::*;
+    use crate::metadata::DisplayDescriptor;
+    use screenshots::image::Rgba;
+    use tokio_stream::StreamExt;
+
+    /// One 16x9 display, or the area asked for, in solid red
+    struct Fake;
+
+    impl Source for Fake {
+        fn displays(&self) -> Result<Vec<DisplayDescriptor>> {
+            Ok(vec![DisplayDescriptor {
+                id: 7,
+                name: String::new(),
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                rotation: 0.0,
+                scale_factor: 2.0,
+                frequency: 60.0,
+                is_primary: true,
+            }])
+        }
+
+        fn capture(&self, index: usize, region: Option<Region>) -> Result<RgbaImage> {
+            if index > 0 {
+                return Err(Error::invalid("display", format!("no display #{index}")));
+            }
+            let region = region.unwrap_or(Region::new(0, 0, 16, 9));
+            Ok(RgbaImage::from_pixel(
+                region.width,
+                region.height,
+                Rgba([255, 0, 0, 255]),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capture_and_list_displays() {
+        let service = Service::new(Fake);
+        let listed = service
+            .list_displays(Request::new(proto::ListDisplaysRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(listed.displays.len(), 1);
+        assert_eq!(
+            (listed.displays[0].id, listed.displays[0].scale_factor),
+            (7, 2.0)
+        );
+
+        let image = service
+            .capture(Request::new(proto::CaptureRequest {
+                region: Some(proto::Region {
+                    x: 1,
+                    y: 1,
+                    width: 4,
+                    height: 3,
+                }),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            (image.mime_type.as_str(), image.width, image.height),
+            ("image/png", 4, 3)
+        );
+        let decoded = screenshots::image::load_from_memory(&image.data).unwrap();
+        assert_eq!(decoded.into_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_are_invalid_arguments() {
+        let service = Service::new(Fake);
+        let capture = |request| service.capture(Request::new(request));
+        let bad = [
+            proto::CaptureRequest {
+                display: 3,
+                ..Default::default()
+            },
+            proto::CaptureRequest {
+                format: "gif".into(),
+                ..Default::default()
+            },
+            proto::CaptureRequest {
+                region: Some(proto::Region::default()),
+                ..Default::default()
+            },
+        ];
+        for request in bad {
+            let e = capture(request).await.unwrap_err();
+            assert_eq!(e.code(), tonic::Code::InvalidArgument, "{e}");
+        }
+
+        let e = service
+            .record(Request::new(proto::RecordRequest {
+                fps: 61,
+                ..Default::default()
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_record_streams_numbered_frames_over_the_network() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Fake, Some("secret".into())));
+
+        let mut client = CaptureServiceClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+        let denied = client
+            .list_displays(proto::ListDisplaysRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::Unauthenticated);
+
+        let mut request = Request::new(proto::RecordRequest {
+            fps: 60,
+            frames: 3,
+            ..Default::default()
+        });
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let mut frames = client.record(request).await.unwrap().into_inner();
+        let mut sequences = Vec::new();
+        while let Some(frame) = frames.next().await {
+            let frame = frame.unwrap();
+            assert_eq!(frame.mime_type, "image/jpeg");
+            sequences.push(frame.sequence);
+        }
+        assert_eq!(sequences, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Perceptual image hashes and near-duplicate detection
+//!
+//! All hashes are 64-bit fingerprints of a downscaled grayscale copy, so
+//! visually similar images have hashes a small Hamming distance apart:
+//!
+//! * aHash - each pixel of an 8×8 thumbnail compared with the mean
+//! * dHash - each pixel of a 9×8 thumbnail compared with its right neighbour
+//! * pHash - the low 8×8 DCT frequencies of a 32×32 thumbnail compared with
+//!   their median; the most robust to scaling and compression
+
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// A 64-bit perceptual hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    /// Number of differing bits, 0 (identical) to 64
+    pub fn distance(&self, other: &ImageHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Which perceptual hash to compute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    Average,
+    #[default]
+    Difference,
+    Perceptual,
+}
+
+impl HashAlgorithm {
+    pub fn hash(self, image: &RgbaImage) -> ImageHash {
+        match self {
+            Self::Average => average_hash(image),
+            Self::Difference => difference_hash(image),
+            Self::Perceptual => perceptual_hash(image),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ahash" | "average" => Ok(Self::Average),
+            "dhash" | "difference" => Ok(Self::Difference),
+            "phash" | "perceptual" => Ok(Self::Perceptual),
+            _ => Err(Error::invalid("hash algorithm (ahash, dhash, phash)", s)),
+        }
+    }
+}
+
+/// Luma values of `image` shrunk to `width`×`height`, row by row
+fn thumbnail(image: &RgbaImage, width: u32, height: u32) -> Vec<f32> {
+    imageops::thumbnail(image, width, height)
+        .pixels()
+        .map(|p| {
+            let [r, g, b, _] = p.0;
+            0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+        })
+        .collect()
+}
+
+fn pack(bits: impl IntoIterator<Item = bool>) -> ImageHash {
+    ImageHash(
+        bits.into_iter()
+            .fold(0u64, |hash, bit| (hash << 1) | bit as u64),
+    )
+}
+
+pub fn average_hash(image: &RgbaImage) -> ImageHash {
+    let pixels = thumbnail(image, 8, 8);
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+    pack(pixels.iter().map(|&p| p > mean))
+}
+
+pub fn difference_hash(image: &RgbaImage) -> ImageHash {
+    let pixels = thumbnail(image, 9, 8);
+    pack(
+        pixels
+            .chunks_exact(9)
+            .flat_map(|row| row.windows(2).map(|pair| pair[0] > pair[1])),
+    )
+}
+
+pub fn perceptual_hash(image: &RgbaImage) -> ImageHash {
+    const SIZE: usize = 32;
+    let pixels = thumbnail(image, SIZE as u32, SIZE as u32);
+    let basis = |k: usize, n: usize| ((2 * n + 1) as f32 * k as f32 * PI / (2 * SIZE) as f32).cos();
+
+    // Only the 8×8 lowest frequencies of the DCT-II are needed
+    let mut coefficients = Vec::with_capacity(64);
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += pixels[y * SIZE + x] * basis(u, x) * basis(v, y);
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC term only reflects overall brightness
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    pack(coefficients.iter().map(|&c| c > median))
+}
+
+/// Drops frames that look like the last kept one
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    algorithm: HashAlgorithm,
+    threshold: u32,
+    last: Option<ImageHash>,
+}
+
+impl Deduplicator {
+    /// Frames within `threshold` bits of the last kept frame are duplicates
+    pub fn new(algorithm: HashAlgorithm, threshold: u32) -> Self {
+        Self {
+            algorithm,
+            threshold,
+            last: None,
+        }
+    }
+
+    /// Whether `image` duplicates the last kept frame; otherwise it becomes
+    /// the new reference
+    pub fn is_duplicate(&mut self, image: &RgbaImage) -> bool {
+        let hash = self.algorithm.hash(image);
+        match self.last {
+            Some(last) if last.distance(&hash) <= self.threshold => true,
+            _ => {
+                self.last = Some(hash);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    const ALGORITHMS: [HashAlgorithm; 3] = [
+        HashAlgorithm::Average,
+        HashAlgorithm::Difference,
+        HashAlgorithm::Perceptual,
+    ];
+
+    fn scene(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width) ^ (y * 255 / height)) as u8;
+            Rgba([v, v / 2, 255 - v, 255])
+        })
+    }
+
+    #[test]
+    fn test_resized_copies_hash_alike() {
+        let original = scene(256, 192);
+        let smaller = imageops::resize(&original, 128, 96, imageops::FilterType::Triangle);
+        for algorithm in ALGORITHMS {
+            let distance = algorithm
+                .hash(&original)
+                .distance(&algorithm.hash(&smaller));
+            assert!(distance <= 6, "{algorithm:?} distance {distance}");
+        }
+    }
+
+    #[test]
+    fn test_different_images_hash_apart() {
+        let a = scene(128, 128);
+        let b = RgbaImage::from_fn(128, 128, |x, _| {
+            let v = if x < 64 { 255 } else { 0 };
+            Rgba([v, v, v, 255])
+        });
+        for algorithm in ALGORITHMS {
+            let distance = algorithm.hash(&a).distance(&algorithm.hash(&b));
+            assert!(distance > 10, "{algorithm:?} distance {distance}");
+        }
+    }
+
+    #[test]
+    fn test_deduplicator_compares_with_last_kept() {
+        let mut dedupe = Deduplicator::new(HashAlgorithm::Difference, 4);
+        let frame = scene(64, 64);
+        let changed =
+            RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255]));
+
+        assert!(!dedupe.is_duplicate(&frame), "First frame is always kept");
+        assert!(dedupe.is_duplicate(&frame));
+        assert!(!dedupe.is_duplicate(&changed));
+        assert!(dedupe.is_duplicate(&changed));
+        assert!(!dedupe.is_duplicate(&frame));
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        assert_eq!(
+            "pHash".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Perceptual
+        );
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+        assert_eq!(ImageHash(0xab).to_string(), "00000000000000ab");
+    }
+}
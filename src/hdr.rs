@@ -0,0 +1,406 @@
+//! HDR capture and tone mapping
+//!
+//! An SDR capture of an HDR display is clipped by the OS, so highlights blow
+//! out. [`capture`] instead reads the display's floating-point scRGB
+//! framebuffer (linear Rec. 709, 1.0 = 80 nits) into an [`HdrImage`], and a
+//! [`ToneMapper`] compresses it back into SDR with a chosen [`Operator`], as
+//! an 8-bit capture for the usual pipeline or a 16-bit one for saving as is.
+//!
+//! HDR capture uses DXGI output duplication on Windows. Other platforms
+//! return [`Error::Unsupported`].
+
+use crate::transform::Rgba16Image;
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+use std::str::FromStr;
+
+/// Nits of scRGB 1.0
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// A linear scRGB capture with straight alpha
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row major; 1.0 is 80 nits and values may exceed it
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl HdrImage {
+    /// Builds an image from `R16G16B16A16_FLOAT` data, the layout HDR
+    /// framebuffers use
+    pub fn from_f16(width: u32, height: u32, data: &[u16]) -> Result<Self> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(Error::invalid("HDR frame", "wrong size for its dimensions"));
+        }
+        let pixels = data
+            .chunks_exact(4)
+            .map(|p| [f16(p[0]), f16(p[1]), f16(p[2]), f16(p[3])])
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Brightest channel value, in scRGB units
+    pub fn peak(&self) -> f32 {
+        self.pixels
+            .iter()
+            .flat_map(|p| &p[..3])
+            .fold(
+                0.0,
+                |peak, &v| if v.is_finite() { peak.max(v) } else { peak },
+            )
+    }
+}
+
+/// An IEEE 754 half-precision float
+fn f16(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// How highlights above SDR white are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Operator {
+    /// Cut everything brighter than SDR white
+    Clip,
+    /// Extended Reinhard on luminance, with the image's peak as white: SDR
+    /// content is unchanged and hues are kept
+    #[default]
+    Reinhard,
+    /// Narkowicz's ACES filmic fit, per channel: more contrast, slightly
+    /// darker midtones
+    Aces,
+}
+
+impl FromStr for Operator {
+    type Err = Error;
+
+    /// Parses `clip`, `reinhard` or `aces`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "clip" => Ok(Self::Clip),
+            "reinhard" => Ok(Self::Reinhard),
+            "aces" => Ok(Self::Aces),
+            _ => Err(Error::invalid(
+                "tone mapping operator (clip, reinhard, aces)",
+                s,
+            )),
+        }
+    }
+}
+
+/// Converts HDR captures to SDR
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub operator: Operator,
+    /// Brightness SDR white is shown at, in nits; the Windows "SDR content
+    /// brightness" setting
+    pub sdr_white: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self::new(Operator::default())
+    }
+}
+
+impl ToneMapper {
+    pub fn new(operator: Operator) -> Self {
+        Self {
+            operator,
+            sdr_white: SCRGB_WHITE_NITS,
+        }
+    }
+
+    pub fn with_sdr_white(mut self, nits: f32) -> Self {
+        self.sdr_white = nits.max(1.0);
+        self
+    }
+
+    /// Tone-maps to sRGB-encoded channels in 0-1, alpha unchanged
+    fn map_pixels<'a>(&self, image: &'a HdrImage) -> impl Iterator<Item = [f32; 4]> + 'a {
+        let scale = SCRGB_WHITE_NITS / self.sdr_white;
+        let white = (image.peak() * scale).max(1.0);
+        let operator = self.operator;
+        image.pixels.iter().map(move |&[r, g, b, a]| {
+            let rgb = [r, g, b].map(|c| {
+                if c.is_finite() {
+                    c.max(0.0) * scale
+                } else {
+                    0.0
+                }
+            });
+            let [r, g, b] = match operator {
+                Operator::Clip => rgb,
+                Operator::Reinhard => {
+                    let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+                    let mapped = luma * (1.0 + luma / (white * white)) / (1.0 + luma);
+                    let ratio = if luma > 0.0 { mapped / luma } else { 0.0 };
+                    rgb.map(|c| c * ratio)
+                }
+                Operator::Aces => {
+                    rgb.map(|c| (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14))
+                }
+            };
+            [
+                encode_srgb(r),
+                encode_srgb(g),
+                encode_srgb(b),
+                a.clamp(0.0, 1.0),
+            ]
+        })
+    }
+
+    /// Tone-maps to an 8-bit capture
+    pub fn map(&self, image: &HdrImage) -> RgbaImage {
+        let data = self
+            .map_pixels(image)
+            .flatten()
+            .map(|c| (c * 255.0).round() as u8)
+            .collect();
+        RgbaImage::from_raw(image.width, image.height, data).unwrap_or_default()
+    }
+
+    /// Tone-maps to 16 bits per channel, keeping the gradations 8 bits lose
+    pub fn map16(&self, image: &HdrImage) -> Rgba16Image {
+        let data = self
+            .map_pixels(image)
+            .flatten()
+            .map(|c| (c * 65535.0).round() as u16)
+            .collect();
+        Rgba16Image::from_raw(image.width, image.height, data).unwrap_or_default()
+    }
+}
+
+/// The sRGB transfer function, clamped to 0-1
+fn encode_srgb(linear: f32) -> f32 {
+    let linear = linear.clamp(0.0, 1.0);
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Captures a display's HDR framebuffer, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+#[cfg(target_os = "windows")]
+pub fn capture(display_id: u32) -> Result<HdrImage> {
+    use screenshots::display_info::DisplayInfo;
+    use windows::core::ComInterface;
+    use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_UNKNOWN;
+    use windows::Win32::Graphics::Direct3D11::{
+        D3D11CreateDevice, ID3D11Texture2D, D3D11_CPU_ACCESS_READ, D3D11_CREATE_DEVICE_FLAG,
+        D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC,
+        D3D11_USAGE_STAGING,
+    };
+    use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R16G16B16A16_FLOAT;
+    use windows::Win32::Graphics::Dxgi::{
+        CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput6, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC,
+    };
+    use windows::Win32::Graphics::Gdi::HMONITOR;
+
+    let os = |e: windows::core::Error| Error::Unsupported(format!("HDR capture: {e}"));
+    let info = DisplayInfo::all()
+        .map_err(|e| Error::Unsupported(format!("HDR capture: {e}")))?
+        .into_iter()
+        .find(|info| info.id == display_id)
+        .ok_or_else(|| Error::invalid("display id", display_id.to_string()))?;
+    let monitor = HMONITOR(info.raw_handle.0);
+
+    unsafe {
+        let factory: IDXGIFactory1 = CreateDXGIFactory1().map_err(os)?;
+        let mut found = None;
+        let mut adapters = 0;
+        while let (None, Ok(adapter)) = (&found, factory.EnumAdapters1(adapters)) {
+            let mut outputs = 0;
+            while let Ok(output) = adapter.EnumOutputs(outputs) {
+                let mut desc = DXGI_OUTPUT_DESC::default();
+                output.GetDesc(&mut desc).map_err(os)?;
+                if desc.Monitor == monitor {
+                    found = Some((adapter.clone(), output));
+                    break;
+                }
+                outputs += 1;
+            }
+            adapters += 1;
+        }
+        let (adapter, output) = found
+            .ok_or_else(|| Error::invalid("display id (no DXGI output)", display_id.to_string()))?;
+        let output: IDXGIOutput6 = output.cast().map_err(os)?;
+
+        let mut device = None;
+        let mut context = None;
+        D3D11CreateDevice(
+            &adapter,
+            D3D_DRIVER_TYPE_UNKNOWN,
+            None,
+            D3D11_CREATE_DEVICE_FLAG(0),
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            None,
+            Some(&mut context),
+        )
+        .map_err(os)?;
+        let missing = || Error::Unsupported("HDR capture: no Direct3D device".into());
+        let device = device.ok_or_else(missing)?;
+        let context = context.ok_or_else(missing)?;
+
+        let duplication = output
+            .DuplicateOutput1(&device, 0, &[DXGI_FORMAT_R16G16B16A16_FLOAT])
+            .map_err(os)?;
+        let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
+        let mut resource = None;
+        duplication
+            .AcquireNextFrame(1000, &mut frame_info, &mut resource)
+            .map_err(os)?;
+        let texture: ID3D11Texture2D = resource.ok_or_else(missing)?.cast().map_err(os)?;
+
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut desc);
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.BindFlags = 0;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+        desc.MiscFlags = 0;
+        let mut staging = None;
+        device
+            .CreateTexture2D(&desc, None, Some(&mut staging))
+            .map_err(os)?;
+        let staging = staging.ok_or_else(missing)?;
+        context.CopyResource(&staging, &texture);
+        let _ = duplication.ReleaseFrame();
+
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        context
+            .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+            .map_err(os)?;
+        let (width, height) = (desc.Width, desc.Height);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in 0..height as usize {
+            let start = (mapped.pData as *const u8).add(row * mapped.RowPitch as usize);
+            let row = std::slice::from_raw_parts(start as *const u16, width as usize * 4);
+            data.extend_from_slice(row);
+        }
+        context.Unmap(&staging, 0);
+        HdrImage::from_f16(width, height, &data)
+    }
+}
+
+/// Captures a display's HDR framebuffer, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+#[cfg(not(target_os = "windows"))]
+pub fn capture(_display_id: u32) -> Result<HdrImage> {
+    Err(Error::Unsupported(
+        "HDR capture is only available on Windows".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: Vec<[f32; 4]>) -> HdrImage {
+        HdrImage {
+            width: pixels.len() as u32,
+            height: 1,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn test_half_floats_decode() {
+        // 1.0, 4.0, 0.5, 1.0 and a negative subnormal
+        let data = [0x3c00, 0x4400, 0x3800, 0x3c00, 0x8001, 0, 0, 0x3c00];
+        let hdr = HdrImage::from_f16(2, 1, &data).unwrap();
+
+        assert_eq!(hdr.pixels[0], [1.0, 4.0, 0.5, 1.0]);
+        assert!(hdr.pixels[1][0] < 0.0 && hdr.pixels[1][0] > -1e-7);
+        assert_eq!(hdr.peak(), 4.0);
+        assert!(HdrImage::from_f16(3, 1, &data).is_err(), "Size mismatch");
+    }
+
+    #[test]
+    fn test_sdr_content_survives_reinhard() {
+        let hdr = image(vec![[1.0, 1.0, 1.0, 1.0], [0.2, 0.2, 0.2, 0.5]]);
+        let out = ToneMapper::default().map(&hdr);
+
+        assert_eq!(out.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(
+            out.get_pixel(1, 0).0,
+            [124, 124, 124, 128],
+            "Linear 0.2 in sRGB"
+        );
+    }
+
+    #[test]
+    fn test_highlights_are_compressed_not_clipped() {
+        // A grey ramp up to 5x SDR white
+        let hdr = image(
+            (1..=5)
+                .map(|v| [v as f32, v as f32, v as f32, 1.0])
+                .collect(),
+        );
+        let clipped = ToneMapper::new(Operator::Clip).map(&hdr);
+        assert!(clipped.pixels().all(|p| p.0[0] == 255), "Blown out");
+
+        for operator in [Operator::Reinhard, Operator::Aces] {
+            let mapped = ToneMapper::new(operator).map(&hdr);
+            let levels: Vec<u8> = mapped.pixels().map(|p| p.0[0]).collect();
+            assert!(
+                levels.windows(2).all(|w| w[0] < w[1]),
+                "{operator:?}: {levels:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sdr_white_scales_exposure() {
+        let hdr = image(vec![[2.5, 2.5, 2.5, 1.0]]);
+        let out = ToneMapper::new(Operator::Clip)
+            .with_sdr_white(200.0)
+            .map(&hdr);
+        assert_eq!(out.get_pixel(0, 0).0[0], 255, "200 nits is SDR white");
+
+        let out = ToneMapper::new(Operator::Clip)
+            .with_sdr_white(400.0)
+            .map(&hdr);
+        assert!(out.get_pixel(0, 0).0[0] < 255);
+    }
+
+    #[test]
+    fn test_16_bit_keeps_gradations() {
+        let hdr = image(
+            (0..64)
+                .map(|v| [v as f32 / 20000.0, 0.0, 0.0, 1.0])
+                .collect(),
+        );
+        let mapper = ToneMapper::new(Operator::Clip);
+
+        let levels = |values: Vec<u32>| {
+            let mut values = values;
+            values.dedup();
+            values.len()
+        };
+        let eight = levels(mapper.map(&hdr).pixels().map(|p| p.0[0] as u32).collect());
+        let sixteen = levels(mapper.map16(&hdr).pixels().map(|p| p.0[0] as u32).collect());
+        assert!(sixteen > eight * 4, "{sixteen} vs {eight}");
+    }
+
+    #[test]
+    fn test_parse_operator() {
+        assert_eq!("ACES".parse::<Operator>().unwrap(), Operator::Aces);
+        assert!("hable".parse::<Operator>().is_err());
+    }
+}
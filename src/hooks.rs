@@ -0,0 +1,130 @@
+//! User shell hooks run around each capture
+//!
+//! Commands run through the platform shell (`sh -c` / `cmd /C`) with details
+//! about the capture exported as `SNAP_*` environment variables.
+
+use crate::{Error, Result};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What a hook gets to know about the capture it runs for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookContext {
+    /// Platform display id
+    pub display: String,
+    /// Saved file; unset for pre-capture hooks
+    pub file: Option<PathBuf>,
+    /// Captured size in physical pixels; zero for pre-capture hooks
+    pub width: u32,
+    pub height: u32,
+}
+
+impl HookContext {
+    /// Context for a hook running before anything is captured
+    pub fn before(display: impl Into<String>) -> Self {
+        Self {
+            display: display.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Context for a hook running after `file` was written
+    pub fn after(
+        display: impl Into<String>,
+        file: impl Into<PathBuf>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self {
+            display: display.into(),
+            file: Some(file.into()),
+            width,
+            height,
+        }
+    }
+
+    /// The `SNAP_*` variables exported to the hook
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("SNAP_DISPLAY", self.display.clone())];
+        if let Some(file) = &self.file {
+            vars.push(("SNAP_FILE", file.display().to_string()));
+            vars.push(("SNAP_WIDTH", self.width.to_string()));
+            vars.push(("SNAP_HEIGHT", self.height.to_string()));
+        }
+        vars
+    }
+}
+
+/// Runs `command` through the shell and waits for it
+///
+/// A non-zero exit status is reported as [`Error::Hook`].
+pub fn run_hook(command: &str, context: &HookContext) -> Result<()> {
+    wait(command, shell(command).envs(context.env()))
+}
+
+/// Like [`run_hook`], but the hook's stdout goes to stderr, for when stdout
+/// carries the capture itself
+pub fn run_hook_to_stderr(command: &str, context: &HookContext) -> Result<()> {
+    wait(
+        command,
+        shell(command).envs(context.env()).stdout(std::io::stderr()),
+    )
+}
+
+fn wait(command: &str, process: &mut Command) -> Result<()> {
+    let status = process.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Hook {
+            command: command.to_string(),
+            status: status.to_string(),
+        })
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_sees_capture_variables() {
+        let context = HookContext::after("DISPLAY1", "/tmp/shot.png", 640, 480);
+        run_hook(
+            r#"[ "$SNAP_FILE" = /tmp/shot.png ] && [ "$SNAP_DISPLAY" = DISPLAY1 ] && [ "$SNAP_WIDTH" = 640 ] && [ "$SNAP_HEIGHT" = 480 ]"#,
+            &context,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_pre_capture_has_no_file() {
+        let context = HookContext::before("DISPLAY1");
+        run_hook(r#"[ -z "$SNAP_FILE" ]"#, &context).unwrap();
+    }
+
+    #[test]
+    fn test_failing_hook_is_an_error() {
+        let err = run_hook("exit 3", &HookContext::before("0")).unwrap_err();
+        assert!(
+            matches!(err, Error::Hook { .. }),
+            "Non-zero exit should be reported: {err}"
+        );
+        let err = run_hook_to_stderr("echo hi; exit 3", &HookContext::before("0")).unwrap_err();
+        assert!(matches!(err, Error::Hook { .. }), "{err}");
+    }
+}
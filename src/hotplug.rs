@@ -0,0 +1,338 @@
+//! Display hotplug events
+//!
+//! A [`DisplayWatcher`] re-enumerates a [`CaptureBackend`] in the background
+//! and reports what changed since the last look, so long-running modes can
+//! pick up monitors as they come and go instead of capturing a display that
+//! isn't there anymore. Polling works the same on every platform and backend,
+//! mock ones included; displays are matched by id. Each poll
+//! [refreshes](CaptureBackend::refresh) the backend first, which keeps its
+//! cached display list current for everyone else too.
+
+use crate::backend::CaptureBackend;
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A change to the display layout; the display is as it is now, or as it was
+/// when it went away
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayEvent {
+    Connected(DisplayDescriptor),
+    Disconnected(DisplayDescriptor),
+    /// Rotation changed, from `from` degrees
+    Rotated {
+        display: DisplayDescriptor,
+        from: f32,
+    },
+    /// Scale factor changed, from `from`
+    Rescaled {
+        display: DisplayDescriptor,
+        from: f32,
+    },
+    /// Logical bounds changed, from `from`, without a rotation: a new
+    /// resolution or a new place in the layout
+    Resized {
+        display: DisplayDescriptor,
+        from: Region,
+    },
+}
+
+impl DisplayEvent {
+    /// The display the event is about
+    pub fn display(&self) -> &DisplayDescriptor {
+        match self {
+            Self::Connected(display) | Self::Disconnected(display) => display,
+            Self::Rotated { display, .. }
+            | Self::Rescaled { display, .. }
+            | Self::Resized { display, .. } => display,
+        }
+    }
+}
+
+impl fmt::Display for DisplayEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = self.display();
+        match self {
+            Self::Connected(_) => write!(
+                f,
+                "display {} connected ({}x{} at {},{})",
+                d.id, d.width, d.height, d.x, d.y
+            ),
+            Self::Disconnected(_) => write!(f, "display {} disconnected", d.id),
+            Self::Rotated { from, .. } => {
+                write!(
+                    f,
+                    "display {} rotated from {from}° to {}°",
+                    d.id, d.rotation
+                )
+            }
+            Self::Rescaled { from, .. } => write!(
+                f,
+                "display {} rescaled from {from}x to {}x",
+                d.id, d.scale_factor
+            ),
+            Self::Resized { from, .. } => write!(
+                f,
+                "display {} changed from {}x{} at {},{} to {}x{} at {},{}",
+                d.id, from.width, from.height, from.x, from.y, d.width, d.height, d.x, d.y
+            ),
+        }
+    }
+}
+
+fn bounds(display: &DisplayDescriptor) -> Region {
+    Region::new(display.x, display.y, display.width, display.height)
+}
+
+/// What changed from `before` to `after`: disconnections first, then changes
+/// and connections in `after`'s order
+pub fn changes(before: &[DisplayDescriptor], after: &[DisplayDescriptor]) -> Vec<DisplayEvent> {
+    let mut events: Vec<_> = before
+        .iter()
+        .filter(|old| !after.iter().any(|new| new.id == old.id))
+        .cloned()
+        .map(DisplayEvent::Disconnected)
+        .collect();
+    for new in after {
+        let Some(old) = before.iter().find(|old| old.id == new.id) else {
+            events.push(DisplayEvent::Connected(new.clone()));
+            continue;
+        };
+        if old.rotation != new.rotation {
+            // Rotating swaps the bounds too; that's part of the rotation
+            events.push(DisplayEvent::Rotated {
+                display: new.clone(),
+                from: old.rotation,
+            });
+        } else if bounds(old) != bounds(new) {
+            events.push(DisplayEvent::Resized {
+                display: new.clone(),
+                from: bounds(old),
+            });
+        }
+        if old.scale_factor != new.scale_factor {
+            events.push(DisplayEvent::Rescaled {
+                display: new.clone(),
+                from: old.scale_factor,
+            });
+        }
+    }
+    events
+}
+
+/// Watches a backend's displays from a background thread, stopped on drop
+pub struct DisplayWatcher {
+    events: Receiver<DisplayEvent>,
+    stopping: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl DisplayWatcher {
+    /// Starts re-enumerating `backend` every `interval`, comparing against the
+    /// displays there are now
+    pub fn start(backend: Arc<dyn CaptureBackend>, interval: Duration) -> Result<Self> {
+        let mut known = backend.enumerate()?;
+        let (sender, events) = mpsc::channel();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&stopping);
+        let worker = std::thread::spawn(move || {
+            let mut failing = false;
+            loop {
+                // In short steps, so dropping the watcher doesn't wait long
+                let wake = Instant::now() + interval;
+                while Instant::now() < wake {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    std::thread::sleep(interval.min(Duration::from_millis(50)));
+                }
+                backend.refresh();
+                let current = match backend.enumerate() {
+                    Ok(current) => current,
+                    Err(e) => {
+                        // Mid-reconfiguration some platforms briefly fail to
+                        // list displays; report it once and keep trying
+                        if !failing {
+                            tracing::warn!("listing displays: {e}");
+                            failing = true;
+                        }
+                        continue;
+                    }
+                };
+                failing = false;
+                for event in changes(&known, &current) {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                known = current;
+            }
+        });
+        Ok(Self {
+            events,
+            stopping,
+            worker: Some(worker),
+        })
+    }
+
+    /// Events seen since the last call, without waiting
+    pub fn poll(&self) -> Vec<DisplayEvent> {
+        self.events.try_iter().collect()
+    }
+
+    /// Events as they happen, for as long as the watcher lives
+    pub fn iter(&self) -> impl Iterator<Item = DisplayEvent> + '_ {
+        self.events.iter()
+    }
+
+    /// Waits up to `timeout` for the next event
+    pub fn next_timeout(&self, timeout: Duration) -> Option<DisplayEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for DisplayWatcher {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::RgbaImage;
+    use std::sync::Mutex;
+
+    fn display(id: u32, x: i32, width: u32, height: u32) -> DisplayDescriptor {
+        DisplayDescriptor {
+            id,
+            name: String::new(),
+            x,
+            y: 0,
+            width,
+            height,
+            rotation: 0.0,
+            scale_factor: 1.0,
+            frequency: 60.0,
+            is_primary: id == 1,
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let before = [display(1, 0, 1920, 1080), display(2, 1920, 1280, 1024)];
+        assert!(changes(&before, &before).is_empty());
+
+        let mut rotated = before[0].clone();
+        (rotated.rotation, rotated.width, rotated.height) = (90.0, 1080, 1920);
+        let mut rescaled = display(3, 1080, 2560, 1440);
+        rescaled.scale_factor = 2.0;
+        let after = [rotated.clone(), rescaled.clone()];
+        assert_eq!(
+            changes(&before, &after),
+            [
+                DisplayEvent::Disconnected(before[1].clone()),
+                DisplayEvent::Rotated {
+                    display: rotated,
+                    from: 0.0
+                },
+                DisplayEvent::Connected(rescaled.clone()),
+            ]
+        );
+
+        let mut denser = rescaled.clone();
+        (denser.scale_factor, denser.width, denser.height) = (1.5, 3413, 1920);
+        assert_eq!(
+            changes(&[rescaled.clone()], &[denser.clone()]),
+            [
+                DisplayEvent::Resized {
+                    display: denser.clone(),
+                    from: Region::new(1080, 0, 2560, 1440)
+                },
+                DisplayEvent::Rescaled {
+                    display: denser,
+                    from: 2.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_event_text() {
+        let event = DisplayEvent::Connected(display(2, 1920, 1280, 1024));
+        assert_eq!(
+            event.to_string(),
+            "display 2 connected (1280x1024 at 1920,0)"
+        );
+        let event = DisplayEvent::Rescaled {
+            display: display(1, 0, 10, 10),
+            from: 2.0,
+        };
+        assert_eq!(event.to_string(), "display 1 rescaled from 2x to 1x");
+    }
+
+    /// A backend whose displays the test swaps out, listed only on refresh
+    struct Swappable {
+        plugged: Mutex<Vec<DisplayDescriptor>>,
+        listed: Mutex<Option<Vec<DisplayDescriptor>>>,
+    }
+
+    impl CaptureBackend for Swappable {
+        fn name(&self) -> &'static str {
+            "swappable"
+        }
+
+        fn enumerate(&self) -> Result<Vec<DisplayDescriptor>> {
+            let mut listed = self.listed.lock().unwrap();
+            Ok(listed
+                .get_or_insert_with(|| self.plugged.lock().unwrap().clone())
+                .clone())
+        }
+
+        fn refresh(&self) {
+            *self.listed.lock().unwrap() = None;
+        }
+
+        fn capture_display(&self, display: &DisplayDescriptor) -> Result<RgbaImage> {
+            Ok(RgbaImage::new(display.width, display.height))
+        }
+
+        fn capture_area(&self, _display: &DisplayDescriptor, area: Region) -> Result<RgbaImage> {
+            Ok(RgbaImage::new(area.width, area.height))
+        }
+    }
+
+    #[test]
+    fn test_watcher_reports_hotplug() {
+        let backend = Arc::new(Swappable {
+            plugged: Mutex::new(vec![display(1, 0, 640, 480)]),
+            listed: Mutex::new(None),
+        });
+        let watcher = DisplayWatcher::start(backend.clone(), Duration::from_millis(10)).unwrap();
+        assert!(watcher.poll().is_empty());
+
+        let second = display(2, 640, 800, 600);
+        backend.plugged.lock().unwrap().push(second.clone());
+        assert_eq!(
+            watcher.next_timeout(Duration::from_secs(5)),
+            Some(DisplayEvent::Connected(second.clone()))
+        );
+        backend.plugged.lock().unwrap().remove(0);
+        assert_eq!(
+            watcher.next_timeout(Duration::from_secs(5)),
+            Some(DisplayEvent::Disconnected(display(1, 0, 640, 480)))
+        );
+        drop(watcher);
+        // The watcher's refreshes are what everyone else enumerates
+        assert_eq!(backend.enumerate().unwrap(), [second]);
+    }
+}
@@ -0,0 +1,346 @@
+//! ICC color profile embedding
+//!
+//! Captures hold pixels in the display's color space. Without a profile,
+//! viewers assume sRGB, so captures of wide-gamut panels look washed out or
+//! oversaturated elsewhere. [`display_profile`] reads the profile the OS has
+//! assigned to a display and [`embed`] writes one into encoded PNG (an `iCCP`
+//! chunk) or JPEG (`APP2` segments) bytes.
+
+use crate::encode::{png_chunks, write_png_chunk, OutputFormat, PNG_SIGNATURE};
+use crate::{Error, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where an embedded profile comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileSource {
+    /// The profile assigned to the captured display
+    Display,
+    /// An `.icc`/`.icm` file
+    File(PathBuf),
+}
+
+impl FromStr for ProfileSource {
+    type Err = Error;
+
+    /// `display`, or a path to a profile
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" => Err(Error::invalid("ICC profile", s)),
+            "display" => Ok(Self::Display),
+            path => Ok(Self::File(path.into())),
+        }
+    }
+}
+
+impl ProfileSource {
+    /// Loads the profile; `None` when the display has no profile assigned
+    pub fn load(&self, display_id: u32) -> Result<Option<Vec<u8>>> {
+        let profile = match self {
+            Self::Display => match display_profile(display_id)? {
+                Some(profile) => profile,
+                None => return Ok(None),
+            },
+            Self::File(path) => std::fs::read(path)?,
+        };
+        validate(&profile)?;
+        Ok(Some(profile))
+    }
+}
+
+/// Checks the fixed-size header of an ICC profile
+pub fn validate(profile: &[u8]) -> Result<()> {
+    let declared = profile
+        .get(..4)
+        .map(|size| u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize);
+    if profile.len() < 128 || profile.get(36..40) != Some(b"acsp") || declared > Some(profile.len())
+    {
+        return Err(Error::invalid("ICC profile", "missing or truncated header"));
+    }
+    Ok(())
+}
+
+/// Adds `profile` to encoded image bytes, replacing any color space
+/// information already there
+pub fn embed(image: &[u8], format: OutputFormat, profile: &[u8]) -> Result<Vec<u8>> {
+    match format {
+        OutputFormat::Png => embed_png(image, profile),
+        OutputFormat::Jpeg => embed_jpeg(image, profile),
+        OutputFormat::WebP | OutputFormat::Qoi | OutputFormat::Tiff => Err(Error::Unsupported(
+            format!("embedding ICC profiles in {format} files"),
+        )),
+    }
+}
+
+fn embed_png(image: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    compressed.write_all(profile)?;
+    // Profile name, NUL, compression method 0 (zlib), data
+    let mut iccp = b"ICC profile\0\0".to_vec();
+    iccp.extend(compressed.finish()?);
+
+    let mut out = PNG_SIGNATURE.to_vec();
+    for chunk in png_chunks(image)? {
+        // sRGB, gAMA and cHRM would contradict or override the profile
+        if !matches!(chunk.kind(), b"iCCP" | b"sRGB" | b"gAMA" | b"cHRM") {
+            out.extend_from_slice(chunk.bytes);
+        }
+        if chunk.kind() == b"IHDR" {
+            write_png_chunk(&mut out, b"iCCP", &iccp);
+        }
+    }
+    Ok(out)
+}
+
+/// Largest profile slice in one APP2 segment: the 16-bit segment length
+/// covers itself, the 12-byte tag and the two sequence bytes
+const JPEG_CHUNK: usize = 65_535 - 2 - 14;
+
+fn embed_jpeg(image: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    let malformed = || Error::invalid("JPEG", "malformed segment layout");
+    let body = image.strip_prefix(&[0xff, 0xd8]).ok_or_else(malformed)?;
+    let chunks: Vec<&[u8]> = profile.chunks(JPEG_CHUNK).collect();
+    let count = u8::try_from(chunks.len())
+        .map_err(|_| Error::invalid("ICC profile", "too large for JPEG"))?;
+
+    let mut out = vec![0xff, 0xd8];
+    let mut rest = body;
+    // Keep leading JFIF/EXIF APPn segments first, dropping old profiles
+    while let [0xff, marker @ 0xe0..=0xef, high, low, ..] = rest {
+        let length = u16::from_be_bytes([*high, *low]) as usize;
+        let segment = rest.get(..length + 2).ok_or_else(malformed)?;
+        let is_icc = *marker == 0xe2 && segment.get(4..16) == Some(b"ICC_PROFILE\0");
+        if !is_icc {
+            out.extend_from_slice(segment);
+        }
+        rest = &rest[segment.len()..];
+    }
+    for (index, chunk) in chunks.iter().enumerate() {
+        out.extend([0xff, 0xe2]);
+        out.extend(((chunk.len() + 16) as u16).to_be_bytes());
+        out.extend(b"ICC_PROFILE\0");
+        out.extend([index as u8 + 1, count]);
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(rest);
+    Ok(out)
+}
+
+/// The ICC profile assigned to a display, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+///
+/// On X11 this is the `_ICC_PROFILE` root window property that colord and
+/// most color managers publish.
+#[cfg(target_os = "linux")]
+pub fn display_profile(display_id: u32) -> Result<Option<Vec<u8>>> {
+    use screenshots::display_info::DisplayInfo;
+    use xcb::x;
+
+    let unavailable = |e: &dyn std::fmt::Display| Error::Unsupported(format!("ICC profile: {e}"));
+    let index = DisplayInfo::all()
+        .map_err(|e| unavailable(&e))?
+        .iter()
+        .position(|info| info.id == display_id)
+        .ok_or_else(|| Error::invalid("display id", display_id.to_string()))?;
+    let (conn, screen_num) = xcb::Connection::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("ICC profile: no X screen".into()))?
+        .root();
+
+    // The first output's profile is `_ICC_PROFILE`, the others are suffixed
+    let name = match index {
+        0 => "_ICC_PROFILE".to_owned(),
+        n => format!("_ICC_PROFILE_{n}"),
+    };
+    let atom = conn
+        .wait_for_reply(conn.send_request(&x::InternAtom {
+            only_if_exists: true,
+            name: name.as_bytes(),
+        }))
+        .map_err(|e| unavailable(&e))?
+        .atom();
+    if atom == x::ATOM_NONE {
+        return Ok(None);
+    }
+    let reply = conn
+        .wait_for_reply(conn.send_request(&x::GetProperty {
+            delete: false,
+            window: root,
+            property: atom,
+            r#type: x::ATOM_ANY,
+            long_offset: 0,
+            long_length: u32::MAX / 4,
+        }))
+        .map_err(|e| unavailable(&e))?;
+    Ok((reply.format() == 8 && !reply.value::<u8>().is_empty())
+        .then(|| reply.value::<u8>().to_vec()))
+}
+
+/// The ICC profile assigned to a display, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+#[cfg(target_os = "windows")]
+pub fn display_profile(display_id: u32) -> Result<Option<Vec<u8>>> {
+    use screenshots::display_info::DisplayInfo;
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Graphics::Gdi::{
+        CreateDCW, DeleteDC, GetMonitorInfoW, HMONITOR, MONITORINFO, MONITORINFOEXW,
+    };
+    use windows::Win32::UI::ColorSystem::GetICMProfileW;
+
+    let info = DisplayInfo::all()
+        .map_err(|e| Error::Unsupported(format!("ICC profile: {e}")))?
+        .into_iter()
+        .find(|info| info.id == display_id)
+        .ok_or_else(|| Error::invalid("display id", display_id.to_string()))?;
+
+    let path = unsafe {
+        let mut monitor = MONITORINFOEXW::default();
+        monitor.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        let monitor_info = &mut monitor as *mut MONITORINFOEXW as *mut MONITORINFO;
+        if !GetMonitorInfoW(HMONITOR(info.raw_handle.0), monitor_info).as_bool() {
+            return Ok(None);
+        }
+        let device = PCWSTR(monitor.szDevice.as_ptr());
+        let dc = CreateDCW(device, device, PCWSTR::null(), None);
+        if dc.is_invalid() {
+            return Ok(None);
+        }
+        let mut buffer = [0u16; 260];
+        let mut len = buffer.len() as u32;
+        let found = GetICMProfileW(dc, &mut len, PWSTR(buffer.as_mut_ptr())).as_bool();
+        let _ = DeleteDC(dc);
+        if !found {
+            return Ok(None);
+        }
+        let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+        String::from_utf16_lossy(&buffer[..end])
+    };
+    Ok(Some(std::fs::read(path)?))
+}
+
+/// The ICC profile assigned to a display, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+#[cfg(target_os = "macos")]
+pub fn display_profile(display_id: u32) -> Result<Option<Vec<u8>>> {
+    use core_foundation::base::TCFType;
+    use core_foundation::data::{CFData, CFDataRef};
+    use std::ffi::c_void;
+
+    extern "C" {
+        fn CGDisplayCopyColorSpace(display: u32) -> *mut c_void;
+        fn CGColorSpaceCopyICCData(space: *mut c_void) -> CFDataRef;
+        fn CGColorSpaceRelease(space: *mut c_void);
+    }
+
+    unsafe {
+        let space = CGDisplayCopyColorSpace(display_id);
+        if space.is_null() {
+            return Ok(None);
+        }
+        let data = CGColorSpaceCopyICCData(space);
+        CGColorSpaceRelease(space);
+        if data.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(CFData::wrap_under_create_rule(data).bytes().to_vec()))
+    }
+}
+
+/// The ICC profile assigned to a display, by
+/// [`DisplayInfo`](screenshots::display_info::DisplayInfo) id
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn display_profile(_display_id: u32) -> Result<Option<Vec<u8>>> {
+    Err(Error::Unsupported(
+        "display color profiles are not available on this platform".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode_to_vec, EncodeOptions};
+    use screenshots::image::{Rgba, RgbaImage};
+
+    /// A minimal profile: a valid header and nothing else
+    fn profile(size: usize) -> Vec<u8> {
+        let mut profile = vec![0u8; size];
+        profile[..4].copy_from_slice(&(size as u32).to_be_bytes());
+        profile[36..40].copy_from_slice(b"acsp");
+        for (i, byte) in profile.iter_mut().enumerate().skip(128) {
+            *byte = (i * 7) as u8;
+        }
+        profile
+    }
+
+    fn frame() -> RgbaImage {
+        RgbaImage::from_pixel(8, 8, Rgba([200, 30, 90, 255]))
+    }
+
+    #[test]
+    fn test_png_profile_round_trips() {
+        let png = encode_to_vec(&frame(), &EncodeOptions::new(OutputFormat::Png)).unwrap();
+        let icc = profile(3000);
+        let tagged = embed(&png, OutputFormat::Png, &icc).unwrap();
+
+        let mut decoder = png::Decoder::new(tagged.as_slice()).read_info().unwrap();
+        assert_eq!(
+            decoder.info().icc_profile.as_deref(),
+            Some(icc.as_slice()),
+            "iCCP decodes back"
+        );
+        let mut pixels = vec![0; decoder.output_buffer_size()];
+        decoder.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, frame().into_raw(), "Pixels untouched");
+
+        let again = embed(&tagged, OutputFormat::Png, &profile(200)).unwrap();
+        let count = again.windows(4).filter(|w| w == b"iCCP").count();
+        assert_eq!(count, 1, "Replaces the old profile");
+    }
+
+    #[test]
+    fn test_jpeg_profile_is_split_into_segments() {
+        let jpeg = encode_to_vec(&frame(), &EncodeOptions::new(OutputFormat::Jpeg)).unwrap();
+        let icc = profile(150_000);
+        let tagged = embed(&jpeg, OutputFormat::Jpeg, &icc).unwrap();
+
+        let segments: Vec<usize> = tagged
+            .windows(12)
+            .enumerate()
+            .filter(|(_, w)| *w == b"ICC_PROFILE\0")
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(segments.len(), 3, "150 kB needs three APP2 segments");
+        assert_eq!(&tagged[segments[2] + 12..segments[2] + 14], [3, 3]);
+        assert_eq!(&tagged[..2], [0xff, 0xd8]);
+        assert!(
+            screenshots::image::load_from_memory(&tagged).is_ok(),
+            "Still decodes"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&profile(128)).is_ok());
+        assert!(validate(&profile(128)[..100]).is_err(), "Too short");
+        let mut wrong = profile(200);
+        wrong[36] = b'x';
+        assert!(validate(&wrong).is_err(), "Bad signature");
+        assert!(embed(b"", OutputFormat::Qoi, &profile(128)).is_err());
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            "display".parse::<ProfileSource>().unwrap(),
+            ProfileSource::Display
+        );
+        assert_eq!(
+            "p3.icc".parse::<ProfileSource>().unwrap(),
+            ProfileSource::File("p3.icc".into())
+        );
+    }
+}
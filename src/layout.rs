@@ -0,0 +1,103 @@
+//! Date-based output directories
+//!
+//! Long watch sessions pile thousands of captures into one directory. A
+//! [`DateLayout`] files each capture under subdirectories named after its
+//! capture time, `target/2024/05/01/shot.png` with the default `%Y/%m/%d`,
+//! so they are organized chronologically without an external sorter.
+
+use crate::{Error, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// `strftime` format used by [`DateLayout::default`]
+pub const DEFAULT_DATE_LAYOUT: &str = "%Y/%m/%d";
+
+/// Subdirectories inserted between an output path's directory and its file
+/// name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateLayout {
+    format: String,
+}
+
+impl Default for DateLayout {
+    fn default() -> Self {
+        Self {
+            format: DEFAULT_DATE_LAYOUT.to_owned(),
+        }
+    }
+}
+
+impl DateLayout {
+    /// A layout from a `strftime` format whose `/`-separated parts become
+    /// directories, such as `%Y-%m/%d`
+    pub fn new(format: impl Into<String>) -> Result<Self> {
+        let format = format.into();
+        let relative = Path::new(&format)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if format.is_empty()
+            || !relative
+            || StrftimeItems::new(&format).any(|item| item == Item::Error)
+        {
+            return Err(Error::invalid("date layout", format));
+        }
+        Ok(Self { format })
+    }
+
+    /// Where a capture taken at `time` and meant for `path` goes
+    pub fn path(&self, path: &Path, time: DateTime<Local>) -> PathBuf {
+        let dirs = time.format(&self.format).to_string();
+        let file = path.file_name().unwrap_or_default();
+        path.with_file_name(dirs).join(file)
+    }
+}
+
+impl FromStr for DateLayout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_default_layout_nests_year_month_day() {
+        let layout = DateLayout::default();
+        assert_eq!(
+            layout.path(Path::new("target/watch/0-000001.png"), time()),
+            PathBuf::from("target/watch/2024/05/01/0-000001.png")
+        );
+        assert_eq!(
+            layout.path(Path::new("shot.png"), time()),
+            PathBuf::from("2024/05/01/shot.png"),
+            "Relative to the working directory"
+        );
+    }
+
+    #[test]
+    fn test_custom_layout() {
+        let layout: DateLayout = "%Y-%m/%H".parse().unwrap();
+        assert_eq!(
+            layout.path(Path::new("target/shot.png"), time()),
+            PathBuf::from("target/2024-05/12/shot.png")
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_layouts() {
+        for format in ["%Y/%", "/%Y", "../%Y", "%Y/../x", ""] {
+            assert!(DateLayout::new(format).is_err(), "{format:?}");
+        }
+    }
+}
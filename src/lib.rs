@@ -0,0 +1,99 @@
+//! Display-aware screen capture toolkit
+//!
+//! The binary in `src/main.rs` is a thin front-end over this library; every
+//! piece of capture, scaling and encoding logic lives here so it can be reused
+//! and tested without a real screen.
+
+pub mod analysis;
+pub mod annotate;
+pub mod arrangement;
+pub mod atomic;
+pub mod backend;
+pub mod batch;
+pub mod beautify;
+pub mod buffer;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+pub mod color;
+pub mod color_mode;
+pub mod config;
+pub mod control;
+pub mod corners;
+pub mod cursor;
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+pub mod dbus;
+pub mod diff;
+pub mod encode;
+pub mod error;
+pub mod eyedropper;
+pub mod focus;
+#[cfg(feature = "frames")]
+pub mod frame;
+pub mod freeze;
+pub mod geometry;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hash;
+pub mod hdr;
+pub mod hooks;
+pub mod hotplug;
+pub mod icc;
+pub mod layout;
+pub mod locate;
+pub mod magnify;
+pub mod mask;
+pub mod metadata;
+pub mod metrics;
+pub mod monitor;
+pub mod mqtt;
+pub mod notify;
+pub mod ocr;
+#[cfg(feature = "optimize")]
+pub mod optimize;
+pub mod preset;
+pub mod preview;
+pub mod quantize;
+pub mod redact;
+pub mod regression;
+pub mod resize;
+pub mod retention;
+pub mod retry;
+#[cfg(feature = "review")]
+pub mod review;
+pub mod rules;
+pub mod scaling;
+#[cfg(feature = "scan")]
+pub mod scan;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod scroll;
+pub mod select;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod soak;
+pub mod source;
+pub mod srgb;
+pub mod stitch;
+pub mod tile;
+pub mod timeout;
+pub mod transform;
+#[cfg(all(feature = "tray", target_os = "linux"))]
+pub mod tray;
+pub mod trim;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod upload;
+pub mod window;
+
+pub use color::Color;
+pub use encode::{encode, EncodeOptions, OutputFormat};
+pub use error::{Error, Result};
+pub use geometry::{Anchor, AspectRatio, CoordinateMapper, Extent, Region, Rotation};
+pub use scaling::ScalingConfig;
+pub use stitch::StitchLayout;
+pub use transform::{Pipeline, Rgba16Image, Transform};
@@ -0,0 +1,366 @@
+//! Finding where an image appears in another
+//!
+//! [`locate`] slides the needle over the haystack and scores every position
+//! by the normalized cross-correlation of their luma: 1 where the pixels
+//! match up to brightness and contrast, around 0 where they're unrelated.
+//! Sums over each window come from integral images, and needles big enough
+//! are first searched for in copies of both shrunk by up to 8 times, so only
+//! the neighborhoods of the promising positions are scored in full.
+
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+
+/// Smallest side a needle is shrunk to for the coarse search
+const MIN_COARSE_SIDE: u32 = 12;
+const MAX_COARSE_FACTOR: u32 = 8;
+/// How much lower than the threshold a coarse score may be and still be
+/// looked at in full; shrinking blurs matches that don't sit on its grid
+const COARSE_SLACK: f32 = 0.25;
+
+/// Where the needle's top-left pixel goes in the haystack, and how well it
+/// matches there, up to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub x: u32,
+    pub y: u32,
+    pub score: f32,
+}
+
+/// Every place `needle` appears in `haystack` with a score of at least
+/// `threshold`, best first
+///
+/// Matches overlapping a better one by more than half the needle's width
+/// and height are dropped. A needle larger than the haystack appears
+/// nowhere, and one of a single color everywhere, so it's refused.
+pub fn locate(haystack: &RgbaImage, needle: &RgbaImage, threshold: f32) -> Result<Vec<Match>> {
+    let (width, height) = needle.dimensions();
+    if width == 0 || height == 0 {
+        return Err(Error::invalid("needle", "it has no pixels"));
+    }
+    if width > haystack.width() || height > haystack.height() {
+        return Ok(Vec::new());
+    }
+    let (hay, needle) = (Luma::of(haystack), Luma::of(needle));
+    let template = Template::new(&needle)
+        .ok_or_else(|| Error::invalid("needle", "a single color matches anywhere"))?;
+
+    let mut factor = 1;
+    while factor < MAX_COARSE_FACTOR && width.min(height) / (factor * 2) > MIN_COARSE_SIDE {
+        factor *= 2;
+    }
+    let fine = Search::new(&hay, &template);
+    let matches = match coarse(&hay, &needle, factor, threshold - COARSE_SLACK) {
+        // Close to where the coarse search put them, for matches that
+        // aren't exact copies
+        Some(candidates) => suppress(candidates, width / 4, height / 4)
+            .into_iter()
+            .filter_map(|candidate| {
+                let xs = candidate.x.saturating_sub(1)..=(candidate.x + 1).min(hay.width - width);
+                let ys = candidate.y.saturating_sub(1)..=(candidate.y + 1).min(hay.height - height);
+                ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| fine.at(x, y))
+                    .max_by(|a, b| a.score.total_cmp(&b.score))
+            })
+            .collect(),
+        None => fine.all(threshold),
+    };
+    Ok(suppress(
+        matches
+            .into_iter()
+            .filter(|m| m.score >= threshold)
+            .collect(),
+        width / 2,
+        height / 2,
+    ))
+}
+
+/// Positions in `hay` where `needle` scores at least `threshold` with both
+/// shrunk by `factor`, or `None` when a full search is due
+///
+/// A copy of the needle seldom starts on a block edge, so it gets shrunk
+/// once for every offset into its first block: the one matching a copy's
+/// offset lines up with the haystack's blocks exactly.
+fn coarse(hay: &Luma, needle: &Luma, factor: u32, threshold: f32) -> Option<Vec<Match>> {
+    if factor == 1 {
+        return None;
+    }
+    let shrunk = hay.shrink(factor);
+    let phases: Vec<(u32, u32)> = (0..factor)
+        .flat_map(|dy| (0..factor).map(move |dx| (dx, dy)))
+        .collect();
+    let templates: Vec<Luma> = phases
+        .iter()
+        .map(|&(dx, dy)| needle.crop(dx, dy).shrink(factor))
+        .collect();
+    let templates = templates
+        .iter()
+        .map(Template::new)
+        .collect::<Option<Vec<_>>>()?;
+    // The phases are independent, so they're spread over the cores
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let candidates = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let (shrunk, phases, templates) = (&shrunk, &phases, &templates);
+                scope.spawn(move || {
+                    let mut found = Vec::new();
+                    for i in (worker..phases.len()).step_by(threads) {
+                        let (dx, dy) = phases[i];
+                        let search = Search::new(shrunk, &templates[i]);
+                        found.extend(search.all(threshold).into_iter().filter_map(|m| {
+                            Some(Match {
+                                x: (m.x * factor).checked_sub(dx)?,
+                                y: (m.y * factor).checked_sub(dy)?,
+                                score: m.score,
+                            })
+                        }));
+                    }
+                    found
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a locate worker panicked"))
+            .collect()
+    });
+    Some(candidates)
+}
+
+/// `matches` best first, without those within `dx` and `dy` of a better one
+fn suppress(mut matches: Vec<Match>, dx: u32, dy: u32) -> Vec<Match> {
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<Match> = Vec::new();
+    for m in matches {
+        if !kept
+            .iter()
+            .any(|k| k.x.abs_diff(m.x) <= dx && k.y.abs_diff(m.y) <= dy)
+        {
+            kept.push(m);
+        }
+    }
+    kept
+}
+
+/// An image's luma, 0-255
+struct Luma {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl Luma {
+    fn of(image: &RgbaImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            values: image
+                .pixels()
+                .map(|p| 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32)
+                .collect(),
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// Everything right of `x` and below `y`
+    fn crop(&self, x: u32, y: u32) -> Self {
+        let (width, height) = (self.width - x, self.height - y);
+        let values = (y..self.height)
+            .flat_map(|row| (x..self.width).map(move |column| self.get(column, row)))
+            .collect();
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+
+    /// Each `factor`x`factor` block averaged into one value; the edges that
+    /// don't fill a block are dropped
+    fn shrink(&self, factor: u32) -> Self {
+        let (width, height) = (self.width / factor, self.height / factor);
+        let mut values = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for dy in 0..factor {
+                    for dx in 0..factor {
+                        sum += self.get(x * factor + dx, y * factor + dy);
+                    }
+                }
+                values.push(sum / (factor * factor) as f32);
+            }
+        }
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+}
+
+/// A needle's luma less its mean, and the norm of that
+struct Template<'a> {
+    luma: &'a Luma,
+    mean: f32,
+    norm: f64,
+}
+
+impl<'a> Template<'a> {
+    /// `None` for a needle of one value, which correlates with nothing
+    fn new(luma: &'a Luma) -> Option<Self> {
+        let mean = luma.values.iter().sum::<f32>() / luma.values.len() as f32;
+        let norm = luma
+            .values
+            .iter()
+            .map(|&v| ((v - mean) as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        (norm > 1e-6).then_some(Self { luma, mean, norm })
+    }
+}
+
+/// A needle to score at positions of one haystack
+struct Search<'a> {
+    hay: &'a Luma,
+    template: &'a Template<'a>,
+    /// Summed-area tables of the haystack's values and their squares, one
+    /// row and column larger than it
+    sums: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl<'a> Search<'a> {
+    fn new(hay: &'a Luma, template: &'a Template<'a>) -> Self {
+        let stride = hay.width as usize + 1;
+        let mut sums = vec![0.0; stride * (hay.height as usize + 1)];
+        let mut squares = sums.clone();
+        for y in 0..hay.height as usize {
+            let (mut row, mut row_squares) = (0.0, 0.0);
+            for x in 0..hay.width as usize {
+                let v = hay.values[y * hay.width as usize + x] as f64;
+                row += v;
+                row_squares += v * v;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+                squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_squares;
+            }
+        }
+        Self {
+            hay,
+            template,
+            sums,
+            squares,
+        }
+    }
+
+    /// Sum of `table` over the needle-sized window at `x`,`y`
+    fn window(&self, table: &[f64], x: u32, y: u32) -> f64 {
+        let stride = self.hay.width as usize + 1;
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (
+            x0 + self.template.luma.width as usize,
+            y0 + self.template.luma.height as usize,
+        );
+        table[y1 * stride + x1] - table[y0 * stride + x1] - table[y1 * stride + x0]
+            + table[y0 * stride + x0]
+    }
+
+    fn at(&self, x: u32, y: u32) -> Match {
+        let needle = self.template.luma;
+        let n = needle.values.len() as f64;
+        let sum = self.window(&self.sums, x, y);
+        let variance = self.window(&self.squares, x, y) - sum * sum / n;
+        // A flat window correlates with nothing
+        let score = if variance <= 1e-6 {
+            0.0
+        } else {
+            let mut product = 0.0;
+            for ny in 0..needle.height {
+                let row = ((y + ny) * self.hay.width + x) as usize;
+                let hay = &self.hay.values[row..row + needle.width as usize];
+                let start = (ny * needle.width) as usize;
+                let tpl = &needle.values[start..start + needle.width as usize];
+                product += hay
+                    .iter()
+                    .zip(tpl)
+                    .map(|(&h, &t)| h as f64 * (t - self.template.mean) as f64)
+                    .sum::<f64>();
+            }
+            (product / (variance.sqrt() * self.template.norm)) as f32
+        };
+        Match { x, y, score }
+    }
+
+    /// Every position scoring at least `threshold`
+    fn all(&self, threshold: f32) -> Vec<Match> {
+        let needle = self.template.luma;
+        if needle.width > self.hay.width || needle.height > self.hay.height {
+            return Vec::new();
+        }
+        (0..=self.hay.height - needle.height)
+            .flat_map(|y| (0..=self.hay.width - needle.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.at(x, y))
+            .filter(|m| m.score >= threshold)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::{imageops, Rgba};
+
+    /// Noise that's the same every run
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let h = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663))
+                .wrapping_mul(2_654_435_761);
+            let v = (h >> 24) as u8;
+            Rgba([v, v.wrapping_mul(3), v ^ 0x5a, 255])
+        })
+    }
+
+    #[test]
+    fn test_finds_every_copy() {
+        let mut haystack = noise(160, 120);
+        let needle = imageops::crop_imm(&haystack, 37, 21, 30, 26).to_image();
+        imageops::replace(&mut haystack, &needle, 101, 77);
+
+        let matches = locate(&haystack, &needle, 0.9).unwrap();
+        assert_eq!(matches.len(), 2, "{matches:?}");
+        let mut spots: Vec<_> = matches.iter().map(|m| (m.x, m.y)).collect();
+        spots.sort();
+        assert_eq!(spots, [(37, 21), (101, 77)]);
+        assert!(matches.iter().all(|m| m.score > 0.999), "{matches:?}");
+    }
+
+    #[test]
+    fn test_small_needles_are_searched_in_full() {
+        let haystack = noise(40, 30);
+        let needle = imageops::crop_imm(&haystack, 5, 9, 6, 6).to_image();
+        let matches = locate(&haystack, &needle, 0.95).unwrap();
+        assert_eq!(matches.first().map(|m| (m.x, m.y)), Some((5, 9)));
+
+        // Brighter, but the same shapes
+        let brighter = RgbaImage::from_fn(6, 6, |x, y| {
+            let p = needle.get_pixel(x, y);
+            Rgba([p[0] / 2 + 100, p[1] / 2 + 100, p[2] / 2 + 100, 255])
+        });
+        let matches = locate(&haystack, &brighter, 0.95).unwrap();
+        assert_eq!(matches.first().map(|m| (m.x, m.y)), Some((5, 9)));
+    }
+
+    #[test]
+    fn test_needles_that_cant_match() {
+        let haystack = noise(20, 20);
+        assert!(locate(&haystack, &noise(21, 4), 0.5).unwrap().is_empty());
+        let flat = RgbaImage::from_pixel(4, 4, Rgba([9, 9, 9, 255]));
+        assert!(matches!(
+            locate(&haystack, &flat, 0.5),
+            Err(Error::Invalid { .. })
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Close-ups for pixel-peeping
+//!
+//! [`Magnifier`] blows a small capture up with nearest-neighbor scaling, so
+//! each pixel becomes a crisp square instead of a blur, rules a grid between
+//! the squares and outlines the center one, with its hex value in a strip
+//! underneath.
+//! `snap_scale magnify` points it at the pixels around the cursor.
+
+use crate::annotate::{blend, draw_text, measure_text, TextStyle};
+use crate::color::Color;
+use screenshots::image::{imageops, Rgba, RgbaImage};
+
+/// Screen pixels per side of each magnified square by default
+pub const DEFAULT_ZOOM: u32 = 12;
+
+/// The grid gets in the way below this zoom
+const MIN_GRID_ZOOM: u32 = 4;
+
+/// Around the hex value in the strip under the close-up
+const LABEL_PADDING: u32 = 4;
+
+/// Draws magnified close-ups
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Magnifier {
+    /// Side of each magnified pixel, at least 1
+    pub zoom: u32,
+    /// Lines between the pixels, or none
+    pub grid: Option<Color>,
+    /// Outline the center pixel and add its hex value below the close-up
+    pub label: bool,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_ZOOM)
+    }
+}
+
+impl Magnifier {
+    /// A magnifier with a faint grid and the center labelled
+    pub fn new(zoom: u32) -> Self {
+        Self {
+            zoom: zoom.max(1),
+            grid: Some(Color::new(0, 0, 0, 64)),
+            label: true,
+        }
+    }
+
+    pub fn with_grid(mut self, grid: Option<Color>) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    pub fn with_label(mut self, label: bool) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// The pixel the close-up centers on
+    pub fn center(image: &RgbaImage) -> (u32, u32) {
+        (image.width() / 2, image.height() / 2)
+    }
+
+    /// The magnified close-up of `image`
+    pub fn magnify(&self, image: &RgbaImage) -> RgbaImage {
+        let zoom = self.zoom.max(1);
+        let mut out = imageops::resize(
+            image,
+            image.width() * zoom,
+            image.height() * zoom,
+            imageops::FilterType::Nearest,
+        );
+        if let Some(color) = self.grid.filter(|_| zoom >= MIN_GRID_ZOOM) {
+            for (x, y, pixel) in out.enumerate_pixels_mut() {
+                if x % zoom == 0 || y % zoom == 0 {
+                    blend(pixel, color, 1.0);
+                }
+            }
+        }
+        if self.label && !image.is_empty() {
+            out = self.label_center(image, out);
+        }
+        out
+    }
+
+    /// Outlines the center pixel and adds a strip with its hex value below
+    fn label_center(&self, image: &RgbaImage, mut out: RgbaImage) -> RgbaImage {
+        let zoom = self.zoom.max(1);
+        let (cx, cy) = Self::center(image);
+        let color = Color::from(*image.get_pixel(cx, cy));
+        // Black around light pixels and white around dark ones
+        let luma = 0.299 * color.r as f32 + 0.587 * color.g as f32 + 0.114 * color.b as f32;
+        let ink = if luma > 128.0 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+        let thickness = (zoom / 6).max(1);
+        let (left, top) = (cx * zoom, cy * zoom);
+        for y in top.saturating_sub(thickness)..(top + zoom + thickness).min(out.height()) {
+            for x in left.saturating_sub(thickness)..(left + zoom + thickness).min(out.width()) {
+                let inside = (left..left + zoom).contains(&x) && (top..top + zoom).contains(&y);
+                if !inside {
+                    blend(out.get_pixel_mut(x, y), ink, 1.0);
+                }
+            }
+        }
+
+        let style = TextStyle {
+            size: 16.0,
+            color: Color::WHITE,
+            outline: None,
+            background: None,
+            ..TextStyle::default()
+        };
+        let text = color.hex();
+        let (width, height) = measure_text(&text, &style);
+        let strip = height + 2 * LABEL_PADDING;
+        let mut labelled = RgbaImage::from_pixel(
+            out.width().max(width + 2 * LABEL_PADDING),
+            out.height() + strip,
+            Rgba([32, 32, 32, 255]),
+        );
+        // Centered over a strip wider than itself
+        let left = (labelled.width() - out.width()) / 2;
+        imageops::replace(&mut labelled, &out, left as i64, 0);
+        let x = (labelled.width() - width) / 2;
+        draw_text(
+            &mut labelled,
+            &text,
+            x as i32,
+            (out.height() + LABEL_PADDING) as i32,
+            &style,
+        );
+        labelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> RgbaImage {
+        RgbaImage::from_fn(5, 5, |x, y| Rgba([x as u8 * 50, y as u8 * 50, 200, 255]))
+    }
+
+    #[test]
+    fn test_magnify_keeps_pixels_crisp() {
+        let image = sample();
+        let out = Magnifier::new(8)
+            .with_grid(None)
+            .with_label(false)
+            .magnify(&image);
+        assert_eq!(out.dimensions(), (40, 40));
+        for (x, y) in [(0, 0), (7, 7), (8, 0), (39, 39), (17, 30)] {
+            assert_eq!(
+                out.get_pixel(x, y),
+                image.get_pixel(x / 8, y / 8),
+                "{x},{y}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_grid_lines() {
+        let image = RgbaImage::from_pixel(3, 3, Rgba([255, 255, 255, 255]));
+        let grid = Some(Color::BLACK);
+        let out = Magnifier::new(4)
+            .with_grid(grid)
+            .with_label(false)
+            .magnify(&image);
+        assert_eq!(*out.get_pixel(4, 1), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(1, 8), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(5, 5), Rgba([255, 255, 255, 255]));
+
+        let small = Magnifier::new(2).with_grid(grid).with_label(false);
+        assert!(small.magnify(&image).pixels().all(|p| p.0[0] == 255));
+    }
+
+    #[test]
+    fn test_center_is_outlined_and_labelled() {
+        let image = sample();
+        let plain = Magnifier::new(24).with_grid(None).with_label(false);
+        let labelled = Magnifier::new(24).with_grid(None);
+        let (plain, labelled) = (plain.magnify(&image), labelled.magnify(&image));
+
+        assert_eq!(Magnifier::center(&image), (2, 2));
+        assert_eq!(labelled.width(), 120);
+        assert!(labelled.height() > 120, "Room for the hex value");
+        // The center pixel is left alone, with its outline drawn around it
+        assert_eq!(labelled.get_pixel(60, 60), image.get_pixel(2, 2));
+        assert_ne!(labelled.get_pixel(46, 60), plain.get_pixel(46, 60));
+        assert_eq!(labelled.get_pixel(2, 2), plain.get_pixel(2, 2));
+        let strip = (0..120).map(|x| *labelled.get_pixel(x, 120 + LABEL_PADDING + 6));
+        assert!(strip.clone().any(|p| p == Rgba([255, 255, 255, 255])));
+        assert!(strip.clone().any(|p| p == Rgba([32, 32, 32, 255])));
+
+        // Narrow close-ups sit in the middle of the strip
+        let narrow = Magnifier::new(4).with_grid(None).magnify(&image);
+        assert_eq!(narrow.width(), 120);
+        assert_eq!(narrow.get_pixel(50, 0), image.get_pixel(0, 0));
+    }
+}
@@ -1,55 +1,4430 @@
-use screenshots::{display_info::DisplayInfo, image::RgbaImage, Screen};
-use std::time::Instant;
+use anyhow::Context;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use screenshots::image::{DynamicImage, ImageFormat, ImageOutputFormat, RgbaImage};
+use snap_scale::annotate::{
+    Annotator, Caption, Font, Position, Shape, TextStyle, Timestamp, DEFAULT_TIMESTAMP_FORMAT,
+};
+use snap_scale::backend::{default_backend, CaptureBackend};
+use snap_scale::batch::{BatchResult, BatchStatus};
+use snap_scale::beautify::Beautifier;
+use snap_scale::buffer::Frame;
+use snap_scale::color_mode::ColorMode;
+use snap_scale::config::{BeautifyConfig, Config};
+use snap_scale::control::Target;
+use snap_scale::corners::RoundedCorners;
+use snap_scale::diff::{compare, DiffOptions};
+use snap_scale::encode::data_uri;
+use snap_scale::eyedropper::{Eyedropper, Sample};
+use snap_scale::focus::FocusTracker;
+use snap_scale::hash::{Deduplicator, HashAlgorithm};
+use snap_scale::hdr::{Operator, ToneMapper, SCRGB_WHITE_NITS};
+use snap_scale::hooks::{run_hook, run_hook_to_stderr, HookContext};
+use snap_scale::hotplug::DisplayWatcher;
+use snap_scale::icc::ProfileSource;
+use snap_scale::layout::{DateLayout, DEFAULT_DATE_LAYOUT};
+use snap_scale::mask::Mask;
+use snap_scale::metadata::{CaptureMetadata, DisplayDescriptor, Sidecar, Trigger};
+use snap_scale::metrics::METRICS;
+use snap_scale::monitor::Monitor;
+use snap_scale::preset::Preset;
+use snap_scale::quantize::Palette;
+use snap_scale::regression::Regression;
+use snap_scale::resize::{Filter, Resize, Size};
+use snap_scale::retention::Retention;
+use snap_scale::rules::RuleEngine;
+use snap_scale::schedule::{Job, Scheduler};
+use snap_scale::select::DisplaySelector;
+use snap_scale::soak::SoakHarness;
+use snap_scale::srgb::ToSrgb;
+use snap_scale::trim::Trim;
+use snap_scale::upload::{parse_header, uploader_for, Uploader};
+use snap_scale::window::Decorations;
+use snap_scale::{
+    Anchor, AspectRatio, Color, CoordinateMapper, EncodeOptions, Extent, OutputFormat, Pipeline,
+    Region, Rgba16Image, ScalingConfig, Transform,
+};
+use std::cell::{Cell, OnceCell};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+/// How often long-running modes look for display changes
+const HOTPLUG_INTERVAL: Duration = Duration::from_secs(2);
+
+/// PNG and JPEG captures larger than this, in pixels, are encoded straight
+/// into their file rather than in memory first: a stitch of three 4K
+/// displays is about 25 million
+const STREAM_PIXELS: u64 = 16_000_000;
+
+/// Set by `--dry-run`: captures are blank frames of the size they'd have
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Display-aware screenshot tool
+#[derive(Debug, Parser)]
+#[command(name = "snap_scale", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Config file (defaults to $SNAP_SCALE_CONFIG or the user config dir)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Most verbose log level on stderr: error, warn, info (each capture
+    /// with its timing), debug (every pipeline stage) or trace
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "warn")]
+    log_level: tracing::Level,
+
+    /// Log as plain text or as one JSON object per line
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Print one JSON object per line on stdout instead of text: one per
+    /// saved capture, or per entry of what the command lists
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print what would be captured and written, with the displays, areas,
+    /// scaling and paths resolved, without capturing or writing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Seconds, or e.g. `30s` or `2m`, a capture may take before it fails,
+    /// overriding `[capture] timeout_secs`; 0 waits forever, e.g. for a
+    /// portal dialog. `wait-for` gives up after it too
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<u64>,
+
+    /// Open each capture in a window to save, copy, annotate or discard it
+    /// before it's written
+    #[cfg(feature = "review")]
+    #[arg(long, global = true)]
+    review: bool,
+
+    /// Fail instead of saving a capture that's a single color throughout,
+    /// e.g. all black for want of permission to record the screen
+    #[arg(long, global = true)]
+    fail_on_blank: bool,
+
+    /// Show a desktop notification after each saved capture
+    #[arg(long, global = true, overrides_with = "no_notify")]
+    notify: bool,
+
+    /// Never show desktop notifications, whatever the config says
+    #[arg(long, global = true)]
+    no_notify: bool,
+
+    /// Shell command to run after each saved capture, in addition to the
+    /// `post_capture` hook; sees $SNAP_FILE, $SNAP_DISPLAY, $SNAP_WIDTH, $SNAP_HEIGHT
+    #[arg(long, global = true, value_name = "CMD")]
+    exec: Option<String>,
+
+    /// Upload each saved capture and print its URL (`imgur`, `discord`,
+    /// `slack`, an `http(s)://` webhook URL or `sftp://[user@]host/path`)
+    #[arg(long, global = true, value_name = "TARGET")]
+    upload: Option<String>,
+
+    /// Message posted with the capture by `--upload discord|slack`; `{file}`
+    /// expands to the file name
+    #[arg(long, global = true, value_name = "TEXT")]
+    message: Option<String>,
+
+    /// Recognize text in each capture and print it, or write it next to the
+    /// image as `<image>.txt` with `--ocr sidecar` (needs the `ocr` feature)
+    #[arg(long, global = true, value_enum, value_name = "OUTPUT", num_args = 0..=1, default_missing_value = "stdout")]
+    ocr: Option<OcrOutput>,
+
+    /// Extra header for webhook uploads, as `Name: value` (repeatable)
+    #[arg(long = "upload-header", global = true, value_name = "HEADER")]
+    upload_headers: Vec<String>,
+
+    /// Blur or pixelate an area of each capture before it is written or
+    /// uploaded, as `x,y,w,h[:blur|pixelate]` in image pixels (repeatable)
+    #[arg(long = "mask", global = true, value_name = "REGION")]
+    masks: Vec<Mask>,
+
+    /// Draw on each capture: `arrow:x1,y1,x2,y2`, `rect:x,y,w,h`,
+    /// `ellipse:x,y,w,h` or `highlight:x,y,w,h` in image pixels (repeatable)
+    #[arg(long = "draw", global = true, value_name = "SHAPE")]
+    shapes: Vec<Shape>,
+
+    /// Color of `--draw` arrows and outlines, as `#rrggbb[aa]`
+    #[arg(long, global = true, value_name = "COLOR", default_value = "#e62828")]
+    stroke_color: Color,
+
+    /// Line width of `--draw` arrows and outlines in pixels
+    #[arg(long, global = true, value_name = "PX", default_value_t = 4)]
+    stroke_width: u32,
+
+    /// Burn a text label into each capture
+    #[arg(long, global = true, value_name = "TEXT")]
+    caption: Option<String>,
+
+    /// Where `--caption` goes: top-left, top-right, bottom-left, bottom-right,
+    /// center or `x,y`
+    #[arg(
+        long,
+        global = true,
+        value_name = "POSITION",
+        default_value = "bottom-left"
+    )]
+    caption_position: Position,
+
+    /// TrueType/OpenType font for burned-in text; a built-in bitmap font
+    /// is used otherwise
+    #[arg(long, global = true, value_name = "PATH")]
+    font: Option<PathBuf>,
+
+    /// Height of burned-in text in pixels
+    #[arg(long, global = true, value_name = "PX", default_value_t = 16.0)]
+    font_size: f32,
+
+    /// Color of burned-in text, as `#rrggbb[aa]`
+    #[arg(long, global = true, value_name = "COLOR", default_value = "#ffffff")]
+    text_color: Color,
+
+    /// Stamp the capture time into each capture, with an optional `strftime`
+    /// format given as `--timestamp=FORMAT` (default `%Y-%m-%d %H:%M:%S`)
+    #[arg(long, global = true, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = DEFAULT_TIMESTAMP_FORMAT)]
+    timestamp: Option<String>,
+
+    /// Add the hostname to `--timestamp`
+    #[arg(long, global = true, requires = "timestamp")]
+    timestamp_host: bool,
+
+    /// Add the display id to `--timestamp`
+    #[arg(long, global = true, requires = "timestamp")]
+    timestamp_display: bool,
+
+    /// Where `--timestamp` goes, as for `--caption-position`
+    #[arg(
+        long,
+        global = true,
+        value_name = "POSITION",
+        default_value = "bottom-right"
+    )]
+    timestamp_position: Position,
+
+    /// Drop color from each capture: color, grayscale, mono or
+    /// `mono:THRESHOLD` (default 128); gray captures are saved single-channel
+    #[arg(long, global = true, value_name = "MODE", default_value = "color")]
+    color_mode: ColorMode,
+
+    /// Write PNGs palette-indexed with at most COLORS colors, optionally as
+    /// `--palette=COLORS` (default 256); much smaller for flat UIs
+    #[arg(long, global = true, value_name = "COLORS", num_args = 0..=1, require_equals = true, default_missing_value = "256", value_parser = clap::value_parser!(u16).range(2..=256))]
+    palette: Option<u16>,
+
+    /// Floyd–Steinberg dither when `--palette` has to merge colors
+    #[arg(long, global = true, requires = "palette")]
+    dither: bool,
+
+    /// Losslessly shrink saved PNGs with oxipng, optionally at
+    /// `--optimize=LEVEL` from 0 (fast) to 6 (smallest), default 2
+    #[cfg(feature = "optimize")]
+    #[arg(long, global = true, value_name = "LEVEL", num_args = 0..=1, require_equals = true, default_missing_value = "2", value_parser = clap::value_parser!(u8).range(0..=6))]
+    optimize: Option<u8>,
+
+    /// Recompress with Zopfli during `--optimize`: smaller but much slower
+    #[cfg(feature = "optimize")]
+    #[arg(long, global = true, requires = "optimize")]
+    zopfli: bool,
+
+    /// Stop `--optimize` after this long, keeping the best result so far
+    #[cfg(feature = "optimize")]
+    #[arg(long, global = true, value_name = "MS", requires = "optimize")]
+    optimize_budget: Option<u64>,
+
+    /// Embed the captured display's ICC color profile in PNG and JPEG
+    /// files, or a given profile as `--icc=PATH`
+    #[arg(long, global = true, value_name = "PATH", num_args = 0..=1, require_equals = true, default_missing_value = "display")]
+    icc: Option<ProfileSource>,
+
+    /// Convert captures from the display's color profile to sRGB, or from a
+    /// given profile as `--srgb=PATH`
+    #[arg(long, global = true, value_name = "PATH", num_args = 0..=1, require_equals = true, default_missing_value = "display", conflicts_with = "icc")]
+    srgb: Option<ProfileSource>,
+
+    /// Capture the HDR framebuffer of whole displays and tone-map it to SDR
+    /// with OPERATOR (`reinhard`, `aces` or `clip`) instead of clipping
+    #[arg(long, global = true, value_name = "OPERATOR", num_args = 0..=1, require_equals = true, default_missing_value = "reinhard")]
+    hdr: Option<Operator>,
+
+    /// Brightness SDR white is shown at for `--hdr`, in nits
+    #[arg(long, global = true, value_name = "NITS", default_value_t = SCRGB_WHITE_NITS, requires = "hdr")]
+    hdr_white: f32,
+
+    /// Bits per channel for `--hdr` captures; 16 keeps the extra precision
+    /// through resizing, trimming and `--color-mode` into PNG or TIFF
+    #[arg(
+        long,
+        global = true,
+        default_value = "8",
+        value_parser = PossibleValuesParser::new(["8", "16"]).map(|bits| bits.parse::<u8>().unwrap_or(8)),
+        requires = "hdr"
+    )]
+    depth: u8,
+
+    /// Embed capture metadata (display, position, scale, rotation, time,
+    /// version) in PNG text chunks or JPEG EXIF
+    #[arg(long, global = true)]
+    metadata: bool,
+
+    /// Write `<file>.json` next to each capture with the display, region,
+    /// scaling and timing it was taken with
+    #[arg(long, global = true)]
+    sidecar: bool,
+
+    /// Fail instead of overwriting a file that already exists
+    #[arg(long, global = true, conflicts_with = "auto_number")]
+    no_clobber: bool,
+
+    /// Save as `shot-1.png`, `shot-2.png`, … when `shot.png` already exists
+    #[arg(long, global = true)]
+    auto_number: bool,
+
+    /// File captures under directories named after the capture date,
+    /// `target/2024/05/01/` by default, or per a `strftime` format given as
+    /// `--date-dirs=FORMAT`
+    #[arg(long, global = true, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = DEFAULT_DATE_LAYOUT)]
+    date_dirs: Option<DateLayout>,
+
+    /// Tag recorded with each capture in the catalog; repeat for more tags
+    #[cfg(feature = "catalog")]
+    #[arg(long = "tag", global = true, value_name = "TAG")]
+    tags: Vec<String>,
+
+    /// Fail when the output directory is missing instead of creating it
+    #[arg(long, global = true)]
+    no_create_dirs: bool,
+
+    /// Save captures larger than PX on either side as a grid of PX×PX tiles
+    /// plus a `.tiles.json` manifest instead of one image
+    #[arg(long, global = true, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    tile: Option<u32>,
+
+    /// Constrain area captures to an aspect ratio such as `16:9`
+    #[arg(long, global = true, value_name = "W:H")]
+    aspect: Option<AspectRatio>,
+
+    /// Corner of the area that `--aspect` keeps fixed: top-left, top-right,
+    /// bottom-left, bottom-right or center
+    #[arg(
+        long,
+        global = true,
+        value_name = "ANCHOR",
+        default_value = "center",
+        requires = "aspect"
+    )]
+    aspect_anchor: Anchor,
+
+    /// Grow the area to reach `--aspect` instead of shrinking it
+    #[arg(long, global = true, requires = "aspect")]
+    aspect_expand: bool,
+
+    /// Crop uniform-color margins, such as the desktop around a window;
+    /// set the per-channel tolerance as `--trim=TOLERANCE` (default 8)
+    #[arg(long, global = true, value_name = "TOLERANCE", num_args = 0..=1, require_equals = true, default_missing_value = "8")]
+    trim: Option<u8>,
+
+    /// Round the corners of each capture with an alpha mask, optionally as
+    /// `--round-corners=RADIUS` (default 12)
+    #[arg(long, global = true, value_name = "PX", num_args = 0..=1, require_equals = true, default_missing_value = "12")]
+    round_corners: Option<u32>,
+
+    /// Trace `--round-corners` with a 1px border of this color
+    #[arg(long, global = true, value_name = "COLOR", requires = "round_corners")]
+    corner_border: Option<Color>,
+
+    /// Frame each capture with padding, a drop shadow and a background; pick
+    /// a `[beautify.PROFILE]` preset as `--beautify=PROFILE`
+    #[arg(long, global = true, value_name = "PROFILE", num_args = 0..=1, require_equals = true, default_missing_value = "default")]
+    beautify: Option<String>,
+
+    /// Place each capture in a device frame: browser, laptop or phone
+    #[cfg(feature = "frames")]
+    #[arg(long, global = true, value_name = "DEVICE")]
+    frame: Option<snap_scale::frame::Device>,
+
+    /// Address bar text for `--frame browser`
+    #[cfg(feature = "frames")]
+    #[arg(long, global = true, value_name = "URL", requires = "frame")]
+    frame_url: Option<String>,
+
+    /// Scale each capture before saving, as `50%`, `1280x`, `x720` or
+    /// `1280x720`
+    #[arg(long, global = true, value_name = "SIZE")]
+    resize: Option<Size>,
+
+    /// Resampling filter for `--resize`: nearest, bilinear or lanczos3
+    #[arg(long, global = true, value_name = "FILTER", default_value = "lanczos3")]
+    resize_filter: Filter,
+
+    /// Quality of JPEG captures, from 1 to 100
+    #[arg(long, global = true, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+
+    /// Format, quality, optimization and resize for what captures are for:
+    /// archival, share or quick; flags given as well win
+    #[arg(long, global = true, value_name = "PRESET")]
+    preset: Option<Preset>,
+}
+
+#[derive(Debug, Subcommand)]
+enum BaselineAction {
+    /// List baselines with the display and scale they were captured at
+    List,
+
+    /// Accept the capture from a failed check as the new baseline
+    Approve {
+        /// Baseline names, e.g. `login-dialog` or `dialogs/settings`
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Delete baselines that no check used since target/regression was cleared
+    Prune,
+}
+
+/// Retention limits of `watch` and `prune`
+#[derive(Debug, Args)]
+struct RetentionArgs {
+    /// Delete captures older than AGE, e.g. `12h` or `7d`
+    #[arg(long, value_name = "AGE", value_parser = snap_scale::retention::parse_age)]
+    max_age: Option<Duration>,
+
+    /// Keep only the newest N captures
+    #[arg(long, value_name = "N")]
+    max_count: Option<usize>,
+
+    /// Delete the oldest captures once all of them take more than SIZE, e.g.
+    /// `500M` or `2G`
+    #[arg(long, value_name = "SIZE", value_parser = snap_scale::retention::parse_size)]
+    max_size: Option<u64>,
+
+    /// Count only files whose names match GLOB (`*` and `?`) as captures,
+    /// instead of images named the way snap_scale names them
+    #[arg(long, value_name = "GLOB")]
+    name: Option<String>,
+
+    /// Count captures in subdirectories too
+    #[arg(long)]
+    recursive: bool,
+}
+
+impl RetentionArgs {
+    fn policy(&self) -> Retention {
+        Retention {
+            max_age: self.max_age,
+            max_count: self.max_count,
+            max_size: self.max_size,
+            name: self.name.clone(),
+            recursive: self.recursive,
+        }
+    }
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Where `--ocr` puts recognized text
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OcrOutput {
+    Stdout,
+    Sidecar,
+}
+
+/// What happens when a capture's path already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Clobber {
+    Overwrite,
+    /// `--no-clobber`
+    Refuse,
+    /// `--auto-number`
+    Number,
+}
+
+/// How a capture written to stdout is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Encoding {
+    /// The encoded image bytes
+    Raw,
+    /// Base64 of the image, on one line
+    Base64,
+    /// A `data:image/...;base64,` URI
+    DataUri,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Capture every display (the default when no command is given)
+    Capture {
+        /// Capture one display to this file instead, or to stdout for `-`
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Display for `--output`: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0")]
+        display: DisplaySelector,
+
+        /// Capture a WxH logical area centered on the mouse cursor, on the
+        /// display under it, to `--output` (`target/cursor.png` by default)
+        #[arg(long, value_name = "WxH", conflicts_with = "display")]
+        at_cursor: Option<Extent>,
+
+        /// Select the area to capture by pointing at two opposite corners,
+        /// pressing Enter at each, to `--output` (`target/selection.png` by
+        /// default)
+        #[arg(long, conflicts_with_all = ["display", "at_cursor"])]
+        select: bool,
+
+        /// Snapshot every display before `--select`, and cut the selection
+        /// from that, so menus and tooltips that close meanwhile still show
+        #[arg(long, requires = "select")]
+        freeze: bool,
+
+        /// Capture a small copy of the display, SCALE (up to 1) of its
+        /// physical size, to `--output` (`target/preview.png` by default);
+        /// much faster than capturing and resizing
+        #[arg(long, value_name = "SCALE", conflicts_with_all = ["at_cursor", "select"])]
+        preview: Option<f32>,
+
+        /// Format written to stdout by `--output -`
+        #[arg(long, default_value = "png")]
+        format: OutputFormat,
+
+        /// Print the capture as text instead of bytes; implies `--output -`
+        #[arg(long, value_enum, default_value = "raw")]
+        encoding: Encoding,
+    },
+
+    /// Run a Rhai capture script
+    #[cfg(feature = "scripting")]
+    Script {
+        /// Path to the `.rhai` file
+        path: PathBuf,
+    },
+
+    /// Capture one display repeatedly, saving each frame
+    Watch {
+        /// Display to capture: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0")]
+        display: DisplaySelector,
+
+        /// Delay between captures
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        interval: u64,
+
+        /// Stop after saving this many frames; runs until interrupted otherwise
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Directory the frames are written to
+        #[arg(long, default_value = "target/watch")]
+        dir: PathBuf,
+
+        /// Skip frames whose perceptual hash is within THRESHOLD bits of the
+        /// last saved frame
+        #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "4")]
+        dedupe: Option<u32>,
+
+        /// Hash used by `--dedupe`: ahash, dhash or phash
+        #[arg(long, default_value = "dhash")]
+        hash: HashAlgorithm,
+
+        /// Old frames to delete after each save
+        #[command(flatten)]
+        retention: RetentionArgs,
+
+        /// Report how resident memory and open handles grew over the
+        /// `--count` frames, and fail if they kept growing; for soak tests
+        /// against the mock backend
+        #[arg(long, requires = "count")]
+        soak: bool,
+
+        /// Serve Prometheus metrics at `http://ADDR/metrics`
+        #[cfg(feature = "serve")]
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<String>,
+    },
+
+    /// Capture a page taller than the screen while it scrolls, stitching
+    /// the screenfuls into one image
+    Scroll {
+        /// Display the page is on: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0")]
+        display: DisplaySelector,
+
+        /// Logical area of the display the page scrolls in, as `x,y,w,h`;
+        /// the whole display by default
+        #[arg(long, value_name = "X,Y,W,H")]
+        region: Option<Region>,
+
+        /// Scroll by sending mouse wheel events over the area instead of
+        /// waiting for you to scroll
+        #[arg(long)]
+        auto: bool,
+
+        /// Wheel notches per step of `--auto`
+        #[arg(long, default_value_t = 3, requires = "auto")]
+        clicks: u32,
+
+        /// Delay between captures, for the page to scroll and redraw
+        #[arg(long, value_name = "MS", default_value_t = 250)]
+        interval: u64,
+
+        /// Stop once the page has stayed still this long
+        #[arg(long, value_name = "MS", default_value_t = 3000)]
+        idle: u64,
+
+        /// Stop once the stitched image is this many pixels tall
+        #[arg(long, value_name = "PX", default_value_t = 30_000)]
+        max_height: u32,
+
+        /// Where the page goes
+        #[arg(short, long, value_name = "PATH", default_value = "target/scroll.png")]
+        output: PathBuf,
+    },
+
+    /// Watch an area for changes, saving it each time more than
+    /// `--threshold` of it changed; hooks, `--upload` and `--notify` run for
+    /// every save as usual
+    Monitor {
+        /// Display the area is on: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0")]
+        display: DisplaySelector,
+
+        /// Logical area of the display to watch, as `x,y,w,h`
+        #[arg(long, value_name = "X,Y,W,H")]
+        region: Region,
+
+        /// Delay between captures
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        interval: u64,
+
+        /// Percentage of the area's pixels that must change to count
+        #[arg(long, value_name = "PERCENT", default_value_t = 1.0)]
+        threshold: f64,
+
+        /// Largest per-channel difference still counted as unchanged
+        #[arg(long, default_value_t = 0)]
+        tolerance: u8,
+
+        /// Show a desktop notification for every change, with how much of
+        /// the area changed
+        #[arg(long)]
+        notify_on_change: bool,
+
+        /// Stop after this many changes; runs until interrupted otherwise
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Directory the changed frames are written to
+        #[arg(long, default_value = "target/monitor")]
+        dir: PathBuf,
+    },
+
+    /// Capture windows as they appear whose titles match the config's
+    /// `[[rules]]`, each to its rule's `dir` with a JSON sidecar saying what
+    /// set it off, and displays on the calendar of its `[[schedules]]`
+    Daemon {
+        /// Delay between looks at the windows
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        interval: u64,
+
+        /// Also take captures asked for by lines such as `capture display=1`
+        /// sent to this Unix socket, or named pipe on Windows
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Also show an icon in the system tray, with a menu to capture each
+        /// display and open recent captures
+        #[cfg(all(feature = "tray", target_os = "linux"))]
+        #[arg(long)]
+        tray: bool,
+    },
+
+    /// Capture the focused window each time the focus moves to another one,
+    /// as a journal of what was worked on
+    Focus {
+        /// Delay between looks at which window has the focus
+        #[arg(long, value_name = "MS", default_value_t = 250)]
+        interval: u64,
+
+        /// Least time between two captures; switching windows faster than
+        /// this captures only the one the focus settles on
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        throttle: u64,
+
+        /// Skip captures whose perceptual hash is within THRESHOLD bits of
+        /// the same window's last saved capture
+        #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "4")]
+        dedupe: Option<u32>,
+
+        /// Hash used by `--dedupe`: ahash, dhash or phash
+        #[arg(long, default_value = "dhash")]
+        hash: HashAlgorithm,
+
+        /// Whether to capture title bars and borders: include or exclude
+        #[arg(long, default_value = "include")]
+        decorations: Decorations,
+
+        /// Stop after saving this many captures; runs until interrupted
+        /// otherwise
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Directory the captures are written to
+        #[arg(long, default_value = "target/focus")]
+        dir: PathBuf,
+    },
+
+    /// Delete old captures from a directory per `--max-age`, `--max-count`
+    /// and `--max-size`
+    Prune {
+        /// Directory to prune; subdirectories only with `--recursive`
+        #[arg(default_value = "target/watch")]
+        dir: PathBuf,
+
+        #[command(flatten)]
+        retention: RetentionArgs,
+    },
+
+    /// Compare two images, printing SSIM, PSNR and the share of changed pixels
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+
+        /// Write a heatmap of the changed pixels here
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Exit with an error when more than this percentage of pixels changed
+        #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+        threshold: f64,
+
+        /// Largest per-channel difference still counted as unchanged
+        #[arg(long, default_value_t = 0)]
+        tolerance: u8,
+    },
+
+    /// Manage visual regression baselines
+    Baseline {
+        /// Baseline directory; defaults to $SNAP_SCALE_BASELINES or tests/baselines
+        #[arg(long, global = true)]
+        dir: Option<PathBuf>,
+
+        #[command(subcommand)]
+        action: BaselineAction,
+    },
+
+    /// Print the color of the pixel at a logical desktop position, or under
+    /// the cursor when no position is given
+    Pick {
+        /// Desktop-global logical x coordinate
+        #[arg(requires = "y", allow_negative_numbers = true)]
+        x: Option<i32>,
+        /// Desktop-global logical y coordinate
+        #[arg(allow_negative_numbers = true)]
+        y: Option<i32>,
+
+        /// Keep reading the pixel, following the cursor, and print each new
+        /// color until Esc
+        #[arg(long)]
+        live: bool,
+
+        /// Milliseconds between reads with `--live`
+        #[arg(long, value_name = "MS", default_value_t = 50, requires = "live")]
+        interval: u64,
+
+        /// Stop after this many reads with `--live`
+        #[arg(long, value_name = "N", requires = "live")]
+        count: Option<u64>,
+    },
+
+    /// Save a magnified close-up of the pixels around a logical desktop
+    /// position, or around the cursor when no position is given
+    Magnify {
+        /// Desktop-global logical x coordinate
+        #[arg(requires = "y", allow_negative_numbers = true)]
+        x: Option<i32>,
+        /// Desktop-global logical y coordinate
+        #[arg(allow_negative_numbers = true)]
+        y: Option<i32>,
+
+        /// Logical pixels to show on each side of the center
+        #[arg(long, default_value_t = 8)]
+        radius: u32,
+
+        /// Side of each magnified pixel
+        #[arg(long, default_value_t = snap_scale::magnify::DEFAULT_ZOOM,
+              value_parser = clap::value_parser!(u32).range(1..=64))]
+        zoom: u32,
+
+        /// Leave out the grid between pixels
+        #[arg(long)]
+        no_grid: bool,
+
+        /// Where the close-up goes, `-` for PNG on stdout
+        #[arg(short, long, value_name = "PATH", default_value = "target/magnify.png")]
+        output: PathBuf,
+    },
+
+    /// List the visible top-level windows, or capture each one to its own file
+    Windows {
+        /// Only windows of this process: its name, e.g. `firefox` or
+        /// `code.exe`, or its pid
+        #[arg(long, value_name = "NAME|PID")]
+        process: Option<String>,
+
+        /// Capture every listed window to `--dir`, named after its title
+        #[arg(long)]
+        each: bool,
+
+        /// Directory `--each` writes to
+        #[arg(long, default_value = "target/windows")]
+        dir: PathBuf,
+
+        /// `include` the title bar and borders in `--each` captures, or
+        /// `exclude` them for the client area alone
+        #[arg(long, default_value = "include")]
+        decorations: Decorations,
+    },
+
+    /// Draw a map of how the displays are arranged, with their positions,
+    /// sizes and the primary one marked
+    Layout {
+        /// Columns to scale the desktop to
+        #[arg(long, default_value_t = snap_scale::arrangement::DEFAULT_WIDTH)]
+        width: usize,
+
+        /// Draw with plain ASCII instead of box-drawing characters
+        #[arg(long)]
+        ascii: bool,
+    },
+
+    /// Print the dominant colors of a display or an image file
+    Palette {
+        /// Image to analyze instead of capturing a display
+        input: Option<PathBuf>,
+
+        /// Display to capture: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0", conflicts_with = "input")]
+        display: DisplaySelector,
+
+        /// Number of colors to extract
+        #[arg(long, default_value_t = 5)]
+        colors: usize,
+    },
+
+    /// Print where an image appears on screen: the desktop-global logical
+    /// position and size of every match, best first
+    Locate {
+        /// Image to look for, e.g. cut from an earlier capture
+        needle: PathBuf,
+
+        /// Display to search: `primary`, an index, `id:<N>` or part of its
+        /// name; every display by default
+        #[arg(long)]
+        display: Option<DisplaySelector>,
+
+        /// Search this image instead of the screen; positions are then its
+        /// pixels
+        #[arg(long, value_name = "PATH", conflicts_with = "display")]
+        input: Option<PathBuf>,
+
+        /// Lowest normalized cross-correlation counted as a match, up to 1
+        #[arg(long, default_value_t = 0.9)]
+        threshold: f32,
+
+        /// Print at most this many matches
+        #[arg(long, value_name = "N")]
+        max: Option<usize>,
+    },
+
+    /// Wait until an image appears on screen, or with `--gone` until it's
+    /// nowhere, polling until `--timeout` if one is given
+    WaitFor {
+        /// Image to wait for, e.g. cut from an earlier capture
+        #[arg(long, value_name = "PATH")]
+        image: PathBuf,
+
+        /// Wait for the image to disappear instead
+        #[arg(long)]
+        gone: bool,
+
+        /// Display to watch: `primary`, an index, `id:<N>` or part of its
+        /// name; every display by default
+        #[arg(long)]
+        display: Option<DisplaySelector>,
+
+        /// Lowest normalized cross-correlation counted as a match, up to 1
+        #[arg(long, default_value_t = 0.9)]
+        threshold: f32,
+
+        /// Delay between looks
+        #[arg(long, value_name = "MS", default_value_t = 250)]
+        interval: u64,
+
+        /// Then capture the display the image appeared on, or `--display`
+        /// (the first display by default), to this file
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Print the per-channel histograms and exposure of a display or image,
+    /// for checking what a display puts out
+    Histogram {
+        /// Image to analyze instead of capturing a display
+        input: Option<PathBuf>,
+
+        /// Display to capture: `primary`, an index, `id:<N>` or part of its name
+        #[arg(long, default_value = "0", conflicts_with = "input")]
+        display: DisplaySelector,
+
+        /// Logical area of the display to analyze, as `x,y,width,height`
+        #[arg(long, conflicts_with = "input")]
+        region: Option<Region>,
+
+        /// Also plot the histogram to this image file
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Size of the plot
+        #[arg(
+            long,
+            value_name = "WxH",
+            default_value = "512x200",
+            requires = "output"
+        )]
+        size: Extent,
+    },
+
+    /// List the most recent captures in the catalog
+    #[cfg(feature = "catalog")]
+    History {
+        /// Number of captures to list
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Find captures in the catalog; every filter given must match
+    #[cfg(feature = "catalog")]
+    Search {
+        /// Text the capture's path contains
+        text: Option<String>,
+
+        /// Tag the capture has; repeat to require several
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Display id the capture was taken on
+        #[arg(long)]
+        display: Option<String>,
+
+        /// Captures taken at or after this date or RFC 3339 time
+        #[arg(long, value_name = "TIME", value_parser = snap_scale::catalog::parse_time)]
+        since: Option<chrono::DateTime<chrono::Local>>,
+
+        /// Captures taken before this date or RFC 3339 time
+        #[arg(long, value_name = "TIME", value_parser = snap_scale::catalog::parse_time)]
+        until: Option<chrono::DateTime<chrono::Local>>,
+
+        /// Captures that look like this image
+        #[arg(long, value_name = "PATH")]
+        like: Option<PathBuf>,
+
+        /// Most hash bits a `--like` match may differ in
+        #[arg(long, value_name = "BITS", default_value_t = 8, requires = "like")]
+        distance: u32,
+
+        /// Number of captures to list; all when omitted
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Serve captures over HTTP: `/displays`, `/capture` and `/capture/area`
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Address to listen on; use `0.0.0.0:8080` for other machines
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Bearer token every request must send; defaults to
+        /// $SNAP_SCALE_TOKEN
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Answer `org.snapscale.Capture` calls on the D-Bus session bus
+    #[cfg(all(feature = "dbus", target_os = "linux"))]
+    Dbus,
+
+    /// Serve the gRPC contract in `proto/snap_scale.proto`
+    #[cfg(feature = "grpc")]
+    Grpc {
+        /// Address to listen on; use `0.0.0.0:50051` for other machines
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:50051")]
+        listen: String,
+
+        /// Bearer token every call must send as `authorization` metadata;
+        /// defaults to $SNAP_SCALE_TOKEN
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Decode QR codes visible on screen and print their payloads
+    #[cfg(feature = "scan")]
+    Scan {
+        /// Display to scan, as `primary`, an index, `id:<N>` or part of its name; all displays when omitted
+        #[arg(long)]
+        display: Option<DisplaySelector>,
+
+        /// Logical area to scan, as `x,y,width,height`
+        #[arg(long)]
+        region: Option<snap_scale::Region>,
+    },
+
+    /// Browse displays, previews and recent captures in the terminal,
+    /// capturing at a key press
+    #[cfg(feature = "tui")]
+    Tui {
+        /// Logical size of the area `m` captures around the mouse cursor
+        #[arg(long, value_name = "WxH", default_value = "640x480")]
+        cursor_area: Extent,
+    },
+
+    /// Print a completion script for `shell`, e.g. `snap_scale completions
+    /// zsh > ~/.zfunc/_snap_scale`
+    Completions { shell: clap_complete::Shell },
+
+    /// Print the man page, or write one per subcommand into `--dir`
+    Man {
+        /// Directory for `snap_scale.1`, `snap_scale-capture.1`, …
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// Fills in what `--preset` stands for, except what flags of its own
+    /// were given for
+    fn apply_preset(&mut self, matches: &ArgMatches) {
+        let Some(preset) = self.preset else {
+            return;
+        };
+        let settings = preset.settings();
+        if !given(matches, "quality") {
+            self.quality = settings.jpeg_quality;
+        }
+        if !given(matches, "resize") {
+            self.resize = settings.resize;
+        }
+        let capture = matches.subcommand_matches("capture");
+        let format_given = capture.is_some_and(|capture| given(capture, "format"));
+        // A PNG setting, so not forced on a format chosen with `--output`'s
+        // extension, or `--format` for stdout
+        #[cfg(feature = "optimize")]
+        if !given(matches, "optimize") {
+            let png = match given_output(matches) {
+                None => true,
+                Some(path) if is_stdout(path) => {
+                    !format_given
+                        || matches!(
+                            self.command,
+                            Some(Command::Capture {
+                                format: OutputFormat::Png,
+                                ..
+                            })
+                        )
+                }
+                Some(path) => {
+                    path.extension()
+                        .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()))
+                        == Some(OutputFormat::Png)
+                }
+            };
+            if png {
+                self.optimize = settings.optimize;
+            }
+        }
+        if let Some(Command::Capture { format, .. }) = &mut self.command {
+            if !format_given {
+                *format = settings.format;
+            }
+        }
+    }
+
+    /// Style of burned-in text
+    fn text_style(&self) -> anyhow::Result<TextStyle> {
+        Ok(TextStyle {
+            font: match &self.font {
+                Some(path) => Font::load(path)?,
+                None => Font::Builtin,
+            },
+            size: self.font_size,
+            color: self.text_color,
+            ..TextStyle::default()
+        })
+    }
+
+    /// Transforms applied to every capture before it is saved
+    fn pipeline(&self) -> anyhow::Result<Pipeline> {
+        let mut pipeline = Pipeline::new();
+        for mask in &self.masks {
+            pipeline.push(*mask);
+        }
+        if !self.shapes.is_empty() {
+            let markup = Annotator::new()
+                .color(self.stroke_color)
+                .width(self.stroke_width);
+            pipeline.push(self.shapes.iter().fold(markup, |m, shape| m.push(*shape)));
+        }
+        // After masks and markup, whose coordinates refer to the full capture
+        if let Some(tolerance) = self.trim {
+            pipeline.push(Trim::new(tolerance));
+        }
+        if let Some(text) = &self.caption {
+            pipeline.push(
+                Caption::new(text)
+                    .with_style(self.text_style()?)
+                    .at(self.caption_position),
+            );
+        }
+        Ok(pipeline)
+    }
+
+    /// Transforms applied last, after the timestamp: shaping and framing
+    /// the capture
+    fn finish(&self, config: &Config) -> anyhow::Result<Pipeline> {
+        let mut pipeline = Pipeline::new();
+        if let Some(radius) = self.round_corners {
+            let mut corners = RoundedCorners::new(radius);
+            corners.border = self.corner_border;
+            pipeline.push(corners);
+        }
+        #[cfg(feature = "frames")]
+        if let Some(device) = self.frame {
+            let mut frame = snap_scale::frame::DeviceFrame::new(device);
+            frame.url = self.frame_url.clone();
+            pipeline.push(frame);
+        }
+        if let Some(profile) = &self.beautify {
+            let preset = match config.beautify.get(profile) {
+                Some(preset) => preset.clone(),
+                None if profile == "default" => BeautifyConfig::default(),
+                None => anyhow::bail!("no [beautify.{profile}] profile in the config"),
+            };
+            pipeline.push(Beautifier::from_config(&preset));
+        }
+        if let Some(size) = self.resize {
+            pipeline.push(Resize::new(size).with_filter(self.resize_filter));
+        }
+        if !self.color_mode.is_color() {
+            pipeline.push(self.color_mode);
+        }
+        Ok(pipeline)
+    }
+
+    fn timestamp(&self) -> anyhow::Result<Option<Timestamp>> {
+        let Some(format) = &self.timestamp else {
+            return Ok(None);
+        };
+        Ok(Some(
+            Timestamp::new(format)?
+                .with_hostname(self.timestamp_host)
+                .with_style(self.text_style()?)
+                .at(self.timestamp_position),
+        ))
+    }
+
+    /// The logical area captured alongside each full display
+    fn area(&self) -> Region {
+        let area = Region::new(300, 300, 300, 300);
+        match self.aspect {
+            Some(ratio) => area.with_aspect(ratio, self.aspect_anchor, self.aspect_expand),
+            None => area,
+        }
+    }
+
+    #[cfg(feature = "optimize")]
+    fn optimizer(&self) -> Option<snap_scale::optimize::Optimizer> {
+        let level = self.optimize?;
+        let optimizer = snap_scale::optimize::Optimizer::new(level).with_zopfli(self.zopfli);
+        Some(match self.optimize_budget {
+            Some(ms) => optimizer.with_budget(Duration::from_millis(ms)),
+            None => optimizer,
+        })
+    }
+
+    fn load_config(&self) -> anyhow::Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::load_default()?,
+        };
+        for header in &self.upload_headers {
+            let (name, value) = parse_header(header)?;
+            config.upload.http.headers.insert(name, value);
+        }
+        if let Some(message) = &self.message {
+            config.upload.discord.message = Some(message.clone());
+            config.upload.slack.message = Some(message.clone());
+        }
+        if self.notify {
+            config.notifications.enabled = true;
+        }
+        if self.no_notify {
+            config.notifications.enabled = false;
+        }
+        Ok(config)
+    }
+}
+
+/// A display and the backend that captures it
 struct ScreenCapture {
-    screen: Screen,
+    backend: Arc<dyn CaptureBackend>,
+    display: DisplayDescriptor,
+    /// With `--dry-run`, nothing is captured
+    dry_run: bool,
+    /// Measured at most once, by the first capture of all of the display or
+    /// a test capture
+    scaling: OnceCell<ScalingConfig>,
 }
 
 impl ScreenCapture {
-    fn from_screen(screen: Screen) -> Self {
-        Self { screen }
+    fn new(backend: Arc<dyn CaptureBackend>, display: DisplayDescriptor) -> Self {
+        Self {
+            backend,
+            display,
+            dry_run: DRY_RUN.load(Ordering::Relaxed),
+            scaling: OnceCell::new(),
+        }
+    }
+
+    /// For `--dry-run`, a blank frame the size capturing the logical `area`
+    /// gives, all of the display when `None`
+    fn placeholder(&self, area: Option<Region>) -> snap_scale::Result<RgbaImage> {
+        let display = &self.display;
+        let bounds = Region::new(0, 0, display.width, display.height);
+        let area = area.unwrap_or(bounds);
+        if area.clamp_to(&bounds) != Some(area) {
+            return Err(snap_scale::Error::Invalid {
+                what: "area",
+                value: format!("{area} is outside display {}", display.id),
+            });
+        }
+        let scaling = ScalingConfig::new(display.scale_factor, 1.0);
+        let (width, height) = (
+            scaling.scale_dimension(area.width).max(1),
+            scaling.scale_dimension(area.height).max(1),
+        );
+        Ok(RgbaImage::new(width, height))
+    }
+
+    fn from_point(x: i32, y: i32) -> snap_scale::Result<Self> {
+        let backend = default_backend()?;
+        let display = backend.display_at(x, y)?;
+        Ok(Self::new(backend, display))
+    }
+
+    fn capture(&self) -> snap_scale::Result<RgbaImage> {
+        if self.dry_run {
+            return self.placeholder(None);
+        }
+        let image = self.backend.capture_display(&self.display)?;
+        let _ = self
+            .scaling
+            .set(ScalingConfig::of_capture(&self.display, &image));
+        Ok(image)
     }
 
-    fn from_point(x: i32, y: i32) -> Option<Self> {
-        Screen::from_point(x, y).ok().map(|screen| Self { screen })
+    fn capture_area(
+        &self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> snap_scale::Result<RgbaImage> {
+        let area = Region::new(x, y, width, height);
+        if self.dry_run {
+            return self.placeholder(Some(area));
+        }
+        self.backend.capture_area(&self.display, area)
     }
 
-    fn capture(&self) -> Option<RgbaImage> {
-        self.screen.capture().ok()
+    fn display_info(&self) -> &DisplayDescriptor {
+        &self.display
     }
 
-    fn capture_area(&self, x: i32, y: i32, width: u32, height: u32) -> Option<RgbaImage> {
-        self.screen.capture_area(x, y, width, height).ok()
+    /// The display's scaling, measured with a test capture unless it's a
+    /// dry run or a capture of all of it already showed it
+    fn scaling(&self) -> ScalingConfig {
+        *self.scaling.get_or_init(|| match self.dry_run {
+            true => ScalingConfig::new(self.display.scale_factor, 1.0),
+            false => ScalingConfig::probe(&*self.backend, &self.display),
+        })
     }
 
-    fn display_info(&self) -> &DisplayInfo {
-        &self.screen.display_info
+    /// A fast copy of the display shrunk to `scale`
+    fn preview(&self, scale: f32) -> snap_scale::Result<RgbaImage> {
+        if self.dry_run {
+            let full = self.placeholder(None)?;
+            let scale = snap_scale::preview::check_scale(scale)?;
+            let (width, height) =
+                snap_scale::preview::preview_size(full.width(), full.height(), scale);
+            return Ok(RgbaImage::new(width, height));
+        }
+        self.backend.capture_preview(&self.display, scale)
     }
 }
 
-fn main() {
-    let start = Instant::now();
-    let screens = Screen::all().unwrap();
+/// Per-run settings shared by every capture
+struct Session {
+    config: Config,
+    exec: Option<String>,
+    uploader: Option<Box<dyn Uploader>>,
+    /// Where capture events go, with `[mqtt] enabled`
+    mqtt: Option<snap_scale::mqtt::Publisher>,
+    /// Whether each saved capture is printed as a line of JSON
+    json: bool,
+    /// When the capture being saved started
+    started: Cell<Option<Instant>>,
+    /// The rule and window that set off the capture being saved, for its
+    /// sidecar
+    trigger: Cell<Option<Trigger>>,
+    /// The daemon's tray icon, which lists what's saved
+    #[cfg(all(feature = "tray", target_os = "linux"))]
+    tray: std::cell::OnceCell<snap_scale::tray::Tray>,
+    /// Whether saves only print where they'd go
+    dry_run: bool,
+    /// Whether each capture is looked over in a window before it's saved
+    #[cfg(feature = "review")]
+    review: bool,
+    /// Whether single-color captures fail rather than being saved
+    fail_on_blank: bool,
+    ocr: Option<OcrOutput>,
+
+    pipeline: Pipeline,
+    timestamp: Option<Timestamp>,
+    /// Whether the timestamp names the display
+    timestamp_display: bool,
+    /// Applied after the timestamp, so framing stays outside it
+    finish: Pipeline,
+    /// Area captured by the area modes
+    area: Region,
+    tile: Option<u32>,
+    color_mode: ColorMode,
+    palette: Option<Palette>,
+    icc: Option<ProfileSource>,
+    srgb: Option<ProfileSource>,
+    /// Format for `--output -`; also sends hook output to stderr
+    stdout: Option<OutputFormat>,
+    /// Format `--preset` saves what would be PNGs in
+    format: Option<OutputFormat>,
+    jpeg_quality: u8,
+    encoding: Encoding,
+    /// Tone mapping for whole-display captures, when capturing HDR
+    hdr: Option<ToneMapper>,
+    /// Bits per channel of HDR captures
+    depth: u8,
+    metadata: bool,
+    sidecar: bool,
+    date_dirs: Option<DateLayout>,
+    clobber: Clobber,
+    /// Whether missing output directories are created
+    create_dirs: bool,
+    /// Where saved captures are recorded, with `[catalog] enabled`
+    #[cfg(feature = "catalog")]
+    catalog: Option<snap_scale::catalog::Catalog>,
+    #[cfg(feature = "catalog")]
+    tags: Vec<String>,
+    #[cfg(feature = "optimize")]
+    optimizer: Option<snap_scale::optimize::Optimizer>,
+}
+
+impl Session {
+    /// Runs the `pre_capture` hook; a failing hook aborts the capture
+    fn before_capture(&self, display: &str) -> anyhow::Result<()> {
+        self.started.set(Some(Instant::now()));
+        if self.dry_run {
+            return Ok(());
+        }
+        if let Some(command) = &self.config.hooks.pre_capture {
+            self.hook(command, &HookContext::before(display))?;
+        }
+        Ok(())
+    }
+
+    /// Obscures windows blocklisted in the `[redact]` config; must run right
+    /// after capturing, while the windows are still where they were
+    fn redact(
+        &self,
+        image: &mut RgbaImage,
+        screen: &ScreenCapture,
+        area: Option<Region>,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let config = &self.config.redact;
+        snap_scale::redact::redact(image, &*screen.backend, &screen.display, area, config)?;
+        Ok(())
+    }
+
+    /// Encodes a finished capture as `--palette`, `--color-mode`,
+    /// `--optimize`, `--icc` and `--metadata` ask
+    fn write(
+        &self,
+        image: &RgbaImage,
+        path: &Path,
+        display: &DisplayDescriptor,
+    ) -> anyhow::Result<()> {
+        let format = self.output_format(path);
+        let _span = tracing::debug_span!("encode", format = ?format).entered();
+        if format != Some(OutputFormat::Png) {
+            anyhow::ensure!(
+                self.palette.is_none(),
+                "--palette needs PNG output, not {}",
+                path.display()
+            );
+            #[cfg(feature = "optimize")]
+            anyhow::ensure!(
+                self.optimizer.is_none(),
+                "--optimize needs PNG output, not {}",
+                path.display()
+            );
+        }
+        let Some(format @ (OutputFormat::Png | OutputFormat::Jpeg)) = format else {
+            anyhow::ensure!(
+                self.icc.is_none(),
+                "--icc needs PNG or JPEG output, not {}",
+                path.display()
+            );
+            anyhow::ensure!(
+                !self.metadata,
+                "--metadata needs PNG or JPEG output, not {}",
+                path.display()
+            );
+            let output = match format {
+                Some(format) => ImageFormat::from_extension(format.extension()),
+                None => Some(ImageFormat::from_path(path)?),
+            };
+            let mut bytes = std::io::Cursor::new(Vec::new());
+            match (format, output) {
+                (_, Some(output)) if !self.color_mode.is_color() => {
+                    snap_scale::color_mode::reduce(image).write_to(&mut bytes, output)?
+                }
+                (Some(format), _) => snap_scale::encode::encode(
+                    image,
+                    &EncodeOptions::new(format).with_jpeg_quality(self.jpeg_quality),
+                    &mut bytes,
+                )?,
+                (None, Some(output)) => image.write_to(&mut bytes, output)?,
+                (None, None) => unreachable!("unknown extensions fail above"),
+            }
+            self.emit(path, bytes.get_ref())?;
+            return Ok(());
+        };
+
+        let options = EncodeOptions::new(format).with_jpeg_quality(self.jpeg_quality);
+        if self.streams(image, path) {
+            let frame = Frame::from_image(image, SystemTime::now());
+            let encode =
+                |file: &mut dyn Write| snap_scale::encode::encode_frame(&frame, &options, file);
+            match self.clobber {
+                Clobber::Overwrite => snap_scale::atomic::write_with(path, encode)?,
+                Clobber::Refuse | Clobber::Number => {
+                    snap_scale::atomic::write_new_with(path, encode)?
+                }
+            }
+            return Ok(());
+        }
+        let bytes = match self.palette {
+            Some(palette) => {
+                snap_scale::encode::encode_to_vec(image, &options.with_palette(palette))?
+            }
+            None if !self.color_mode.is_color() => {
+                let output = match format {
+                    OutputFormat::Jpeg => ImageOutputFormat::Jpeg(options.jpeg_quality),
+                    _ => ImageOutputFormat::Png,
+                };
+                let mut bytes = std::io::Cursor::new(Vec::new());
+                snap_scale::color_mode::reduce(image).write_to(&mut bytes, output)?;
+                bytes.into_inner()
+            }
+            None => snap_scale::encode::encode_to_vec(image, &options)?,
+        };
+        self.write_encoded(bytes, format, path, display)
+    }
+
+    /// Whether a PNG or JPEG capture goes straight into its file: only big
+    /// ones, and only when nothing has to change the encoded bytes after
+    fn streams(&self, image: &RgbaImage, path: &Path) -> bool {
+        #[cfg(feature = "optimize")]
+        if self.optimizer.is_some() {
+            return false;
+        }
+        let pixels = image.width() as u64 * image.height() as u64;
+        pixels > STREAM_PIXELS
+            && !is_stdout(path)
+            && self.palette.is_none()
+            && self.color_mode.is_color()
+            && self.icc.is_none()
+            && !self.metadata
+    }
+
+    /// Writes a 16-bit capture as PNG, with `--optimize`, `--icc` and
+    /// `--metadata` applied, or as TIFF
+    fn write16(
+        &self,
+        image: &Rgba16Image,
+        path: &Path,
+        display: &DisplayDescriptor,
+    ) -> anyhow::Result<()> {
+        let format = self.output_format(path);
+        let Some(format @ (OutputFormat::Png | OutputFormat::Tiff)) = format else {
+            anyhow::bail!(
+                "--depth 16 needs PNG or TIFF output, not {}",
+                path.display()
+            );
+        };
+        let bytes = snap_scale::encode::encode16_to_vec(image, format)?;
+        if format == OutputFormat::Png {
+            return self.write_encoded(bytes, format, path, display);
+        }
+        anyhow::ensure!(
+            self.icc.is_none() && !self.metadata,
+            "--icc and --metadata need PNG output with --depth 16"
+        );
+        #[cfg(feature = "optimize")]
+        anyhow::ensure!(self.optimizer.is_none(), "--optimize needs PNG output");
+        self.emit(path, &bytes)?;
+        Ok(())
+    }
+
+    /// The format `path` is written in: its extension's, or `--format` for
+    /// stdout
+    fn output_format(&self, path: &Path) -> Option<OutputFormat> {
+        if is_stdout(path) {
+            return self.stdout;
+        }
+        path.extension()
+            .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()))
+    }
+
+    /// Writes encoded bytes to `path`, or to stdout for `-` as `--encoding`
+    /// asks
+    fn emit(&self, path: &Path, bytes: &[u8]) -> snap_scale::Result<()> {
+        let _span = tracing::debug_span!("write", bytes = bytes.len()).entered();
+        let Some(format) = self.stdout.filter(|_| is_stdout(path)) else {
+            return match self.clobber {
+                Clobber::Overwrite => snap_scale::atomic::write(path, bytes),
+                // Also catches files created since `target` checked
+                Clobber::Refuse | Clobber::Number => snap_scale::atomic::write_new(path, bytes),
+            };
+        };
+        let mut stdout = std::io::stdout().lock();
+        match self.encoding {
+            Encoding::Raw => stdout.write_all(bytes)?,
+            Encoding::Base64 => writeln!(stdout, "{}", BASE64_STANDARD.encode(bytes))?,
+            Encoding::DataUri => writeln!(stdout, "{}", data_uri(bytes, format))?,
+        }
+        Ok(stdout.flush()?)
+    }
+
+    fn hook(&self, command: &str, context: &HookContext) -> snap_scale::Result<()> {
+        if self.stdout.is_some() {
+            run_hook_to_stderr(command, context)
+        } else {
+            run_hook(command, context)
+        }
+    }
+
+    /// Post-processes encoded PNG or JPEG bytes of a capture of `display`
+    /// and writes them out
+    fn write_encoded(
+        &self,
+        bytes: Vec<u8>,
+        format: OutputFormat,
+        path: &Path,
+        display: &DisplayDescriptor,
+    ) -> anyhow::Result<()> {
+        #[cfg(feature = "optimize")]
+        let bytes = match &self.optimizer {
+            Some(optimizer) if format == OutputFormat::Png => optimizer.optimize(&bytes)?,
+            _ => bytes,
+        };
+        let bytes = match &self.icc {
+            Some(source) => match source.load(display.id)? {
+                Some(profile) => snap_scale::icc::embed(&bytes, format, &profile)?,
+                None => {
+                    // `display` would name tracing's helper inside the macro
+                    let id = display.id;
+                    tracing::warn!("display {id} has no color profile");
+                    bytes
+                }
+            },
+            None => bytes,
+        };
+        let bytes = if self.metadata {
+            let metadata = CaptureMetadata::new(display, chrono::Local::now());
+            snap_scale::metadata::embed(&bytes, format, &metadata)?
+        } else {
+            bytes
+        };
+        self.emit(path, &bytes)?;
+        Ok(())
+    }
+
+    /// Converts a capture to sRGB for `--srgb`, before anything is drawn on it
+    fn to_srgb(&self, image: &RgbaImage, display: &str) -> anyhow::Result<RgbaImage> {
+        let Some(source) = &self.srgb else {
+            return Ok(image.clone());
+        };
+        match source.load(display.parse()?)? {
+            Some(profile) => Ok(ToSrgb::new(&profile)?.apply(image.clone())?),
+            None => {
+                let id = display;
+                tracing::warn!("display {id} has no color profile, assuming sRGB");
+                Ok(image.clone())
+            }
+        }
+    }
+
+    /// Transforms and saves a capture, then runs the post-save side effects
+    ///
+    /// Nothing is written when a transform fails.
+    /// `region` is the logical area captured on `screen`, if not all of it.
+    /// Returns the file written: the image, or a tiled capture's manifest.
+    fn save(
+        &self,
+        image: &RgbaImage,
+        path: impl AsRef<Path>,
+        screen: &ScreenCapture,
+        region: Option<Region>,
+    ) -> anyhow::Result<PathBuf> {
+        let started = chrono::Local::now();
+        let path = path.as_ref();
+        let display = &screen.display.id.to_string();
+        let id = display;
+        let _span = tracing::info_span!("save", path = %path.display(), display = id).entered();
+        self.check_blank(image, display)?;
+        let convert = tracing::debug_span!("convert").entered();
+        let mut image = self.pipeline.apply(self.to_srgb(image, display)?)?;
+        if let Some(stamp) = &self.timestamp {
+            image = if self.timestamp_display {
+                stamp.clone().with_display(display).apply(image)?
+            } else {
+                stamp.apply(image)?
+            };
+        }
+        let image = self.finish.apply(image)?;
+        drop(convert);
+        #[cfg(feature = "review")]
+        let image = match self.review && !self.dry_run {
+            true => match snap_scale::review::review(image)? {
+                snap_scale::review::Decision::Save(image) => image,
+                snap_scale::review::Decision::Discard => anyhow::bail!("discarded in review"),
+            },
+            false => image,
+        };
+        let image = &image;
+        let tiled = self
+            .tile
+            .filter(|&size| image.width() > size || image.height() > size);
+        let preset = self
+            .format
+            .filter(|_| self.output_format(path) == Some(OutputFormat::Png) && !is_stdout(path))
+            .map(|format| path.with_extension(format.extension()));
+        let requested = preset.as_deref().unwrap_or(path);
+        let path = &self.target(requested, tiled.is_some())?;
+        if self.dry_run {
+            self.print_plan(image, path, tiled, display, region);
+            return Ok(path.clone());
+        }
+        // Hooks and notifications get the manifest for tiled captures
+        let (path, saved) = match tiled {
+            Some(size) => {
+                let manifest = snap_scale::tile::save_tiles(image, path, size)?;
+                if !self.json {
+                    println!("{} tiles", manifest.tiles.len());
+                }
+                (path.clone(), snap_scale::tile::manifest_path(path))
+            }
+            None => {
+                let encoding = Instant::now();
+                let path = self.publish(requested, path, |path| {
+                    self.write(image, path, &screen.display)
+                })?;
+                // Nothing to measure on stdout
+                if let Ok(file) = std::fs::metadata(&path) {
+                    METRICS.encoded(encoding.elapsed(), file.len());
+                }
+                (path.clone(), path)
+            }
+        };
+        let path = &path;
+        if self.sidecar {
+            self.write_sidecar(image, path, &saved, screen, region, started)?;
+        }
+        self.after_save(image, path, &saved, tiled.is_some(), display, region);
+        Ok(saved)
+    }
 
+    /// Fails with [`snap_scale::Error::Blank`] for a single-color `image`
+    /// with `--fail-on-blank`, except in dry runs, whose captures are blank
+    /// placeholders
+    fn check_blank(&self, image: &RgbaImage, display: &str) -> snap_scale::Result<()> {
+        let check = self.fail_on_blank && !self.dry_run;
+        match check.then(|| snap_scale::analysis::blank(image)).flatten() {
+            Some(color) => Err(snap_scale::Error::Blank {
+                display: display.to_owned(),
+                color,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// What `--dry-run` prints for a capture of `region`, or all of
+    /// `display`, saved to `path` as `image`
+    fn print_plan(
+        &self,
+        image: &RgbaImage,
+        path: &Path,
+        tiled: Option<u32>,
+        display: &str,
+        region: Option<Region>,
+    ) {
+        let format = self
+            .output_format(path)
+            .map_or("from the extension".into(), |format| format.to_string());
+        let (width, height) = image.dimensions();
+        if self.json {
+            print_json(&serde_json::json!({
+                "event": "capture",
+                "dry_run": true,
+                "path": path,
+                "display": display,
+                "region": region,
+                "width": width,
+                "height": height,
+                "format": self.output_format(path).map(|format| format.to_string()),
+                "tile": tiled,
+            }));
+            return;
+        }
+        let area = region.map_or("all".into(), |region| format!("area {region}"));
+        let tiles = tiled.map_or(String::new(), |size| format!(", in {size}px tiles"));
+        let target = match is_stdout(path) {
+            true => "stdout".into(),
+            false => path.display().to_string(),
+        };
+        println!("would capture display {display} ({area}) to {target}: {width}x{height}, {format}{tiles}");
+    }
+
+    /// Captures a whole display, from its HDR framebuffer with `--hdr`, and
+    /// saves it
+    fn capture_display(
+        &self,
+        capturer: &ScreenCapture,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<PathBuf> {
+        let display = &capturer.display.id.to_string();
+        self.before_capture(display)?;
+        let id = display;
+        let capture = tracing::debug_span!("capture", display = id).entered();
+        let Some(mapper) = self.hdr.as_ref().filter(|_| !self.dry_run) else {
+            let mut image = capturer.capture()?;
+            drop(capture);
+            self.redact(&mut image, capturer, None)?;
+            return self.save(&image, path, capturer, None);
+        };
+        let id = capturer.display_info().id;
+        let config = snap_scale::backend::capture_config();
+        let hdr = config.retry().run(|| {
+            snap_scale::timeout::within(config.timeout(), "capture", move || {
+                snap_scale::hdr::capture(id)
+            })
+        })?;
+        drop(capture);
+        if self.depth == 16 {
+            return self.save16(mapper.map16(&hdr), path.as_ref(), capturer);
+        }
+        let mut image = mapper.map(&hdr);
+        self.redact(&mut image, capturer, None)?;
+        self.save(&image, path, capturer, None)
+    }
+
+    /// Transforms and saves a 16-bit capture; steps that can't keep 16 bits
+    /// fail before anything is written
+    fn save16(
+        &self,
+        image: Rgba16Image,
+        path: &Path,
+        screen: &ScreenCapture,
+    ) -> anyhow::Result<PathBuf> {
+        let started = chrono::Local::now();
+        let display = &screen.display.id.to_string();
+        // Checked at startup: nothing to redact, stamp or tile
+        let image = self.finish.apply16(self.pipeline.apply16(image)?)?;
+        if self.fail_on_blank {
+            let image = DynamicImage::ImageRgba16(image.clone()).into_rgba8();
+            self.check_blank(&image, display)?;
+        }
+        let target = self.target(path, false)?;
+        let path = &self.publish(path, &target, |path| {
+            self.write16(&image, path, &screen.display)
+        })?;
+        let preview = DynamicImage::ImageRgba16(image).into_rgba8();
+        if self.sidecar {
+            self.write_sidecar(&preview, path, path, screen, None, started)?;
+        }
+        self.after_save(&preview, path, path, false, display, None);
+        Ok(path.clone())
+    }
+
+    /// Writes the `--sidecar` JSON next to `path` for a capture of `screen`
+    /// saved as `saved`, its tile manifest when tiled; processing time counts
+    /// from `started`
+    fn write_sidecar(
+        &self,
+        image: &RgbaImage,
+        path: &Path,
+        saved: &Path,
+        screen: &ScreenCapture,
+        region: Option<Region>,
+        started: chrono::DateTime<chrono::Local>,
+    ) -> anyhow::Result<()> {
+        let processing = chrono::Local::now() - started;
+        let sidecar = Sidecar {
+            file: saved
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            width: image.width(),
+            height: image.height(),
+            display: screen.display.clone(),
+            region,
+            scaling: (&screen.scaling()).into(),
+            captured_at: started.to_rfc3339(),
+            processing_ms: processing.num_microseconds().unwrap_or_default() as f64 / 1000.0,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            trigger: self.trigger.take(),
+        };
+        Ok(sidecar.write(path)?)
+    }
+
+    /// Runs `write` for `path`, which [`Self::target`] picked for
+    /// `requested`. Under `--auto-number` a file created there since moves
+    /// the capture on to the next free number rather than failing.
+    fn publish(
+        &self,
+        requested: &Path,
+        path: &Path,
+        write: impl Fn(&Path) -> anyhow::Result<()>,
+    ) -> anyhow::Result<PathBuf> {
+        let mut path = path.to_owned();
+        loop {
+            match write(&path) {
+                Err(e) if self.clobber == Clobber::Number && already_exists(&e) => {
+                    tracing::debug!("{} was just created, numbering on", path.display());
+                    path = self.target(requested, false)?;
+                }
+                result => return result.map(|()| path),
+            }
+        }
+    }
+
+    /// Where a capture meant for `path` goes under `--date-dirs`,
+    /// `--no-clobber` and `--auto-number`; a tiled capture is judged by its
+    /// manifest. Creates the directory unless `--no-create-dirs`.
+    fn target(&self, path: &Path, tiled: bool) -> anyhow::Result<PathBuf> {
+        if is_stdout(path) {
+            return Ok(path.to_owned());
+        }
+        let taken = |path: &Path| {
+            if tiled {
+                snap_scale::tile::manifest_path(path).exists()
+            } else {
+                path.exists()
+            }
+        };
+        let dated = self
+            .date_dirs
+            .as_ref()
+            .map(|layout| layout.path(path, chrono::Local::now()));
+        let path = dated.as_deref().unwrap_or(path);
+        let path = match self.clobber {
+            Clobber::Overwrite => path.to_owned(),
+            Clobber::Refuse => {
+                anyhow::ensure!(
+                    !taken(path),
+                    "{} already exists; not overwriting it with --no-clobber",
+                    path.display()
+                );
+                path.to_owned()
+            }
+            Clobber::Number => snap_scale::atomic::numbered(path, taken),
+        };
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                anyhow::ensure!(
+                    self.create_dirs,
+                    "output directory {} doesn't exist",
+                    dir.display()
+                );
+                if self.dry_run {
+                    return Ok(path);
+                }
+                std::fs::create_dir_all(dir).with_context(|| {
+                    format!("couldn't create output directory {}", dir.display())
+                })?;
+            }
+            _ => {}
+        }
+        Ok(path)
+    }
+
+    /// OCR, upload, `post_capture` hooks and notifications for a saved
+    /// capture; failures are warnings
+    fn after_save(
+        &self,
+        image: &RgbaImage,
+        path: &Path,
+        saved: &Path,
+        tiled: bool,
+        display: &str,
+        region: Option<Region>,
+    ) {
+        let mut text = None;
+        match self.ocr {
+            // Printed as part of the JSON line
+            Some(OcrOutput::Stdout) if self.json => {
+                text = snap_scale::ocr::extract_text(image)
+                    .inspect_err(|e| tracing::warn!("{e}"))
+                    .ok()
+                    .map(|result| result.text);
+            }
+            Some(output) => {
+                if let Err(e) = write_text(image, path, output) {
+                    tracing::warn!("{e}");
+                }
+            }
+            None => {}
+        }
+
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = &self.catalog {
+            let entry =
+                snap_scale::catalog::Entry::new(image, saved, display, chrono::Local::now())
+                    .with_region(region)
+                    .with_tags(&self.tags);
+            if let Err(e) = catalog.add(&entry) {
+                tracing::warn!("{e}");
+            }
+        }
+        #[cfg(not(feature = "catalog"))]
+        let _ = region;
+
+        #[cfg(all(feature = "tray", target_os = "linux"))]
+        if let Some(tray) = self.tray.get() {
+            tray.saved(saved);
+        }
+
+        let mut url = None;
+        if let Some(uploader) = &self.uploader {
+            if tiled {
+                tracing::warn!("tiled captures are not uploaded");
+            } else {
+                match uploader.upload_file(path) {
+                    Ok(result) => {
+                        if !self.json {
+                            println!("{}", result.url);
+                        }
+                        url = Some(result.url);
+                    }
+                    Err(e) => tracing::warn!("{} upload failed: {e}", uploader.name()),
+                }
+            }
+        }
+
+        if self.json {
+            let started = self.started.take();
+            let mut line = serde_json::json!({
+                "event": "capture",
+                "path": saved,
+                "display": display,
+                "width": image.width(),
+                "height": image.height(),
+                "duration_ms": started.map(|started| started.elapsed().as_millis() as u64),
+                "hash": snap_scale::hash::difference_hash(image).to_string(),
+            });
+            if let Some(url) = &url {
+                line["url"] = url.as_str().into();
+            }
+            if let Some(text) = text {
+                line["text"] = text.into();
+            }
+            print_json(&line);
+        }
+
+        if let Some(mqtt) = &self.mqtt {
+            let event =
+                snap_scale::mqtt::CaptureEvent::new(saved, display, image.width(), image.height())
+                    .with_url(url);
+            // A tile manifest isn't an image
+            let encoded = match mqtt.wants_image() && !tiled {
+                true => std::fs::read(saved)
+                    .inspect_err(|e| tracing::warn!("{}: {e}", saved.display()))
+                    .ok(),
+                false => None,
+            };
+            if let Err(e) = mqtt.publish(&event, encoded.as_deref()) {
+                tracing::warn!("{e}");
+            }
+        }
+
+        let context = HookContext::after(display, saved, image.width(), image.height());
+        let post_hooks = [&self.config.hooks.post_capture, &self.exec];
+        for command in post_hooks.into_iter().flatten() {
+            if let Err(e) = self.hook(command, &context) {
+                tracing::warn!("{e}");
+            }
+        }
+
+        if self.config.notifications.enabled && self.stdout.is_none() {
+            if let Err(e) = snap_scale::notify::capture_saved(saved, &self.config.notifications) {
+                tracing::warn!("{e}");
+            }
+        }
+    }
+}
+
+/// Parses `--timeout`: seconds, or a duration such as `30s` or `2m`
+fn parse_timeout(s: &str) -> snap_scale::Result<u64> {
+    s.parse()
+        .or_else(|_| snap_scale::retention::parse_age(s).map(|age| age.as_secs()))
+        .map_err(|_| snap_scale::Error::Invalid {
+            what: "timeout (expected seconds or e.g. 30s or 2m)",
+            value: s.to_owned(),
+        })
+}
+
+/// Prints `value` as one line of `--json` output
+fn print_json(value: &serde_json::Value) {
+    println!("{value}");
+}
+
+/// `-` as an output path: stdout
+/// Whether `id` was given on the command line or in the environment rather
+/// than left at its default
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches
+        .value_source(id)
+        .is_some_and(|source| source != ValueSource::DefaultValue)
+}
+
+/// The subcommand's `--output`, if given rather than left at its default
+fn given_output(matches: &ArgMatches) -> Option<&PathBuf> {
+    let (_, command) = matches.subcommand()?;
+    // Not every subcommand has one, and asking `value_source` about it then
+    // panics
+    let output = command.try_get_one::<PathBuf>("output").ok().flatten()?;
+    given(command, "output").then_some(output)
+}
+
+/// Whether `error` is a write refused because its file already exists
+fn already_exists(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(snap_scale::Error::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+    )
+}
+
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Runs OCR on a capture and emits the text per `--ocr`
+fn write_text(image: &RgbaImage, path: &Path, output: OcrOutput) -> anyhow::Result<()> {
+    let text = snap_scale::ocr::extract_text(image)?.text;
+    match output {
+        OcrOutput::Stdout => println!("{text}"),
+        OcrOutput::Sidecar => {
+            snap_scale::atomic::write(snap_scale::metadata::text_path(path), text + "\n")?
+        }
+    }
+    Ok(())
+}
+
+/// Logs to stderr, reporting how long each span took when it closes
+fn init_logging(level: tracing::Level, format: LogFormat) {
+    let logger = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()));
+    match format {
+        LogFormat::Text => logger.init(),
+        LogFormat::Json => logger.json().init(),
+    }
+}
+
+/// Every display, in index order
+fn screens() -> anyhow::Result<Vec<ScreenCapture>> {
+    let backend = default_backend()?;
+    let _span = tracing::debug_span!("enumerate", backend = backend.name()).entered();
+    let displays = backend.enumerate()?;
+    tracing::debug!(count = displays.len(), "found displays");
+    Ok(displays
+        .into_iter()
+        .map(|display| ScreenCapture::new(Arc::clone(&backend), display))
+        .collect())
+}
+
+/// The display `selector` picks
+fn select_screen(selector: &DisplaySelector) -> anyhow::Result<ScreenCapture> {
+    let mut screens = screens()?;
+    let displays: Vec<_> = screens.iter().map(|s| s.display.clone()).collect();
+    let index = selector.position(&displays)?;
+    Ok(screens.swap_remove(index))
+}
+
+/// Exits with 1 on failure, or with 2 when only some captures of a batch
+/// failed
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            let code = e
+                .downcast_ref::<BatchResult<PathBuf>>()
+                .map_or(1, BatchResult::exit_code);
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    cli.apply_preset(&matches);
+    init_logging(cli.log_level, cli.log_format);
+    // Neither needs the config, which may be what's being debugged
+    match &cli.command {
+        Some(Command::Completions { shell }) => {
+            let mut command = Cli::command();
+            clap_complete::generate(*shell, &mut command, "snap_scale", &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(Command::Man { dir }) => return man(dir.as_deref()),
+        _ => {}
+    }
+    let config = cli.load_config()?;
+    DRY_RUN.store(cli.dry_run, Ordering::Relaxed);
+    if cli.dry_run {
+        match &cli.command {
+            None
+            | Some(Command::Capture { select: false, .. })
+            | Some(Command::Windows { .. })
+            | Some(Command::Magnify { .. })
+            | Some(Command::Prune { .. })
+            | Some(Command::Baseline { .. }) => {}
+            Some(_) => {
+                anyhow::bail!("--dry-run works with capture, windows, magnify, prune and baseline")
+            }
+        }
+    }
+
+    let mut capture = config.capture.clone();
+    if let Some(seconds) = cli.timeout {
+        capture.timeout_secs = seconds;
+    }
+    snap_scale::backend::configure(&capture);
+    let uploader = cli
+        .upload
+        .as_deref()
+        .map(|spec| uploader_for(spec, &config.upload))
+        .transpose()?;
+    let finish = cli.finish(&config)?;
+    // A dry run neither publishes nor records, so it connects to neither
+    let mqtt = (config.mqtt.enabled && !cli.dry_run)
+        .then(|| snap_scale::mqtt::Publisher::connect(&config.mqtt))
+        .and_then(|publisher| {
+            publisher
+                .inspect_err(|e| tracing::warn!("{e}; capture events aren't published"))
+                .ok()
+        });
+    #[cfg(feature = "catalog")]
+    let catalog = (config.catalog.enabled && !cli.dry_run)
+        .then(|| open_catalog(&config))
+        .transpose()?;
+    let session = Session {
+        config,
+        exec: cli.exec.clone(),
+        uploader,
+        mqtt,
+        json: cli.json,
+        started: Cell::new(None),
+        #[cfg(all(feature = "tray", target_os = "linux"))]
+        tray: std::cell::OnceCell::new(),
+        trigger: Cell::new(None),
+        dry_run: cli.dry_run,
+        #[cfg(feature = "review")]
+        review: cli.review,
+        fail_on_blank: cli.fail_on_blank,
+        ocr: cli.ocr,
+        pipeline: cli.pipeline()?,
+        timestamp: cli.timestamp()?,
+        timestamp_display: cli.timestamp_display,
+        finish,
+        area: cli.area(),
+        tile: cli.tile,
+        color_mode: cli.color_mode,
+        palette: cli
+            .palette
+            .map(|colors| Palette::new(colors).with_dither(cli.dither)),
+        icc: cli.icc.clone(),
+        srgb: cli.srgb.clone(),
+        hdr: cli
+            .hdr
+            .map(|operator| ToneMapper::new(operator).with_sdr_white(cli.hdr_white)),
+        depth: cli.depth,
+        stdout: match &cli.command {
+            Some(command @ Command::Capture { format, .. })
+                if command.output().is_some_and(is_stdout) =>
+            {
+                Some(*format)
+            }
+            _ => None,
+        },
+        // Only default names take the preset's format; `--output`'s is chosen
+        format: cli
+            .preset
+            .filter(|_| given_output(&matches).is_none())
+            .map(|preset| preset.settings().format),
+        jpeg_quality: cli.quality,
+        encoding: match &cli.command {
+            Some(Command::Capture { encoding, .. }) => *encoding,
+            _ => Encoding::Raw,
+        },
+        metadata: cli.metadata,
+        // What set off a rule's capture is only recorded in sidecars
+        sidecar: cli.sidecar || matches!(cli.command, Some(Command::Daemon { .. })),
+        clobber: match (cli.no_clobber, cli.auto_number) {
+            (true, _) => Clobber::Refuse,
+            (_, true) => Clobber::Number,
+            _ => Clobber::Overwrite,
+        },
+        date_dirs: cli.date_dirs.clone(),
+        create_dirs: !cli.no_create_dirs,
+        #[cfg(feature = "catalog")]
+        catalog,
+        #[cfg(feature = "catalog")]
+        tags: cli.tags.clone(),
+        #[cfg(feature = "optimize")]
+        optimizer: cli.optimizer(),
+    };
+    #[cfg(not(feature = "catalog"))]
+    if session.config.catalog.enabled {
+        tracing::warn!("[catalog] needs the catalog feature; captures aren't recorded");
+    }
+    if session.depth == 16 {
+        anyhow::ensure!(
+            session.timestamp.is_none()
+                && session.palette.is_none()
+                && session.tile.is_none()
+                && session.srgb.is_none(),
+            "--depth 16 can't be combined with --timestamp, --palette, --tile or --srgb"
+        );
+        let redact = &session.config.redact;
+        anyhow::ensure!(
+            redact.apps.is_empty() && redact.titles.is_empty(),
+            "--depth 16 can't redact windows; clear [redact] in the config"
+        );
+    }
+
+    match &cli.command {
+        #[cfg(feature = "scripting")]
+        Some(Command::Script { path }) => Ok(snap_scale::script::run_file(path)?),
+        #[cfg(feature = "scan")]
+        Some(Command::Scan { display, region }) => {
+            scan_screens(display.as_ref(), *region, cli.json)
+        }
+        Some(Command::Watch {
+            display,
+            interval,
+            count,
+            dir,
+            dedupe,
+            hash,
+            retention,
+            soak,
+            #[cfg(feature = "serve")]
+            metrics,
+        }) => {
+            #[cfg(feature = "serve")]
+            if let Some(addr) = metrics {
+                let addr = snap_scale::serve::spawn_metrics(addr)?;
+                println!("metrics on http://{addr}/metrics");
+            }
+            let dedupe = dedupe.map(|threshold| Deduplicator::new(*hash, threshold));
+            watch(
+                &session,
+                display,
+                Duration::from_millis(*interval),
+                *count,
+                dir,
+                dedupe,
+                // `--date-dirs` puts the frames below `dir`
+                Retention {
+                    recursive: retention.recursive || session.date_dirs.is_some(),
+                    ..retention.policy()
+                },
+                soak.then(|| SoakHarness::new(count.unwrap_or_default())),
+            )
+        }
+        Some(Command::Scroll {
+            display,
+            region,
+            auto,
+            clicks,
+            interval,
+            idle,
+            max_height,
+            output,
+        }) => scroll(
+            &session,
+            display,
+            *region,
+            auto.then_some(*clicks),
+            (
+                Duration::from_millis(*interval),
+                Duration::from_millis(*idle),
+            ),
+            *max_height,
+            output,
+        ),
+        Some(Command::Daemon {
+            interval,
+            socket,
+            #[cfg(all(feature = "tray", target_os = "linux"))]
+            tray,
+        }) => {
+            #[cfg(all(feature = "tray", target_os = "linux"))]
+            let tray = *tray;
+            #[cfg(not(all(feature = "tray", target_os = "linux")))]
+            let tray = false;
+            daemon(
+                &session,
+                Duration::from_millis(*interval),
+                socket.as_deref(),
+                tray,
+            )
+        }
+        Some(Command::Focus {
+            interval,
+            throttle,
+            dedupe,
+            hash,
+            decorations,
+            count,
+            dir,
+        }) => {
+            let dedupe = dedupe.map(|threshold| Deduplicator::new(*hash, threshold));
+            let tracker = FocusTracker::new(Duration::from_millis(*throttle), dedupe);
+            focus(
+                &session,
+                Duration::from_millis(*interval),
+                tracker,
+                *decorations,
+                *count,
+                dir,
+            )
+        }
+        Some(Command::Monitor {
+            display,
+            region,
+            interval,
+            threshold,
+            tolerance,
+            notify_on_change,
+            count,
+            dir,
+        }) => monitor(
+            &session,
+            display,
+            *region,
+            Duration::from_millis(*interval),
+            Monitor::new(
+                *threshold,
+                DiffOptions::default().with_tolerance(*tolerance),
+            ),
+            *notify_on_change,
+            *count,
+            dir,
+        ),
+        #[cfg(feature = "serve")]
+        Some(Command::Serve { listen, token }) => serve(listen, token.clone(), cli.json),
+        #[cfg(feature = "grpc")]
+        Some(Command::Grpc { listen, token }) => grpc(listen, token.clone(), cli.json),
+        #[cfg(all(feature = "dbus", target_os = "linux"))]
+        Some(Command::Dbus) => dbus(&session),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui { cursor_area }) => tui(&session, *cursor_area),
+        Some(Command::Prune { dir, retention }) => {
+            prune(dir, retention.policy(), cli.dry_run, cli.json)
+        }
+        Some(Command::Diff {
+            a,
+            b,
+            output,
+            threshold,
+            tolerance,
+        }) => diff(a, b, output.as_deref(), *threshold, *tolerance, cli.json),
+        Some(Command::Baseline { dir, action }) => {
+            baseline(dir.as_deref(), action, cli.dry_run, cli.json)
+        }
+        Some(Command::Pick {
+            x, y, live: false, ..
+        }) => pick(x.zip(*y), cli.json),
+        Some(Command::Pick {
+            x,
+            y,
+            live: true,
+            interval,
+            count,
+        }) => pick_live(
+            x.zip(*y),
+            Duration::from_millis(*interval),
+            *count,
+            cli.json,
+        ),
+        Some(Command::Magnify {
+            x,
+            y,
+            radius,
+            zoom,
+            no_grid,
+            output,
+        }) => {
+            let mut magnifier = snap_scale::magnify::Magnifier::new(*zoom);
+            if *no_grid {
+                magnifier = magnifier.with_grid(None);
+            }
+            magnify(x.zip(*y), *radius, magnifier, output, cli.json)
+        }
+        Some(Command::Windows {
+            process,
+            each,
+            dir,
+            decorations,
+        }) => windows(
+            &session,
+            process.as_deref(),
+            (*each).then_some(dir.as_path()),
+            *decorations,
+        ),
+        Some(Command::Layout { width, ascii }) => {
+            let style = snap_scale::arrangement::Style {
+                width: *width,
+                ascii: *ascii,
+            };
+            let displays = default_backend()?.enumerate()?;
+            if cli.json {
+                for display in &displays {
+                    print_json(&serde_json::to_value(display)?);
+                }
+                return Ok(());
+            }
+            print!("{}", snap_scale::arrangement::render(&displays, style));
+            Ok(())
+        }
+        #[cfg(feature = "catalog")]
+        Some(Command::History { limit }) => {
+            let entries = open_catalog(&session.config)?.history(*limit)?;
+            print_entries(&entries, cli.json)
+        }
+        #[cfg(feature = "catalog")]
+        Some(Command::Search {
+            text,
+            tags,
+            display,
+            since,
+            until,
+            like,
+            distance,
+            limit,
+        }) => {
+            let similar_to = match like {
+                Some(path) => {
+                    let image = screenshots::image::open(path)?.into_rgba8();
+                    Some((snap_scale::hash::difference_hash(&image), *distance))
+                }
+                None => None,
+            };
+            let query = snap_scale::catalog::Query {
+                text: text.clone(),
+                tags: tags.clone(),
+                display: display.clone(),
+                since: *since,
+                until: *until,
+                similar_to,
+                limit: *limit,
+            };
+            let entries = open_catalog(&session.config)?.search(&query)?;
+            print_entries(&entries, cli.json)
+        }
+        Some(Command::Histogram {
+            input,
+            display,
+            region,
+            output,
+            size,
+        }) => histogram(
+            input.as_deref(),
+            display,
+            *region,
+            output.as_deref(),
+            *size,
+            cli.json,
+        ),
+        Some(Command::WaitFor {
+            image,
+            gone,
+            display,
+            threshold,
+            interval,
+            output,
+        }) => wait_for(
+            &session,
+            (image, *gone),
+            display.as_ref(),
+            *threshold,
+            Duration::from_millis(*interval),
+            cli.timeout
+                .filter(|&seconds| seconds > 0)
+                .map(Duration::from_secs),
+            output.as_deref(),
+        ),
+        Some(Command::Locate {
+            needle,
+            display,
+            input,
+            threshold,
+            max,
+        }) => locate(
+            needle,
+            display.as_ref(),
+            input.as_deref(),
+            *threshold,
+            *max,
+            cli.json,
+        ),
+        Some(Command::Palette {
+            input,
+            display,
+            colors,
+        }) => palette(input.as_deref(), display, *colors, cli.json),
+        Some(
+            command @ Command::Capture {
+                select: true,
+                freeze,
+                ..
+            },
+        ) => capture_selection(
+            &session,
+            *freeze,
+            command
+                .output()
+                .unwrap_or(Path::new("target/selection.png")),
+        ),
+        Some(
+            command @ Command::Capture {
+                display,
+                preview: Some(scale),
+                ..
+            },
+        ) => capture_preview(
+            &session,
+            display,
+            *scale,
+            command.output().unwrap_or(Path::new("target/preview.png")),
+        ),
+        Some(
+            command @ Command::Capture {
+                display, at_cursor, ..
+            },
+        ) => match (command.output(), at_cursor) {
+            (Some(output), None) => capture_to(&session, display, output),
+            (output, Some(size)) => capture_at_cursor(
+                &session,
+                *size,
+                output.unwrap_or(Path::new("target/cursor.png")),
+            ),
+            (None, None) => capture_all(&session),
+        },
+        Some(Command::Completions { .. } | Command::Man { .. }) => {
+            unreachable!("handled before the config is loaded")
+        }
+        None => capture_all(&session),
+    }
+}
+
+/// Prints the man page, or writes a page per subcommand into `dir`
+fn man(dir: Option<&Path>) -> anyhow::Result<()> {
+    let command = Cli::command();
+    let Some(dir) = dir else {
+        clap_mangen::Man::new(command).render(&mut std::io::stdout())?;
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir)?;
+    clap_mangen::generate_to(command, dir)?;
+    Ok(())
+}
+
+/// Prints display changes in the background for the rest of the run, which
+/// keeps the cached display list current for long-running servers
+#[cfg_attr(
+    not(any(
+        feature = "serve",
+        feature = "grpc",
+        all(feature = "dbus", target_os = "linux")
+    )),
+    allow(dead_code)
+)]
+fn follow_displays(json: bool) {
+    let watcher =
+        default_backend().and_then(|backend| DisplayWatcher::start(backend, HOTPLUG_INTERVAL));
+    match watcher {
+        Ok(watcher) => {
+            std::thread::spawn(move || {
+                for event in watcher.iter() {
+                    print_event(&event, json);
+                }
+            });
+        }
+        Err(e) => tracing::warn!("not watching for display changes: {e}"),
+    }
+}
+
+/// Prints a display change as text or a line of JSON
+fn print_event(event: &snap_scale::hotplug::DisplayEvent, json: bool) {
+    match json {
+        true => print_json(&serde_json::json!({
+            "event": "display",
+            "change": event.to_string(),
+        })),
+        false => println!("{event}"),
+    }
+}
+
+/// Runs the HTTP server until interrupted
+#[cfg(feature = "serve")]
+fn serve(listen: &str, token: Option<String>, json: bool) -> anyhow::Result<()> {
+    follow_displays(json);
+    let token = token.or_else(|| std::env::var("SNAP_SCALE_TOKEN").ok());
+    let server =
+        snap_scale::serve::Server::bind(listen, default_backend()?)?.with_token(token.clone());
+    let addr = server.local_addr();
+    if token.is_none() && !addr.is_some_and(|addr| addr.ip().is_loopback()) {
+        tracing::warn!("anyone who can reach {listen} can capture this screen; set --token");
+    }
+    let url = format!(
+        "http://{}",
+        addr.map_or(listen.to_owned(), |a| a.to_string())
+    );
+    match json {
+        true => print_json(&serde_json::json!({ "event": "listening", "url": url })),
+        false => println!("listening on {url}"),
+    }
+    Ok(server.run()?)
+}
+
+/// Runs the gRPC server until interrupted
+#[cfg(feature = "grpc")]
+fn grpc(listen: &str, token: Option<String>, json: bool) -> anyhow::Result<()> {
+    follow_displays(json);
+    let token = token.or_else(|| std::env::var("SNAP_SCALE_TOKEN").ok());
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(listen)
+            .await
+            .with_context(|| format!("listening on {listen}"))?;
+        let addr = listener.local_addr()?;
+        if token.is_none() && !addr.ip().is_loopback() {
+            tracing::warn!("anyone who can reach {listen} can capture this screen; set --token");
+        }
+        match json {
+            true => print_json(&serde_json::json!({ "event": "listening", "address": addr })),
+            false => println!("serving gRPC on {addr}"),
+        }
+        snap_scale::grpc::serve(listener, default_backend()?, token).await?;
+        Ok(())
+    })
+}
+
+/// Answers D-Bus capture calls, one at a time, until interrupted
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+fn dbus(session: &Session) -> anyhow::Result<()> {
+    // Calls stop arriving once the connection is dropped
+    let (_connection, jobs) = snap_scale::dbus::serve()?;
+    follow_displays(session.json);
+    match session.json {
+        true => print_json(&serde_json::json!({
+            "event": "listening",
+            "name": snap_scale::dbus::NAME,
+            "path": snap_scale::dbus::PATH,
+        })),
+        false => println!(
+            "serving {} at {} on the session bus",
+            snap_scale::dbus::NAME,
+            snap_scale::dbus::PATH
+        ),
+    }
+    for job in jobs {
+        let result = capture_target(session, job.target, job.path.clone());
+        if let Err(e) = &result {
+            tracing::warn!("D-Bus capture failed: {e:#}");
+        }
+        job.finish(result.map_err(|e| format!("{e:#}")));
+    }
+    Ok(())
+}
+
+/// Captures what a D-Bus call or control socket command asked for, to
+/// `path` or a timestamped file under `target`
+fn capture_target(
+    session: &Session,
+    target: Target,
+    path: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    use snap_scale::source::Source;
+
+    let screens = screens()?;
+    let on_display = |screens: Vec<ScreenCapture>, index: usize, region: Option<Region>| {
+        let screen = screens
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| anyhow::anyhow!("no display #{index}"))?;
+        session.before_capture(&screen.display.id.to_string())?;
+        let mut image = screen.backend.capture(index, region)?;
+        session.redact(&mut image, &screen, region)?;
+        anyhow::Ok((screen, image, region))
+    };
+    let (screen, image, region) = match target {
+        Target::Display(index) => on_display(screens, index, None)?,
+        Target::Region { display, region } => on_display(screens, display, Some(region))?,
+        Target::Window(id) => {
+            let window = snap_scale::window::list()?
+                .into_iter()
+                .find(|window| window.id == id)
+                .ok_or_else(|| anyhow::anyhow!("no window {id}"))?;
+            let (screen, image, region) =
+                capture_window(session, screens, &window, Decorations::default())?;
+            (screen, image, Some(region))
+        }
+    };
+    let id = screen.display.id.to_string();
+    let path = path.unwrap_or_else(|| timestamped(&id));
+    session.save(&image, path, &screen, region)
+}
+
+/// `target/<display>-<time>.png`, for captures nobody named
+fn timestamped(display: &str) -> PathBuf {
+    let time = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f");
+    PathBuf::from(format!("target/{display}-{time}.png"))
+}
+
+/// Runs the terminal front end until quit, `m` capturing `cursor_area`
+/// around the mouse cursor
+#[cfg(feature = "tui")]
+fn tui(session: &Session, cursor_area: Extent) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !session.json,
+        "--json can't be combined with tui, which draws on stdout"
+    );
+    let host = TuiHost {
+        session,
+        cursor_area,
+        captured: Default::default(),
+    };
+    Ok(snap_scale::tui::run(&host)?)
+}
+
+/// Captures for the TUI through the session, like any other command
+#[cfg(feature = "tui")]
+struct TuiHost<'a> {
+    session: &'a Session,
+    cursor_area: Extent,
+    /// Captures made this run, newest last; listed when there's no catalog
+    captured: std::cell::RefCell<Vec<snap_scale::tui::Recent>>,
+}
+
+#[cfg(feature = "tui")]
+impl TuiHost<'_> {
+    fn screen(&self, index: usize) -> anyhow::Result<ScreenCapture> {
+        screens()?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| anyhow::anyhow!("no display #{index}"))
+    }
+
+    fn capture_paths(
+        &self,
+        mode: snap_scale::tui::Mode,
+        index: usize,
+    ) -> anyhow::Result<Vec<(String, PathBuf)>> {
+        use snap_scale::tui::Mode;
+
+        let session = self.session;
+        let on_screen = |screen: &ScreenCapture| {
+            let id = screen.display.id.to_string();
+            let path = session.capture_display(screen, timestamped(&id))?;
+            anyhow::Ok((id, path))
+        };
+        match mode {
+            Mode::Display => Ok(vec![on_screen(&self.screen(index)?)?]),
+            Mode::All => {
+                let mut batch = BatchResult::new();
+                for screen in screens()? {
+                    batch.record(format!("display {}", screen.display.id), on_screen(&screen));
+                }
+                match batch.status() {
+                    BatchStatus::Complete => {
+                        Ok(batch.successes().map(|(_, s)| s.clone()).collect())
+                    }
+                    _ => Err(batch.into()),
+                }
+            }
+            Mode::Area => {
+                let screen = self.screen(index)?;
+                let id = screen.display.id.to_string();
+                let path = capture_test_area(session, &screen, timestamped(&format!("{id}-area")))?;
+                Ok(vec![(id, path)])
+            }
+            Mode::Cursor => {
+                let (screen, area) = cursor_area(self.cursor_area)?;
+                let id = screen.display.id.to_string();
+                let path = timestamped(&format!("{id}-cursor"));
+                Ok(vec![(
+                    id.clone(),
+                    capture_region(session, &screen, area, &path)?,
+                )])
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl snap_scale::tui::Host for TuiHost<'_> {
+    fn displays(&self) -> snap_scale::Result<Vec<DisplayDescriptor>> {
+        default_backend()?.enumerate()
+    }
+
+    fn preview(&self, index: usize, scale: f32) -> snap_scale::Result<RgbaImage> {
+        let backend = default_backend()?;
+        let display = backend
+            .enumerate()?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| snap_scale::Error::Unsupported(format!("no display #{index}")))?;
+        ScreenCapture::new(backend, display).preview(scale)
+    }
+
+    fn recent(&self, limit: usize) -> snap_scale::Result<Vec<snap_scale::tui::Recent>> {
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = &self.session.catalog {
+            let recent = catalog.history(limit)?.into_iter().map(|entry| {
+                let time = chrono::DateTime::parse_from_rfc3339(&entry.captured_at)
+                    .map_or(entry.captured_at, |t| {
+                        t.format("%Y-%m-%d %H:%M:%S").to_string()
+                    });
+                snap_scale::tui::Recent {
+                    path: entry.path,
+                    display: entry.display,
+                    time,
+                }
+            });
+            return Ok(recent.collect());
+        }
+        let captured = self.captured.borrow();
+        Ok(captured.iter().rev().take(limit).cloned().collect())
+    }
+
+    fn capture(
+        &self,
+        mode: snap_scale::tui::Mode,
+        index: usize,
+    ) -> std::result::Result<Vec<PathBuf>, String> {
+        let saved = self
+            .capture_paths(mode, index)
+            .map_err(|e| format!("{e:#}"))?;
+        let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let mut captured = self.captured.borrow_mut();
+        for (display, path) in &saved {
+            captured.push(snap_scale::tui::Recent {
+                path: path.clone(),
+                display: display.clone(),
+                time: time.clone(),
+            });
+        }
+        Ok(saved.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+/// Lists the windows of `process`, or all of them, capturing `decorations`
+/// of each to a file in `each` named after its title
+fn windows(
+    session: &Session,
+    process: Option<&str>,
+    each: Option<&Path>,
+    decorations: Decorations,
+) -> anyhow::Result<()> {
+    let windows: Vec<_> = snap_scale::window::list()?
+        .into_iter()
+        // snap_scale's own windows are never what's wanted
+        .filter(|window| window.pid != Some(std::process::id()))
+        .filter(|window| process.is_none_or(|process| window.belongs_to(process)))
+        .collect();
+    if let Some(process) = process {
+        anyhow::ensure!(!windows.is_empty(), "no windows of {process}");
+    }
+    let Some(dir) = each else {
+        for window in &windows {
+            if session.json {
+                print_json(&serde_json::json!({
+                    "id": window.id,
+                    "pid": window.pid,
+                    "app": window.app,
+                    "title": window.title,
+                    "region": window.region,
+                }));
+                continue;
+            }
+            let pid = window.pid.map_or("-".into(), |pid| pid.to_string());
+            let r = &window.region;
+            println!(
+                "{:>10} {pid:>7} {:<16} {}x{} at {},{}  {}",
+                window.id, window.app, r.width, r.height, r.x, r.y, window.title
+            );
+        }
+        return Ok(());
+    };
+
+    // Windows with the same title get numbered
+    let mut taken = std::collections::HashSet::new();
+    let mut saved = 0;
+    for window in &windows {
+        let stem = window.file_stem();
+        let name = (1..)
+            .map(|n| match n {
+                1 => format!("{stem}.png"),
+                n => format!("{stem}-{n}.png"),
+            })
+            .find(|name| taken.insert(name.clone()))
+            .expect("a free name");
+        let result = capture_window(session, screens()?, window, decorations).and_then(
+            |(screen, image, region)| session.save(&image, dir.join(&name), &screen, Some(region)),
+        );
+        match result {
+            Ok(path) => {
+                saved += 1;
+                if !session.json {
+                    println!("{}  {}", path.display(), window.title);
+                }
+            }
+            // Windows can close or move off-screen while the others are captured
+            Err(e) => tracing::warn!("skipping window {} ({}): {e:#}", window.id, window.title),
+        }
+    }
+    anyhow::ensure!(
+        saved > 0 || windows.is_empty(),
+        "none of the windows could be captured"
+    );
+    Ok(())
+}
+
+/// Captures `decorations` of `window` on whichever of `screens` shows its
+/// center, returning that display, the image and the display-local logical
+/// area captured
+///
+/// The window's own contents come out whole, covered parts too, when the
+/// platform can read them; else whatever of it shows on the display.
+fn capture_window(
+    session: &Session,
+    screens: Vec<ScreenCapture>,
+    window: &snap_scale::window::WindowInfo,
+    decorations: Decorations,
+) -> anyhow::Result<(ScreenCapture, RgbaImage, Region)> {
+    use snap_scale::source::Source;
+
+    let bounds: Vec<_> = screens
+        .iter()
+        .map(|screen| {
+            let info = &screen.display;
+            Region::new(info.x, info.y, info.width, info.height)
+        })
+        .collect();
+    let area = snap_scale::window::bounds(window, decorations)?;
+    let (index, region) = snap_scale::window::locate(&area, &bounds)
+        .ok_or_else(|| anyhow::anyhow!("window {} isn't on a display", window.id))?;
+    let screen = screens
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| anyhow::anyhow!("no display #{index}"))?;
+    session.before_capture(&screen.display.id.to_string())?;
+    if screen.dry_run {
+        let image = screen.placeholder(Some(region))?;
+        return Ok((screen, image, region));
+    }
+    let image = match snap_scale::window::capture(window.id, decorations) {
+        Ok(mut image) => {
+            // No other window is in it, so only this one can need redacting
+            let redact = &session.config.redact;
+            if redact.matches(window) {
+                let (width, height) = image.dimensions();
+                snap_scale::mask::Mask::new(Region::new(0, 0, width, height), redact.style)
+                    .with_strength(redact.strength)
+                    .apply_to(&mut image);
+            }
+            image
+        }
+        Err(e) => {
+            tracing::debug!("falling back to the screen: {e}");
+            let mut image = screen.backend.capture(index, Some(region))?;
+            session.redact(&mut image, &screen, Some(region))?;
+            image
+        }
+    };
+    Ok((screen, image, region))
+}
+
+/// Deletes captures in `dir` that break `retention`, or lists them
+fn prune(dir: &Path, retention: Retention, dry_run: bool, json: bool) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        retention.is_active(),
+        "give --max-age, --max-count or --max-size"
+    );
+    let expired = if dry_run {
+        retention.plan(dir, std::time::SystemTime::now())?
+    } else {
+        retention.prune(dir)?
+    };
+    if json {
+        for capture in &expired {
+            print_json(&serde_json::json!({
+                "event": "pruned",
+                "path": capture.path,
+                "bytes": capture.size,
+                "dry_run": dry_run,
+            }));
+        }
+        return Ok(());
+    }
+    let verb = if dry_run { "would delete" } else { "deleted" };
+    for capture in &expired {
+        println!("{verb} {}", capture.path.display());
+    }
+    let bytes: u64 = expired.iter().map(|capture| capture.size).sum();
+    println!("{verb} {} captures, {bytes} bytes", expired.len());
+    Ok(())
+}
+
+/// Opens the catalog at `[catalog] path` or the default location
+#[cfg(feature = "catalog")]
+fn open_catalog(config: &Config) -> anyhow::Result<snap_scale::catalog::Catalog> {
+    let path = config
+        .catalog
+        .path
+        .clone()
+        .or_else(snap_scale::catalog::Catalog::default_path)
+        .ok_or_else(|| anyhow::anyhow!("no data directory for the catalog; set [catalog] path"))?;
+    Ok(snap_scale::catalog::Catalog::open(path)?)
+}
+
+/// Prints catalog entries as `time  display  path  tags` lines or JSON
+#[cfg(feature = "catalog")]
+fn print_entries(entries: &[snap_scale::catalog::Entry], json: bool) -> anyhow::Result<()> {
+    if json {
+        for entry in entries {
+            print_json(&serde_json::to_value(entry)?);
+        }
+        return Ok(());
+    }
+    for entry in entries {
+        let mut line = format!(
+            "{}  {}  {}",
+            entry.captured_at,
+            entry.display,
+            entry.path.display()
+        );
+        if !entry.tags.is_empty() {
+            line += &format!("  [{}]", entry.tags.join(", "));
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Prints the k-means palette of a capture or image file
+fn palette(
+    input: Option<&Path>,
+    display: &DisplaySelector,
+    colors: usize,
+    json: bool,
+) -> anyhow::Result<()> {
+    let image = match input {
+        Some(path) => screenshots::image::open(path)?.into_rgba8(),
+        None => select_screen(display)?.capture()?,
+    };
+    let swatches = snap_scale::analysis::palette(&image, colors);
+
+    if json {
+        for s in &swatches {
+            print_json(&serde_json::json!({
+                "hex": s.color.hex(),
+                "rgb": [s.color.r, s.color.g, s.color.b],
+                "share": s.share,
+            }));
+        }
+    } else {
+        for swatch in swatches {
+            println!("{}  {:5.1}%", swatch.color.hex(), swatch.share * 100.0);
+        }
+    }
+    Ok(())
+}
+
+/// A match of [`snap_scale::locate::locate`], in desktop-global logical
+/// coordinates, or an input image's pixels
+struct Found {
+    region: Region,
+    /// The display it's on, and where in its physical pixels
+    on: Option<(u32, i32, i32)>,
+    score: f32,
+}
+
+impl Found {
+    /// What `--json` prints for it
+    fn json(&self) -> serde_json::Value {
+        let region = self.region;
+        let mut line = serde_json::json!({
+            "x": region.x,
+            "y": region.y,
+            "width": region.width,
+            "height": region.height,
+            "score": self.score,
+        });
+        if let Some((display, x, y)) = self.on {
+            line["display"] = display.into();
+            line["physical"] = serde_json::json!([x, y]);
+        }
+        line
+    }
+}
+
+impl std::fmt::Display for Found {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Region {
+            x,
+            y,
+            width,
+            height,
+        } = self.region;
+        write!(f, "{x},{y} {width}x{height}  score {:.3}", self.score)?;
+        if let Some((display, px, py)) = self.on {
+            write!(f, "  (display {display}, physical {px},{py})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the image `locate` and `wait-for` look for
+fn open_needle(path: &Path) -> anyhow::Result<RgbaImage> {
+    Ok(screenshots::image::open(path)
+        .with_context(|| path.display().to_string())?
+        .into_rgba8())
+}
+
+/// Where `needle` appears on `display`, or on every display, best first
+fn find_on_screen(
+    needle: &RgbaImage,
+    display: Option<&DisplaySelector>,
+    threshold: f32,
+) -> anyhow::Result<Vec<Found>> {
+    let (width, height) = needle.dimensions();
+    let screens = match display {
+        Some(display) => vec![select_screen(display)?],
+        None => screens()?,
+    };
+    let mut found = Vec::new();
     for screen in screens {
-        println!("capturer {screen:?}");
-        let capturer = ScreenCapture::from_screen(screen);
+        let haystack = screen.capture()?;
+        let mapper = CoordinateMapper::probe(&*screen.backend, &screen.display);
+        let display = &screen.display;
+        for m in snap_scale::locate::locate(&haystack, needle, threshold)? {
+            let physical = Region::new(m.x as i32, m.y as i32, width, height);
+            found.push(Found {
+                region: mapper.to_logical(&physical).translate(display.x, display.y),
+                on: Some((display.id, physical.x, physical.y)),
+                score: m.score,
+            });
+        }
+    }
+    found.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(found)
+}
+
+/// Prints where `needle` appears in an image file, or on `display` or every
+/// display, failing when it's nowhere
+fn locate(
+    needle: &Path,
+    display: Option<&DisplaySelector>,
+    input: Option<&Path>,
+    threshold: f32,
+    max: Option<usize>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let image = open_needle(needle)?;
+    let found = match input {
+        Some(path) => {
+            let haystack = screenshots::image::open(path)?.into_rgba8();
+            let (width, height) = image.dimensions();
+            snap_scale::locate::locate(&haystack, &image, threshold)?
+                .into_iter()
+                .map(|m| Found {
+                    region: Region::new(m.x as i32, m.y as i32, width, height),
+                    on: None,
+                    score: m.score,
+                })
+                .collect()
+        }
+        None => find_on_screen(&image, display, threshold)?,
+    };
+    anyhow::ensure!(
+        !found.is_empty(),
+        "{} appears nowhere with a score of {threshold} or more",
+        needle.display()
+    );
+    for found in found.iter().take(max.unwrap_or(usize::MAX)) {
+        match json {
+            true => print_json(&found.json()),
+            false => println!("{found}"),
+        }
+    }
+    Ok(())
+}
+
+/// Looks for the image at `needle` every `interval` until it's there, or
+/// with `gone` until it isn't, for up to `timeout`, then captures to `output`
+fn wait_for(
+    session: &Session,
+    (needle, gone): (&Path, bool),
+    display: Option<&DisplaySelector>,
+    threshold: f32,
+    interval: Duration,
+    timeout: Option<Duration>,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let image = open_needle(needle)?;
+    let started = Instant::now();
+    let best = loop {
+        let found = find_on_screen(&image, display, threshold)?;
+        if found.is_empty() == gone {
+            break found.into_iter().next();
+        }
+        if let Some(after) = timeout.filter(|&after| started.elapsed() >= after) {
+            let what = match gone {
+                true => "disappear",
+                false => "appear",
+            };
+            return Err(snap_scale::Error::Timeout {
+                what: format!("waiting for {} to {what}", needle.display()),
+                after,
+            }
+            .into());
+        }
+        std::thread::sleep(interval);
+    };
+
+    let elapsed = started.elapsed();
+    match (&best, session.json) {
+        (Some(found), true) => {
+            let mut line = found.json();
+            line["event"] = "appeared".into();
+            line["elapsed_ms"] = (elapsed.as_millis() as u64).into();
+            print_json(&line);
+        }
+        (None, true) => print_json(&serde_json::json!({
+            "event": "gone",
+            "elapsed_ms": elapsed.as_millis() as u64,
+        })),
+        (Some(found), false) => {
+            println!("appeared after {:.1}s: {found}", elapsed.as_secs_f64())
+        }
+        (None, false) => println!("gone after {:.1}s", elapsed.as_secs_f64()),
+    }
+
+    let Some(path) = output else {
+        return Ok(());
+    };
+    let screen = match (display, best.and_then(|found| found.on)) {
+        (Some(display), _) => select_screen(display)?,
+        (None, Some((id, _, _))) => select_screen(&DisplaySelector::Id(id))?,
+        (None, None) => select_screen(&DisplaySelector::Index(0))?,
+    };
+    session.capture_display(&screen, path)?;
+    Ok(())
+}
+
+/// Prints the histograms and exposure of an image file or of `region` of a
+/// display, or all of it, plotting them to `output` at `size`
+fn histogram(
+    input: Option<&Path>,
+    display: &DisplaySelector,
+    region: Option<Region>,
+    output: Option<&Path>,
+    size: Extent,
+    json: bool,
+) -> anyhow::Result<()> {
+    let image = match (input, region) {
+        (Some(path), _) => screenshots::image::open(path)?.into_rgba8(),
+        (None, Some(region)) => {
+            let screen = select_screen(display)?;
+            let scaling = screen.scaling();
+            screen.capture_area(
+                scaling.scale_coordinate(region.x),
+                scaling.scale_coordinate(region.y),
+                scaling.scale_dimension(region.width),
+                scaling.scale_dimension(region.height),
+            )?
+        }
+        (None, None) => select_screen(display)?.capture()?,
+    };
+    let histogram = snap_scale::analysis::Histogram::of(&image);
+    let exposure = histogram.exposure();
+    if let Some(output) = output {
+        let format = output
+            .extension()
+            .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()))
+            .unwrap_or(OutputFormat::Png);
+        let plot = histogram.render(size.width, size.height);
+        let bytes = snap_scale::encode::encode_to_vec(&plot, &EncodeOptions::new(format))?;
+        if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        snap_scale::atomic::write(output, &bytes)?;
+    }
+
+    if json {
+        let mut line = serde_json::to_value(&histogram)?;
+        line["exposure"] = serde_json::to_value(exposure)?;
+        if let Some(output) = output {
+            line["path"] = serde_json::to_value(output)?;
+        }
+        print_json(&line);
+        return Ok(());
+    }
+    let [r, g, b] = exposure.mean;
+    println!("pixels: {}", histogram.pixels);
+    println!(
+        "mean luminance: {:.1} (median {})",
+        exposure.mean_luminance, exposure.median_luminance
+    );
+    println!("mean rgb: {r:.1}, {g:.1}, {b:.1}");
+    println!(
+        "clipped: {:.2}% black, {:.2}% white, {:.2}% with a channel at 0 or 255",
+        exposure.clipped_black, exposure.clipped_white, exposure.clipped_channels
+    );
+    Ok(())
+}
+
+/// Captures a display every `interval`, optionally dropping near-duplicates
+#[allow(clippy::too_many_arguments)]
+fn watch(
+    session: &Session,
+    display: &DisplaySelector,
+    interval: Duration,
+    count: Option<u64>,
+    dir: &Path,
+    mut dedupe: Option<Deduplicator>,
+    retention: Retention,
+    soak: Option<SoakHarness>,
+) -> anyhow::Result<()> {
+    let mut screen = select_screen(display)?;
+    let mut id = screen.display.id.to_string();
+    let watcher = DisplayWatcher::start(Arc::clone(&screen.backend), HOTPLUG_INTERVAL)
+        .map_err(|e| tracing::warn!("not watching for display changes: {e}"))
+        .ok();
 
-        let mut image = capturer.capture().unwrap();
-        image
-            .save(format!("target/{}.png", capturer.display_info().id))
-            .unwrap();
+    let mut saved = 0;
+    let mut last: Option<Instant> = None;
+    let mut soak = soak.as_ref().map(SoakHarness::monitor);
+    while count.is_none_or(|count| saved < count) {
+        let events = watcher
+            .as_ref()
+            .map(DisplayWatcher::poll)
+            .unwrap_or_default();
+        if !events.is_empty() {
+            for event in &events {
+                print_event(event, session.json);
+            }
+            // Indexes shift as displays come and go, so look it up again
+            match select_screen(display) {
+                Ok(current) => {
+                    screen = current;
+                    id = screen.display.id.to_string();
+                }
+                Err(e) => {
+                    tracing::warn!("{e}; waiting for it");
+                    last = None;
+                    std::thread::sleep(interval);
+                    continue;
+                }
+            }
+        }
+        session.before_capture(&id)?;
+        let started = Instant::now();
+        if let Some(last) = last {
+            METRICS.frame_interval(started - last);
+        }
+        last = Some(started);
+        let capture = tracing::debug_span!("capture", display = %id).in_scope(|| screen.capture());
+        let mut image = match capture {
+            Ok(image) => image,
+            Err(e) => {
+                METRICS.capture_failed();
+                return Err(e.into());
+            }
+        };
+        METRICS.captured();
+        session.redact(&mut image, &screen, None)?;
+        if dedupe.as_mut().is_some_and(|d| d.is_duplicate(&image)) {
+            match session.json {
+                true => print_json(&serde_json::json!({ "event": "duplicate", "display": id })),
+                false => println!("skipped duplicate frame"),
+            }
+        } else {
+            saved += 1;
+            session.save(
+                &image,
+                dir.join(format!("{id}-{saved:06}.png")),
+                &screen,
+                None,
+            )?;
+            if retention.is_active() {
+                for capture in retention.prune(dir)? {
+                    match session.json {
+                        true => print_json(&serde_json::json!({
+                            "event": "pruned",
+                            "path": capture.path,
+                            "bytes": capture.size,
+                            "dry_run": false,
+                        })),
+                        false => println!("pruned {}", capture.path.display()),
+                    }
+                }
+            }
+        }
+        if let Some(soak) = &mut soak {
+            soak.frame();
+        }
+        std::thread::sleep(interval);
+    }
+    let Some(soak) = soak else {
+        return Ok(());
+    };
+    let report = soak.finish();
+    match session.json {
+        true => print_json(&serde_json::json!({
+            "event": "soak",
+            "frames": report.frames,
+            "seconds": report.elapsed.as_secs_f64(),
+            "rss_bytes": [report.baseline.rss_bytes, report.peak.rss_bytes],
+            "open_handles": [report.baseline.open_handles, report.last.open_handles],
+        })),
+        false => println!("soaked {report}"),
+    }
+    let violations = report.violations();
+    anyhow::ensure!(
+        violations.is_empty(),
+        "soak run leaked: {}",
+        violations.join("; ")
+    );
+    Ok(())
+}
+
+/// Prints diff metrics and fails when the change exceeds `threshold` percent
+fn diff(
+    a: &Path,
+    b: &Path,
+    output: Option<&Path>,
+    threshold: f64,
+    tolerance: u8,
+    json: bool,
+) -> anyhow::Result<()> {
+    let a = screenshots::image::open(a)?.into_rgba8();
+    let b = screenshots::image::open(b)?.into_rgba8();
+    let report = compare(&a, &b, &DiffOptions::default().with_tolerance(tolerance))?;
+
+    if json {
+        print_json(&serde_json::json!({
+            "changed_pixels": report.changed_pixels,
+            "changed_percent": report.changed_percent(),
+            "ssim": report.ssim,
+            "psnr": report.psnr,
+        }));
+    } else {
+        println!(
+            "changed: {} px ({:.3}%)",
+            report.changed_pixels,
+            report.changed_percent()
+        );
+        println!("ssim:    {:.5}", report.ssim);
+        println!("psnr:    {:.2} dB", report.psnr);
+    }
+    if let Some(output) = output {
+        snap_scale::atomic::save(&report.heatmap, output)?;
+    }
+
+    anyhow::ensure!(
+        report.changed_percent() <= threshold,
+        "difference exceeds the {threshold}% threshold"
+    );
+    Ok(())
+}
+
+fn baseline(
+    dir: Option<&Path>,
+    action: &BaselineAction,
+    dry_run: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let regression = match dir {
+        Some(dir) => Regression::new(dir),
+        None => Regression::from_env(),
+    };
+
+    match action {
+        BaselineAction::List => {
+            for baseline in regression.list()? {
+                if json {
+                    print_json(&serde_json::json!({
+                        "name": baseline.name,
+                        "meta": baseline.meta,
+                        "pending": baseline.pending,
+                    }));
+                    continue;
+                }
+                let details = match &baseline.meta {
+                    Some(meta) => match &meta.display {
+                        Some(display) => format!(
+                            "{}x{}  display {} @ {}x (total {}x)",
+                            meta.width,
+                            meta.height,
+                            display.id,
+                            display.scale_factor,
+                            display.total_scale
+                        ),
+                        None => format!("{}x{}", meta.width, meta.height),
+                    },
+                    None => "no metadata".into(),
+                };
+                let pending = if baseline.pending {
+                    "  [failed capture pending]"
+                } else {
+                    ""
+                };
+                println!("{}  {details}{pending}", baseline.name);
+            }
+        }
+        BaselineAction::Approve { names } => {
+            for name in names {
+                if dry_run {
+                    match json {
+                        true => print_json(&serde_json::json!({
+                            "event": "approved",
+                            "name": name,
+                            "dry_run": true,
+                        })),
+                        false => println!("would approve {name}"),
+                    }
+                    continue;
+                }
+                regression.approve(name)?;
+                match json {
+                    true => print_json(&serde_json::json!({ "event": "approved", "name": name })),
+                    false => println!("approved {name}"),
+                }
+            }
+        }
+        BaselineAction::Prune => {
+            for name in regression.prune(dry_run)? {
+                let verb = if dry_run { "would delete" } else { "deleted" };
+                match json {
+                    true => print_json(&serde_json::json!({
+                        "event": "pruned",
+                        "name": name,
+                        "dry_run": dry_run,
+                    })),
+                    false => println!("{verb} {name}"),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the color of one pixel in RGBA, hex and HSL notation
+fn pick(position: Option<(i32, i32)>, json: bool) -> anyhow::Result<()> {
+    let (x, y) = match position {
+        Some(position) => position,
+        None => snap_scale::cursor::position()?,
+    };
+    let screen = ScreenCapture::from_point(x, y)?;
+    let mapper = CoordinateMapper::probe(&*screen.backend, &screen.display);
+    let local = mapper
+        .global_to_local(&Region::new(x, y, 1, 1))
+        .ok_or_else(|| anyhow::anyhow!("{x},{y} is outside display {}", screen.display.id))?;
+    let physical = mapper.to_physical(&local);
+
+    let image = screen.capture_area(physical.x, physical.y, 1, 1)?;
+    let sample = Sample {
+        x,
+        y,
+        display: screen.display.id,
+        physical: (physical.x, physical.y),
+        color: Color::from(*image.get_pixel(0, 0)),
+    };
+    if json {
+        print_json(&sample_json(&sample));
+        return Ok(());
+    }
+    let color = sample.color;
+    println!(
+        "position: {x},{y} (display {}, physical {},{})",
+        sample.display, physical.x, physical.y
+    );
+    println!("{}", color.rgba_string());
+    println!("{}", color.hex());
+    println!("{}", color.hsl_string());
+    Ok(())
+}
+
+/// What `pick --json` prints for `sample`
+fn sample_json(sample: &Sample) -> serde_json::Value {
+    let color = sample.color;
+    serde_json::json!({
+        "x": sample.x,
+        "y": sample.y,
+        "display": sample.display,
+        "physical": [sample.physical.0, sample.physical.1],
+        "rgba": [color.r, color.g, color.b, color.a],
+        "hex": color.hex(),
+        "hsl": color.hsl_string(),
+    })
+}
 
-        image = capturer.capture_area(300, 300, 300, 300).unwrap();
-        image
-            .save(format!("target/{}-2.png", capturer.display_info().id))
-            .unwrap();
+/// Reads the pixel at `position`, or under the cursor, every `interval`
+/// and prints it whenever it changes, until Esc, `q` or Ctrl-C, or `count`
+/// reads
+///
+/// Keys are read when stdin is a terminal; otherwise it runs until
+/// interrupted. On a terminal the text output is one line, redrawn in place.
+fn pick_live(
+    position: Option<(i32, i32)>,
+    interval: Duration,
+    count: Option<u64>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+    use std::io::IsTerminal;
+
+    let mut dropper = Eyedropper::new(default_backend()?)?;
+    let keys = std::io::stdin().is_terminal() && crossterm::terminal::enable_raw_mode().is_ok();
+    // Raw mode leaves carriage returns to us
+    let newline = if keys { "\r\n" } else { "\n" };
+    let in_place = !json && std::io::stdout().is_terminal();
+    let mut stdout = std::io::stdout();
+    let mut last = None;
+    let mut reads = 0;
+    let result = (|| {
+        while count.is_none_or(|count| reads < count) {
+            reads += 1;
+            let (x, y) = match position {
+                Some(position) => position,
+                None => snap_scale::cursor::position()?,
+            };
+            let sample = match dropper.sample(x, y) {
+                // Off every display, e.g. one just unplugged
+                Err(snap_scale::Error::Invalid { .. }) => {
+                    dropper.refresh()?;
+                    dropper.sample(x, y)?
+                }
+                sample => sample?,
+            };
+            if last != Some(sample) {
+                last = Some(sample);
+                let color = sample.color;
+                match (json, in_place) {
+                    (true, _) => write!(stdout, "{}{newline}", sample_json(&sample))?,
+                    (false, true) => write!(
+                        stdout,
+                        "\r\x1b[2K{x},{y}  display {}  {}  {}  {}",
+                        sample.display,
+                        color.hex(),
+                        color.rgba_string(),
+                        color.hsl_string()
+                    )?,
+                    (false, false) => write!(
+                        stdout,
+                        "{x},{y}  display {}  {}  {}  {}{newline}",
+                        sample.display,
+                        color.hex(),
+                        color.rgba_string(),
+                        color.hsl_string()
+                    )?,
+                }
+                stdout.flush()?;
+            }
+            if !keys {
+                std::thread::sleep(interval);
+                continue;
+            }
+            if event::poll(interval)? {
+                if let Event::Key(key) = event::read()? {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl_c || matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                        break;
+                    }
+                }
+            }
+        }
+        anyhow::Ok(())
+    })();
+    if keys {
+        crossterm::terminal::disable_raw_mode()?;
+    }
+    if in_place && last.is_some() {
+        println!();
     }
+    result
+}
+
+/// Saves a close-up of the `radius` logical pixels around `position` or the
+/// cursor, printing the center pixel's color unless it goes to stdout
+fn magnify(
+    position: Option<(i32, i32)>,
+    radius: u32,
+    magnifier: snap_scale::magnify::Magnifier,
+    output: &Path,
+    json: bool,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !(json && is_stdout(output)),
+        "--json needs a file, not stdout"
+    );
+    let (x, y) = match position {
+        Some(position) => position,
+        None => snap_scale::cursor::position()?,
+    };
+    let screen = ScreenCapture::from_point(x, y)?;
+    let display = &screen.display;
+    let side = radius.saturating_mul(2).saturating_add(1);
+    let wanted = Region::around(x - display.x, y - display.y, side, side);
+    let visible = wanted
+        .clamp_to(&Region::new(0, 0, display.width, display.height))
+        .ok_or_else(|| anyhow::anyhow!("{x},{y} is outside display {}", display.id))?;
+    if screen.dry_run {
+        match json {
+            true => print_json(&serde_json::json!({
+                "event": "capture",
+                "dry_run": true,
+                "path": output,
+                "display": display.id,
+                "region": visible,
+            })),
+            false => println!(
+                "would magnify display {} (area {visible}) to {}",
+                display.id,
+                output.display()
+            ),
+        }
+        return Ok(());
+    }
+
+    let scaling = screen.scaling();
+    let pixels = screen.capture_area(
+        scaling.scale_coordinate(visible.x),
+        scaling.scale_coordinate(visible.y),
+        scaling.scale_dimension(visible.width),
+        scaling.scale_dimension(visible.height),
+    )?;
+    // Past the display's edges stays transparent, so the cursor's pixel is
+    // always the center one
+    let ratio = pixels.width() as f64 / visible.width as f64;
+    let physical = |logical: i64| (logical as f64 * ratio).round() as i64;
+    let size = physical(side as i64) as u32;
+    let mut close_up = RgbaImage::new(size, size);
+    screenshots::image::imageops::replace(
+        &mut close_up,
+        &pixels,
+        physical((visible.x - wanted.x) as i64),
+        physical((visible.y - wanted.y) as i64),
+    );
+    let (cx, cy) = snap_scale::magnify::Magnifier::center(&close_up);
+    let color = Color::from(*close_up.get_pixel(cx, cy));
+
+    let mut png = Vec::new();
+    snap_scale::encode(
+        &magnifier.magnify(&close_up),
+        &EncodeOptions::new(OutputFormat::Png),
+        &mut png,
+    )?;
+    if is_stdout(output) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&png)?;
+        return Ok(stdout.flush()?);
+    }
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    snap_scale::atomic::write(output, &png)?;
+    if json {
+        print_json(&serde_json::json!({
+            "event": "capture",
+            "path": output,
+            "display": display.id,
+            "x": x,
+            "y": y,
+            "hex": color.hex(),
+        }));
+        return Ok(());
+    }
+    println!(
+        "{} at {x},{y} (display {}) saved to {}",
+        color.hex(),
+        display.id,
+        output.display()
+    );
+    Ok(())
+}
 
-    let capturer = ScreenCapture::from_point(100, 100).unwrap();
-    println!("capturer {:?}", capturer.screen);
+/// Prints the payload of every QR code found on the selected displays
+#[cfg(feature = "scan")]
+fn scan_screens(
+    display: Option<&DisplaySelector>,
+    region: Option<snap_scale::Region>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let selected = match display {
+        Some(display) => vec![select_screen(display)?],
+        None => screens()?,
+    };
+
+    let mut found = 0;
+    for screen in selected {
+        let image = match region {
+            Some(region) => {
+                let scaling = screen.scaling();
+                screen.capture_area(
+                    scaling.scale_coordinate(region.x),
+                    scaling.scale_coordinate(region.y),
+                    scaling.scale_dimension(region.width),
+                    scaling.scale_dimension(region.height),
+                )?
+            }
+            None => screen.capture()?,
+        };
+        for detection in snap_scale::scan::scan(&image) {
+            match json {
+                true => print_json(&serde_json::json!({
+                    "display": screen.display.id,
+                    "payload": detection.payload,
+                })),
+                false => println!("{}", detection.payload),
+            }
+            found += 1;
+        }
+    }
+
+    anyhow::ensure!(found > 0, "no QR codes found");
+    Ok(())
+}
+
+impl Command {
+    /// Where `capture` writes its single capture, if it writes one
+    fn output(&self) -> Option<&Path> {
+        match self {
+            Self::Capture {
+                output, encoding, ..
+            } => match output {
+                Some(output) => Some(output),
+                None if *encoding != Encoding::Raw => Some(Path::new("-")),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Captures a copy of a display `scale` of its size to `path`
+fn capture_preview(
+    session: &Session,
+    display: &DisplaySelector,
+    scale: f32,
+    path: &Path,
+) -> anyhow::Result<()> {
+    check_single_output(session, path)?;
+    let screen = select_screen(display)?;
+    let id = screen.display.id.to_string();
+    session.before_capture(&id)?;
+    let redact = &session.config.redact;
+    let image = if redact.apps.is_empty() && redact.titles.is_empty() {
+        screen.preview(scale)?
+    } else {
+        // Windows are redacted where they are on the full-size capture
+        let mut image = screen.capture()?;
+        session.redact(&mut image, &screen, None)?;
+        let frame = Frame::from_image(&image, SystemTime::now());
+        snap_scale::preview::downsample(&frame, snap_scale::preview::check_scale(scale)?)
+    };
+    session.save(&image, path, &screen, None)?;
+    Ok(())
+}
+
+/// Captures one display to `path`, or to stdout for `-`
+fn capture_to(session: &Session, display: &DisplaySelector, path: &Path) -> anyhow::Result<()> {
+    check_single_output(session, path)?;
+    let screen = select_screen(display)?;
+    session.capture_display(&screen, path)?;
+    Ok(())
+}
+
+/// Captures `size` logical pixels around the cursor to `path`, moved in
+/// from the edges of the display under it
+fn capture_at_cursor(session: &Session, size: Extent, path: &Path) -> anyhow::Result<()> {
+    check_single_output(session, path)?;
+    let (screen, area) = cursor_area(size)?;
+    capture_region(session, &screen, area, path)?;
+    Ok(())
+}
+
+/// The display under the cursor and `size` logical pixels of it around the
+/// cursor, moved in from its edges
+fn cursor_area(size: Extent) -> anyhow::Result<(ScreenCapture, Region)> {
+    let (x, y) = snap_scale::cursor::position()?;
+    let screen = ScreenCapture::from_point(x, y)?;
+    let display = &screen.display;
+    let bounds = Region::new(0, 0, display.width, display.height);
+    let area = Region::around(x - display.x, y - display.y, size.width, size.height)
+        .shift_into(&bounds)
+        .ok_or_else(|| anyhow::anyhow!("display {} has no area", display.id))?;
+    let id = display.id;
+    tracing::info!(
+        display = id,
+        ?area,
+        "capturing around the cursor at {x},{y}"
+    );
+    Ok((screen, area))
+}
+
+/// Captures `region` of a display, or all of it, as its page scrolls,
+/// sending `auto` wheel notches a step or leaving the scrolling to the user,
+/// until it has stayed still for `idle` or is `max_height` pixels tall
+fn scroll(
+    session: &Session,
+    display: &DisplaySelector,
+    region: Option<Region>,
+    auto: Option<u32>,
+    (interval, idle): (Duration, Duration),
+    max_height: u32,
+    path: &Path,
+) -> anyhow::Result<()> {
+    use snap_scale::scroll::{ScrollStitcher, Step};
+
+    check_single_output(session, path)?;
+    let screen = select_screen(display)?;
+    let bounds = Region::new(0, 0, screen.display.width, screen.display.height);
+    let area = match region {
+        Some(region) => region
+            .clamp_to(&bounds)
+            .ok_or_else(|| anyhow::anyhow!("{region:?} is off the display"))?,
+        None => bounds,
+    };
+    let id = screen.display.id.to_string();
+    session.before_capture(&id)?;
+    let scaling = screen.scaling();
+    let grab = || -> anyhow::Result<RgbaImage> {
+        let mut frame = screen.capture_area(
+            scaling.scale_coordinate(area.x),
+            scaling.scale_coordinate(area.y),
+            scaling.scale_dimension(area.width),
+            scaling.scale_dimension(area.height),
+        )?;
+        session.redact(&mut frame, &screen, Some(area))?;
+        Ok(frame)
+    };
+    // The wheel goes to whatever is under the middle of the area
+    let center = (
+        screen.display.x + area.x + area.width as i32 / 2,
+        screen.display.y + area.y + area.height as i32 / 2,
+    );
+
+    let mut stitcher = ScrollStitcher::new(grab()?);
+    if auto.is_none() {
+        eprintln!(
+            "Scroll down the page; capturing stops once it stays still for {:.1}s.",
+            idle.as_secs_f64()
+        );
+    }
+    let (mut frames, mut still) = (1, Instant::now());
+    while stitcher.image().height() < max_height && still.elapsed() < idle {
+        if let Some(clicks) = auto {
+            snap_scale::scroll::wheel(center.0, center.1, clicks as i32)?;
+        }
+        std::thread::sleep(interval);
+        match stitcher.push(grab()?)? {
+            Step::Scrolled(rows) => {
+                frames += 1;
+                still = Instant::now();
+                let height = stitcher.image().height();
+                tracing::debug!(rows, height, "page scrolled");
+            }
+            Step::Unmoved => {}
+            Step::Lost if auto.is_some() => {
+                tracing::warn!("lost track of the page; try fewer --clicks");
+                break;
+            }
+            Step::Lost => {
+                tracing::warn!("lost track of the page; scroll less than a screenful at a time");
+            }
+        }
+    }
+
+    let mut image = stitcher.into_image();
+    if image.height() > max_height {
+        image = screenshots::image::imageops::crop_imm(&image, 0, 0, image.width(), max_height)
+            .to_image();
+    }
+    eprintln!(
+        "Stitched {frames} frame(s) into {}x{}.",
+        image.width(),
+        image.height()
+    );
+    session.save(&image, path, &screen, None)?;
+    Ok(())
+}
+
+/// Looks at the windows every `interval`, capturing each that newly matches
+/// a `[[rules]]` entry to the rule's directory, and takes the captures of
+/// the `[[schedules]]` as they come due
+fn daemon(
+    session: &Session,
+    interval: Duration,
+    socket: Option<&Path>,
+    tray: bool,
+) -> anyhow::Result<()> {
+    let mut engine = RuleEngine::new(&session.config.rules)?;
+    let now = || chrono::Local::now().naive_local();
+    let mut scheduler = Scheduler::new(&session.config.schedules, now())?;
+    anyhow::ensure!(
+        !engine.is_empty() || !scheduler.is_empty() || socket.is_some() || tray,
+        "the config has no [[rules]] or [[schedules]] to follow, and there's no --socket"
+    );
+    let commands = socket
+        .map(|path| {
+            let commands = snap_scale::control::listen(path)
+                .with_context(|| format!("listening on {}", path.display()))?;
+            match session.json {
+                true => print_json(&serde_json::json!({ "event": "listening", "socket": path })),
+                false => println!("listening on {}", path.display()),
+            }
+            anyhow::Ok(commands)
+        })
+        .transpose()?;
+    #[cfg(all(feature = "tray", target_os = "linux"))]
+    let commands = match tray {
+        true => Some(show_tray(session, commands)?),
+        false => commands,
+    };
+    loop {
+        for job in scheduler.due(now()) {
+            match capture_scheduled(session, job) {
+                Ok(saved) if !session.json => println!("{}: {}", job.name, saved.display()),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(schedule = job.name, "{e:#}"),
+            }
+        }
+        let windows: Vec<_> = match engine.is_empty() {
+            true => Vec::new(),
+            false => snap_scale::window::list()?
+                .into_iter()
+                .filter(|window| window.pid != Some(std::process::id()))
+                .collect(),
+        };
+        for (rule, window) in engine.poll(&windows) {
+            let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            let path = rule.dir.join(format!("{}-{time}.png", window.file_stem()));
+            session.trigger.set(Some(rule.trigger(window)));
+            let result = capture_window(session, screens()?, window, Decorations::Include)
+                .and_then(|(screen, image, region)| {
+                    session.save(&image, &path, &screen, Some(region))
+                });
+            // Unused when the capture failed
+            session.trigger.take();
+            match result {
+                Ok(saved) if !session.json => {
+                    println!("{}: {} -> {}", rule.name, window.title, saved.display())
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(rule = rule.name, "{}: {e:#}", window.title),
+            }
+        }
+        answer_commands(session, commands.as_ref(), interval);
+    }
+}
+
+/// Shows the daemon's tray icon, whose captures arrive with those of the
+/// control socket's `commands`, if it listens
+#[cfg(all(feature = "tray", target_os = "linux"))]
+fn show_tray(
+    session: &Session,
+    commands: Option<Receiver<snap_scale::control::Job>>,
+) -> anyhow::Result<Receiver<snap_scale::control::Job>> {
+    let (jobs, merged) = std::sync::mpsc::channel();
+    if let Some(commands) = commands {
+        let jobs = jobs.clone();
+        std::thread::spawn(move || {
+            for job in commands {
+                if jobs.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let displays = screens()?
+        .iter()
+        .enumerate()
+        .map(|(index, screen)| {
+            let display = &screen.display;
+            let name = match display.name.is_empty() {
+                true => format!("display {index}"),
+                false => display.name.clone(),
+            };
+            format!("{name} ({}x{})", display.width, display.height)
+        })
+        .collect();
+    let tray = snap_scale::tray::serve(displays, jobs, Config::default_path())?;
+    // Only the daemon shows one, once
+    let _ = session.tray.set(tray);
+    match session.json {
+        true => print_json(&serde_json::json!({ "event": "tray" })),
+        false => println!("showing the tray icon"),
+    }
+    Ok(merged)
+}
+
+/// Takes the captures of control socket commands and tray clicks as they
+/// arrive for `interval`, or just waits it out without either
+fn answer_commands(
+    session: &Session,
+    commands: Option<&Receiver<snap_scale::control::Job>>,
+    interval: Duration,
+) {
+    let deadline = Instant::now() + interval;
+    let Some(commands) = commands else {
+        return std::thread::sleep(interval);
+    };
+    loop {
+        let job = match commands.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => return,
+            // Nothing listens anymore
+            Err(RecvTimeoutError::Disconnected) => {
+                return std::thread::sleep(deadline.saturating_duration_since(Instant::now()))
+            }
+        };
+        let result = capture_target(session, job.target, job.path.clone());
+        match &result {
+            Ok(saved) if !session.json => println!("requested: {}", saved.display()),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("requested capture failed: {e:#}"),
+        }
+        job.finish(result.map_err(|e| format!("{e:#}")));
+    }
+}
+
+/// Captures the display of a due `[[schedules]]` job to its `dir`, named
+/// after the display and the time
+fn capture_scheduled(session: &Session, job: &Job) -> anyhow::Result<PathBuf> {
+    let screen = select_screen(&job.display)?;
+    let id = screen.display.id.to_string();
+    session.before_capture(&id)?;
+    let mut image = screen.capture()?;
+    session.redact(&mut image, &screen, None)?;
+    let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let path = job.dir.join(format!("{id}-{time}.png"));
+    session.save(&image, path, &screen, None)
+}
+
+/// Looks at the focused window every `interval`, saving it to `dir` each
+/// time `tracker` says to, until `count` saves
+fn focus(
+    session: &Session,
+    interval: Duration,
+    mut tracker: FocusTracker,
+    decorations: Decorations,
+    count: Option<u64>,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let mut saved = 0;
+    while count.is_none_or(|count| saved < count) {
+        let focused =
+            snap_scale::window::focused()?.filter(|window| window.pid != Some(std::process::id()));
+        let id = focused.as_ref().map(|window| window.id);
+        if let (Some(window), Some(_)) = (focused, tracker.observe(id, Instant::now())) {
+            let result = capture_window(session, screens()?, &window, decorations).and_then(
+                |(screen, image, region)| {
+                    if tracker.is_duplicate(window.id, &image) {
+                        return Ok(None);
+                    }
+                    let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+                    let path = dir.join(format!("{}-{time}.png", window.file_stem()));
+                    session.trigger.set(Some(Trigger {
+                        rule: "focus".into(),
+                        window: window.id,
+                        title: window.title.clone(),
+                        app: window.app.clone(),
+                        pid: window.pid,
+                    }));
+                    session.save(&image, &path, &screen, Some(region)).map(Some)
+                },
+            );
+            session.trigger.take();
+            match result {
+                Ok(Some(path)) => {
+                    saved += 1;
+                    match session.json {
+                        true => print_json(&serde_json::json!({
+                            "event": "focused",
+                            "window": window.id,
+                            "title": window.title,
+                            "app": window.app,
+                            "path": path,
+                        })),
+                        false => println!("{} -> {}", window.title, path.display()),
+                    }
+                }
+                Ok(None) => tracing::debug!("{} is unchanged since its last capture", window.title),
+                Err(e) => tracing::warn!("{}: {e:#}", window.title),
+            }
+        }
+        std::thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// Captures `region` of a display every `interval`, saving it to `dir`
+/// whenever `monitor` finds it changed, until `count` changes
+#[allow(clippy::too_many_arguments)]
+fn monitor(
+    session: &Session,
+    display: &DisplaySelector,
+    region: Region,
+    interval: Duration,
+    mut monitor: Monitor,
+    notify_on_change: bool,
+    count: Option<u64>,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let screen = select_screen(display)?;
+    let bounds = Region::new(0, 0, screen.display.width, screen.display.height);
+    let area = region
+        .clamp_to(&bounds)
+        .ok_or_else(|| anyhow::anyhow!("{region:?} is off the display"))?;
+    let id = screen.display.id.to_string();
+    let scaling = screen.scaling();
+    let mut changes = 0;
+    loop {
+        session.before_capture(&id)?;
+        let mut frame = screen.capture_area(
+            scaling.scale_coordinate(area.x),
+            scaling.scale_coordinate(area.y),
+            scaling.scale_dimension(area.width),
+            scaling.scale_dimension(area.height),
+        )?;
+        session.redact(&mut frame, &screen, Some(area))?;
+        if let Some(report) = monitor.check(frame.clone())? {
+            changes += 1;
+            let percent = report.changed_percent();
+            let path = dir.join(format!("{id}-{changes:06}.png"));
+            let saved = session.save(&frame, path, &screen, Some(area))?;
+            match session.json {
+                true => print_json(&serde_json::json!({
+                    "event": "changed",
+                    "display": id,
+                    "changed_percent": percent,
+                    "path": saved,
+                })),
+                false => println!("{percent:.1}% changed: {}", saved.display()),
+            }
+            if notify_on_change {
+                let notifications = &session.config.notifications;
+                if let Err(e) = snap_scale::notify::region_changed(&saved, percent, notifications) {
+                    tracing::warn!("{e}");
+                }
+            }
+            if count.is_some_and(|count| changes >= count) {
+                return Ok(());
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Captures an area picked by pointing at its corners, from a snapshot
+/// taken beforehand with `freeze`
+fn capture_selection(session: &Session, freeze: bool, path: &Path) -> anyhow::Result<()> {
+    check_single_output(session, path)?;
+    let live = default_backend()?;
+    let backend: Arc<dyn CaptureBackend> = if freeze {
+        let frozen = snap_scale::freeze::FrozenDesktop::capture(&*live)?;
+        eprintln!("Desktop frozen.");
+        Arc::new(frozen)
+    } else {
+        live
+    };
+    let corner = |which: &str| -> anyhow::Result<(i32, i32)> {
+        eprint!("Point at the {which} corner of the area and press Enter... ");
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        anyhow::ensure!(
+            std::io::stdin().read_line(&mut line)? > 0,
+            "selection cancelled"
+        );
+        let point = snap_scale::cursor::position()?;
+        eprintln!("{},{}", point.0, point.1);
+        Ok(point)
+    };
+    let first = corner("first")?;
+    let second = corner("opposite")?;
+
+    // Cut to the display the selection started on
+    let display = backend.display_at(first.0, first.1)?;
+    let bounds = Region::new(display.x, display.y, display.width, display.height);
+    let global = Region::spanning(first, second);
+    let area = global
+        .clamp_to(&bounds)
+        .map(|area| area.translate(-display.x, -display.y))
+        .ok_or_else(|| anyhow::anyhow!("the selection misses display {}", display.id))?;
+    if area.translate(display.x, display.y) != global {
+        let id = display.id;
+        tracing::warn!("selection cut to display {id}");
+    }
+    let screen = ScreenCapture::new(backend, display);
+    capture_region(session, &screen, area, path)?;
+    Ok(())
+}
+
+/// Captures the display-local logical `area` of `screen` to `path`
+fn capture_region(
+    session: &Session,
+    screen: &ScreenCapture,
+    area: Region,
+    path: &Path,
+) -> anyhow::Result<PathBuf> {
+    let id = screen.display.id.to_string();
+    session.before_capture(&id)?;
+    let scaling = screen.scaling();
+    let mut image = screen.capture_area(
+        scaling.scale_coordinate(area.x),
+        scaling.scale_coordinate(area.y),
+        scaling.scale_dimension(area.width),
+        scaling.scale_dimension(area.height),
+    )?;
+    session.redact(&mut image, screen, Some(area))?;
+    session.save(&image, path, screen, Some(area))
+}
+
+/// Fails for options that a single capture to `path` can't honor
+fn check_single_output(session: &Session, path: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        session.encoding == Encoding::Raw || is_stdout(path),
+        "--encoding prints to stdout; use --output - rather than a file"
+    );
+    if session.stdout.is_some() {
+        anyhow::ensure!(session.tile.is_none(), "--tile can't write to stdout");
+        anyhow::ensure!(
+            session.uploader.is_none(),
+            "--upload needs a file, not stdout"
+        );
+        anyhow::ensure!(session.ocr.is_none(), "--ocr needs a file, not stdout");
+        anyhow::ensure!(!session.sidecar, "--sidecar needs a file, not stdout");
+        anyhow::ensure!(!session.json, "--json needs a file, not stdout");
+    }
+    Ok(())
+}
+
+/// Captures every display plus a fixed test area, carrying on past the
+/// ones that fail; fails with the [`BatchResult`] when any did
+fn capture_all(session: &Session) -> anyhow::Result<()> {
+    // Logs the total time when it closes
+    let _span = tracing::info_span!("capture_all").entered();
+    let screens = screens()?;
+    let area = session.area;
+    let mut batch = BatchResult::new();
+
+    for capturer in screens {
+        tracing::info!(display = ?capturer.display, "capturing");
+        let id = capturer.display_info().id.to_string();
+
+        let saved = session.capture_display(&capturer, format!("target/{id}.png"));
+        batch.record(format!("display {id}"), saved);
+
+        let saved = capture_test_area(session, &capturer, format!("target/{id}-2.png"));
+        batch.record(format!("display {id} area {area}"), saved);
+    }
+
+    let saved = ScreenCapture::from_point(100, 100)
+        .map_err(anyhow::Error::from)
+        .and_then(|capturer| {
+            tracing::info!(display = ?capturer.display, "capturing");
+            capture_test_area(session, &capturer, "target/capture_display_with_point.png")
+        });
+    batch.record(format!("display at 100,100 area {area}"), saved);
+
+    match batch.status() {
+        BatchStatus::Complete => Ok(()),
+        _ => Err(batch.into()),
+    }
+}
 
-    let image = capturer.capture_area(300, 300, 300, 300).unwrap();
-    image.save("target/capture_display_with_point.png").unwrap();
-    println!("Time elapsed: {:?}", start.elapsed());
+/// Captures the session's area of `capturer` to `path`
+fn capture_test_area(
+    session: &Session,
+    capturer: &ScreenCapture,
+    path: impl AsRef<Path>,
+) -> anyhow::Result<PathBuf> {
+    let id = capturer.display_info().id.to_string();
+    session.before_capture(&id)?;
+    let area = session.area;
+    let mut image = tracing::debug_span!("capture", display = %id, ?area)
+        .in_scope(|| capturer.capture_area(area.x, area.y, area.width, area.height))?;
+    session.redact(&mut image, capturer, Some(area))?;
+    session.save(&image, path, capturer, Some(area))
 }
@@ -0,0 +1,204 @@
+//! Obscuring sensitive areas of a capture
+//!
+//! A [`Mask`] blurs or pixelates one region of the image as a pipeline
+//! [`Transform`]. Regions are in image pixels and are clipped to the image,
+//! so masks partly off-screen are fine.
+
+use crate::geometry::Region;
+use crate::transform::Transform;
+use crate::{Error, Result};
+use screenshots::image::{imageops, Rgba, RgbaImage};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Default blur sigma / pixelation block size in pixels
+pub const DEFAULT_STRENGTH: u32 = 16;
+
+/// How a masked region is obscured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskStyle {
+    /// Gaussian blur
+    #[default]
+    Blur,
+    /// Blocks filled with their average color
+    Pixelate,
+}
+
+impl FromStr for MaskStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "blur" => Ok(Self::Blur),
+            "pixelate" => Ok(Self::Pixelate),
+            _ => Err(Error::invalid("mask style (blur, pixelate)", s)),
+        }
+    }
+}
+
+/// A region to obscure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mask {
+    pub region: Region,
+    pub style: MaskStyle,
+    /// Blur sigma or pixel block size; larger hides more
+    pub strength: u32,
+}
+
+impl Mask {
+    pub fn new(region: Region, style: MaskStyle) -> Self {
+        Self {
+            region,
+            style,
+            strength: DEFAULT_STRENGTH,
+        }
+    }
+
+    pub fn blur(region: Region) -> Self {
+        Self::new(region, MaskStyle::Blur)
+    }
+
+    pub fn pixelate(region: Region) -> Self {
+        Self::new(region, MaskStyle::Pixelate)
+    }
+
+    pub fn with_strength(mut self, strength: u32) -> Self {
+        self.strength = strength.max(1);
+        self
+    }
+
+    /// Obscures the region in place
+    pub fn apply_to(&self, image: &mut RgbaImage) {
+        let frame = Region::new(0, 0, image.width(), image.height());
+        let Some(region) = self.region.clamp_to(&frame) else {
+            return;
+        };
+        let (x, y) = (region.x as u32, region.y as u32);
+        let area = imageops::crop_imm(image, x, y, region.width, region.height).to_image();
+
+        let obscured = match self.style {
+            MaskStyle::Blur => imageops::blur(&area, self.strength as f32),
+            MaskStyle::Pixelate => pixelate(&area, self.strength),
+        };
+        imageops::replace(image, &obscured, x as i64, y as i64);
+    }
+}
+
+impl FromStr for Mask {
+    type Err = Error;
+
+    /// Parses `x,y,w,h[:blur|pixelate]`
+    fn from_str(s: &str) -> Result<Self> {
+        let (region, style) = match s.split_once(':') {
+            Some((region, style)) => (region, style.parse()?),
+            None => (s, MaskStyle::default()),
+        };
+        Ok(Self::new(region.parse()?, style))
+    }
+}
+
+impl Transform for Mask {
+    fn name(&self) -> &str {
+        match self.style {
+            MaskStyle::Blur => "blur",
+            MaskStyle::Pixelate => "pixelate",
+        }
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        self.apply_to(&mut image);
+        Ok(image)
+    }
+}
+
+/// Replaces each `block`×`block` tile with its mean color
+fn pixelate(image: &RgbaImage, block: u32) -> RgbaImage {
+    let mut out = image.clone();
+    let (width, height) = image.dimensions();
+    for ty in (0..height).step_by(block as usize) {
+        for tx in (0..width).step_by(block as usize) {
+            let (tw, th) = (block.min(width - tx), block.min(height - ty));
+            let mut sum = [0u64; 4];
+            for y in ty..ty + th {
+                for x in tx..tx + tw {
+                    for (total, &channel) in sum.iter_mut().zip(&image.get_pixel(x, y).0) {
+                        *total += channel as u64;
+                    }
+                }
+            }
+            let count = (tw * th) as u64;
+            let mean = Rgba(sum.map(|total| (total / count) as u8));
+            for y in ty..ty + th {
+                for x in tx..tx + tw {
+                    out.put_pixel(x, y, mean);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let v = if (x + y) % 2 == 0 { 255 } else { 0 };
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_pixelate_flattens_blocks() {
+        let mask = Mask::pixelate(Region::new(0, 0, 8, 8)).with_strength(4);
+        let image = mask.apply(checkerboard(16)).unwrap();
+
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(image.get_pixel(x, y).0, [127, 127, 127, 255], "({x}, {y})");
+            }
+        }
+        assert_eq!(
+            image.get_pixel(8, 8).0[0],
+            255,
+            "Outside the mask is untouched"
+        );
+        assert_eq!(image.get_pixel(9, 8).0[0], 0);
+    }
+
+    #[test]
+    fn test_blur_smooths_detail() {
+        let mask = Mask::blur(Region::new(4, 4, 8, 8)).with_strength(2);
+        let image = mask.apply(checkerboard(16)).unwrap();
+
+        let p = image.get_pixel(8, 8).0[0];
+        assert!((64..192).contains(&p), "Blurred pixel {p}");
+        assert_eq!(image.get_pixel(0, 0).0[0], 255);
+    }
+
+    #[test]
+    fn test_offscreen_masks_are_clipped() {
+        let mask = Mask::pixelate(Region::new(-4, 12, 100, 100));
+        let image = mask.apply(checkerboard(16)).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+
+        let missing = Mask::blur(Region::new(40, 40, 5, 5));
+        assert_eq!(missing.apply(checkerboard(16)).unwrap(), checkerboard(16));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "1,2,3,4".parse::<Mask>().unwrap(),
+            Mask::blur(Region::new(1, 2, 3, 4))
+        );
+        assert_eq!(
+            "1,2,3,4:pixelate".parse::<Mask>().unwrap(),
+            Mask::pixelate(Region::new(1, 2, 3, 4))
+        );
+        assert!("1,2,3,4:smudge".parse::<Mask>().is_err());
+        assert!("1,2,3".parse::<Mask>().is_err());
+    }
+}
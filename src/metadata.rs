@@ -0,0 +1,446 @@
+//! Capture provenance embedded in output files
+//!
+//! [`CaptureMetadata`] records which display a capture came from, how it was
+//! scaled and rotated, when it was taken and by which snap_scale version.
+//! [`embed`] writes it into PNG `tEXt` chunks or a JPEG EXIF block, and
+//! [`read_png`] gets it back, so tools (and regression checks) can tell where
+//! an image came from without a sidecar. Encoding stays deterministic unless
+//! metadata is embedded, because it includes the capture time.
+//!
+//! A [`Sidecar`] carries more, for any format: the full display description,
+//! the captured region, scaling factors and timing, as `capture.png.json`
+//! next to the image.
+
+use crate::encode::{png_chunks, write_png_chunk, OutputFormat, PNG_SIGNATURE};
+use crate::geometry::Region;
+use crate::scaling::ScalingConfig;
+use crate::{atomic, Error, Result};
+use chrono::{DateTime, Local};
+use screenshots::display_info::DisplayInfo;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// `tEXt` keyword holding the JSON-encoded metadata
+pub const PNG_KEYWORD: &str = "snap_scale";
+
+/// Where and when a capture was taken
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureMetadata {
+    pub display_id: u32,
+    /// Display origin in desktop-global logical coordinates
+    pub x: i32,
+    pub y: i32,
+    pub scale_factor: f32,
+    /// Clockwise, in degrees
+    pub rotation: f32,
+    /// RFC 3339 local time
+    pub captured_at: String,
+    pub version: String,
+}
+
+impl CaptureMetadata {
+    /// Metadata for a capture of `display` taken at `time`
+    pub fn new(display: &DisplayDescriptor, time: DateTime<Local>) -> Self {
+        Self {
+            display_id: display.id,
+            x: display.x,
+            y: display.y,
+            scale_factor: display.scale_factor,
+            rotation: display.rotation,
+            captured_at: time.to_rfc3339(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+        }
+    }
+
+    fn software(&self) -> String {
+        format!("snap_scale {}", self.version)
+    }
+
+    fn json(&self) -> String {
+        // Only numbers and ASCII strings, so this is valid Latin-1 for tEXt
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// A display's geometry and properties, as recorded in a [`Sidecar`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayDescriptor {
+    pub id: u32,
+    /// Monitor name, e.g. `HDMI-1` on X11 or `\\.\DISPLAY2` on Windows;
+    /// empty where the platform doesn't name displays
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Origin in desktop-global logical coordinates
+    pub x: i32,
+    pub y: i32,
+    /// Logical size
+    pub width: u32,
+    pub height: u32,
+    /// Clockwise, in degrees
+    pub rotation: f32,
+    pub scale_factor: f32,
+    /// Refresh rate in Hz
+    pub frequency: f32,
+    pub is_primary: bool,
+}
+
+impl From<&DisplayInfo> for DisplayDescriptor {
+    fn from(info: &DisplayInfo) -> Self {
+        Self {
+            id: info.id,
+            name: String::new(),
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+            rotation: info.rotation,
+            scale_factor: info.scale_factor,
+            frequency: info.frequency,
+            is_primary: info.is_primary,
+        }
+    }
+}
+
+/// Factors of a [`ScalingConfig`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScalingDescriptor {
+    pub dpi_scale: f32,
+    /// Scale beyond DPI found by the test capture
+    pub extra_scale: f32,
+    pub total_scale: f32,
+}
+
+impl From<&ScalingConfig> for ScalingDescriptor {
+    fn from(scaling: &ScalingConfig) -> Self {
+        Self {
+            dpi_scale: scaling.dpi_scale(),
+            extra_scale: scaling.extra_scale(),
+            total_scale: scaling.total_scale(),
+        }
+    }
+}
+
+/// Contents of a `capture.png.json` file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// Name of the image file the sidecar describes
+    pub file: String,
+    /// Size of the saved image in pixels
+    pub width: u32,
+    pub height: u32,
+    pub display: DisplayDescriptor,
+    /// Logical area captured on the display; `None` for the whole display
+    pub region: Option<Region>,
+    pub scaling: ScalingDescriptor,
+    /// RFC 3339 local time
+    pub captured_at: String,
+    /// Time spent transforming, encoding and writing the image
+    pub processing_ms: f64,
+    pub version: String,
+    /// The window and rule that set off the capture, for `snap_scale daemon`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+}
+
+/// A window that matched a `[[rules]]` entry, see [`crate::rules`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trigger {
+    /// Name of the rule
+    pub rule: String,
+    /// Platform window id
+    pub window: u64,
+    pub title: String,
+    pub app: String,
+    pub pid: Option<u32>,
+}
+
+/// `capture.png.json` for `capture.png`
+pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    path.with_file_name(name)
+}
+
+/// `capture.png.txt` for `capture.png`, where `--ocr sidecar` writes the
+/// text recognized in it
+pub fn text_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".txt");
+    path.with_file_name(name)
+}
+
+impl Sidecar {
+    /// Writes the sidecar next to `image`, described by [`Sidecar::file`]
+    pub fn write(&self, image: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).unwrap_or_default();
+        atomic::write(sidecar_path(image), json + "\n")
+    }
+
+    /// Reads the sidecar of `image`
+    pub fn read(image: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read(sidecar_path(image))?;
+        serde_json::from_slice(&json).map_err(|e| Error::invalid("sidecar", e.to_string()))
+    }
+}
+
+/// Adds `metadata` to encoded image bytes, replacing metadata from an
+/// earlier [`embed`]
+pub fn embed(image: &[u8], format: OutputFormat, metadata: &CaptureMetadata) -> Result<Vec<u8>> {
+    match format {
+        OutputFormat::Png => embed_png(image, metadata),
+        OutputFormat::Jpeg => embed_jpeg(image, metadata),
+        OutputFormat::WebP | OutputFormat::Qoi | OutputFormat::Tiff => Err(Error::Unsupported(
+            format!("embedding metadata in {format} files"),
+        )),
+    }
+}
+
+/// Standard PNG keywords written alongside [`PNG_KEYWORD`]
+const PNG_KEYWORDS: [&str; 3] = [PNG_KEYWORD, "Software", "Creation Time"];
+
+fn embed_png(image: &[u8], metadata: &CaptureMetadata) -> Result<Vec<u8>> {
+    let texts = [
+        (PNG_KEYWORD, metadata.json()),
+        ("Software", metadata.software()),
+        ("Creation Time", metadata.captured_at.clone()),
+    ];
+    let mut out = PNG_SIGNATURE.to_vec();
+    for chunk in png_chunks(image)? {
+        let ours = chunk.kind() == b"tEXt"
+            && PNG_KEYWORDS
+                .iter()
+                .any(|keyword| text_keyword(chunk.data()) == Some(keyword.as_bytes()));
+        if !ours {
+            out.extend_from_slice(chunk.bytes);
+        }
+        if chunk.kind() == b"IHDR" {
+            for (keyword, text) in &texts {
+                let data = [keyword.as_bytes(), b"\0", text.as_bytes()].concat();
+                write_png_chunk(&mut out, b"tEXt", &data);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn text_keyword(data: &[u8]) -> Option<&[u8]> {
+    data.split(|&b| b == 0).next()
+}
+
+/// Reads metadata written by [`embed`] from PNG bytes
+pub fn read_png(image: &[u8]) -> Option<CaptureMetadata> {
+    png_chunks(image)
+        .ok()?
+        .iter()
+        .filter(|chunk| chunk.kind() == b"tEXt")
+        .find_map(|chunk| {
+            let text = chunk.data().strip_prefix(PNG_KEYWORD.as_bytes())?;
+            serde_json::from_slice(text.strip_prefix(b"\0")?).ok()
+        })
+}
+
+// EXIF tags in IFD0, which must be sorted
+const IMAGE_DESCRIPTION: u16 = 0x010e;
+const SOFTWARE: u16 = 0x0131;
+const DATE_TIME: u16 = 0x0132;
+const ASCII: u16 = 2;
+
+/// A little-endian TIFF structure with one IFD of ASCII tags
+fn exif(metadata: &CaptureMetadata) -> Vec<u8> {
+    // EXIF dates are `YYYY:MM:DD HH:MM:SS` without a zone
+    let date = DateTime::parse_from_rfc3339(&metadata.captured_at)
+        .map(|time| time.format("%Y:%m:%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let tags = [
+        (IMAGE_DESCRIPTION, metadata.json()),
+        (SOFTWARE, metadata.software()),
+        (DATE_TIME, date),
+    ];
+
+    let ifd_size = 2 + tags.len() * 12 + 4;
+    let mut data_offset = 8 + ifd_size;
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend((tags.len() as u16).to_le_bytes());
+    let mut data = Vec::new();
+    for (tag, value) in &tags {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(ASCII.to_le_bytes());
+        tiff.extend((value.len() as u32).to_le_bytes());
+        if value.len() <= 4 {
+            value.resize(4, 0);
+            tiff.extend(&value);
+        } else {
+            tiff.extend((data_offset as u32).to_le_bytes());
+            data_offset += value.len();
+            data.extend(value);
+        }
+    }
+    // No next IFD
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend(data);
+    tiff
+}
+
+fn embed_jpeg(image: &[u8], metadata: &CaptureMetadata) -> Result<Vec<u8>> {
+    let malformed = || Error::invalid("JPEG", "malformed segment layout");
+    let mut rest = image.strip_prefix(&[0xff, 0xd8]).ok_or_else(malformed)?;
+    let mut payload = b"Exif\0\0".to_vec();
+    payload.extend(exif(metadata));
+    let length = u16::try_from(payload.len() + 2)
+        .map_err(|_| Error::invalid("metadata", "too large for JPEG"))?;
+
+    let mut out = vec![0xff, 0xd8];
+    // JFIF must stay first; earlier EXIF blocks are replaced
+    if let [0xff, 0xe0, high, low, ..] = rest {
+        let jfif = rest
+            .get(..u16::from_be_bytes([*high, *low]) as usize + 2)
+            .ok_or_else(malformed)?;
+        out.extend_from_slice(jfif);
+        rest = &rest[jfif.len()..];
+    }
+    while let [0xff, 0xe1, high, low, ..] = rest {
+        let segment = rest
+            .get(..u16::from_be_bytes([*high, *low]) as usize + 2)
+            .ok_or_else(malformed)?;
+        if !segment[4..].starts_with(b"Exif\0\0") {
+            out.extend_from_slice(segment);
+        }
+        rest = &rest[segment.len()..];
+    }
+    out.extend([0xff, 0xe1]);
+    out.extend(length.to_be_bytes());
+    out.extend(payload);
+    out.extend_from_slice(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode_to_vec, EncodeOptions};
+    use screenshots::image::{Rgba, RgbaImage};
+
+    fn metadata() -> CaptureMetadata {
+        CaptureMetadata {
+            display_id: 7,
+            x: -1920,
+            y: 0,
+            scale_factor: 1.5,
+            rotation: 90.0,
+            captured_at: "2024-05-01T12:30:00+02:00".into(),
+            version: "0.1.0".into(),
+        }
+    }
+
+    fn encoded(format: OutputFormat) -> Vec<u8> {
+        let frame = RgbaImage::from_pixel(8, 8, Rgba([10, 120, 200, 255]));
+        encode_to_vec(&frame, &EncodeOptions::new(format)).unwrap()
+    }
+
+    #[test]
+    fn test_png_metadata_round_trips() {
+        let tagged = embed(&encoded(OutputFormat::Png), OutputFormat::Png, &metadata()).unwrap();
+
+        assert_eq!(read_png(&tagged), Some(metadata()));
+        let decoder = png::Decoder::new(tagged.as_slice()).read_info().unwrap();
+        let software = decoder
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .find(|text| text.keyword == "Software")
+            .map(|text| text.text.clone());
+        assert_eq!(software.as_deref(), Some("snap_scale 0.1.0"));
+
+        let mut newer = metadata();
+        newer.display_id = 8;
+        let again = embed(&tagged, OutputFormat::Png, &newer).unwrap();
+        assert_eq!(read_png(&again).unwrap().display_id, 8, "Replaced");
+        let count = again.windows(4).filter(|w| w == b"tEXt").count();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_jpeg_gets_an_exif_block() {
+        let tagged = embed(
+            &encoded(OutputFormat::Jpeg),
+            OutputFormat::Jpeg,
+            &metadata(),
+        )
+        .unwrap();
+
+        let exif = tagged
+            .windows(6)
+            .position(|w| w == b"Exif\0\0")
+            .expect("APP1 segment");
+        assert_eq!(&tagged[exif - 4..exif - 2], [0xff, 0xe1]);
+        assert_eq!(&tagged[exif + 6..exif + 10], b"II*\0");
+        let text = String::from_utf8_lossy(&tagged);
+        assert!(text.contains("2024:05:01 12:30:00"), "EXIF DateTime");
+        assert!(text.contains(r#""display_id":7"#), "Description JSON");
+        assert!(screenshots::image::load_from_memory(&tagged).is_ok());
+    }
+
+    #[test]
+    fn test_sidecar_round_trips() {
+        let dir = std::env::temp_dir().join(format!(
+            "snap_scale_metadata_sidecar_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("capture.png");
+        let sidecar = Sidecar {
+            file: "capture.png".into(),
+            width: 600,
+            height: 450,
+            display: DisplayDescriptor {
+                id: 7,
+                name: String::new(),
+                x: -1920,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                rotation: 0.0,
+                scale_factor: 1.5,
+                frequency: 60.0,
+                is_primary: false,
+            },
+            region: Some(Region::new(300, 300, 400, 300)),
+            scaling: (&ScalingConfig::new(1.5, 1.0)).into(),
+            captured_at: "2024-05-01T12:30:00+02:00".into(),
+            processing_ms: 12.5,
+            version: "0.1.0".into(),
+            trigger: Some(Trigger {
+                rule: "errors".into(),
+                window: 0x3a0_0007,
+                title: "Build failed".into(),
+                app: "ci".into(),
+                pid: Some(4242),
+            }),
+        };
+        sidecar.write(&image).unwrap();
+
+        assert!(dir.join("capture.png.json").exists());
+        assert_eq!(Sidecar::read(&image).unwrap(), sidecar);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sidecar_path_keeps_the_extension() {
+        assert_eq!(
+            sidecar_path("target/shot.png"),
+            PathBuf::from("target/shot.png.json")
+        );
+        assert_eq!(sidecar_path("shot"), PathBuf::from("shot.json"));
+    }
+
+    #[test]
+    fn test_untagged_png_has_no_metadata() {
+        assert_eq!(read_png(&encoded(OutputFormat::Png)), None);
+        assert_eq!(read_png(b"garbage"), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Prometheus metrics for long-running modes
+//!
+//! Watch mode and the HTTP server count into [`METRICS`]; `/metrics` renders
+//! it in the Prometheus text format so a deployment can alert on failing
+//! captures or a frame rate that falls behind. Everything is a lock-free
+//! atomic, cheap enough to update on every frame.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The process-wide metrics
+pub static METRICS: Metrics = Metrics::new();
+
+/// `Content-Type` of [`Metrics::render`]
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Counters, histograms and gauges of captures and encoding
+pub struct Metrics {
+    captures: AtomicU64,
+    failures: AtomicU64,
+    encode_seconds: Histogram<9>,
+    frame_bytes: Histogram<7>,
+    /// An `f64`, as bits
+    fps: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            captures: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            encode_seconds: Histogram::new([0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+            frame_bytes: Histogram::new([
+                16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0, 67108864.0,
+            ]),
+            fps: AtomicU64::new(0),
+        }
+    }
+
+    pub fn captured(&self) {
+        self.captures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn capture_failed(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a frame that took `took` to encode into `bytes` bytes
+    pub fn encoded(&self, took: Duration, bytes: u64) {
+        self.encode_seconds.observe(took.as_secs_f64());
+        self.frame_bytes.observe(bytes as f64);
+    }
+
+    /// Sets the achieved frame rate from the time since the previous frame
+    pub fn frame_interval(&self, interval: Duration) {
+        if !interval.is_zero() {
+            let fps = 1.0 / interval.as_secs_f64();
+            self.fps.store(fps.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// The Prometheus text exposition of every metric
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "snap_scale_captures_total",
+            "Captures taken",
+            self.captures.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "snap_scale_capture_failures_total",
+            "Captures that failed",
+            self.failures.load(Ordering::Relaxed),
+        );
+        self.encode_seconds.render(
+            &mut out,
+            "snap_scale_encode_duration_seconds",
+            "Time spent encoding a frame",
+        );
+        self.frame_bytes.render(
+            &mut out,
+            "snap_scale_frame_size_bytes",
+            "Size of an encoded frame",
+        );
+        let _ = writeln!(
+            out,
+            "# HELP snap_scale_fps Frame rate achieved by the latest watch or stream frame\n\
+             # TYPE snap_scale_fps gauge\n\
+             snap_scale_fps {}",
+            f64::from_bits(self.fps.load(Ordering::Relaxed))
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+    );
+}
+
+/// Cumulative buckets with upper `bounds`, plus the implicit `+Inf` one
+struct Histogram<const N: usize> {
+    bounds: [f64; N],
+    buckets: [AtomicU64; N],
+    count: AtomicU64,
+    /// An `f64`, as bits
+    sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    const fn new(bounds: [f64; N]) -> Self {
+        Self {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{le=\"{bound}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = f64::from_bits(self.sum.load(Ordering::Relaxed));
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{le=\"+Inf\"}} {count}\n{name}_sum {sum}\n{name}_count {count}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_and_buckets() {
+        let metrics = Metrics::new();
+        metrics.captured();
+        metrics.captured();
+        metrics.capture_failed();
+        metrics.encoded(Duration::from_millis(20), 100_000);
+        metrics.encoded(Duration::from_secs(3), 10);
+        metrics.frame_interval(Duration::from_millis(250));
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE snap_scale_captures_total counter",
+            "snap_scale_captures_total 2",
+            "snap_scale_capture_failures_total 1",
+            "# TYPE snap_scale_encode_duration_seconds histogram",
+            "snap_scale_encode_duration_seconds_bucket{le=\"0.01\"} 0",
+            "snap_scale_encode_duration_seconds_bucket{le=\"0.025\"} 1",
+            "snap_scale_encode_duration_seconds_bucket{le=\"2.5\"} 1",
+            "snap_scale_encode_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "snap_scale_encode_duration_seconds_sum 3.02",
+            "snap_scale_encode_duration_seconds_count 2",
+            "snap_scale_frame_size_bytes_bucket{le=\"16384\"} 1",
+            "snap_scale_frame_size_bytes_bucket{le=\"262144\"} 2",
+            "snap_scale_fps 4",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line}\n{text}");
+        }
+    }
+
+    #[test]
+    fn test_fresh_metrics_are_zero() {
+        let text = Metrics::default().render();
+        assert!(text.contains("snap_scale_captures_total 0\n"));
+        assert!(text.contains("snap_scale_frame_size_bytes_count 0\n"));
+        assert!(text.ends_with("snap_scale_fps 0\n"));
+    }
+}
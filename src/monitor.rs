@@ -0,0 +1,84 @@
+//! Watching an area of the screen for changes
+//!
+//! `snap_scale monitor` captures an area over and over and alerts when it
+//! differs from what it was, e.g. when a build dashboard goes red. A
+//! [`Monitor`] compares each frame with the one it last reported rather
+//! than the one just before, so a change that creeps in a few pixels a frame
+//! is still caught once it adds up.
+
+use crate::diff::{self, DiffOptions, DiffReport};
+use crate::Result;
+use screenshots::image::RgbaImage;
+
+/// Compares frames of one area with the last one that changed
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    /// Percentage of pixels that must change for a frame to count, 0-100
+    threshold: f64,
+    options: DiffOptions,
+    reference: Option<RgbaImage>,
+}
+
+impl Monitor {
+    pub fn new(threshold: f64, options: DiffOptions) -> Self {
+        Self {
+            threshold,
+            options,
+            reference: None,
+        }
+    }
+
+    /// How `frame` differs from the last frame that changed, when more
+    /// than the threshold of its pixels did; `frame` is then what the next
+    /// ones are compared with
+    ///
+    /// The first frame, and one of another size, e.g. after the display's
+    /// resolution changed, only start over the comparison.
+    pub fn check(&mut self, frame: RgbaImage) -> Result<Option<DiffReport>> {
+        let Some(reference) = self
+            .reference
+            .as_ref()
+            .filter(|r| r.dimensions() == frame.dimensions())
+        else {
+            self.reference = Some(frame);
+            return Ok(None);
+        };
+        let report = diff::compare(reference, &frame, &self.options)?;
+        if report.changed_percent() <= self.threshold {
+            return Ok(None);
+        }
+        self.reference = Some(frame);
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    #[test]
+    fn test_changes_past_the_threshold() {
+        let green = RgbaImage::from_pixel(10, 10, Rgba([0, 200, 0, 255]));
+        // Nine pixels of a hundred turn red, one at a time
+        let reddened = |n: u32| {
+            let mut frame = green.clone();
+            for i in 0..n {
+                frame.put_pixel(i, 0, Rgba([200, 0, 0, 255]));
+            }
+            frame
+        };
+        let mut monitor = Monitor::new(5.0, DiffOptions::default());
+        assert!(monitor.check(green.clone()).unwrap().is_none(), "first");
+        for n in 1..=5 {
+            assert!(monitor.check(reddened(n)).unwrap().is_none(), "{n}%");
+        }
+        let report = monitor.check(reddened(6)).unwrap().expect("6% changed");
+        assert_eq!(report.changed_pixels, 6);
+        assert!(monitor.check(reddened(9)).unwrap().is_none(), "3% since");
+
+        // A new size starts over
+        assert!(monitor.check(RgbaImage::new(4, 4)).unwrap().is_none());
+        assert!(monitor.check(RgbaImage::new(4, 4)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,343 @@
+//! Publishing of capture events to an MQTT broker
+//!
+//! With `[mqtt] enabled`, every saved capture is announced to `topic` as a
+//! JSON [`CaptureEvent`], and with `image` set the encoded file follows on
+//! `<topic>/image`, so home-automation and monitoring setups can react to
+//! captures without polling a directory. Messages are queued for a background
+//! connection that reconnects on its own; when the broker is away and the
+//! queue is full, events are dropped rather than holding up capturing.
+//!
+//! Backed by `rumqttc` when the `mqtt` feature is enabled; without it
+//! [`Publisher::connect`] returns [`Error::Unsupported`](crate::Error::Unsupported).
+
+use crate::{Error, Result};
+use serde::Serialize;
+use std::path::Path;
+
+/// Port of unencrypted MQTT
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// The JSON announced for each saved capture
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureEvent {
+    /// Always `capture`
+    pub event: &'static str,
+    pub path: String,
+    pub display: String,
+    pub width: u32,
+    pub height: u32,
+    /// RFC 3339, local time
+    pub captured_at: String,
+    pub host: String,
+    /// Where the capture was uploaded, with `--upload`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl CaptureEvent {
+    /// An event for a capture saved just now
+    pub fn new(path: &Path, display: &str, width: u32, height: u32) -> Self {
+        Self {
+            event: "capture",
+            path: path.display().to_string(),
+            display: display.to_owned(),
+            width,
+            height,
+            captured_at: chrono::Local::now().to_rfc3339(),
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            url: None,
+        }
+    }
+
+    pub fn with_url(mut self, url: Option<String>) -> Self {
+        self.url = url;
+        self
+    }
+}
+
+/// Splits `host`, `host:port` or `mqtt://host:port` into host and port
+pub fn parse_broker(broker: &str) -> Result<(String, u16)> {
+    let invalid = || Error::invalid("MQTT broker (expected host or host:port)", broker);
+    if broker.starts_with("mqtts://") || broker.starts_with("ssl://") {
+        return Err(Error::invalid("MQTT broker (TLS isn't supported)", broker));
+    }
+    let address = broker
+        .strip_prefix("mqtt://")
+        .or_else(|| broker.strip_prefix("tcp://"))
+        .unwrap_or(broker);
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+        None => (address, DEFAULT_PORT),
+    };
+    if host.is_empty() || host.contains('/') {
+        return Err(invalid());
+    }
+    Ok((host.to_owned(), port))
+}
+
+#[cfg(feature = "mqtt")]
+pub use imp::Publisher;
+
+#[cfg(feature = "mqtt")]
+mod imp {
+    use super::{parse_broker, CaptureEvent};
+    use crate::config::MqttConfig;
+    use crate::{Error, Result};
+    use rumqttc::{Client, Event, MqttOptions, Outgoing, QoS};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    /// Messages waiting for the broker before new ones are dropped
+    const QUEUE: usize = 16;
+
+    /// A connection to the broker, flushed and closed on drop
+    pub struct Publisher {
+        client: Client,
+        topic: String,
+        image: bool,
+        qos: QoS,
+        retain: bool,
+        stopping: Arc<AtomicBool>,
+        worker: Option<JoinHandle<()>>,
+    }
+
+    impl Publisher {
+        /// Starts connecting to the broker in the background
+        pub fn connect(config: &MqttConfig) -> Result<Self> {
+            let (host, port) = parse_broker(&config.broker)?;
+            let qos = match config.qos {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                2 => QoS::ExactlyOnce,
+                qos => return Err(Error::invalid("MQTT QoS (0 to 2)", qos.to_string())),
+            };
+            if config.topic.is_empty() || config.topic.contains(['+', '#']) {
+                return Err(Error::invalid("MQTT topic", &config.topic));
+            }
+            let client_id = config.client_id.clone().unwrap_or_else(|| {
+                format!(
+                    "snap_scale-{}",
+                    gethostname::gethostname().to_string_lossy()
+                )
+            });
+
+            let mut options = MqttOptions::new(client_id, host, port);
+            options.set_keep_alive(Duration::from_secs(30));
+            if let Some(username) = &config.username {
+                options.set_credentials(username, config.password.as_deref().unwrap_or(""));
+            }
+            let (client, mut connection) = Client::new(options, QUEUE);
+
+            let stopping = Arc::new(AtomicBool::new(false));
+            let stop = Arc::clone(&stopping);
+            let broker = config.broker.clone();
+            let worker = std::thread::spawn(move || {
+                let mut reported = false;
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                        Ok(_) => reported = false,
+                        Err(_) if stop.load(Ordering::Relaxed) => break,
+                        Err(e) => {
+                            // Once per outage, not on every retry
+                            if !reported {
+                                tracing::warn!("MQTT broker {broker}: {e}");
+                                reported = true;
+                            }
+                            std::thread::sleep(Duration::from_secs(1));
+                        }
+                    }
+                }
+            });
+
+            Ok(Self {
+                client,
+                topic: config.topic.clone(),
+                image: config.image,
+                qos,
+                retain: config.retain,
+                stopping,
+                worker: Some(worker),
+            })
+        }
+
+        /// Queues `event`, and `image` when the config asks for images
+        pub fn publish(&self, event: &CaptureEvent, image: Option<&[u8]>) -> Result<()> {
+            let json = serde_json::to_vec(event).map_err(|e| Error::Upload(e.to_string()))?;
+            self.send(self.topic.clone(), json)?;
+            if let Some(image) = image.filter(|_| self.image) {
+                self.send(format!("{}/image", self.topic), image.to_vec())?;
+            }
+            Ok(())
+        }
+
+        /// Whether [`Publisher::publish`] sends images
+        pub fn wants_image(&self) -> bool {
+            self.image
+        }
+
+        fn send(&self, topic: String, payload: Vec<u8>) -> Result<()> {
+            self.client
+                .try_publish(topic, self.qos, self.retain, payload)
+                .map_err(|e| Error::Upload(format!("MQTT publish: {e}")))
+        }
+    }
+
+    impl Drop for Publisher {
+        fn drop(&mut self) {
+            self.stopping.store(true, Ordering::Relaxed);
+            // Queued after the messages, so those go out first
+            let _ = self.client.try_disconnect();
+            if let Some(worker) = self.worker.take() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+/// Stand-in for builds without the `mqtt` feature
+#[cfg(not(feature = "mqtt"))]
+pub struct Publisher(());
+
+#[cfg(not(feature = "mqtt"))]
+impl Publisher {
+    pub fn connect(_config: &crate::config::MqttConfig) -> Result<Self> {
+        Err(Error::Unsupported(
+            "MQTT publishing requires the `mqtt` feature".into(),
+        ))
+    }
+
+    pub fn publish(&self, _event: &CaptureEvent, _image: Option<&[u8]>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn wants_image(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MqttConfig;
+
+    #[test]
+    fn test_event_json() {
+        let event = CaptureEvent {
+            event: "capture",
+            path: "shots/a.png".into(),
+            display: "0".into(),
+            width: 1920,
+            height: 1080,
+            captured_at: "2024-05-01T12:00:00+02:00".into(),
+            host: "desk".into(),
+            url: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"capture","path":"shots/a.png","display":"0","width":1920,"height":1080,"captured_at":"2024-05-01T12:00:00+02:00","host":"desk"}"#
+        );
+        let json = serde_json::to_value(event.with_url(Some("https://x/a".into()))).unwrap();
+        assert_eq!(json["url"], "https://x/a");
+    }
+
+    #[test]
+    fn test_parse_broker() {
+        assert_eq!(
+            parse_broker("localhost").unwrap(),
+            ("localhost".into(), 1883)
+        );
+        assert_eq!(
+            parse_broker("mqtt://broker.lan:1884").unwrap(),
+            ("broker.lan".into(), 1884)
+        );
+        assert_eq!(
+            parse_broker("tcp://10.0.0.2").unwrap(),
+            ("10.0.0.2".into(), 1883)
+        );
+        for bad in ["", ":1883", "host:port", "mqtts://broker", "http://x/y"] {
+            assert!(parse_broker(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    #[test]
+    fn test_connect_needs_the_feature() {
+        assert!(matches!(
+            Publisher::connect(&MqttConfig::default()),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn test_publishes_to_a_broker() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        /// Reads one packet: its first byte and the rest after the length
+        fn packet(stream: &mut impl Read) -> (u8, Vec<u8>) {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            let kind = byte[0];
+            let (mut length, mut shift) = (0usize, 0);
+            loop {
+                stream.read_exact(&mut byte).unwrap();
+                length |= ((byte[0] & 0x7f) as usize) << shift;
+                shift += 7;
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).unwrap();
+            (kind, body)
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(packet(&mut stream).0, 0x10, "CONNECT");
+            stream.write_all(&[0x20, 0x02, 0, 0]).unwrap();
+            let mut published = Vec::new();
+            loop {
+                match packet(&mut stream) {
+                    (0xe0, _) => break,
+                    (kind, body) => {
+                        assert_eq!(kind & 0xf0, 0x30, "PUBLISH");
+                        let topic_length = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let topic = String::from_utf8(body[2..2 + topic_length].to_vec());
+                        published.push((topic.unwrap(), body[2 + topic_length..].to_vec()));
+                    }
+                }
+            }
+            published
+        });
+
+        let config = MqttConfig {
+            enabled: true,
+            broker: format!("127.0.0.1:{port}"),
+            topic: "home/shots".into(),
+            image: true,
+            qos: 0,
+            ..MqttConfig::default()
+        };
+        let publisher = Publisher::connect(&config).unwrap();
+        let event = CaptureEvent::new(Path::new("a.png"), "0", 4, 3);
+        publisher.publish(&event, Some(b"PNG")).unwrap();
+        drop(publisher);
+
+        let published = broker.join().unwrap();
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[0].0, "home/shots");
+        let json: serde_json::Value = serde_json::from_slice(&published[0].1).unwrap();
+        assert_eq!(
+            (json["event"].as_str(), json["width"].as_u64()),
+            (Some("capture"), Some(4))
+        );
+        assert_eq!(published[1], ("home/shots/image".into(), b"PNG".to_vec()));
+    }
+}
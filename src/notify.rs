@@ -0,0 +1,103 @@
+//! Native desktop notifications for saved captures
+//!
+//! Backed by `notify-rust` when the `notify` feature is enabled; without it
+//! every call returns [`Error::Unsupported`](crate::Error::Unsupported).
+
+use crate::config::NotificationConfig;
+use crate::Result;
+use std::path::Path;
+
+/// Announces a saved capture, optionally with a thumbnail and click-to-open
+///
+/// When `open_on_click` is set this blocks until the notification is clicked,
+/// dismissed or times out.
+#[cfg(feature = "notify")]
+pub fn capture_saved(path: &Path, config: &NotificationConfig) -> Result<()> {
+    show(
+        "Screenshot saved",
+        &path.display().to_string(),
+        path,
+        config,
+    )
+}
+
+/// Announces that a monitored area changed, `percent` of it, with the
+/// capture of it saved to `path`
+#[cfg(feature = "notify")]
+pub fn region_changed(path: &Path, percent: f64, config: &NotificationConfig) -> Result<()> {
+    let body = format!("{percent:.1}% changed, saved to {}", path.display());
+    show("Monitored area changed", &body, path, config)
+}
+
+/// Shows a notification about the capture at `path`
+#[cfg(feature = "notify")]
+fn show(summary: &str, body: &str, path: &Path, config: &NotificationConfig) -> Result<()> {
+    use notify_rust::{Notification, Timeout};
+
+    let mut notification = Notification::new();
+    notification
+        .appname("snap_scale")
+        .summary(summary)
+        .body(body)
+        .timeout(Timeout::Milliseconds(config.timeout_ms));
+
+    if config.thumbnail {
+        if let Ok(absolute) = path.canonicalize() {
+            notification.image_path(&absolute.to_string_lossy());
+        }
+    }
+    if config.open_on_click {
+        notification.action("default", "Open");
+    }
+
+    let handle = notification
+        .show()
+        .map_err(|e| crate::Error::Unsupported(format!("desktop notification failed: {e}")))?;
+
+    #[cfg(not(target_os = "macos"))]
+    if config.open_on_click {
+        let path = path.to_path_buf();
+        handle.wait_for_action(move |action| {
+            if action == "default" {
+                let _ = open_path(&path);
+            }
+        });
+    }
+    #[cfg(target_os = "macos")]
+    drop(handle);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "notify"))]
+pub fn capture_saved(_path: &Path, _config: &NotificationConfig) -> Result<()> {
+    Err(crate::Error::Unsupported(
+        "desktop notifications require the `notify` feature".into(),
+    ))
+}
+
+#[cfg(not(feature = "notify"))]
+pub fn region_changed(_path: &Path, _percent: f64, _config: &NotificationConfig) -> Result<()> {
+    Err(crate::Error::Unsupported(
+        "desktop notifications require the `notify` feature".into(),
+    ))
+}
+
+/// Opens a file with the platform's default application
+#[cfg(any(feature = "notify", all(feature = "tray", target_os = "linux")))]
+pub(crate) fn open_path(path: &Path) -> std::io::Result<()> {
+    use std::process::Command;
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = Command::new("xdg-open");
+
+    command.arg(path).spawn().map(drop)
+}
@@ -0,0 +1,158 @@
+//! Text recognition in captures
+//!
+//! Runs the `tesseract` command-line engine when the `ocr` feature is
+//! enabled; without it [`extract_text`] returns
+//! [`Error::Unsupported`](crate::Error::Unsupported). The image is piped in
+//! as PNG and word boxes are read back from tesseract's TSV output.
+
+use crate::geometry::Region;
+use crate::Result;
+use screenshots::image::RgbaImage;
+
+/// A recognized word and where it was found, in image pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    /// Engine confidence, 0-100
+    pub confidence: f32,
+    pub region: Region,
+}
+
+/// Everything recognized in an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OcrResult {
+    /// Recognized text with one line per detected text line and a blank line
+    /// between paragraphs
+    pub text: String,
+    pub words: Vec<OcrWord>,
+}
+
+/// Recognizes text in `image` with the English model
+pub fn extract_text(image: &RgbaImage) -> Result<OcrResult> {
+    extract_text_with(image, "eng")
+}
+
+/// Recognizes text in `image` with the given tesseract language(s), e.g.
+/// `"eng+deu"`
+#[cfg(feature = "ocr")]
+pub fn extract_text_with(image: &RgbaImage, language: &str) -> Result<OcrResult> {
+    use crate::encode::{encode_to_vec, EncodeOptions, OutputFormat};
+    use crate::Error;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let png = encode_to_vec(image, &EncodeOptions::new(OutputFormat::Png))?;
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout", "-l", language, "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Unsupported(format!("cannot run tesseract: {e}")))?;
+
+    // Feed stdin from a thread so a large image can't deadlock on a full
+    // stdout pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(&png));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| Error::Ocr("stdin writer panicked".into()))??;
+
+    if !output.status.success() {
+        return Err(Error::Ocr(format!(
+            "tesseract failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(not(feature = "ocr"))]
+pub fn extract_text_with(_image: &RgbaImage, _language: &str) -> Result<OcrResult> {
+    Err(crate::Error::Unsupported(
+        "OCR requires the `ocr` feature".into(),
+    ))
+}
+
+/// Builds an [`OcrResult`] from tesseract's `tsv` output
+#[cfg(feature = "ocr")]
+fn parse_tsv(tsv: &str) -> OcrResult {
+    let mut result = OcrResult::default();
+    let mut last_line = None;
+    let mut last_paragraph = None;
+
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.split('\t').collect();
+        // level page block par line word left top width height conf text
+        let [level, _, block, par, line, _, left, top, width, height, conf, text] = columns[..]
+        else {
+            continue;
+        };
+        let text = text.trim();
+        if level != "5" || text.is_empty() {
+            continue;
+        }
+
+        let paragraph = (block, par);
+        let line = (block, par, line);
+        if last_line == Some(line) {
+            result.text.push(' ');
+        } else if last_line.is_some() {
+            result.text.push('\n');
+            if last_paragraph != Some(paragraph) {
+                result.text.push('\n');
+            }
+        }
+        result.text.push_str(text);
+        last_line = Some(line);
+        last_paragraph = Some(paragraph);
+
+        result.words.push(OcrWord {
+            text: text.to_string(),
+            confidence: conf.parse().unwrap_or(0.0),
+            region: Region::new(
+                left.parse().unwrap_or(0),
+                top.parse().unwrap_or(0),
+                width.parse().unwrap_or(0),
+                height.parse().unwrap_or(0),
+            ),
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "ocr")]
+    #[test]
+    fn test_parse_tsv_lines_and_paragraphs() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n\
+                   1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t5\t40\t12\t96.5\tHello\n\
+                   5\t1\t1\t1\t1\t2\t55\t5\t50\t12\t91\tworld\n\
+                   5\t1\t1\t1\t2\t1\t10\t20\t30\t12\t88\tagain\n\
+                   5\t1\t2\t1\t1\t1\t10\t60\t30\t12\t80\tBye\n\
+                   5\t1\t2\t1\t1\t2\t45\t60\t5\t12\t10\t \n";
+
+        let result = parse_tsv(tsv);
+
+        assert_eq!(result.text, "Hello world\nagain\n\nBye");
+        assert_eq!(result.words.len(), 4, "Blank words are dropped");
+        assert_eq!(result.words[0].region, Region::new(10, 5, 40, 12));
+        assert_eq!(result.words[0].confidence, 96.5);
+    }
+
+    #[cfg(not(feature = "ocr"))]
+    #[test]
+    fn test_unsupported_without_feature() {
+        let err = extract_text(&RgbaImage::new(1, 1)).unwrap_err();
+        assert!(
+            matches!(err, crate::Error::Unsupported(_)),
+            "Expected Unsupported: {err}"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Lossless PNG optimization
+//!
+//! [`Optimizer`] runs [oxipng](https://github.com/shssoichiro/oxipng) over an
+//! encoded PNG: it tries reductions (bit depth, palette, color type) and
+//! filter strategies, then recompresses with libdeflate or, optionally,
+//! Zopfli. Pixels never change. A time budget stops the search early, keeping
+//! the best result found so far.
+
+use crate::{Error, Result};
+use std::num::NonZeroU8;
+use std::time::Duration;
+
+/// Default oxipng preset
+pub const DEFAULT_LEVEL: u8 = 2;
+
+/// Iterations for Zopfli; more is slower for little gain on screenshots
+const ZOPFLI_ITERATIONS: u8 = 15;
+
+/// Shrinks PNG files without changing their pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Optimizer {
+    /// oxipng preset, 0 (fastest) to 6 (smallest)
+    pub level: u8,
+    /// Recompress with Zopfli instead of libdeflate
+    pub zopfli: bool,
+    /// Stop trying further optimizations after this long
+    pub budget: Option<Duration>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEVEL)
+    }
+}
+
+impl Optimizer {
+    pub fn new(level: u8) -> Self {
+        Self {
+            level: level.min(6),
+            zopfli: false,
+            budget: None,
+        }
+    }
+
+    pub fn with_zopfli(mut self, zopfli: bool) -> Self {
+        self.zopfli = zopfli;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Optimizes an encoded PNG; returns the input unchanged when it can't be
+    /// made smaller
+    pub fn optimize(&self, png: &[u8]) -> Result<Vec<u8>> {
+        let mut options = oxipng::Options::from_preset(self.level);
+        options.timeout = self.budget;
+        if self.zopfli {
+            options.deflate = oxipng::Deflaters::Zopfli {
+                iterations: NonZeroU8::new(ZOPFLI_ITERATIONS).unwrap_or(NonZeroU8::MIN),
+            };
+        }
+        oxipng::optimize_from_memory(png, &options).map_err(|e| Error::Io(std::io::Error::other(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode_to_vec, EncodeOptions};
+    use screenshots::image::{Rgba, RgbaImage};
+
+    /// Opaque flat UI bands: plenty of room for color type reduction
+    fn capture() -> Vec<u8> {
+        let image = RgbaImage::from_fn(200, 120, |x, y| match (x / 50 + y / 40) % 3 {
+            0 => Rgba([255, 255, 255, 255]),
+            1 => Rgba([32, 96, 200, 255]),
+            _ => Rgba([20, 20, 20, 255]),
+        });
+        encode_to_vec(&image, &EncodeOptions::default()).unwrap()
+    }
+
+    fn decode(png: &[u8]) -> RgbaImage {
+        screenshots::image::load_from_memory(png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn test_optimized_png_is_smaller_and_identical() {
+        let input = capture();
+        for optimizer in [Optimizer::default(), Optimizer::new(1).with_zopfli(true)] {
+            let output = optimizer.optimize(&input).unwrap();
+
+            assert!(
+                output.len() < input.len(),
+                "{optimizer:?}: {} vs {}",
+                output.len(),
+                input.len()
+            );
+            assert_eq!(decode(&output), decode(&input), "Lossless");
+        }
+    }
+
+    #[test]
+    fn test_zero_budget_still_yields_a_valid_png() {
+        let input = capture();
+        let output = Optimizer::default()
+            .with_budget(Duration::ZERO)
+            .optimize(&input)
+            .unwrap();
+
+        assert!(output.len() <= input.len());
+        assert_eq!(decode(&output), decode(&input));
+    }
+
+    #[test]
+    fn test_rejects_non_png_input() {
+        assert!(Optimizer::default().optimize(b"not a png").is_err());
+    }
+
+    #[test]
+    fn test_level_is_clamped() {
+        assert_eq!(Optimizer::new(9).level, 6);
+    }
+}
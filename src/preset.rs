@@ -0,0 +1,115 @@
+//! Bundled encoder settings for common intents
+//!
+//! `--preset` stands in for the output format, JPEG quality, `--optimize`
+//! level and `--resize` that suit what a capture is for, so they needn't be
+//! remembered; any of those flags given as well wins over the preset, and so
+//! does the extension of a path given with `--output`:
+//!
+//! | Preset | Format | Quality | Optimize | Resize |
+//! |---|---|---|---|---|
+//! | `archival` | PNG | | 6 | |
+//! | `share` | JPEG | 85 | | to fit 1920×1080 |
+//! | `quick` | JPEG | 75 | | 50% |
+
+use crate::encode::OutputFormat;
+use crate::resize::Size;
+use crate::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a capture is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// Lossless at full size, compressed as far as it goes
+    Archival,
+    /// Small enough to paste into chats and issues, still readable
+    Share,
+    /// Fast to write and small, for a quick look
+    Quick,
+}
+
+/// The settings a [`Preset`] stands in for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PresetSettings {
+    /// Format of captures that would otherwise be PNGs
+    pub format: OutputFormat,
+    /// JPEG quality, 1-100
+    pub jpeg_quality: u8,
+    /// oxipng level, with the `optimize` feature
+    pub optimize: Option<u8>,
+    pub resize: Option<Size>,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Self::Archival, Self::Share, Self::Quick];
+
+    pub fn settings(self) -> PresetSettings {
+        match self {
+            Self::Archival => PresetSettings {
+                format: OutputFormat::Png,
+                jpeg_quality: 100,
+                optimize: Some(6),
+                resize: None,
+            },
+            Self::Share => PresetSettings {
+                format: OutputFormat::Jpeg,
+                jpeg_quality: 85,
+                optimize: None,
+                resize: Some(Size::Within {
+                    width: 1920,
+                    height: 1080,
+                }),
+            },
+            Self::Quick => PresetSettings {
+                format: OutputFormat::Jpeg,
+                jpeg_quality: 75,
+                optimize: None,
+                resize: Some(Size::Percent(50.0)),
+            },
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::invalid("preset (archival, share or quick)", s))
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Archival => "archival",
+            Self::Share => "share",
+            Self::Quick => "quick",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.to_string().parse::<Preset>().unwrap(), preset);
+        }
+        assert_eq!("Share".parse::<Preset>().unwrap(), Preset::Share);
+        assert!("small".parse::<Preset>().is_err());
+
+        let archival = Preset::Archival.settings();
+        assert_eq!(
+            (archival.format, archival.resize),
+            (OutputFormat::Png, None)
+        );
+        let share = Preset::Share.settings().resize.unwrap();
+        assert_eq!(share.dimensions(3840, 2160), (1920, 1080));
+        assert_eq!(share.dimensions(1280, 720), (1280, 720), "Never grows");
+    }
+}
@@ -0,0 +1,119 @@
+//! Small copies of displays, made fast
+//!
+//! Live previews and tray thumbnails want a display a few hundred pixels
+//! wide, many times a second. A full capture followed by a
+//! [`Resize`](crate::resize::Resize) copies the frame into an image and then
+//! runs a resampling filter over all of it. [`downsample`] instead averages
+//! boxes of pixels straight out of a borrowed [`Frame`] in one pass, and
+//! [`CaptureBackend::capture_preview`](crate::backend::CaptureBackend::capture_preview)
+//! runs it on what `capture_frame` lends out. Backends that can capture at a
+//! lower resolution, as ScreenCaptureKit and the mock backend can, make the
+//! preview without a full-size frame at all.
+
+use crate::buffer::Frame;
+use crate::{Error, Result};
+use screenshots::image::{Rgba, RgbaImage};
+
+/// `scale` if it's a fraction of the size to shrink to, in `(0, 1]`
+pub fn check_scale(scale: f32) -> Result<f32> {
+    if scale > 0.0 && scale <= 1.0 {
+        Ok(scale)
+    } else {
+        Err(Error::invalid(
+            "preview scale (expected more than 0 and at most 1)",
+            scale.to_string(),
+        ))
+    }
+}
+
+/// `width`x`height` times `scale`, at least a pixel each way
+pub fn preview_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let side =
+        |length: u32| ((length as f64 * scale as f64).round() as u32).clamp(1, length.max(1));
+    (side(width), side(height))
+}
+
+/// `frame` shrunk to `scale` of its size, every pixel the average of the
+/// box of pixels it stands for
+pub fn downsample(frame: &Frame, scale: f32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    let (out_width, out_height) = preview_size(width, height, scale);
+    let mut out = RgbaImage::new(out_width, out_height);
+    if width == 0 || height == 0 {
+        return out;
+    }
+    // Where each box starts, the last entry being where the last one ends
+    let edges = |out: u32, source: u32| -> Vec<usize> {
+        (0..=out)
+            .map(|i| (i as u64 * source as u64 / out as u64) as usize)
+            .collect()
+    };
+    let columns = edges(out_width, width);
+    let rows = edges(out_height, height);
+    let format = frame.format();
+    let mut sums = vec![[0u64; 4]; out_width as usize];
+    for (out_y, band) in rows.windows(2).enumerate() {
+        sums.iter_mut().for_each(|sum| *sum = [0; 4]);
+        for y in band[0]..band[1] {
+            let row = frame.row(y as u32);
+            for (sum, edge) in sums.iter_mut().zip(columns.windows(2)) {
+                for pixel in row[edge[0] * 4..edge[1] * 4].chunks_exact(4) {
+                    for (total, value) in sum.iter_mut().zip(pixel) {
+                        *total += *value as u64;
+                    }
+                }
+            }
+        }
+        for (out_x, (sum, edge)) in sums.iter().zip(columns.windows(2)).enumerate() {
+            let count = ((edge[1] - edge[0]) * (band[1] - band[0])) as u64;
+            let average = sum.map(|total| ((total + count / 2) / count) as u8);
+            out.put_pixel(out_x as u32, out_y as u32, Rgba(format.to_rgba(average)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::PixelFormat;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_boxes_are_averaged() {
+        // 2x2 boxes of one black and three white pixels
+        let image = RgbaImage::from_fn(8, 4, |x, y| match (x % 2, y % 2) {
+            (0, 0) => Rgba([0, 0, 0, 255]),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+        let preview = downsample(&Frame::from_image(&image, SystemTime::now()), 0.5);
+        assert_eq!(preview.dimensions(), (4, 2));
+        assert!(preview.pixels().all(|p| p.0 == [191, 191, 191, 255]));
+
+        // Sizes that don't divide evenly still cover every pixel
+        let odd = RgbaImage::from_pixel(7, 5, Rgba([10, 20, 30, 40]));
+        let preview = downsample(&Frame::from_image(&odd, SystemTime::now()), 0.3);
+        assert_eq!(preview.dimensions(), (2, 2));
+        assert!(preview.pixels().all(|p| p.0 == [10, 20, 30, 40]));
+    }
+
+    #[test]
+    fn test_bgra_frames() {
+        let data = [30, 20, 10, 255].repeat(16);
+        let frame = Frame::new(&data, 4, 4, 16, PixelFormat::Bgra8, SystemTime::now()).unwrap();
+        let preview = downsample(&frame, 0.25);
+        assert_eq!(preview.dimensions(), (1, 1));
+        assert_eq!(preview.get_pixel(0, 0).0, [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_sizes_and_scales() {
+        assert_eq!(preview_size(3840, 2160, 0.1), (384, 216));
+        assert_eq!(preview_size(100, 3, 0.01), (1, 1), "Never empty");
+        assert_eq!(preview_size(10, 10, 1.0), (10, 10));
+        for bad in [0.0, -0.5, 1.5, f32::NAN] {
+            assert!(check_scale(bad).is_err(), "{bad}");
+        }
+        assert_eq!(check_scale(0.25).unwrap(), 0.25);
+    }
+}
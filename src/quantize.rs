@@ -0,0 +1,241 @@
+//! Palette-indexed PNG output
+//!
+//! Flat UI screenshots often use a few hundred colors at most, and storing
+//! them as 8-bit (or smaller) palette indices instead of RGBA shrinks the file
+//! severalfold. [`quantize`] keeps the exact colors when there are few enough
+//! and otherwise builds a palette with NeuQuant, optionally Floyd–Steinberg
+//! dithering the image against it. Selected through
+//! [`EncodeOptions::with_palette`](crate::EncodeOptions::with_palette).
+
+use crate::{Error, Result};
+use color_quant::NeuQuant;
+use screenshots::image::imageops::colorops::dither;
+use screenshots::image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Largest palette a PNG can hold
+pub const MAX_COLORS: u16 = 256;
+
+/// Sample factor for NeuQuant training; 10 is its suggested balance of speed
+/// and quality
+const SAMPLE_FACTOR: i32 = 10;
+
+/// Palette settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Palette size, 2-256; merged palettes look best with 64 or more
+    pub colors: u16,
+    /// Floyd–Steinberg error diffusion when colors had to be merged
+    pub dither: bool,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new(MAX_COLORS)
+    }
+}
+
+impl Palette {
+    pub fn new(colors: u16) -> Self {
+        Self {
+            colors: colors.clamp(2, MAX_COLORS),
+            dither: false,
+        }
+    }
+
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+}
+
+/// An image as palette indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indexed {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA entries, at most 256
+    pub palette: Vec<[u8; 4]>,
+    /// One index per pixel, row major
+    pub indices: Vec<u8>,
+}
+
+impl Indexed {
+    /// Expands back to RGBA
+    pub fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let index = self.indices[(y * self.width + x) as usize];
+            Rgba(self.palette[index as usize])
+        })
+    }
+}
+
+/// Reduces `image` to at most `palette.colors` colors
+pub fn quantize(image: &RgbaImage, palette: &Palette) -> Indexed {
+    let (width, height) = image.dimensions();
+    let limit = palette.colors.clamp(2, MAX_COLORS) as usize;
+    if let Some(indexed) = exact(image, limit) {
+        return indexed;
+    }
+
+    let map = NeuQuant::new(SAMPLE_FACTOR, limit, image.as_raw());
+    let mut image = image.clone();
+    if palette.dither {
+        dither(&mut image, &map);
+    }
+    Indexed {
+        width,
+        height,
+        palette: (0..limit).filter_map(|i| map.lookup(i)).collect(),
+        indices: image.pixels().map(|p| map.index_of(&p.0) as u8).collect(),
+    }
+}
+
+/// Indexes the image losslessly, if it has at most `limit` colors
+fn exact(image: &RgbaImage, limit: usize) -> Option<Indexed> {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.len() / 4);
+    for pixel in image.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(&index) => index,
+            None => {
+                if palette.len() == limit {
+                    return None;
+                }
+                // First-seen order keeps the output deterministic
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some(Indexed {
+        width: image.width(),
+        height: image.height(),
+        palette,
+        indices,
+    })
+}
+
+/// Writes an indexed PNG at the smallest bit depth that fits the palette
+pub fn write_png(indexed: &Indexed, writer: impl Write) -> Result<()> {
+    let bits: u32 = match indexed.palette.len() {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+    let per_byte = (8 / bits) as usize;
+    let width = indexed.width as usize;
+    let mut data = Vec::with_capacity(width.div_ceil(per_byte) * indexed.height as usize);
+    for row in indexed.indices.chunks(width.max(1)) {
+        // Pack from the most significant bit; rows start on a byte boundary
+        for group in row.chunks(per_byte) {
+            let byte = group.iter().enumerate().fold(0u8, |byte, (i, &index)| {
+                byte | index << (8 - bits as usize * (i + 1))
+            });
+            data.push(byte);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(writer, indexed.width, indexed.height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(match bits {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    });
+    encoder.set_compression(png::Compression::Best);
+    // Filtering rarely helps palette data
+    encoder.set_filter(png::FilterType::NoFilter);
+    encoder.set_palette(
+        indexed
+            .palette
+            .iter()
+            .flat_map(|&[r, g, b, _]| [r, g, b])
+            .collect::<Vec<u8>>(),
+    );
+    let alpha: Vec<u8> = indexed.palette.iter().map(|color| color[3]).collect();
+    if let Some(last) = alpha.iter().rposition(|&a| a != 255) {
+        encoder.set_trns(alpha[..=last].to_vec());
+    }
+
+    let io = |e: png::EncodingError| Error::Io(std::io::Error::other(e));
+    let mut writer = encoder.write_header().map_err(io)?;
+    writer.write_image_data(&data).map_err(io)?;
+    writer.finish().map_err(io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode_to_vec, EncodeOptions};
+
+    /// A flat UI: three solid bands, one translucent
+    fn flat_ui() -> RgbaImage {
+        RgbaImage::from_fn(320, 90, |_, y| match y / 30 {
+            0 => Rgba([255, 255, 255, 255]),
+            1 => Rgba([40, 90, 200, 255]),
+            _ => Rgba([0, 0, 0, 100]),
+        })
+    }
+
+    fn decode(bytes: &[u8]) -> RgbaImage {
+        screenshots::image::load_from_memory(bytes)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn test_few_colors_are_exact() {
+        let image = flat_ui();
+        let indexed = quantize(&image, &Palette::default());
+
+        assert_eq!(indexed.palette.len(), 3);
+        assert_eq!(indexed.to_rgba(), image, "Lossless");
+    }
+
+    #[test]
+    fn test_png_round_trips_with_alpha() {
+        let image = flat_ui();
+        let mut png = Vec::new();
+        write_png(&quantize(&image, &Palette::default()), &mut png).unwrap();
+
+        assert_eq!(decode(&png), image, "Palette and tRNS decode back");
+        let rgba = encode_to_vec(&image, &EncodeOptions::default()).unwrap();
+        assert!(png.len() < rgba.len(), "{} vs {}", png.len(), rgba.len());
+    }
+
+    #[test]
+    fn test_many_colors_are_reduced() {
+        let image = RgbaImage::from_fn(64, 64, |x, y| Rgba([x as u8 * 4, y as u8 * 4, 128, 255]));
+
+        for dither in [false, true] {
+            let palette = Palette::new(64).with_dither(dither);
+            let indexed = quantize(&image, &palette);
+            assert!(indexed.palette.len() <= 64, "{}", indexed.palette.len());
+
+            let mut png = Vec::new();
+            write_png(&indexed, &mut png).unwrap();
+            let decoded = decode(&png);
+            let error: u32 = decoded
+                .pixels()
+                .zip(image.pixels())
+                .map(|(a, b)| a.0[0].abs_diff(b.0[0]) as u32)
+                .sum();
+            let mean = error / (64 * 64);
+            assert!(mean < 24, "Close to the source (dither {dither}): {mean}");
+        }
+    }
+
+    #[test]
+    fn test_palette_size_is_clamped() {
+        assert_eq!(Palette::new(0).colors, 2);
+        assert_eq!(Palette::new(1000).colors, 256);
+    }
+}
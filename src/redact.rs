@@ -0,0 +1,152 @@
+//! Automatic redaction of blocklisted windows
+//!
+//! Windows whose application or title matches the `[redact]` config section
+//! are looked up at capture time and their on-screen bounds are obscured with
+//! [`Mask`]s, so password managers or chat apps never end up in a capture.
+
+use crate::backend::CaptureBackend;
+use crate::config::RedactConfig;
+use crate::geometry::{CoordinateMapper, Region};
+use crate::mask::Mask;
+use crate::metadata::DisplayDescriptor;
+use crate::window::{self, WindowInfo};
+use crate::Result;
+use screenshots::image::RgbaImage;
+
+impl RedactConfig {
+    /// Whether no windows are blocklisted
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty() && self.titles.is_empty()
+    }
+
+    /// Whether `window` is blocklisted
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let title = window.title.to_lowercase();
+        self.apps
+            .iter()
+            .any(|app| app.eq_ignore_ascii_case(&window.app))
+            || self
+                .titles
+                .iter()
+                .any(|t| !t.is_empty() && title.contains(&t.to_lowercase()))
+    }
+
+    /// Masks covering the blocklisted `windows` in a capture of the display
+    /// described by `mapper`
+    ///
+    /// `area` is the captured display-local logical area, or `None` for the
+    /// whole display.
+    pub fn masks(
+        &self,
+        windows: &[WindowInfo],
+        mapper: &CoordinateMapper,
+        area: Option<Region>,
+    ) -> Vec<Mask> {
+        windows
+            .iter()
+            .filter(|window| self.matches(window))
+            .filter_map(|window| {
+                let local = mapper.global_to_local(&window.region)?;
+                match area {
+                    Some(area) => {
+                        let visible = local.intersect(&area)?.translate(-area.x, -area.y);
+                        Some(mapper.to_physical(&visible))
+                    }
+                    None => mapper.to_frame(&local),
+                }
+            })
+            .map(|region| Mask::new(region, self.style).with_strength(self.strength))
+            .collect()
+    }
+}
+
+/// Obscures blocklisted windows in a fresh capture of `display`, returning
+/// how many were masked
+///
+/// Fails when windows can't be enumerated, so a capture is never saved
+/// unredacted by accident. Nothing is enumerated when the blocklist is empty.
+pub fn redact(
+    image: &mut RgbaImage,
+    backend: &dyn CaptureBackend,
+    display: &DisplayDescriptor,
+    area: Option<Region>,
+    config: &RedactConfig,
+) -> Result<usize> {
+    if config.is_empty() {
+        return Ok(0);
+    }
+    let mapper = CoordinateMapper::probe(backend, display);
+    let masks = config.masks(&window::list()?, &mapper, area);
+    for mask in &masks {
+        mask.apply_to(image);
+    }
+    Ok(masks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rotation;
+    use crate::mask::MaskStyle;
+    use crate::scaling::ScalingConfig;
+
+    fn window(app: &str, title: &str, region: Region) -> WindowInfo {
+        WindowInfo {
+            id: 1,
+            title: title.into(),
+            app: app.into(),
+            pid: None,
+            region,
+        }
+    }
+
+    fn blocklist() -> RedactConfig {
+        RedactConfig {
+            apps: vec!["KeePassXC".into()],
+            titles: vec!["- signal".into()],
+            ..RedactConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_matching() {
+        let config = blocklist();
+        let region = Region::new(0, 0, 10, 10);
+
+        assert!(config.matches(&window("keepassxc", "Passwords", region)));
+        assert!(config.matches(&window("signal-desktop", "Alice - Signal", region)));
+        assert!(!config.matches(&window("firefox", "Signal handling in Rust", region)));
+        assert!(RedactConfig::default().is_empty());
+    }
+
+    #[test]
+    fn test_masks_map_to_frame_pixels() {
+        // Second display at x=1000, scaled 2x
+        let mapper = CoordinateMapper::new(
+            Region::new(1000, 0, 800, 600),
+            ScalingConfig::new(2.0, 1.0),
+            Rotation::Deg0,
+        );
+        let windows = [
+            window("KeePassXC", "Vault", Region::new(1100, 50, 200, 100)),
+            window("KeePassXC", "Other display", Region::new(0, 0, 300, 300)),
+            window("firefox", "Docs", Region::new(1000, 0, 800, 600)),
+        ];
+
+        let masks = blocklist().masks(&windows, &mapper, None);
+        assert_eq!(
+            masks.len(),
+            1,
+            "Off-display and unlisted windows are skipped"
+        );
+        assert_eq!(masks[0].region, Region::new(200, 100, 400, 200));
+        assert_eq!(masks[0].style, MaskStyle::Blur);
+
+        let masks = blocklist().masks(&windows, &mapper, Some(Region::new(150, 0, 100, 100)));
+        assert_eq!(
+            masks[0].region,
+            Region::new(0, 100, 200, 100),
+            "Area captures are offset and clipped"
+        );
+    }
+}
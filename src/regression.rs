@@ -0,0 +1,636 @@
+//! Visual regression checks against stored baselines
+//!
+//! ```no_run
+//! use snap_scale::{regression, Region};
+//!
+//! #[test]
+//! fn login_dialog_looks_right() {
+//!     // ...drive the application under test...
+//!     regression::assert_screen_matches("login-dialog", Some(Region::new(100, 100, 400, 300)));
+//! }
+//! ```
+//!
+//! Baselines are PNGs in `tests/baselines/` (or `$SNAP_SCALE_BASELINES`).
+//! When a check fails, or a baseline doesn't exist yet, the capture and a diff
+//! heatmap are written to `target/regression/` as `<name>.actual.png` and
+//! `<name>.diff.png`. Run with `SNAP_SCALE_UPDATE_BASELINES=1` to overwrite
+//! the baselines with the current captures instead.
+//!
+//! Each baseline has a `<name>.json` sidecar recording its size and the
+//! display and scale it was captured at; baselines without one fall back to
+//! [metadata](crate::metadata) embedded in the PNG. Every check also touches
+//! `target/regression/seen/<name>`, which lets [`Regression::prune`] find
+//! baselines no test uses anymore.
+
+use crate::atomic;
+use crate::backend::{default_backend, CaptureBackend};
+use crate::diff::{compare, DiffOptions, DiffReport};
+use crate::geometry::{CoordinateMapper, Region};
+use crate::metadata::{self, DisplayDescriptor};
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+use screenshots::Screen;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Overrides the baseline directory
+pub const BASELINES_ENV: &str = "SNAP_SCALE_BASELINES";
+/// Set to `1` to write captures as the new baselines
+pub const UPDATE_ENV: &str = "SNAP_SCALE_UPDATE_BASELINES";
+
+/// The display a baseline was captured on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayMeta {
+    pub id: u32,
+    /// OS-reported scale factor
+    pub scale_factor: f32,
+    /// Scale actually applied to logical coordinates, see [`crate::ScalingConfig`]
+    pub total_scale: f32,
+}
+
+impl DisplayMeta {
+    pub fn of(screen: &Screen) -> Self {
+        Self {
+            id: screen.display_info.id,
+            scale_factor: screen.display_info.scale_factor,
+            total_scale: CoordinateMapper::detect(screen).scaling.total_scale(),
+        }
+    }
+
+    /// The display of `backend`, its scale probed by capturing it
+    pub fn probe(backend: &dyn CaptureBackend, display: &DisplayDescriptor) -> Self {
+        Self {
+            id: display.id,
+            scale_factor: display.scale_factor,
+            total_scale: CoordinateMapper::probe(backend, display)
+                .scaling
+                .total_scale(),
+        }
+    }
+}
+
+/// Sidecar metadata stored next to each baseline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineMeta {
+    pub width: u32,
+    pub height: u32,
+    /// Unset for baselines created from images rather than screen captures
+    pub display: Option<DisplayMeta>,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+}
+
+impl BaselineMeta {
+    fn new(image: &RgbaImage, display: Option<DisplayMeta>) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            display,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+}
+
+/// A stored baseline, as returned by [`Regression::list`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baseline {
+    pub name: String,
+    /// Missing for baselines added by hand
+    pub meta: Option<BaselineMeta>,
+    /// Whether a failed check left a capture that [`Regression::approve`]
+    /// would promote
+    pub pending: bool,
+}
+
+/// Where baselines and failure artifacts live, and how strictly they match
+#[derive(Debug, Clone)]
+pub struct Regression {
+    baselines: PathBuf,
+    artifacts: PathBuf,
+    options: DiffOptions,
+    max_changed_percent: f64,
+    update: bool,
+}
+
+impl Regression {
+    /// Exact matching against `baselines`, with artifacts in
+    /// `target/regression`
+    pub fn new(baselines: impl Into<PathBuf>) -> Self {
+        Self {
+            baselines: baselines.into(),
+            artifacts: PathBuf::from("target/regression"),
+            options: DiffOptions::default(),
+            max_changed_percent: 0.0,
+            update: false,
+        }
+    }
+
+    /// Settings from `$SNAP_SCALE_BASELINES` and `$SNAP_SCALE_UPDATE_BASELINES`
+    pub fn from_env() -> Self {
+        let baselines = std::env::var_os(BASELINES_ENV)
+            .map_or_else(|| PathBuf::from("tests/baselines"), PathBuf::from);
+        let mut regression = Self::new(baselines);
+        regression.update = std::env::var_os(UPDATE_ENV).is_some_and(|v| v == "1");
+        regression
+    }
+
+    pub fn with_artifacts(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.artifacts = dir.into();
+        self
+    }
+
+    /// Largest per-channel difference still counted as unchanged
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.options.tolerance = tolerance;
+        self
+    }
+
+    /// Share of pixels (0-100) allowed to change before a check fails
+    pub fn with_max_changed_percent(mut self, percent: f64) -> Self {
+        self.max_changed_percent = percent;
+        self
+    }
+
+    /// Ignores an area of the capture, in capture pixels
+    pub fn ignore(mut self, region: Region) -> Self {
+        self.options.ignore.push(region);
+        self
+    }
+
+    /// Writes captures as baselines instead of comparing
+    pub fn updating(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    pub fn baseline_dir(&self) -> &Path {
+        &self.baselines
+    }
+
+    pub fn artifact_dir(&self) -> &Path {
+        &self.artifacts
+    }
+
+    pub fn baseline_path(&self, name: &str) -> PathBuf {
+        self.baselines.join(format!("{name}.png"))
+    }
+
+    /// Path of the capture kept from the last failed check of `name`
+    pub fn actual_path(&self, name: &str) -> PathBuf {
+        self.artifacts.join(format!("{name}.actual.png"))
+    }
+
+    pub fn diff_path(&self, name: &str) -> PathBuf {
+        self.artifacts.join(format!("{name}.diff.png"))
+    }
+
+    fn seen_path(&self, name: &str) -> PathBuf {
+        self.artifacts.join("seen").join(name)
+    }
+
+    /// Compares `image` with the baseline `name`
+    ///
+    /// Fails with [`Error::Mismatch`] when the baseline is missing, differs
+    /// in size, or changed more than allowed; the artifacts are written first.
+    pub fn check(&self, name: &str, image: &RgbaImage) -> Result<Option<DiffReport>> {
+        self.check_capture(name, image, None)
+    }
+
+    fn check_capture(
+        &self,
+        name: &str,
+        image: &RgbaImage,
+        display: Option<DisplayMeta>,
+    ) -> Result<Option<DiffReport>> {
+        validate_name(name)?;
+        let seen = self.seen_path(name);
+        write_file(&seen, b"")?;
+
+        let meta = BaselineMeta::new(image, display);
+        let baseline_path = self.baseline_path(name);
+        if self.update {
+            save(image, &meta, &baseline_path)?;
+            return Ok(None);
+        }
+
+        let mismatch = |detail: String| Error::Mismatch {
+            name: name.to_string(),
+            detail,
+        };
+        if !baseline_path.exists() {
+            save(image, &meta, &self.actual_path(name))?;
+            return Err(mismatch(format!(
+                "no baseline at {}; capture saved to {}",
+                baseline_path.display(),
+                self.actual_path(name).display()
+            )));
+        }
+
+        let baseline = screenshots::image::open(&baseline_path)?.into_rgba8();
+        if baseline.dimensions() != image.dimensions() {
+            save(image, &meta, &self.actual_path(name))?;
+            return Err(mismatch(format!(
+                "size {}x{} differs from baseline {}x{}",
+                image.width(),
+                image.height(),
+                baseline.width(),
+                baseline.height()
+            )));
+        }
+
+        let report = compare(&baseline, image, &self.options)?;
+        if report.changed_percent() > self.max_changed_percent {
+            save(image, &meta, &self.actual_path(name))?;
+            atomic::save(&report.heatmap, self.diff_path(name))?;
+            return Err(mismatch(format!(
+                "{:.3}% of pixels changed (allowed {}%, ssim {:.4}); see {}",
+                report.changed_percent(),
+                self.max_changed_percent,
+                report.ssim,
+                self.diff_path(name).display()
+            )));
+        }
+        Ok(Some(report))
+    }
+
+    /// [`check`](Self::check), panicking on failure
+    #[track_caller]
+    pub fn assert_matches(&self, name: &str, image: &RgbaImage) {
+        if let Err(e) = self.check(name, image) {
+            panic!("{e}");
+        }
+    }
+
+    /// Captures a logical area of the primary display (all of it for
+    /// `None`) and asserts it matches the baseline `name`
+    #[track_caller]
+    pub fn assert_screen_matches(&self, name: &str, region: Option<Region>) {
+        let (backend, display) =
+            primary_display().unwrap_or_else(|e| panic!("capture failed: {e}"));
+        let image =
+            capture(&*backend, &display, region).unwrap_or_else(|e| panic!("capture failed: {e}"));
+        let meta = DisplayMeta::probe(&*backend, &display);
+        if let Err(e) = self.check_capture(name, &image, Some(meta)) {
+            panic!("{e}");
+        }
+    }
+
+    /// Every baseline under the baseline directory, sorted by name
+    pub fn list(&self) -> Result<Vec<Baseline>> {
+        let mut names = Vec::new();
+        if self.baselines.exists() {
+            collect_pngs(&self.baselines, "", &mut names)?;
+        }
+        names.sort();
+        Ok(names
+            .into_iter()
+            .map(|name| Baseline {
+                meta: read_meta(&self.baseline_path(&name)),
+                pending: self.actual_path(&name).exists(),
+                name,
+            })
+            .collect())
+    }
+
+    /// Promotes the capture from the last failed check of `name` to be its
+    /// baseline
+    pub fn approve(&self, name: &str) -> Result<()> {
+        validate_name(name)?;
+        let actual = self.actual_path(name);
+        if !actual.exists() {
+            return Err(Error::invalid(
+                "baseline to approve (no failed capture)",
+                name,
+            ));
+        }
+        let baseline = self.baseline_path(name);
+        if let Some(parent) = baseline.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&actual, &baseline)?;
+        std::fs::remove_file(&actual)?;
+        if std::fs::rename(
+            actual.with_extension("json"),
+            baseline.with_extension("json"),
+        )
+        .is_err()
+        {
+            let image = screenshots::image::open(&baseline)?.into_rgba8();
+            write_meta(&baseline, &BaselineMeta::new(&image, None))?;
+        }
+        let _ = std::fs::remove_file(self.diff_path(name));
+        Ok(())
+    }
+
+    /// Deletes baselines that no check has touched since the artifact
+    /// directory was last cleared, returning their names
+    ///
+    /// With `dry_run` nothing is deleted. Clear `target/regression/`, run the
+    /// full test suite, then prune.
+    pub fn prune(&self, dry_run: bool) -> Result<Vec<String>> {
+        if !self.artifacts.join("seen").exists() {
+            return Err(Error::Config(format!(
+                "no checks recorded under {}; run the tests first",
+                self.artifacts.display()
+            )));
+        }
+
+        let stale: Vec<_> = self
+            .list()?
+            .into_iter()
+            .map(|baseline| baseline.name)
+            .filter(|name| !self.seen_path(name).exists())
+            .collect();
+        if !dry_run {
+            for name in &stale {
+                let path = self.baseline_path(name);
+                std::fs::remove_file(&path)?;
+                let _ = std::fs::remove_file(path.with_extension("json"));
+            }
+        }
+        Ok(stale)
+    }
+}
+
+/// [`Regression::assert_screen_matches`] with settings from the environment
+#[track_caller]
+pub fn assert_screen_matches(name: &str, region: Option<Region>) {
+    Regression::from_env().assert_screen_matches(name, region);
+}
+
+/// Captures a display-local logical area of the primary display
+pub fn capture_primary(region: Option<Region>) -> Result<RgbaImage> {
+    let (backend, display) = primary_display()?;
+    capture(&*backend, &display, region)
+}
+
+fn primary_display() -> Result<(Arc<dyn CaptureBackend>, DisplayDescriptor)> {
+    let backend = default_backend()?;
+    let displays = backend.enumerate()?;
+    let display = displays
+        .iter()
+        .find(|d| d.is_primary)
+        .or(displays.first())
+        .cloned()
+        .ok_or_else(|| Error::Unsupported("no display to capture".into()))?;
+    Ok((backend, display))
+}
+
+fn capture(
+    backend: &dyn CaptureBackend,
+    display: &DisplayDescriptor,
+    region: Option<Region>,
+) -> Result<RgbaImage> {
+    match region {
+        Some(region) => {
+            let physical = CoordinateMapper::probe(backend, display).to_physical(&region);
+            backend.capture_area(display, physical)
+        }
+        None => backend.capture_display(display),
+    }
+}
+
+/// Baseline names may contain `/` for grouping but must stay relative
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('/')
+        && name.split('/').all(|part| {
+            !part.is_empty()
+                && part != ".."
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::invalid("baseline name", name))
+    }
+}
+
+/// Names (relative, without `.png`) of the baselines under `dir`
+fn collect_pngs(dir: &Path, prefix: &str, names: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            collect_pngs(&path, &format!("{prefix}{file_name}/"), names)?;
+        } else if let Some(stem) = file_name.strip_suffix(".png") {
+            names.push(format!("{prefix}{stem}"));
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    atomic::write(path, contents)
+}
+
+/// Writes `image` and its `.json` sidecar
+fn save(image: &RgbaImage, meta: &BaselineMeta, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    atomic::save(image, path)?;
+    write_meta(path, meta)
+}
+
+fn write_meta(image_path: &Path, meta: &BaselineMeta) -> Result<()> {
+    let json = serde_json::to_vec_pretty(meta).map_err(|e| Error::Io(e.into()))?;
+    write_file(&image_path.with_extension("json"), &json)
+}
+
+fn read_meta(image_path: &Path) -> Option<BaselineMeta> {
+    match std::fs::read(image_path.with_extension("json")) {
+        Ok(json) => serde_json::from_slice(&json).ok(),
+        Err(_) => embedded_meta(image_path),
+    }
+}
+
+/// Metadata of a baseline saved with `--metadata` instead of by [`Regression`]
+fn embedded_meta(image_path: &Path) -> Option<BaselineMeta> {
+    let bytes = std::fs::read(image_path).ok()?;
+    let meta = metadata::read_png(&bytes)?;
+    let (width, height) = screenshots::image::load_from_memory(&bytes)
+        .ok()?
+        .to_rgba8()
+        .dimensions();
+    let created = chrono::DateTime::parse_from_rfc3339(&meta.captured_at)
+        .map(|time| time.timestamp().max(0) as u64)
+        .unwrap_or_default();
+    Some(BaselineMeta {
+        width,
+        height,
+        display: Some(DisplayMeta {
+            id: meta.display_id,
+            scale_factor: meta.scale_factor,
+            // Only the OS factor is embedded
+            total_scale: meta.scale_factor,
+        }),
+        created,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "snap_scale_regression_{test}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn setup(dir: &Path) -> Regression {
+        Regression::new(dir.join("baselines")).with_artifacts(dir.join("artifacts"))
+    }
+
+    fn frame(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(20, 10, Rgba(color))
+    }
+
+    #[test]
+    fn test_missing_baseline_fails_with_artifact() {
+        let dir = scratch("missing");
+        let regression = setup(&dir);
+
+        let err = regression
+            .check("dialog", &frame([1, 2, 3, 255]))
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Mismatch { .. }), "{err}");
+        assert!(regression.actual_path("dialog").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_then_match() {
+        let dir = scratch("update");
+        let regression = setup(&dir);
+        let image = frame([1, 2, 3, 255]);
+
+        regression
+            .clone()
+            .updating(true)
+            .check("group/dialog", &image)
+            .unwrap();
+        let report = regression.check("group/dialog", &image).unwrap().unwrap();
+
+        assert!(report.is_identical());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_change_writes_diff_unless_masked() {
+        let dir = scratch("change");
+        let regression = setup(&dir);
+        regression
+            .clone()
+            .updating(true)
+            .check("clock", &frame([0, 0, 0, 255]))
+            .unwrap();
+        let mut changed = frame([0, 0, 0, 255]);
+        changed.put_pixel(18, 2, Rgba([255, 255, 255, 255]));
+
+        assert!(regression.check("clock", &changed).is_err());
+        assert!(regression.diff_path("clock").exists());
+
+        let masked = regression.clone().ignore(Region::new(15, 0, 5, 5));
+        assert!(masked.check("clock", &changed).is_ok());
+        let lenient = regression.with_max_changed_percent(1.0);
+        assert!(lenient.check("clock", &changed).is_ok(), "1 of 200 pixels");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_approve_list_and_prune() {
+        let dir = scratch("manage");
+        let regression = setup(&dir);
+        let image = frame([9, 9, 9, 255]);
+        regression
+            .clone()
+            .updating(true)
+            .check("old", &image)
+            .unwrap();
+        let _ = std::fs::remove_dir_all(regression.artifact_dir());
+
+        assert!(regression.check("dialogs/new", &image).is_err());
+        let listed = regression.list().unwrap();
+        assert_eq!(listed.len(), 1, "Unapproved captures aren't baselines");
+
+        regression.approve("dialogs/new").unwrap();
+        regression.check("dialogs/new", &image).unwrap();
+        let listed = regression.list().unwrap();
+        let names: Vec<_> = listed.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["dialogs/new", "old"]);
+        let meta = listed[0]
+            .meta
+            .as_ref()
+            .expect("approved baselines keep metadata");
+        assert_eq!((meta.width, meta.height), (20, 10));
+        assert!(!listed[0].pending);
+
+        assert_eq!(regression.prune(true).unwrap(), ["old"]);
+        assert!(
+            regression.baseline_path("old").exists(),
+            "Dry runs keep files"
+        );
+        regression.prune(false).unwrap();
+        assert!(!regression.baseline_path("old").exists());
+        assert!(!regression
+            .baseline_path("old")
+            .with_extension("json")
+            .exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_embedded_metadata_stands_in_for_sidecar() {
+        let dir = scratch("embedded");
+        let regression = setup(&dir);
+        let meta = metadata::CaptureMetadata {
+            display_id: 3,
+            x: 0,
+            y: 0,
+            scale_factor: 2.0,
+            rotation: 0.0,
+            captured_at: "2024-05-01T12:30:00+00:00".into(),
+            version: "0.1.0".into(),
+        };
+        let options = crate::EncodeOptions::default();
+        let png = crate::encode::encode_to_vec(&frame([4, 5, 6, 255]), &options).unwrap();
+        let png = metadata::embed(&png, options.format, &meta).unwrap();
+        std::fs::create_dir_all(&regression.baselines).unwrap();
+        std::fs::write(regression.baseline_path("tagged"), png).unwrap();
+
+        let listed = regression.list().unwrap();
+        let meta = listed[0].meta.as_ref().expect("read from the PNG");
+        assert_eq!((meta.width, meta.height), (20, 10));
+        assert_eq!(meta.created, 1_714_566_600);
+        assert_eq!(meta.display.as_ref().map(|d| d.id), Some(3));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_names_stay_inside_the_baseline_dir() {
+        for name in ["ok", "group/ok-1", "a.b_c"] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+        for name in ["", "/abs", "../up", "a//b", "sp ace"] {
+            assert!(validate_name(name).is_err(), "{name}");
+        }
+    }
+}
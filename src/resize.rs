@@ -0,0 +1,347 @@
+//! Downscaling captures at save time
+//!
+//! A [`Resize`] step scales the finished image to a percentage or to a target
+//! width and/or height, so HiDPI captures can be written at a practical size
+//! without a separate tool. A missing width or height follows the aspect
+//! ratio, and `1920x1080>` only shrinks captures larger than that to fit.
+//! Targets over [`MAX_PIXELS`] are refused rather than allocated.
+
+use crate::transform::{Rgba16Image, Transform};
+use crate::{Error, Result};
+use screenshots::image::imageops::{self, FilterType};
+use screenshots::image::{ImageBuffer, Pixel, RgbaImage};
+use std::str::FromStr;
+
+/// Most pixels a resize may produce, 16384×16384: a gigabyte of RGBA
+pub const MAX_PIXELS: u64 = 1 << 28;
+
+/// Fails for a `width`×`height` target over [`MAX_PIXELS`]
+fn check_pixels(width: u32, height: u32) -> Result<()> {
+    match (width as u64).checked_mul(height as u64) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(Error::invalid(
+            "resize",
+            format!("{width}x{height} is over {MAX_PIXELS} pixels"),
+        )),
+    }
+}
+
+/// Resampling filter, from fastest to sharpest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Keeps hard pixel edges; best for pixel-exact UI at integer factors
+    Nearest,
+    Bilinear,
+    #[default]
+    Lanczos3,
+}
+
+impl Filter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Bilinear => FilterType::Triangle,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Self::Nearest),
+            "bilinear" => Ok(Self::Bilinear),
+            "lanczos3" | "lanczos" => Ok(Self::Lanczos3),
+            _ => Err(Error::invalid(
+                "resize filter (nearest, bilinear, lanczos3)",
+                s,
+            )),
+        }
+    }
+}
+
+/// Target size of a [`Resize`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    /// Scale both sides by a percentage
+    Percent(f32),
+    /// Fixed width and/or height; at least one is set
+    Exact {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    /// Shrink to fit within a width and height, keeping the aspect ratio;
+    /// smaller images are left as they are
+    Within { width: u32, height: u32 },
+}
+
+impl Size {
+    /// The output dimensions for an image of `width`×`height`, at least 1×1
+    pub fn dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |side: u32, factor: f64| ((side as f64 * factor).round() as u32).max(1);
+        match *self {
+            Self::Percent(percent) => {
+                let factor = percent as f64 / 100.0;
+                (scaled(width, factor), scaled(height, factor))
+            }
+            Self::Exact {
+                width: Some(w),
+                height: Some(h),
+            } => (w, h),
+            Self::Exact {
+                width: Some(w),
+                height: None,
+            } => (w, scaled(height, w as f64 / width.max(1) as f64)),
+            Self::Exact {
+                width: None,
+                height: Some(h),
+            } => (scaled(width, h as f64 / height.max(1) as f64), h),
+            Self::Exact {
+                width: None,
+                height: None,
+            } => (width, height),
+            Self::Within {
+                width: max_width,
+                height: max_height,
+            } => {
+                let factor = (max_width as f64 / width.max(1) as f64)
+                    .min(max_height as f64 / height.max(1) as f64);
+                match factor < 1.0 {
+                    true => (scaled(width, factor), scaled(height, factor)),
+                    false => (width, height),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = Error;
+
+    /// Parses `50%`, `1280x`, `x720`, `1280x720` or `1280x720>`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::invalid("resize (50%, 1280x, x720, 1280x720 or 1280x720>)", s);
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f32 = percent.trim().parse().map_err(|_| invalid())?;
+            if !percent.is_finite() || percent <= 0.0 {
+                return Err(invalid());
+            }
+            return Ok(Self::Percent(percent));
+        }
+
+        let within = s.strip_suffix('>');
+        let (width, height) = within
+            .unwrap_or(s)
+            .split_once(['x', 'X'])
+            .ok_or_else(invalid)?;
+        let side = |value: &str| -> Result<Option<u32>> {
+            match value.trim() {
+                "" => Ok(None),
+                value => match value.parse() {
+                    Ok(0) | Err(_) => Err(invalid()),
+                    Ok(side) => Ok(Some(side)),
+                },
+            }
+        };
+        let (width, height) = (side(width)?, side(height)?);
+        if let (Some(width), Some(height)) = (width, height) {
+            check_pixels(width, height)?;
+        }
+        match (width, height) {
+            (None, None) => Err(invalid()),
+            (Some(width), Some(height)) if within.is_some() => Ok(Self::Within { width, height }),
+            _ if within.is_some() => Err(invalid()),
+            _ => Ok(Self::Exact { width, height }),
+        }
+    }
+}
+
+/// Scales captures to a target size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resize {
+    pub size: Size,
+    pub filter: Filter,
+}
+
+impl Resize {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            filter: Filter::default(),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Fails for a target over [`MAX_PIXELS`], which a percentage or a
+    /// single side only gives away once the image's size is known
+    fn scale<P>(
+        &self,
+        image: ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> Result<ImageBuffer<P, Vec<P::Subpixel>>>
+    where
+        P: Pixel + 'static,
+    {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(image);
+        }
+        let (new_width, new_height) = self.size.dimensions(width, height);
+        if (new_width, new_height) == (width, height) {
+            return Ok(image);
+        }
+        check_pixels(new_width, new_height)?;
+        Ok(imageops::resize(
+            &image,
+            new_width,
+            new_height,
+            self.filter.filter_type(),
+        ))
+    }
+}
+
+impl Transform for Resize {
+    fn name(&self) -> &str {
+        "resize"
+    }
+
+    fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+        self.scale(image)
+    }
+
+    fn apply16(&self, image: Rgba16Image) -> Result<Rgba16Image> {
+        self.scale(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!("50%".parse::<Size>().unwrap(), Size::Percent(50.0));
+        assert_eq!(
+            "1280x".parse::<Size>().unwrap(),
+            Size::Exact {
+                width: Some(1280),
+                height: None
+            }
+        );
+        assert_eq!(
+            "x720".parse::<Size>().unwrap(),
+            Size::Exact {
+                width: None,
+                height: Some(720)
+            }
+        );
+        assert_eq!(
+            "800X600".parse::<Size>().unwrap(),
+            Size::Exact {
+                width: Some(800),
+                height: Some(600)
+            }
+        );
+        assert_eq!(
+            "1920x1080>".parse::<Size>().unwrap(),
+            Size::Within {
+                width: 1920,
+                height: 1080
+            }
+        );
+        for bad in [
+            "",
+            "x",
+            "0%",
+            "-5%",
+            "0x10",
+            "abc",
+            "12",
+            "1920x>",
+            "50%>",
+            "100000x100000",
+            "4294967295x4294967295>",
+        ] {
+            assert!(bad.parse::<Size>().is_err(), "{bad:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_dimensions_keep_aspect_ratio() {
+        let size = |s: &str| s.parse::<Size>().unwrap();
+
+        assert_eq!(size("50%").dimensions(2880, 1800), (1440, 900));
+        assert_eq!(size("1280x").dimensions(2560, 1600), (1280, 800));
+        assert_eq!(size("x400").dimensions(2560, 1600), (640, 400));
+        assert_eq!(size("100x100").dimensions(2560, 1600), (100, 100));
+        assert_eq!(size("1%").dimensions(10, 10), (1, 1), "Never collapses");
+        assert_eq!(size("1920x1080>").dimensions(3840, 1600), (1920, 800));
+        assert_eq!(size("1920x1080>").dimensions(1440, 2160), (720, 1080));
+        assert_eq!(
+            size("1920x1080>").dimensions(800, 600),
+            (800, 600),
+            "Never grows"
+        );
+    }
+
+    #[test]
+    fn test_resize_applies_filter() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+
+        let nearest = Resize::new(Size::Percent(50.0))
+            .with_filter(Filter::Nearest)
+            .apply(image.clone())
+            .unwrap();
+        assert_eq!(nearest.dimensions(), (2, 2));
+        assert!(
+            nearest.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255),
+            "Nearest keeps original values"
+        );
+
+        let smooth = Resize::new(Size::Percent(50.0))
+            .with_filter(Filter::Bilinear)
+            .apply(image)
+            .unwrap();
+        let corner = smooth.get_pixel(0, 0).0[0];
+        assert!(corner > 0 && corner < 255, "Bilinear averages: {corner}");
+    }
+
+    #[test]
+    fn test_resize_keeps_16_bit_precision() {
+        let image = Rgba16Image::from_fn(4, 4, |x, _| Rgba([1000 + x as u16, 0, 0, 65535]));
+        let out = Resize::new(Size::Percent(50.0))
+            .with_filter(Filter::Bilinear)
+            .apply16(image)
+            .unwrap();
+
+        assert_eq!(out.dimensions(), (2, 2));
+        let level = out.get_pixel(0, 0).0[0];
+        assert!(
+            (1000..=1003).contains(&level),
+            "Not rounded to 8 bits: {level}"
+        );
+    }
+
+    #[test]
+    fn test_refuses_huge_targets() {
+        let image = RgbaImage::new(100, 10);
+        for size in ["100000%", "x5000000"] {
+            let resize = Resize::new(size.parse().unwrap());
+            assert!(resize.apply(image.clone()).is_err(), "{size} should fail");
+        }
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!("Lanczos3".parse::<Filter>().unwrap(), Filter::Lanczos3);
+        assert_eq!("bilinear".parse::<Filter>().unwrap(), Filter::Bilinear);
+        assert!("cubic".parse::<Filter>().is_err());
+    }
+}
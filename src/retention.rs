@@ -0,0 +1,438 @@
+//! Pruning of old captures
+//!
+//! Watch mode runs until stopped and would fill the disk eventually. A
+//! [`Retention`] policy caps a capture directory by age, number of captures
+//! and total size; [`Retention::plan`] lists what breaks the caps, oldest
+//! captures first to go, and [`Retention::prune`] deletes it. A capture is an
+//! image file in the directory, or below it when [`Retention::recursive`],
+//! named the way snap_scale names captures (see [`is_capture_name`]) or
+//! matching [`Retention::name`], together with its `.json` and `.txt`
+//! sidecars: `shot-1.png.json` and `shot-1.png.txt` for `shot-1.png`. Other
+//! files, such as photos in a directory given by mistake, are never counted
+//! or deleted.
+
+use crate::encode::OutputFormat;
+use crate::metadata::{sidecar_path, text_path};
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Limits on what a capture directory keeps; unset limits don't apply
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_count: Option<usize>,
+    /// Total bytes of the kept captures and their sidecars
+    pub max_size: Option<u64>,
+    /// Glob with `*` and `?` that captures' file names match, instead of
+    /// [`is_capture_name`]
+    pub name: Option<String>,
+    /// Also look for captures in subdirectories, e.g. from `--date-dirs`
+    pub recursive: bool,
+}
+
+/// A capture found by [`Retention::plan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub path: PathBuf,
+    /// Sidecar files that go with it
+    pub sidecars: Vec<PathBuf>,
+    /// Bytes of the image and its sidecars
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl Retention {
+    /// Whether any limit is set
+    pub fn is_active(&self) -> bool {
+        self.max_age.is_some() || self.max_count.is_some() || self.max_size.is_some()
+    }
+
+    /// Captures under `dir` that break a limit at time `now`, oldest first
+    ///
+    /// Captures are kept newest first while they fit every limit; once one
+    /// doesn't fit the count or size, all older ones go too.
+    pub fn plan(&self, dir: impl AsRef<Path>, now: SystemTime) -> Result<Vec<Capture>> {
+        let mut captures = Vec::new();
+        collect(self, dir.as_ref(), &mut captures, &mut HashSet::new())?;
+        captures.sort_by(|a, b| b.modified.cmp(&a.modified).then(b.path.cmp(&a.path)));
+
+        let (mut count, mut size) = (0, 0);
+        let mut full = false;
+        let mut expired = Vec::new();
+        for capture in captures {
+            let age = now.duration_since(capture.modified).unwrap_or_default();
+            full = full
+                || self.max_count.is_some_and(|max| count + 1 > max)
+                || self.max_size.is_some_and(|max| size + capture.size > max);
+            if full || self.max_age.is_some_and(|max| age > max) {
+                expired.push(capture);
+            } else {
+                count += 1;
+                size += capture.size;
+            }
+        }
+        expired.reverse();
+        Ok(expired)
+    }
+
+    /// Deletes what [`Retention::plan`] lists, returning it
+    ///
+    /// Files already gone, e.g. deleted by hand meanwhile, are skipped.
+    pub fn prune(&self, dir: impl AsRef<Path>) -> Result<Vec<Capture>> {
+        let expired = self.plan(dir, SystemTime::now())?;
+        for capture in &expired {
+            for path in std::iter::once(&capture.path).chain(&capture.sidecars) {
+                match fs::remove_file(path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Whether `path` is a capture these limits apply to
+    fn counts(&self, path: &Path) -> bool {
+        let image = path
+            .extension()
+            .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()))
+            .is_some();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        image
+            && match &self.name {
+                Some(pattern) => glob_matches(pattern, &name),
+                None => is_capture_name(&name),
+            }
+    }
+}
+
+/// Whether `name` is one snap_scale gives captures: a stem ending in `-` and
+/// a number, a frame number or the time, as in `1-000042.png` or
+/// `1-20261014-104211-123.png`
+pub fn is_capture_name(name: &str) -> bool {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.rsplit_once('-')
+        .is_some_and(|(_, number)| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Matches `name` against `pattern`, where `*` stands for any run of
+/// characters and `?` for one
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // Where the last `*` matched, to backtrack to with one more character
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => (p, n) = (p + 1, n + 1),
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    (p, n) = (star_p + 1, star_n + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Adds the captures in `dir` that `retention` counts to `captures`;
+/// `claimed` holds the sidecars already counted, so none goes with two
+/// captures
+fn collect(
+    retention: &Retention,
+    dir: &Path,
+    captures: &mut Vec<Capture>,
+    claimed: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing saved yet
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            if retention.recursive {
+                collect(retention, &path, captures, claimed)?;
+            }
+            continue;
+        }
+        if !retention.counts(&path) {
+            continue;
+        }
+        let sidecars: Vec<_> = [sidecar_path(&path), text_path(&path)]
+            .into_iter()
+            .filter(|sidecar| sidecar.is_file() && claimed.insert(sidecar.clone()))
+            .collect();
+        let mut size = metadata.len();
+        for sidecar in &sidecars {
+            size += fs::metadata(sidecar)?.len();
+        }
+        captures.push(Capture {
+            path,
+            sidecars,
+            size,
+            modified: metadata.modified()?,
+        });
+    }
+    Ok(())
+}
+
+/// Parses an age such as `90s`, `30m`, `12h`, `7d` or `2w`
+pub fn parse_age(s: &str) -> Result<Duration> {
+    let invalid = || Error::invalid("age (expected e.g. 30m, 12h or 7d)", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Parses a size such as `500M`, `1.5G` or `2GiB`; units are powers of 1024
+pub fn parse_size(s: &str) -> Result<u64> {
+    let invalid = || Error::invalid("size (expected e.g. 500M or 2G)", s);
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or(unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let power = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(invalid()),
+    };
+    let bytes = number * 1024f64.powi(power);
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// A directory of `shot-<n>.png` files, `n` minutes old and `n + 1`
+    /// kilobytes each; returns the directory and the time they're judged at
+    fn captures(test: &str, ages: &[u64]) -> (PathBuf, SystemTime) {
+        let dir = std::env::temp_dir().join(format!(
+            "snap_scale_retention_{test}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let now = SystemTime::now();
+        for &age in ages {
+            let path = dir.join(format!("shot-{age}.png"));
+            fs::write(&path, vec![0; 1024 * (age as usize + 1)]).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - MINUTE * age as u32).unwrap();
+        }
+        (dir, now)
+    }
+
+    fn names(plan: &[Capture]) -> Vec<String> {
+        plan.iter()
+            .map(|c| c.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_limits_expire_the_oldest() {
+        let (dir, now) = captures("limits", &[0, 1, 2, 3]);
+        let by_age = Retention {
+            max_age: Some(MINUTE + MINUTE / 2),
+            ..Retention::default()
+        };
+        assert_eq!(
+            names(&by_age.plan(&dir, now).unwrap()),
+            ["shot-3.png", "shot-2.png"]
+        );
+
+        let by_count = Retention {
+            max_count: Some(3),
+            ..Retention::default()
+        };
+        assert_eq!(names(&by_count.plan(&dir, now).unwrap()), ["shot-3.png"]);
+
+        // 1 + 2 KiB fit, 3 more don't
+        let by_size = Retention {
+            max_size: Some(5 * 1024),
+            ..Retention::default()
+        };
+        assert_eq!(
+            names(&by_size.plan(&dir, now).unwrap()),
+            ["shot-3.png", "shot-2.png"]
+        );
+        assert!(Retention::default().plan(&dir, now).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune_deletes_captures_and_sidecars() {
+        let (dir, _) = captures("prune", &[0, 5]);
+        fs::write(dir.join("shot-5.png.json"), "{}").unwrap();
+        fs::write(dir.join("shot-5.png.txt"), "text").unwrap();
+        // Same name, other capture
+        fs::write(dir.join("shot-5.txt"), "notes").unwrap();
+        fs::write(dir.join("shot-5.jpg"), "jpg").unwrap();
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.join("shot-5.jpg"))
+            .unwrap();
+        file.set_modified(SystemTime::now() - MINUTE * 5).unwrap();
+        fs::write(dir.join("notes.md"), "not a capture").unwrap();
+        for old in [
+            dir.join("photo.png"),
+            dir.join("nested").join("shot-10.png"),
+        ] {
+            fs::write(&old, "png").unwrap();
+            let file = fs::File::options().write(true).open(&old).unwrap();
+            file.set_modified(SystemTime::now() - MINUTE * 10).unwrap();
+        }
+
+        let retention = Retention {
+            max_age: Some(MINUTE),
+            ..Retention::default()
+        };
+        let pruned = retention.prune(&dir).unwrap();
+        let mut pruned_names = names(&pruned);
+        pruned_names.sort();
+        assert_eq!(pruned_names, ["shot-5.jpg", "shot-5.png"]);
+        let png = pruned
+            .iter()
+            .find(|c| c.path.ends_with("shot-5.png"))
+            .unwrap();
+        assert_eq!(png.size, 6 * 1024 + 2 + 4, "Counts sidecars");
+
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "nested",
+                "notes.md",
+                "photo.png",
+                "shot-0.png",
+                "shot-5.txt"
+            ],
+            "Images not named like captures and subdirectories are left alone"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recursive_and_named_captures() {
+        let (dir, now) = captures("named", &[3]);
+        for (name, age) in [("nested/shot-2.png", 2), ("photo.png", 1)] {
+            let path = dir.join(name);
+            fs::write(&path, "png").unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - MINUTE * age).unwrap();
+        }
+
+        let recursive = Retention {
+            max_count: Some(1),
+            recursive: true,
+            ..Retention::default()
+        };
+        assert_eq!(names(&recursive.plan(&dir, now).unwrap()), ["shot-3.png"]);
+
+        let named = Retention {
+            max_count: Some(1),
+            name: Some("*.png".into()),
+            ..Retention::default()
+        };
+        assert_eq!(names(&named.plan(&dir, now).unwrap()), ["shot-3.png"]);
+        let photos = Retention {
+            name: Some("photo.*".into()),
+            ..named
+        };
+        assert!(photos.plan(&dir, now).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capture_names() {
+        for name in [
+            "1-000042.png",
+            "1-20261014-104211-123.png",
+            "editor-20261014-104211.jpg",
+        ] {
+            assert!(is_capture_name(name), "{name}");
+        }
+        for name in ["photo.png", "IMG_1234.jpg", "shot-.png", "shot-1a.png"] {
+            assert!(!is_capture_name(name), "{name}");
+        }
+
+        assert!(glob_matches("*.png", "shot.png"));
+        assert!(glob_matches("shot-??.p*g", "shot-12.png"));
+        assert!(glob_matches("*-*-*", "a-b-c"));
+        assert!(!glob_matches("shot-?.png", "shot-12.png"));
+        assert!(!glob_matches("*.png", "shot.png.json"));
+    }
+
+    #[test]
+    fn test_missing_directory_has_nothing_to_prune() {
+        let retention = Retention {
+            max_count: Some(1),
+            ..Retention::default()
+        };
+        assert!(retention.prune("target/does-not-exist").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_age_and_size() {
+        assert_eq!(parse_age("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86400));
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_secs(14 * 86400));
+        for bad in ["", "7", "d", "7y", "-1d"] {
+            assert!(parse_age(bad).is_err(), "{bad:?}");
+        }
+
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_size("1.5G").unwrap(), 3 << 29);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("4kb").unwrap(), 4096);
+        for bad in ["", "M", "5X", "1e9"] {
+            assert!(parse_size(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Trying captures again when they fail for a moment
+//!
+//! Right after a display wakes or changes resolution, capture APIs can fail
+//! for a few hundred milliseconds before things settle. A [`RetryPolicy`]
+//! runs a capture again after a delay that grows with every failure, and
+//! reports every attempt's failure when none of them succeed.
+
+use crate::{Error, Result};
+use std::thread;
+use std::time::Duration;
+
+/// How many times to try, and how long to wait in between
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first included; 0 counts as 1
+    pub attempts: u32,
+    /// Wait before the first retry
+    pub delay: Duration,
+    /// What each wait is multiplied by for the next one
+    pub backoff: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_millis(100),
+            backoff: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Try once and never again
+    pub fn none() -> Self {
+        Self {
+            attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The wait before retry `retry`, counting from 0
+    pub fn delay_before(&self, retry: u32) -> Duration {
+        let factor = self
+            .backoff
+            .max(0.0)
+            .powi(retry.min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+
+    /// Runs `attempt` until it succeeds or the attempts run out
+    ///
+    /// Invalid arguments and panics fail the same way every time, and a call
+    /// that timed out may be hung still, so those are returned at once. A
+    /// single failed attempt is returned as it is; more fail with
+    /// [`Error::Retries`] holding each of them.
+    pub fn run<T>(&self, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+        let attempts = self.attempts.max(1);
+        let mut failures = Vec::new();
+        for retry in 0..attempts {
+            if retry > 0 {
+                thread::sleep(self.delay_before(retry - 1));
+            }
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(
+                    e @ (Error::Invalid { .. } | Error::Timeout { .. } | Error::Panicked { .. }),
+                ) => return Err(e),
+                Err(e) => {
+                    tracing::debug!(attempt = retry + 1, attempts, "{e}");
+                    failures.push(e);
+                }
+            }
+        }
+        match failures.len() {
+            1 => Err(failures.remove(0)),
+            _ => Err(Error::Retries { failures }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn quick(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            delay: Duration::from_millis(1),
+            backoff: 2.0,
+        }
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let calls = Cell::new(0);
+        let result = quick(3).run(|| {
+            calls.set(calls.get() + 1);
+            match calls.get() {
+                3 => Ok("captured"),
+                n => Err(Error::Unsupported(format!("display asleep ({n})"))),
+            }
+        });
+        assert_eq!(result.unwrap(), "captured");
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_failures_are_aggregated() {
+        let calls = Cell::new(0);
+        let err = quick(3)
+            .run(|| -> Result<()> {
+                calls.set(calls.get() + 1);
+                Err(Error::Unsupported(format!("attempt {}", calls.get())))
+            })
+            .unwrap_err();
+        let Error::Retries { failures } = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(failures.len(), 3);
+        let message = err.to_string();
+        assert!(message.contains("3 attempts"), "{message}");
+        assert!(message.contains("attempt 1") && message.contains("attempt 3"));
+
+        // Nothing to aggregate
+        let once = quick(1).run(|| -> Result<()> { Err(Error::Unsupported("gone".into())) });
+        assert!(matches!(once, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_invalid_arguments_and_timeouts_arent_retried() {
+        let calls = Cell::new(0);
+        let err = quick(5)
+            .run(|| -> Result<()> {
+                calls.set(calls.get() + 1);
+                Err(Error::invalid("area", "outside the display"))
+            })
+            .unwrap_err();
+        assert!(matches!(err, Error::Invalid { .. }));
+        assert_eq!(calls.get(), 1);
+
+        let hung = quick(5).run(|| -> Result<()> {
+            calls.set(calls.get() + 1);
+            Err(Error::Timeout {
+                what: "capture".into(),
+                after: Duration::from_secs(1),
+            })
+        });
+        assert!(matches!(hung, Err(Error::Timeout { .. })));
+        assert_eq!(calls.get(), 2, "Timeouts aren't retried either");
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay_before(0), Duration::from_millis(100));
+        assert_eq!(policy.delay_before(2), Duration::from_millis(400));
+        let flat = RetryPolicy {
+            backoff: 1.0,
+            ..policy
+        };
+        assert_eq!(flat.delay_before(10), Duration::from_millis(100));
+        let huge = RetryPolicy {
+            backoff: 1e300,
+            ..policy
+        };
+        assert_eq!(huge.delay_before(5), Duration::MAX);
+    }
+}
@@ -0,0 +1,462 @@
+//! Looking a capture over before it's saved
+//!
+//! [`review`] opens a capture in a small window with a toolbar, the quick
+//! review screenshot tools show after a capture: **Save** it, **Copy** it to
+//! the clipboard, **Annotate** it by dragging rectangles over it, or
+//! **Discard** it. The keys `S`, `C`, `A` and `Esc` do the same, and
+//! `Backspace` takes back the last rectangle. Closing the window discards.
+//!
+//! [`Review`] holds the state and draws each frame, so everything but the
+//! window itself is tested without a display.
+
+use crate::annotate::{draw_text, measure_text, Annotator, Shape, TextStyle};
+use crate::color::Color;
+use crate::geometry::Region;
+use crate::{Error, Result};
+use screenshots::image::{imageops, Rgba, RgbaImage};
+
+/// Largest the captured image is shown, in window pixels; bigger captures
+/// are shrunk to fit
+pub const MAX_VIEW: (u32, u32) = (1280, 800);
+
+/// Height of the toolbar under the image
+pub const TOOLBAR_HEIGHT: u32 = 32;
+
+/// Narrowest the window gets, so the toolbar's labels fit
+const MIN_WIDTH: u32 = 480;
+
+const TOOLBAR: Color = Color::new(40, 40, 40, 255);
+const ACTIVE: Color = Color::new(70, 110, 170, 255);
+const DIVIDER: Color = Color::new(90, 90, 90, 255);
+
+/// A toolbar button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Save,
+    Copy,
+    Annotate,
+    Discard,
+}
+
+impl Button {
+    pub const ALL: [Button; 4] = [
+        Button::Save,
+        Button::Copy,
+        Button::Annotate,
+        Button::Discard,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Button::Save => "Save (S)",
+            Button::Copy => "Copy (C)",
+            Button::Annotate => "Annotate (A)",
+            Button::Discard => "Discard (Esc)",
+        }
+    }
+}
+
+/// What the reviewer decided
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Save this image: the capture with any annotations burned in
+    Save(RgbaImage),
+    Discard,
+}
+
+/// A capture under review and what's been done to it
+#[derive(Debug, Clone)]
+pub struct Review {
+    original: RgbaImage,
+    shapes: Vec<Shape>,
+    /// The capture as shown, scaled and annotated
+    view: RgbaImage,
+    annotating: bool,
+    /// Where the rectangle being dragged started, in window pixels
+    drag: Option<(i32, i32)>,
+    /// Shown in place of the title, e.g. after copying
+    status: Option<String>,
+}
+
+impl Review {
+    pub fn new(image: RgbaImage) -> Self {
+        let mut review = Self {
+            view: RgbaImage::new(0, 0),
+            original: image,
+            shapes: Vec::new(),
+            annotating: false,
+            drag: None,
+            status: None,
+        };
+        review.redraw();
+        review
+    }
+
+    /// Window pixels per image pixel, at most 1
+    pub fn scale(&self) -> f64 {
+        let (width, height) = self.original.dimensions();
+        (MAX_VIEW.0 as f64 / width.max(1) as f64)
+            .min(MAX_VIEW.1 as f64 / height.max(1) as f64)
+            .min(1.0)
+    }
+
+    /// The window's size: the image as shown, with the toolbar under it
+    pub fn size(&self) -> (u32, u32) {
+        (
+            self.view.width().max(MIN_WIDTH),
+            self.view.height() + TOOLBAR_HEIGHT,
+        )
+    }
+
+    pub fn annotating(&self) -> bool {
+        self.annotating
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    /// The capture with every annotation, at full size
+    pub fn annotated(&self) -> RgbaImage {
+        let mut image = self.original.clone();
+        self.annotator(1.0).draw(&mut image);
+        image
+    }
+
+    /// The shapes scaled by `scale`, strokes included
+    fn annotator(&self, scale: f64) -> Annotator {
+        let width = ((4.0 * scale).round() as u32).max(1);
+        self.shapes
+            .iter()
+            .fold(Annotator::new().width(width), |annotator, shape| {
+                let Shape::Rect(r) = shape else {
+                    return annotator.push(*shape);
+                };
+                annotator.rect(Region::new(
+                    (r.x as f64 * scale).round() as i32,
+                    (r.y as f64 * scale).round() as i32,
+                    ((r.width as f64 * scale).round() as u32).max(1),
+                    ((r.height as f64 * scale).round() as u32).max(1),
+                ))
+            })
+    }
+
+    fn redraw(&mut self) {
+        let scale = self.scale();
+        let (width, height) = self.original.dimensions();
+        let (view_width, view_height) = (
+            ((width as f64 * scale).round() as u32).max(1),
+            ((height as f64 * scale).round() as u32).max(1),
+        );
+        self.view = match (view_width, view_height) == (width, height) {
+            true => self.original.clone(),
+            false => imageops::thumbnail(&self.original, view_width, view_height),
+        };
+        self.annotator(scale).draw(&mut self.view);
+    }
+
+    /// The button under window pixel `x`, `y`, if any
+    pub fn button_at(&self, x: i32, y: i32) -> Option<Button> {
+        let (width, height) = self.size();
+        let top = (height - TOOLBAR_HEIGHT) as i32;
+        if y < top || y >= height as i32 || x < 0 || x >= width as i32 {
+            return None;
+        }
+        let index = x as usize * Button::ALL.len() / width as usize;
+        Some(Button::ALL[index])
+    }
+
+    /// Window pixel `x`, `y` in image pixels, kept inside the image
+    pub fn to_image(&self, x: i32, y: i32) -> (i32, i32) {
+        let scale = self.scale();
+        let clamp = |v: i32, side: u32| {
+            ((v as f64 / scale).round() as i32).clamp(0, side.saturating_sub(1) as i32)
+        };
+        (
+            clamp(x, self.original.width()),
+            clamp(y, self.original.height()),
+        )
+    }
+
+    /// Does what `button` does, returning the decision once there is one;
+    /// copying goes through `copy`
+    pub fn click(
+        &mut self,
+        button: Button,
+        copy: impl FnOnce(&RgbaImage) -> Result<()>,
+    ) -> Option<Decision> {
+        match button {
+            Button::Save => return Some(Decision::Save(self.annotated())),
+            Button::Discard => return Some(Decision::Discard),
+            Button::Copy => {
+                let status = match copy(&self.annotated()) {
+                    Ok(()) => "Copied to the clipboard".to_owned(),
+                    Err(e) => format!("Copying failed: {e}"),
+                };
+                self.status = Some(status);
+            }
+            Button::Annotate => {
+                self.annotating = !self.annotating;
+                self.drag = None;
+            }
+        }
+        None
+    }
+
+    /// The mouse button went down at window pixel `x`, `y`; starts a
+    /// rectangle over the image while annotating
+    pub fn press(&mut self, x: i32, y: i32) {
+        let inside =
+            x >= 0 && y >= 0 && (x as u32) < self.view.width() && (y as u32) < self.view.height();
+        if self.annotating && inside {
+            self.drag = Some((x, y));
+        }
+    }
+
+    /// The mouse button came up at window pixel `x`, `y`, ending the
+    /// rectangle being dragged
+    pub fn release(&mut self, x: i32, y: i32) {
+        let Some(start) = self.drag.take() else {
+            return;
+        };
+        let (x1, y1) = self.to_image(start.0, start.1);
+        let (x2, y2) = self.to_image(x, y);
+        let region = Region::new(x1.min(x2), y1.min(y2), x1.abs_diff(x2), y1.abs_diff(y2));
+        if region.width > 0 && region.height > 0 {
+            self.shapes.push(Shape::Rect(region));
+            self.redraw();
+        }
+    }
+
+    /// Takes back the last annotation
+    pub fn undo(&mut self) {
+        if self.shapes.pop().is_some() {
+            self.redraw();
+        }
+    }
+
+    /// The window's pixels as `0RGB` words, the mouse being at `mouse`
+    pub fn render(&self, mouse: Option<(i32, i32)>) -> Vec<u32> {
+        let (width, height) = self.size();
+        let mut frame = RgbaImage::from_pixel(width, height, Rgba([24, 24, 24, 255]));
+        imageops::replace(&mut frame, &self.view, 0, 0);
+        if let (Some((x1, y1)), Some((x2, y2))) = (self.drag, mouse) {
+            let band = Region::new(
+                x1.min(x2),
+                y1.min(y2),
+                x1.abs_diff(x2).max(1),
+                y1.abs_diff(y2).max(1),
+            );
+            Annotator::new().width(2).rect(band).draw(&mut frame);
+        }
+        self.draw_toolbar(&mut frame);
+        frame
+            .pixels()
+            .map(|p| u32::from_be_bytes([0, p[0], p[1], p[2]]))
+            .collect()
+    }
+
+    fn draw_toolbar(&self, frame: &mut RgbaImage) {
+        let (width, height) = frame.dimensions();
+        let top = height - TOOLBAR_HEIGHT;
+        let style = TextStyle {
+            outline: None,
+            ..TextStyle::default()
+        };
+        let count = Button::ALL.len() as u32;
+        for (i, button) in Button::ALL.into_iter().enumerate() {
+            let (left, right) = (i as u32 * width / count, (i as u32 + 1) * width / count);
+            let fill = match button == Button::Annotate && self.annotating {
+                true => ACTIVE,
+                false => TOOLBAR,
+            };
+            for y in top..height {
+                for x in left..right {
+                    let divider = x == left && i > 0;
+                    frame.put_pixel(x, y, if divider { DIVIDER } else { fill }.into());
+                }
+            }
+            let (text_width, text_height) = measure_text(button.label(), &style);
+            let x = left as i32 + (right - left).saturating_sub(text_width) as i32 / 2;
+            let y = top as i32 + TOOLBAR_HEIGHT.saturating_sub(text_height) as i32 / 2;
+            draw_text(frame, button.label(), x, y, &style);
+        }
+    }
+
+    /// The window title: the status, or how to annotate while annotating
+    pub fn title(&self) -> String {
+        match (&self.status, self.annotating) {
+            (Some(status), _) => format!("snap_scale: {status}"),
+            (None, true) => "snap_scale: drag to mark up, Backspace to undo".into(),
+            (None, false) => "snap_scale: review capture".into(),
+        }
+    }
+}
+
+/// Copies `image` to the system clipboard
+///
+/// On X11 and Wayland the copy lasts while this process runs, unless a
+/// clipboard manager takes it over.
+pub fn copy(image: &RgbaImage) -> Result<()> {
+    let clipboard_error = |e: arboard::Error| Error::Unsupported(format!("clipboard: {e}"));
+    let mut clipboard = arboard::Clipboard::new().map_err(clipboard_error)?;
+    clipboard
+        .set_image(arboard::ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: image.as_raw().into(),
+        })
+        .map_err(clipboard_error)
+}
+
+/// Shows `image` in a review window until the reviewer decides what becomes
+/// of it
+pub fn review(image: RgbaImage) -> Result<Decision> {
+    use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+    let mut review = Review::new(image);
+    let (width, height) = review.size();
+    let mut window = Window::new(
+        &review.title(),
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    )
+    .map_err(|e| Error::Unsupported(format!("opening the review window: {e}")))?;
+    // Captures taken while it's open shouldn't show it, where that's possible
+    if let Err(e) = crate::window::exclude_from_capture(window.get_window_handle() as usize as u64)
+    {
+        tracing::debug!("{e}");
+    }
+    window.set_target_fps(30);
+    let mut title = review.title();
+    let mut was_down = false;
+    while window.is_open() {
+        let mouse = window
+            .get_mouse_pos(MouseMode::Clamp)
+            .map(|(x, y)| (x as i32, y as i32));
+        let down = window.get_mouse_down(MouseButton::Left);
+        let pressed = |key| window.is_key_pressed(key, KeyRepeat::No);
+        let mut clicked = [
+            (Key::S, Button::Save),
+            (Key::C, Button::Copy),
+            (Key::A, Button::Annotate),
+            (Key::Escape, Button::Discard),
+        ]
+        .into_iter()
+        .find_map(|(key, button)| pressed(key).then_some(button));
+        if pressed(Key::Backspace) {
+            review.undo();
+        }
+        if let Some((x, y)) = mouse {
+            match (was_down, down) {
+                (false, true) => match review.button_at(x, y) {
+                    Some(button) => clicked = Some(button),
+                    None => review.press(x, y),
+                },
+                (true, false) => review.release(x, y),
+                _ => {}
+            }
+        }
+        was_down = down;
+        if let Some(decision) = clicked.and_then(|button| review.click(button, copy)) {
+            return Ok(decision);
+        }
+        if review.title() != title {
+            title = review.title();
+            window.set_title(&title);
+        }
+        window
+            .update_with_buffer(&review.render(mouse), width as usize, height as usize)
+            .map_err(|e| Error::Unsupported(format!("drawing the review window: {e}")))?;
+    }
+    Ok(Decision::Discard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([200, 200, 200, 255]))
+    }
+
+    #[test]
+    fn test_layout_fits_the_capture() {
+        let review = Review::new(capture(2560, 1600));
+        assert_eq!(review.scale(), 0.5);
+        assert_eq!(review.size(), (1280, 800 + TOOLBAR_HEIGHT));
+        assert_eq!(review.to_image(640, 400), (1280, 800));
+        assert_eq!(review.to_image(-5, 5000), (0, 1599));
+
+        let small = Review::new(capture(100, 50));
+        assert_eq!(small.scale(), 1.0);
+        assert_eq!(small.size(), (MIN_WIDTH, 50 + TOOLBAR_HEIGHT));
+        assert_eq!(small.button_at(0, 60), Some(Button::Save));
+        assert_eq!(small.button_at(479, 81), Some(Button::Discard));
+        assert_eq!(small.button_at(250, 60), Some(Button::Annotate));
+        assert_eq!(small.button_at(10, 10), None);
+        assert_eq!(small.button_at(10, 50 + TOOLBAR_HEIGHT as i32), None);
+    }
+
+    #[test]
+    fn test_annotating_draws_rectangles() {
+        let mut review = Review::new(capture(2560, 1600));
+        // Not annotating yet: dragging does nothing
+        review.press(10, 10);
+        review.release(100, 100);
+        assert!(review.shapes().is_empty());
+
+        assert_eq!(review.click(Button::Annotate, |_| Ok(())), None);
+        assert!(review.annotating());
+        review.press(100, 50);
+        review.release(10, 10);
+        assert_eq!(review.shapes(), [Shape::Rect(Region::new(20, 20, 180, 80))]);
+        let Some(Decision::Save(saved)) = review.click(Button::Save, |_| Ok(())) else {
+            panic!("saving decides");
+        };
+        assert_eq!(saved.dimensions(), (2560, 1600));
+        assert_eq!(saved.get_pixel(20, 50), &Rgba::from(Color::RED));
+        assert_eq!(saved.get_pixel(100, 50), &Rgba([200, 200, 200, 255]));
+
+        review.undo();
+        assert!(review.shapes().is_empty());
+        assert_eq!(review.annotated(), capture(2560, 1600));
+    }
+
+    #[test]
+    fn test_copy_and_discard() {
+        let mut review = Review::new(capture(64, 48));
+        let mut copied = None;
+        assert_eq!(
+            review.click(Button::Copy, |image| {
+                copied = Some(image.dimensions());
+                Ok(())
+            }),
+            None,
+            "copying keeps the window open"
+        );
+        assert_eq!(copied, Some((64, 48)));
+        assert_eq!(review.title(), "snap_scale: Copied to the clipboard");
+        review.click(Button::Copy, |_| {
+            Err(Error::Unsupported("no clipboard".into()))
+        });
+        assert!(review.title().contains("no clipboard"));
+        assert_eq!(
+            review.click(Button::Discard, |_| Ok(())),
+            Some(Decision::Discard)
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let review = Review::new(capture(64, 48));
+        let (width, height) = review.size();
+        let frame = review.render(None);
+        assert_eq!(frame.len(), (width * height) as usize);
+        assert_eq!(frame[0], 0x00c8c8c8, "the capture");
+        assert_eq!(frame[(64 + 10) as usize], 0x00181818, "beside it");
+        let toolbar = frame[((height - 1) * width + 5) as usize];
+        assert_eq!(toolbar, 0x00282828);
+    }
+}
@@ -0,0 +1,179 @@
+//! Capturing windows as they appear, by title
+//!
+//! `snap_scale daemon` lists the windows every so often and captures each
+//! one that newly matches a `[[rules]]` entry of the config, e.g. error
+//! dialogs:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "errors"
+//! title = "(?i)error|failed"
+//! dir = "captures/errors"
+//! ```
+//!
+//! A [`RuleEngine`] remembers which windows matched at the last look, so a
+//! window is captured once when it appears, or is retitled to match, and
+//! not again until it has stopped matching in between.
+
+use crate::config::RuleConfig;
+use crate::metadata::Trigger;
+use crate::window::WindowInfo;
+use crate::{Error, Result};
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// One `[[rules]]` entry, its title pattern compiled
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    title: Regex,
+    app: Option<String>,
+    /// Directory its captures go to
+    pub dir: PathBuf,
+}
+
+impl Rule {
+    /// Fails with [`Error::Config`] for a title that isn't a valid regular
+    /// expression
+    pub fn new(config: &RuleConfig) -> Result<Self> {
+        let name = config.name.clone().unwrap_or_else(|| config.title.clone());
+        let title = Regex::new(&config.title)
+            .map_err(|e| Error::Config(format!("rule `{name}` has an invalid title: {e}")))?;
+        Ok(Self {
+            name,
+            title,
+            app: config.app.clone(),
+            dir: config.dir.clone(),
+        })
+    }
+
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        self.app
+            .as_ref()
+            .is_none_or(|app| app.eq_ignore_ascii_case(&window.app))
+            && self.title.is_match(&window.title)
+    }
+
+    /// What a capture of `window` for this rule records about why it was
+    /// taken
+    pub fn trigger(&self, window: &WindowInfo) -> Trigger {
+        Trigger {
+            rule: self.name.clone(),
+            window: window.id,
+            title: window.title.clone(),
+            app: window.app.clone(),
+            pid: window.pid,
+        }
+    }
+}
+
+/// Rules and the windows that matched them at the last look
+#[derive(Debug, Clone, Default)]
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    /// Window ids, each with the index of the rule it matched
+    matched: HashSet<(u64, usize)>,
+}
+
+impl RuleEngine {
+    pub fn new(rules: &[RuleConfig]) -> Result<Self> {
+        Ok(Self {
+            rules: rules.iter().map(Rule::new).collect::<Result<_>>()?,
+            matched: HashSet::new(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The windows of `windows` matching a rule they didn't at the last
+    /// look, each with the rule; windows open at the first look count as
+    /// having just appeared
+    pub fn poll<'a>(&mut self, windows: &'a [WindowInfo]) -> Vec<(&Rule, &'a WindowInfo)> {
+        let matched: HashSet<_> = windows
+            .iter()
+            .flat_map(|window| {
+                self.rules
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rule)| rule.matches(window))
+                    .map(|(index, _)| (window.id, index))
+            })
+            .collect();
+        let mut appeared = Vec::new();
+        for window in windows {
+            for (index, rule) in self.rules.iter().enumerate() {
+                let key = (window.id, index);
+                if matched.contains(&key) && !self.matched.contains(&key) {
+                    appeared.push((rule, window));
+                }
+            }
+        }
+        self.matched = matched;
+        appeared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Region;
+
+    fn window(id: u64, app: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            id,
+            title: title.into(),
+            app: app.into(),
+            pid: None,
+            region: Region::new(0, 0, 400, 300),
+        }
+    }
+
+    fn rule(title: &str, app: Option<&str>) -> RuleConfig {
+        RuleConfig {
+            name: None,
+            title: title.into(),
+            app: app.map(Into::into),
+            dir: "target/rules".into(),
+        }
+    }
+
+    #[test]
+    fn test_windows_trigger_once_as_they_appear() {
+        let mut engine = RuleEngine::new(&[rule("(?i)error", None)]).unwrap();
+        let editor = window(1, "code", "main.rs");
+        let dialog = window(2, "ci", "Build Error");
+
+        let both = [editor, dialog.clone()];
+        let found = engine.poll(&both);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].0.name.as_str(), found[0].1.id), ("(?i)error", 2));
+        assert!(engine.poll(&both).is_empty());
+
+        // Retitled to match, and the dialog gone and back
+        let retitled = [window(1, "code", "error.rs")];
+        assert_eq!(engine.poll(&retitled)[0].1.id, 1);
+        let back = [retitled[0].clone(), dialog];
+        let found = engine.poll(&back);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.id, 2);
+    }
+
+    #[test]
+    fn test_apps_and_invalid_patterns() {
+        let mut engine = RuleEngine::new(&[rule("failed", Some("CI"))]).unwrap();
+        let windows = [
+            window(1, "mail", "Delivery failed"),
+            window(2, "ci", "failed"),
+        ];
+        let found = engine.poll(&windows);
+        assert_eq!(found.len(), 1);
+        let trigger = found[0].0.trigger(found[0].1);
+        assert_eq!((trigger.window, trigger.app.as_str()), (2, "ci"));
+
+        let err = RuleEngine::new(&[rule("(unclosed", None)]).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{err}");
+    }
+}
@@ -0,0 +1,268 @@
+use crate::backend::CaptureBackend;
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use screenshots::image::RgbaImage;
+use screenshots::Screen;
+
+/// Logical size of the area [`ScalingConfig::detect`] captures
+const TEST_SIZE: u32 = 100;
+
+/// Represents the scaling configuration for display-aware screen captures
+///
+/// `dpi_scale` is the factor reported by the operating system; the extra
+/// factor covers the gap between that value and what the capture API actually
+/// returns, and `total_scale` is their product.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScalingConfig {
+    dpi_scale: f32,
+    total_scale: f32,
+}
+
+impl ScalingConfig {
+    /// Extra scale assumed when a test capture is impossible
+    pub const FALLBACK_EXTRA_SCALE: f32 = 1.56;
+
+    /// Creates a config from a DPI scale and an extra capture scale
+    ///
+    /// Non-finite or non-positive factors are treated as `1.0` so the mapping
+    /// math never divides by zero, and so is an extra factor whose product
+    /// with the DPI scale overflows or underflows.
+    pub fn new(dpi_scale: f32, extra_scale: f32) -> Self {
+        let dpi_scale = sanitize(dpi_scale);
+        let total_scale = dpi_scale * sanitize(extra_scale);
+        Self {
+            dpi_scale,
+            total_scale: if total_scale.is_finite() && total_scale > 0.0 {
+                total_scale
+            } else {
+                dpi_scale
+            },
+        }
+    }
+
+    /// A config that maps logical units 1:1 to physical pixels
+    pub fn identity() -> Self {
+        Self::new(1.0, 1.0)
+    }
+
+    /// Determines the actual scaling factor by performing a test capture
+    pub fn detect(screen: &Screen) -> Self {
+        let test = screen.capture_area(0, 0, TEST_SIZE, TEST_SIZE).ok();
+        Self::measured(
+            screen.display_info.scale_factor,
+            TEST_SIZE,
+            test.map(|test| test.width()),
+        )
+    }
+
+    /// Like [`ScalingConfig::detect`], with the test capture taken by `backend`
+    ///
+    /// The test area shrinks to fit displays smaller than it.
+    pub fn probe(backend: &(impl CaptureBackend + ?Sized), display: &DisplayDescriptor) -> Self {
+        let size = TEST_SIZE.min(display.width).min(display.height).max(1);
+        let test = backend
+            .capture_area(display, Region::new(0, 0, size, size))
+            .ok();
+        Self::measured(display.scale_factor, size, test.map(|test| test.width()))
+    }
+
+    /// The scaling a capture of all of `display` shows, measured as
+    /// [`ScalingConfig::probe`] would with it as the test capture
+    pub fn of_capture(display: &DisplayDescriptor, capture: &RgbaImage) -> Self {
+        Self::measured(
+            display.scale_factor,
+            display.width.max(1),
+            Some(capture.width()),
+        )
+    }
+
+    /// From a test capture `test_size` logical pixels wide that came out
+    /// `test_width` pixels wide, if it could be taken
+    fn measured(dpi_scale: f32, test_size: u32, test_width: Option<u32>) -> Self {
+        let extra_scale = match test_width {
+            Some(test_width) => {
+                let dpi_scaled_size = test_size as f32 * sanitize(dpi_scale);
+                test_width as f32 / dpi_scaled_size
+            }
+            None => Self::FALLBACK_EXTRA_SCALE,
+        };
+        Self::new(dpi_scale, extra_scale)
+    }
+
+    pub fn dpi_scale(&self) -> f32 {
+        self.dpi_scale
+    }
+
+    pub fn extra_scale(&self) -> f32 {
+        self.total_scale / self.dpi_scale
+    }
+
+    pub fn total_scale(&self) -> f32 {
+        self.total_scale
+    }
+
+    /// Converts a logical size to physical pixels
+    pub fn scale_dimension(&self, logical_size: u32) -> u32 {
+        (logical_size as f32 * self.total_scale) as u32
+    }
+
+    /// Converts a logical coordinate to physical pixels
+    pub fn scale_coordinate(&self, logical_coord: i32) -> i32 {
+        (logical_coord as f32 * self.total_scale) as i32
+    }
+
+    /// Converts a physical size back to logical units, rounding to nearest
+    pub fn unscale_dimension(&self, physical_size: u32) -> u32 {
+        (physical_size as f32 / self.total_scale).round() as u32
+    }
+
+    /// Converts a physical coordinate back to logical units, rounding to nearest
+    pub fn unscale_coordinate(&self, physical_coord: i32) -> i32 {
+        (physical_coord as f32 / self.total_scale).round() as i32
+    }
+}
+
+impl Default for ScalingConfig {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+fn sanitize(factor: f32) -> f32 {
+    if factor.is_finite() && factor > 0.0 {
+        factor
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_scaling_config() {
+        let config = ScalingConfig::new(1.25, 1.56);
+
+        assert!(
+            (config.dpi_scale() - 1.25).abs() < f32::EPSILON,
+            "DPI scale should be 1.25"
+        );
+        assert!(
+            (config.total_scale() - 1.25 * 1.56).abs() < f32::EPSILON,
+            "Total scale should be DPI scale * extra scale"
+        );
+    }
+
+    #[test]
+    fn test_scaling_of_a_full_capture() {
+        let display = DisplayDescriptor {
+            id: 1,
+            name: String::new(),
+            x: 0,
+            y: 0,
+            width: 1280,
+            height: 800,
+            rotation: 0.0,
+            scale_factor: 1.5,
+            frequency: 60.0,
+            is_primary: true,
+        };
+        let scaling = ScalingConfig::of_capture(&display, &RgbaImage::new(2400, 10));
+        assert!((scaling.extra_scale() - 1.25).abs() < 1e-6);
+        assert_eq!(scaling.scale_dimension(1280), 2400);
+    }
+
+    #[test]
+    fn test_scale_dimension() {
+        let config = ScalingConfig::new(1.0, 1.56);
+
+        assert_eq!(
+            config.scale_dimension(100),
+            (100.0 * 1.56) as u32,
+            "Should scale dimensions correctly"
+        );
+    }
+
+    #[test]
+    fn test_scale_coordinate() {
+        let config = ScalingConfig::new(2.0, 1.56);
+
+        assert_eq!(
+            config.scale_coordinate(50),
+            (50.0 * 2.0 * 1.56) as i32,
+            "Should scale coordinates correctly"
+        );
+    }
+
+    #[test]
+    fn test_invalid_factors_fall_back_to_identity() {
+        let config = ScalingConfig::new(0.0, f32::NAN);
+        assert_eq!(config, ScalingConfig::identity());
+
+        // Each factor is fine, their product isn't
+        for (dpi, extra) in [(1e30, 1e30), (1e-30, 1e-30)] {
+            let config = ScalingConfig::new(dpi, extra);
+            assert_eq!(config.total_scale(), dpi, "{dpi} x {extra}");
+            assert_eq!(config.extra_scale(), 1.0);
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    mod property_tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_scaling_properties(
+                logical_size in 1u32..1000,
+                scale_factor in 1.0f32..4.0
+            ) {
+                let config = ScalingConfig::new(scale_factor, ScalingConfig::FALLBACK_EXTRA_SCALE);
+                let scaled = config.scale_dimension(logical_size);
+
+                prop_assert!(scaled > logical_size, "Scaled size should be larger than logical size");
+                prop_assert!(scaled as f32 / logical_size as f32 >= scale_factor,
+                    "Scaling should at least match the scale factor");
+            }
+
+            #[test]
+            fn test_dimension_round_trip(
+                logical_size in 0u32..20_000,
+                dpi_scale in 1.0f32..4.0,
+                extra_scale in 1.0f32..2.0
+            ) {
+                let config = ScalingConfig::new(dpi_scale, extra_scale);
+                let back = config.unscale_dimension(config.scale_dimension(logical_size));
+
+                prop_assert!(back.abs_diff(logical_size) <= 1,
+                    "logical -> physical -> logical drifted: {} -> {}", logical_size, back);
+            }
+
+            #[test]
+            fn test_coordinate_round_trip(
+                logical_coord in -20_000i32..20_000,
+                dpi_scale in 1.0f32..4.0,
+                extra_scale in 1.0f32..2.0
+            ) {
+                let config = ScalingConfig::new(dpi_scale, extra_scale);
+                let back = config.unscale_coordinate(config.scale_coordinate(logical_coord));
+
+                prop_assert!(back.abs_diff(logical_coord) <= 1,
+                    "logical -> physical -> logical drifted: {} -> {}", logical_coord, back);
+            }
+
+            #[test]
+            fn test_scaling_is_monotonic(
+                a in 0u32..20_000,
+                b in 0u32..20_000,
+                dpi_scale in 0.5f32..4.0
+            ) {
+                let config = ScalingConfig::new(dpi_scale, 1.0);
+                let (lo, hi) = (a.min(b), a.max(b));
+                prop_assert!(config.scale_dimension(lo) <= config.scale_dimension(hi));
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! QR code detection in captures
+//!
+//! Decoding uses the pure-Rust `rqrr` detector, so only QR codes are
+//! recognized; linear barcodes are not.
+
+use crate::geometry::Region;
+use screenshots::image::RgbaImage;
+
+/// A decoded code and where it sits in the image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub payload: String,
+    /// Bounding box of the code's corners, in image pixels
+    pub region: Region,
+}
+
+/// Finds and decodes every QR code in `image`
+///
+/// Codes that are detected but fail to decode (blurred, cut off) are
+/// skipped.
+pub fn scan(image: &RgbaImage) -> Vec<Detection> {
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| luma(image.get_pixel(x as u32, y as u32).0),
+    );
+
+    prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| {
+            let (_, payload) = grid.decode().ok()?;
+            let xs = grid.bounds.map(|p| p.x);
+            let ys = grid.bounds.map(|p| p.y);
+            let (left, top) = (xs.into_iter().min()?, ys.into_iter().min()?);
+            let (right, bottom) = (xs.into_iter().max()?, ys.into_iter().max()?);
+            Some(Detection {
+                payload,
+                region: Region::new(
+                    left,
+                    top,
+                    right.abs_diff(left) + 1,
+                    bottom.abs_diff(top) + 1,
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Rec. 601 luma, with transparent pixels treated as white
+fn luma([r, g, b, a]: [u8; 4]) -> u8 {
+    let gray = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+    let alpha = a as u32;
+    ((gray * alpha + 255 * (255 - alpha)) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    /// Renders `data` as a QR code with `scale`-pixel modules at `offset`
+    fn render_qr(data: &str, scale: u32, offset: u32, canvas: u32) -> RgbaImage {
+        let code = qrcode::QrCode::new(data).unwrap();
+        let width = code.width() as u32;
+        let colors = code.to_colors();
+        let mut image = RgbaImage::from_pixel(canvas, canvas, Rgba([255, 255, 255, 255]));
+        for (i, color) in colors.iter().enumerate() {
+            if *color != qrcode::Color::Dark {
+                continue;
+            }
+            let (mx, my) = (i as u32 % width, i as u32 / width);
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    image.put_pixel(
+                        offset + mx * scale + dx,
+                        offset + my * scale + dy,
+                        Rgba([0, 0, 0, 255]),
+                    );
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_decodes_qr_code() {
+        let uri = "otpauth://totp/snap_scale:me?secret=JBSWY3DPEHPK3PXP";
+        let image = render_qr(uri, 4, 40, 240);
+
+        let detections = scan(&image);
+
+        assert_eq!(detections.len(), 1, "Expected one code: {detections:?}");
+        assert_eq!(detections[0].payload, uri);
+        let region = detections[0].region;
+        assert!(
+            Region::new(30, 30, 180, 180).contains_region(&region),
+            "Bounds should hug the code: {region}"
+        );
+    }
+
+    #[test]
+    fn test_blank_image_has_no_codes() {
+        let image = RgbaImage::from_pixel(64, 64, Rgba([255, 255, 255, 255]));
+        assert!(scan(&image).is_empty());
+    }
+
+    #[test]
+    fn test_transparent_is_white() {
+        assert_eq!(luma([0, 0, 0, 0]), 255);
+        assert_eq!(luma([0, 0, 0, 255]), 0);
+    }
+}
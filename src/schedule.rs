@@ -0,0 +1,334 @@
+//! Capturing on a calendar
+//!
+//! `snap_scale daemon` also takes the captures of the config's
+//! `[[schedules]]` entries, each when its crontab-style expression says:
+//!
+//! ```toml
+//! [[schedules]]
+//! name = "hourly"
+//! schedule = "0 */1 * * *"
+//! display = "primary"
+//! dir = "captures/hourly"
+//! ```
+//!
+//! A [`Schedule`] holds the five fields of a crontab line, minute, hour, day
+//! of month, month and day of week, each `*`, a number, a range such as
+//! `1-5` or a list of them, optionally stepped as in `*/15`; months and days
+//! of the week can be named (`jan`, `mon`). As in cron, a day matching either
+//! day field counts when both are restricted, and `@hourly`, `@daily`,
+//! `@weekly`, `@monthly` and `@yearly` stand for the usual lines.
+//!
+//! Times are wall-clock local ones: a capture whose time a daylight saving
+//! change skips is taken when the clock has gone past it.
+
+use crate::config::ScheduleConfig;
+use crate::select::DisplaySelector;
+use crate::{Error, Result};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How many years ahead [`Schedule::next_after`] looks, so that a line only
+/// matching a day that doesn't exist, e.g. `0 0 30 2 *`, gives up
+const HORIZON_YEARS: i32 = 8;
+
+/// When to capture, as a crontab line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// A bit per minute 0-59
+    minutes: u64,
+    /// A bit per hour 0-23
+    hours: u64,
+    /// A bit per day of the month 1-31
+    days: u64,
+    /// A bit per month 1-12
+    months: u64,
+    /// A bit per day of the week, Sunday 0
+    weekdays: u64,
+    /// Whether both day fields were restricted, when matching either will do
+    either_day: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let line = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            line => line,
+        };
+        let invalid = |reason: String| Error::invalid("schedule", format!("`{s}`: {reason}"));
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let &[minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(invalid(format!(
+                "expected 5 fields (minute, hour, day of month, month, day of week), got {}",
+                fields.len()
+            )));
+        };
+        let weekdays = field(weekday, "day of week", 0, 7, &WEEKDAYS).map_err(invalid)?;
+        Ok(Self {
+            minutes: field(minute, "minute", 0, 59, &[]).map_err(invalid)?,
+            hours: field(hour, "hour", 0, 23, &[]).map_err(invalid)?,
+            days: field(day, "day of month", 1, 31, &[]).map_err(invalid)?,
+            months: field(month, "month", 1, 12, &MONTHS).map_err(invalid)?,
+            // 7 is Sunday too
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+}
+
+/// The bits set by one field of a crontab line, `min` to `max`; `names`
+/// stand for `min` onwards
+fn field(
+    s: &str,
+    what: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+) -> std::result::Result<u64, String> {
+    let value = |v: &str| {
+        names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(v))
+            .map(|index| min + index as u32)
+            .or_else(|| v.parse().ok())
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(|| format!("{what} `{v}` is not in {min}-{max}"))
+    };
+    let mut bits = 0;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("{what} step `{step}` is not a positive number")),
+            },
+            None => (part, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // `5/10` runs from 5 to the end
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let v = value(range)?;
+                (v, v)
+            }
+        };
+        if first > last {
+            return Err(format!("{what} range `{range}` runs backwards"));
+        }
+        for v in (first..=last).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+impl Schedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & 1 << date.day() != 0;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        match self.either_day {
+            true => day || weekday,
+            false => day && weekday,
+        }
+    }
+
+    /// The first minute after `after` the schedule matches, if any within
+    /// the next few years
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let after = after.with_second(0)?.with_nanosecond(0)?;
+        let mut date = after.date();
+        let mut time = Some(after.time());
+        let horizon = date.year() + HORIZON_YEARS;
+        while date.year() <= horizon {
+            if self.months & 1 << date.month() == 0 || !self.matches_day(date) {
+                date = date.succ_opt()?;
+                time = None;
+                continue;
+            }
+            // Today from just after `after`, other days from midnight
+            let start = time.map_or(0, |t| t.hour() * 60 + t.minute() + 1);
+            let found = (start..24 * 60).find(|minute| {
+                self.hours & 1 << (minute / 60) != 0 && self.minutes & 1 << (minute % 60) != 0
+            });
+            if let Some(minute) = found {
+                return Some(date.and_time(NaiveTime::from_hms_opt(minute / 60, minute % 60, 0)?));
+            }
+            date = date.succ_opt()?;
+            time = None;
+        }
+        None
+    }
+}
+
+/// A `[[schedules]]` entry, its expression parsed
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub schedule: Schedule,
+    pub display: DisplaySelector,
+    /// Directory its captures go to
+    pub dir: PathBuf,
+    /// When it's next due, in local time; `None` when never again
+    next: Option<NaiveDateTime>,
+}
+
+impl Job {
+    /// Fails with [`Error::Config`] for an invalid expression or display
+    pub fn new(config: &ScheduleConfig, now: NaiveDateTime) -> Result<Self> {
+        let name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| config.schedule.clone());
+        let config_error = |e: Error| Error::Config(format!("schedule `{name}`: {e}"));
+        let schedule: Schedule = config.schedule.parse().map_err(config_error)?;
+        let display = config.display.parse().map_err(config_error)?;
+        Ok(Self {
+            next: schedule.next_after(now),
+            name,
+            schedule,
+            display,
+            dir: config.dir.clone(),
+        })
+    }
+}
+
+/// The jobs of the `[[schedules]]` and when each is due
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    /// Jobs are first due at their first time after `now`
+    pub fn new(schedules: &[ScheduleConfig], now: NaiveDateTime) -> Result<Self> {
+        Ok(Self {
+            jobs: schedules
+                .iter()
+                .map(|config| Job::new(config, now))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// The jobs due by `now`, each then due at its next time after `now`;
+    /// a job missed several times over, e.g. while the machine slept, is
+    /// due once
+    pub fn due(&mut self, now: NaiveDateTime) -> Vec<&Job> {
+        let mut due = Vec::new();
+        for job in &mut self.jobs {
+            if job.next.is_some_and(|next| next <= now) {
+                job.next = job.schedule.next_after(now);
+                due.push(&*job);
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<NaiveDateTime> {
+        schedule.parse::<Schedule>().unwrap().next_after(at(after))
+    }
+
+    #[test]
+    fn test_next_times() {
+        let cases = [
+            ("0 */1 * * *", "2026-10-14 10:42:13", "2026-10-14 11:00:00"),
+            ("*/15 * * * *", "2026-10-14 10:45:00", "2026-10-14 11:00:00"),
+            (
+                "30 9 * * mon-fri",
+                "2026-10-16 09:30:00",
+                "2026-10-19 09:30:00",
+            ),
+            (
+                "0 0 1 jan,jul *",
+                "2026-10-14 00:00:00",
+                "2027-01-01 00:00:00",
+            ),
+            ("0 12 29 2 *", "2026-03-01 00:00:00", "2028-02-29 12:00:00"),
+            (
+                "5/20 8-9 * * *",
+                "2026-10-14 08:45:00",
+                "2026-10-14 09:05:00",
+            ),
+            ("@daily", "2026-12-31 23:59:59", "2027-01-01 00:00:00"),
+            ("0 0 * * 7", "2026-10-14 00:00:00", "2026-10-18 00:00:00"),
+            // Either the 1st or a Friday
+            ("0 0 1 * fri", "2026-10-14 00:00:00", "2026-10-16 00:00:00"),
+            ("0 0 1 * fri", "2026-10-30 00:00:00", "2026-11-01 00:00:00"),
+        ];
+        for (schedule, after, expected) in cases {
+            assert_eq!(next(schedule, after), Some(at(expected)), "{schedule}");
+        }
+        assert_eq!(next("0 0 30 2 *", "2026-01-01 00:00:00"), None);
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        for bad in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "10-5 * * * *",
+            "* * * foo *",
+            "@often",
+        ] {
+            let err = bad.parse::<Schedule>().unwrap_err();
+            assert!(matches!(err, Error::Invalid { .. }), "{bad:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_jobs_come_due() {
+        let config = |schedule: &str| ScheduleConfig {
+            name: None,
+            schedule: schedule.into(),
+            display: "primary".into(),
+            dir: "target/schedule".into(),
+        };
+        let mut scheduler = Scheduler::new(
+            &[config("0 * * * *"), config("30 10 * * *")],
+            at("2026-10-14 09:59:30"),
+        )
+        .unwrap();
+        assert!(scheduler.due(at("2026-10-14 09:59:59")).is_empty());
+        let due = scheduler.due(at("2026-10-14 10:00:00"));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].name, "0 * * * *");
+        assert!(scheduler.due(at("2026-10-14 10:00:00")).is_empty());
+
+        // Hours later, both are due once
+        assert_eq!(scheduler.due(at("2026-10-14 14:10:00")).len(), 2);
+        assert!(scheduler.due(at("2026-10-14 14:20:00")).is_empty());
+
+        let err = Scheduler::new(&[config("* * *")], at("2026-10-14 00:00:00")).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{err}");
+    }
+}
@@ -0,0 +1,258 @@
+//! Embedded Rhai scripting for capture pipelines
+//!
+//! Scripts get a small API on top of the capture and encode layers:
+//!
+//! ```rhai
+//! for d in displays() {
+//!     if d.primary {
+//!         let shot = capture_area(d.index, 0, 0, 800, 600);
+//!         shot.grayscale().save(`primary-${d.id}.png`);
+//!     }
+//! }
+//! ```
+//!
+//! | Function | Returns |
+//! |---|---|
+//! | `displays()` | array of maps: `index id x y width height scale rotation primary` |
+//! | `capture(index)` | `Image` of the whole display |
+//! | `capture_area(index, x, y, w, h)` | `Image` of a logical area of the display |
+//! | `load(path)` / `image(w, h)` | `Image` from a file / a transparent canvas |
+//! | `img.width` / `img.height` | dimensions in pixels |
+//! | `img.crop(x, y, w, h)` / `img.resize(w, h)` / `img.grayscale()` | new `Image` |
+//! | `img.pixel(x, y)` | `"#rrggbbaa"` |
+//! | `img.save(path)` | encodes by extension (png, jpg, webp, qoi) |
+
+use crate::atomic;
+use crate::backend::{default_backend, CaptureBackend};
+use crate::encode::{encode_to_vec, EncodeOptions, OutputFormat};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::scaling::ScalingConfig;
+use crate::{Error, Result};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, INT};
+use screenshots::image::{imageops, DynamicImage, RgbaImage};
+use std::path::Path;
+use std::sync::Arc;
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// An image value inside a script
+#[derive(Debug, Clone)]
+pub struct ScriptImage(pub RgbaImage);
+
+impl ScriptImage {
+    fn width(&mut self) -> INT {
+        self.0.width() as INT
+    }
+
+    fn height(&mut self) -> INT {
+        self.0.height() as INT
+    }
+
+    fn crop(&mut self, x: INT, y: INT, width: INT, height: INT) -> ScriptResult<Self> {
+        let (x, y, width, height) = (to_u32(x)?, to_u32(y)?, to_u32(width)?, to_u32(height)?);
+        if x.saturating_add(width) > self.0.width() || y.saturating_add(height) > self.0.height() {
+            return Err(format!(
+                "crop {x},{y},{width},{height} exceeds {}x{} image",
+                self.0.width(),
+                self.0.height()
+            )
+            .into());
+        }
+        Ok(Self(
+            imageops::crop_imm(&self.0, x, y, width, height).to_image(),
+        ))
+    }
+
+    fn resize(&mut self, width: INT, height: INT) -> ScriptResult<Self> {
+        Ok(Self(imageops::resize(
+            &self.0,
+            to_u32(width)?,
+            to_u32(height)?,
+            imageops::FilterType::Triangle,
+        )))
+    }
+
+    fn grayscale(&mut self) -> Self {
+        Self(
+            DynamicImage::ImageRgba8(self.0.clone())
+                .grayscale()
+                .into_rgba8(),
+        )
+    }
+
+    fn pixel(&mut self, x: INT, y: INT) -> ScriptResult<String> {
+        let (x, y) = (to_u32(x)?, to_u32(y)?);
+        let pixel = self
+            .0
+            .get_pixel_checked(x, y)
+            .ok_or_else(|| format!("pixel {x},{y} is outside the image"))?;
+        let [r, g, b, a] = pixel.0;
+        Ok(format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+    }
+
+    fn save(&mut self, path: &str) -> ScriptResult<()> {
+        save_image(&self.0, Path::new(path)).map_err(|e| e.to_string().into())
+    }
+}
+
+fn to_u32(value: INT) -> ScriptResult<u32> {
+    u32::try_from(value).map_err(|_| format!("{value} is not a valid pixel value").into())
+}
+
+fn to_i32(value: INT) -> ScriptResult<i32> {
+    i32::try_from(value).map_err(|_| format!("{value} is not a valid coordinate").into())
+}
+
+fn save_image(image: &RgbaImage, path: &Path) -> Result<()> {
+    let format = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(OutputFormat::from_extension)
+        .ok_or_else(|| Error::invalid("output format", path.display().to_string()))?;
+    atomic::write(path, encode_to_vec(image, &EncodeOptions::new(format))?)
+}
+
+fn display(index: INT) -> ScriptResult<(Arc<dyn CaptureBackend>, DisplayDescriptor)> {
+    let backend = default_backend().map_err(|e| e.to_string())?;
+    let displays = backend.enumerate().map_err(|e| e.to_string())?;
+    let display = usize::try_from(index)
+        .ok()
+        .and_then(|i| displays.into_iter().nth(i))
+        .ok_or_else(|| format!("no display #{index}"))?;
+    Ok((backend, display))
+}
+
+fn displays() -> ScriptResult<Array> {
+    let displays = default_backend()
+        .and_then(|backend| backend.enumerate())
+        .map_err(|e| e.to_string())?;
+    Ok(displays
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            let mut map = Map::new();
+            map.insert("index".into(), (index as INT).into());
+            map.insert("id".into(), (info.id as INT).into());
+            map.insert("x".into(), (info.x as INT).into());
+            map.insert("y".into(), (info.y as INT).into());
+            map.insert("width".into(), (info.width as INT).into());
+            map.insert("height".into(), (info.height as INT).into());
+            map.insert(
+                "scale".into(),
+                Dynamic::from_float(info.scale_factor as f64),
+            );
+            map.insert("rotation".into(), Dynamic::from_float(info.rotation as f64));
+            map.insert("primary".into(), info.is_primary.into());
+            Dynamic::from_map(map)
+        })
+        .collect())
+}
+
+fn capture(index: INT) -> ScriptResult<ScriptImage> {
+    let (backend, display) = display(index)?;
+    let image = backend
+        .capture_display(&display)
+        .map_err(|e| e.to_string())?;
+    Ok(ScriptImage(image))
+}
+
+fn capture_area(index: INT, x: INT, y: INT, width: INT, height: INT) -> ScriptResult<ScriptImage> {
+    let (backend, display) = display(index)?;
+    let scaling = ScalingConfig::probe(&*backend, &display);
+    let area = Region::new(
+        scaling.scale_coordinate(to_i32(x)?),
+        scaling.scale_coordinate(to_i32(y)?),
+        scaling.scale_dimension(to_u32(width)?),
+        scaling.scale_dimension(to_u32(height)?),
+    );
+    let image = backend
+        .capture_area(&display, area)
+        .map_err(|e| e.to_string())?;
+    Ok(ScriptImage(image))
+}
+
+fn load(path: &str) -> ScriptResult<ScriptImage> {
+    let image = screenshots::image::open(path).map_err(|e| e.to_string())?;
+    Ok(ScriptImage(image.into_rgba8()))
+}
+
+fn blank(width: INT, height: INT) -> ScriptResult<ScriptImage> {
+    Ok(ScriptImage(RgbaImage::new(to_u32(width)?, to_u32(height)?)))
+}
+
+/// Builds an engine with the snap_scale API registered
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<ScriptImage>("Image")
+        .register_get("width", ScriptImage::width)
+        .register_get("height", ScriptImage::height)
+        .register_fn("crop", ScriptImage::crop)
+        .register_fn("resize", ScriptImage::resize)
+        .register_fn("grayscale", ScriptImage::grayscale)
+        .register_fn("pixel", ScriptImage::pixel)
+        .register_fn("save", ScriptImage::save)
+        .register_fn("displays", displays)
+        .register_fn("capture", capture)
+        .register_fn("capture_area", capture_area)
+        .register_fn("load", load)
+        .register_fn("image", blank);
+    engine
+}
+
+/// Runs a script file
+pub fn run_file(path: impl AsRef<Path>) -> Result<()> {
+    engine()
+        .run_file(path.as_ref().to_path_buf())
+        .map_err(|e| Error::Script(e.to_string()))
+}
+
+/// Evaluates a script and returns its final value
+pub fn eval(script: &str) -> Result<Dynamic> {
+    engine()
+        .eval::<Dynamic>(script)
+        .map_err(|e| Error::Script(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_pipeline_without_screen() {
+        let width = eval("let img = image(40, 30); img.crop(10, 10, 20, 5).resize(8, 2).width")
+            .unwrap()
+            .as_int()
+            .unwrap();
+        assert_eq!(width, 8);
+    }
+
+    #[test]
+    fn test_pixel_readback() {
+        let hex = eval("image(2, 2).grayscale().pixel(1, 1)")
+            .unwrap()
+            .into_string()
+            .unwrap();
+        assert_eq!(hex, "#00000000");
+    }
+
+    #[test]
+    fn test_errors_surface_as_script_errors() {
+        let err = eval("image(4, 4).crop(2, 2, 4, 4)").unwrap_err();
+        assert!(matches!(err, Error::Script(_)), "{err}");
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("snap_scale_script_{}.png", std::process::id()));
+        let script = format!(
+            r#"image(5, 7).save("{0}"); load("{0}").height"#,
+            path.display().to_string().replace('\\', "/")
+        );
+        let height = eval(&script).unwrap().as_int().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(height, 7);
+    }
+}
@@ -0,0 +1,369 @@
+//! Scrolling captures
+//!
+//! A page taller than the screen is captured a screenful at a time while it
+//! scrolls. [`ScrollStitcher`] compares each frame with the one before it,
+//! finds how far the content moved and appends only the rows that came into
+//! view, giving one tall image. Rows that stay put from frame to frame, such
+//! as a sticky header, a footer or a toolbar, are kept once instead of being
+//! repeated down the page.
+//!
+//! [`wheel`] sends the scroll events for unattended captures.
+
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Rows two frames have to share before they're taken to be the same page
+pub const MIN_OVERLAP: u32 = 8;
+
+/// Share of the overlapping rows that have to match, so a blinking caret or
+/// an animation in view doesn't lose track of the page
+const MATCH_RATIO: f64 = 0.95;
+
+/// How a frame continues the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The content moved up this many rows, which were added to the image
+    Scrolled(u32),
+    /// Nothing moved: the end of the page, or no scroll yet
+    Unmoved,
+    /// No overlap with the previous frame, which moved by more than a
+    /// screenful or went back up; the frame was left out
+    Lost,
+}
+
+/// How far the content between a fixed header and footer moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlap {
+    /// Rows at the top that didn't move
+    pub header: u32,
+    /// Rows at the bottom that didn't move
+    pub footer: u32,
+    /// Rows the content between them moved up by
+    pub offset: u32,
+}
+
+/// Hashes of each row, with whether the row is all one color
+fn rows(image: &RgbaImage) -> Vec<(u64, bool)> {
+    let stride = image.width() as usize * 4;
+    image
+        .as_raw()
+        .chunks_exact(stride.max(1))
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            let plain = row.chunks_exact(4).all(|pixel| pixel == &row[..4]);
+            (hasher.finish(), plain)
+        })
+        .collect()
+}
+
+/// How `next` continues `previous`, two frames of the same area
+///
+/// `None` means no offset lines the two up, while an `offset` of 0 means
+/// nothing moved. Plain rows, such as blank margins, match anywhere and so
+/// don't count toward lining frames up.
+pub fn overlap(previous: &RgbaImage, next: &RgbaImage) -> Option<Overlap> {
+    if previous.dimensions() != next.dimensions() {
+        return None;
+    }
+    let (before, after) = (rows(previous), rows(next));
+    let height = before.len();
+    let header = before
+        .iter()
+        .zip(&after)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if header == height {
+        return Some(Overlap {
+            header: height as u32,
+            footer: 0,
+            offset: 0,
+        });
+    }
+    let footer = before[header..]
+        .iter()
+        .rev()
+        .zip(after[header..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (before, after) = (
+        &before[header..height - footer],
+        &after[header..height - footer],
+    );
+
+    let band = before.len();
+    let mut best: Option<(f64, usize)> = None;
+    for offset in 1..band.saturating_sub(MIN_OVERLAP as usize - 1) {
+        let shared = before[offset..].iter().zip(after);
+        let (mut matched, mut counted) = (0, 0);
+        for (a, b) in shared {
+            if b.1 {
+                continue;
+            }
+            counted += 1;
+            if a == b {
+                matched += 1;
+            }
+        }
+        if counted < MIN_OVERLAP / 2 {
+            continue;
+        }
+        let score = matched as f64 / counted as f64;
+        if score >= MATCH_RATIO && best.is_none_or(|(top, _)| score > top) {
+            best = Some((score, offset));
+        }
+    }
+    best.map(|(_, offset)| Overlap {
+        header: header as u32,
+        footer: footer as u32,
+        offset: offset as u32,
+    })
+}
+
+/// Builds one tall image from frames of a scrolling area
+#[derive(Debug, Clone)]
+pub struct ScrollStitcher {
+    image: RgbaImage,
+    last: RgbaImage,
+}
+
+impl ScrollStitcher {
+    /// Starts from the top of the page
+    pub fn new(first: RgbaImage) -> Self {
+        Self {
+            image: first.clone(),
+            last: first,
+        }
+    }
+
+    /// Adds the rows of `frame` that scrolled into view
+    ///
+    /// Frames have to be the size of the first one.
+    pub fn push(&mut self, frame: RgbaImage) -> Result<Step> {
+        if frame.dimensions() != self.last.dimensions() {
+            let (width, height) = self.last.dimensions();
+            return Err(Error::invalid(
+                "scroll frame",
+                format!(
+                    "{}x{}, expected {width}x{height}",
+                    frame.width(),
+                    frame.height()
+                ),
+            ));
+        }
+        let Some(overlap) = overlap(&self.last, &frame) else {
+            return Ok(Step::Lost);
+        };
+        if overlap.offset == 0 {
+            return Ok(Step::Unmoved);
+        }
+
+        // The image ends with the last frame's footer; the new rows go
+        // above the footer, which `frame` brings along again
+        let (width, height) = frame.dimensions();
+        let kept = self.image.height() - overlap.footer;
+        let added = imageops::crop_imm(
+            &frame,
+            0,
+            height - overlap.footer - overlap.offset,
+            width,
+            overlap.footer + overlap.offset,
+        );
+        let mut image = RgbaImage::new(width, kept + overlap.footer + overlap.offset);
+        imageops::replace(
+            &mut image,
+            &*imageops::crop_imm(&self.image, 0, 0, width, kept),
+            0,
+            0,
+        );
+        imageops::replace(&mut image, &*added, 0, kept as i64);
+        self.image = image;
+        self.last = frame;
+        Ok(Step::Scrolled(overlap.offset))
+    }
+
+    /// The page so far
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+}
+
+/// Scrolls whatever is under desktop-global logical `(x, y)` by `clicks`
+/// wheel notches, down for positive counts, moving the cursor there
+#[cfg(target_os = "linux")]
+pub fn wheel(x: i32, y: i32, clicks: i32) -> Result<()> {
+    use xcb::{x, xtest, BaseEvent};
+
+    let unavailable = |e: &dyn std::fmt::Display| Error::Unsupported(format!("scrolling: {e}"));
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Test], &[])
+            .map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("scrolling: no X screen".into()))?
+        .root();
+    let scale = crate::cursor::x11_scale();
+    let input = |r#type: u8, detail: u8| xtest::FakeInput {
+        r#type,
+        detail,
+        time: x::CURRENT_TIME,
+        root,
+        root_x: (x as f32 * scale) as i16,
+        root_y: (y as f32 * scale) as i16,
+        deviceid: 0,
+    };
+    conn.send_request(&input(x::MotionNotifyEvent::NUMBER as u8, 0));
+    // Buttons 4 and 5 are the wheel up and down
+    let button = if clicks < 0 { 4 } else { 5 };
+    for _ in 0..clicks.unsigned_abs() {
+        conn.send_request(&input(x::ButtonPressEvent::NUMBER as u8, button));
+        conn.send_request(&input(x::ButtonReleaseEvent::NUMBER as u8, button));
+    }
+    conn.flush().map_err(|e| unavailable(&e))
+}
+
+/// Scrolls whatever is under desktop-global logical `(x, y)` by `clicks`
+/// wheel notches, down for positive counts, moving the cursor there
+#[cfg(target_os = "windows")]
+pub fn wheel(x: i32, y: i32, clicks: i32) -> Result<()> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_MOUSE, MOUSEEVENTF_WHEEL, MOUSEINPUT,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{SetCursorPos, WHEEL_DELTA};
+
+    unsafe { SetCursorPos(x, y) }.map_err(|e| Error::Unsupported(format!("scrolling: {e}")))?;
+    let input = INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                // Positive deltas scroll up
+                mouseData: -clicks * WHEEL_DELTA as i32,
+                dwFlags: MOUSEEVENTF_WHEEL,
+                ..Default::default()
+            },
+        },
+    };
+    let sent = unsafe { SendInput(&[input], std::mem::size_of::<INPUT>() as i32) };
+    if sent == 0 {
+        return Err(Error::Unsupported(format!(
+            "scrolling: {}",
+            windows::core::Error::from_win32()
+        )));
+    }
+    Ok(())
+}
+
+/// Scrolls whatever is under desktop-global logical `(x, y)` by `clicks`
+/// wheel notches, down for positive counts, moving the cursor there
+#[cfg(target_os = "macos")]
+pub fn wheel(x: i32, y: i32, clicks: i32) -> Result<()> {
+    use core_graphics::event::ScrollEventUnit;
+    use core_graphics::event::{CGEvent, CGEventTapLocation, CGEventType, CGMouseButton};
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+    use core_graphics::geometry::CGPoint;
+
+    let unavailable = || Error::Unsupported("scrolling: no event source".into());
+    let source =
+        || CGEventSource::new(CGEventSourceStateID::HIDSystemState).map_err(|_| unavailable());
+    let point = CGPoint::new(x as f64, y as f64);
+    CGEvent::new_mouse_event(
+        source()?,
+        CGEventType::MouseMoved,
+        point,
+        CGMouseButton::Left,
+    )
+    .map_err(|_| unavailable())?
+    .post(CGEventTapLocation::HID);
+    // Positive wheel counts scroll up
+    CGEvent::new_scroll_event(source()?, ScrollEventUnit::LINE, 1, -clicks * 3, 0, 0)
+        .map_err(|_| unavailable())?
+        .post(CGEventTapLocation::HID);
+    Ok(())
+}
+
+/// Scrolls whatever is under desktop-global logical `(x, y)` by `clicks`
+/// wheel notches, down for positive counts, moving the cursor there
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn wheel(_x: i32, _y: i32, _clicks: i32) -> Result<()> {
+    Err(Error::Unsupported(
+        "scrolling is not available on this platform".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    /// A page of distinct rows, with a plain band now and then
+    fn page(height: u32) -> RgbaImage {
+        RgbaImage::from_fn(16, height, |x, y| {
+            if y % 37 >= 33 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([(y * 7 % 251) as u8, (y / 3 % 256) as u8, x as u8 * 9, 255])
+            }
+        })
+    }
+
+    /// What a `height`-row window shows of `page` scrolled to `top`, under a
+    /// header of `header` rows and over a footer of `footer` rows
+    fn view(page: &RgbaImage, top: u32, height: u32, header: u32, footer: u32) -> RgbaImage {
+        RgbaImage::from_fn(page.width(), height, |x, y| match y {
+            y if y < header => Rgba([200, 0, 0, 255]),
+            y if y >= height - footer => Rgba([0, 0, (y % 2) as u8 * 200, 255]),
+            y => *page.get_pixel(x, top + y - header),
+        })
+    }
+
+    #[test]
+    fn test_overlap() {
+        let page = page(400);
+        let (a, b) = (view(&page, 0, 100, 10, 5), view(&page, 30, 100, 10, 5));
+        assert_eq!(
+            overlap(&a, &b),
+            Some(Overlap {
+                header: 10,
+                footer: 5,
+                offset: 30
+            })
+        );
+        assert_eq!(overlap(&a, &a).unwrap().offset, 0);
+        assert_eq!(overlap(&a, &view(&page, 200, 100, 10, 5)), None);
+        assert_eq!(overlap(&b, &a), None, "Scrolled back up");
+    }
+
+    #[test]
+    fn test_stitching_keeps_the_header_and_footer_once() {
+        let page = page(400);
+        let frame = |top| view(&page, top, 100, 10, 5);
+        let mut stitcher = ScrollStitcher::new(frame(0));
+        assert_eq!(stitcher.push(frame(0)).unwrap(), Step::Unmoved);
+        assert_eq!(stitcher.push(frame(40)).unwrap(), Step::Scrolled(40));
+        assert_eq!(stitcher.push(frame(300)).unwrap(), Step::Lost);
+        assert_eq!(stitcher.push(frame(95)).unwrap(), Step::Scrolled(55));
+        assert_eq!(stitcher.push(frame(95)).unwrap(), Step::Unmoved);
+
+        // Header, 95 + 85 rows of page, footer
+        let image = stitcher.into_image();
+        let expected = RgbaImage::from_fn(16, 10 + 180 + 5, |x, y| match y {
+            y if y < 10 => Rgba([200, 0, 0, 255]),
+            y if y >= 190 => *frame(0).get_pixel(x, y - 95),
+            y => *page.get_pixel(x, y - 10),
+        });
+        assert_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_frames_must_match_the_first() {
+        let mut stitcher = ScrollStitcher::new(page(100));
+        assert!(stitcher.push(page(99)).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Picking a display the way a user names it
+//!
+//! Every capture command takes the same `--display`: `primary`, a zero-based
+//! index in enumeration order, the platform's display id, or part of the
+//! monitor's name such as `HDMI` or `DISPLAY2`. Ids and names stay put as
+//! displays come and go, where indexes shift.
+
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which display a command captures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplaySelector {
+    /// The one the system calls primary
+    Primary,
+    /// Position in enumeration order, from 0; a number too big for an index
+    /// is taken as an id instead
+    Index(usize),
+    /// The platform's id, written `id:<N>`
+    Id(u32),
+    /// A case-insensitive part of the monitor's name
+    Name(String),
+}
+
+impl Default for DisplaySelector {
+    /// The first display
+    fn default() -> Self {
+        Self::Index(0)
+    }
+}
+
+impl FromStr for DisplaySelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::invalid("display", s));
+        }
+        if s.eq_ignore_ascii_case("primary") {
+            return Ok(Self::Primary);
+        }
+        if let Some(id) = s.strip_prefix("id:") {
+            return id
+                .parse()
+                .map(Self::Id)
+                .map_err(|_| Error::invalid("display id", id));
+        }
+        Ok(match s.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(s.into()),
+        })
+    }
+}
+
+impl fmt::Display for DisplaySelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary => f.write_str("primary"),
+            Self::Index(index) => write!(f, "#{index}"),
+            Self::Id(id) => write!(f, "id:{id}"),
+            Self::Name(name) => write!(f, "{name:?}"),
+        }
+    }
+}
+
+impl DisplaySelector {
+    /// The index of the selected display in `displays`
+    pub fn position(&self, displays: &[DisplayDescriptor]) -> Result<usize> {
+        let missing = || {
+            let known: Vec<_> = displays
+                .iter()
+                .enumerate()
+                .map(|(index, d)| match d.name.as_str() {
+                    "" => format!("#{index} (id {})", d.id),
+                    name => format!("#{index} (id {}, {name})", d.id),
+                })
+                .collect();
+            let known = match known.is_empty() {
+                true => "there are no displays".into(),
+                false => format!("displays are {}", known.join(", ")),
+            };
+            Error::invalid("display", format!("no display {self}; {known}"))
+        };
+        let by_id = |id: u32| displays.iter().position(|d| d.id == id);
+        match self {
+            Self::Primary => displays
+                .iter()
+                .position(|d| d.is_primary)
+                // Some systems don't mark one; the first is the usual stand-in
+                .or((!displays.is_empty()).then_some(0))
+                .ok_or_else(missing),
+            Self::Index(index) if *index < displays.len() => Ok(*index),
+            Self::Index(index) => u32::try_from(*index)
+                .ok()
+                .and_then(by_id)
+                .ok_or_else(missing),
+            Self::Id(id) => by_id(*id).ok_or_else(missing),
+            Self::Name(part) => {
+                let part = part.to_lowercase();
+                let matching: Vec<_> = displays
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, d)| d.name.to_lowercase().contains(&part))
+                    .collect();
+                match matching[..] {
+                    [(index, _)] => Ok(index),
+                    [] => Err(missing()),
+                    _ => {
+                        let names: Vec<_> = matching.iter().map(|(_, d)| d.name.as_str()).collect();
+                        Err(Error::invalid(
+                            "display",
+                            format!("{self} matches {}", names.join(", ")),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    /// The selected display of `displays`
+    pub fn select<'a>(&self, displays: &'a [DisplayDescriptor]) -> Result<&'a DisplayDescriptor> {
+        Ok(&displays[self.position(displays)?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: u32, name: &str, is_primary: bool) -> DisplayDescriptor {
+        DisplayDescriptor {
+            id,
+            name: name.into(),
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            rotation: 0.0,
+            scale_factor: 1.0,
+            frequency: 60.0,
+            is_primary,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |s: &str| s.parse::<DisplaySelector>().unwrap();
+        assert_eq!(parse("primary"), DisplaySelector::Primary);
+        assert_eq!(parse("PRIMARY"), DisplaySelector::Primary);
+        assert_eq!(parse("2"), DisplaySelector::Index(2));
+        assert_eq!(parse("id:478"), DisplaySelector::Id(478));
+        assert_eq!(parse("HDMI"), DisplaySelector::Name("HDMI".into()));
+        assert_eq!(parse(" -1 "), DisplaySelector::Name("-1".into()));
+        assert!("".parse::<DisplaySelector>().is_err());
+        assert!("id:x".parse::<DisplaySelector>().is_err());
+    }
+
+    #[test]
+    fn test_position() {
+        let displays = [
+            display(478, "eDP-1", false),
+            display(480, "HDMI-1", true),
+            display(482, "HDMI-2", false),
+        ];
+        let position = |s: &str| s.parse::<DisplaySelector>().unwrap().position(&displays);
+        assert_eq!(position("primary").unwrap(), 1);
+        assert_eq!(position("0").unwrap(), 0);
+        assert_eq!(position("482").unwrap(), 2, "Too big for an index");
+        assert_eq!(position("id:478").unwrap(), 0);
+        assert_eq!(position("edp").unwrap(), 0);
+        assert_eq!(position("hdmi-2").unwrap(), 2);
+
+        let ambiguous = position("HDMI").unwrap_err().to_string();
+        assert!(ambiguous.contains("HDMI-1, HDMI-2"), "{ambiguous}");
+        let missing = position("DP-3").unwrap_err().to_string();
+        assert!(missing.contains("#1 (id 480, HDMI-1)"), "{missing}");
+        assert!(position("3").is_err());
+        assert!(position("id:1").is_err());
+    }
+
+    #[test]
+    fn test_primary_falls_back_to_the_first() {
+        let displays = [display(1, "", false), display(2, "", false)];
+        let first = DisplaySelector::Primary.select(&displays).unwrap();
+        assert_eq!(first.id, 1);
+        assert!(DisplaySelector::Primary.select(&[]).is_err());
+    }
+}
@@ -0,0 +1,947 @@
+//! HTTP server for remote captures
+//!
+//! `snap_scale serve` answers plain HTTP requests so scripts and other
+//! machines can take screenshots without a shell on the target:
+//!
+//! | Endpoint | Returns |
+//! |---|---|
+//! | `GET /displays` | JSON array of displays with their index |
+//! | `GET /capture?display=0&format=png` | a whole display, encoded |
+//! | `GET /capture/area?display=0&x=0&y=0&width=640&height=480` | a logical area of a display |
+//! | `GET /stream?display=0&fps=5` | a live MJPEG stream of a display, or of an area given as above |
+//! | `GET /ws?display=0&fps=5&format=jpg` | a WebSocket pushing each frame as a binary message |
+//! | `GET /metrics` | [`METRICS`] in the Prometheus text format |
+//!
+//! `format` is any [`OutputFormat`] extension and defaults to PNG; `display`
+//! defaults to 0. Streams take `fps` (default 5, at most 60), `quality` (JPEG
+//! quality, default 80) and `frames` to end after that many frames; browsers
+//! show them in an `<img>` tag. WebSockets take the same parameters, with
+//! `format` one of PNG, JPEG (the default) or WebP, and close normally after
+//! the last frame; a dashboard turns each message into a `Blob` URL. Neither
+//! kind of stream queues frames for a slow client: the next frame is
+//! captured once the last one is written, so the client gets fewer of them
+//! instead of an ever older picture. With a token set, every request needs an
+//! `Authorization: Bearer <token>` header. Errors are JSON objects with an
+//! `error` message. Requests are served on a thread each, so a slow capture
+//! doesn't hold up the others, up to [`MAX_HANDLERS`] at a time, streams
+//! included; requests beyond that get a 503 until one finishes.
+
+use crate::encode::{encode_to_vec, EncodeOptions, OutputFormat};
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::metrics::{self, METRICS};
+pub use crate::source::{Screens, Source};
+use crate::{Error, Result};
+use screenshots::image::RgbaImage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response};
+
+/// Separates the parts of an MJPEG stream
+const BOUNDARY: &str = "snap_scale_frame";
+
+/// Appended to a client's key to prove the server speaks WebSocket
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Requests [`Server::run`] serves at once unless told otherwise
+pub const MAX_HANDLERS: usize = 32;
+
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+
+/// Writes a streamed response body until done or the client leaves
+pub type Stream = Box<dyn FnOnce(&mut dyn Write) -> io::Result<()> + Send>;
+
+/// What a [`Reply`] sends
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// Sent as it is produced, ending the connection afterwards
+    Stream(Stream),
+    /// WebSocket frames, sent once the connection is upgraded
+    Socket(Stream),
+}
+
+impl Body {
+    /// The bytes of a [`Body::Bytes`]; empty for streams
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Body::Bytes(bytes) => bytes,
+            Body::Stream(_) | Body::Socket(_) => &[],
+        }
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Body::Stream(_) => write!(f, "Stream"),
+            Body::Socket(_) => write!(f, "Socket"),
+        }
+    }
+}
+
+/// An HTTP response before it is sent
+#[derive(Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Body,
+}
+
+impl Reply {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json".into(),
+            body: Body::Bytes(serde_json::to_vec(value).unwrap_or_default()),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.into() }))
+    }
+
+    /// 400 for bad parameters, 500 for everything else
+    fn from_error(e: Error) -> Self {
+        match e {
+            Error::Invalid { .. } => Self::error(400, e.to_string()),
+            e => Self::error(500, e.to_string()),
+        }
+    }
+}
+
+/// A `/displays` entry
+#[derive(Serialize)]
+struct Listed {
+    index: usize,
+    #[serde(flatten)]
+    display: DisplayDescriptor,
+}
+
+/// Answers one request; `url` is the path plus query string
+pub fn handle<S: Source + 'static>(source: &Arc<S>, method: &str, url: &str) -> Reply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = parse_query(query);
+    let routed = match path {
+        "/displays" | "/capture" | "/capture/area" | "/stream" | "/ws" | "/metrics"
+            if method != "GET" =>
+        {
+            return Reply::error(405, format!("{method} isn't supported; use GET"));
+        }
+        "/displays" => displays(&**source),
+        "/capture" => capture(&**source, &query, false),
+        "/capture/area" => capture(&**source, &query, true),
+        "/stream" => stream(source, &query),
+        "/ws" => socket(source, &query),
+        "/metrics" => Ok(metrics_reply()),
+        _ => return Reply::error(404, format!("no endpoint {path}")),
+    };
+    routed.unwrap_or_else(Reply::from_error)
+}
+
+fn displays(source: &impl Source) -> Result<Reply> {
+    let listed: Vec<_> = source
+        .displays()?
+        .into_iter()
+        .enumerate()
+        .map(|(index, display)| Listed { index, display })
+        .collect();
+    Ok(Reply::json(200, &listed))
+}
+
+fn capture(source: &impl Source, query: &HashMap<String, String>, area: bool) -> Result<Reply> {
+    let display = param(query, "display")?.unwrap_or(0);
+    let format: OutputFormat = param(query, "format")?.unwrap_or(OutputFormat::Png);
+    let region = if area { Some(region(query)?) } else { None };
+    let image = counted(source.capture(display, region))?;
+    Ok(Reply {
+        status: 200,
+        content_type: format.mime_type().into(),
+        body: Body::Bytes(encode(&image, &EncodeOptions::new(format))?),
+    })
+}
+
+fn metrics_reply() -> Reply {
+    Reply {
+        status: 200,
+        content_type: metrics::CONTENT_TYPE.into(),
+        body: Body::Bytes(METRICS.render().into_bytes()),
+    }
+}
+
+/// Passes a capture through, counting it in [`METRICS`]
+fn counted(capture: Result<RgbaImage>) -> Result<RgbaImage> {
+    match &capture {
+        Ok(_) => METRICS.captured(),
+        Err(_) => METRICS.capture_failed(),
+    }
+    capture
+}
+
+/// [`encode_to_vec`], timed into [`METRICS`]
+fn encode(image: &RgbaImage, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    let bytes = encode_to_vec(image, options)?;
+    METRICS.encoded(started.elapsed(), bytes.len() as u64);
+    Ok(bytes)
+}
+
+/// The area named by the `x`, `y`, `width` and `height` parameters
+fn region(query: &HashMap<String, String>) -> Result<Region> {
+    let missing = |name: &str| Error::invalid("query", format!("{name} is missing"));
+    let region = Region::new(
+        param(query, "x")?.ok_or_else(|| missing("x"))?,
+        param(query, "y")?.ok_or_else(|| missing("y"))?,
+        param(query, "width")?.ok_or_else(|| missing("width"))?,
+        param(query, "height")?.ok_or_else(|| missing("height"))?,
+    );
+    if region.is_empty() {
+        return Err(Error::invalid("area", "width and height must be positive"));
+    }
+    Ok(region)
+}
+
+/// Frames of a display, or an area of it, at a steady rate
+struct Feed {
+    display: usize,
+    region: Option<Region>,
+    interval: Duration,
+    frames: Option<u64>,
+    options: EncodeOptions,
+}
+
+impl Feed {
+    /// Reads `display`, `fps`, `quality`, `frames` and an optional area
+    fn from_query(query: &HashMap<String, String>, format: OutputFormat) -> Result<Self> {
+        let fps: u32 = param(query, "fps")?.unwrap_or(5);
+        if !(1..=60).contains(&fps) {
+            return Err(Error::invalid("fps (1 to 60)", fps.to_string()));
+        }
+        let quality: u8 = param(query, "quality")?.unwrap_or(80);
+        if !(1..=100).contains(&quality) {
+            return Err(Error::invalid(
+                "JPEG quality (1 to 100)",
+                quality.to_string(),
+            ));
+        }
+        let region = if query.contains_key("x") {
+            Some(region(query)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            display: param(query, "display")?.unwrap_or(0),
+            region,
+            interval: Duration::from_secs(1) / fps,
+            frames: param(query, "frames")?,
+            options: EncodeOptions::new(format).with_jpeg_quality(quality),
+        })
+    }
+
+    fn capture(&self, source: &impl Source) -> Result<RgbaImage> {
+        counted(source.capture(self.display, self.region))
+    }
+
+    /// Hands `first` and the frames after it, encoded, to `send` until
+    /// `frames` have gone out or sending fails
+    ///
+    /// The next frame is captured only after `send` returns, so a client
+    /// that reads slower than `fps` holds up the capture rather than a queue.
+    fn run(
+        &self,
+        source: &impl Source,
+        first: RgbaImage,
+        mut send: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut frame = first;
+        let mut sent = 0;
+        let mut last: Option<Instant> = None;
+        loop {
+            let started = Instant::now();
+            if let Some(last) = last {
+                METRICS.frame_interval(started - last);
+            }
+            last = Some(started);
+            send(&encode(&frame, &self.options).map_err(io::Error::other)?)?;
+            sent += 1;
+            if self.frames.is_some_and(|frames| sent >= frames) {
+                return Ok(());
+            }
+            std::thread::sleep(self.interval.saturating_sub(started.elapsed()));
+            frame = self.capture(source).map_err(io::Error::other)?;
+        }
+    }
+}
+
+/// An MJPEG stream; the first frame is captured up front so bad
+/// parameters still get an error status
+fn stream<S: Source + 'static>(source: &Arc<S>, query: &HashMap<String, String>) -> Result<Reply> {
+    let feed = Feed::from_query(query, OutputFormat::Jpeg)?;
+    let first = feed.capture(&**source)?;
+    let source = Arc::clone(source);
+    let stream: Stream = Box::new(move |out| {
+        feed.run(&*source, first, |jpeg| {
+            write!(
+                out,
+                "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                jpeg.len()
+            )?;
+            out.write_all(jpeg)?;
+            out.write_all(b"\r\n")?;
+            out.flush()
+        })
+    });
+    Ok(Reply {
+        status: 200,
+        content_type: format!("multipart/x-mixed-replace; boundary={BOUNDARY}"),
+        body: Body::Stream(stream),
+    })
+}
+
+/// A WebSocket feed of binary messages, ending with a normal close; like
+/// [`stream`], it fails early on bad parameters
+fn socket<S: Source + 'static>(source: &Arc<S>, query: &HashMap<String, String>) -> Result<Reply> {
+    let format: OutputFormat = param(query, "format")?.unwrap_or(OutputFormat::Jpeg);
+    if !matches!(
+        format,
+        OutputFormat::Png | OutputFormat::Jpeg | OutputFormat::WebP
+    ) {
+        return Err(Error::invalid(
+            "WebSocket frame format (png, jpg or webp)",
+            format.to_string(),
+        ));
+    }
+    let feed = Feed::from_query(query, format)?;
+    let first = feed.capture(&**source)?;
+    let source = Arc::clone(source);
+    let stream: Stream = Box::new(move |out| {
+        feed.run(&*source, first, |bytes| {
+            write_frame(out, OPCODE_BINARY, bytes)
+        })?;
+        // 1000: normal closure
+        write_frame(out, OPCODE_CLOSE, &1000u16.to_be_bytes())
+    });
+    Ok(Reply {
+        status: 101,
+        content_type: format.mime_type().into(),
+        body: Body::Socket(stream),
+    })
+}
+
+/// Writes one unfragmented, unmasked WebSocket frame, as servers send them
+fn write_frame(out: &mut dyn Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut head = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => head.push(len as u8),
+        len @ 126..=0xFFFF => {
+            head.push(126);
+            head.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            head.push(127);
+            head.extend((len as u64).to_be_bytes());
+        }
+    }
+    out.write_all(&head)?;
+    out.write_all(payload)?;
+    out.flush()
+}
+
+/// The `Sec-WebSocket-Accept` answer to a `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    use base64::Engine;
+    let digest = sha1_smol::Sha1::from(format!("{key}{WEBSOCKET_GUID}")).digest();
+    base64::engine::general_purpose::STANDARD.encode(digest.bytes())
+}
+
+/// Parses query parameter `name` if present
+fn param<T: std::str::FromStr>(query: &HashMap<String, String>, name: &str) -> Result<Option<T>> {
+    query
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| Error::invalid("query", format!("{name}={value}")))
+        })
+        .transpose()
+}
+
+/// Splits `a=1&b=2`, decoding `+` and `%XX` escapes
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// A bound HTTP server
+pub struct Server<S> {
+    http: tiny_http::Server,
+    source: Arc<S>,
+    token: Option<String>,
+    max_handlers: usize,
+    /// Requests being served, streams until their client leaves
+    handlers: Arc<AtomicUsize>,
+}
+
+impl<S: Source + 'static> Server<S> {
+    /// Listens on `addr`, such as `127.0.0.1:8080`; port 0 picks a free one
+    pub fn bind(addr: &str, source: S) -> Result<Self> {
+        let http = tiny_http::Server::http(addr)
+            .map_err(|e| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("{addr}: {e}")))?;
+        Ok(Self {
+            http,
+            source: Arc::new(source),
+            token: None,
+            max_handlers: MAX_HANDLERS,
+            handlers: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Requires `Authorization: Bearer <token>` on every request
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Serves at most `max` requests at once instead of [`MAX_HANDLERS`]
+    pub fn with_max_handlers(mut self, max: usize) -> Self {
+        self.max_handlers = max.max(1);
+        self
+    }
+
+    /// The address actually bound
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Serves requests until the process ends
+    pub fn run(&self) -> Result<()> {
+        loop {
+            let request = self.http.recv()?;
+            if self.handlers.fetch_add(1, Ordering::SeqCst) >= self.max_handlers {
+                self.handlers.fetch_sub(1, Ordering::SeqCst);
+                let reply = Reply::error(503, "too many requests at once; try again later");
+                let content_type =
+                    Header::from_bytes("Content-Type", reply.content_type.as_bytes())
+                        .expect("content types are valid header values");
+                let response = Response::from_data(reply.body.bytes())
+                    .with_status_code(reply.status)
+                    .with_header(content_type);
+                // Answered here, so a flood gets no threads at all
+                let _ = request.respond(response);
+                continue;
+            }
+            let handler = Handler(Arc::clone(&self.handlers));
+            let source = Arc::clone(&self.source);
+            let token = self.token.clone();
+            std::thread::spawn(move || {
+                respond(&source, token.as_deref(), request);
+                drop(handler);
+            });
+        }
+    }
+}
+
+/// Counts a request in [`Server::run`]'s handlers while it's served
+struct Handler(Arc<AtomicUsize>);
+
+impl Drop for Handler {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn respond<S: Source + 'static>(source: &Arc<S>, token: Option<&str>, request: Request) {
+    let mut reply = if authorized(&request, token) {
+        handle(source, request.method().as_str(), request.url())
+    } else {
+        Reply::error(401, "missing or wrong bearer token")
+    };
+    let key = header(&request, "Sec-WebSocket-Key").filter(|_| {
+        header(&request, "Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+    });
+    if matches!(reply.body, Body::Socket(_)) && key.is_none() {
+        reply = Reply::error(400, "expected a WebSocket upgrade request");
+    }
+    let body = match reply.body {
+        Body::Bytes(bytes) => bytes,
+        Body::Stream(stream) => {
+            // Written straight to the socket: tiny_http's chunked encoder
+            // would hold back the end of each frame until the next one
+            let mut out = request.into_writer();
+            let head = format!(
+                "HTTP/1.1 {} OK\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                reply.status, reply.content_type
+            );
+            // Ends when the client goes away
+            let _ = out
+                .write_all(head.as_bytes())
+                .and_then(|()| stream(&mut out));
+            return;
+        }
+        Body::Socket(stream) => {
+            let accept = Header::from_bytes(
+                "Sec-WebSocket-Accept",
+                accept_key(key.expect("socket replies without a key became errors")),
+            )
+            .expect("base64 is a valid header value");
+            let mut socket = request.upgrade("websocket", Response::empty(101).with_header(accept));
+            // Ends when the client goes away
+            let _ = stream(&mut socket);
+            return;
+        }
+    };
+    let content_type = Header::from_bytes("Content-Type", reply.content_type.as_bytes())
+        .expect("content types are valid header values");
+    let mut response = Response::from_data(body)
+        .with_status_code(reply.status)
+        .with_header(content_type);
+    if reply.status == 401 {
+        response
+            .add_header(Header::from_bytes("WWW-Authenticate", "Bearer").expect("valid header"));
+    }
+    // The client may have hung up already; nothing to do about it
+    let _ = request.respond(response);
+}
+
+/// Answers `GET /metrics` on `addr` from a background thread, for modes
+/// that don't run the full server; returns the address actually bound
+pub fn spawn_metrics(addr: &str) -> Result<SocketAddr> {
+    let http = tiny_http::Server::http(addr)
+        .map_err(|e| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("{addr}: {e}")))?;
+    let bound = http
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| Error::invalid("metrics address", addr))?;
+    std::thread::spawn(move || {
+        for request in http.incoming_requests() {
+            let reply = match (request.method().as_str(), request.url()) {
+                ("GET", "/metrics") => metrics_reply(),
+                (_, url) => Reply::error(404, format!("no endpoint {url}; try /metrics")),
+            };
+            let content_type = Header::from_bytes("Content-Type", reply.content_type.as_bytes())
+                .expect("content types are valid header values");
+            let response = Response::from_data(reply.body.bytes())
+                .with_status_code(reply.status)
+                .with_header(content_type);
+            let _ = request.respond(response);
+        }
+    });
+    Ok(bound)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn authorized(request: &Request, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    request
+        .headers()
+        .iter()
+        .filter(|header| header.field.equiv("Authorization"))
+        .filter_map(|header| header.value.as_str().strip_prefix("Bearer "))
+        .any(|given| same_token(given.as_bytes(), token.as_bytes()))
+}
+
+/// Compares tokens in time that depends on their length only, so timing
+/// responses doesn't reveal how much of a guess was right
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    /// Two displays of solid colors, sized by index
+    struct Fake;
+
+    impl Source for Fake {
+        fn displays(&self) -> Result<Vec<DisplayDescriptor>> {
+            Ok((0..2)
+                .map(|i| DisplayDescriptor {
+                    id: 10 + i,
+                    name: String::new(),
+                    x: 1920 * i as i32,
+                    y: 0,
+                    width: 1920,
+                    height: 1080,
+                    rotation: 0.0,
+                    scale_factor: 1.0,
+                    frequency: 60.0,
+                    is_primary: i == 0,
+                })
+                .collect())
+        }
+
+        fn capture(&self, index: usize, region: Option<Region>) -> Result<RgbaImage> {
+            if index > 1 {
+                return Err(Error::invalid("display", format!("no display #{index}")));
+            }
+            let region = region.unwrap_or(Region::new(0, 0, 16, 9));
+            Ok(RgbaImage::from_pixel(
+                region.width,
+                region.height,
+                Rgba([index as u8 * 100, 0, 0, 255]),
+            ))
+        }
+    }
+
+    fn fake() -> Arc<Fake> {
+        Arc::new(Fake)
+    }
+
+    fn decoded(reply: &Reply) -> RgbaImage {
+        screenshots::image::load_from_memory(reply.body.bytes())
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn test_displays_lists_indices() {
+        let reply = handle(&fake(), "GET", "/displays");
+        assert_eq!(
+            (reply.status, reply.content_type.as_str()),
+            (200, "application/json")
+        );
+        let listed: serde_json::Value = serde_json::from_slice(reply.body.bytes()).unwrap();
+        assert_eq!(listed[1]["index"], 1);
+        assert_eq!(listed[1]["id"], 11);
+        assert_eq!(listed[1]["x"], 1920);
+    }
+
+    #[test]
+    fn test_capture_encodes_the_display() {
+        let reply = handle(&fake(), "GET", "/capture?display=1");
+        assert_eq!(
+            (reply.status, reply.content_type.as_str()),
+            (200, "image/png")
+        );
+        let image = decoded(&reply);
+        assert_eq!(image.dimensions(), (16, 9));
+        assert_eq!(image.get_pixel(0, 0).0, [100, 0, 0, 255]);
+
+        let reply = handle(&fake(), "GET", "/capture?format=jpg");
+        assert_eq!(reply.content_type, "image/jpeg");
+    }
+
+    #[test]
+    fn test_capture_area_needs_a_region() {
+        let reply = handle(&fake(), "GET", "/capture/area?x=5&y=5&width=30&height=20");
+        assert_eq!(decoded(&reply).dimensions(), (30, 20));
+
+        for url in [
+            "/capture/area?x=5&y=5&width=30",
+            "/capture/area?x=5&y=5&width=0&height=20",
+            "/capture?display=two",
+            "/capture?display=7",
+            "/capture?format=gif",
+        ] {
+            let reply = handle(&fake(), "GET", url);
+            assert_eq!(reply.status, 400, "{url}");
+            let error: serde_json::Value = serde_json::from_slice(reply.body.bytes()).unwrap();
+            assert!(error["error"].is_string(), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_stream_sends_jpeg_parts() {
+        let reply = handle(
+            &fake(),
+            "GET",
+            "/stream?display=1&fps=60&frames=2&x=0&y=0&width=8&height=8",
+        );
+        assert_eq!(reply.status, 200);
+        assert_eq!(
+            reply.content_type,
+            format!("multipart/x-mixed-replace; boundary={BOUNDARY}")
+        );
+        let Body::Stream(stream) = reply.body else {
+            panic!("Not a stream");
+        };
+        let mut out = Vec::new();
+        stream(&mut out).unwrap();
+
+        let text = String::from_utf8_lossy(&out);
+        assert_eq!(text.matches(&format!("--{BOUNDARY}\r\n")).count(), 2);
+        let header_end = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let length: usize = text
+            .split("Content-Length: ")
+            .nth(1)
+            .and_then(|rest| rest.split("\r\n").next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let jpeg = &out[header_end..header_end + length];
+        let frame = screenshots::image::load_from_memory(jpeg).unwrap();
+        assert_eq!((frame.width(), frame.height()), (8, 8));
+    }
+
+    #[test]
+    fn test_stream_rejects_bad_parameters() {
+        for url in [
+            "/stream?fps=0",
+            "/stream?fps=61",
+            "/stream?quality=0",
+            "/stream?x=1",
+            "/stream?display=9",
+        ] {
+            assert_eq!(handle(&fake(), "GET", url).status, 400, "{url}");
+        }
+    }
+
+    /// Splits server frames into (opcode, payload) pairs
+    fn frames(mut bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while !bytes.is_empty() {
+            assert_eq!(bytes[0] & 0x80, 0x80, "FIN set");
+            let (len, head) = match bytes[1] {
+                126 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+                127 => (
+                    u64::from_be_bytes(bytes[2..10].try_into().unwrap()) as usize,
+                    10,
+                ),
+                len => (len as usize, 2),
+            };
+            frames.push((bytes[0] & 0x0F, bytes[head..head + len].to_vec()));
+            bytes = &bytes[head + len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn test_socket_sends_binary_frames_then_closes() {
+        let reply = handle(&fake(), "GET", "/ws?display=1&fps=60&frames=2&format=png");
+        assert_eq!(
+            (reply.status, reply.content_type.as_str()),
+            (101, "image/png")
+        );
+        let Body::Socket(stream) = reply.body else {
+            panic!("Not a socket");
+        };
+        let mut out = Vec::new();
+        stream(&mut out).unwrap();
+
+        let frames = frames(&out);
+        assert_eq!(frames.len(), 3);
+        for (opcode, payload) in &frames[..2] {
+            assert_eq!(*opcode, OPCODE_BINARY);
+            let image = screenshots::image::load_from_memory(payload).unwrap();
+            assert_eq!(image.into_rgba8().get_pixel(0, 0).0, [100, 0, 0, 255]);
+        }
+        assert_eq!(frames[2], (OPCODE_CLOSE, vec![0x03, 0xE8]));
+
+        for url in ["/ws?format=tiff", "/ws?fps=0", "/ws?display=9"] {
+            assert_eq!(handle(&fake(), "GET", url).status, 400, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_frame_lengths_and_accept_key() {
+        for len in [0, 125, 126, 0xFFFF, 0x10000] {
+            let mut out = Vec::new();
+            write_frame(&mut out, OPCODE_BINARY, &vec![7; len]).unwrap();
+            assert_eq!(frames(&out), [(OPCODE_BINARY, vec![7; len])], "{len}");
+        }
+        // The example handshake from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_metrics_count_served_captures() {
+        handle(&fake(), "GET", "/capture");
+        handle(&fake(), "GET", "/capture?display=7");
+        let reply = handle(&fake(), "GET", "/metrics");
+        assert_eq!(
+            (reply.status, reply.content_type.as_str()),
+            (200, metrics::CONTENT_TYPE)
+        );
+        // Other tests count into the same metrics
+        let text = String::from_utf8(reply.body.bytes().to_vec()).unwrap();
+        let value = |name: &str| -> u64 {
+            let line = text.lines().find(|l| l.starts_with(name)).unwrap();
+            line[name.len() + 1..].parse().unwrap()
+        };
+        assert!(value("snap_scale_captures_total") >= 1);
+        assert!(value("snap_scale_capture_failures_total") >= 1);
+        assert!(value("snap_scale_frame_size_bytes_count") >= 1);
+    }
+
+    #[test]
+    fn test_metrics_server() {
+        let addr = spawn_metrics("127.0.0.1:0").unwrap();
+        let metrics = get(addr, "/metrics", None);
+        assert!(metrics.starts_with("HTTP/1.1 200"), "{metrics}");
+        assert!(metrics.contains("# TYPE snap_scale_fps gauge"));
+        assert!(get(addr, "/capture", None).starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_unknown_routes_and_methods() {
+        assert_eq!(handle(&fake(), "GET", "/nope").status, 404);
+        assert_eq!(handle(&fake(), "POST", "/capture").status, 405);
+    }
+
+    #[test]
+    fn test_query_decoding() {
+        let query = parse_query("a=1&b=x%2By+z&flag&=&c=%zz");
+        assert_eq!(query["a"], "1");
+        assert_eq!(query["b"], "x+y z");
+        assert_eq!(query["flag"], "");
+        assert_eq!(query["c"], "%zz", "Bad escapes stay as they are");
+    }
+
+    /// Sends a request with extra `headers` and reads all of the response
+    fn request(addr: SocketAddr, path: &str, headers: &str) -> Vec<u8> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: x\r\n{headers}Connection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    fn get(addr: SocketAddr, path: &str, token: Option<&str>) -> String {
+        let auth = token.map_or(String::new(), |t| format!("Authorization: Bearer {t}\r\n"));
+        String::from_utf8_lossy(&request(addr, path, &auth)).into_owned()
+    }
+
+    #[test]
+    fn test_server_checks_the_token() {
+        let server = Server::bind("127.0.0.1:0", Fake)
+            .unwrap()
+            .with_token(Some("secret".into()));
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+
+        let denied = get(addr, "/displays", None);
+        assert!(denied.starts_with("HTTP/1.1 401"), "{denied}");
+        assert!(get(addr, "/displays", Some("wrong")).starts_with("HTTP/1.1 401"));
+        let allowed = get(addr, "/displays", Some("secret"));
+        assert!(allowed.starts_with("HTTP/1.1 200"), "{allowed}");
+        assert!(allowed.contains(r#""is_primary":true"#));
+
+        let stream = get(addr, "/stream?frames=1", Some("secret"));
+        assert!(stream.starts_with("HTTP/1.1 200"), "{stream}");
+        assert!(
+            stream.contains(&format!("\r\n\r\n--{BOUNDARY}\r\n")),
+            "{stream}"
+        );
+    }
+
+    #[test]
+    fn test_tokens_compare_whole() {
+        assert!(same_token(b"secret", b"secret"));
+        assert!(!same_token(b"secreT", b"secret"));
+        assert!(!same_token(b"secret!", b"secret"));
+        assert!(!same_token(b"", b"secret"));
+    }
+
+    #[test]
+    fn test_server_limits_concurrent_requests() {
+        let server = Server::bind("127.0.0.1:0", Fake)
+            .unwrap()
+            .with_max_handlers(1);
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+
+        // An endless stream holds the only handler while its client stays
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET /stream?fps=60 HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        let mut head = [0; 12];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"HTTP/1.1 200");
+        let busy = get(addr, "/displays", None);
+        assert!(busy.starts_with("HTTP/1.1 503"), "{busy}");
+        assert!(busy.contains(r#""error""#), "{busy}");
+
+        drop(stream);
+        let start = Instant::now();
+        while !get(addr, "/displays", None).starts_with("HTTP/1.1 200") {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "The handler is freed"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_server_upgrades_websockets() {
+        let server = Server::bind("127.0.0.1:0", Fake).unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+
+        let plain = get(addr, "/ws", None);
+        assert!(plain.starts_with("HTTP/1.1 400"), "{plain}");
+
+        let response = request(
+            addr,
+            "/ws?frames=1",
+            "Upgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n",
+        );
+        let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&response[..head_end]);
+        assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        let frames = frames(&response[head_end..]);
+        assert_eq!(
+            frames.iter().map(|(opcode, _)| *opcode).collect::<Vec<_>>(),
+            [OPCODE_BINARY, OPCODE_CLOSE]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Long-running soak harness
+//!
+//! Repeats a per-frame operation many times while sampling resident memory and
+//! open handle counts, then reports any growth beyond the configured budget.
+//! [`SoakHarness::run`] drives the frames itself; a loop of its own, like
+//! `watch --soak`, reports each to a [`SoakMonitor`] instead.
+//! Sampling uses `/proc` on Linux and `/dev/fd` on macOS; elsewhere the
+//! numbers are unavailable and only the frame loop itself is exercised.
+
+use crate::Result;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A point-in-time view of the process's resource usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// Resident set size in bytes
+    pub rss_bytes: Option<u64>,
+    /// Open file descriptors / handles
+    pub open_handles: Option<usize>,
+}
+
+impl ResourceUsage {
+    /// Samples the current process
+    pub fn sample() -> Self {
+        Self {
+            rss_bytes: rss_bytes(),
+            open_handles: open_handles(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn open_handles() -> Option<usize> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else {
+        "/dev/fd"
+    };
+    // The directory handle used for listing is itself counted; subtract it
+    Some(std::fs::read_dir(dir).ok()?.count().saturating_sub(1))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_handles() -> Option<usize> {
+    None
+}
+
+/// Runs a frame operation repeatedly and checks for resource growth
+#[derive(Debug, Clone)]
+pub struct SoakHarness {
+    frames: u64,
+    warmup_frames: u64,
+    sample_every: u64,
+    max_rss_growth: u64,
+    max_handle_growth: usize,
+}
+
+impl SoakHarness {
+    /// A harness running `frames` iterations with default budgets
+    pub fn new(frames: u64) -> Self {
+        Self {
+            frames,
+            warmup_frames: (frames / 100).min(1_000),
+            sample_every: (frames / 1_000).max(1),
+            max_rss_growth: 16 * 1024 * 1024,
+            max_handle_growth: 0,
+        }
+    }
+
+    /// Frames run before the baseline is sampled, so allocator and cache
+    /// warm-up doesn't count as growth
+    pub fn warmup_frames(mut self, frames: u64) -> Self {
+        self.warmup_frames = frames;
+        self
+    }
+
+    /// How often (in frames) resource usage is sampled
+    pub fn sample_every(mut self, frames: u64) -> Self {
+        self.sample_every = frames.max(1);
+        self
+    }
+
+    /// Allowed resident memory growth over the baseline, in bytes
+    pub fn max_rss_growth(mut self, bytes: u64) -> Self {
+        self.max_rss_growth = bytes;
+        self
+    }
+
+    /// Allowed growth in open handles over the baseline
+    pub fn max_handle_growth(mut self, handles: usize) -> Self {
+        self.max_handle_growth = handles;
+        self
+    }
+
+    /// Starts tracking a loop that calls [`SoakMonitor::frame`] after each
+    /// of its frames, such as `watch --soak`
+    pub fn monitor(&self) -> SoakMonitor {
+        let baseline = (self.warmup_frames == 0).then(ResourceUsage::sample);
+        SoakMonitor {
+            harness: self.clone(),
+            frames: 0,
+            started: Instant::now(),
+            baseline,
+            peak: baseline.unwrap_or_default(),
+        }
+    }
+
+    /// Runs the soak, stopping at the first frame error
+    pub fn run(&self, mut frame: impl FnMut(u64) -> Result<()>) -> Result<SoakReport> {
+        let mut monitor = self.monitor();
+        for index in 0..self.frames {
+            frame(index)?;
+            monitor.frame();
+        }
+        Ok(monitor.finish())
+    }
+}
+
+/// Resource usage of a running soak, sampled as its frames go by
+#[derive(Debug, Clone)]
+pub struct SoakMonitor {
+    harness: SoakHarness,
+    frames: u64,
+    started: Instant,
+    /// Sampled once the warm-up frames are done
+    baseline: Option<ResourceUsage>,
+    peak: ResourceUsage,
+}
+
+impl SoakMonitor {
+    /// Counts a finished frame, sampling usage when one is due
+    pub fn frame(&mut self) {
+        self.frames += 1;
+        let warmup = self.harness.warmup_frames;
+        match self.baseline {
+            None if self.frames >= warmup => {
+                let baseline = ResourceUsage::sample();
+                (self.baseline, self.peak) = (Some(baseline), baseline);
+            }
+            Some(_) if (self.frames - warmup) % self.harness.sample_every == 0 => {
+                self.peak = max_usage(self.peak, ResourceUsage::sample());
+            }
+            _ => {}
+        }
+    }
+
+    /// Takes a last sample and reports on the frames so far
+    pub fn finish(self) -> SoakReport {
+        let last = ResourceUsage::sample();
+        // A run shorter than its warm-up has nothing to compare against
+        let baseline = self.baseline.unwrap_or(last);
+        SoakReport {
+            frames: self.frames,
+            elapsed: self.started.elapsed(),
+            baseline,
+            peak: max_usage(max_usage(self.peak, baseline), last),
+            last,
+            max_rss_growth: self.harness.max_rss_growth,
+            max_handle_growth: self.harness.max_handle_growth,
+        }
+    }
+}
+
+fn max_usage(a: ResourceUsage, b: ResourceUsage) -> ResourceUsage {
+    ResourceUsage {
+        rss_bytes: a.rss_bytes.max(b.rss_bytes),
+        open_handles: a.open_handles.max(b.open_handles),
+    }
+}
+
+/// Outcome of a soak run; displays as a line like `100000 frames in 12.3s
+/// (8130 fps), resident memory 9.1 → 9.4 MiB, 6 → 6 open handles`
+#[derive(Debug, Clone)]
+pub struct SoakReport {
+    pub frames: u64,
+    pub elapsed: Duration,
+    pub baseline: ResourceUsage,
+    pub peak: ResourceUsage,
+    pub last: ResourceUsage,
+    max_rss_growth: u64,
+    max_handle_growth: usize,
+}
+
+impl SoakReport {
+    /// Frames per second achieved over the whole run
+    pub fn fps(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Budget violations, empty when the run stayed bounded
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if let (Some(base), Some(peak)) = (self.baseline.rss_bytes, self.peak.rss_bytes) {
+            let growth = peak.saturating_sub(base);
+            if growth > self.max_rss_growth {
+                violations.push(format!(
+                    "resident memory grew by {growth} bytes (budget {})",
+                    self.max_rss_growth
+                ));
+            }
+        }
+        if let (Some(base), Some(last)) = (self.baseline.open_handles, self.last.open_handles) {
+            let growth = last.saturating_sub(base);
+            if growth > self.max_handle_growth {
+                violations.push(format!(
+                    "{growth} handles leaked (budget {})",
+                    self.max_handle_growth
+                ));
+            }
+        }
+        violations
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames in {:.1}s ({:.0} fps)",
+            self.frames,
+            self.elapsed.as_secs_f64(),
+            self.fps()
+        )?;
+        if let (Some(base), Some(peak)) = (self.baseline.rss_bytes, self.peak.rss_bytes) {
+            let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+            write!(
+                f,
+                ", resident memory {:.1} → {:.1} MiB",
+                mib(base),
+                mib(peak)
+            )?;
+        }
+        if let (Some(base), Some(last)) = (self.baseline.open_handles, self.last.open_handles) {
+            write!(f, ", {base} → {last} open handles")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Resource usage is process-wide, so these tests must not overlap
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_bounded_loop_has_no_violations() {
+        let _guard = SERIAL.lock().unwrap();
+        let report = SoakHarness::new(2_000)
+            .run(|i| {
+                let buffer = vec![i as u8; 4096];
+                assert_eq!(buffer.len(), 4096);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(report.frames, 2_000);
+        assert!(report.violations().is_empty(), "{:?}", report.violations());
+    }
+
+    #[test]
+    fn test_monitor_follows_a_loop_of_its_own() {
+        let _guard = SERIAL.lock().unwrap();
+        let mut monitor = SoakHarness::new(10).warmup_frames(3).monitor();
+        for _ in 0..7 {
+            monitor.frame();
+        }
+        let report = monitor.finish();
+
+        assert_eq!(report.frames, 7, "Counts the frames it saw");
+        assert!(report.violations().is_empty(), "{:?}", report.violations());
+        assert!(report.to_string().starts_with("7 frames in "), "{report}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_handle_leak_is_reported() {
+        let _guard = SERIAL.lock().unwrap();
+        let mut leaked = Vec::new();
+        let report = SoakHarness::new(50)
+            .warmup_frames(0)
+            .run(|_| {
+                leaked.push(std::fs::File::open("/proc/self/status")?);
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(
+            report
+                .violations()
+                .iter()
+                .any(|v| v.contains("handles leaked")),
+            "Leaking a file per frame should be caught: {:?}",
+            report.violations()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memory_growth_is_reported() {
+        let _guard = SERIAL.lock().unwrap();
+        let mut leaked: Vec<Vec<u8>> = Vec::new();
+        let report = SoakHarness::new(64)
+            .warmup_frames(0)
+            .max_rss_growth(1024 * 1024)
+            .run(|_| {
+                leaked.push(vec![1u8; 256 * 1024]);
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(
+            report.violations().iter().any(|v| v.contains("memory")),
+            "Leaking 16 MiB should be caught: {:?}",
+            report.violations()
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Displays and pixels for the remote front ends
+//!
+//! The HTTP and gRPC servers capture through a [`Source`] rather than the
+//! screens directly, so their tests run against synthetic displays. Every
+//! [`CaptureBackend`](crate::backend::CaptureBackend) is a source.
+
+use crate::geometry::Region;
+use crate::metadata::DisplayDescriptor;
+use crate::Result;
+use screenshots::image::RgbaImage;
+
+pub use crate::backend::Screens;
+
+/// Where a server gets displays and pixels from
+pub trait Source: Send + Sync {
+    /// Displays in index order
+    fn displays(&self) -> Result<Vec<DisplayDescriptor>>;
+
+    /// Captures display `index`, all of it or a logical `region` of it
+    fn capture(&self, index: usize, region: Option<Region>) -> Result<RgbaImage>;
+}
@@ -0,0 +1,139 @@
+//! Conversion of captures to sRGB
+//!
+//! Embedding the display profile (see [`crate::icc`]) only helps viewers that
+//! honour it. [`ToSrgb`] converts the pixels themselves from the display's
+//! color space to sRGB, so captures of wide-gamut panels look right anywhere;
+//! colors outside sRGB are clipped. Conversion uses
+//! [qcms](https://github.com/FirefoxGraphics/qcms) with perceptual intent and
+//! supports the matrix/curve and LUT-based RGB profiles displays use.
+
+use crate::transform::Transform;
+use crate::{Error, Result};
+use qcms::{DataType, Intent, Profile};
+use screenshots::image::RgbaImage;
+
+/// Converts pixels from a display profile to sRGB, keeping alpha
+pub struct ToSrgb {
+    transform: qcms::Transform,
+}
+
+impl ToSrgb {
+    /// Builds the conversion from an ICC profile
+    pub fn new(profile: &[u8]) -> Result<Self> {
+        let input = Profile::new_from_slice(profile, false)
+            .ok_or_else(|| Error::invalid("ICC profile", "not an RGB display profile"))?;
+        let mut srgb = Profile::new_sRGB();
+        srgb.precache_output_transform();
+        let transform = qcms::Transform::new(&input, &srgb, DataType::RGBA8, Intent::Perceptual)
+            .ok_or_else(|| Error::Unsupported("converting from this ICC profile".into()))?;
+        Ok(Self { transform })
+    }
+}
+
+impl Transform for ToSrgb {
+    fn name(&self) -> &str {
+        "srgb"
+    }
+
+    fn apply(&self, mut image: RgbaImage) -> Result<RgbaImage> {
+        self.transform.apply(&mut image);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    /// A v2 matrix/curve display profile with pure gamma curves
+    fn profile(colorants: [[f64; 3]; 3], gamma: f64) -> Vec<u8> {
+        let fixed = |v: f64| ((v * 65536.0).round() as i32).to_be_bytes();
+        let xyz = |[x, y, z]: [f64; 3]| {
+            [b"XYZ \0\0\0\0".as_slice(), &fixed(x), &fixed(y), &fixed(z)].concat()
+        };
+        let curve = [
+            b"curv\0\0\0\0\0\0\0\x01".as_slice(),
+            &((gamma * 256.0).round() as u16).to_be_bytes(),
+            b"\0\0",
+        ]
+        .concat();
+        let tags = [
+            (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+            (b"rXYZ", xyz(colorants[0])),
+            (b"gXYZ", xyz(colorants[1])),
+            (b"bXYZ", xyz(colorants[2])),
+            (b"rTRC", curve.clone()),
+            (b"gTRC", curve.clone()),
+            (b"bTRC", curve),
+        ];
+
+        let mut header = vec![0u8; 128];
+        header[8] = 2;
+        header[12..24].copy_from_slice(b"mntrRGB XYZ ");
+        header[36..40].copy_from_slice(b"acsp");
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = Vec::new();
+        let mut offset = 128 + 4 + tags.len() * 12;
+        for (signature, tag) in &tags {
+            table.extend(*signature);
+            table.extend((offset as u32).to_be_bytes());
+            table.extend((tag.len() as u32).to_be_bytes());
+            offset += tag.len();
+            data.extend(tag);
+        }
+        let mut profile = [header, table, data].concat();
+        let size = (profile.len() as u32).to_be_bytes();
+        profile[..4].copy_from_slice(&size);
+        profile
+    }
+
+    const SRGB_PRIMARIES: [[f64; 3]; 3] = [
+        [0.4361, 0.2225, 0.0139],
+        [0.3851, 0.7169, 0.0971],
+        [0.1431, 0.0606, 0.7141],
+    ];
+
+    const P3_PRIMARIES: [[f64; 3]; 3] = [
+        [0.5151, 0.2412, -0.0011],
+        [0.2920, 0.6922, 0.0419],
+        [0.1571, 0.0666, 0.7841],
+    ];
+
+    fn convert(profile: &[u8], color: [u8; 4]) -> [u8; 4] {
+        let image = RgbaImage::from_pixel(4, 4, Rgba(color));
+        let out = ToSrgb::new(profile).unwrap().apply(image).unwrap();
+        out.get_pixel(3, 3).0
+    }
+
+    #[test]
+    fn test_neutrals_stay_neutral() {
+        let p3 = profile(P3_PRIMARIES, 2.2);
+        assert_eq!(convert(&p3, [255, 255, 255, 255]), [255, 255, 255, 255]);
+        assert_eq!(convert(&p3, [0, 0, 0, 40]), [0, 0, 0, 40], "Keeps alpha");
+
+        let [r, g, b, _] = convert(&p3, [128, 128, 128, 255]);
+        assert!(r == g && g == b, "{r} {g} {b}");
+        assert!(r.abs_diff(128) <= 3, "Gamma 2.2 is close to sRGB: {r}");
+    }
+
+    #[test]
+    fn test_wide_gamut_colors_gain_saturation() {
+        let [r, g, b, a] = convert(&profile(P3_PRIMARIES, 2.2), [200, 100, 100, 255]);
+        assert!(r > 200 && g < 100 && b < 100, "{r} {g} {b}");
+        assert_eq!(a, 255);
+
+        let [r, g, b, _] = convert(&profile(SRGB_PRIMARIES, 2.2), [200, 100, 100, 255]);
+        for (out, source) in [(r, 200u8), (g, 100), (b, 100)] {
+            assert!(out.abs_diff(source) <= 4, "sRGB primaries: {r} {g} {b}");
+        }
+    }
+
+    #[test]
+    fn test_rejects_unusable_profiles() {
+        assert!(ToSrgb::new(b"not a profile").is_err());
+        let mut gray = profile(SRGB_PRIMARIES, 2.2);
+        gray[16..20].copy_from_slice(b"GRAY");
+        assert!(ToSrgb::new(&gray).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::geometry::{saturate_u32, Region};
+
+/// Where each display's frame lands on a stitched, all-displays canvas
+///
+/// Displays are given in desktop-global coordinates (as reported by the OS,
+/// possibly negative for monitors left of or above the primary one). The
+/// canvas is their bounding box, shifted so its top-left corner is `(0, 0)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StitchLayout {
+    /// Bounding box of all displays in desktop-global coordinates
+    pub bounds: Region,
+    /// Canvas-local placement of each display, in input order
+    pub placements: Vec<Region>,
+}
+
+impl StitchLayout {
+    /// Computes the layout for the given display bounds
+    ///
+    /// Returns `None` when there are no displays.
+    pub fn new(displays: &[Region]) -> Option<Self> {
+        let (first, rest) = displays.split_first()?;
+        let bounds = rest.iter().fold(*first, |acc, r| acc.union(r));
+
+        let placements = displays
+            .iter()
+            .map(|display| {
+                Region::new(
+                    saturate_u32(display.x as i64 - bounds.x as i64) as i32,
+                    saturate_u32(display.y as i64 - bounds.y as i64) as i32,
+                    display.width,
+                    display.height,
+                )
+            })
+            .collect();
+
+        Some(Self { bounds, placements })
+    }
+
+    /// Width and height of the stitched canvas
+    pub fn canvas_size(&self) -> (u32, u32) {
+        (self.bounds.width, self.bounds.height)
+    }
+
+    /// Canvas-local area not covered by any display (the gaps between
+    /// monitors of different sizes), in pixels
+    pub fn uncovered_area(&self) -> u64 {
+        let covered: u64 = self.placements.iter().map(Region::area).sum();
+        self.bounds.area().saturating_sub(covered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_side_by_side_layout() {
+        let layout = StitchLayout::new(&[
+            Region::new(0, 0, 1920, 1080),
+            Region::new(-1280, 0, 1280, 1024),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.canvas_size(), (3200, 1080));
+        assert_eq!(layout.placements[0], Region::new(1280, 0, 1920, 1080));
+        assert_eq!(layout.placements[1], Region::new(0, 0, 1280, 1024));
+        assert_eq!(layout.uncovered_area(), 1280 * 56);
+    }
+
+    #[test]
+    fn test_empty_layout() {
+        assert!(StitchLayout::new(&[]).is_none());
+    }
+
+    #[cfg(feature = "proptest")]
+    mod property_tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Up to six non-overlapping displays arranged on a coarse grid, the
+        /// way real multi-monitor setups are
+        fn display_configuration() -> impl Strategy<Value = Vec<Region>> {
+            prop::collection::btree_set((-3i32..3, -3i32..3), 1..6).prop_flat_map(|cells| {
+                let cells: Vec<_> = cells.into_iter().collect();
+                let sizes = prop::collection::vec((640u32..=4096, 480u32..=4096), cells.len());
+                sizes.prop_map(move |sizes| {
+                    cells
+                        .iter()
+                        .zip(sizes)
+                        .map(|(&(cx, cy), (w, h))| Region::new(cx * 4096, cy * 4096, w, h))
+                        .collect()
+                })
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn test_placements_fit_canvas(displays in display_configuration()) {
+                let layout = StitchLayout::new(&displays).unwrap();
+                let (w, h) = layout.canvas_size();
+                let canvas = Region::new(0, 0, w, h);
+
+                prop_assert_eq!(layout.placements.len(), displays.len());
+                for placement in &layout.placements {
+                    prop_assert!(canvas.contains_region(placement));
+                }
+            }
+
+            #[test]
+            fn test_canvas_is_tight(displays in display_configuration()) {
+                let layout = StitchLayout::new(&displays).unwrap();
+                let (w, h) = layout.canvas_size();
+
+                prop_assert!(layout.placements.iter().any(|p| p.x == 0));
+                prop_assert!(layout.placements.iter().any(|p| p.y == 0));
+                prop_assert!(layout.placements.iter().any(|p| p.right() == w as i64));
+                prop_assert!(layout.placements.iter().any(|p| p.bottom() == h as i64));
+            }
+
+            #[test]
+            fn test_relative_offsets_preserved(displays in display_configuration()) {
+                let layout = StitchLayout::new(&displays).unwrap();
+                let origin = (displays[0].x, displays[0].y);
+                let placed_origin = (layout.placements[0].x, layout.placements[0].y);
+
+                for (display, placement) in displays.iter().zip(&layout.placements) {
+                    prop_assert_eq!(display.x - origin.0, placement.x - placed_origin.0);
+                    prop_assert_eq!(display.y - origin.1, placement.y - placed_origin.1);
+                    prop_assert_eq!((display.width, display.height), (placement.width, placement.height));
+                }
+            }
+
+            #[test]
+            fn test_no_overlap_introduced(displays in display_configuration()) {
+                let layout = StitchLayout::new(&displays).unwrap();
+                for (i, a) in layout.placements.iter().enumerate() {
+                    for b in &layout.placements[i + 1..] {
+                        prop_assert!(a.intersect(b).is_none());
+                    }
+                }
+                prop_assert!(layout.uncovered_area() < layout.bounds.area());
+            }
+        }
+    }
+}
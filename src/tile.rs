@@ -0,0 +1,164 @@
+//! Splitting very large captures into tiles
+//!
+//! Stitched multi-monitor canvases and 8K captures can be too large for some
+//! viewers and upload targets. [`save_tiles`] cuts an image into a grid of at
+//! most `size`×`size` tiles, written next to each other as
+//! `<stem>-<row>-<column>.<ext>`, plus a `<stem>.tiles.json` [`Manifest`]
+//! recording where each tile sits in the full image.
+
+use crate::atomic;
+use crate::geometry::Region;
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// One tile of a [`Manifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    /// File name, relative to the manifest
+    pub file: String,
+    pub row: u32,
+    pub column: u32,
+    /// Offset in the full image
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Describes how a tiled image is reassembled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Size of the full image
+    pub width: u32,
+    pub height: u32,
+    /// Largest tile side; tiles on the right and bottom edges may be smaller
+    pub tile_size: u32,
+    pub rows: u32,
+    pub columns: u32,
+    /// Tiles in row-major order
+    pub tiles: Vec<Tile>,
+}
+
+/// The grid of tiles covering a `width`×`height` image, row major
+pub fn grid(width: u32, height: u32, size: u32) -> Vec<Region> {
+    if size == 0 {
+        return Vec::new();
+    }
+    (0..height)
+        .step_by(size as usize)
+        .flat_map(|y| {
+            (0..width).step_by(size as usize).map(move |x| {
+                Region::new(
+                    x as i32,
+                    y as i32,
+                    size.min(width - x),
+                    size.min(height - y),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Path of the manifest written for `path`
+pub fn manifest_path(path: &Path) -> PathBuf {
+    path.with_extension("tiles.json")
+}
+
+/// Writes `image` as tiles of at most `size`×`size` next to `path`, in the
+/// format given by its extension, and returns the manifest
+///
+/// The manifest is written to [`manifest_path`]; `path` itself is not
+/// created.
+pub fn save_tiles(image: &RgbaImage, path: &Path, size: u32) -> Result<Manifest> {
+    if size == 0 {
+        return Err(Error::invalid("tile size", "0"));
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .ok_or_else(|| Error::invalid("tile path", path.display().to_string()))?;
+    let extension = path
+        .extension()
+        .map_or_else(|| "png".into(), |ext| ext.to_string_lossy().into_owned());
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let (width, height) = image.dimensions();
+    let columns = width.div_ceil(size);
+    let mut tiles = Vec::new();
+    for (index, region) in grid(width, height, size).into_iter().enumerate() {
+        let (row, column) = (index as u32 / columns, index as u32 % columns);
+        let file = format!("{stem}-{row}-{column}.{extension}");
+        let (x, y) = (region.x as u32, region.y as u32);
+        let tile = imageops::crop_imm(image, x, y, region.width, region.height).to_image();
+        atomic::save(&tile, dir.join(&file))?;
+        tiles.push(Tile {
+            file,
+            row,
+            column,
+            x,
+            y,
+            width: region.width,
+            height: region.height,
+        });
+    }
+
+    let manifest = Manifest {
+        width,
+        height,
+        tile_size: size,
+        rows: height.div_ceil(size),
+        columns,
+        tiles,
+    };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| Error::Io(e.into()))?;
+    atomic::write(manifest_path(path), json)?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    #[test]
+    fn test_grid_covers_the_image() {
+        let tiles = grid(5000, 3000, 2048);
+
+        assert_eq!(tiles.len(), 3 * 2);
+        assert_eq!(tiles[0], Region::new(0, 0, 2048, 2048));
+        assert_eq!(tiles[2], Region::new(4096, 0, 904, 2048), "Right edge");
+        assert_eq!(tiles[5], Region::new(4096, 2048, 904, 952));
+        let area: u64 = tiles.iter().map(Region::area).sum();
+        assert_eq!(area, 5000 * 3000);
+
+        assert_eq!(grid(100, 100, 2048), vec![Region::new(0, 0, 100, 100)]);
+        assert!(grid(100, 100, 0).is_empty());
+    }
+
+    #[test]
+    fn test_save_tiles_writes_manifest() {
+        let dir = std::env::temp_dir().join(format!("snap_scale_tiles_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = RgbaImage::from_fn(30, 20, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+
+        let manifest = save_tiles(&image, &dir.join("wall.png"), 16).unwrap();
+
+        assert_eq!((manifest.rows, manifest.columns), (2, 2));
+        let last = &manifest.tiles[3];
+        assert_eq!(last.file, "wall-1-1.png");
+        assert_eq!((last.x, last.y, last.width, last.height), (16, 16, 14, 4));
+
+        let tile = screenshots::image::open(dir.join(&last.file))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(tile.get_pixel(0, 0).0, [16, 16, 0, 255], "Tile content");
+        let json = std::fs::read(dir.join("wall.tiles.json")).unwrap();
+        let read: Manifest = serde_json::from_slice(&json).unwrap();
+        assert_eq!(read, manifest);
+        assert!(!dir.join("wall.png").exists(), "No full-size image");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+//! Giving up on platform calls that never return
+//!
+//! Some captures wait on something outside the process: a desktop portal
+//! dialog nobody answers, or a compositor that stopped responding. [`within`]
+//! runs such a call on a thread of its own and fails with
+//! [`Error::Timeout`] when it takes too long, so the caller gets its thread
+//! back. The call itself can't be cancelled and keeps running until it
+//! returns; its result is then dropped.
+
+use crate::{Error, Result};
+use std::any::Any;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Runs `call`, failing once it has taken longer than `limit`; without a
+/// limit it runs on the calling thread and is waited for however long it
+/// takes
+///
+/// `what` names the call in the error. A call that panics on its thread
+/// fails with [`Error::Panicked`].
+pub fn within<T: Send + 'static>(
+    limit: Option<Duration>,
+    what: &str,
+    call: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let Some(limit) = limit else {
+        return call();
+    };
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let worker = thread::Builder::new()
+        .name(format!("snap_scale {what}"))
+        .spawn(move || {
+            // Nobody listens once the caller gave up
+            let _ = sender.send(call());
+        })?;
+    match receiver.recv_timeout(limit) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(Error::Timeout {
+            what: what.into(),
+            after: limit,
+        }),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::Panicked {
+            what: what.into(),
+            message: worker.join().err().map_or_else(String::new, panic_message),
+        }),
+    }
+}
+
+/// What a panic was raised with, when it was a message
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or_else(String::new, |message| message.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hung_calls_time_out() {
+        let err = within(Some(Duration::from_millis(20)), "capture", || {
+            thread::sleep(Duration::from_secs(5));
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{err}");
+        assert_eq!(err.to_string(), "capture timed out after 0.02s");
+    }
+
+    #[test]
+    fn test_quick_calls_return() {
+        let limit = Some(Duration::from_secs(5));
+        assert_eq!(within(limit, "capture", || Ok(7)).unwrap(), 7);
+        let failed = within(limit, "capture", || -> Result<()> {
+            Err(Error::Unsupported("no display".into()))
+        });
+        assert!(matches!(failed, Err(Error::Unsupported(_))));
+        let panicked = within(limit, "capture", || -> Result<()> { panic!("broken") });
+        let err = panicked.unwrap_err();
+        assert!(matches!(err, Error::Panicked { .. }), "{err}");
+        assert_eq!(err.to_string(), "capture panicked: broken");
+        assert_eq!(within(None, "capture", || Ok("inline")).unwrap(), "inline");
+    }
+}
@@ -0,0 +1,140 @@
+//! Image transforms applied to captures before they are saved
+//!
+//! Each step implements [`Transform`]; a [`Pipeline`] runs them in order. The
+//! CLI builds one pipeline per run from its flags and applies it to every
+//! capture, so what gets written (and uploaded) is the transformed image.
+//!
+//! Captures with more than 8 bits per channel go through
+//! [`Pipeline::apply16`]. Only steps that can keep the extra precision
+//! support it; the rest fail rather than silently dropping to 8 bits.
+
+use crate::{Error, Result};
+use screenshots::image::{ImageBuffer, Rgba, RgbaImage};
+use std::fmt;
+
+/// A 16-bit per channel RGBA image
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// A step that edits or replaces a capture
+pub trait Transform {
+    /// Short name used in messages
+    fn name(&self) -> &str;
+
+    /// Transforms `image`; steps may return an image of a different size
+    fn apply(&self, image: RgbaImage) -> Result<RgbaImage>;
+
+    /// Transforms a 16-bit capture; unsupported unless the step overrides it
+    fn apply16(&self, image: Rgba16Image) -> Result<Rgba16Image> {
+        let _ = image;
+        Err(Error::Unsupported(format!(
+            "{} on 16-bit captures",
+            self.name()
+        )))
+    }
+}
+
+/// An ordered list of transforms
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step
+    pub fn push(&mut self, step: impl Transform + 'static) -> &mut Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Runs every step in order, stopping at the first failure
+    #[tracing::instrument(level = "debug", name = "pipeline", skip_all, fields(steps = self.steps.len()))]
+    pub fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+        self.steps
+            .iter()
+            .try_fold(image, |image, step| step.apply(image))
+    }
+
+    /// Runs every step on a 16-bit capture, stopping at the first failure
+    pub fn apply16(&self, image: Rgba16Image) -> Result<Rgba16Image> {
+        self.steps
+            .iter()
+            .try_fold(image, |image, step| step.apply16(image))
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.steps.iter().map(|step| step.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Grow;
+
+    impl Transform for Grow {
+        fn name(&self) -> &str {
+            "grow"
+        }
+
+        fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+            Ok(RgbaImage::new(image.width() + 1, image.height()))
+        }
+    }
+
+    struct Fail;
+
+    impl Transform for Fail {
+        fn name(&self) -> &str {
+            "fail"
+        }
+
+        fn apply(&self, _image: RgbaImage) -> Result<RgbaImage> {
+            Err(Error::Unsupported("nope".into()))
+        }
+    }
+
+    #[test]
+    fn test_steps_run_in_order() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Grow).push(Grow);
+
+        let image = pipeline.apply(RgbaImage::new(1, 1)).unwrap();
+
+        assert_eq!(image.width(), 3);
+        assert_eq!(format!("{pipeline:?}"), r#"["grow", "grow"]"#);
+    }
+
+    #[test]
+    fn test_failure_stops_the_pipeline() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Fail).push(Grow);
+        assert!(pipeline.apply(RgbaImage::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn test_16_bit_needs_support_from_every_step() {
+        let mut pipeline = Pipeline::new();
+        assert!(pipeline.apply16(Rgba16Image::new(1, 1)).is_ok(), "Empty");
+
+        pipeline.push(Grow);
+        let err = pipeline.apply16(Rgba16Image::new(1, 1)).unwrap_err();
+        assert!(err.to_string().contains("grow"), "{err}");
+    }
+}
@@ -0,0 +1,518 @@
+//! System tray icon for the daemon on Linux
+//!
+//! `snap_scale daemon --tray` also shows an icon in the system tray: a
+//! StatusNotifierItem on the session bus, which KDE, XFCE, Cinnamon, and
+//! GNOME with the AppIndicator extension display, with its menu exported
+//! through `com.canonical.dbusmenu`:
+//!
+//! - a capture of each display; clicking the icon itself captures the first
+//! - the last [`RECENT`] captures the daemon saved, opened with the default
+//!   application
+//! - the config file, which the daemon reads at startup, to edit its rules
+//!   and schedules
+//! - quitting the daemon
+//!
+//! Captures become [`Job`]s for the daemon's own loop, as socket commands do,
+//! so they run one at a time through the same pipeline, hooks and catalog.
+
+use crate::control::{Job, Target};
+use crate::{Error, Result};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use zbus::fdo;
+use zbus::zvariant::{ObjectPath, OwnedValue, Type, Value};
+
+/// How many captures the menu lists
+pub const RECENT: usize = 5;
+
+/// Object path of the StatusNotifierItem
+pub const ITEM_PATH: &str = "/StatusNotifierItem";
+
+/// Object path of its menu
+pub const MENU_PATH: &str = "/MenuBar";
+
+/// Where trays register their items
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+
+/// Captures saved since the tray came up, newest first
+type Recent = Arc<Mutex<VecDeque<PathBuf>>>;
+
+/// An entry of the menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Display(usize),
+    Recents,
+    Recent(usize),
+    Settings,
+    Quit,
+}
+
+impl Entry {
+    /// Its dbusmenu id; 0 is the root
+    fn id(self) -> i32 {
+        match self {
+            Self::Display(index) => 1 + index as i32,
+            Self::Recents => 1000,
+            Self::Recent(index) => 1001 + index as i32,
+            Self::Settings => 2000,
+            Self::Quit => 2001,
+        }
+    }
+
+    fn from_id(id: i32) -> Option<Self> {
+        match id {
+            1..=999 => Some(Self::Display(id as usize - 1)),
+            1000 => Some(Self::Recents),
+            1001..=1999 => Some(Self::Recent(id as usize - 1001)),
+            2000 => Some(Self::Settings),
+            2001 => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// A menu item and the ones under it, as `GetLayout` returns them
+#[derive(Debug, Serialize, Type, Value)]
+#[zvariant(crate = "zbus::zvariant")]
+struct Layout {
+    id: i32,
+    properties: HashMap<String, OwnedValue>,
+    /// More [`Layout`]s, each in a variant
+    children: Vec<OwnedValue>,
+}
+
+/// A menu item before its properties become variants
+struct Node {
+    id: i32,
+    properties: Vec<(&'static str, Value<'static>)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn item(entry: Entry, label: impl Into<String>, enabled: bool) -> Self {
+        Self {
+            id: entry.id(),
+            properties: vec![
+                ("label", Value::from(label.into())),
+                ("enabled", Value::from(enabled)),
+            ],
+            children: Vec::new(),
+        }
+    }
+
+    fn separator(id: i32) -> Self {
+        Self {
+            id,
+            properties: vec![("type", Value::from("separator"))],
+            children: Vec::new(),
+        }
+    }
+
+    fn find(&self, id: i32) -> Option<&Node> {
+        match self.id == id {
+            true => Some(self),
+            false => self.children.iter().find_map(|child| child.find(id)),
+        }
+    }
+
+    /// The properties among `names`, or all of them when it's empty
+    fn properties(&self, names: &[String]) -> HashMap<String, OwnedValue> {
+        self.properties
+            .iter()
+            .filter(|(name, _)| names.is_empty() || names.iter().any(|n| n == name))
+            .filter_map(|(name, value)| {
+                let value = OwnedValue::try_from(value.try_clone().ok()?).ok()?;
+                Some((name.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// The layout `depth` levels down, or all the way for -1
+    fn layout(&self, depth: i32, names: &[String]) -> Layout {
+        let children = match depth {
+            0 => Vec::new(),
+            _ => self
+                .children
+                .iter()
+                .filter_map(|child| {
+                    let layout = child.layout(depth - 1, names);
+                    OwnedValue::try_from(Value::from(layout)).ok()
+                })
+                .collect(),
+        };
+        Layout {
+            id: self.id,
+            properties: self.properties(names),
+            children,
+        }
+    }
+}
+
+/// The `com.canonical.dbusmenu` object
+struct Menu {
+    displays: Vec<String>,
+    recent: Recent,
+    revision: Arc<AtomicU32>,
+    jobs: Sender<Job>,
+    config: Option<PathBuf>,
+}
+
+impl Menu {
+    fn tree(&self) -> Node {
+        let recent = self.recent.lock().unwrap();
+        let mut recents = Node::item(Entry::Recents, "Recent captures", !recent.is_empty());
+        recents.children = recent
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                Node::item(Entry::Recent(index), name.to_string_lossy(), true)
+            })
+            .collect();
+        recents
+            .properties
+            .push(("children-display", Value::from("submenu")));
+        let editable = self.config.as_deref().is_some_and(Path::is_file);
+
+        let mut children: Vec<_> = self
+            .displays
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Node::item(Entry::Display(index), format!("Capture {name}"), true))
+            .collect();
+        children.push(Node::separator(3000));
+        children.push(recents);
+        children.push(Node::item(Entry::Settings, "Edit settings", editable));
+        children.push(Node::separator(3001));
+        children.push(Node::item(Entry::Quit, "Quit", true));
+        Node {
+            id: 0,
+            properties: vec![("children-display", Value::from("submenu"))],
+            children,
+        }
+    }
+
+    fn clicked(&self, entry: Entry) {
+        match entry {
+            Entry::Display(index) => capture(&self.jobs, index),
+            Entry::Recent(index) => {
+                let path = self.recent.lock().unwrap().get(index).cloned();
+                if let Some(Err(e)) = path.map(|path| crate::notify::open_path(&path)) {
+                    tracing::warn!("can't open the capture: {e}");
+                }
+            }
+            Entry::Settings => {
+                if let Some(Err(e)) = self.config.as_deref().map(crate::notify::open_path) {
+                    tracing::warn!("can't open the config file: {e}");
+                }
+            }
+            Entry::Quit => std::process::exit(0),
+            Entry::Recents => {}
+        }
+    }
+}
+
+/// Asks the daemon for a capture of display `index`; it reports the outcome
+fn capture(jobs: &Sender<Job>, index: usize) {
+    let (job, _) = Job::new(Target::Display(index), None);
+    if jobs.send(job).is_err() {
+        tracing::warn!("the capture loop has stopped");
+    }
+}
+
+#[zbus::interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: i32,
+        property_names: Vec<String>,
+    ) -> fdo::Result<(u32, Layout)> {
+        let tree = self.tree();
+        let parent = tree
+            .find(parent_id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no menu item {parent_id}")))?;
+        let revision = self.revision.load(Ordering::Relaxed);
+        Ok((revision, parent.layout(recursion_depth, &property_names)))
+    }
+
+    fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        let tree = self.tree();
+        ids.into_iter()
+            .filter_map(|id| Some((id, tree.find(id)?.properties(&property_names))))
+            .collect()
+    }
+
+    fn get_property(&self, id: i32, name: String) -> fdo::Result<OwnedValue> {
+        let tree = self.tree();
+        let node = tree
+            .find(id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no menu item {id}")))?;
+        node.properties(std::slice::from_ref(&name))
+            .remove(&name)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no property {name}")))
+    }
+
+    fn event(&self, id: i32, event_id: String, _data: OwnedValue, _timestamp: u32) {
+        match Entry::from_id(id) {
+            Some(entry) if event_id == "clicked" => self.clicked(entry),
+            _ => {}
+        }
+    }
+
+    /// Handles each event, returning the ids that name no item
+    fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        let mut unknown = Vec::new();
+        for (id, event_id, data, timestamp) in events {
+            match Entry::from_id(id) {
+                Some(_) => self.event(id, event_id, data, timestamp),
+                None => unknown.push(id),
+            }
+        }
+        unknown
+    }
+
+    /// Never needs a fresh layout: it's sent whenever it changes
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The `org.kde.StatusNotifierItem` object
+struct Item {
+    jobs: Sender<Job>,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    /// Captures the first display
+    fn activate(&self, _x: i32, _y: i32) {
+        capture(&self.jobs, 0);
+    }
+
+    fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    fn context_menu(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: String) {}
+
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "snap_scale"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> &str {
+        "snap_scale"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "Active"
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        "camera-photo"
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        ObjectPath::from_static_str_unchecked(MENU_PATH)
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+}
+
+/// The icon, for as long as it's kept
+pub struct Tray {
+    connection: zbus::blocking::Connection,
+    recent: Recent,
+    revision: Arc<AtomicU32>,
+}
+
+impl Tray {
+    /// Lists `path` first among the recent captures
+    pub fn saved(&self, path: &Path) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|listed| listed != path);
+        recent.push_front(path.to_owned());
+        recent.truncate(RECENT);
+        drop(recent);
+        let revision = self.revision.fetch_add(1, Ordering::Relaxed) + 1;
+        let updated = self.connection.emit_signal(
+            None::<()>,
+            MENU_PATH,
+            "com.canonical.dbusmenu",
+            "LayoutUpdated",
+            &(revision, 0i32),
+        );
+        if let Err(e) = updated {
+            tracing::debug!("tray menu not updated: {e}");
+        }
+    }
+}
+
+/// Shows the icon, with a menu capturing `displays` by their names in
+/// enumeration order; captures arrive on `jobs`, and `config` is the file
+/// the settings entry opens
+pub fn serve(displays: Vec<String>, jobs: Sender<Job>, config: Option<PathBuf>) -> Result<Tray> {
+    let recent = Recent::default();
+    let revision = Arc::new(AtomicU32::new(1));
+    let menu = Menu {
+        displays,
+        recent: Arc::clone(&recent),
+        revision: Arc::clone(&revision),
+        jobs: jobs.clone(),
+        config,
+    };
+    let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+    let unsupported = |e: zbus::Error| Error::Unsupported(format!("system tray: {e}"));
+    let connection = zbus::blocking::connection::Builder::session()
+        .and_then(|builder| builder.name(name.as_str()))
+        .and_then(|builder| builder.serve_at(ITEM_PATH, Item { jobs }))
+        .and_then(|builder| builder.serve_at(MENU_PATH, menu))
+        .and_then(|builder| builder.build())
+        .map_err(unsupported)?;
+    connection
+        .call_method(
+            Some(WATCHER),
+            "/StatusNotifierWatcher",
+            Some(WATCHER),
+            "RegisterStatusNotifierItem",
+            &(name.as_str(),),
+        )
+        .map_err(unsupported)?;
+    Ok(Tray {
+        connection,
+        recent,
+        revision,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn menu(displays: &[&str]) -> (Menu, mpsc::Receiver<Job>) {
+        let (jobs, receiver) = mpsc::channel();
+        let menu = Menu {
+            displays: displays.iter().map(|name| name.to_string()).collect(),
+            recent: Recent::default(),
+            revision: Arc::new(AtomicU32::new(1)),
+            jobs,
+            config: None,
+        };
+        (menu, receiver)
+    }
+
+    fn label(layout: &Layout) -> String {
+        let label = layout
+            .properties
+            .get("label")
+            .and_then(|label| label.downcast_ref::<String>().ok());
+        label.unwrap_or_default()
+    }
+
+    #[test]
+    fn test_entry_ids_round_trip() {
+        for entry in [
+            Entry::Display(0),
+            Entry::Display(7),
+            Entry::Recents,
+            Entry::Recent(4),
+            Entry::Settings,
+            Entry::Quit,
+        ] {
+            assert_eq!(Entry::from_id(entry.id()), Some(entry));
+        }
+        assert_eq!(Entry::from_id(0), None, "The root isn't clickable");
+        assert_eq!(Entry::from_id(3000), None, "Nor are separators");
+    }
+
+    #[test]
+    fn test_layout_lists_displays_and_recent_captures() {
+        let (menu, _jobs) = menu(&["DP-1", "HDMI-1"]);
+        menu.recent
+            .lock()
+            .unwrap()
+            .push_front("target/1-20261014.png".into());
+
+        let (_, root) = menu.get_layout(0, -1, Vec::new()).unwrap();
+        let children: Vec<Layout> = root
+            .children
+            .into_iter()
+            .map(|child| Layout::try_from(Value::from(child)).unwrap())
+            .collect();
+        assert_eq!(label(&children[0]), "Capture DP-1");
+        assert_eq!(label(&children[1]), "Capture HDMI-1");
+        assert_eq!(children[3].id, Entry::Recents.id());
+        assert_eq!(children[3].children.len(), 1);
+
+        let (_, recents) = menu.get_layout(Entry::Recents.id(), 0, Vec::new()).unwrap();
+        assert!(recents.children.is_empty(), "Depth 0 has no children");
+        assert!(menu.get_layout(42, -1, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_clicks_ask_for_captures() {
+        let (menu, jobs) = menu(&["DP-1", "HDMI-1"]);
+        menu.event(
+            Entry::Display(1).id(),
+            "clicked".into(),
+            OwnedValue::from(0i32),
+            0,
+        );
+        menu.event(
+            Entry::Display(0).id(),
+            "hovered".into(),
+            OwnedValue::from(0i32),
+            0,
+        );
+
+        let job = jobs.try_recv().unwrap();
+        assert_eq!((job.target, job.path), (Target::Display(1), None));
+        assert!(jobs.try_recv().is_err(), "Only clicks capture");
+    }
+}
@@ -0,0 +1,173 @@
+//! Cropping uniform margins
+//!
+//! [`Trim`] removes border rows and columns that match the top-left pixel,
+//! such as the desktop background around a centered window. The tolerance is
+//! the largest per-channel difference, alpha included, still counted as the
+//! border color, so dithered or slightly noisy backgrounds trim too.
+
+use crate::geometry::Region;
+use crate::transform::{Rgba16Image, Transform};
+use crate::Result;
+use screenshots::image::{imageops, DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage};
+
+/// Default per-channel tolerance
+pub const DEFAULT_TOLERANCE: u8 = 8;
+
+/// Crops uniform-color margins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    pub tolerance: u8,
+}
+
+impl Default for Trim {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE)
+    }
+}
+
+impl Trim {
+    pub fn new(tolerance: u8) -> Self {
+        Self { tolerance }
+    }
+
+    /// The part of `image` left after trimming, or `None` when the whole
+    /// image is the border color
+    pub fn bounds(&self, image: &RgbaImage) -> Option<Region> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let border = *image.get_pixel(0, 0);
+        let matches = |pixel: &Rgba<u8>| {
+            pixel
+                .0
+                .iter()
+                .zip(border.0)
+                .all(|(&a, b)| a.abs_diff(b) <= self.tolerance)
+        };
+        let row = |y: u32| (0..width).all(|x| matches(image.get_pixel(x, y)));
+        let column =
+            |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| matches(image.get_pixel(x, y)));
+
+        let top = (0..height).find(|&y| !row(y))?;
+        let bottom = (top..height).rfind(|&y| !row(y))? + 1;
+        let left = (0..width).find(|&x| !column(x, top, bottom))?;
+        let right = (left..width).rfind(|&x| !column(x, top, bottom))? + 1;
+        Some(Region::new(
+            left as i32,
+            top as i32,
+            right - left,
+            bottom - top,
+        ))
+    }
+}
+
+impl Transform for Trim {
+    fn name(&self) -> &str {
+        "trim"
+    }
+
+    /// Leaves uniform images untouched rather than cropping them to nothing
+    fn apply(&self, image: RgbaImage) -> Result<RgbaImage> {
+        let bounds = self.bounds(&image);
+        Ok(crop_to(image, bounds))
+    }
+
+    /// Finds the border at 8 bits, so the tolerance means the same
+    fn apply16(&self, image: Rgba16Image) -> Result<Rgba16Image> {
+        let bounds = self.bounds(&DynamicImage::ImageRgba16(image.clone()).into_rgba8());
+        Ok(crop_to(image, bounds))
+    }
+}
+
+fn crop_to<P>(
+    image: ImageBuffer<P, Vec<P::Subpixel>>,
+    bounds: Option<Region>,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+{
+    match bounds {
+        Some(region) if (region.width, region.height) != image.dimensions() => imageops::crop_imm(
+            &image,
+            region.x as u32,
+            region.y as u32,
+            region.width,
+            region.height,
+        )
+        .to_image(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESKTOP: Rgba<u8> = Rgba([30, 60, 90, 255]);
+
+    fn centered_window() -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(100, 80, DESKTOP);
+        for y in 20..50 {
+            for x in 10..70 {
+                image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_trims_to_the_window() {
+        let trim = Trim::new(0);
+        assert_eq!(
+            trim.bounds(&centered_window()),
+            Some(Region::new(10, 20, 60, 30))
+        );
+
+        let out = trim.apply(centered_window()).unwrap();
+        assert_eq!(out.dimensions(), (60, 30));
+        assert!(out.pixels().all(|p| p.0 == [255; 4]), "Only the window");
+    }
+
+    #[test]
+    fn test_tolerance_absorbs_noise() {
+        let mut image = centered_window();
+        image.put_pixel(95, 5, Rgba([34, 57, 90, 255]));
+
+        assert_eq!(
+            Trim::new(0).bounds(&image),
+            Some(Region::new(10, 5, 86, 45)),
+            "Exact match keeps the speck"
+        );
+        assert_eq!(
+            Trim::new(4).bounds(&image),
+            Some(Region::new(10, 20, 60, 30))
+        );
+    }
+
+    #[test]
+    fn test_uniform_image_is_unchanged() {
+        let image = RgbaImage::from_pixel(8, 8, DESKTOP);
+
+        assert_eq!(Trim::default().bounds(&image), None);
+        assert_eq!(Trim::default().apply(image.clone()).unwrap(), image);
+    }
+
+    #[test]
+    fn test_sides_trim_independently() {
+        let mut image = centered_window();
+        image.put_pixel(99, 79, Rgba([0, 0, 0, 255]));
+
+        let out = Trim::new(0).apply(image).unwrap();
+        assert_eq!(out.dimensions(), (90, 60), "Trims top and left only");
+        assert_eq!(Trim::new(0).bounds(&RgbaImage::new(0, 0)), None);
+    }
+
+    #[test]
+    fn test_trims_16_bit_captures() {
+        let image = DynamicImage::ImageRgba8(centered_window()).into_rgba16();
+        let out = Trim::default().apply16(image).unwrap();
+        assert_eq!(out.dimensions(), (60, 30));
+        assert_eq!(out.get_pixel(0, 0).0, [65535; 4]);
+    }
+}
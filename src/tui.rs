@@ -0,0 +1,524 @@
+//! Terminal front end
+//!
+//! `snap_scale tui` lists the displays next to a preview of the selected
+//! one, captured again every [`PREVIEW_INTERVAL`], and the most recent
+//! captures below them; keys start captures. [`App`] holds what's on screen
+//! and turns keys into [`Action`]s, while a [`Host`] enumerates, previews and
+//! captures, so the screen is drawn and tested on a ratatui `TestBackend`
+//! without a display.
+//!
+//! | Key | Does |
+//! |---|---|
+//! | `↑` `↓` or `k` `j` | select a display |
+//! | `Enter` or `c` | capture the selected display |
+//! | `a` | capture every display |
+//! | `r` | capture the test area of the selected display |
+//! | `m` | capture an area around the mouse cursor |
+//! | `F5` | enumerate the displays again |
+//! | `q`, `Esc` or `Ctrl-C` | quit |
+
+use crate::metadata::DisplayDescriptor;
+use crate::{Error, Result};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, StatefulWidget, Widget};
+use ratatui::Frame;
+use screenshots::image::RgbaImage;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How often the preview of the selected display is captured again
+pub const PREVIEW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most captures listed under the displays
+pub const RECENT: usize = 20;
+
+/// What a capture key captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// All of the selected display
+    Display,
+    /// Every display, to a file each
+    All,
+    /// The test area of the selected display, as `capture` takes next to
+    /// each display
+    Area,
+    /// An area around the mouse cursor, on whichever display it's on
+    Cursor,
+}
+
+/// What a key press asks of the [`Host`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Capture(Mode),
+    /// Enumerate the displays and read the recent captures again
+    Refresh,
+    Quit,
+}
+
+/// A capture listed as recent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recent {
+    pub path: PathBuf,
+    pub display: String,
+    /// When it was captured, as the host formats it
+    pub time: String,
+}
+
+/// Where the front end gets displays, previews and captures from
+pub trait Host {
+    /// Displays in index order
+    fn displays(&self) -> Result<Vec<DisplayDescriptor>>;
+
+    /// Display `index` shrunk to `scale` of its physical size
+    fn preview(&self, index: usize, scale: f32) -> Result<RgbaImage>;
+
+    /// Up to `limit` captures, newest first
+    fn recent(&self, limit: usize) -> Result<Vec<Recent>>;
+
+    /// Captures `mode` with display `index` selected, returning the saved
+    /// paths
+    fn capture(&self, mode: Mode, index: usize) -> std::result::Result<Vec<PathBuf>, String>;
+}
+
+/// What's on screen
+#[derive(Debug, Default)]
+pub struct App {
+    displays: Vec<DisplayDescriptor>,
+    selected: usize,
+    preview: Option<RgbaImage>,
+    previewed: Option<Instant>,
+    /// Cells the preview was last drawn in, which its scale is fitted to
+    preview_area: Rect,
+    recent: Vec<Recent>,
+    /// The outcome of the last action
+    status: String,
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn displays(&self) -> &[DisplayDescriptor] {
+        &self.displays
+    }
+
+    /// Index of the selected display
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn recent(&self) -> &[Recent] {
+        &self.recent
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Enumerates the displays and reads the recent captures, keeping the
+    /// selection where it can; failures show in the status line
+    pub fn load(&mut self, host: &dyn Host) {
+        match host.displays() {
+            Ok(displays) => {
+                self.selected = self.selected.min(displays.len().saturating_sub(1));
+                self.displays = displays;
+            }
+            Err(e) => self.status = format!("listing displays failed: {e}"),
+        }
+        self.load_recent(host);
+        self.previewed = None;
+    }
+
+    fn load_recent(&mut self, host: &dyn Host) {
+        match host.recent(RECENT) {
+            Ok(recent) => self.recent = recent,
+            Err(e) => self.status = format!("reading recent captures failed: {e}"),
+        }
+    }
+
+    /// Moves the selection or maps `key` to an action
+    pub fn key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => Some(Action::Quit),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.select(self.selected.saturating_sub(1));
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.select(self.selected + 1);
+                None
+            }
+            KeyCode::Enter | KeyCode::Char('c') => Some(Action::Capture(Mode::Display)),
+            KeyCode::Char('a') => Some(Action::Capture(Mode::All)),
+            KeyCode::Char('r') => Some(Action::Capture(Mode::Area)),
+            KeyCode::Char('m') => Some(Action::Capture(Mode::Cursor)),
+            KeyCode::F(5) => Some(Action::Refresh),
+            _ => None,
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        let index = index.min(self.displays.len().saturating_sub(1));
+        if index != self.selected {
+            self.selected = index;
+            self.preview = None;
+            self.previewed = None;
+        }
+    }
+
+    /// Does `action`, returning false once it's time to quit
+    pub fn perform(&mut self, host: &dyn Host, action: Action) -> bool {
+        match action {
+            Action::Quit => return false,
+            Action::Refresh => {
+                self.load(host);
+                self.status = format!("found {} displays", self.displays.len());
+            }
+            Action::Capture(mode) => {
+                self.status = match host.capture(mode, self.selected) {
+                    Ok(paths) => match paths.as_slice() {
+                        [path] => format!("saved {}", path.display()),
+                        paths => format!("saved {} captures", paths.len()),
+                    },
+                    Err(e) => format!("capture failed: {e}"),
+                };
+                self.load_recent(host);
+            }
+        }
+        true
+    }
+
+    /// Whether the preview is older than [`PREVIEW_INTERVAL`], or missing
+    pub fn preview_due(&self) -> bool {
+        self.previewed
+            .is_none_or(|previewed| previewed.elapsed() >= PREVIEW_INTERVAL)
+    }
+
+    /// Captures the selected display again, at the scale that fills the
+    /// preview's cells
+    pub fn refresh_preview(&mut self, host: &dyn Host) {
+        self.previewed = Some(Instant::now());
+        let Some(display) = self.displays.get(self.selected) else {
+            return;
+        };
+        let area = self.preview_area;
+        let scale = fit_scale(display, area.width, area.height * 2);
+        match host.preview(self.selected, scale) {
+            Ok(image) => self.preview = Some(image),
+            Err(e) => {
+                self.preview = None;
+                self.status = format!("preview failed: {e}");
+            }
+        }
+    }
+
+    /// Draws the displays and preview side by side, the recent captures
+    /// under them and the keys and status at the bottom
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [top, recent, status] = Layout::vertical([
+            Constraint::Percentage(60),
+            Constraint::Min(3),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+        let [displays, preview] =
+            Layout::horizontal([Constraint::Length(40), Constraint::Min(10)]).areas(top);
+
+        let items: Vec<ListItem> = self.displays.iter().enumerate().map(describe).collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(" Displays "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(list, displays, frame.buffer_mut(), &mut state);
+
+        let block = Block::bordered().title(" Preview ");
+        self.preview_area = block.inner(preview);
+        frame.render_widget(block, preview);
+        if let Some(image) = &self.preview {
+            frame.render_widget(Preview(image), self.preview_area);
+        }
+
+        let items: Vec<ListItem> = self
+            .recent
+            .iter()
+            .map(|recent| {
+                ListItem::new(format!(
+                    "{}  {}  {}",
+                    recent.time,
+                    recent.display,
+                    recent.path.display()
+                ))
+            })
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Recent captures ")),
+            recent,
+        );
+
+        let keys = "↑↓ select  ⏎ capture  a all  r area  m at mouse  F5 refresh  q quit";
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(self.status.as_str()),
+                Line::styled(keys, Style::new().fg(Color::DarkGray)),
+            ]),
+            status,
+        );
+    }
+}
+
+/// `0  HDMI-1  1920x1080 at 2x  primary`
+fn describe((index, display): (usize, &DisplayDescriptor)) -> ListItem<'static> {
+    let mut line = format!("{index}  ");
+    if !display.name.is_empty() {
+        line += &format!("{}  ", display.name);
+    }
+    line += &format!(
+        "{}x{} at {}x",
+        display.width, display.height, display.scale_factor
+    );
+    if display.is_primary {
+        line += "  primary";
+    }
+    ListItem::new(line)
+}
+
+/// The preview scale that fits `display`'s physical pixels into `width`x
+/// `height`, never enlarging
+pub fn fit_scale(display: &DisplayDescriptor, width: u16, height: u16) -> f32 {
+    let physical = |logical: u32| (logical as f32 * display.scale_factor).max(1.0);
+    let scale = (width.max(1) as f32 / physical(display.width))
+        .min(height.max(1) as f32 / physical(display.height));
+    scale.clamp(f32::MIN_POSITIVE, 1.0)
+}
+
+/// An image drawn with half blocks, two pixels to a cell, shrunk to fit and
+/// centered
+struct Preview<'a>(&'a RgbaImage);
+
+impl Widget for Preview<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let image = self.0;
+        if image.width() == 0 || image.height() == 0 || area.is_empty() {
+            return;
+        }
+        let (width, height) = (area.width as u32, area.height as u32 * 2);
+        let scale = (width as f64 / image.width() as f64)
+            .min(height as f64 / image.height() as f64)
+            .min(1.0);
+        let columns = ((image.width() as f64 * scale) as u32).clamp(1, width);
+        let rows = ((image.height() as f64 * scale) as u32).clamp(1, height);
+        let left = area.x + ((width - columns) / 2) as u16;
+        let top = area.y + ((height - rows) / 4) as u16;
+        let pixel = |x: u32, y: u32| {
+            let p = image.get_pixel(
+                (x as u64 * image.width() as u64 / columns as u64) as u32,
+                (y as u64 * image.height() as u64 / rows as u64) as u32,
+            );
+            Color::Rgb(p[0], p[1], p[2])
+        };
+        for y in (0..rows).step_by(2) {
+            for x in 0..columns {
+                let cell = &mut buf[(left + x as u16, top + (y / 2) as u16)];
+                cell.set_symbol("▀").set_fg(pixel(x, y));
+                if y + 1 < rows {
+                    cell.set_bg(pixel(x, y + 1));
+                }
+            }
+        }
+    }
+}
+
+/// Runs the front end on this terminal until quit
+pub fn run(host: &dyn Host) -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        return Err(Error::Unsupported("the TUI needs a terminal".into()));
+    }
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, host);
+    ratatui::try_restore()?;
+    result
+}
+
+fn event_loop(terminal: &mut ratatui::DefaultTerminal, host: &dyn Host) -> Result<()> {
+    let mut app = App::new();
+    app.load(host);
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        // Drawn once first, for the size of the preview
+        if app.preview_due() {
+            app.refresh_preview(host);
+            continue;
+        }
+        let wait = app.previewed.map_or(Duration::ZERO, |previewed| {
+            PREVIEW_INTERVAL.saturating_sub(previewed.elapsed())
+        });
+        if !event::poll(wait)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if let Some(action) = app.key(key) {
+            if !app.perform(host, action) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::CaptureBackend;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::cell::RefCell;
+
+    /// Two mock displays; every capture is recorded as recent
+    struct MockHost {
+        backend: MockBackend,
+        captures: RefCell<Vec<Recent>>,
+    }
+
+    impl MockHost {
+        fn new() -> Self {
+            Self {
+                backend: "64x48,32x24@2".parse().unwrap(),
+                captures: RefCell::default(),
+            }
+        }
+    }
+
+    impl Host for MockHost {
+        fn displays(&self) -> Result<Vec<DisplayDescriptor>> {
+            self.backend.enumerate()
+        }
+
+        fn preview(&self, index: usize, scale: f32) -> Result<RgbaImage> {
+            self.backend
+                .capture_preview(&self.displays()?[index], scale)
+        }
+
+        fn recent(&self, limit: usize) -> Result<Vec<Recent>> {
+            Ok(self
+                .captures
+                .borrow()
+                .iter()
+                .rev()
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+
+        fn capture(&self, mode: Mode, index: usize) -> std::result::Result<Vec<PathBuf>, String> {
+            let indexes = match mode {
+                Mode::All => vec![0, 1],
+                Mode::Cursor => return Err("no cursor".into()),
+                _ => vec![index],
+            };
+            let paths: Vec<_> = indexes
+                .into_iter()
+                .map(|i| PathBuf::from(format!("target/{i}.png")))
+                .collect();
+            for path in &paths {
+                self.captures.borrow_mut().push(Recent {
+                    path: path.clone(),
+                    display: index.to_string(),
+                    time: "12:00:00".into(),
+                });
+            }
+            Ok(paths)
+        }
+    }
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_keys_select_and_capture() {
+        let host = MockHost::new();
+        let mut app = App::new();
+        app.load(&host);
+        assert_eq!(app.displays().len(), 2);
+
+        assert_eq!(app.key(press(KeyCode::Down)), None);
+        assert_eq!(app.key(press(KeyCode::Down)), None);
+        assert_eq!(app.selected(), 1, "the selection stops at the last display");
+        assert_eq!(app.key(press(KeyCode::Char('k'))), None);
+        assert_eq!(app.selected(), 0);
+
+        let capture = app.key(press(KeyCode::Enter)).unwrap();
+        assert_eq!(capture, Action::Capture(Mode::Display));
+        assert!(app.perform(&host, capture));
+        assert_eq!(app.status(), "saved target/0.png");
+        assert!(app.perform(&host, Action::Capture(Mode::All)));
+        assert_eq!(app.status(), "saved 2 captures");
+        assert_eq!(app.recent().len(), 3);
+        assert!(app.perform(&host, Action::Capture(Mode::Cursor)));
+        assert_eq!(app.status(), "capture failed: no cursor");
+
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(app.key(ctrl_c), Some(Action::Quit));
+        assert!(!app.perform(&host, Action::Quit));
+    }
+
+    #[test]
+    fn test_draws_displays_preview_and_recent_captures() {
+        let host = MockHost::new();
+        let mut app = App::new();
+        app.load(&host);
+        app.perform(&host, Action::Capture(Mode::Display));
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        assert!(app.preview_due());
+        app.refresh_preview(&host);
+        assert!(!app.preview_due());
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+
+        let screen = screen(&terminal);
+        assert!(
+            screen.contains("0  MOCK-1  64x48 at 1x  primary"),
+            "{screen}"
+        );
+        assert!(screen.contains("1  MOCK-2  32x24 at 2x"));
+        assert!(screen.contains("12:00:00  0  target/0.png"));
+        assert!(screen.contains("saved target/0.png"));
+        assert!(screen.contains('▀'), "the preview is drawn");
+    }
+
+    #[test]
+    fn test_fit_scale() {
+        let display = DisplayDescriptor {
+            width: 1920,
+            height: 1080,
+            scale_factor: 2.0,
+            ..MockBackend::single(1, 1).enumerate().unwrap()[0].clone()
+        };
+        assert_eq!(fit_scale(&display, 96, 108), 0.025);
+        assert_eq!(fit_scale(&display, 10_000, 10_000), 1.0);
+        assert!(fit_scale(&display, 0, 0) > 0.0);
+    }
+}
@@ -0,0 +1,497 @@
+//! Uploading saved captures to image hosts
+//!
+//! Every destination implements [`Uploader`]; [`uploader_for`] turns a
+//! command-line spec such as `imgur` or `https://host/hook` into the matching
+//! implementation. The HTTP-based providers need the `upload` feature; SFTP
+//! uses the system `sftp` client and is always available.
+
+#[cfg(feature = "upload")]
+mod chat;
+mod sftp;
+
+#[cfg(feature = "upload")]
+pub use chat::{DiscordUploader, SlackUploader};
+pub use sftp::{SftpTarget, SftpUploader};
+
+#[cfg(feature = "upload")]
+use crate::config::HttpBodyMode;
+use crate::config::{HttpUploadConfig, UploadConfig};
+use crate::{Error, Result};
+use std::path::Path;
+
+/// Where an upload ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadResult {
+    /// Shareable URL of the uploaded image
+    pub url: String,
+    /// URL or token that deletes the upload, when the host provides one
+    pub delete_handle: Option<String>,
+}
+
+/// An upload destination
+pub trait Uploader {
+    /// Short provider name used in messages
+    fn name(&self) -> &str;
+
+    /// Uploads encoded image bytes
+    fn upload(&self, data: &[u8], filename: &str, mime_type: &str) -> Result<UploadResult>;
+
+    /// Uploads a file from disk, guessing the MIME type from its extension
+    fn upload_file(&self, path: &Path) -> Result<UploadResult> {
+        let data = std::fs::read(path)?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "capture".into());
+        let mime_type = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(crate::OutputFormat::from_extension)
+            .map_or("application/octet-stream", |format| format.mime_type());
+        self.upload(&data, &filename, mime_type)
+    }
+}
+
+/// Resolves an upload spec into an uploader
+///
+/// * `imgur` - anonymous Imgur upload
+/// * `http://...` / `https://...` - POST to a webhook, see [`HttpUploader`]
+/// * `sftp://[user@]host[:port]/path` - copy over SFTP, see [`SftpUploader`]
+/// * `discord` / `slack` - post to the channel set up in `[upload.discord]` /
+///   `[upload.slack]`
+pub fn uploader_for(spec: &str, config: &UploadConfig) -> Result<Box<dyn Uploader>> {
+    match spec {
+        "imgur" => imgur(config),
+        "discord" | "slack" => chat(spec, config),
+        url if url.starts_with("http://") || url.starts_with("https://") => http(url, &config.http),
+        url if url.starts_with("sftp://") => Ok(Box::new(SftpUploader::new(
+            SftpTarget::parse(url)?,
+            config.sftp.clone(),
+        ))),
+        _ => Err(Error::invalid("upload target", spec)),
+    }
+}
+
+#[cfg(feature = "upload")]
+fn http(url: &str, config: &HttpUploadConfig) -> Result<Box<dyn Uploader>> {
+    Ok(Box::new(HttpUploader::from_config(url, config)))
+}
+
+#[cfg(not(feature = "upload"))]
+fn http(_url: &str, _config: &HttpUploadConfig) -> Result<Box<dyn Uploader>> {
+    Err(Error::Unsupported(
+        "HTTP uploads require the `upload` feature".into(),
+    ))
+}
+
+#[cfg(feature = "upload")]
+fn imgur(config: &UploadConfig) -> Result<Box<dyn Uploader>> {
+    let client_id = config
+        .imgur_client_id
+        .clone()
+        .or_else(|| std::env::var("IMGUR_CLIENT_ID").ok())
+        .ok_or_else(|| {
+            Error::Config("imgur uploads need upload.imgur_client_id or $IMGUR_CLIENT_ID".into())
+        })?;
+    Ok(Box::new(ImgurUploader::new(client_id)))
+}
+
+#[cfg(not(feature = "upload"))]
+fn imgur(_config: &UploadConfig) -> Result<Box<dyn Uploader>> {
+    Err(Error::Unsupported(
+        "imgur uploads require the `upload` feature".into(),
+    ))
+}
+
+#[cfg(feature = "upload")]
+fn chat(service: &str, config: &UploadConfig) -> Result<Box<dyn Uploader>> {
+    Ok(match service {
+        "discord" => Box::new(DiscordUploader::from_config(&config.discord)?),
+        _ => Box::new(SlackUploader::from_config(&config.slack)?),
+    })
+}
+
+#[cfg(not(feature = "upload"))]
+fn chat(service: &str, _config: &UploadConfig) -> Result<Box<dyn Uploader>> {
+    Err(Error::Unsupported(format!(
+        "{service} uploads require the `upload` feature"
+    )))
+}
+
+/// Anonymous uploads to Imgur (or any host speaking the Imgur v3 API)
+#[cfg(feature = "upload")]
+#[derive(Debug, Clone)]
+pub struct ImgurUploader {
+    client_id: String,
+    endpoint: String,
+}
+
+#[cfg(feature = "upload")]
+impl ImgurUploader {
+    pub const DEFAULT_ENDPOINT: &'static str = "https://api.imgur.com/3/image";
+
+    pub fn new(client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            endpoint: Self::DEFAULT_ENDPOINT.into(),
+        }
+    }
+
+    /// Points the uploader at an Imgur-compatible host
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Extracts the link and delete hash from an Imgur API response
+    fn parse_response(body: &str) -> Result<UploadResult> {
+        let json: serde_json::Value =
+            serde_json::from_str(body).map_err(|e| Error::Upload(format!("bad response: {e}")))?;
+        let data = &json["data"];
+        match data["link"].as_str() {
+            Some(link) if json["success"].as_bool() != Some(false) => Ok(UploadResult {
+                url: link.to_string(),
+                delete_handle: data["deletehash"].as_str().map(str::to_string),
+            }),
+            _ => Err(Error::Upload(format!(
+                "imgur rejected the upload: {}",
+                data["error"].as_str().unwrap_or(body)
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "upload")]
+impl Uploader for ImgurUploader {
+    fn name(&self) -> &str {
+        "imgur"
+    }
+
+    fn upload(&self, data: &[u8], filename: &str, mime_type: &str) -> Result<UploadResult> {
+        let mut form = Multipart::new();
+        form.file("image", filename, mime_type, data);
+        form.text("type", "file");
+
+        let response = ureq::post(&self.endpoint)
+            .set("Authorization", &format!("Client-ID {}", self.client_id))
+            .set("Content-Type", &form.content_type())
+            .send_bytes(&form.finish());
+        Self::parse_response(&read_body(response)?)
+    }
+}
+
+/// POSTs the encoded image to an arbitrary URL
+///
+/// The image is sent either as a `multipart/form-data` file field or as the
+/// raw request body with its MIME type as `Content-Type`. The upload URL
+/// reported back is the response's `Location` header, or the endpoint itself
+/// when the server doesn't send one.
+#[cfg(feature = "upload")]
+#[derive(Debug, Clone)]
+pub struct HttpUploader {
+    url: String,
+    mode: HttpBodyMode,
+    field: String,
+    headers: Vec<(String, String)>,
+}
+
+#[cfg(feature = "upload")]
+impl HttpUploader {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::from_config(url, &HttpUploadConfig::default())
+    }
+
+    pub fn from_config(url: impl Into<String>, config: &HttpUploadConfig) -> Self {
+        let mut headers: Vec<_> = config
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        headers.sort();
+        Self {
+            url: url.into(),
+            mode: config.mode,
+            field: config.field.clone(),
+            headers,
+        }
+    }
+
+    /// Adds a request header
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+#[cfg(feature = "upload")]
+impl Uploader for HttpUploader {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn upload(&self, data: &[u8], filename: &str, mime_type: &str) -> Result<UploadResult> {
+        let mut request = ureq::post(&self.url);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+
+        let response = match self.mode {
+            HttpBodyMode::Multipart => {
+                let mut form = Multipart::new();
+                form.file(&self.field, filename, mime_type, data);
+                request
+                    .set("Content-Type", &form.content_type())
+                    .send_bytes(&form.finish())
+            }
+            HttpBodyMode::Raw => request
+                .set("Content-Type", mime_type)
+                .set(
+                    "Content-Disposition",
+                    &format!("attachment; filename=\"{filename}\""),
+                )
+                .send_bytes(data),
+        };
+
+        let location = response
+            .as_ref()
+            .ok()
+            .and_then(|r| r.header("Location"))
+            .map(str::to_string);
+        read_body(response)?;
+        Ok(UploadResult {
+            url: location.unwrap_or_else(|| self.url.clone()),
+            delete_handle: None,
+        })
+    }
+}
+
+/// Parses a `Name: value` header argument
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(Error::invalid("header (expected `Name: value`)", header)),
+    }
+}
+
+/// Reads a response body, keeping error bodies for diagnostics
+#[cfg(feature = "upload")]
+pub(crate) fn read_body(
+    response: std::result::Result<ureq::Response, ureq::Error>,
+) -> Result<String> {
+    match response {
+        Ok(response) => Ok(response.into_string()?),
+        Err(ureq::Error::Status(code, response)) => Err(Error::Upload(format!(
+            "HTTP {code}: {}",
+            response.into_string().unwrap_or_default()
+        ))),
+        Err(e) => Err(Error::Upload(e.to_string())),
+    }
+}
+
+/// A minimal `multipart/form-data` body builder
+#[derive(Debug, Clone)]
+pub struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Self::with_boundary(format!("snap_scale-{nanos:x}"))
+    }
+
+    /// Uses a fixed boundary, which keeps bodies reproducible in tests
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Adds a plain text field
+    pub fn text(&mut self, name: &str, value: &str) -> &mut Self {
+        self.part_header(&format!("form-data; name=\"{name}\""), None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Adds a file field
+    pub fn file(&mut self, name: &str, filename: &str, mime_type: &str, data: &[u8]) -> &mut Self {
+        self.part_header(
+            &format!("form-data; name=\"{name}\"; filename=\"{filename}\""),
+            Some(mime_type),
+        );
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Value for the request's `Content-Type` header
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Closes the body and returns its bytes
+    pub fn finish(mut self) -> Vec<u8> {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body
+    }
+
+    fn part_header(&mut self, disposition: &str, mime_type: Option<&str>) {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: {disposition}\r\n",
+            self.boundary
+        );
+        if let Some(mime_type) = mime_type {
+            header.push_str(&format!("Content-Type: {mime_type}\r\n"));
+        }
+        header.push_str("\r\n");
+        self.body.extend_from_slice(header.as_bytes());
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_layout() {
+        let mut form = Multipart::with_boundary("XYZ");
+        form.text("type", "file");
+        form.file("image", "a.png", "image/png", b"PNG");
+
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=XYZ");
+        assert_eq!(
+            String::from_utf8(form.finish()).unwrap(),
+            "--XYZ\r\nContent-Disposition: form-data; name=\"type\"\r\n\r\nfile\r\n\
+             --XYZ\r\nContent-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\n\
+             Content-Type: image/png\r\n\r\nPNG\r\n--XYZ--\r\n"
+        );
+    }
+
+    #[cfg(feature = "upload")]
+    #[test]
+    fn test_imgur_success_response() {
+        let result = ImgurUploader::parse_response(
+            r#"{"data":{"link":"https://i.imgur.com/abc.png","deletehash":"d3l"},"success":true,"status":200}"#,
+        )
+        .unwrap();
+
+        assert_eq!(result.url, "https://i.imgur.com/abc.png");
+        assert_eq!(result.delete_handle.as_deref(), Some("d3l"));
+    }
+
+    #[cfg(feature = "upload")]
+    #[test]
+    fn test_imgur_error_response() {
+        let err = ImgurUploader::parse_response(
+            r#"{"data":{"error":"Invalid client_id"},"success":false,"status":403}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid client_id"), "{err}");
+    }
+
+    /// Accepts one request, returns it raw and answers with `response`
+    #[cfg(feature = "upload")]
+    pub(super) fn one_shot_server(response: &str) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+
+        let response = response.to_string();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|l| {
+                            l.to_ascii_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+        (url, handle)
+    }
+
+    #[cfg(feature = "upload")]
+    #[test]
+    fn test_http_raw_upload() {
+        let (url, server) = one_shot_server(
+            "HTTP/1.1 201 Created\r\nLocation: http://files/1.png\r\nContent-Length: 0\r\n\r\n",
+        );
+        let uploader = HttpUploader::from_config(
+            &url,
+            &HttpUploadConfig {
+                mode: HttpBodyMode::Raw,
+                ..HttpUploadConfig::default()
+            },
+        )
+        .header("X-Token", "secret");
+
+        let result = uploader
+            .upload(b"PNGDATA", "shot.png", "image/png")
+            .unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(result.url, "http://files/1.png");
+        assert!(request.starts_with("POST /hook"), "{request}");
+        assert!(request.contains("X-Token: secret"), "{request}");
+        assert!(request.contains("Content-Type: image/png"), "{request}");
+        assert!(request.ends_with("PNGDATA"), "{request}");
+    }
+
+    #[cfg(feature = "upload")]
+    #[test]
+    fn test_http_error_status_is_reported() {
+        let (url, server) =
+            one_shot_server("HTTP/1.1 403 Forbidden\r\nContent-Length: 6\r\n\r\nnope!!");
+        let err = HttpUploader::new(&url)
+            .upload(b"x", "x.png", "image/png")
+            .unwrap_err();
+        server.join().unwrap();
+
+        assert!(err.to_string().contains("HTTP 403"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("Authorization: Bearer abc:def").unwrap(),
+            ("Authorization".to_string(), "Bearer abc:def".to_string())
+        );
+        assert!(parse_header("no colon").is_err());
+        assert!(parse_header(": empty name").is_err());
+    }
+
+    #[test]
+    fn test_unknown_target_is_rejected() {
+        assert!(uploader_for("ftp", &UploadConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Posting captures to team chat for quick bug reports
+
+use super::{read_body, Multipart, UploadResult, Uploader};
+use crate::config::{DiscordConfig, SlackConfig};
+use crate::{Error, Result};
+use serde_json::{json, Value};
+
+/// Expands `{file}` in a configured message
+fn render_message(template: &str, filename: &str) -> String {
+    template.replace("{file}", filename)
+}
+
+/// Posts the image with an optional message through a Discord webhook
+#[derive(Debug, Clone)]
+pub struct DiscordUploader {
+    webhook_url: String,
+    message: Option<String>,
+    username: Option<String>,
+}
+
+impl DiscordUploader {
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        Self {
+            webhook_url: webhook_url.into(),
+            message: None,
+            username: None,
+        }
+    }
+
+    pub fn from_config(config: &DiscordConfig) -> Result<Self> {
+        let webhook_url = config.webhook_url.clone().ok_or_else(|| {
+            Error::Config("discord uploads need upload.discord.webhook_url".into())
+        })?;
+        Ok(Self {
+            message: config.message.clone(),
+            username: config.username.clone(),
+            ..Self::new(webhook_url)
+        })
+    }
+
+    /// Sets the text posted with the image; `{file}` expands to the file name
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    fn payload(&self, filename: &str) -> Value {
+        let mut payload = json!({
+            "content": self.message.as_deref().map(|m| render_message(m, filename)).unwrap_or_default(),
+            "attachments": [{ "id": 0, "filename": filename }],
+        });
+        if let Some(username) = &self.username {
+            payload["username"] = username.as_str().into();
+        }
+        payload
+    }
+
+    /// Extracts the attachment URL from the created message
+    fn parse_response(body: &str) -> Result<UploadResult> {
+        let json: Value =
+            serde_json::from_str(body).map_err(|e| Error::Upload(format!("bad response: {e}")))?;
+        json["attachments"][0]["url"]
+            .as_str()
+            .map(|url| UploadResult {
+                url: url.to_string(),
+                delete_handle: None,
+            })
+            .ok_or_else(|| Error::Upload(format!("discord sent no attachment: {body}")))
+    }
+}
+
+impl Uploader for DiscordUploader {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn upload(&self, data: &[u8], filename: &str, mime_type: &str) -> Result<UploadResult> {
+        let mut form = Multipart::new();
+        form.text("payload_json", &self.payload(filename).to_string());
+        form.file("files[0]", filename, mime_type, data);
+
+        // `wait=true` makes Discord return the created message
+        let response = ureq::post(&self.webhook_url)
+            .query("wait", "true")
+            .set("Content-Type", &form.content_type())
+            .send_bytes(&form.finish());
+        Self::parse_response(&read_body(response)?)
+    }
+}
+
+/// Shares the image to a Slack channel with an optional comment
+///
+/// Uses the external upload flow: reserve an upload URL, send the bytes, then
+/// complete the upload into the channel.
+#[derive(Debug, Clone)]
+pub struct SlackUploader {
+    token: String,
+    channel: String,
+    message: Option<String>,
+    api_base: String,
+}
+
+impl SlackUploader {
+    pub const DEFAULT_API_BASE: &'static str = "https://slack.com/api";
+
+    pub fn new(token: impl Into<String>, channel: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            channel: channel.into(),
+            message: None,
+            api_base: Self::DEFAULT_API_BASE.into(),
+        }
+    }
+
+    pub fn from_config(config: &SlackConfig) -> Result<Self> {
+        let token = config
+            .token
+            .clone()
+            .or_else(|| std::env::var("SLACK_TOKEN").ok())
+            .ok_or_else(|| {
+                Error::Config("slack uploads need upload.slack.token or $SLACK_TOKEN".into())
+            })?;
+        let channel = config
+            .channel
+            .clone()
+            .ok_or_else(|| Error::Config("slack uploads need upload.slack.channel".into()))?;
+        Ok(Self {
+            message: config.message.clone(),
+            ..Self::new(token, channel)
+        })
+    }
+
+    /// Sets the comment posted with the image; `{file}` expands to the file name
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Points the uploader at a Slack-compatible API
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    /// Unwraps Slack's `{"ok": false, "error": ...}` envelope
+    fn check(method: &str, body: &str) -> Result<Value> {
+        let json: Value =
+            serde_json::from_str(body).map_err(|e| Error::Upload(format!("bad response: {e}")))?;
+        if json["ok"].as_bool() == Some(true) {
+            Ok(json)
+        } else {
+            Err(Error::Upload(format!(
+                "slack {method} failed: {}",
+                json["error"].as_str().unwrap_or(body)
+            )))
+        }
+    }
+
+    fn endpoint(&self, method: &str) -> String {
+        format!("{}/{method}", self.api_base.trim_end_matches('/'))
+    }
+}
+
+impl Uploader for SlackUploader {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn upload(&self, data: &[u8], filename: &str, _mime_type: &str) -> Result<UploadResult> {
+        let auth = format!("Bearer {}", self.token);
+
+        let method = "files.getUploadURLExternal";
+        let response = ureq::post(&self.endpoint(method))
+            .set("Authorization", &auth)
+            .send_form(&[("filename", filename), ("length", &data.len().to_string())]);
+        let reserved = Self::check(method, &read_body(response)?)?;
+        let (Some(upload_url), Some(file_id)) = (
+            reserved["upload_url"].as_str(),
+            reserved["file_id"].as_str(),
+        ) else {
+            return Err(Error::Upload(format!("slack {method} sent no upload URL")));
+        };
+
+        read_body(ureq::post(upload_url).send_bytes(data))?;
+
+        let method = "files.completeUploadExternal";
+        let mut request = json!({
+            "files": [{ "id": file_id, "title": filename }],
+            "channel_id": self.channel,
+        });
+        if let Some(message) = &self.message {
+            request["initial_comment"] = render_message(message, filename).into();
+        }
+        let response = ureq::post(&self.endpoint(method))
+            .set("Authorization", &auth)
+            .set("Content-Type", "application/json; charset=utf-8")
+            .send_string(&request.to_string());
+        let completed = Self::check(method, &read_body(response)?)?;
+
+        Ok(UploadResult {
+            url: completed["files"][0]["permalink"]
+                .as_str()
+                .unwrap_or(upload_url)
+                .to_string(),
+            delete_handle: Some(file_id.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::one_shot_server;
+    use super::*;
+
+    #[test]
+    fn test_discord_posts_message_and_file() {
+        let body = r#"{"id":"1","attachments":[{"url":"https://cdn.discordapp.com/a/shot.png"}]}"#;
+        let (url, server) = one_shot_server(&format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ));
+
+        let result = DiscordUploader::new(url)
+            .message("Bug report: {file}")
+            .upload(b"PNGDATA", "shot.png", "image/png")
+            .unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(result.url, "https://cdn.discordapp.com/a/shot.png");
+        assert!(request.starts_with("POST /hook?wait=true"), "{request}");
+        assert!(
+            request.contains(r#""content":"Bug report: shot.png""#),
+            "{request}"
+        );
+        assert!(
+            request.contains(r#"name="files[0]"; filename="shot.png""#),
+            "{request}"
+        );
+    }
+
+    #[test]
+    fn test_discord_needs_webhook() {
+        let err = DiscordUploader::from_config(&DiscordConfig::default()).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{err}");
+    }
+
+    #[test]
+    fn test_slack_error_envelope() {
+        let err = SlackUploader::check(
+            "files.completeUploadExternal",
+            r#"{"ok":false,"error":"not_in_channel"}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not_in_channel"), "{err}");
+        assert!(SlackUploader::check("x", r#"{"ok":true}"#).is_ok());
+    }
+
+    #[test]
+    fn test_slack_endpoint() {
+        let uploader = SlackUploader::new("xoxb", "C1").with_api_base("http://localhost/api/");
+        assert_eq!(
+            uploader.endpoint("files.getUploadURLExternal"),
+            "http://localhost/api/files.getUploadURLExternal"
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! SFTP uploads through the system `sftp` client
+//!
+//! Shelling out keeps key handling (agents, `~/.ssh/config`, known hosts)
+//! identical to the user's own ssh setup. `BatchMode` is always on, so only
+//! key-based authentication is attempted and nothing ever prompts.
+
+use super::{UploadResult, Uploader};
+use crate::config::SftpUploadConfig;
+use crate::{Error, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Exit status ssh tools use for connection-level failures
+const CONNECTION_FAILURE: i32 = 255;
+
+/// What ssh prints when it exits with [`CONNECTION_FAILURE`] for a reason
+/// that another attempt won't change
+const PERMANENT_FAILURES: [&str; 2] = ["Permission denied", "Host key verification failed"];
+
+/// Tells apart the staging directories of concurrent uploads from one process
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Destination parsed from `sftp://[user@]host[:port]/path`, where `host`
+/// may be a bracketed IPv6 address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// Remote path; a trailing `/` means "directory, keep the file name"
+    pub path: String,
+}
+
+impl SftpTarget {
+    pub fn parse(url: &str) -> Result<Self> {
+        let invalid = || Error::invalid("sftp URL (expected sftp://[user@]host[:port]/path)", url);
+        let rest = url.strip_prefix("sftp://").ok_or_else(invalid)?;
+        let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_string()), host_port),
+            None => (None, authority),
+        };
+        // An IPv6 address is bracketed so that its colons aren't the port's
+        let (host, port) = match host_port.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, port) = bracketed.split_once(']').ok_or_else(invalid)?;
+                match port {
+                    "" => (host, None),
+                    port => (host, Some(port.strip_prefix(':').ok_or_else(invalid)?)),
+                }
+            }
+            None => match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            },
+        };
+        let port = port
+            .map(|port| port.parse().map_err(|_| invalid()))
+            .transpose()?;
+        // A leading `-` would make `sftp` read the user or host as an option
+        if host.is_empty()
+            || host.starts_with('-')
+            || user.as_deref().is_some_and(|user| user.starts_with('-'))
+        {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+            path: format!("/{path}"),
+        })
+    }
+
+    /// `user@host` or `host`, as passed to `sftp`, with IPv6 addresses
+    /// bracketed again
+    fn destination(&self) -> String {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+        match &self.user {
+            Some(user) => format!("{user}@{host}"),
+            None => host,
+        }
+    }
+
+    /// Remote file path for an upload named `filename`
+    pub fn remote_path(&self, filename: &str) -> String {
+        if self.path.ends_with('/') {
+            format!("{}{filename}", self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+}
+
+/// Uploads via `sftp -b -`, retrying connection failures with backoff;
+/// refused keys and unknown host keys fail at once
+#[derive(Debug, Clone)]
+pub struct SftpUploader {
+    target: SftpTarget,
+    config: SftpUploadConfig,
+    program: PathBuf,
+}
+
+impl SftpUploader {
+    pub fn new(target: SftpTarget, config: SftpUploadConfig) -> Self {
+        Self {
+            target,
+            config,
+            program: PathBuf::from("sftp"),
+        }
+    }
+
+    /// Uses a different client binary (mainly for tests)
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(["-b", "-", "-o", "BatchMode=yes"]);
+        command.arg("-o").arg(format!(
+            "ConnectTimeout={}",
+            self.config.connect_timeout_secs
+        ));
+        if let Some(identity) = &self.config.identity_file {
+            command.arg("-i").arg(identity);
+        }
+        if let Some(port) = self.target.port {
+            command.arg("-P").arg(port.to_string());
+        }
+        command.arg("--").arg(self.target.destination());
+        command
+    }
+
+    /// Runs one `put`; `Ok(None)` means a retryable connection failure
+    fn attempt(&self, local: &Path, remote: &str) -> Result<Option<()>> {
+        let batch = format!(
+            "put {} {}\n",
+            quote(&local.to_string_lossy())?,
+            quote(remote)?
+        );
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Upload(format!("cannot run {}: {e}", self.program.display())))?;
+
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(batch.as_bytes())?;
+
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let permanent = PERMANENT_FAILURES
+            .iter()
+            .any(|reason| stderr.contains(reason));
+        match output.status.code() {
+            Some(0) => Ok(Some(())),
+            Some(CONNECTION_FAILURE) if !permanent => Ok(None),
+            _ => Err(Error::Upload(format!(
+                "sftp put failed ({}): {}",
+                output.status,
+                stderr.trim()
+            ))),
+        }
+    }
+
+    fn put(&self, local: &Path, filename: &str) -> Result<UploadResult> {
+        let remote = self.target.remote_path(filename);
+        let mut delay = Duration::from_millis(self.config.retry_delay_ms);
+
+        for attempt in 1..=self.config.attempts.max(1) {
+            if self.attempt(local, &remote)?.is_some() {
+                return Ok(UploadResult {
+                    url: format!("sftp://{}{remote}", self.target.destination()),
+                    delete_handle: None,
+                });
+            }
+            if attempt < self.config.attempts {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+
+        Err(Error::Upload(format!(
+            "could not connect to {} after {} attempt(s)",
+            self.target.host,
+            self.config.attempts.max(1)
+        )))
+    }
+}
+
+impl Uploader for SftpUploader {
+    fn name(&self) -> &str {
+        "sftp"
+    }
+
+    fn upload(&self, data: &[u8], filename: &str, _mime_type: &str) -> Result<UploadResult> {
+        let dir = std::env::temp_dir().join(format!(
+            "snap_scale_sftp_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let local = dir.join(filename);
+        std::fs::write(&local, data)?;
+        let result = self.put(&local, filename);
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn upload_file(&self, path: &Path) -> Result<UploadResult> {
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "capture".into());
+        self.put(path, &filename)
+    }
+}
+
+/// Quotes a path for an sftp batch file
+///
+/// Control characters are refused: a newline would end the `put` and start
+/// a command of the path's own, `!` shell escapes included.
+fn quote(path: &str) -> Result<String> {
+    if path.chars().any(char::is_control) {
+        return Err(Error::invalid("sftp path", path.escape_debug().to_string()));
+    }
+    Ok(format!(
+        "\"{}\"",
+        path.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_url() {
+        let target = SftpTarget::parse("sftp://deploy@files.example.com:2222/srv/shots/").unwrap();
+        assert_eq!(
+            target,
+            SftpTarget {
+                user: Some("deploy".into()),
+                host: "files.example.com".into(),
+                port: Some(2222),
+                path: "/srv/shots/".into(),
+            }
+        );
+        assert_eq!(target.remote_path("a.png"), "/srv/shots/a.png");
+    }
+
+    #[test]
+    fn test_parse_file_url() {
+        let target = SftpTarget::parse("sftp://host/tmp/latest.png").unwrap();
+        assert_eq!(target.user, None);
+        assert_eq!(target.port, None);
+        assert_eq!(target.remote_path("ignored.png"), "/tmp/latest.png");
+    }
+
+    #[test]
+    fn test_parse_ipv6_hosts() {
+        let target = SftpTarget::parse("sftp://[::1]/x").unwrap();
+        assert_eq!((target.host.as_str(), target.port), ("::1", None));
+        assert_eq!(target.destination(), "[::1]");
+        let target = SftpTarget::parse("sftp://me@[fe80::2]:2222/srv/").unwrap();
+        assert_eq!(target.host, "fe80::2");
+        assert_eq!(target.port, Some(2222));
+        assert_eq!(target.destination(), "me@[fe80::2]");
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_urls() {
+        for url in [
+            "http://host/x",
+            "sftp://host",
+            "sftp:///path",
+            "sftp://h:port/x",
+            "sftp://[::1/x",
+            "sftp://[::1]2222/x",
+            "sftp://[]/x",
+            "sftp://-oProxyCommand=evil/x",
+            "sftp://-oProxyCommand=evil@host/x",
+            "sftp://me@-host/x",
+        ] {
+            assert!(SftpTarget::parse(url).is_err(), "{url} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_quote_escapes() {
+        assert_eq!(quote(r#"a "b" c"#).unwrap(), r#""a \"b\" c""#);
+    }
+
+    #[test]
+    fn test_quote_refuses_control_characters() {
+        for path in ["shot.png\n!rm -rf ~", "a\rb", "tab\there", "nul\0"] {
+            assert!(quote(path).is_err(), "{path:?} should be refused");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connection_failures_are_retried() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A fake client that fails to "connect" twice, then succeeds and
+        // records the batch commands it received
+        let dir = std::env::temp_dir().join(format!("snap_scale_sftp_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("fake-sftp");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncd {0}\nn=$(cat count 2>/dev/null || echo 0)\necho $((n+1)) > count\n\
+                 [ \"$n\" -lt 2 ] && exit 255\necho \"$@\" > args\ncat > batch\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let uploader = SftpUploader::new(
+            SftpTarget::parse("sftp://me@host/up/").unwrap(),
+            SftpUploadConfig {
+                attempts: 3,
+                retry_delay_ms: 1,
+                ..SftpUploadConfig::default()
+            },
+        )
+        .with_program(&script);
+
+        let result = uploader.upload(b"data", "shot.png", "image/png").unwrap();
+        let batch = std::fs::read_to_string(dir.join("batch")).unwrap();
+        let attempts = std::fs::read_to_string(dir.join("count")).unwrap();
+        let args = std::fs::read_to_string(dir.join("args")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.url, "sftp://me@host/up/shot.png");
+        assert_eq!(attempts.trim(), "3");
+        assert!(args.trim_end().ends_with(" -- me@host"), "{args}");
+        assert!(batch.starts_with("put \""), "{batch}");
+        assert!(batch.trim_end().ends_with("\"/up/shot.png\""), "{batch}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_refused_keys_are_not_retried() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("snap_scale_sftp_denied_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("fake-sftp");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho x >> {}/count\n\
+                 echo 'me@host: Permission denied (publickey).' >&2\nexit 255\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let uploader = SftpUploader::new(
+            SftpTarget::parse("sftp://me@host/up/").unwrap(),
+            SftpUploadConfig {
+                attempts: 3,
+                retry_delay_ms: 1,
+                ..SftpUploadConfig::default()
+            },
+        )
+        .with_program(&script);
+
+        let error = uploader.upload_file(&script).unwrap_err().to_string();
+        let attempts = std::fs::read_to_string(dir.join("count")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error.contains("Permission denied"), "{error}");
+        assert_eq!(attempts.lines().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrent_uploads_stage_apart() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A slow fake client that sends back what the staged file held
+        let dir =
+            std::env::temp_dir().join(format!("snap_scale_sftp_staging_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("fake-sftp");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nlocal=$(sed 's/^put \"\\([^\"]*\\)\".*/\\1/')\nsleep 0.2\n\
+                 cat \"$local\" >> {}/received\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let uploader = SftpUploader::new(
+            SftpTarget::parse("sftp://host/up/").unwrap(),
+            SftpUploadConfig::default(),
+        )
+        .with_program(&script);
+        thread::scope(|scope| {
+            for data in ["a", "b"] {
+                let uploader = &uploader;
+                scope.spawn(move || uploader.upload(data.as_bytes(), "shot.png", "image/png"));
+            }
+        });
+        let mut received: Vec<char> = std::fs::read_to_string(dir.join("received"))
+            .unwrap()
+            .chars()
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        received.sort_unstable();
+        assert_eq!(received, ['a', 'b']);
+    }
+}
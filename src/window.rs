@@ -0,0 +1,974 @@
+//! Top-level window enumeration
+//!
+//! Window bounds are desktop-global logical coordinates, the same space as
+//! [`DisplayInfo`](screenshots::display_info::DisplayInfo) bounds and
+//! [`crate::cursor::position`]. Windows are listed front to back and only
+//! visible ones are included.
+//!
+//! [`capture`] reads a window's own contents rather than the screen under it,
+//! so windows covered by others come out whole. [`Decorations`] picks between
+//! the window as it shows, title bar and borders included, and the client
+//! area alone; [`bounds`] gives where either is on the desktop.
+
+use crate::geometry::{saturate_i32, Region};
+use crate::{Error, Result};
+use screenshots::image::{imageops, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// What of a window its captures take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Decorations {
+    /// The window as it shows, with the title bar and borders around it
+    #[default]
+    Include,
+    /// Only the client area the application draws, without title bar,
+    /// borders or the shadows of client-side decorations
+    Exclude,
+}
+
+impl FromStr for Decorations {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "exclude" => Ok(Self::Exclude),
+            _ => Err(Error::invalid("decorations (include or exclude)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Decorations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Include => "include",
+            Self::Exclude => "exclude",
+        })
+    }
+}
+
+/// How far decorations reach past the client area on each side, in
+/// physical pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+struct Insets {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+impl Insets {
+    /// `image` without the insets, keeping at least one pixel
+    fn crop(self, image: RgbaImage) -> RgbaImage {
+        if self == Self::default() {
+            return image;
+        }
+        let (width, height) = image.dimensions();
+        let left = self.left.min(width - 1);
+        let top = self.top.min(height - 1);
+        let width = width.saturating_sub(left + self.right).max(1);
+        let height = height.saturating_sub(top + self.bottom).max(1);
+        imageops::crop_imm(&image, left, top, width, height).to_image()
+    }
+
+    /// Physical `region` without the insets
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn shrink(self, region: Region) -> Region {
+        Region::new(
+            saturate_i32(region.x as i64 + self.left as i64),
+            saturate_i32(region.y as i64 + self.top as i64),
+            region.width.saturating_sub(self.left + self.right).max(1),
+            region.height.saturating_sub(self.top + self.bottom).max(1),
+        )
+    }
+}
+
+/// Outside insets when the non-client `frame` surrounds `client`, both in
+/// the same coordinates
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn insets_between(frame: Region, client: Region) -> Insets {
+    let gap = |outer: i64, inner: i64| (inner - outer).clamp(0, u32::MAX as i64) as u32;
+    Insets {
+        left: gap(frame.x as i64, client.x as i64),
+        top: gap(frame.y as i64, client.y as i64),
+        right: gap(client.right(), frame.right()),
+        bottom: gap(client.bottom(), frame.bottom()),
+    }
+}
+
+/// A visible top-level window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    /// Platform window handle
+    pub id: u64,
+    pub title: String,
+    /// Application name: the WM_CLASS class on X11, the executable name on
+    /// Windows, the owning application on macOS
+    pub app: String,
+    pub pid: Option<u32>,
+    pub region: Region,
+}
+
+impl WindowInfo {
+    /// Whether the window is `process`'s: its pid, or its application name
+    /// in any case, with or without `.exe`
+    pub fn belongs_to(&self, process: &str) -> bool {
+        if let Ok(pid) = process.parse::<u32>() {
+            return self.pid == Some(pid);
+        }
+        let name = match process.len().checked_sub(4) {
+            Some(end) if process[end..].eq_ignore_ascii_case(".exe") => &process[..end],
+            _ => process,
+        };
+        !name.is_empty() && self.app.eq_ignore_ascii_case(name)
+    }
+
+    /// A file name for captures of the window, without an extension: its
+    /// title in lowercase letters and digits joined by dashes, or its id
+    /// when the title has none
+    pub fn file_stem(&self) -> String {
+        const MAX_LEN: usize = 64;
+        let mut stem = String::new();
+        for word in self
+            .title
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            let word = word.to_lowercase();
+            if stem.len() + 1 + word.len() > MAX_LEN {
+                break;
+            }
+            if !stem.is_empty() {
+                stem.push('-');
+            }
+            stem += &word;
+        }
+        if stem.is_empty() {
+            stem = format!("window-{}", self.id);
+        }
+        stem
+    }
+}
+
+/// The display showing the center of `window`, among `displays` in
+/// desktop-global logical coordinates, and the part of `window` on it in
+/// display-local coordinates
+pub fn locate(window: &Region, displays: &[Region]) -> Option<(usize, Region)> {
+    let x = saturate_i32(window.x as i64 + window.width as i64 / 2);
+    let y = saturate_i32(window.y as i64 + window.height as i64 / 2);
+    let (index, display) = displays
+        .iter()
+        .enumerate()
+        .find(|(_, display)| display.contains(x, y))?;
+    let visible = window.clamp_to(display)?;
+    Some((index, visible.translate(-display.x, -display.y)))
+}
+
+/// Lists visible top-level windows, frontmost first
+#[cfg(target_os = "linux")]
+pub fn list() -> Result<Vec<WindowInfo>> {
+    use xcb::{x, Xid};
+
+    let unavailable = |e: &dyn std::fmt::Display| Error::Unsupported(format!("window list: {e}"));
+    let (conn, screen_num) = xcb::Connection::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("window list: no X screen".into()))?
+        .root();
+
+    let atom = |name: &[u8]| {
+        conn.wait_for_reply(conn.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name,
+        }))
+        .map(|reply| reply.atom())
+        .map_err(|e| unavailable(&e))
+    };
+    let property = |window: x::Window, property: x::Atom, r#type: x::Atom| {
+        conn.wait_for_reply(conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: 0,
+            long_length: 4096,
+        }))
+        .ok()
+        // An unset property comes back with format 0
+        .filter(|reply| reply.format() != 0)
+    };
+    let text = |reply: Option<x::GetPropertyReply>| {
+        reply
+            .filter(|reply| reply.format() == 8)
+            .map(|reply| String::from_utf8_lossy(reply.value::<u8>()).into_owned())
+    };
+
+    let stacking = atom(b"_NET_CLIENT_LIST_STACKING")?;
+    let net_wm_name = atom(b"_NET_WM_NAME")?;
+    let utf8_string = atom(b"UTF8_STRING")?;
+    let net_wm_pid = atom(b"_NET_WM_PID")?;
+
+    let clients = property(root, stacking, x::ATOM_WINDOW)
+        .filter(|reply| reply.format() == 32)
+        .ok_or_else(|| {
+            Error::Unsupported(
+                "window list: the window manager does not publish _NET_CLIENT_LIST_STACKING".into(),
+            )
+        })?;
+    let scale = crate::cursor::x11_scale();
+
+    let mut windows = Vec::new();
+    // Stacking order is bottom to top
+    for &window in clients.value::<x::Window>().iter().rev() {
+        let viewable = conn
+            .wait_for_reply(conn.send_request(&x::GetWindowAttributes { window }))
+            .is_ok_and(|attributes| attributes.map_state() == x::MapState::Viewable);
+        if !viewable {
+            continue;
+        }
+        let Ok(geometry) = conn.wait_for_reply(conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        })) else {
+            continue;
+        };
+        let Ok(origin) = conn.wait_for_reply(conn.send_request(&x::TranslateCoordinates {
+            src_window: window,
+            dst_window: root,
+            src_x: 0,
+            src_y: 0,
+        })) else {
+            continue;
+        };
+
+        let title = text(property(window, net_wm_name, utf8_string))
+            .or_else(|| text(property(window, x::ATOM_WM_NAME, x::ATOM_STRING)))
+            .unwrap_or_default();
+        // WM_CLASS holds "instance\0class\0"
+        let app = text(property(window, x::ATOM_WM_CLASS, x::ATOM_STRING))
+            .and_then(|class| {
+                let mut parts = class.split('\0').filter(|part| !part.is_empty());
+                let instance = parts.next();
+                parts.next().or(instance).map(str::to_owned)
+            })
+            .unwrap_or_default();
+        let pid = property(window, net_wm_pid, x::ATOM_CARDINAL)
+            .filter(|reply| reply.format() == 32)
+            .and_then(|reply| reply.value::<u32>().first().copied());
+
+        windows.push(WindowInfo {
+            id: window.resource_id() as u64,
+            title,
+            app,
+            pid,
+            region: Region::new(
+                (origin.dst_x() as f32 / scale) as i32,
+                (origin.dst_y() as f32 / scale) as i32,
+                (geometry.width() as f32 / scale).round() as u32,
+                (geometry.height() as f32 / scale).round() as u32,
+            ),
+        });
+    }
+    Ok(windows)
+}
+
+/// Lists visible top-level windows, frontmost first
+#[cfg(target_os = "windows")]
+pub fn list() -> Result<Vec<WindowInfo>> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, BOOL, HWND, LPARAM, RECT};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowRect, GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible,
+    };
+
+    unsafe extern "system" fn collect(hwnd: HWND, windows: LPARAM) -> BOOL {
+        let windows = &mut *(windows.0 as *mut Vec<HWND>);
+        windows.push(hwnd);
+        true.into()
+    }
+
+    fn executable_name(pid: u32) -> Option<String> {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let mut buffer = [0u16; 1024];
+            let mut len = buffer.len() as u32;
+            let result = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buffer.as_mut_ptr()),
+                &mut len,
+            );
+            let _ = CloseHandle(process);
+            result.ok()?;
+            let path = String::from_utf16_lossy(&buffer[..len as usize]);
+            std::path::Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        }
+    }
+
+    // EnumWindows walks the Z order from the top
+    let mut handles: Vec<HWND> = Vec::new();
+    unsafe { EnumWindows(Some(collect), LPARAM(&mut handles as *mut _ as isize)) }
+        .map_err(|e| Error::Unsupported(format!("window list: {e}")))?;
+
+    let mut windows = Vec::new();
+    for hwnd in handles {
+        if !unsafe { IsWindowVisible(hwnd) }.as_bool() {
+            continue;
+        }
+        let mut rect = RECT::default();
+        if unsafe { GetWindowRect(hwnd, &mut rect) }.is_err() {
+            continue;
+        }
+        let mut buffer = [0u16; 512];
+        let len = unsafe { GetWindowTextW(hwnd, &mut buffer) };
+        let title = String::from_utf16_lossy(&buffer[..len.max(0) as usize]);
+        if title.is_empty() {
+            // Tool and helper windows are visible but untitled
+            continue;
+        }
+        let mut pid = 0;
+        unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+
+        windows.push(WindowInfo {
+            id: hwnd.0 as u64,
+            title,
+            app: executable_name(pid).unwrap_or_default(),
+            pid: (pid != 0).then_some(pid),
+            region: Region::new(
+                rect.left,
+                rect.top,
+                (rect.right - rect.left).max(0) as u32,
+                (rect.bottom - rect.top).max(0) as u32,
+            ),
+        });
+    }
+    Ok(windows)
+}
+
+/// Lists visible top-level windows, frontmost first
+#[cfg(target_os = "macos")]
+pub fn list() -> Result<Vec<WindowInfo>> {
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::dictionary::CFDictionary;
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::{CFString, CFStringRef};
+    use core_graphics::window::{
+        copy_window_info, kCGNullWindowID, kCGWindowBounds, kCGWindowLayer,
+        kCGWindowListOptionExcludeDesktopElements, kCGWindowListOptionOnScreenOnly, kCGWindowName,
+        kCGWindowNumber, kCGWindowOwnerName, kCGWindowOwnerPID,
+    };
+
+    fn get(dict: &CFDictionary, key: CFTypeRef) -> Option<CFType> {
+        dict.find(key)
+            .map(|value| unsafe { CFType::wrap_under_get_rule(*value as CFTypeRef) })
+    }
+    fn string(dict: &CFDictionary, key: CFStringRef) -> Option<String> {
+        get(dict, key as CFTypeRef)?
+            .downcast::<CFString>()
+            .map(|s| s.to_string())
+    }
+    fn number(dict: &CFDictionary, key: CFTypeRef) -> Option<f64> {
+        get(dict, key)?.downcast::<CFNumber>()?.to_f64()
+    }
+
+    // Front to back
+    let info = copy_window_info(
+        kCGWindowListOptionOnScreenOnly | kCGWindowListOptionExcludeDesktopElements,
+        kCGNullWindowID,
+    )
+    .ok_or_else(|| Error::Unsupported("window list: unavailable".into()))?;
+
+    let mut windows = Vec::new();
+    for item in info.iter() {
+        let dict: CFDictionary = unsafe { CFDictionary::wrap_under_get_rule(*item as _) };
+        // Layer 0 holds normal application windows; menus and the dock sit above
+        if unsafe { number(&dict, kCGWindowLayer as CFTypeRef) } != Some(0.0) {
+            continue;
+        }
+        let Some(bounds) = unsafe { get(&dict, kCGWindowBounds as CFTypeRef) }
+            .and_then(|bounds| bounds.downcast::<CFDictionary>())
+        else {
+            continue;
+        };
+        let edge = |name: &'static str| {
+            number(&bounds, CFString::from_static_string(name).as_CFTypeRef()).unwrap_or(0.0)
+        };
+
+        windows.push(unsafe {
+            WindowInfo {
+                id: number(&dict, kCGWindowNumber as CFTypeRef).unwrap_or(0.0) as u64,
+                title: string(&dict, kCGWindowName).unwrap_or_default(),
+                app: string(&dict, kCGWindowOwnerName).unwrap_or_default(),
+                pid: number(&dict, kCGWindowOwnerPID as CFTypeRef).map(|pid| pid as u32),
+                region: Region::new(
+                    edge("X") as i32,
+                    edge("Y") as i32,
+                    edge("Width") as u32,
+                    edge("Height") as u32,
+                ),
+            }
+        });
+    }
+    Ok(windows)
+}
+
+/// Lists visible top-level windows, frontmost first
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn list() -> Result<Vec<WindowInfo>> {
+    Err(Error::Unsupported(
+        "window enumeration is not available on this platform".into(),
+    ))
+}
+
+/// The window with the keyboard focus, when [`list`] includes it
+pub fn focused() -> Result<Option<WindowInfo>> {
+    let Some(id) = focused_id()? else {
+        return Ok(None);
+    };
+    Ok(list()?.into_iter().find(|window| window.id == id))
+}
+
+#[cfg(target_os = "linux")]
+fn focused_id() -> Result<Option<u64>> {
+    use xcb::{x, Xid};
+
+    let unavailable =
+        |e: &dyn std::fmt::Display| Error::Unsupported(format!("focused window: {e}"));
+    let (conn, screen_num) = xcb::Connection::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("focused window: no X screen".into()))?
+        .root();
+    let active = conn
+        .wait_for_reply(conn.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name: b"_NET_ACTIVE_WINDOW",
+        }))
+        .map_err(|e| unavailable(&e))?
+        .atom();
+    let reply = conn
+        .wait_for_reply(conn.send_request(&x::GetProperty {
+            delete: false,
+            window: root,
+            property: active,
+            r#type: x::ATOM_WINDOW,
+            long_offset: 0,
+            long_length: 1,
+        }))
+        .map_err(|e| unavailable(&e))?;
+    if reply.format() != 32 {
+        return Err(Error::Unsupported(
+            "focused window: the window manager does not publish _NET_ACTIVE_WINDOW".into(),
+        ));
+    }
+    // None while no window has the focus
+    Ok(reply
+        .value::<x::Window>()
+        .first()
+        .filter(|window| !window.is_none())
+        .map(|window| window.resource_id() as u64))
+}
+
+#[cfg(target_os = "windows")]
+fn focused_id() -> Result<Option<u64>> {
+    use windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+
+    // Null while focus is changing, or on a secure desktop
+    let hwnd = unsafe { GetForegroundWindow() };
+    Ok((hwnd.0 != 0).then_some(hwnd.0 as u64))
+}
+
+/// The frontmost window stands in for the focused one
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn focused_id() -> Result<Option<u64>> {
+    Ok(list()?.first().map(|window| window.id))
+}
+
+/// The X window holding `decorations` of client `id`, and what to cut off it
+///
+/// Reparenting window managers draw title bars and borders on a frame window
+/// between the client and the root. Clients drawing their own decorations
+/// keep their shadows inside the window and publish how far they reach.
+#[cfg(target_os = "linux")]
+fn x11_target(
+    conn: &xcb::Connection,
+    id: u64,
+    decorations: Decorations,
+) -> Result<(xcb::x::Window, Insets)> {
+    use xcb::{x, XidNew};
+
+    // `XidNew::new` is unsafe before xcb 1.7 and safe from it on; an id that
+    // names no window only makes the requests on it fail
+    #[allow(unused_unsafe)]
+    let mut window = u32::try_from(id)
+        .map(|id| unsafe { x::Window::new(id) })
+        .map_err(|_| Error::invalid("window id", id.to_string()))?;
+    match decorations {
+        Decorations::Include => {
+            use xcb::Xid;
+            while let Ok(tree) = conn.wait_for_reply(conn.send_request(&x::QueryTree { window })) {
+                if tree.parent().is_none() || tree.parent() == tree.root() {
+                    break;
+                }
+                window = tree.parent();
+            }
+            Ok((window, Insets::default()))
+        }
+        Decorations::Exclude => {
+            let extents = conn
+                .wait_for_reply(conn.send_request(&x::InternAtom {
+                    only_if_exists: true,
+                    name: b"_GTK_FRAME_EXTENTS",
+                }))
+                .ok()
+                .map(|reply| reply.atom())
+                .filter(|atom| *atom != x::ATOM_NONE)
+                .and_then(|property| {
+                    conn.wait_for_reply(conn.send_request(&x::GetProperty {
+                        delete: false,
+                        window,
+                        property,
+                        r#type: x::ATOM_CARDINAL,
+                        long_offset: 0,
+                        long_length: 4,
+                    }))
+                    .ok()
+                })
+                .filter(|reply| reply.format() == 32);
+            let insets = match extents.as_ref().map(|reply| reply.value::<u32>()) {
+                Some(&[left, right, top, bottom]) => Insets {
+                    left,
+                    right,
+                    top,
+                    bottom,
+                },
+                _ => Insets::default(),
+            };
+            Ok((window, insets))
+        }
+    }
+}
+
+/// Where `decorations` of `window` are on the desktop, in desktop-global
+/// logical coordinates
+#[cfg(target_os = "linux")]
+pub fn bounds(window: &WindowInfo, decorations: Decorations) -> Result<Region> {
+    use xcb::x;
+
+    let unavailable = |e: &dyn std::fmt::Display| Error::Unsupported(format!("window bounds: {e}"));
+    let (conn, screen_num) = xcb::Connection::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| Error::Unsupported("window bounds: no X screen".into()))?
+        .root();
+    let (target, insets) = x11_target(&conn, window.id, decorations)?;
+    let gone = |_| Error::invalid("window", format!("no window {}", window.id));
+    let geometry = conn
+        .wait_for_reply(conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(target),
+        }))
+        .map_err(gone)?;
+    let origin = conn
+        .wait_for_reply(conn.send_request(&x::TranslateCoordinates {
+            src_window: target,
+            dst_window: root,
+            src_x: 0,
+            src_y: 0,
+        }))
+        .map_err(gone)?;
+    let physical = insets.shrink(Region::new(
+        origin.dst_x() as i32,
+        origin.dst_y() as i32,
+        geometry.width() as u32,
+        geometry.height() as u32,
+    ));
+    let scale = crate::cursor::x11_scale();
+    Ok(Region::new(
+        (physical.x as f32 / scale) as i32,
+        (physical.y as f32 / scale) as i32,
+        (physical.width as f32 / scale).round() as u32,
+        (physical.height as f32 / scale).round() as u32,
+    ))
+}
+
+/// Captures `decorations` of window `id`, even where other windows cover it,
+/// at its physical size
+///
+/// Redirects the window through XComposite for the duration, so it works
+/// without a compositing window manager too. Minimized and other unmapped
+/// windows have no contents to read.
+#[cfg(target_os = "linux")]
+pub fn capture(id: u64, decorations: Decorations) -> Result<RgbaImage> {
+    use xcb::{composite, x, Xid};
+
+    let unavailable =
+        |e: &dyn std::fmt::Display| Error::Unsupported(format!("window capture: {e}"));
+    let (conn, _) =
+        xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Composite], &[])
+            .map_err(|e| unavailable(&e))?;
+    // NameWindowPixmap needs 0.2
+    conn.wait_for_reply(conn.send_request(&composite::QueryVersion {
+        client_major_version: 0,
+        client_minor_version: 4,
+    }))
+    .map_err(|e| unavailable(&e))?;
+
+    let (window, insets) = x11_target(&conn, id, decorations)?;
+    let geometry = conn
+        .wait_for_reply(conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        }))
+        .map_err(|_| Error::invalid("window", format!("no window {id}")))?;
+
+    conn.send_and_check_request(&composite::RedirectWindow {
+        window,
+        update: composite::Redirect::Automatic,
+    })
+    .map_err(|e| unavailable(&e))?;
+    let pixmap = conn.generate_id();
+    let image = conn
+        .send_and_check_request(&composite::NameWindowPixmap { window, pixmap })
+        .map_err(|_| Error::invalid("window", format!("window {id} isn't mapped")))
+        .and_then(|()| {
+            let image = conn
+                .wait_for_reply(conn.send_request(&x::GetImage {
+                    format: x::ImageFormat::ZPixmap,
+                    drawable: x::Drawable::Pixmap(pixmap),
+                    x: 0,
+                    y: 0,
+                    width: geometry.width(),
+                    height: geometry.height(),
+                    plane_mask: u32::MAX,
+                }))
+                .map_err(|e| unavailable(&e));
+            conn.send_request(&x::FreePixmap { pixmap });
+            image
+        });
+    // Undoes only this connection's redirect; the compositor's stays
+    conn.send_request(&composite::UnredirectWindow {
+        window,
+        update: composite::Redirect::Automatic,
+    });
+    let _ = conn.flush();
+    let image = image?;
+
+    tracing::debug!(
+        window = window.resource_id(),
+        depth = image.depth(),
+        "composited"
+    );
+    from_bgrx(
+        image.data(),
+        geometry.width() as u32,
+        geometry.height() as u32,
+        image.depth() == 32,
+    )
+    .map(|image| insets.crop(image))
+}
+
+/// The visible frame of `hwnd` and its client area, in screen coordinates
+///
+/// The frame leaves out the invisible resize borders `GetWindowRect` counts.
+#[cfg(target_os = "windows")]
+fn frame_and_client(id: u64) -> Result<(Region, Region)> {
+    use windows::Win32::Foundation::{HWND, POINT, RECT};
+    use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+    use windows::Win32::Graphics::Gdi::ClientToScreen;
+    use windows::Win32::UI::WindowsAndMessaging::GetClientRect;
+
+    let hwnd = HWND(id as isize);
+    let gone = |e: &dyn std::fmt::Display| Error::invalid("window", format!("window {id}: {e}"));
+    let size = |rect: RECT| {
+        (
+            (rect.right - rect.left).max(0) as u32,
+            (rect.bottom - rect.top).max(0) as u32,
+        )
+    };
+    let mut frame = RECT::default();
+    unsafe {
+        DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_EXTENDED_FRAME_BOUNDS,
+            &mut frame as *mut RECT as *mut std::ffi::c_void,
+            std::mem::size_of::<RECT>() as u32,
+        )
+    }
+    .map_err(|e| gone(&e))?;
+    let mut client = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut client) }.map_err(|e| gone(&e))?;
+    let mut origin = POINT::default();
+    if !unsafe { ClientToScreen(hwnd, &mut origin) }.as_bool() {
+        return Err(gone(&"no client area"));
+    }
+    let (frame_width, frame_height) = size(frame);
+    let (client_width, client_height) = size(client);
+    Ok((
+        Region::new(frame.left, frame.top, frame_width, frame_height),
+        Region::new(origin.x, origin.y, client_width, client_height),
+    ))
+}
+
+/// Where `decorations` of `window` are on the desktop, in desktop-global
+/// logical coordinates
+#[cfg(target_os = "windows")]
+pub fn bounds(window: &WindowInfo, decorations: Decorations) -> Result<Region> {
+    let (frame, client) = frame_and_client(window.id)?;
+    Ok(match decorations {
+        Decorations::Include => frame,
+        Decorations::Exclude => client,
+    })
+}
+
+/// Captures `decorations` of window `id`, even where other windows cover it,
+/// at its physical size
+///
+/// Goes through Windows.Graphics.Capture, so it needs Windows 10 1903 or
+/// later. It captures the visible frame, which the client area is cut from.
+#[cfg(target_os = "windows")]
+pub fn capture(id: u64, decorations: Decorations) -> Result<RgbaImage> {
+    let image = crate::backend::wgc::WgcBackend::new()?.capture_window(id)?;
+    Ok(match decorations {
+        Decorations::Include => image,
+        Decorations::Exclude => {
+            let (frame, client) = frame_and_client(id)?;
+            insets_between(frame, client).crop(image)
+        }
+    })
+}
+
+/// Where `decorations` of `window` are on the desktop, in desktop-global
+/// logical coordinates
+///
+/// Only whole windows are known here: window managers don't say where the
+/// client area of another application's window starts.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn bounds(window: &WindowInfo, decorations: Decorations) -> Result<Region> {
+    match decorations {
+        Decorations::Include => Ok(window.region),
+        Decorations::Exclude => Err(no_client_area()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn no_client_area() -> Error {
+    Error::Unsupported("client-area window captures are not available on this platform".into())
+}
+
+/// Captures `decorations` of window `id`, even where other windows cover it,
+/// at its physical size
+///
+/// Goes through ScreenCaptureKit, so it needs the `sckit` feature and macOS
+/// 12.3 or later. Only whole windows can be captured.
+#[cfg(all(feature = "sckit", target_os = "macos"))]
+pub fn capture(id: u64, decorations: Decorations) -> Result<RgbaImage> {
+    match decorations {
+        Decorations::Include => crate::backend::sckit::ScreenCaptureKit.capture_window(id),
+        Decorations::Exclude => Err(no_client_area()),
+    }
+}
+
+/// Captures `decorations` of window `id`, even where other windows cover it,
+/// at its physical size
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "windows",
+    all(feature = "sckit", target_os = "macos")
+)))]
+pub fn capture(id: u64, _decorations: Decorations) -> Result<RgbaImage> {
+    Err(Error::Unsupported(format!(
+        "capturing window {id} is not available on this platform"
+    )))
+}
+
+/// Keeps one of this process's own windows out of every screen capture,
+/// this crate's and other applications', while it stays on screen for the
+/// user
+///
+/// Meant for overlays, previews and popups that would otherwise end up in
+/// the pixels they're part of taking. `handle` is the window's `HWND` on
+/// Windows and its `NSWindow` pointer on macOS, where this has to be called
+/// on the main thread. X11 has no such setting; hide the window for the
+/// capture there instead.
+pub fn exclude_from_capture(handle: u64) -> Result<()> {
+    exclude(handle)
+}
+
+#[cfg(target_os = "windows")]
+fn exclude(handle: u64) -> Result<()> {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE,
+    };
+
+    // Windows 10 2004 and later; only windows of this process qualify
+    unsafe { SetWindowDisplayAffinity(HWND(handle as isize), WDA_EXCLUDEFROMCAPTURE) }
+        .map_err(|e| Error::Unsupported(format!("excluding window {handle} from capture: {e}")))
+}
+
+#[cfg(all(feature = "sckit", target_os = "macos"))]
+fn exclude(handle: u64) -> Result<()> {
+    use objc2::msg_send;
+    use objc2::runtime::AnyObject;
+
+    let window = handle as usize as *mut AnyObject;
+    if window.is_null() {
+        return Err(Error::invalid("window", "a null NSWindow"));
+    }
+    // NSWindowSharingNone
+    let none: usize = 0;
+    unsafe {
+        let _: () = msg_send![window, setSharingType: none];
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", all(feature = "sckit", target_os = "macos"))))]
+fn exclude(handle: u64) -> Result<()> {
+    Err(Error::Unsupported(format!(
+        "excluding window {handle} from capture is not available on this platform"
+    )))
+}
+
+/// An image from 32-bit little-endian BGRX pixels, the layout of 24- and
+/// 32-bit X visuals; `alpha` keeps the X byte, which only ARGB visuals fill
+#[cfg_attr(all(target_os = "macos", not(feature = "sckit")), allow(dead_code))]
+pub(crate) fn from_bgrx(data: &[u8], width: u32, height: u32, alpha: bool) -> Result<RgbaImage> {
+    if data.len() != width as usize * height as usize * 4 {
+        return Err(Error::Unsupported(format!(
+            "window capture: {} bytes for {width}x{height}, expected 32 bits a pixel",
+            data.len()
+        )));
+    }
+    let rgba = data
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0], if alpha { p[3] } else { 255 }])
+        .collect();
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| Error::Unsupported("window capture: bad image".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate_picks_the_display_under_the_center() {
+        let displays = [
+            Region::new(0, 0, 1920, 1080),
+            Region::new(1920, 0, 1280, 1024),
+        ];
+        assert_eq!(
+            locate(&Region::new(100, 50, 400, 300), &displays),
+            Some((0, Region::new(100, 50, 400, 300)))
+        );
+        // Mostly on the second display; clipped to it
+        assert_eq!(
+            locate(&Region::new(1800, 10, 600, 200), &displays),
+            Some((1, Region::new(0, 10, 480, 200)))
+        );
+        assert_eq!(locate(&Region::new(5000, 0, 10, 10), &displays), None);
+    }
+
+    fn window(title: &str) -> WindowInfo {
+        WindowInfo {
+            id: 42,
+            title: title.into(),
+            app: "MyApp".into(),
+            pid: Some(1234),
+            region: Region::new(0, 0, 800, 600),
+        }
+    }
+
+    #[test]
+    fn test_belongs_to() {
+        let window = window("Settings");
+        assert!(window.belongs_to("myapp"));
+        assert!(window.belongs_to("MyApp.EXE"));
+        assert!(window.belongs_to("1234"));
+        assert!(!window.belongs_to("4321"));
+        assert!(!window.belongs_to("my"));
+        assert!(!window.belongs_to(".exe"));
+    }
+
+    #[test]
+    fn test_file_stem() {
+        let stem = |title: &str| window(title).file_stem();
+        assert_eq!(stem("Report.pdf — Viewer"), "report-pdf-viewer");
+        assert_eq!(stem("  Ünïcode: Größe  "), "ünïcode-größe");
+        assert_eq!(stem("/\\:*?"), "window-42");
+        assert_eq!(stem(""), "window-42");
+        let long = stem(&"word ".repeat(40));
+        assert!(long.len() <= 64 && long.ends_with("word"), "{long}");
+    }
+
+    #[test]
+    fn test_decorations() {
+        assert_eq!(
+            "include".parse::<Decorations>().unwrap(),
+            Decorations::Include
+        );
+        assert_eq!(
+            "Exclude".parse::<Decorations>().unwrap(),
+            Decorations::Exclude
+        );
+        assert!("none".parse::<Decorations>().is_err());
+        assert_eq!(Decorations::default().to_string(), "include");
+    }
+
+    #[test]
+    fn test_insets() {
+        let frame = Region::new(100, 50, 400, 300);
+        let client = Region::new(108, 82, 384, 260);
+        let insets = insets_between(frame, client);
+        assert_eq!(
+            insets,
+            Insets {
+                left: 8,
+                right: 8,
+                top: 32,
+                bottom: 8
+            }
+        );
+        assert_eq!(insets.shrink(frame), client);
+
+        let image = RgbaImage::from_fn(400, 300, |x, y| {
+            screenshots::image::Rgba([(x % 256) as u8, (y % 256) as u8, 0, 255])
+        });
+        let cropped = insets.crop(image);
+        assert_eq!(cropped.dimensions(), (384, 260));
+        assert_eq!(cropped.get_pixel(0, 0).0[..2], [8, 32]);
+        // Insets bigger than the window leave a pixel
+        let huge = Insets {
+            left: 500,
+            right: 500,
+            top: 0,
+            bottom: 0,
+        };
+        assert_eq!(huge.crop(RgbaImage::new(10, 10)).dimensions(), (1, 10));
+    }
+
+    #[test]
+    fn test_from_bgrx() {
+        let data = [1, 2, 3, 0, 10, 20, 30, 128];
+        let opaque = from_bgrx(&data, 2, 1, false).unwrap();
+        assert_eq!(opaque.as_raw(), &[3, 2, 1, 255, 30, 20, 10, 255]);
+        let argb = from_bgrx(&data, 1, 2, true).unwrap();
+        assert_eq!(argb.as_raw(), &[3, 2, 1, 0, 30, 20, 10, 128]);
+        // 16-bit visuals pack two bytes a pixel
+        assert!(from_bgrx(&data, 2, 2, false).is_err());
+    }
+}
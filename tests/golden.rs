@@ -0,0 +1,84 @@
+//! Byte-for-byte golden tests for every encoder
+//!
+//! Each synthetic frame is encoded with the default (fixed) settings for every
+//! [`OutputFormat`] and compared against the file checked in under
+//! `tests/golden/`. Run with `SNAP_SCALE_UPDATE_GOLDEN=1` to regenerate the
+//! files after an intentional encoder change, and review the diff.
+
+use screenshots::image::{Rgba, RgbaImage};
+use snap_scale::encode::encode_to_vec;
+use snap_scale::{EncodeOptions, OutputFormat};
+use std::path::PathBuf;
+
+/// Horizontal/vertical colour ramp with a fully opaque alpha channel
+fn gradient_frame() -> RgbaImage {
+    RgbaImage::from_fn(64, 48, |x, y| {
+        Rgba([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255])
+    })
+}
+
+/// 8px checkerboard, the worst case for lossy encoders
+fn checkerboard_frame() -> RgbaImage {
+    RgbaImage::from_fn(64, 48, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+/// Solid colour with an alpha ramp, exercising transparency handling
+fn alpha_frame() -> RgbaImage {
+    RgbaImage::from_fn(64, 48, |x, _| Rgba([200, 40, 90, (x * 4) as u8]))
+}
+
+fn golden_path(name: &str, format: OutputFormat) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.{}", format.extension()))
+}
+
+fn check_golden(name: &str, frame: &RgbaImage) {
+    let update = std::env::var_os("SNAP_SCALE_UPDATE_GOLDEN").is_some();
+
+    for format in OutputFormat::ALL {
+        let encoded = encode_to_vec(frame, &EncodeOptions::new(format)).unwrap();
+        let path = golden_path(name, format);
+
+        if update {
+            std::fs::write(&path, &encoded).unwrap();
+            continue;
+        }
+
+        let expected = std::fs::read(&path).unwrap_or_else(|e| {
+            panic!(
+                "Missing golden file {} ({e}); rerun with SNAP_SCALE_UPDATE_GOLDEN=1",
+                path.display()
+            )
+        });
+        assert!(
+            encoded == expected,
+            "{format} encoding of '{name}' drifted from {} ({} bytes, expected {})",
+            path.display(),
+            encoded.len(),
+            expected.len()
+        );
+    }
+}
+
+#[test]
+fn test_gradient_golden() {
+    check_golden("gradient", &gradient_frame());
+}
+
+#[test]
+fn test_checkerboard_golden() {
+    check_golden("checkerboard", &checkerboard_frame());
+}
+
+#[test]
+fn test_alpha_golden() {
+    check_golden("alpha", &alpha_frame());
+}
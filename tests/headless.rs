@@ -0,0 +1,767 @@
+//! The command line against the mock backend
+//!
+//! `SNAP_SCALE_BACKEND=mock:<displays>` swaps the screens for synthetic ones,
+//! so these run the real binary end to end on machines with no display.
+
+use screenshots::image::{self, Rgba};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A 64x48 display and a 32x24 one at scale 2 to its right
+const DISPLAYS: &str = "mock:64x48,32x24@2";
+
+fn snap_scale(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(args)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn scratch(test: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("snap_scale_headless_{test}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_capture_a_mock_display() {
+    let dir = scratch("capture");
+    let path = dir.join("shot.png");
+    snap_scale(&[
+        "capture",
+        "--display",
+        "1",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+
+    let image = image::open(&path).unwrap().into_rgba8();
+    assert_eq!(image.dimensions(), (64, 48), "Physical pixels at scale 2");
+    assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 64, 255]));
+    assert_eq!(*image.get_pixel(63, 47), Rgba([255, 255, 64, 255]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_capture_options_go_either_side_of_the_subcommand() {
+    let dir = scratch("options");
+    let before = dir.join("before.png");
+    let after = dir.join("after.png");
+    snap_scale(&[
+        "--caption",
+        "x",
+        "--resize",
+        "50%",
+        "capture",
+        "-o",
+        before.to_str().unwrap(),
+    ]);
+    snap_scale(&[
+        "capture",
+        "--caption",
+        "x",
+        "--resize",
+        "50%",
+        "-o",
+        after.to_str().unwrap(),
+    ]);
+
+    let before = image::open(&before).unwrap().into_rgba8();
+    let after = image::open(&after).unwrap().into_rgba8();
+    assert_eq!(after.dimensions(), (32, 24));
+    assert!(before == after, "The same capture either way");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_metadata_names_the_display() {
+    let dir = scratch("metadata");
+    let path = dir.join("shot.png");
+    snap_scale(&[
+        "--metadata",
+        "capture",
+        "--display",
+        "2",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+
+    let metadata = snap_scale::metadata::read_png(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!((metadata.display_id, metadata.x), (2, 64));
+    assert_eq!(metadata.scale_factor, 2.0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_capture_a_preview() {
+    let dir = scratch("preview");
+    let path = dir.join("preview.png");
+    snap_scale(&[
+        "capture",
+        "--display",
+        "1",
+        "--preview",
+        "0.25",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+
+    let image = image::open(&path).unwrap().into_rgba8();
+    assert_eq!(image.dimensions(), (16, 12), "A quarter of 64x48");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_display_selectors() {
+    let dir = scratch("select");
+    for selector in ["1", "id:2", "mock-2", "primary"] {
+        let path = dir.join(format!("{selector}.png"));
+        snap_scale(&[
+            "capture",
+            "--display",
+            selector,
+            "--output",
+            path.to_str().unwrap(),
+        ]);
+        // Blue tells the displays apart
+        let image = image::open(&path).unwrap().into_rgba8();
+        let blue = if selector == "primary" { 0 } else { 64 };
+        assert_eq!(image.get_pixel(0, 0)[2], blue, "{selector}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_saves_every_frame() {
+    let dir = scratch("watch");
+    let dir_arg = dir.to_str().unwrap();
+    snap_scale(&["watch", "--count", "3", "--interval", "0", "--dir", dir_arg]);
+
+    let mut frames: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    frames.sort();
+    assert_eq!(frames, ["1-000001.png", "1-000002.png", "1-000003.png"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_sidecars_describe_the_display() {
+    let dir = scratch("watch_sidecar");
+    let dir_arg = dir.to_str().unwrap();
+    snap_scale(&[
+        "--sidecar",
+        "watch",
+        "--display",
+        "2",
+        "--count",
+        "2",
+        "--interval",
+        "0",
+        "--dir",
+        dir_arg,
+    ]);
+
+    for frame in ["2-000001.png.json", "2-000002.png.json"] {
+        let sidecar = std::fs::read_to_string(dir.join(frame)).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_str(&sidecar).unwrap();
+        assert_eq!(sidecar["display"]["id"], 2);
+        assert_eq!(sidecar["scaling"]["total_scale"], 2.0);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pick_reads_the_pattern() {
+    let output = snap_scale(&["pick", "32", "47"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("position: 32,47 (display 1, physical 32,47)"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("rgba(129, 255, 0, 1.00)\n#81ff00\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_magnify_centers_on_the_point() {
+    let dir = scratch("magnify");
+    let path = dir.join("close-up.png");
+    let path_arg = path.to_str().unwrap();
+    let output = snap_scale(&[
+        "magnify", "32", "47", "--radius", "2", "--zoom", "4", "-o", path_arg,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("#81ff00 at 32,47 (display 1)"),
+        "{stdout}"
+    );
+
+    let image = image::open(&path).unwrap().into_rgba8();
+    // 5 pixels 4 times over, centered above the wider hex value
+    assert_eq!(image.width(), 120);
+    assert_eq!(*image.get_pixel(51, 1), Rgba([121, 244, 0, 255]));
+    // Below the display's bottom edge is left empty
+    assert_eq!(image.get_pixel(51, 17)[3], 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_scroll_stops_on_a_still_page() {
+    let dir = scratch("scroll");
+    let path = dir.join("page.png");
+    let output = snap_scale(&[
+        "scroll",
+        "--region",
+        "4,4,20,10",
+        "--interval",
+        "10",
+        "--idle",
+        "50",
+        "-o",
+        path.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Stitched 1 frame(s) into 20x10."),
+        "{stderr}"
+    );
+    let image = image::open(&path).unwrap().into_rgba8();
+    assert_eq!(image.dimensions(), (20, 10));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_layout_maps_the_displays() {
+    let output = snap_scale(&["layout", "--width", "30", "--ascii"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (map, list) = stdout.split_once("\n\n").unwrap();
+    assert_eq!(
+        map,
+        "\
++------------------+---------+
+|#0 *              |#1       |
+|64x48             |32x24    |
+|0,0               |64,0     |
+|                  +---------+
+|                  |
+|                  |
++------------------+"
+    );
+    assert!(
+        list.contains(
+            "#1 display 2 (MOCK-2)\n|- position: 64,0\n|- logical: 32x24\n|- physical: 64x48 at 2x\n"
+        ),
+        "{list}"
+    );
+}
+
+#[test]
+fn test_screen_baselines_capture_the_mock() {
+    // Every test hands children the same backend, so setting it here holds
+    std::env::set_var("SNAP_SCALE_BACKEND", DISPLAYS);
+    let dir = scratch("regression");
+    let regression = || {
+        snap_scale::regression::Regression::new(dir.join("baselines"))
+            .with_artifacts(dir.join("artifacts"))
+    };
+    let area = Some(snap_scale::Region::new(8, 8, 16, 12));
+    regression()
+        .updating(true)
+        .assert_screen_matches("area", area);
+    regression().assert_screen_matches("area", area);
+
+    let baseline = image::open(dir.join("baselines/area.png")).unwrap();
+    assert_eq!((baseline.width(), baseline.height()), (16, 12));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn test_scripts_capture_the_mock() {
+    let dir = scratch("script");
+    let shot = dir.join("shot.png");
+    let script = dir.join("capture.rhai");
+    std::fs::write(
+        &script,
+        format!(
+            r#"let d = displays(); if d.len() == 2 {{ capture_area(0, 8, 8, 16, 12).save("{}"); }}"#,
+            shot.display().to_string().replace('\\', "/")
+        ),
+    )
+    .unwrap();
+    snap_scale(&["script", script.to_str().unwrap()]);
+    let image = image::open(&shot).unwrap();
+    assert_eq!((image.width(), image.height()), (16, 12));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "serve")]
+#[test]
+fn test_serve_lists_the_mock_displays() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["serve", "--listen", "127.0.0.1:0"])
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let addr = line.trim().rsplit("http://").next().unwrap().to_owned();
+
+    let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+    write!(
+        stream,
+        "GET /displays HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("MOCK-2"), "{response}");
+}
+
+#[test]
+fn test_partial_failures_exit_with_2() {
+    // The fixed test areas at 300,300 are off both mock displays, while
+    // the whole displays capture fine
+    let dir = scratch("batch");
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .current_dir(&dir)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 of 5 captures failed"), "{stderr}");
+    assert!(stderr.contains("display 2 area 300,300,300,300: invalid area"));
+    assert!(dir.join("target/1.png").exists() && dir.join("target/2.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_blank_captures_fail() {
+    // A preview this small is a single pixel, so a single color
+    let dir = scratch("blank");
+    let path = dir.join("blank.png");
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["--fail-on-blank", "capture", "--preview", "0.01", "-o"])
+        .arg(&path)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("display 1 is blank, every pixel #"),
+        "{stderr}"
+    );
+    assert!(!path.exists(), "Nothing is written");
+
+    let path = dir.join("preview.png");
+    snap_scale(&[
+        "--fail-on-blank",
+        "capture",
+        "--preview",
+        "0.5",
+        "-o",
+        path.to_str().unwrap(),
+    ]);
+    assert!(path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_monitor_needs_its_region_on_the_display() {
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["monitor", "--region", "300,300,10,10", "--count", "1"])
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is off the display"), "{stderr}");
+}
+
+#[test]
+fn test_locate_an_image() {
+    let dir = scratch("locate");
+    let noise = image::RgbaImage::from_fn(90, 60, |x, y| {
+        let v = ((x * 7919 + y * 104_729) ^ (x * y)).wrapping_mul(2_654_435_761) >> 24;
+        Rgba([v as u8, 255 - v as u8, 128, 255])
+    });
+    let haystack = dir.join("haystack.png");
+    noise.save(&haystack).unwrap();
+    let needle = dir.join("needle.png");
+    image::imageops::crop_imm(&noise, 41, 17, 20, 14)
+        .to_image()
+        .save(&needle)
+        .unwrap();
+
+    let output = snap_scale(&[
+        "locate",
+        needle.to_str().unwrap(),
+        "--input",
+        haystack.to_str().unwrap(),
+        "--json",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let best: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    assert_eq!((&best["x"], &best["y"]), (&41.into(), &17.into()), "{best}");
+    assert_eq!((&best["width"], &best["height"]), (&20.into(), &14.into()));
+    assert!(best["score"].as_f64().unwrap() > 0.999);
+
+    // Nowhere in a flipped copy
+    image::imageops::flip_vertical(&noise)
+        .save(&haystack)
+        .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .arg("locate")
+        .arg(&needle)
+        .arg("--input")
+        .arg(&haystack)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("appears nowhere"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_wait_for_an_image() {
+    let dir = scratch("wait_for");
+    let needle = dir.join("needle.png");
+    let shot = dir.join("shot.png");
+    snap_scale(&["capture", "--display", "1", "-o", needle.to_str().unwrap()]);
+
+    let output = snap_scale(&[
+        "wait-for",
+        "--image",
+        needle.to_str().unwrap(),
+        "--display",
+        "1",
+        "-o",
+        shot.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("appeared after"), "{stdout}");
+    assert!(stdout.contains("64,0 32x24"), "{stdout}");
+    assert!(shot.exists());
+
+    // It's still there when the time runs out
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["--timeout", "1s", "wait-for", "--gone", "--display", "1"])
+        .arg("--image")
+        .arg(&needle)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("to disappear timed out after 1s"),
+        "{stderr}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_daemon_needs_valid_rules() {
+    let dir = scratch("daemon");
+    let config = dir.join("config.toml");
+    let daemon = |rules: &str| {
+        std::fs::write(&config, rules).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+            .arg("daemon")
+            .env("SNAP_SCALE_BACKEND", DISPLAYS)
+            .env("SNAP_SCALE_CONFIG", &config)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let stderr = daemon("");
+    assert!(stderr.contains("no [[rules]]"), "{stderr}");
+    let stderr = daemon("[[rules]]\nname = \"errors\"\ntitle = \"(error\"\n");
+    assert!(
+        stderr.contains("rule `errors` has an invalid title"),
+        "{stderr}"
+    );
+    let stderr = daemon("[[schedules]]\nname = \"hourly\"\nschedule = \"0 */0 * * *\"\n");
+    assert!(stderr.contains("schedule `hourly`"), "{stderr}");
+    assert!(stderr.contains("hour step `0`"), "{stderr}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_huge_resizes_are_refused() {
+    let dir = scratch("huge");
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["--resize", "100000x100000", "capture", "-o"])
+        .arg(dir.join("huge.png"))
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "A usage error, not an abort");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("100000x100000 is over"), "{stderr}");
+    assert!(!dir.join("huge.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_presets_pick_format_and_size() {
+    let dir = scratch("preset");
+    let shot = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+    let frames = dir.join("frames");
+    let args = ["--preset", "quick", "watch", "--count", "1", "--dir"];
+    snap_scale(&[&args[..], &[frames.to_str().unwrap()]].concat());
+    let quick = image::open(frames.join("1-000001.jpg")).unwrap();
+    assert_eq!((quick.width(), quick.height()), (32, 24));
+
+    // A named file keeps the format its extension asks for
+    snap_scale(&["--preset", "quick", "capture", "-o", &shot("named.png")]);
+    let named = image::open(shot("named.png")).unwrap();
+    assert_eq!(named.width(), 32);
+    assert!(!dir.join("named.jpg").exists());
+    // Without `archival`'s PNG optimization, which JPEGs can't take
+    snap_scale(&["--preset", "archival", "capture", "-o", &shot("kept.jpg")]);
+    assert_eq!(image::open(shot("kept.jpg")).unwrap().width(), 64);
+
+    // Flags of their own win, and other extensions keep their format
+    let args = ["--preset", "quick", "--resize", "16x", "capture", "-o"];
+    snap_scale(&[&args[..], &[&shot("given.qoi")]].concat());
+    let given = image::open(shot("given.qoi")).unwrap();
+    assert_eq!((given.width(), given.height()), (16, 12));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_daemon_socket_captures() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::process::Stdio;
+
+    let dir = scratch("socket");
+    let socket = dir.join("control.sock");
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["daemon", "--socket"])
+        .arg(&socket)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Kept open for the daemon to print to
+    let mut stdout = BufReader::new(daemon.stdout.take().unwrap());
+    let mut listening = String::new();
+    stdout.read_line(&mut listening).unwrap();
+    assert!(listening.starts_with("listening on"), "{listening}");
+
+    let path = dir.join("second display.png");
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let mut replies = BufReader::new(stream.try_clone().unwrap());
+    let mut ask = |line: String| {
+        writeln!(stream, "{line}").unwrap();
+        let mut reply = String::new();
+        replies.read_line(&mut reply).unwrap();
+        reply
+    };
+    let reply = ask(format!("capture display=1 path=\"{}\"", path.display()));
+    assert_eq!(reply.trim(), format!("ok {}", path.display()));
+    assert_eq!(
+        image::open(&path).unwrap().to_rgba8().dimensions(),
+        (64, 48)
+    );
+    assert!(ask("capture display=9".into()).starts_with("error"));
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_lines() {
+    let dir = scratch("json");
+    let path = dir.join("shot.png");
+    let output = snap_scale(&[
+        "capture",
+        "--json",
+        "--display",
+        "1",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1, "{stdout}");
+    let capture = &lines[0];
+    assert_eq!(capture["event"], "capture");
+    assert_eq!(capture["path"], path.to_str().unwrap());
+    assert_eq!(
+        (capture["width"].as_u64(), capture["height"].as_u64()),
+        (Some(64), Some(48))
+    );
+    assert_eq!(capture["hash"].as_str().map(str::len), Some(16));
+    assert!(capture["duration_ms"].is_u64());
+
+    let output = snap_scale(&["layout", "--json"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2, "One line per display");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dry_run_writes_nothing() {
+    let dir = scratch("dry_run");
+    let path = dir.join("new/shot.png");
+    let output = snap_scale(&[
+        "--resize",
+        "50%",
+        "capture",
+        "--display",
+        "1",
+        "--output",
+        path.to_str().unwrap(),
+        "--dry-run",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.trim(),
+        format!(
+            "would capture display 2 (all) to {}: 32x24, png",
+            path.display()
+        )
+    );
+    assert!(!dir.join("new").exists(), "Not even the directory");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "catalog")]
+#[test]
+fn test_dry_run_leaves_the_catalog_alone() {
+    let dir = scratch("dry_run_catalog");
+    let config = dir.join("config.toml");
+    let database = dir.join("catalog.sqlite");
+    std::fs::write(
+        &config,
+        format!("[catalog]\nenabled = true\npath = {:?}\n", database),
+    )
+    .unwrap();
+    let path = dir.join("shot.png");
+    let output = Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args(["capture", "--dry-run", "--output"])
+        .arg(&path)
+        .env("SNAP_SCALE_BACKEND", DISPLAYS)
+        .env("SNAP_SCALE_CONFIG", &config)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!database.exists(), "The catalog isn't created");
+    assert!(!path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_completions_and_man_pages() {
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let output = snap_scale(&["completions", shell]);
+        let script = String::from_utf8(output.stdout).unwrap();
+        assert!(script.contains("snap_scale"), "{shell}");
+        assert!(script.contains("dry-run"), "{shell}: global flags");
+    }
+
+    let page = String::from_utf8(snap_scale(&["man"]).stdout).unwrap();
+    assert!(page.starts_with(".ie"), "roff");
+    assert!(page.contains(".TH snap_scale 1"));
+    let dir = scratch("man");
+    snap_scale(&["man", "--dir", dir.to_str().unwrap()]);
+    assert!(dir.join("snap_scale.1").exists());
+    assert!(dir.join("snap_scale-capture.1").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_live_pick_prints_changes() {
+    let output = snap_scale(&[
+        "pick",
+        "32",
+        "47",
+        "--live",
+        "--count",
+        "3",
+        "--interval",
+        "1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // The pixel never changes, so it's printed once
+    assert_eq!(
+        stdout,
+        "32,47  display 1  #81ff00  rgba(129, 255, 0, 1.00)  hsl(90, 100%, 50%)\n"
+    );
+
+    let output = snap_scale(&["pick", "74", "5", "--live", "--count", "1", "--json"]);
+    let line: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(line["display"], 2);
+    assert_eq!(line["physical"], serde_json::json!([20, 10]));
+}
+
+#[test]
+fn test_histogram_reports_exposure() {
+    let dir = scratch("histogram");
+    let plot = dir.join("histogram.png");
+    let output = snap_scale(&[
+        "histogram",
+        "--display",
+        "1",
+        "-o",
+        plot.to_str().unwrap(),
+        "--size",
+        "128x50",
+        "--json",
+    ]);
+    let line: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(line["pixels"], 64 * 48);
+    assert_eq!(line["red"].as_array().unwrap().len(), 256);
+    let exposure = &line["exposure"];
+    assert_eq!(exposure["clipped_white"], 0.0);
+    assert!(exposure["mean_luminance"].as_f64().unwrap() > 0.0);
+    let image = image::open(&plot).unwrap();
+    assert_eq!((image.width(), image.height()), (128, 50));
+
+    let text = String::from_utf8(snap_scale(&["histogram"]).stdout).unwrap();
+    assert!(text.starts_with("pixels: 3072\nmean luminance: "), "{text}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,86 @@
+//! Soak test for the watch loop
+//!
+//! Runs `watch --soak` against the mock backend, so every frame goes through
+//! the real capture, save and retention path while the binary checks its own
+//! resident memory and open handles. The long run is ignored by default; run
+//! it with
+//!
+//! ```bash
+//! SNAP_SCALE_SOAK_FRAMES=1000000 cargo test --release --test soak -- --ignored
+//! ```
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn soak_frames() -> u64 {
+    std::env::var("SNAP_SCALE_SOAK_FRAMES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1_000_000)
+}
+
+/// `watch --soak` for `frames` frames into `dir`, keeping the last few
+fn soak(frames: u64, dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_snap_scale"))
+        .args([
+            "--json",
+            "watch",
+            "--soak",
+            "--interval",
+            "0",
+            "--max-count",
+            "10",
+        ])
+        .args(["--count", &frames.to_string(), "--dir"])
+        .arg(dir)
+        .env("SNAP_SCALE_BACKEND", "mock:64x48")
+        .env("SNAP_SCALE_CONFIG", "target/no-such-config.toml")
+        .output()
+        .unwrap()
+}
+
+fn scratch(test: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("snap_scale_soak_{test}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The `soak` event of a run's JSON lines
+fn report(output: &Output) -> serde_json::Value {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|event| event["event"] == "soak")
+        .expect("a soak report")
+}
+
+#[test]
+fn test_watch_reports_its_growth() {
+    let dir = scratch("short");
+    let output = soak(200, &dir);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report = report(&output);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(report["frames"], 200);
+    #[cfg(target_os = "linux")]
+    assert!(report["open_handles"][0].is_u64(), "{report}");
+}
+
+#[test]
+#[ignore = "long-running; run with --ignored"]
+fn test_soak_watch() {
+    let dir = scratch("long");
+    let output = soak(soak_frames(), &dir);
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("{}", report(&output));
+    assert!(
+        output.status.success(),
+        "Soak run leaked resources: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}